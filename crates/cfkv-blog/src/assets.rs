@@ -0,0 +1,126 @@
+use crate::error::{BlogError, Result};
+use crate::types::BlogPost;
+use pulldown_cmark::{Event, Parser, Tag};
+use std::path::{Component, Path};
+
+/// Key prefix for uploaded assets
+pub const ASSET_KEY_PREFIX: &str = "asset:";
+/// Largest value Cloudflare KV accepts (25 MiB)
+pub const MAX_ASSET_SIZE: u64 = 25 * 1024 * 1024;
+
+/// Content type for an asset, based on its file extension
+pub fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "bmp" => "image/bmp",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "pdf" => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Local files referenced by a post: its cover image and markdown images
+///
+/// Remote URLs (`https://…`, `//…`, `data:…`) are skipped. References are
+/// returned once each, in the order they first appear.
+pub fn referenced_assets(post: &BlogPost) -> Vec<String> {
+    let images = Parser::new(&post.content).filter_map(|event| match event {
+        Event::Start(Tag::Image { dest_url, .. }) => Some(dest_url.to_string()),
+        _ => None,
+    });
+
+    let mut assets: Vec<String> = Vec::new();
+    for reference in post.cover_image.clone().into_iter().chain(images) {
+        if is_local(&reference) && !assets.contains(&reference) {
+            assets.push(reference);
+        }
+    }
+    assets
+}
+
+/// Normalize a reference into the name stored after `asset:`
+///
+/// Leading `./` and `/` are dropped; references that climb out of the assets
+/// directory with `..` are rejected.
+pub fn asset_name(reference: &str) -> Result<String> {
+    let mut parts = Vec::new();
+    for component in Path::new(reference).components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().into_owned()),
+            Component::CurDir | Component::RootDir => {}
+            _ => {
+                return Err(BlogError::ValidationError(format!(
+                    "Asset path must stay inside the assets directory: {}",
+                    reference
+                )))
+            }
+        }
+    }
+
+    if parts.is_empty() {
+        return Err(BlogError::ValidationError(format!(
+            "Invalid asset path: {}",
+            reference
+        )));
+    }
+    Ok(parts.join("/"))
+}
+
+fn is_local(reference: &str) -> bool {
+    !(reference.is_empty()
+        || reference.starts_with("//")
+        || reference.starts_with('#')
+        || reference.contains("://")
+        || reference.starts_with("data:"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::MarkdownParser;
+
+    #[test]
+    fn test_referenced_assets() {
+        let post = MarkdownParser::parse_post(
+            "---\nslug: p\ntitle: P\ndescription: d\nauthor: a\ndate: 2025-01-15\n\
+             cover_image: blog/cover.jpg\n---\n\
+             ![one](blog/cover.jpg) ![two](./img/a.png \"title\")\n\
+             ![remote](https://example.com/x.png) ![proto](//cdn.example.com/y.png)\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            referenced_assets(&post),
+            vec!["blog/cover.jpg".to_string(), "./img/a.png".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_asset_name() {
+        assert_eq!(asset_name("./img/a.png").unwrap(), "img/a.png");
+        assert_eq!(asset_name("/blog/cover.jpg").unwrap(), "blog/cover.jpg");
+        assert!(asset_name("../secret.png").is_err());
+        assert!(asset_name("img/../../x.png").is_err());
+        assert!(asset_name("./").is_err());
+    }
+
+    #[test]
+    fn test_content_type() {
+        assert_eq!(content_type(Path::new("a/b.JPG")), "image/jpeg");
+        assert_eq!(content_type(Path::new("logo.svg")), "image/svg+xml");
+        assert_eq!(content_type(Path::new("noext")), "application/octet-stream");
+    }
+}
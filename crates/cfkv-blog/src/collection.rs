@@ -0,0 +1,448 @@
+//! Content collections: markdown files with frontmatter stored under a key prefix
+//!
+//! A collection describes where its entries live in KV and what their
+//! frontmatter must contain. Any frontmatter is accepted and kept as JSON, so
+//! the same publisher works for docs, changelogs, author pages and so on.
+//! Blog posts are the built-in `blog` collection, handled by `BlogPublisher`.
+
+use crate::assets::ASSET_KEY_PREFIX;
+use crate::error::{BlogError, Result};
+use crate::index::{AUTHOR_KEY_PREFIX, BLOG_LIST_KEY, TAG_KEY_PREFIX};
+use crate::list::PagedList;
+use crate::parser::{MarkdownParser, REQUIRED_FIELDS};
+use crate::publisher::{DRAFT_KEY_PREFIX, POST_KEY_PREFIX, SCHEDULED_KEY_PREFIX};
+use crate::render::{self, RenderMode, RenderOptions};
+use cloudflare_kv::client::KvClient;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+use tracing::debug;
+
+/// Key prefixes the blog writes under; `_blog` covers the list, its pages,
+/// feeds and the sitemap
+const BLOG_KEY_PREFIXES: [&str; 7] = [
+    POST_KEY_PREFIX,
+    DRAFT_KEY_PREFIX,
+    SCHEDULED_KEY_PREFIX,
+    ASSET_KEY_PREFIX,
+    TAG_KEY_PREFIX,
+    AUTHOR_KEY_PREFIX,
+    "_blog",
+];
+
+/// Where a collection's entries are stored and which fields they require
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Collection {
+    pub name: String,
+    /// Entries are stored under `<key_prefix><slug>`
+    pub key_prefix: String,
+    /// Key holding the list of entry metadata
+    pub list_key: String,
+    /// Frontmatter fields every entry must set
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub required_fields: Vec<String>,
+    /// Frontmatter field holding the entry's slug
+    #[serde(default = "default_slug_field")]
+    pub slug_field: String,
+}
+
+fn default_slug_field() -> String {
+    "slug".to_string()
+}
+
+impl Collection {
+    /// Name of the built-in blog collection
+    pub const BLOG: &'static str = "blog";
+
+    /// The built-in blog collection (`post:<slug>`, listed in `_blog_list`)
+    pub fn blog() -> Self {
+        Self {
+            name: Self::BLOG.to_string(),
+            key_prefix: "post:".to_string(),
+            list_key: BLOG_LIST_KEY.to_string(),
+            required_fields: REQUIRED_FIELDS.iter().map(|f| f.to_string()).collect(),
+            slug_field: default_slug_field(),
+        }
+    }
+
+    /// Whether this is the built-in blog collection
+    pub fn is_blog(&self) -> bool {
+        self.name == Self::BLOG
+    }
+
+    /// KV key of an entry
+    pub fn entry_key(&self, slug: &str) -> String {
+        format!("{}{}", self.key_prefix, slug)
+    }
+
+    /// Check the collection definition is usable
+    pub fn validate(&self) -> Result<()> {
+        if self.name.is_empty() || self.key_prefix.is_empty() || self.list_key.is_empty() {
+            return Err(BlogError::ValidationError(format!(
+                "Collection '{}' needs a name, key_prefix and list_key",
+                self.name
+            )));
+        }
+        let pages = format!("{}:", self.list_key);
+        if self.list_key.starts_with(&self.key_prefix) || self.key_prefix.starts_with(&pages) {
+            return Err(BlogError::ValidationError(format!(
+                "Collection '{}': list_key '{}' and its pages must not share keys with key_prefix '{}'",
+                self.name, self.list_key, self.key_prefix
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check a configured collection, which on top of `validate` must not
+    /// be named `blog` or share keys with the blog
+    pub fn validate_custom(&self) -> Result<()> {
+        if self.is_blog() {
+            return Err(BlogError::ValidationError(format!(
+                "Collection name '{}' is reserved for the built-in blog collection",
+                Self::BLOG
+            )));
+        }
+        self.validate()?;
+
+        // Entry keys may not overlap a blog prefix either way round, as
+        // `<key_prefix><slug>` could then be a blog key
+        let reserved = BLOG_KEY_PREFIXES.iter().find(|prefix| {
+            self.key_prefix.starts_with(*prefix)
+                || prefix.starts_with(&self.key_prefix)
+                || self.list_key.starts_with(*prefix)
+        });
+        if let Some(prefix) = reserved {
+            return Err(BlogError::ValidationError(format!(
+                "Collection '{}': keys starting with '{}' are reserved for the blog",
+                self.name, prefix
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// A stored collection entry: all frontmatter fields plus the markdown body
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ContentEntry {
+    pub slug: String,
+    /// Every frontmatter field, including the slug field
+    pub fields: BTreeMap<String, Value>,
+    pub content: String,
+    /// Rendered HTML of the content, when published with HTML rendering
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub html: Option<String>,
+    /// SHA-256 of the source markdown
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+}
+
+/// Entry in a collection's list (the entry without its body)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ContentMeta {
+    pub slug: String,
+    pub fields: BTreeMap<String, Value>,
+}
+
+impl ContentEntry {
+    /// Parse and validate a markdown file against a collection
+    pub fn parse(collection: &Collection, content: &str) -> Result<Self> {
+        let parsed = MarkdownParser::parse(content)?;
+        MarkdownParser::validate_required_fields(&parsed.metadata, &collection.required_fields)?;
+
+        let slug = MarkdownParser::get_string(&parsed.metadata, &collection.slug_field)?;
+        MarkdownParser::validate_slug(&slug)?;
+
+        let fields = parsed
+            .metadata
+            .iter()
+            .map(|(key, value)| {
+                let value = serde_json::to_value(value).map_err(|e| {
+                    BlogError::FrontmatterError(format!("Invalid value for field {}: {}", key, e))
+                })?;
+                Ok((key.clone(), value))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            slug,
+            fields,
+            content: parsed.content,
+            html: None,
+            content_hash: Some(MarkdownParser::content_hash(content)),
+        })
+    }
+
+    /// Apply a render mode, as `render::render_post` does for blog posts
+    pub fn render(mut self, mode: RenderMode, options: &RenderOptions) -> Self {
+        match mode {
+            RenderMode::Markdown => {}
+            RenderMode::Html => {
+                self.html = Some(render::render_html(&self.content, options));
+                self.content.clear();
+            }
+            RenderMode::Both => {
+                self.html = Some(render::render_html(&self.content, options));
+            }
+        }
+        self
+    }
+
+    /// Reconstruct the markdown source: YAML frontmatter followed by the content
+    pub fn to_markdown(&self) -> std::result::Result<String, serde_yaml::Error> {
+        let frontmatter = serde_yaml::to_string(&self.fields)?;
+        Ok(format!("---\n{}---\n\n{}\n", frontmatter, self.content))
+    }
+
+    /// List entry for this entry
+    pub fn meta(&self) -> ContentMeta {
+        ContentMeta {
+            slug: self.slug.clone(),
+            fields: self.fields.clone(),
+        }
+    }
+}
+
+/// Publisher for entries of a configured collection
+pub struct ContentPublisher<'a> {
+    client: &'a KvClient,
+    collection: Collection,
+    render_mode: RenderMode,
+    render_options: RenderOptions,
+}
+
+impl<'a> ContentPublisher<'a> {
+    /// Create a publisher for a collection
+    pub fn new(client: &'a KvClient, collection: Collection) -> Self {
+        Self {
+            client,
+            collection,
+            render_mode: RenderMode::default(),
+            render_options: RenderOptions::default(),
+        }
+    }
+
+    /// Render entry content to HTML when publishing
+    pub fn with_render(mut self, mode: RenderMode, options: RenderOptions) -> Self {
+        self.render_mode = mode;
+        self.render_options = options;
+        self
+    }
+
+    /// The collection this publisher writes to
+    pub fn collection(&self) -> &Collection {
+        &self.collection
+    }
+
+    /// Publish an entry from a markdown file
+    pub async fn publish_from_file(&self, file_path: &Path) -> Result<ContentEntry> {
+        debug!(
+            "Publishing {} entry from: {}",
+            self.collection.name,
+            file_path.display()
+        );
+
+        let content = std::fs::read_to_string(file_path)?;
+        let entry = ContentEntry::parse(&self.collection, &content)?
+            .render(self.render_mode, &self.render_options);
+
+        let value = serde_json::to_string(&entry)?;
+        self.put_value(&self.collection.entry_key(&entry.slug), &value)
+            .await?;
+
+        let meta = entry.meta();
+        self.modify_list(|list| {
+            match list.iter().position(|m| m.slug == meta.slug) {
+                Some(pos) if list[pos] == meta => return false,
+                Some(pos) => list[pos] = meta.clone(),
+                None => list.insert(0, meta.clone()),
+            }
+            true
+        })
+        .await?;
+
+        debug!("Published {} entry: {}", self.collection.name, entry.slug);
+        Ok(entry)
+    }
+
+    /// Get an entry by slug
+    pub async fn get(&self, slug: &str) -> Result<Option<ContentEntry>> {
+        match self.get_value(&self.collection.entry_key(slug)).await? {
+            Some(value) => Ok(Some(serde_json::from_str(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// List entries, most recently added first
+    pub async fn list(&self) -> Result<Vec<ContentMeta>> {
+        Ok(self.paged_list().load().await?.entries)
+    }
+
+    /// Get one page of the list, empty past the last page
+    pub async fn list_page(&self, page: usize) -> Result<Vec<ContentMeta>> {
+        self.paged_list().page(page).await
+    }
+
+    /// Delete an entry and remove it from the list
+    pub async fn delete(&self, slug: &str) -> Result<()> {
+        self.client
+            .delete(&self.collection.entry_key(slug))
+            .await
+            .map_err(|e| BlogError::KvError(e.to_string()))?;
+
+        self.modify_list(|list| {
+            let original_len = list.len();
+            list.retain(|m| m.slug != slug);
+            list.len() < original_len
+        })
+        .await?;
+
+        debug!("Deleted {} entry: {}", self.collection.name, slug);
+        Ok(())
+    }
+
+    fn paged_list(&self) -> PagedList<'a> {
+        PagedList::new(self.client, self.collection.list_key.clone())
+    }
+
+    /// Apply a repeatable change to the list (see `PagedList::modify`)
+    async fn modify_list<F>(&self, modify: F) -> Result<()>
+    where
+        F: Fn(&mut Vec<ContentMeta>) -> bool,
+    {
+        self.paged_list()
+            .modify(modify, |_| {}, |_, _| Ok(Vec::new()))
+            .await?;
+        Ok(())
+    }
+
+    async fn get_value(&self, key: &str) -> Result<Option<String>> {
+        self.client
+            .get(key)
+            .await
+            .map(|pair| pair.map(|p| p.value))
+            .map_err(|e| BlogError::KvError(e.to_string()))
+    }
+
+    async fn put_value(&self, key: &str, value: &str) -> Result<()> {
+        self.client
+            .put(key, value.as_bytes())
+            .await
+            .map_err(|e| BlogError::KvError(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn docs() -> Collection {
+        Collection {
+            name: "docs".to_string(),
+            key_prefix: "doc:".to_string(),
+            list_key: "_docs_list".to_string(),
+            required_fields: vec!["title".to_string()],
+            slug_field: "id".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_entry() {
+        let source = "---\nid: getting-started\ntitle: Getting Started\norder: 1\n---\n# Hi\n";
+        let entry = ContentEntry::parse(&docs(), source).unwrap();
+
+        assert_eq!(entry.slug, "getting-started");
+        assert_eq!(entry.fields["order"], serde_json::json!(1));
+        assert_eq!(entry.fields["id"], serde_json::json!("getting-started"));
+        assert_eq!(entry.content, "# Hi");
+        assert_eq!(docs().entry_key(&entry.slug), "doc:getting-started");
+
+        let reparsed = ContentEntry::parse(&docs(), &entry.to_markdown().unwrap()).unwrap();
+        assert_eq!(reparsed.fields, entry.fields);
+        assert_eq!(reparsed.content, entry.content);
+    }
+
+    #[test]
+    fn test_parse_entry_validation() {
+        let missing_title = "---\nid: intro\n---\nBody";
+        assert!(ContentEntry::parse(&docs(), missing_title).is_err());
+
+        let missing_slug = "---\ntitle: Intro\n---\nBody";
+        assert!(ContentEntry::parse(&docs(), missing_slug).is_err());
+
+        let bad_slug = "---\nid: Intro Page\ntitle: Intro\n---\nBody";
+        assert!(ContentEntry::parse(&docs(), bad_slug).is_err());
+    }
+
+    #[test]
+    fn test_collection_validate() {
+        assert!(docs().validate().is_ok());
+        assert!(Collection::blog().validate().is_ok());
+        assert!(Collection::blog().is_blog());
+
+        let overlapping = Collection {
+            list_key: "doc:_list".to_string(),
+            ..docs()
+        };
+        assert!(overlapping.validate().is_err());
+
+        let collection: Collection = serde_json::from_str(
+            r#"{"name": "changelog", "key_prefix": "changelog:", "list_key": "_changelog"}"#,
+        )
+        .unwrap();
+        assert_eq!(collection.slug_field, "slug");
+        assert!(collection.required_fields.is_empty());
+
+        let pages_in_entries = Collection {
+            key_prefix: "_docs_list:".to_string(),
+            ..docs()
+        };
+        assert!(pages_in_entries.validate().is_err());
+    }
+
+    #[test]
+    fn test_reserved_collections() {
+        assert!(docs().validate_custom().is_ok());
+        assert!(Collection::blog().validate_custom().is_err());
+        for (key_prefix, list_key) in [
+            ("post:", "_docs_list"),
+            ("p", "_docs_list"),
+            ("post:docs/", "_docs_list"),
+            ("doc:", "_blog_list"),
+            ("doc:", "_blog_docs"),
+            ("doc:", "tag:docs"),
+        ] {
+            let collection = Collection {
+                key_prefix: key_prefix.to_string(),
+                list_key: list_key.to_string(),
+                ..docs()
+            };
+            assert!(
+                collection.validate_custom().is_err(),
+                "{} {}",
+                key_prefix,
+                list_key
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publish_paged_and_rendered() {
+        let client = KvClient::in_memory();
+        let publisher = ContentPublisher::new(&client, docs())
+            .with_render(RenderMode::Both, RenderOptions::default());
+        let path = std::env::temp_dir().join("cfkv-content-paged.md");
+        std::fs::write(&path, "---\nid: intro\ntitle: Intro\n---\n# Hi\n").unwrap();
+        let entry = publisher.publish_from_file(&path).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(entry.html.as_deref(), Some("<h1>Hi</h1>\n"));
+        assert_eq!(publisher.get("intro").await.unwrap().unwrap(), entry);
+        assert!(client.get("_docs_list:0").await.unwrap().is_some());
+        assert_eq!(publisher.list_page(0).await.unwrap(), vec![entry.meta()]);
+        assert!(publisher.list_page(1).await.unwrap().is_empty());
+
+        publisher.delete("intro").await.unwrap();
+        assert!(publisher.list().await.unwrap().is_empty());
+    }
+}
@@ -18,6 +18,12 @@ pub enum BlogError {
     #[error("YAML error: {0}")]
     YamlError(#[from] serde_yaml::Error),
 
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Conflict: {0}")]
+    ConflictError(String),
+
     #[error("KV error: {0}")]
     KvError(String),
 }
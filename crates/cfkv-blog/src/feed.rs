@@ -0,0 +1,288 @@
+use crate::error::BlogError;
+use crate::types::BlogMeta;
+use chrono::NaiveDate;
+use std::fmt::Write;
+use std::str::FromStr;
+
+/// Feed document formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedFormat {
+    Rss,
+    Atom,
+    Sitemap,
+}
+
+impl FeedFormat {
+    /// KV key the feed is stored under
+    pub fn storage_key(&self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "_blog_feed.xml",
+            FeedFormat::Atom => "_blog_feed.atom.xml",
+            FeedFormat::Sitemap => "_blog_sitemap.xml",
+        }
+    }
+
+    /// MIME type to serve the feed with
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "application/rss+xml",
+            FeedFormat::Atom => "application/atom+xml",
+            FeedFormat::Sitemap => "application/xml",
+        }
+    }
+}
+
+impl FromStr for FeedFormat {
+    type Err = BlogError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "rss" => Ok(FeedFormat::Rss),
+            "atom" => Ok(FeedFormat::Atom),
+            "sitemap" => Ok(FeedFormat::Sitemap),
+            other => Err(BlogError::ValidationError(format!(
+                "Unknown feed format: {} (expected rss, atom or sitemap)",
+                other
+            ))),
+        }
+    }
+}
+
+/// Site details used to build feeds
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedConfig {
+    /// Base URL of the site, e.g. `https://example.com`
+    pub site_url: String,
+    pub title: String,
+    pub description: Option<String>,
+    /// Maximum number of posts in RSS and Atom feeds (sitemaps list all posts)
+    pub max_entries: usize,
+    /// Path posts are served under, joined with the slug
+    pub post_path: String,
+}
+
+impl FeedConfig {
+    /// Create a config with the default limit of 20 entries under `/blog/`
+    pub fn new(site_url: impl Into<String>, title: impl Into<String>) -> Self {
+        Self {
+            site_url: site_url.into(),
+            title: title.into(),
+            description: None,
+            max_entries: 20,
+            post_path: "/blog/".to_string(),
+        }
+    }
+
+    fn base_url(&self) -> &str {
+        self.site_url.trim_end_matches('/')
+    }
+
+    fn post_url(&self, slug: &str) -> String {
+        match self.post_path.trim_matches('/') {
+            "" => format!("{}/{}", self.base_url(), slug),
+            path => format!("{}/{}/{}", self.base_url(), path, slug),
+        }
+    }
+}
+
+/// Build a feed from blog list entries (expected newest first)
+pub fn build_feed(posts: &[BlogMeta], format: FeedFormat, config: &FeedConfig) -> String {
+    match format {
+        FeedFormat::Rss => build_rss(&posts[..posts.len().min(config.max_entries)], config),
+        FeedFormat::Atom => build_atom(&posts[..posts.len().min(config.max_entries)], config),
+        FeedFormat::Sitemap => build_sitemap(posts, config),
+    }
+}
+
+fn build_rss(posts: &[BlogMeta], config: &FeedConfig) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<rss version=\"2.0\">\n<channel>\n");
+    let _ = writeln!(xml, "  <title>{}</title>", escape(&config.title));
+    let _ = writeln!(xml, "  <link>{}</link>", escape(config.base_url()));
+    let _ = writeln!(
+        xml,
+        "  <description>{}</description>",
+        escape(config.description.as_deref().unwrap_or(&config.title))
+    );
+    if let Some(latest) = posts.first() {
+        let _ = writeln!(
+            xml,
+            "  <lastBuildDate>{}</lastBuildDate>",
+            rfc2822(&latest.date)
+        );
+    }
+
+    for post in posts {
+        let url = escape(&config.post_url(&post.slug));
+        xml.push_str("  <item>\n");
+        let _ = writeln!(xml, "    <title>{}</title>", escape(&post.title));
+        let _ = writeln!(xml, "    <link>{}</link>", url);
+        let _ = writeln!(xml, "    <guid>{}</guid>", url);
+        let _ = writeln!(
+            xml,
+            "    <description>{}</description>",
+            escape(&post.description)
+        );
+        let _ = writeln!(xml, "    <author>{}</author>", escape(&post.author));
+        let _ = writeln!(xml, "    <pubDate>{}</pubDate>", rfc2822(&post.date));
+        for tag in &post.tags {
+            let _ = writeln!(xml, "    <category>{}</category>", escape(tag));
+        }
+        xml.push_str("  </item>\n");
+    }
+
+    xml.push_str("</channel>\n</rss>\n");
+    xml
+}
+
+fn build_atom(posts: &[BlogMeta], config: &FeedConfig) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    let _ = writeln!(xml, "  <title>{}</title>", escape(&config.title));
+    if let Some(description) = &config.description {
+        let _ = writeln!(xml, "  <subtitle>{}</subtitle>", escape(description));
+    }
+    let _ = writeln!(xml, "  <id>{}/</id>", escape(config.base_url()));
+    let _ = writeln!(xml, "  <link href=\"{}/\"/>", escape(config.base_url()));
+    let updated = posts
+        .first()
+        .map(|p| rfc3339(&p.date))
+        .unwrap_or_else(|| "1970-01-01T00:00:00Z".to_string());
+    let _ = writeln!(xml, "  <updated>{}</updated>", updated);
+
+    for post in posts {
+        let url = escape(&config.post_url(&post.slug));
+        xml.push_str("  <entry>\n");
+        let _ = writeln!(xml, "    <title>{}</title>", escape(&post.title));
+        let _ = writeln!(xml, "    <link href=\"{}\"/>", url);
+        let _ = writeln!(xml, "    <id>{}</id>", url);
+        let _ = writeln!(xml, "    <updated>{}</updated>", rfc3339(&post.date));
+        let _ = writeln!(
+            xml,
+            "    <author><name>{}</name></author>",
+            escape(&post.author)
+        );
+        let _ = writeln!(xml, "    <summary>{}</summary>", escape(&post.description));
+        for tag in &post.tags {
+            let _ = writeln!(xml, "    <category term=\"{}\"/>", escape(tag));
+        }
+        xml.push_str("  </entry>\n");
+    }
+
+    xml.push_str("</feed>\n");
+    xml
+}
+
+fn build_sitemap(posts: &[BlogMeta], config: &FeedConfig) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for post in posts {
+        xml.push_str("  <url>\n");
+        let _ = writeln!(
+            xml,
+            "    <loc>{}</loc>",
+            escape(&config.post_url(&post.slug))
+        );
+        let _ = writeln!(xml, "    <lastmod>{}</lastmod>", escape(&post.date));
+        xml.push_str("  </url>\n");
+    }
+    xml.push_str("</urlset>\n");
+    xml
+}
+
+/// Escape text for XML content and attributes
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn parse_date(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()
+}
+
+fn rfc2822(date: &str) -> String {
+    parse_date(date)
+        .map(|d| d.format("%a, %d %b %Y 00:00:00 +0000").to_string())
+        .unwrap_or_else(|| date.to_string())
+}
+
+fn rfc3339(date: &str) -> String {
+    parse_date(date)
+        .map(|d| d.format("%Y-%m-%dT00:00:00Z").to_string())
+        .unwrap_or_else(|| date.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(slug: &str, date: &str) -> BlogMeta {
+        BlogMeta {
+            slug: slug.to_string(),
+            title: format!("Post & {}", slug),
+            description: "A <short> post".to_string(),
+            author: "Author".to_string(),
+            date: date.to_string(),
+            cover_image: None,
+            tags: vec!["rust".to_string()],
+            publish_at: None,
+            content_hash: None,
+        }
+    }
+
+    fn config() -> FeedConfig {
+        FeedConfig::new("https://example.com/", "My Blog")
+    }
+
+    #[test]
+    fn test_rss() {
+        let posts = vec![meta("b", "2025-01-15"), meta("a", "2025-01-01")];
+        let xml = build_feed(&posts, FeedFormat::Rss, &config());
+        assert!(xml.contains("<rss version=\"2.0\">"));
+        assert!(xml.contains("<link>https://example.com/blog/b</link>"));
+        assert!(xml.contains("<title>Post &amp; b</title>"));
+        assert!(xml.contains("<description>A &lt;short&gt; post</description>"));
+        assert!(xml.contains("<pubDate>Wed, 15 Jan 2025 00:00:00 +0000</pubDate>"));
+        assert_eq!(xml.matches("<item>").count(), 2);
+    }
+
+    #[test]
+    fn test_atom_respects_max_entries() {
+        let posts = vec![meta("b", "2025-01-15"), meta("a", "2025-01-01")];
+        let mut config = config();
+        config.max_entries = 1;
+        config.post_path = "posts".to_string();
+
+        let xml = build_feed(&posts, FeedFormat::Atom, &config);
+        assert!(xml.contains("<updated>2025-01-15T00:00:00Z</updated>"));
+        assert!(xml.contains("<link href=\"https://example.com/posts/b\"/>"));
+        assert_eq!(xml.matches("<entry>").count(), 1);
+    }
+
+    #[test]
+    fn test_sitemap_lists_all_posts() {
+        let posts: Vec<BlogMeta> = (0..30)
+            .map(|i| meta(&i.to_string(), "2025-01-15"))
+            .collect();
+        let xml = build_feed(&posts, FeedFormat::Sitemap, &config());
+        assert_eq!(xml.matches("<url>").count(), 30);
+        assert!(xml.contains("<lastmod>2025-01-15</lastmod>"));
+    }
+
+    #[test]
+    fn test_feed_format() {
+        assert_eq!("RSS".parse::<FeedFormat>().unwrap(), FeedFormat::Rss);
+        assert_eq!(FeedFormat::Rss.storage_key(), "_blog_feed.xml");
+        assert!("json".parse::<FeedFormat>().is_err());
+    }
+}
@@ -0,0 +1,296 @@
+//! Storage layout of the blog list
+//!
+//! `_blog_list` holds a small manifest; the entries themselves live in
+//! fixed-size pages (`_blog_list:0`, `_blog_list:1`, ...) sorted newest first.
+//! Each tag and author also gets an index key (`tag:<tag>`, `author:<name>`)
+//! holding the entries of its newest `INDEX_SIZE` posts, so filtered listings
+//! of all but the largest tags are a single read.
+//!
+//! Namespaces written before pagination hold the whole list as an array under
+//! `_blog_list`. Such a list is read as-is and kept in that format, for
+//! Workers that read it directly, until it is converted explicitly.
+//!
+//! Writes are computed as a diff between the old and new list so only pages
+//! and indexes whose contents changed are rewritten. Collection lists use the
+//! same pages without indexes (see `list::PagedList`).
+
+use crate::error::Result;
+use crate::types::BlogMeta;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Key of the blog list manifest
+pub const BLOG_LIST_KEY: &str = "_blog_list";
+/// Number of entries per list page
+pub const LIST_PAGE_SIZE: usize = 100;
+/// Most entries kept in a tag or author index
+pub const INDEX_SIZE: usize = LIST_PAGE_SIZE;
+pub(crate) const TAG_KEY_PREFIX: &str = "tag:";
+pub(crate) const AUTHOR_KEY_PREFIX: &str = "author:";
+
+/// Manifest stored under `_blog_list`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ListManifest {
+    /// Incremented on every list write
+    pub version: u64,
+    pub total: usize,
+    pub pages: usize,
+    pub page_size: usize,
+}
+
+/// Value found under a list key: a manifest, or a pre-pagination list
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum StoredList<T = BlogMeta> {
+    Legacy(Vec<T>),
+    Paged(ListManifest),
+}
+
+/// A key write needed to bring KV in line with a new list
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyChange {
+    Put(String, String),
+    Delete(String),
+}
+
+/// Key of a blog list page
+pub fn page_key(page: usize) -> String {
+    list_page_key(BLOG_LIST_KEY, page)
+}
+
+/// Key of a page of the list under `list_key`
+pub fn list_page_key(list_key: &str, page: usize) -> String {
+    format!("{}:{}", list_key, page)
+}
+
+/// Key of a tag index
+pub fn tag_key(tag: &str) -> String {
+    format!("{}{}", TAG_KEY_PREFIX, tag)
+}
+
+/// Key of an author index
+pub fn author_key(author: &str) -> String {
+    format!("{}{}", AUTHOR_KEY_PREFIX, author)
+}
+
+/// Manifest describing a list of `total` entries
+pub fn manifest_for(total: usize, version: u64) -> ListManifest {
+    ListManifest {
+        version,
+        total,
+        pages: total.div_ceil(LIST_PAGE_SIZE),
+        page_size: LIST_PAGE_SIZE,
+    }
+}
+
+/// Exponential backoff with jitter before retry `attempt` of a list update
+pub fn retry_delay(attempt: u32) -> Duration {
+    let base = 50u64 << attempt.min(6);
+    let jitter = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u64 % base)
+        .unwrap_or_default();
+    Duration::from_millis(base + jitter)
+}
+
+/// Group list entries into index keys, preserving list order and keeping at
+/// most `INDEX_SIZE` entries per index
+fn indexes(posts: &[BlogMeta]) -> BTreeMap<String, Vec<&BlogMeta>> {
+    let mut indexes: BTreeMap<String, Vec<&BlogMeta>> = BTreeMap::new();
+    for post in posts {
+        let tags: BTreeSet<&String> = post.tags.iter().collect();
+        let keys = tags
+            .into_iter()
+            .map(|tag| tag_key(tag))
+            .chain([author_key(&post.author)]);
+        for key in keys {
+            let entries = indexes.entry(key).or_default();
+            if entries.len() < INDEX_SIZE {
+                entries.push(post);
+            }
+        }
+    }
+    indexes
+}
+
+/// Compute the page and index writes that turn the blog list `old` into
+/// `new`
+///
+/// The manifest itself is not included; callers write it last.
+pub fn diff(old: &[BlogMeta], new: &[BlogMeta]) -> Result<Vec<KeyChange>> {
+    let mut changes = diff_pages(BLOG_LIST_KEY, old, new)?;
+    changes.extend(diff_indexes(old, new)?);
+    Ok(changes)
+}
+
+/// Compute the page writes that turn the list under `list_key` from `old`
+/// into `new`
+pub fn diff_pages<T: Serialize + PartialEq>(
+    list_key: &str,
+    old: &[T],
+    new: &[T],
+) -> Result<Vec<KeyChange>> {
+    let mut changes = Vec::new();
+
+    let old_pages: Vec<&[T]> = old.chunks(LIST_PAGE_SIZE).collect();
+    let new_pages: Vec<&[T]> = new.chunks(LIST_PAGE_SIZE).collect();
+    for i in 0..old_pages.len().max(new_pages.len()) {
+        match (old_pages.get(i), new_pages.get(i)) {
+            (old_page, Some(new_page)) if old_page != Some(new_page) => {
+                changes.push(KeyChange::Put(
+                    list_page_key(list_key, i),
+                    serde_json::to_string(new_page)?,
+                ));
+            }
+            (Some(_), None) => changes.push(KeyChange::Delete(list_page_key(list_key, i))),
+            _ => {}
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Compute the tag and author index writes that turn the blog list `old`
+/// into `new`
+pub fn diff_indexes(old: &[BlogMeta], new: &[BlogMeta]) -> Result<Vec<KeyChange>> {
+    let mut changes = Vec::new();
+
+    let old_indexes = indexes(old);
+    let new_indexes = indexes(new);
+    let keys: BTreeSet<&String> = old_indexes.keys().chain(new_indexes.keys()).collect();
+    for key in keys {
+        match (old_indexes.get(key), new_indexes.get(key)) {
+            (old_entries, Some(new_entries)) if old_entries != Some(new_entries) => {
+                changes.push(KeyChange::Put(
+                    key.clone(),
+                    serde_json::to_string(new_entries)?,
+                ));
+            }
+            (Some(_), None) => changes.push(KeyChange::Delete(key.clone())),
+            _ => {}
+        }
+    }
+
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(slug: &str, author: &str, tags: &[&str]) -> BlogMeta {
+        BlogMeta {
+            slug: slug.to_string(),
+            title: slug.to_string(),
+            description: String::new(),
+            author: author.to_string(),
+            date: "2025-01-15".to_string(),
+            cover_image: None,
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            publish_at: None,
+            content_hash: None,
+        }
+    }
+
+    fn keys(changes: &[KeyChange]) -> Vec<String> {
+        changes
+            .iter()
+            .map(|c| match c {
+                KeyChange::Put(k, _) => format!("put {}", k),
+                KeyChange::Delete(k) => format!("delete {}", k),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_from_empty() {
+        let new = vec![
+            meta("a", "ann", &["rust", "web"]),
+            meta("b", "bob", &["rust"]),
+        ];
+        let changes = diff(&[], &new).unwrap();
+        assert_eq!(
+            keys(&changes),
+            vec![
+                "put _blog_list:0",
+                "put author:ann",
+                "put author:bob",
+                "put tag:rust",
+                "put tag:web"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_only_touches_changed_indexes() {
+        let old = vec![meta("a", "ann", &["rust"]), meta("b", "bob", &["go"])];
+        let mut new = old.clone();
+        new[1].tags = vec!["zig".to_string()];
+
+        let changes = diff(&old, &new).unwrap();
+        assert_eq!(
+            keys(&changes),
+            vec![
+                "put _blog_list:0",
+                "put author:bob",
+                "delete tag:go",
+                "put tag:zig"
+            ]
+        );
+        assert!(diff(&new, &new).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_diff_pages() {
+        let old: Vec<BlogMeta> = (0..LIST_PAGE_SIZE + 1)
+            .map(|i| meta(&format!("p{}", i), "ann", &[]))
+            .collect();
+        let new = old[..LIST_PAGE_SIZE].to_vec();
+
+        let changes = diff(&old, &new).unwrap();
+        assert_eq!(keys(&changes), vec!["delete _blog_list:1"]);
+    }
+
+    #[test]
+    fn test_indexes_are_capped() {
+        let old: Vec<BlogMeta> = (0..INDEX_SIZE)
+            .map(|i| meta(&format!("p{}", i), "ann", &["rust"]))
+            .collect();
+        let mut new = old.clone();
+        new.push(meta("oldest", "ann", &["rust"]));
+
+        let changes = diff(&old, &new).unwrap();
+        assert_eq!(keys(&changes), vec!["put _blog_list:1"]);
+        let indexes = indexes(&new);
+        assert_eq!(indexes["tag:rust"].len(), INDEX_SIZE);
+        assert_eq!(indexes["author:ann"].len(), INDEX_SIZE);
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        for attempt in 1..4 {
+            let delay = retry_delay(attempt);
+            let base = Duration::from_millis(50 << attempt);
+            assert!(delay >= base && delay < base * 2);
+        }
+    }
+
+    #[test]
+    fn test_manifest_and_stored_list() {
+        let manifest = manifest_for(LIST_PAGE_SIZE * 2 + 1, 7);
+        assert_eq!(manifest.pages, 3);
+        assert_eq!(manifest_for(0, 0).pages, 0);
+
+        let json = serde_json::to_string(&manifest).unwrap();
+        assert!(matches!(
+            serde_json::from_str::<StoredList>(&json).unwrap(),
+            StoredList::Paged(m) if m == manifest
+        ));
+        let legacy = serde_json::to_string(&vec![meta("a", "ann", &[])]).unwrap();
+        assert!(matches!(
+            serde_json::from_str::<StoredList>(&legacy).unwrap(),
+            StoredList::Legacy(posts) if posts.len() == 1
+        ));
+    }
+}
@@ -2,12 +2,24 @@
 //!
 //! This module provides functionality to publish, manage, and delete blog posts
 //! stored in Cloudflare KV. It supports parsing markdown files with YAML frontmatter.
+//! Other markdown collections (docs, changelogs, ...) are published with
+//! `ContentPublisher`, with blog posts as the built-in `blog` collection.
 
+pub mod assets;
+pub mod collection;
 pub mod error;
+pub mod feed;
+pub mod index;
+pub mod list;
 pub mod parser;
 pub mod publisher;
+pub mod render;
 pub mod types;
 
+pub use collection::{Collection, ContentEntry, ContentMeta, ContentPublisher};
 pub use error::{BlogError, Result};
+pub use feed::{FeedConfig, FeedFormat};
+pub use index::ListManifest;
 pub use publisher::BlogPublisher;
-pub use types::{BlogMeta, BlogPost};
+pub use render::{RenderMode, RenderOptions};
+pub use types::{BlogMeta, BlogPost, GcReport, SyncReport};
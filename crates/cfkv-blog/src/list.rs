@@ -0,0 +1,259 @@
+//! Paged entry lists shared by the blog list and content collections
+//!
+//! A list lives under its key as a small manifest, with the entries in
+//! fixed-size pages (`<key>:0`, `<key>:1`, ...). Lists written before
+//! pagination hold all entries as an array under the key; they are read
+//! as-is and converted on the next write, unless the list keeps that format
+//! for readers that depend on it.
+//!
+//! KV has no compare-and-swap, so `PagedList::modify` applies changes with
+//! optimistic concurrency (see its docs).
+
+use crate::error::{BlogError, Result};
+use crate::index::{self, KeyChange, ListManifest, StoredList, LIST_PAGE_SIZE};
+use cloudflare_kv::client::KvClient;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tracing::debug;
+
+const MAX_LIST_WRITE_ATTEMPTS: u32 = 5;
+
+/// A list as loaded from KV
+pub struct LoadedList<T> {
+    /// `None` for a legacy or missing list
+    pub manifest: Option<ListManifest>,
+    /// Whether the key holds a pre-pagination array
+    pub legacy: bool,
+    pub entries: Vec<T>,
+}
+
+impl<T> Default for LoadedList<T> {
+    fn default() -> Self {
+        Self {
+            manifest: None,
+            legacy: false,
+            entries: Vec::new(),
+        }
+    }
+}
+
+/// A paged list of entries under a key
+pub struct PagedList<'a> {
+    client: &'a KvClient,
+    key: String,
+    keep_legacy: bool,
+}
+
+impl<'a> PagedList<'a> {
+    /// The list stored under `key`
+    pub fn new(client: &'a KvClient, key: impl Into<String>) -> Self {
+        Self {
+            client,
+            key: key.into(),
+            keep_legacy: false,
+        }
+    }
+
+    /// Write a legacy list back in the legacy format instead of converting
+    /// it; `save` with an empty `original` converts it
+    pub fn keep_legacy(mut self) -> Self {
+        self.keep_legacy = true;
+        self
+    }
+
+    /// Key of a page of the list
+    pub fn page_key(&self, page: usize) -> String {
+        index::list_page_key(&self.key, page)
+    }
+
+    /// Read the value under the list key
+    pub(crate) async fn stored<T: DeserializeOwned>(&self) -> Result<Option<StoredList<T>>> {
+        match self.get_value(&self.key).await? {
+            Some(value) => Ok(Some(serde_json::from_str(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Read only the manifest; `None` when missing or a legacy list
+    pub async fn manifest(&self) -> Result<Option<ListManifest>> {
+        Ok(match self.stored::<serde_json::Value>().await? {
+            Some(StoredList::Paged(manifest)) => Some(manifest),
+            _ => None,
+        })
+    }
+
+    /// The manifest, made up for a legacy or missing list
+    pub async fn summary(&self) -> Result<ListManifest> {
+        Ok(match self.stored::<serde_json::Value>().await? {
+            None => index::manifest_for(0, 0),
+            Some(StoredList::Legacy(entries)) => index::manifest_for(entries.len(), 0),
+            Some(StoredList::Paged(manifest)) => manifest,
+        })
+    }
+
+    /// Load the manifest and every page
+    pub async fn load<T: DeserializeOwned>(&self) -> Result<LoadedList<T>> {
+        match self.stored().await? {
+            None => Ok(LoadedList::default()),
+            Some(StoredList::Legacy(entries)) => Ok(LoadedList {
+                manifest: None,
+                legacy: true,
+                entries,
+            }),
+            Some(StoredList::Paged(manifest)) => {
+                let mut entries = Vec::with_capacity(manifest.total);
+                for page in 0..manifest.pages {
+                    entries.extend(self.get_entries(&self.page_key(page)).await?);
+                }
+                Ok(LoadedList {
+                    manifest: Some(manifest),
+                    legacy: false,
+                    entries,
+                })
+            }
+        }
+    }
+
+    /// Get one page of entries, empty past the last page
+    pub async fn page<T: DeserializeOwned + Clone>(&self, page: usize) -> Result<Vec<T>> {
+        match self.stored().await? {
+            None => Ok(vec![]),
+            Some(StoredList::Legacy(entries)) => Ok(entries
+                .chunks(LIST_PAGE_SIZE)
+                .nth(page)
+                .map(|p| p.to_vec())
+                .unwrap_or_default()),
+            Some(StoredList::Paged(manifest)) if page < manifest.pages => {
+                self.get_entries(&self.page_key(page)).await
+            }
+            Some(StoredList::Paged(_)) => Ok(vec![]),
+        }
+    }
+
+    /// Write the pages that changed since `original` was loaded and the
+    /// `extra` changes computed from the old and new entries, followed by
+    /// the manifest
+    pub async fn save<T, X>(&self, original: &LoadedList<T>, entries: &[T], extra: X) -> Result<()>
+    where
+        T: Serialize + PartialEq,
+        X: Fn(&[T], &[T]) -> Result<Vec<KeyChange>>,
+    {
+        if original.legacy && self.keep_legacy {
+            self.put_value(&self.key, &serde_json::to_string(entries)?)
+                .await?;
+            debug!(
+                "Updated legacy list {} ({} entries)",
+                self.key,
+                entries.len()
+            );
+            return Ok(());
+        }
+
+        // A legacy list has no pages yet, so everything is written
+        let old: &[T] = match original.manifest {
+            Some(_) => &original.entries,
+            None => &[],
+        };
+        let mut changes = index::diff_pages(&self.key, old, entries)?;
+        changes.extend(extra(old, entries)?);
+        for change in changes {
+            match change {
+                KeyChange::Put(key, value) => self.put_value(&key, &value).await?,
+                KeyChange::Delete(key) => self
+                    .client
+                    .delete(&key)
+                    .await
+                    .map_err(|e| BlogError::KvError(e.to_string()))?,
+            }
+        }
+
+        let version = original.manifest.as_ref().map_or(0, |m| m.version) + 1;
+        let manifest = index::manifest_for(entries.len(), version);
+        self.put_value(&self.key, &serde_json::to_string(&manifest)?)
+            .await?;
+
+        debug!("Updated list {} ({} entries)", self.key, entries.len());
+        Ok(())
+    }
+
+    /// Apply a change to the list with optimistic concurrency
+    ///
+    /// The manifest version is checked before writing, and after a short
+    /// settle delay the list is reloaded to confirm the change survived
+    /// (re-applying `modify` must be a no-op). On a conflict the change is
+    /// re-applied to a freshly loaded list. `modify` must be repeatable and
+    /// return whether it changed anything; `sort` puts the entries in list
+    /// order afterwards, and `extra` is passed to `save`. Returns the entries
+    /// as they were before the successful write.
+    pub async fn modify<T, F, S, X>(&self, modify: F, sort: S, extra: X) -> Result<Vec<T>>
+    where
+        T: DeserializeOwned + Serialize + PartialEq + Clone,
+        F: Fn(&mut Vec<T>) -> bool,
+        S: Fn(&mut Vec<T>),
+        X: Fn(&[T], &[T]) -> Result<Vec<KeyChange>>,
+    {
+        for attempt in 0..MAX_LIST_WRITE_ATTEMPTS {
+            if attempt > 0 {
+                tokio::time::sleep(index::retry_delay(attempt)).await;
+                debug!("Retrying update of {} (attempt {})", self.key, attempt + 1);
+            }
+
+            let original = self.load::<T>().await?;
+            let mut entries = original.entries.clone();
+            if !modify(&mut entries) {
+                return Ok(original.entries);
+            }
+            sort(&mut entries);
+
+            // Verify before write
+            let version = |m: &Option<ListManifest>| m.as_ref().map_or(0, |m| m.version);
+            if version(&self.manifest().await?) != version(&original.manifest) {
+                continue;
+            }
+
+            let started = std::time::Instant::now();
+            self.save(&original, &entries, &extra).await?;
+
+            // Verify after write: give writers that checked the version before
+            // ours landed time to finish (their writes take about as long as
+            // ours did), then check the change is still in the pages. The
+            // manifest alone proves nothing as pages are written before it.
+            tokio::time::sleep(index::retry_delay(0) + started.elapsed() * 2).await;
+            let current = self.load::<T>().await?;
+            let mut check = current.entries.clone();
+            modify(&mut check);
+            sort(&mut check);
+            if check == current.entries {
+                return Ok(original.entries);
+            }
+        }
+
+        Err(BlogError::ConflictError(format!(
+            "{} was modified concurrently; gave up after {} attempts",
+            self.key, MAX_LIST_WRITE_ATTEMPTS
+        )))
+    }
+
+    /// Read a key holding an array of entries, empty if missing
+    pub(crate) async fn get_entries<T: DeserializeOwned>(&self, key: &str) -> Result<Vec<T>> {
+        match self.get_value(key).await? {
+            Some(value) => Ok(serde_json::from_str(&value)?),
+            None => Ok(vec![]),
+        }
+    }
+
+    async fn get_value(&self, key: &str) -> Result<Option<String>> {
+        self.client
+            .get(key)
+            .await
+            .map(|pair| pair.map(|p| p.value))
+            .map_err(|e| BlogError::KvError(e.to_string()))
+    }
+
+    async fn put_value(&self, key: &str, value: &str) -> Result<()> {
+        self.client
+            .put(key, value.as_bytes())
+            .await
+            .map_err(|e| BlogError::KvError(e.to_string()))
+    }
+}
@@ -1,8 +1,26 @@
 use crate::error::{BlogError, Result};
+use crate::types::BlogPost;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
 use regex::Regex;
 use serde_yaml::Value;
+use sha2::{Digest, Sha256};
 use std::collections::BTreeMap;
 
+/// Fields every post must have
+pub(crate) const REQUIRED_FIELDS: [&str; 5] = ["slug", "title", "description", "author", "date"];
+/// Fields mapped onto `BlogPost`; anything else is kept in `BlogPost::extra`
+const KNOWN_FIELDS: [&str; 9] = [
+    "slug",
+    "title",
+    "description",
+    "author",
+    "date",
+    "cover_image",
+    "tags",
+    "draft",
+    "publish_at",
+];
+
 /// Parsed markdown file with frontmatter and content
 #[derive(Debug)]
 pub struct ParsedMarkdown {
@@ -76,11 +94,131 @@ impl MarkdownParser {
         }
     }
 
+    /// Extract an optional boolean value from metadata, defaulting to false
+    pub fn get_bool(metadata: &BTreeMap<String, Value>, key: &str) -> Result<bool> {
+        match metadata.get(key) {
+            Some(Value::Bool(b)) => Ok(*b),
+            None | Some(Value::Null) => Ok(false),
+            _ => Err(BlogError::ValidationError(format!(
+                "Invalid format for field: {}",
+                key
+            ))),
+        }
+    }
+
+    /// Extract an optional timestamp from metadata
+    ///
+    /// Accepts RFC 3339 (`2025-01-15T09:00:00+02:00`), `YYYY-MM-DD HH:MM` in
+    /// UTC, or a bare `YYYY-MM-DD` meaning midnight UTC.
+    pub fn get_datetime(
+        metadata: &BTreeMap<String, Value>,
+        key: &str,
+    ) -> Result<Option<DateTime<Utc>>> {
+        let value = match metadata.get(key) {
+            None | Some(Value::Null) => return Ok(None),
+            Some(Value::String(s)) => s.trim(),
+            Some(_) => {
+                return Err(BlogError::ValidationError(format!(
+                    "Invalid format for field: {}",
+                    key
+                )))
+            }
+        };
+
+        if let Ok(at) = DateTime::parse_from_rfc3339(value) {
+            return Ok(Some(at.with_timezone(&Utc)));
+        }
+        if let Ok(at) = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M") {
+            return Ok(Some(at.and_utc()));
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+            return Ok(Some(
+                date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc(),
+            ));
+        }
+        Err(BlogError::ValidationError(format!(
+            "{} must be an RFC 3339 timestamp, YYYY-MM-DD HH:MM or YYYY-MM-DD",
+            key
+        )))
+    }
+
+    /// Hex-encoded SHA-256 of a markdown source file
+    pub fn content_hash(content: &str) -> String {
+        Sha256::digest(content.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// Parse and validate a markdown file into a blog post
+    pub fn parse_post(content: &str) -> Result<BlogPost> {
+        Self::parse_post_with_required(content, &[])
+    }
+
+    /// Parse a blog post, also requiring each of `required_fields` to be set
+    pub fn parse_post_with_required(content: &str, required_fields: &[String]) -> Result<BlogPost> {
+        let parsed = Self::parse(content)?;
+        Self::validate_metadata(&parsed.metadata)?;
+        Self::validate_required_fields(&parsed.metadata, required_fields)?;
+
+        Ok(BlogPost {
+            slug: Self::get_string(&parsed.metadata, "slug")?,
+            title: Self::get_string(&parsed.metadata, "title")?,
+            description: Self::get_string(&parsed.metadata, "description")?,
+            author: Self::get_string(&parsed.metadata, "author")?,
+            date: Self::get_string(&parsed.metadata, "date")?,
+            cover_image: Self::get_optional_string(&parsed.metadata, "cover_image"),
+            tags: Self::get_string_list(&parsed.metadata, "tags")?,
+            draft: Self::get_bool(&parsed.metadata, "draft")?,
+            publish_at: Self::get_datetime(&parsed.metadata, "publish_at")?,
+            extra: Self::extra_fields(&parsed.metadata)?,
+            content: parsed.content,
+            html: None,
+            content_hash: Some(Self::content_hash(content)),
+        })
+    }
+
+    /// Frontmatter fields that aren't part of the built-in schema, as JSON
+    pub fn extra_fields(
+        metadata: &BTreeMap<String, Value>,
+    ) -> Result<BTreeMap<String, serde_json::Value>> {
+        metadata
+            .iter()
+            .filter(|(key, _)| !KNOWN_FIELDS.contains(&key.as_str()))
+            .map(|(key, value)| {
+                let value = serde_json::to_value(value).map_err(|e| {
+                    BlogError::FrontmatterError(format!("Invalid value for field {}: {}", key, e))
+                })?;
+                Ok((key.clone(), value))
+            })
+            .collect()
+    }
+
+    /// Check that each field is present and not empty
+    pub fn validate_required_fields(
+        metadata: &BTreeMap<String, Value>,
+        required_fields: &[String],
+    ) -> Result<()> {
+        for field in required_fields {
+            let present = match metadata.get(field) {
+                None | Some(Value::Null) => false,
+                Some(Value::String(s)) => !s.is_empty(),
+                Some(Value::Sequence(seq)) => !seq.is_empty(),
+                Some(_) => true,
+            };
+            if !present {
+                return Err(BlogError::ValidationError(format!(
+                    "Missing required field: {}",
+                    field
+                )));
+            }
+        }
+        Ok(())
+    }
+
     /// Validate metadata has required fields
     pub fn validate_metadata(metadata: &BTreeMap<String, Value>) -> Result<()> {
-        let required = ["slug", "title", "description", "author", "date"];
-
-        for field in &required {
+        for field in &REQUIRED_FIELDS {
             if !metadata.contains_key(*field) {
                 return Err(BlogError::ValidationError(format!(
                     "Missing required field: {}",
@@ -100,12 +238,15 @@ impl MarkdownParser {
             ));
         }
 
-        // Validate slug format (lowercase, numbers, hyphens only)
-        let slug = Self::get_string(metadata, "slug")?;
+        Self::validate_slug(&Self::get_string(metadata, "slug")?)
+    }
+
+    /// Validate slug format (lowercase, numbers, hyphens only)
+    pub fn validate_slug(slug: &str) -> Result<()> {
         let slug_regex =
             Regex::new(r"^[a-z0-9-]+$").map_err(|e| BlogError::FrontmatterError(e.to_string()))?;
 
-        if !slug_regex.is_match(&slug) {
+        if !slug_regex.is_match(slug) {
             return Err(BlogError::ValidationError(
                 "Slug must contain only lowercase letters, numbers, and hyphens".to_string(),
             ));
@@ -204,6 +345,97 @@ Content only."#
         assert_eq!(tags, vec!["rust", "webdev"]);
     }
 
+    #[test]
+    fn test_parse_post() {
+        let post = MarkdownParser::parse_post(&sample_markdown()).unwrap();
+        assert_eq!(post.slug, "my-post");
+        assert_eq!(post.tags, vec!["rust", "webdev"]);
+        assert!(!post.draft);
+        assert!(post.content.starts_with("# Hello World"));
+        assert_eq!(
+            post.content_hash,
+            Some(MarkdownParser::content_hash(&sample_markdown()))
+        );
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let hash = MarkdownParser::content_hash("hello");
+        assert_eq!(
+            hash,
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert_ne!(hash, MarkdownParser::content_hash("hello!"));
+    }
+
+    #[test]
+    fn test_parse_post_draft() {
+        let markdown = sample_markdown().replacen("slug: my-post", "slug: my-post\ndraft: true", 1);
+        assert!(MarkdownParser::parse_post(&markdown).unwrap().draft);
+
+        let markdown =
+            sample_markdown().replacen("slug: my-post", "slug: my-post\ndraft: maybe", 1);
+        assert!(MarkdownParser::parse_post(&markdown).is_err());
+    }
+
+    #[test]
+    fn test_get_datetime() {
+        let parse = |value: &str| {
+            let markdown = sample_markdown().replacen(
+                "slug: my-post",
+                &format!("slug: my-post\npublish_at: {}", value),
+                1,
+            );
+            MarkdownParser::parse_post(&markdown).map(|p| p.publish_at)
+        };
+
+        let expected = "2025-03-01T08:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(parse("2025-03-01T09:00:00+01:00").unwrap(), Some(expected));
+        assert_eq!(parse("\"2025-03-01 08:00\"").unwrap(), Some(expected));
+        assert_eq!(
+            parse("2025-03-01").unwrap(),
+            Some("2025-03-01T00:00:00Z".parse().unwrap())
+        );
+        assert!(parse("next tuesday").is_err());
+        assert_eq!(
+            MarkdownParser::parse_post(&sample_markdown())
+                .unwrap()
+                .publish_at,
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_post_extra_fields() {
+        let markdown = sample_markdown().replacen(
+            "slug: my-post",
+            "slug: my-post\nseries: intro\nreading_time: 5\nsources:\n  - a\n  - b",
+            1,
+        );
+        let post = MarkdownParser::parse_post(&markdown).unwrap();
+
+        assert_eq!(post.extra.len(), 3);
+        assert_eq!(post.extra["series"], serde_json::json!("intro"));
+        assert_eq!(post.extra["reading_time"], serde_json::json!(5));
+        assert_eq!(post.extra["sources"], serde_json::json!(["a", "b"]));
+        assert!(!post.extra.contains_key("tags"));
+    }
+
+    #[test]
+    fn test_parse_post_with_required() {
+        let required = vec!["cover_image".to_string(), "series".to_string()];
+        let err = MarkdownParser::parse_post_with_required(&sample_markdown(), &required)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("series"));
+
+        let markdown = sample_markdown().replacen("slug: my-post", "slug: my-post\nseries: x", 1);
+        assert!(MarkdownParser::parse_post_with_required(&markdown, &required).is_ok());
+
+        let markdown = sample_markdown().replacen("slug: my-post", "slug: my-post\nseries: ''", 1);
+        assert!(MarkdownParser::parse_post_with_required(&markdown, &required).is_err());
+    }
+
     #[test]
     fn test_validate_metadata_valid() {
         let parsed = MarkdownParser::parse(&sample_markdown()).unwrap();
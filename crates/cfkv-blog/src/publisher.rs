@@ -1,71 +1,370 @@
+use crate::assets;
 use crate::error::{BlogError, Result};
+use crate::feed::{self, FeedConfig, FeedFormat};
+use crate::index::{self, ListManifest, StoredList, BLOG_LIST_KEY, INDEX_SIZE};
+use crate::list::{LoadedList, PagedList};
 use crate::parser::MarkdownParser;
-use crate::types::{BlogMeta, BlogPost};
+use crate::render::{self, RenderMode, RenderOptions};
+use crate::types::{BlogMeta, BlogPost, GcReport, SyncReport};
+use chrono::{DateTime, Utc};
 use cloudflare_kv::client::KvClient;
-use std::path::Path;
+use cloudflare_kv::PaginationParams;
+use std::path::{Path, PathBuf};
 use tracing::debug;
 
-const BLOG_LIST_KEY: &str = "_blog_list";
-const POST_KEY_PREFIX: &str = "post:";
+pub(crate) const POST_KEY_PREFIX: &str = "post:";
+pub(crate) const DRAFT_KEY_PREFIX: &str = "draft:";
+pub(crate) const SCHEDULED_KEY_PREFIX: &str = "scheduled:";
 
 /// Blog post publisher for managing blog posts in Cloudflare KV
 pub struct BlogPublisher<'a> {
     client: &'a KvClient,
+    render_mode: RenderMode,
+    render_options: RenderOptions,
+    required_fields: Vec<String>,
 }
 
 impl<'a> BlogPublisher<'a> {
     /// Create a new blog publisher
     pub fn new(client: &'a KvClient) -> Self {
-        Self { client }
+        Self {
+            client,
+            render_mode: RenderMode::default(),
+            render_options: RenderOptions::default(),
+            required_fields: Vec::new(),
+        }
+    }
+
+    /// Render post content to HTML when publishing
+    pub fn with_render(mut self, mode: RenderMode, options: RenderOptions) -> Self {
+        self.render_mode = mode;
+        self.render_options = options;
+        self
+    }
+
+    /// Reject posts missing any of these frontmatter fields, on top of the
+    /// built-in required ones
+    pub fn with_required_fields(mut self, fields: Vec<String>) -> Self {
+        self.required_fields = fields;
+        self
     }
 
     /// Publish a blog post from a markdown file
-    pub async fn publish_from_file(&self, file_path: &Path) -> Result<()> {
+    ///
+    /// Files with `draft: true` in their frontmatter are stored as drafts.
+    pub async fn publish_from_file(&self, file_path: &Path) -> Result<BlogPost> {
         debug!("Publishing blog post from: {}", file_path.display());
 
-        // Read file
-        let content = std::fs::read_to_string(file_path).map_err(BlogError::IoError)?;
+        self.publish_post(Self::read_post(file_path, &self.required_fields)?)
+            .await
+    }
 
-        // Parse markdown
-        let parsed = MarkdownParser::parse(&content)?;
-
-        // Validate metadata
-        MarkdownParser::validate_metadata(&parsed.metadata)?;
-
-        // Extract metadata
-        let slug = MarkdownParser::get_string(&parsed.metadata, "slug")?;
-        let title = MarkdownParser::get_string(&parsed.metadata, "title")?;
-        let description = MarkdownParser::get_string(&parsed.metadata, "description")?;
-        let author = MarkdownParser::get_string(&parsed.metadata, "author")?;
-        let date = MarkdownParser::get_string(&parsed.metadata, "date")?;
-        let cover_image = MarkdownParser::get_optional_string(&parsed.metadata, "cover_image");
-        let tags = MarkdownParser::get_string_list(&parsed.metadata, "tags")?;
-
-        // Create blog post
-        let post = BlogPost {
-            slug: slug.clone(),
-            title: title.clone(),
-            description: description.clone(),
-            author: author.clone(),
-            date: date.clone(),
-            cover_image: cover_image.clone(),
-            tags: tags.clone(),
-            content: parsed.content.clone(),
-        };
+    /// Publish a markdown file after uploading the local images it references
+    ///
+    /// The cover image and markdown images are resolved relative to
+    /// `assets_dir` and stored under `asset:<path>` before the post goes live.
+    /// Returns the post and the asset keys written.
+    pub async fn publish_with_assets(
+        &self,
+        file_path: &Path,
+        assets_dir: &Path,
+    ) -> Result<(BlogPost, Vec<String>)> {
+        debug!(
+            "Publishing blog post with assets from: {}",
+            file_path.display()
+        );
+
+        let post = Self::read_post(file_path, &self.required_fields)?;
+        let mut names: Vec<String> = Vec::new();
+        for reference in assets::referenced_assets(&post) {
+            let name = assets::asset_name(&reference)?;
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        if let Some(missing) = names.iter().find(|name| !assets_dir.join(name).is_file()) {
+            return Err(BlogError::NotFound(format!(
+                "asset '{}' in {}",
+                missing,
+                assets_dir.display()
+            )));
+        }
+
+        let mut keys = Vec::new();
+        for name in &names {
+            keys.push(self.upload_asset(&assets_dir.join(name), name).await?);
+        }
+
+        Ok((self.publish_post(post).await?, keys))
+    }
+
+    /// Store a local file under `asset:<name>` with its content type in the
+    /// key metadata, returning the key
+    pub async fn upload_asset(&self, path: &Path, name: &str) -> Result<String> {
+        let size = std::fs::metadata(path)
+            .map_err(|e| {
+                BlogError::NotFound(format!("asset '{}' ({}): {}", name, path.display(), e))
+            })?
+            .len();
+        if size > assets::MAX_ASSET_SIZE {
+            return Err(BlogError::ValidationError(format!(
+                "Asset '{}' is {} bytes, over the 25 MiB KV value limit",
+                name, size
+            )));
+        }
+
+        let key = format!("{}{}", assets::ASSET_KEY_PREFIX, name);
+        let bytes = std::fs::read(path)?;
+        self.client
+            .put_with_options(
+                &key,
+                &bytes,
+                None,
+                Some(serde_json::json!({ "content_type": assets::content_type(path) })),
+            )
+            .await
+            .map_err(|e| BlogError::KvError(e.to_string()))?;
+
+        debug!("Uploaded asset: {}", key);
+        Ok(key)
+    }
+
+    /// Render and store a parsed post, as a draft if it is marked as one and
+    /// as scheduled if its `publish_at` is in the future
+    async fn publish_post(&self, post: BlogPost) -> Result<BlogPost> {
+        let post = self.render(post);
+        if post.draft {
+            self.save_post(DRAFT_KEY_PREFIX, &post).await?;
+            debug!("Saved draft: {}", post.title);
+            return Ok(post);
+        }
+        if post.is_scheduled(Utc::now()) {
+            self.save_post(SCHEDULED_KEY_PREFIX, &post).await?;
+            // A published version stays hidden until the scheduled one is due
+            self.delete_key(POST_KEY_PREFIX, &post.slug).await?;
+            self.remove_from_blog_list(&post.slug).await?;
+            debug!("Scheduled post: {}", post.title);
+            return Ok(post);
+        }
 
         // Save post to KV
-        self.save_post(&post).await?;
+        self.save_post(POST_KEY_PREFIX, &post).await?;
 
         // Update blog list
         self.update_blog_list(&post.meta()).await?;
+        if post.publish_at.is_some() {
+            self.delete_key(SCHEDULED_KEY_PREFIX, &post.slug).await?;
+        }
+
+        debug!("Successfully published: {}", post.title);
+        Ok(post)
+    }
 
-        debug!("Successfully published: {}", title);
+    /// Store a markdown file as a draft, regardless of its `draft` field
+    pub async fn publish_draft(&self, file_path: &Path) -> Result<BlogPost> {
+        debug!("Saving draft from: {}", file_path.display());
+
+        let mut post = self.render(Self::read_post(file_path, &self.required_fields)?);
+        post.draft = true;
+        self.save_post(DRAFT_KEY_PREFIX, &post).await?;
+
+        debug!("Saved draft: {}", post.title);
+        Ok(post)
+    }
+
+    /// Publish a stored draft, replacing any published post with the same slug
+    pub async fn promote(&self, slug: &str) -> Result<BlogPost> {
+        let mut post = self
+            .get_draft(slug)
+            .await?
+            .ok_or_else(|| BlogError::NotFound(format!("draft '{}'", slug)))?;
+        post.draft = false;
+
+        self.save_post(POST_KEY_PREFIX, &post).await?;
+        self.update_blog_list(&post.meta()).await?;
+        self.delete_key(DRAFT_KEY_PREFIX, slug).await?;
+
+        debug!("Promoted draft: {}", slug);
+        Ok(post)
+    }
+
+    /// Move a published post back to drafts
+    pub async fn unpublish(&self, slug: &str) -> Result<BlogPost> {
+        let mut post = self
+            .get_post(slug)
+            .await?
+            .ok_or_else(|| BlogError::NotFound(format!("post '{}'", slug)))?;
+        post.draft = true;
+
+        self.save_post(DRAFT_KEY_PREFIX, &post).await?;
+        self.delete_key(POST_KEY_PREFIX, slug).await?;
+        self.remove_from_blog_list(slug).await?;
+
+        debug!("Unpublished post: {}", slug);
+        Ok(post)
+    }
+
+    /// Publish every markdown file under a directory
+    ///
+    /// Posts whose content hash matches the blog list are skipped. Published
+    /// posts whose file is now a draft, or scheduled for later, are taken off
+    /// the blog list, like `unpublish`. With `prune`, published posts whose slug no longer has a
+    /// source file are deleted. The blog list is written once at the end.
+    pub async fn publish_dir(&self, dir: &Path, prune: bool) -> Result<SyncReport> {
+        let mut files = Vec::new();
+        Self::collect_markdown_files(dir, &mut files)?;
+        files.sort();
+
+        let mut posts: Vec<BlogPost> = Vec::with_capacity(files.len());
+        for file in &files {
+            let post = Self::read_post(file, &self.required_fields).map_err(|e| match e {
+                BlogError::ValidationError(msg) | BlogError::FrontmatterError(msg) => {
+                    BlogError::ValidationError(format!("{}: {}", file.display(), msg))
+                }
+                other => other,
+            })?;
+            if posts.iter().any(|p| p.slug == post.slug) {
+                return Err(BlogError::ValidationError(format!(
+                    "Duplicate slug '{}' in {}",
+                    post.slug,
+                    file.display()
+                )));
+            }
+            posts.push(self.render(post));
+        }
+
+        let existing = self.get_blog_list().await?;
+        let mut report = SyncReport::default();
+        let mut changed: Vec<BlogMeta> = Vec::new();
+        let now = Utc::now();
+
+        for post in &posts {
+            if post.draft {
+                self.save_post(DRAFT_KEY_PREFIX, post).await?;
+                report.drafts += 1;
+                continue;
+            }
+            if post.is_scheduled(now) {
+                self.save_post(SCHEDULED_KEY_PREFIX, post).await?;
+                report.scheduled += 1;
+                continue;
+            }
+
+            match existing.iter().find(|m| m.slug == post.slug) {
+                Some(entry) if entry.content_hash == post.content_hash => {
+                    report.unchanged += 1;
+                    continue;
+                }
+                Some(_) => report.updated += 1,
+                None => report.created += 1,
+            }
+
+            self.save_post(POST_KEY_PREFIX, post).await?;
+            if post.publish_at.is_some() {
+                self.delete_key(SCHEDULED_KEY_PREFIX, &post.slug).await?;
+            }
+            changed.push(post.meta());
+        }
+
+        // Stale slugs are decided against the list actually written, so a post
+        // published concurrently from elsewhere is pruned consistently
+        let is_stale = |m: &BlogMeta| prune && !posts.iter().any(|p| p.slug == m.slug);
+        let is_withheld = |m: &BlogMeta| {
+            posts
+                .iter()
+                .any(|p| (p.draft || p.is_scheduled(now)) && p.slug == m.slug)
+        };
+        let before = self
+            .modify_blog_list(|blog_list| {
+                for meta in &changed {
+                    Self::upsert_meta(blog_list, meta);
+                }
+                let original_len = blog_list.len();
+                blog_list.retain(|m| !is_stale(m) && !is_withheld(m));
+                !changed.is_empty() || blog_list.len() < original_len
+            })
+            .await?;
+
+        let withdrawn: Vec<String> = before
+            .iter()
+            .filter(|m| is_withheld(m))
+            .map(|m| m.slug.clone())
+            .collect();
+        for slug in &withdrawn {
+            self.delete_key(POST_KEY_PREFIX, slug).await?;
+            debug!("Unpublished post: {}", slug);
+        }
+        report.unpublished = withdrawn;
+
+        let stale: Vec<String> = before
+            .iter()
+            .filter(|m| is_stale(m))
+            .map(|m| m.slug.clone())
+            .collect();
+        for slug in &stale {
+            self.delete_key(POST_KEY_PREFIX, slug).await?;
+            debug!("Pruned post: {}", slug);
+        }
+        report.deleted = stale;
+        report.published = changed;
+
+        Ok(report)
+    }
+
+    /// Recursively collect `.md` files under a directory
+    fn collect_markdown_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                Self::collect_markdown_files(&path, files)?;
+            } else if path.extension().is_some_and(|e| e == "md") {
+                files.push(path);
+            }
+        }
         Ok(())
     }
 
-    /// Save a blog post to KV
-    async fn save_post(&self, post: &BlogPost) -> Result<()> {
-        let key = format!("{}{}", POST_KEY_PREFIX, post.slug);
+    /// Apply the configured render mode to a parsed post
+    fn render(&self, post: BlogPost) -> BlogPost {
+        render::render_post(post, self.render_mode, &self.render_options)
+    }
+
+    /// Parse, validate and render a markdown file exactly as it would be
+    /// stored, without touching KV
+    pub fn preview(
+        file_path: &Path,
+        mode: RenderMode,
+        options: &RenderOptions,
+        required_fields: &[String],
+    ) -> Result<BlogPost> {
+        Ok(render::render_post(
+            Self::read_post(file_path, required_fields)?,
+            mode,
+            options,
+        ))
+    }
+
+    /// KV key a post is stored under
+    pub fn storage_key(post: &BlogPost) -> String {
+        let prefix = if post.draft {
+            DRAFT_KEY_PREFIX
+        } else if post.is_scheduled(Utc::now()) {
+            SCHEDULED_KEY_PREFIX
+        } else {
+            POST_KEY_PREFIX
+        };
+        format!("{}{}", prefix, post.slug)
+    }
+
+    /// Read and parse a markdown file
+    fn read_post(file_path: &Path, required_fields: &[String]) -> Result<BlogPost> {
+        let content = std::fs::read_to_string(file_path).map_err(BlogError::IoError)?;
+        MarkdownParser::parse_post_with_required(&content, required_fields)
+    }
+
+    /// Save a blog post to KV under the given key prefix
+    async fn save_post(&self, prefix: &str, post: &BlogPost) -> Result<()> {
+        let key = format!("{}{}", prefix, post.slug);
         let value = serde_json::to_string(post).map_err(BlogError::JsonError)?;
 
         self.client
@@ -73,13 +372,29 @@ impl<'a> BlogPublisher<'a> {
             .await
             .map_err(|e| BlogError::KvError(e.to_string()))?;
 
-        debug!("Saved post content for: {}", post.slug);
+        debug!("Saved post content for: {}", key);
         Ok(())
     }
 
+    async fn delete_key(&self, prefix: &str, slug: &str) -> Result<()> {
+        self.client
+            .delete(&format!("{}{}", prefix, slug))
+            .await
+            .map_err(|e| BlogError::KvError(e.to_string()))
+    }
+
     /// Get a blog post by slug
     pub async fn get_post(&self, slug: &str) -> Result<Option<BlogPost>> {
-        let key = format!("{}{}", POST_KEY_PREFIX, slug);
+        self.load_post(POST_KEY_PREFIX, slug).await
+    }
+
+    /// Get a draft by slug
+    pub async fn get_draft(&self, slug: &str) -> Result<Option<BlogPost>> {
+        self.load_post(DRAFT_KEY_PREFIX, slug).await
+    }
+
+    async fn load_post(&self, prefix: &str, slug: &str) -> Result<Option<BlogPost>> {
+        let key = format!("{}{}", prefix, slug);
 
         match self.client.get(&key).await {
             Ok(Some(kv_pair)) => {
@@ -94,13 +409,9 @@ impl<'a> BlogPublisher<'a> {
 
     /// Delete a blog post by slug
     pub async fn delete_post(&self, slug: &str) -> Result<()> {
-        let key = format!("{}{}", POST_KEY_PREFIX, slug);
-
-        // Delete the post
-        self.client
-            .delete(&key)
-            .await
-            .map_err(|e| BlogError::KvError(e.to_string()))?;
+        // Delete the post, and any scheduled version that would bring it back
+        self.delete_key(POST_KEY_PREFIX, slug).await?;
+        self.delete_key(SCHEDULED_KEY_PREFIX, slug).await?;
 
         debug!("Deleted post content for: {}", slug);
 
@@ -112,35 +423,252 @@ impl<'a> BlogPublisher<'a> {
 
     /// Get all blog posts (metadata only)
     pub async fn list_posts(&self) -> Result<Vec<BlogMeta>> {
-        match self.get_blog_list().await {
-            Ok(posts) => Ok(posts),
-            Err(e) => {
-                if e.to_string().contains("not found") {
-                    Ok(vec![])
-                } else {
-                    Err(e)
+        self.get_blog_list().await
+    }
+
+    /// Get one page of the blog list (newest first), empty past the last page
+    pub async fn list_posts_page(&self, page: usize) -> Result<Vec<BlogMeta>> {
+        self.blog_list().page(page).await
+    }
+
+    /// Get the blog list manifest (page count and totals)
+    pub async fn list_manifest(&self) -> Result<ListManifest> {
+        self.blog_list().summary().await
+    }
+
+    /// Build a feed or sitemap from the blog list, optionally storing it in KV
+    ///
+    /// The stored copy goes under the format's key (e.g. `_blog_feed.xml`)
+    /// with its content type in the key metadata.
+    pub async fn generate_feed(
+        &self,
+        format: FeedFormat,
+        config: &FeedConfig,
+        store: bool,
+    ) -> Result<String> {
+        let posts = self.list_posts().await?;
+        let xml = feed::build_feed(&posts, format, config);
+
+        if store {
+            self.client
+                .put_with_options(
+                    format.storage_key(),
+                    xml.as_bytes(),
+                    None,
+                    Some(serde_json::json!({ "content_type": format.content_type() })),
+                )
+                .await
+                .map_err(|e| BlogError::KvError(e.to_string()))?;
+            debug!("Stored feed under: {}", format.storage_key());
+        }
+
+        Ok(xml)
+    }
+
+    /// Get posts waiting for their `publish_at`, soonest first
+    pub async fn list_scheduled(&self) -> Result<Vec<BlogPost>> {
+        let mut posts = Vec::new();
+        for key in self.list_keys(SCHEDULED_KEY_PREFIX).await? {
+            let slug = &key[SCHEDULED_KEY_PREFIX.len()..];
+            if let Some(post) = self.load_post(SCHEDULED_KEY_PREFIX, slug).await? {
+                posts.push(post);
+            }
+        }
+        posts.sort_by_key(|p| p.publish_at);
+        Ok(posts)
+    }
+
+    /// Publish every scheduled post whose `publish_at` is at or before `now`
+    ///
+    /// Released posts are added to the blog list in a single update. Returns
+    /// the released posts.
+    pub async fn release_due(&self, now: DateTime<Utc>) -> Result<Vec<BlogPost>> {
+        let due: Vec<BlogPost> = self
+            .list_scheduled()
+            .await?
+            .into_iter()
+            .filter(|p| !p.is_scheduled(now))
+            .collect();
+        if due.is_empty() {
+            return Ok(due);
+        }
+
+        for post in &due {
+            self.save_post(POST_KEY_PREFIX, post).await?;
+        }
+        self.modify_blog_list(|blog_list| {
+            for post in &due {
+                Self::upsert_meta(blog_list, &post.meta());
+            }
+            true
+        })
+        .await?;
+        for post in &due {
+            self.delete_key(SCHEDULED_KEY_PREFIX, &post.slug).await?;
+            debug!("Released scheduled post: {}", post.slug);
+        }
+
+        Ok(due)
+    }
+
+    /// Cross-check the `post:` keys against the blog list
+    ///
+    /// Reports posts stored but missing from the list and list entries whose
+    /// post key is gone. With `fix`, unlisted posts are added to the list and
+    /// missing entries removed from it, in a single update. Post keys that
+    /// do not hold a valid post are only reported. A listed post missing
+    /// from the key listing is read directly before being reported, as the
+    /// listing may lag behind recent writes.
+    pub async fn gc(&self, fix: bool) -> Result<GcReport> {
+        let stored: Vec<String> = self
+            .list_keys(POST_KEY_PREFIX)
+            .await?
+            .into_iter()
+            .map(|key| key[POST_KEY_PREFIX.len()..].to_string())
+            .collect();
+        let listed = self.get_blog_list().await?;
+        let mut report = GcReport::default();
+
+        for slug in &stored {
+            if listed.iter().any(|m| &m.slug == slug) {
+                continue;
+            }
+            match self.get_post(slug).await {
+                Ok(Some(post)) => {
+                    report.unlisted.push(slug.clone());
+                    report.relisted.push(post.meta());
                 }
+                // Deleted since the keys were listed
+                Ok(None) => {}
+                Err(BlogError::JsonError(_)) => report.unreadable.push(slug.clone()),
+                Err(e) => return Err(e),
+            }
+        }
+        for meta in &listed {
+            if stored.contains(&meta.slug) {
+                continue;
+            }
+            let key = format!("{}{}", POST_KEY_PREFIX, meta.slug);
+            if self.get_value(&key).await?.is_none() {
+                report.missing.push(meta.slug.clone());
             }
         }
+
+        if fix && !(report.relisted.is_empty() && report.missing.is_empty()) {
+            self.modify_blog_list(|blog_list| {
+                let original_len = blog_list.len();
+                blog_list.retain(|m| !report.missing.contains(&m.slug));
+                let mut changed = blog_list.len() < original_len;
+                for meta in &report.relisted {
+                    if !blog_list.iter().any(|m| m.slug == meta.slug) {
+                        Self::upsert_meta(blog_list, meta);
+                        changed = true;
+                    }
+                }
+                changed
+            })
+            .await?;
+            report.fixed = true;
+            debug!(
+                "Repaired blog list: {} added, {} removed",
+                report.unlisted.len(),
+                report.missing.len()
+            );
+        }
+
+        Ok(report)
     }
 
-    /// Get the blog list from KV
-    async fn get_blog_list(&self) -> Result<Vec<BlogMeta>> {
-        match self.client.get(BLOG_LIST_KEY).await {
-            Ok(Some(kv_pair)) => {
-                let posts: Vec<BlogMeta> =
-                    serde_json::from_str(&kv_pair.value).map_err(BlogError::JsonError)?;
-                Ok(posts)
+    /// All key names starting with a prefix
+    async fn list_keys(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut keys = Vec::new();
+        let mut cursor = None;
+        loop {
+            let params = PaginationParams {
+                prefix: Some(prefix.to_string()),
+                cursor,
+                ..PaginationParams::new()
+            };
+            let response = self
+                .client
+                .list(Some(params))
+                .await
+                .map_err(|e| BlogError::KvError(e.to_string()))?;
+            keys.extend(response.keys.into_iter().map(|k| k.name));
+            match response.cursor {
+                Some(next) if !response.list_complete && !next.is_empty() => cursor = Some(next),
+                _ => break,
             }
-            Ok(None) => Ok(vec![]),
-            Err(e) => Err(BlogError::KvError(e.to_string())),
         }
+        Ok(keys)
+    }
+
+    /// Get published posts with a tag, newest first
+    pub async fn list_posts_by_tag(&self, tag: &str) -> Result<Vec<BlogMeta>> {
+        self.list_index(&index::tag_key(tag), |p| p.tags.iter().any(|t| t == tag))
+            .await
+    }
+
+    /// Get published posts by an author, newest first
+    pub async fn list_posts_by_author(&self, author: &str) -> Result<Vec<BlogMeta>> {
+        self.list_index(&index::author_key(author), |p| p.author == author)
+            .await
+    }
+
+    /// Read an index key, filtering the full list if it predates indexes or
+    /// the index is full and may be missing older posts
+    async fn list_index(
+        &self,
+        key: &str,
+        filter: impl Fn(&BlogMeta) -> bool,
+    ) -> Result<Vec<BlogMeta>> {
+        let list = self.blog_list();
+        match list.stored::<BlogMeta>().await? {
+            None => Ok(vec![]),
+            Some(StoredList::Legacy(posts)) => {
+                Ok(posts.into_iter().filter(|p| filter(p)).collect())
+            }
+            Some(StoredList::Paged(_)) => {
+                let posts: Vec<BlogMeta> = list.get_entries(key).await?;
+                if posts.len() < INDEX_SIZE {
+                    return Ok(posts);
+                }
+                let posts = self.get_blog_list().await?;
+                Ok(posts.into_iter().filter(|p| filter(p)).collect())
+            }
+        }
+    }
+
+    async fn get_value(&self, key: &str) -> Result<Option<String>> {
+        self.client
+            .get(key)
+            .await
+            .map(|pair| pair.map(|p| p.value))
+            .map_err(|e| BlogError::KvError(e.to_string()))
+    }
+
+    /// The blog list, kept in the legacy format until `migrate_list`
+    fn blog_list(&self) -> PagedList<'a> {
+        PagedList::new(self.client, BLOG_LIST_KEY).keep_legacy()
+    }
+
+    /// Get the blog list from KV
+    async fn get_blog_list(&self) -> Result<Vec<BlogMeta>> {
+        Ok(self.blog_list().load().await?.entries)
     }
 
     /// Update the blog list after publishing a post
     async fn update_blog_list(&self, post_meta: &BlogMeta) -> Result<()> {
-        let mut blog_list = self.get_blog_list().await?;
+        self.modify_blog_list(|blog_list| {
+            Self::upsert_meta(blog_list, post_meta);
+            true
+        })
+        .await?;
+        Ok(())
+    }
 
+    /// Insert or replace a post's entry in the blog list
+    fn upsert_meta(blog_list: &mut Vec<BlogMeta>, post_meta: &BlogMeta) {
         // Check if post already exists
         if let Some(pos) = blog_list.iter().position(|p| p.slug == post_meta.slug) {
             blog_list[pos] = post_meta.clone();
@@ -149,40 +677,61 @@ impl<'a> BlogPublisher<'a> {
             blog_list.insert(0, post_meta.clone()); // Insert at beginning (newest first)
             debug!("Added new entry to blog list");
         }
+    }
 
-        // Sort by date (newest first)
-        blog_list.sort_by(|a, b| b.date.cmp(&a.date));
-
-        // Save updated list
-        let list_json = serde_json::to_string(&blog_list).map_err(BlogError::JsonError)?;
-
-        self.client
-            .put(BLOG_LIST_KEY, list_json.as_bytes())
+    /// Apply a change to the blog list, kept sorted newest first, and update
+    /// the indexes (see `PagedList::modify`)
+    ///
+    /// Returns the list as it was before the successful write.
+    async fn modify_blog_list<F>(&self, modify: F) -> Result<Vec<BlogMeta>>
+    where
+        F: Fn(&mut Vec<BlogMeta>) -> bool,
+    {
+        self.blog_list()
+            .modify(
+                modify,
+                |list| Self::sort_blog_list(list),
+                index::diff_indexes,
+            )
             .await
-            .map_err(|e| BlogError::KvError(e.to_string()))?;
+    }
 
-        debug!("Updated blog list ({} posts)", blog_list.len());
-        Ok(())
+    /// Sort the blog list by date (newest first)
+    fn sort_blog_list(blog_list: &mut [BlogMeta]) {
+        blog_list.sort_by(|a, b| b.date.cmp(&a.date));
+    }
+
+    /// Convert a blog list written before pagination (a plain array under
+    /// `_blog_list`) to pages, a manifest and indexes
+    ///
+    /// Until then such a list is kept in its format, so Workers reading
+    /// `_blog_list` directly keep working; update them before converting.
+    /// Returns the number of posts converted, `None` if there was no legacy
+    /// list.
+    pub async fn migrate_list(&self) -> Result<Option<usize>> {
+        let list = self.blog_list();
+        let mut loaded = list.load::<BlogMeta>().await?;
+        if !loaded.legacy {
+            return Ok(None);
+        }
+        let total = loaded.entries.len();
+        Self::sort_blog_list(&mut loaded.entries);
+        list.save(&LoadedList::default(), &loaded.entries, index::diff_indexes)
+            .await?;
+        debug!("Converted legacy blog list ({} posts)", total);
+        Ok(Some(total))
     }
 
     /// Remove a post from the blog list
     async fn remove_from_blog_list(&self, slug: &str) -> Result<()> {
-        let mut blog_list = self.get_blog_list().await?;
-        let original_len = blog_list.len();
-
-        blog_list.retain(|p| p.slug != slug);
-
-        if blog_list.len() < original_len {
-            let list_json = serde_json::to_string(&blog_list).map_err(BlogError::JsonError)?;
-
-            self.client
-                .put(BLOG_LIST_KEY, list_json.as_bytes())
-                .await
-                .map_err(|e| BlogError::KvError(e.to_string()))?;
-
-            debug!("Removed post from blog list");
-        }
+        self.modify_blog_list(|blog_list| {
+            let original_len = blog_list.len();
+            blog_list.retain(|p| p.slug != slug);
+            blog_list.len() < original_len
+        })
+        .await?;
 
+        debug!("Removed post from blog list");
         Ok(())
     }
 }
@@ -215,6 +764,289 @@ mod tests {
         assert_eq!(POST_KEY_PREFIX, "post:");
     }
 
+    #[test]
+    fn test_draft_key_prefix_constant() {
+        assert_eq!(DRAFT_KEY_PREFIX, "draft:");
+        assert_ne!(DRAFT_KEY_PREFIX, POST_KEY_PREFIX);
+    }
+
+    #[test]
+    fn test_read_post_from_file() {
+        let path = std::env::temp_dir().join("cfkv-blog-read-post.md");
+        std::fs::write(
+            &path,
+            "---\nslug: wip\ntitle: WIP\ndescription: d\nauthor: a\ndate: 2025-01-15\ndraft: true\n---\nBody",
+        )
+        .unwrap();
+        let post = BlogPublisher::read_post(&path, &[]).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(post.slug, "wip");
+        assert!(post.draft);
+    }
+
+    #[tokio::test]
+    async fn test_publish_and_delete_in_memory() {
+        let client = KvClient::in_memory();
+        let publisher = BlogPublisher::new(&client);
+        let path = std::env::temp_dir().join("cfkv-blog-in-memory.md");
+        std::fs::write(
+            &path,
+            "---\nslug: hello\ntitle: Hello\ndescription: d\nauthor: a\ndate: 2025-01-15\n---\nBody",
+        )
+        .unwrap();
+        let published = publisher.publish_from_file(&path).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let stored = publisher.get_post("hello").await.unwrap().unwrap();
+        assert_eq!(stored.title, published.title);
+        let list = publisher.list_posts().await.unwrap();
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].slug, "hello");
+
+        publisher.delete_post("hello").await.unwrap();
+        assert!(publisher.get_post("hello").await.unwrap().is_none());
+        assert!(publisher.list_posts().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_gc() {
+        let client = KvClient::in_memory();
+        let publisher = BlogPublisher::new(&client);
+        for slug in ["kept", "unlisted", "missing"] {
+            let path = std::env::temp_dir().join(format!("cfkv-blog-gc-{}.md", slug));
+            std::fs::write(
+                &path,
+                format!(
+                    "---\nslug: {}\ntitle: T\ndescription: d\nauthor: a\ndate: 2025-01-15\n---\nBody",
+                    slug
+                ),
+            )
+            .unwrap();
+            publisher.publish_from_file(&path).await.unwrap();
+            std::fs::remove_file(&path).unwrap();
+        }
+        // Break the list both ways, and store a post that is not one
+        publisher.remove_from_blog_list("unlisted").await.unwrap();
+        client.delete("post:missing").await.unwrap();
+        client.put("post:broken", "not json").await.unwrap();
+
+        let report = publisher.gc(false).await.unwrap();
+        assert_eq!(report.unlisted, ["unlisted"]);
+        assert_eq!(report.missing, ["missing"]);
+        assert_eq!(report.unreadable, ["broken"]);
+        assert!(!report.fixed && !report.is_clean());
+        assert_eq!(publisher.list_posts().await.unwrap().len(), 2);
+
+        let report = publisher.gc(true).await.unwrap();
+        assert!(report.fixed);
+        assert_eq!(report.relisted[0].slug, "unlisted");
+        let mut slugs: Vec<String> = publisher
+            .list_posts()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|m| m.slug)
+            .collect();
+        slugs.sort();
+        assert_eq!(slugs, ["kept", "unlisted"]);
+
+        client.delete("post:broken").await.unwrap();
+        assert!(publisher.gc(true).await.unwrap().is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_publish_dir_unpublishes_new_drafts() {
+        let client = KvClient::in_memory();
+        let publisher = BlogPublisher::new(&client);
+        let dir = std::env::temp_dir().join("cfkv-blog-publish-dir-drafts");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |slug: &str, draft: bool| {
+            std::fs::write(
+                dir.join(format!("{}.md", slug)),
+                format!(
+                    "---\nslug: {}\ntitle: T\ndescription: d\nauthor: a\ndate: 2025-01-15\ndraft: {}\n---\nBody",
+                    slug, draft
+                ),
+            )
+            .unwrap();
+        };
+        write("kept", false);
+        write("shelved", false);
+        publisher.publish_dir(&dir, false).await.unwrap();
+
+        write("shelved", true);
+        let report = publisher.publish_dir(&dir, false).await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(report.unpublished, ["shelved"]);
+        assert_eq!(report.drafts, 1);
+        assert!(publisher.get_post("shelved").await.unwrap().is_none());
+        assert!(publisher.get_draft("shelved").await.unwrap().is_some());
+        let list = publisher.list_posts().await.unwrap();
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].slug, "kept");
+    }
+
+    #[tokio::test]
+    async fn test_scheduling_hides_published_post() {
+        let client = KvClient::in_memory();
+        let publisher = BlogPublisher::new(&client);
+        let path = std::env::temp_dir().join("cfkv-blog-reschedule.md");
+        let source = "---\nslug: later\ntitle: T\ndescription: d\nauthor: a\ndate: 2025-01-15\n";
+        std::fs::write(&path, format!("{}---\nBody", source)).unwrap();
+        publisher.publish_from_file(&path).await.unwrap();
+
+        std::fs::write(
+            &path,
+            format!("{}publish_at: 2999-01-01\n---\nBody", source),
+        )
+        .unwrap();
+        publisher.publish_from_file(&path).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(publisher.get_post("later").await.unwrap().is_none());
+        assert!(publisher.list_posts().await.unwrap().is_empty());
+        assert_eq!(publisher.list_scheduled().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_legacy_list_kept_until_migrated() {
+        let client = KvClient::in_memory();
+        let publisher = BlogPublisher::new(&client);
+        let old = BlogMeta {
+            slug: "old".to_string(),
+            title: "Old".to_string(),
+            description: "d".to_string(),
+            author: "a".to_string(),
+            date: "2024-01-01".to_string(),
+            cover_image: None,
+            tags: vec!["rust".to_string()],
+            publish_at: None,
+            content_hash: None,
+        };
+        client
+            .put(BLOG_LIST_KEY, serde_json::to_string(&vec![old]).unwrap())
+            .await
+            .unwrap();
+
+        let path = std::env::temp_dir().join("cfkv-blog-legacy-list.md");
+        std::fs::write(
+            &path,
+            "---\nslug: new\ntitle: New\ndescription: d\nauthor: a\ndate: 2025-01-15\ntags: [rust]\n---\nBody",
+        )
+        .unwrap();
+        publisher.publish_from_file(&path).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // Still a plain array, newest first, with no pages or indexes
+        let stored = client.get(BLOG_LIST_KEY).await.unwrap().unwrap().value;
+        let stored: Vec<BlogMeta> = serde_json::from_str(&stored).unwrap();
+        assert_eq!(stored[0].slug, "new");
+        assert_eq!(stored.len(), 2);
+        assert!(client.get("tag:rust").await.unwrap().is_none());
+
+        assert_eq!(publisher.migrate_list().await.unwrap(), Some(2));
+        assert_eq!(publisher.list_manifest().await.unwrap().total, 2);
+        assert_eq!(publisher.list_posts_by_tag("rust").await.unwrap().len(), 2);
+        assert_eq!(publisher.list_posts().await.unwrap(), stored);
+        assert_eq!(publisher.migrate_list().await.unwrap(), None);
+    }
+
+    #[test]
+    fn test_collect_markdown_files() {
+        let dir = std::env::temp_dir().join("cfkv-blog-collect");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        std::fs::write(dir.join("a.md"), "").unwrap();
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+        std::fs::write(dir.join("nested/b.md"), "").unwrap();
+
+        let mut files = Vec::new();
+        BlogPublisher::collect_markdown_files(&dir, &mut files).unwrap();
+        files.sort();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(files, vec![dir.join("a.md"), dir.join("nested/b.md")]);
+    }
+
+    #[test]
+    fn test_upsert_meta() {
+        let meta = |slug: &str, hash: &str| BlogMeta {
+            slug: slug.to_string(),
+            title: slug.to_string(),
+            description: String::new(),
+            author: String::new(),
+            date: "2025-01-15".to_string(),
+            cover_image: None,
+            tags: vec![],
+            publish_at: None,
+            content_hash: Some(hash.to_string()),
+        };
+        let mut list = vec![meta("a", "1")];
+        BlogPublisher::upsert_meta(&mut list, &meta("b", "1"));
+        BlogPublisher::upsert_meta(&mut list, &meta("a", "2"));
+
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].slug, "b");
+        assert_eq!(list[1].content_hash.as_deref(), Some("2"));
+    }
+
+    #[test]
+    fn test_render_modes() {
+        let client = create_test_client();
+        let post = MarkdownParser::parse_post(
+            "---\nslug: p\ntitle: P\ndescription: d\nauthor: a\ndate: 2025-01-15\n---\n# Hi",
+        )
+        .unwrap();
+
+        let markdown = BlogPublisher::new(&client).render(post.clone());
+        assert_eq!(markdown.content, "# Hi");
+        assert!(markdown.html.is_none());
+
+        let both = BlogPublisher::new(&client)
+            .with_render(RenderMode::Both, RenderOptions::default())
+            .render(post.clone());
+        assert_eq!(both.content, "# Hi");
+        assert_eq!(both.html.as_deref(), Some("<h1>Hi</h1>\n"));
+
+        let html = BlogPublisher::new(&client)
+            .with_render(RenderMode::Html, RenderOptions::default())
+            .render(post);
+        assert!(html.content.is_empty());
+        assert!(html.html.is_some());
+    }
+
+    #[test]
+    fn test_preview_and_storage_key() {
+        let path = std::env::temp_dir().join("cfkv-blog-preview.md");
+        std::fs::write(
+            &path,
+            "---\nslug: hello\ntitle: Hello\ndescription: d\nauthor: a\ndate: 2025-01-15\n---\n# Hi",
+        )
+        .unwrap();
+        let post = BlogPublisher::preview(&path, RenderMode::Both, &RenderOptions::default(), &[])
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(post.html.as_deref(), Some("<h1>Hi</h1>\n"));
+        assert_eq!(BlogPublisher::storage_key(&post), "post:hello");
+
+        let draft = BlogPost {
+            draft: true,
+            ..post
+        };
+        assert_eq!(BlogPublisher::storage_key(&draft), "draft:hello");
+
+        let scheduled = BlogPost {
+            draft: false,
+            publish_at: Some(Utc::now() + chrono::Duration::hours(1)),
+            ..draft
+        };
+        assert_eq!(BlogPublisher::storage_key(&scheduled), "scheduled:hello");
+    }
+
     #[test]
     fn test_post_key_format() {
         let slug = "my-post";
@@ -0,0 +1,174 @@
+use crate::error::BlogError;
+use crate::types::BlogPost;
+use pulldown_cmark::{html, CodeBlockKind, Event, Options, Parser, Tag};
+use std::str::FromStr;
+
+/// How post content is stored in KV
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Store the raw markdown only
+    #[default]
+    Markdown,
+    /// Store rendered HTML instead of the markdown
+    Html,
+    /// Store both the markdown and the rendered HTML
+    Both,
+}
+
+impl RenderMode {
+    /// Name used on the command line
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RenderMode::Markdown => "markdown",
+            RenderMode::Html => "html",
+            RenderMode::Both => "both",
+        }
+    }
+}
+
+impl FromStr for RenderMode {
+    type Err = BlogError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "markdown" | "md" => Ok(RenderMode::Markdown),
+            "html" => Ok(RenderMode::Html),
+            "both" => Ok(RenderMode::Both),
+            other => Err(BlogError::ValidationError(format!(
+                "Unknown render mode: {} (expected markdown, html or both)",
+                other
+            ))),
+        }
+    }
+}
+
+/// Markdown extensions used when rendering HTML
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderOptions {
+    pub footnotes: bool,
+    pub tables: bool,
+    pub strikethrough: bool,
+    pub task_lists: bool,
+    /// Tag fenced code blocks with `class="language-…"` for client-side highlighters
+    pub code_language_classes: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            footnotes: true,
+            tables: true,
+            strikethrough: true,
+            task_lists: true,
+            code_language_classes: true,
+        }
+    }
+}
+
+impl RenderOptions {
+    fn parser_options(&self) -> Options {
+        let mut options = Options::empty();
+        options.set(Options::ENABLE_FOOTNOTES, self.footnotes);
+        options.set(Options::ENABLE_TABLES, self.tables);
+        options.set(Options::ENABLE_STRIKETHROUGH, self.strikethrough);
+        options.set(Options::ENABLE_TASKLISTS, self.task_lists);
+        options
+    }
+}
+
+/// Apply a render mode to a parsed post
+pub fn render_post(mut post: BlogPost, mode: RenderMode, options: &RenderOptions) -> BlogPost {
+    match mode {
+        RenderMode::Markdown => {}
+        RenderMode::Html => {
+            post.html = Some(render_html(&post.content, options));
+            post.content.clear();
+        }
+        RenderMode::Both => {
+            post.html = Some(render_html(&post.content, options));
+        }
+    }
+    post
+}
+
+/// Render markdown to HTML
+pub fn render_html(markdown: &str, options: &RenderOptions) -> String {
+    let parser = Parser::new_ext(markdown, options.parser_options());
+    let mut output = String::with_capacity(markdown.len() * 3 / 2);
+
+    if options.code_language_classes {
+        html::push_html(&mut output, parser);
+    } else {
+        // Indented code blocks render without a language class
+        html::push_html(
+            &mut output,
+            parser.map(|event| match event {
+                Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(_))) => {
+                    Event::Start(Tag::CodeBlock(CodeBlockKind::Indented))
+                }
+                other => other,
+            }),
+        );
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_basic() {
+        let html = render_html("# Title\n\nSome *text*.", &RenderOptions::default());
+        assert!(html.contains("<h1>Title</h1>"));
+        assert!(html.contains("<em>text</em>"));
+    }
+
+    #[test]
+    fn test_render_tables_option() {
+        let markdown = "| a | b |\n|---|---|\n| 1 | 2 |\n";
+        assert!(render_html(markdown, &RenderOptions::default()).contains("<table>"));
+
+        let options = RenderOptions {
+            tables: false,
+            ..Default::default()
+        };
+        assert!(!render_html(markdown, &options).contains("<table>"));
+    }
+
+    #[test]
+    fn test_render_footnotes_option() {
+        let markdown = "Text[^1].\n\n[^1]: Note.\n";
+        assert!(render_html(markdown, &RenderOptions::default()).contains("footnote-definition"));
+
+        let options = RenderOptions {
+            footnotes: false,
+            ..Default::default()
+        };
+        assert!(!render_html(markdown, &options).contains("footnote-definition"));
+    }
+
+    #[test]
+    fn test_render_code_language_classes() {
+        let markdown = "```rust\nfn main() {}\n```\n";
+        assert!(render_html(markdown, &RenderOptions::default())
+            .contains(r#"<code class="language-rust">"#));
+
+        let options = RenderOptions {
+            code_language_classes: false,
+            ..Default::default()
+        };
+        let html = render_html(markdown, &options);
+        assert!(html.contains("<pre><code>fn main() {}"));
+        assert!(!html.contains("language-rust"));
+    }
+
+    #[test]
+    fn test_render_mode_from_str() {
+        assert_eq!("html".parse::<RenderMode>().unwrap(), RenderMode::Html);
+        assert_eq!("Both".parse::<RenderMode>().unwrap(), RenderMode::Both);
+        assert_eq!("md".parse::<RenderMode>().unwrap(), RenderMode::Markdown);
+        assert!("pdf".parse::<RenderMode>().is_err());
+    }
+}
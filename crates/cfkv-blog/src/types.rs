@@ -1,4 +1,7 @@
+use chrono::{DateTime, SecondsFormat, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
 
 /// Blog post metadata (for the blog list)
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -10,6 +13,12 @@ pub struct BlogMeta {
     pub date: String,
     pub cover_image: Option<String>,
     pub tags: Vec<String>,
+    /// Scheduled publication time, if the post had one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publish_at: Option<DateTime<Utc>>,
+    /// SHA-256 of the source markdown, used to skip unchanged posts on sync
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
 }
 
 /// Complete blog post (with content)
@@ -22,10 +31,70 @@ pub struct BlogPost {
     pub date: String,
     pub cover_image: Option<String>,
     pub tags: Vec<String>,
+    /// Frontmatter fields beyond the built-in ones, kept as-is
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra: BTreeMap<String, Value>,
     pub content: String,
+    /// Rendered HTML of the content, when published with HTML rendering
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub html: Option<String>,
+    /// Drafts are stored under the `draft:` prefix and kept out of the blog list
+    #[serde(default)]
+    pub draft: bool,
+    /// Posts with a future `publish_at` are stored under the `scheduled:`
+    /// prefix and kept out of the blog list until released
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publish_at: Option<DateTime<Utc>>,
+    /// SHA-256 of the source markdown
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+}
+
+/// Frontmatter fields in the order they are written back out
+#[derive(Serialize)]
+struct Frontmatter<'a> {
+    slug: &'a str,
+    title: &'a str,
+    description: &'a str,
+    author: &'a str,
+    date: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cover_image: Option<&'a str>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    tags: &'a [String],
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    draft: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    publish_at: Option<String>,
+    #[serde(flatten)]
+    extra: &'a BTreeMap<String, Value>,
 }
 
 impl BlogPost {
+    /// Reconstruct the markdown source: YAML frontmatter followed by the content
+    pub fn to_markdown(&self) -> Result<String, serde_yaml::Error> {
+        let frontmatter = serde_yaml::to_string(&Frontmatter {
+            slug: &self.slug,
+            title: &self.title,
+            description: &self.description,
+            author: &self.author,
+            date: &self.date,
+            cover_image: self.cover_image.as_deref(),
+            tags: &self.tags,
+            draft: self.draft,
+            publish_at: self
+                .publish_at
+                .map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true)),
+            extra: &self.extra,
+        })?;
+        Ok(format!("---\n{}---\n\n{}\n", frontmatter, self.content))
+    }
+
+    /// Whether the post is due later than `now`
+    pub fn is_scheduled(&self, now: DateTime<Utc>) -> bool {
+        self.publish_at.is_some_and(|at| at > now)
+    }
+
     /// Extract metadata from the blog post
     pub fn meta(&self) -> BlogMeta {
         BlogMeta {
@@ -36,10 +105,58 @@ impl BlogPost {
             date: self.date.clone(),
             cover_image: self.cover_image.clone(),
             tags: self.tags.clone(),
+            publish_at: self.publish_at,
+            content_hash: self.content_hash.clone(),
         }
     }
 }
 
+/// Outcome of publishing a directory of markdown files
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct SyncReport {
+    pub created: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    /// Files saved as drafts
+    pub drafts: usize,
+    /// Files stored for publication at a later `publish_at`
+    #[serde(default)]
+    pub scheduled: usize,
+    /// Slugs of published posts removed because their source file is gone
+    pub deleted: Vec<String>,
+    /// Slugs of published posts taken off the blog list because their source
+    /// file is now a draft or scheduled for later
+    #[serde(default)]
+    pub unpublished: Vec<String>,
+    /// Posts created or updated, for hooks run after publishing
+    #[serde(skip)]
+    pub published: Vec<BlogMeta>,
+}
+
+/// Mismatches between the `post:` keys and the blog list, found by
+/// `BlogPublisher::gc`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct GcReport {
+    /// Slugs of stored posts missing from the blog list
+    pub unlisted: Vec<String>,
+    /// Slugs in the blog list whose post key is gone
+    pub missing: Vec<String>,
+    /// Slugs of post keys that do not hold a valid post; never changed
+    pub unreadable: Vec<String>,
+    /// Whether the blog list was repaired
+    pub fixed: bool,
+    /// List entries of the unlisted posts, for hooks run after a repair
+    #[serde(skip)]
+    pub relisted: Vec<BlogMeta>,
+}
+
+impl GcReport {
+    /// Whether the posts and the blog list agree
+    pub fn is_clean(&self) -> bool {
+        self.unlisted.is_empty() && self.missing.is_empty() && self.unreadable.is_empty()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -54,6 +171,8 @@ mod tests {
             date: "2025-01-15".to_string(),
             cover_image: Some("image.jpg".to_string()),
             tags: vec!["rust".to_string(), "web".to_string()],
+            publish_at: None,
+            content_hash: None,
         };
 
         assert_eq!(meta.slug, "test-post");
@@ -70,7 +189,12 @@ mod tests {
             date: "2025-01-15".to_string(),
             cover_image: None,
             tags: vec!["test".to_string()],
+            extra: BTreeMap::new(),
             content: "# Content".to_string(),
+            html: None,
+            draft: false,
+            publish_at: None,
+            content_hash: None,
         };
 
         let meta = post.meta();
@@ -79,6 +203,23 @@ mod tests {
         assert!(meta.cover_image.is_none());
     }
 
+    #[test]
+    fn test_to_markdown_roundtrip() {
+        let source = "---\nslug: my-post\ntitle: My Post\ndescription: Description\nauthor: Author\ndate: 2025-01-15\ntags:\n- rust\ndraft: true\npublish_at: 2030-01-01T09:00:00Z\nseries: intro\n---\n\n# Content\n";
+        let post = crate::parser::MarkdownParser::parse_post(source).unwrap();
+        let markdown = post.to_markdown().unwrap();
+
+        let reparsed = crate::parser::MarkdownParser::parse_post(&markdown).unwrap();
+        assert_eq!(reparsed.meta().title, "My Post");
+        assert_eq!(reparsed.tags, vec!["rust"]);
+        assert!(reparsed.draft);
+        assert_eq!(reparsed.extra, post.extra);
+        assert_eq!(reparsed.publish_at, post.publish_at);
+        assert!(reparsed.is_scheduled("2029-12-31T00:00:00Z".parse().unwrap()));
+        assert_eq!(reparsed.content, "# Content");
+        assert!(!markdown.contains("cover_image"));
+    }
+
     #[test]
     fn test_blog_meta_equality() {
         let meta1 = BlogMeta {
@@ -89,6 +230,8 @@ mod tests {
             date: "2025-01-15".to_string(),
             cover_image: None,
             tags: vec![],
+            publish_at: None,
+            content_hash: None,
         };
 
         let meta2 = meta1.clone();
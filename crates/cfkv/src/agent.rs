@@ -0,0 +1,401 @@
+//! Recurring jobs for `cfkv agent`
+//!
+//! The agent reads a jobs file and runs each job's cfkv command on its own
+//! interval, instead of a crontab of separate invocations:
+//!
+//! ```yaml
+//! jobs:
+//!   - name: nightly-export
+//!     every: 24h
+//!     storage: prod
+//!     args: [batch, export, /backups/prod.json]
+//!   - name: release-posts
+//!     every: 1h
+//!     jitter: 5m
+//!     run_on_start: true
+//!     args: [blog, release-due]
+//!   - name: prune-sessions
+//!     every: 6h
+//!     notify: https://hooks.slack.com/services/T000/B000/XXXX
+//!     notify_on: failure
+//!     args: [--yes, sessions, purge]
+//! ```
+//!
+//! Each run is a child cfkv process, so a failing job never takes the agent
+//! down. Waits are jittered (by a tenth of the interval unless `jitter` is
+//! set) so jobs on the same interval do not hit the API together, and a job
+//! never overlaps with its own previous run.
+//!
+//! A job with `notify` posts a summary of each run to the webhook (see
+//! `notify`), with the job's run and failure counts since the agent started.
+
+use crate::notify::{Notifier, NotifyOn, Summary};
+use cloudflare_kv::{KvError, Result};
+use rand::Rng;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Contents of a jobs file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobsFile {
+    pub jobs: Vec<Job>,
+}
+
+/// A cfkv command run on an interval
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Job {
+    pub name: String,
+    /// Interval between runs, such as `10m` or `1h30m`
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub every: Duration,
+    /// Most a wait is lengthened by at random
+    #[serde(default, deserialize_with = "deserialize_optional_duration")]
+    pub jitter: Option<Duration>,
+    /// Storage to run against instead of the current one
+    #[serde(default)]
+    pub storage: Option<String>,
+    /// Run once as soon as the agent starts, then on the interval
+    #[serde(default)]
+    pub run_on_start: bool,
+    /// cfkv arguments, without the program name
+    pub args: Vec<String>,
+    /// Webhook to post a summary of each run to
+    #[serde(default)]
+    pub notify: Option<String>,
+    /// Which runs `notify` reports: `always` (the default) or `failure`
+    #[serde(default)]
+    pub notify_on: Option<NotifyOn>,
+}
+
+/// How one run of a job went
+#[derive(Debug)]
+struct JobRun {
+    duration: Duration,
+    exit_code: Option<i32>,
+    /// Why the run did not exit normally
+    error: Option<String>,
+}
+
+impl JobRun {
+    fn succeeded(&self) -> bool {
+        self.error.is_none() && self.exit_code == Some(0)
+    }
+}
+
+/// Runs of a job since the agent started
+#[derive(Debug, Default, PartialEq)]
+pub struct JobStats {
+    pub runs: u64,
+    pub failures: u64,
+    pub consecutive_failures: u64,
+}
+
+impl JobStats {
+    fn record(&mut self, succeeded: bool) {
+        self.runs += 1;
+        if succeeded {
+            self.consecutive_failures = 0;
+        } else {
+            self.failures += 1;
+            self.consecutive_failures += 1;
+        }
+    }
+
+    fn counts(&self) -> BTreeMap<&'static str, u64> {
+        BTreeMap::from([
+            ("runs", self.runs),
+            ("failures", self.failures),
+            ("consecutive_failures", self.consecutive_failures),
+        ])
+    }
+}
+
+impl Job {
+    /// Wait before the next run: the interval plus up to `jitter`
+    pub fn next_wait(&self) -> Duration {
+        let jitter = self.jitter.unwrap_or(self.every / 10);
+        if jitter.is_zero() {
+            return self.every;
+        }
+        self.every + rand::thread_rng().gen_range(Duration::ZERO..=jitter)
+    }
+
+    /// Full argument list of a run, after the agent's own `global` options
+    fn command_args(&self, global: &[String]) -> Vec<String> {
+        let mut args = global.to_vec();
+        if let Some(storage) = &self.storage {
+            args.extend(["--storage".to_string(), storage.clone()]);
+        }
+        args.extend(self.args.iter().cloned());
+        args
+    }
+
+    /// Run the job once and send its notification; true when it succeeded
+    pub async fn run(&self, global: &[String], stats: &mut JobStats) -> bool {
+        let run = self.execute(global).await;
+        stats.record(run.succeeded());
+        if let Some(url) = &self.notify {
+            let notifier = Notifier::new(url.clone(), self.notify_on.unwrap_or_default());
+            notifier.notify(&self.summary(&run, stats)).await;
+        }
+        run.succeeded()
+    }
+
+    fn summary(&self, run: &JobRun, stats: &JobStats) -> Summary {
+        Summary {
+            name: format!("job {}", self.name),
+            success: run.succeeded(),
+            duration_ms: run.duration.as_millis() as u64,
+            exit_code: run.exit_code,
+            error: run.error.clone(),
+            counts: stats.counts(),
+        }
+    }
+
+    async fn execute(&self, global: &[String]) -> JobRun {
+        let started = Instant::now();
+        info!(job = %self.name, args = ?self.args, "Job started");
+        let status = match std::env::current_exe() {
+            Ok(exe) => {
+                tokio::process::Command::new(exe)
+                    .args(self.command_args(global))
+                    .stdin(Stdio::null())
+                    .status()
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        let duration = started.elapsed();
+        let duration_ms = duration.as_millis() as u64;
+        let (exit_code, error) = match status {
+            Ok(status) if status.success() => {
+                info!(job = %self.name, duration_ms, "Job succeeded");
+                (status.code(), None)
+            }
+            Ok(status) => {
+                warn!(job = %self.name, duration_ms, exit_code = status.code(), "Job failed");
+                // No exit code when the run was killed by a signal
+                let error = status.code().is_none().then(|| status.to_string());
+                (status.code(), error)
+            }
+            Err(e) => {
+                warn!(job = %self.name, duration_ms, error = %e, "Job could not be started");
+                (None, Some(format!("could not be started: {}", e)))
+            }
+        };
+        JobRun {
+            duration,
+            exit_code,
+            error,
+        }
+    }
+}
+
+impl JobsFile {
+    pub fn read(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Self::parse(&content)
+            .map_err(|e| KvError::InvalidConfig(format!("{}: {}", path.display(), e)))
+    }
+
+    fn parse(content: &str) -> std::result::Result<Self, String> {
+        let file: JobsFile = serde_yaml::from_str(content).map_err(|e| e.to_string())?;
+        if file.jobs.is_empty() {
+            return Err("no jobs defined".to_string());
+        }
+        for (i, job) in file.jobs.iter().enumerate() {
+            if job.every.is_zero() {
+                return Err(format!("job '{}' needs a non-zero interval", job.name));
+            }
+            if job.args.is_empty() {
+                return Err(format!("job '{}' has no args", job.name));
+            }
+            if file.jobs[..i].iter().any(|other| other.name == job.name) {
+                return Err(format!("job '{}' is defined twice", job.name));
+            }
+            if let Some(url) = &job.notify {
+                crate::notify::parse_url(url).map_err(|e| format!("job '{}': {}", job.name, e))?;
+            }
+        }
+        Ok(file)
+    }
+}
+
+/// Run every job on its interval until interrupted
+///
+/// `global` options, such as `--local`, are passed to every run.
+pub async fn run(jobs: Vec<Job>, global: Vec<String>) {
+    let tasks: Vec<_> = jobs
+        .into_iter()
+        .map(|job| {
+            let global = global.clone();
+            tokio::spawn(async move {
+                let mut stats = JobStats::default();
+                if job.run_on_start {
+                    job.run(&global, &mut stats).await;
+                }
+                loop {
+                    let wait = job.next_wait();
+                    info!(job = %job.name, wait_secs = wait.as_secs(), "Next run scheduled");
+                    tokio::time::sleep(wait).await;
+                    job.run(&global, &mut stats).await;
+                }
+            })
+        })
+        .collect();
+
+    tokio::signal::ctrl_c().await.ok();
+    info!("Agent stopping");
+    for task in tasks {
+        task.abort();
+    }
+}
+
+/// Parse a duration such as `500ms`, `90s`, `10m`, `1h30m` or `1d`
+pub fn parse_duration(text: &str) -> std::result::Result<Duration, String> {
+    let invalid = || {
+        format!(
+            "invalid duration '{}' (use e.g. 500ms, 30s, 10m, 1h30m, 1d)",
+            text
+        )
+    };
+    let mut total = Duration::ZERO;
+    let mut number = String::new();
+    let mut chars = text.trim().chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            'm' if chars.next_if_eq(&'s').is_some() => Duration::from_millis(1),
+            's' => Duration::from_secs(1),
+            'm' => Duration::from_secs(60),
+            'h' => Duration::from_secs(3600),
+            'd' => Duration::from_secs(86400),
+            _ => return Err(invalid()),
+        };
+        let value: u32 = number.parse().map_err(|_| invalid())?;
+        total = unit
+            .checked_mul(value)
+            .and_then(|duration| total.checked_add(duration))
+            .ok_or_else(invalid)?;
+        number.clear();
+    }
+    if !number.is_empty() || text.trim().is_empty() {
+        return Err(invalid());
+    }
+    Ok(total)
+}
+
+fn deserialize_duration<'de, D>(deserializer: D) -> std::result::Result<Duration, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let text = String::deserialize(deserializer)?;
+    parse_duration(&text).map_err(serde::de::Error::custom)
+}
+
+pub(crate) fn deserialize_optional_duration<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Option<Duration>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    deserialize_duration(deserializer).map(Some)
+}
+
+/// Write a duration the way `parse_duration` reads it, in whole seconds
+/// when it has no fraction of one
+pub fn format_duration(duration: Duration) -> String {
+    if duration.subsec_millis() == 0 {
+        format!("{}s", duration.as_secs())
+    } else {
+        format!("{}ms", duration.as_millis())
+    }
+}
+
+pub(crate) fn serialize_optional_duration<S>(
+    duration: &Option<Duration>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match duration {
+        Some(duration) => serializer.serialize_str(&format_duration(*duration)),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("1d"), Ok(Duration::from_secs(86400)));
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("1m30s"), Ok(Duration::from_secs(90)));
+        for text in ["", "10", "5x", "m"] {
+            assert!(parse_duration(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_parse_jobs() {
+        let file = JobsFile::parse(
+            "jobs:\n  - name: export\n    every: 10m\n    storage: prod\n    args: [batch, export, out.json]\n",
+        )
+        .unwrap();
+        let job = &file.jobs[0];
+        assert_eq!(
+            job.command_args(&["--yes".to_string()]),
+            ["--yes", "--storage", "prod", "batch", "export", "out.json"]
+        );
+        let wait = job.next_wait();
+        assert!(wait >= Duration::from_secs(600) && wait <= Duration::from_secs(660));
+
+        let twice = "jobs:\n  - {name: a, every: 1m, args: [list]}\n  - {name: a, every: 1m, args: [list]}\n";
+        assert!(JobsFile::parse(twice)
+            .unwrap_err()
+            .contains("defined twice"));
+        assert!(JobsFile::parse("jobs: []").is_err());
+        assert!(JobsFile::parse("jobs:\n  - {name: a, every: soon, args: [list]}\n").is_err());
+    }
+
+    #[test]
+    fn test_notify_settings() {
+        let file = JobsFile::parse(
+            "jobs:\n  - {name: a, every: 1m, notify: 'https://example.com/hook', notify_on: failure, args: [list]}\n",
+        )
+        .unwrap();
+        assert_eq!(file.jobs[0].notify_on, Some(NotifyOn::Failure));
+        let invalid = "jobs:\n  - {name: a, every: 1m, notify: example.com, args: [list]}\n";
+        assert!(JobsFile::parse(invalid).unwrap_err().contains("job 'a'"));
+    }
+
+    #[test]
+    fn test_job_stats() {
+        let mut stats = JobStats::default();
+        for succeeded in [false, true, false, false] {
+            stats.record(succeeded);
+        }
+        assert_eq!(
+            stats,
+            JobStats {
+                runs: 4,
+                failures: 3,
+                consecutive_failures: 2,
+            }
+        );
+    }
+}
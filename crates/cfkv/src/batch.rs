@@ -0,0 +1,343 @@
+//! Batch import and export files
+//!
+//! Import files are JSON or YAML (chosen by extension) and contain either a
+//! list of records or a plain mapping of keys to values:
+//!
+//! ```json
+//! [{"key": "a", "value": "1", "expiration_ttl": 3600, "metadata": {"owner": "x"}}]
+//! {"a": "1", "b": {"nested": true}}
+//! ```
+//!
+//! Records take the fields of the Cloudflare bulk write API: `expiration`
+//! (a Unix timestamp) or `expiration_ttl`, `metadata`, and `base64` for
+//! binary values. Non-string values are stored as their JSON text. This is
+//! the format of `wrangler kv bulk put`, and exports are written in it too.
+//!
+//! Exports can be narrowed with an `ExportFilter`: a key pattern, value size
+//! bounds, and a `cfkv verify` manifest whose unchanged values are skipped.
+//! KV keeps no modification times, so the manifest stands in for one: what
+//! differs from it changed after it was generated.
+
+use crate::diff::Digest;
+use crate::verify::Manifest;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use cloudflare_kv::{BatchBuilder, KvError, Result};
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// A single key-value record in an import file
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ImportRecord {
+    pub key: String,
+    #[serde(deserialize_with = "value_as_string")]
+    pub value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiration_ttl: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiration: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+    /// `value` is base64 of the bytes to store
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub base64: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ImportFile {
+    Records(Vec<ImportRecord>),
+    Map(BTreeMap<String, serde_json::Value>),
+}
+
+fn value_as_string<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<String, D::Error> {
+    Ok(json_to_string(serde_json::Value::deserialize(
+        deserializer,
+    )?))
+}
+
+fn json_to_string(value: serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s,
+        other => other.to_string(),
+    }
+}
+
+/// Parse import file content; YAML when `yaml` is set, JSON otherwise
+pub fn parse_import(content: &str, yaml: bool) -> Result<Vec<ImportRecord>> {
+    let file: ImportFile = if yaml {
+        serde_yaml::from_str(content).map_err(|e| KvError::SerializationError(e.to_string()))?
+    } else {
+        serde_json::from_str(content)?
+    };
+
+    Ok(match file {
+        ImportFile::Records(records) => records,
+        ImportFile::Map(map) => map
+            .into_iter()
+            .map(|(key, value)| ImportRecord {
+                key,
+                value: json_to_string(value),
+                expiration_ttl: None,
+                expiration: None,
+                metadata: None,
+                base64: false,
+            })
+            .collect(),
+    })
+}
+
+/// Read and parse an import file, choosing the format by extension
+pub fn read_import_file(path: &Path) -> Result<Vec<ImportRecord>> {
+    let content = fs::read_to_string(path)?;
+    parse_import(&content, is_yaml_path(path))
+}
+
+/// Batch writing the records, with base64 values decoded
+pub fn to_batch(records: &[ImportRecord]) -> Result<BatchBuilder> {
+    records
+        .iter()
+        .try_fold(BatchBuilder::new(), |batch, record| {
+            let value = if record.base64 {
+                BASE64.decode(&record.value).map_err(|e| {
+                    KvError::SerializationError(format!(
+                        "Invalid base64 value for key {}: {}",
+                        record.key, e
+                    ))
+                })?
+            } else {
+                record.value.clone().into_bytes()
+            };
+            Ok(batch.put_with_options(
+                &record.key,
+                value,
+                record.expiration_ttl,
+                record.expiration,
+                record.metadata.clone(),
+                record.base64,
+            ))
+        })
+}
+
+/// Content of an export file holding the batch's puts, as a list of records
+///
+/// Records whose key `is_sensitive` are marked `"sensitive": true`; import
+/// ignores the field.
+pub fn export_content(
+    batch: &BatchBuilder,
+    yaml: bool,
+    is_sensitive: impl Fn(&str) -> bool,
+) -> Result<String> {
+    let mut records = batch.bulk_write_body();
+    if let Some(records) = records.as_array_mut() {
+        for record in records {
+            let sensitive = record["key"].as_str().is_some_and(&is_sensitive);
+            if sensitive {
+                record["sensitive"] = true.into();
+            }
+        }
+    }
+    if yaml {
+        serde_yaml::to_string(&records).map_err(|e| KvError::SerializationError(e.to_string()))
+    } else {
+        Ok(serde_json::to_string_pretty(&records)?)
+    }
+}
+
+/// Which keys `batch export` writes
+#[derive(Default)]
+pub struct ExportFilter {
+    /// Keys must match this pattern
+    pub pattern: Option<Regex>,
+    /// Smallest value size, in bytes
+    pub min_size: Option<u64>,
+    /// Largest value size, in bytes
+    pub max_size: Option<u64>,
+    /// Skip values that still match this manifest
+    pub baseline: Option<Manifest>,
+}
+
+impl ExportFilter {
+    /// Check the key before its value is downloaded
+    pub fn matches_key(&self, key: &str) -> bool {
+        self.pattern
+            .as_ref()
+            .is_none_or(|pattern| pattern.is_match(key))
+    }
+
+    /// Check a value that passed `matches_key`
+    pub fn matches_value(&self, key: &str, value: &[u8]) -> bool {
+        let size = value.len() as u64;
+        if self.min_size.is_some_and(|min| size < min)
+            || self.max_size.is_some_and(|max| size > max)
+        {
+            return false;
+        }
+        match self
+            .baseline
+            .as_ref()
+            .and_then(|manifest| manifest.keys.get(key))
+        {
+            Some(entry) => Digest::of(value).hash != entry.sha256,
+            // New since the manifest, or no manifest
+            None => true,
+        }
+    }
+
+    /// Whether anything is filtered beyond the listed prefix
+    pub fn is_active(&self) -> bool {
+        self.pattern.is_some()
+            || self.min_size.is_some()
+            || self.max_size.is_some()
+            || self.baseline.is_some()
+    }
+}
+
+/// Check whether a path has a YAML extension
+pub fn is_yaml_path(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("yaml") | Some("yml")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_records() {
+        let json = r#"[
+            {"key": "a", "value": "1"},
+            {"key": "b", "value": {"x": 1}, "expiration_ttl": 60, "metadata": {"owner": "me"}}
+        ]"#;
+        let records = parse_import(json, false).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].value, "1");
+        assert_eq!(records[1].value, r#"{"x":1}"#);
+        assert_eq!(records[1].expiration_ttl, Some(60));
+        assert_eq!(
+            records[1].metadata,
+            Some(serde_json::json!({"owner": "me"}))
+        );
+    }
+
+    #[test]
+    fn test_parse_map() {
+        let records = parse_import(r#"{"b": "2", "a": 1}"#, false).unwrap();
+        assert_eq!(records[0].key, "a");
+        assert_eq!(records[0].value, "1");
+        assert_eq!(records[1].key, "b");
+        assert_eq!(records[1].value, "2");
+    }
+
+    #[test]
+    fn test_parse_yaml() {
+        let yaml = "- key: a\n  value: hello\n- key: b\n  value: world\n";
+        let records = parse_import(yaml, true).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].value, "world");
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse_import("not json", false).is_err());
+        assert!(parse_import(r#"[{"value": "missing key"}]"#, false).is_err());
+    }
+
+    #[test]
+    fn test_to_batch() {
+        let json = r#"[
+            {"key": "a", "value": "1", "expiration": 1900000000},
+            {"key": "logo", "value": "/9g=", "base64": true, "metadata": {"type": "jpeg"}}
+        ]"#;
+        let batch = to_batch(&parse_import(json, false).unwrap()).unwrap();
+        assert_eq!(
+            batch.bulk_write_body(),
+            serde_json::json!([
+                {"key": "a", "value": "1", "expiration": 1900000000u64},
+                {"key": "logo", "value": "/9g=", "base64": true, "metadata": {"type": "jpeg"}}
+            ])
+        );
+
+        let invalid = parse_import(r#"[{"key": "x", "value": "!", "base64": true}]"#, false);
+        assert!(to_batch(&invalid.unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_export_round_trip() {
+        let batch = BatchBuilder::new()
+            .put("text", "hello")
+            .put_with_options(
+                "session",
+                "{}",
+                None,
+                Some(1900000000),
+                Some(serde_json::json!({"user": 1})),
+                false,
+            )
+            .put("binary", [0u8, 159, 146, 150]);
+
+        for yaml in [false, true] {
+            let content = export_content(&batch, yaml, |key| key == "session").unwrap();
+            assert_eq!(content.matches("sensitive").count(), 1);
+            let imported = to_batch(&parse_import(&content, yaml).unwrap()).unwrap();
+            assert_eq!(imported.bulk_write_body(), batch.bulk_write_body());
+        }
+
+        // wrangler's bulk format
+        let wrangler = r#"[{"key": "k", "value": "djE=", "base64": true, "expiration_ttl": 600}]"#;
+        let imported = to_batch(&parse_import(wrangler, false).unwrap()).unwrap();
+        match &imported.operations()[0] {
+            cloudflare_kv::BatchOperation::Put {
+                value,
+                expiration_ttl,
+                ..
+            } => {
+                assert_eq!(value, b"v1");
+                assert_eq!(*expiration_ttl, Some(600));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_export_filter() {
+        let baseline = Manifest::from_snapshot(
+            [("a".to_string(), Digest::of(b"old"))]
+                .into_iter()
+                .collect(),
+            "",
+        );
+        let filter = ExportFilter {
+            pattern: Some(Regex::new("^(a|b)$").unwrap()),
+            min_size: Some(2),
+            max_size: Some(5),
+            baseline: Some(baseline),
+        };
+        assert!(filter.is_active());
+        assert!(filter.matches_key("a"));
+        assert!(!filter.matches_key("c"));
+
+        assert!(!filter.matches_value("a", b"old"));
+        assert!(filter.matches_value("a", b"new"));
+        assert!(filter.matches_value("b", b"xy"));
+        assert!(!filter.matches_value("b", b"x"));
+        assert!(!filter.matches_value("b", b"toolong"));
+
+        assert!(!ExportFilter::default().is_active());
+        assert!(ExportFilter::default().matches_value("a", b""));
+    }
+
+    #[test]
+    fn test_is_yaml_path() {
+        assert!(is_yaml_path(Path::new("data.yaml")));
+        assert!(is_yaml_path(Path::new("data.yml")));
+        assert!(!is_yaml_path(Path::new("data.json")));
+    }
+}
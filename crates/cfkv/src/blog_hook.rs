@@ -0,0 +1,169 @@
+//! Hook run after blog posts go live or leave the blog
+//!
+//! `blog.post_publish_hook` in the config is a webhook or a shell command:
+//!
+//! ```json
+//! { "blog": { "post_publish_hook": { "url": "https://api.example.com/rebuild" } } }
+//! { "blog": { "post_publish_hook": { "command": "./scripts/rebuild.sh" } } }
+//! ```
+//!
+//! Either receives the event as JSON, in the request body or on stdin:
+//!
+//! ```json
+//! { "event": "publish", "posts": [{ "slug": "hello", "title": "Hello", ... }], "deleted": [] }
+//! ```
+//!
+//! Events are `publish` (publish, publish-dir, promote, release-due),
+//! `unpublish` and `delete`. Drafts and posts scheduled for later run no hook.
+
+use crate::config::PublishHook;
+use cfkv_blog::BlogMeta;
+use serde::Serialize;
+use std::io::Write;
+use std::process::Stdio;
+use std::time::Duration;
+
+/// How long a webhook may take to answer
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlogEventKind {
+    Publish,
+    Unpublish,
+    Delete,
+}
+
+/// Posts that went live and slugs that left the blog in one command
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BlogEvent {
+    pub event: BlogEventKind,
+    pub posts: Vec<BlogMeta>,
+    pub deleted: Vec<String>,
+}
+
+impl BlogEvent {
+    pub fn published(posts: Vec<BlogMeta>) -> Self {
+        Self {
+            event: BlogEventKind::Publish,
+            posts,
+            deleted: Vec::new(),
+        }
+    }
+
+    pub fn removed(event: BlogEventKind, slug: &str) -> Self {
+        Self {
+            event,
+            posts: Vec::new(),
+            deleted: vec![slug.to_string()],
+        }
+    }
+
+    /// Whether the event changed anything worth a hook run
+    pub fn is_empty(&self) -> bool {
+        self.posts.is_empty() && self.deleted.is_empty()
+    }
+}
+
+/// Run the hook with the event as JSON
+pub async fn run(hook: &PublishHook, event: &BlogEvent) -> Result<(), String> {
+    let body = serde_json::to_vec(event).map_err(|e| e.to_string())?;
+    match hook {
+        PublishHook::Webhook { url, headers } => {
+            let client = reqwest::Client::builder()
+                .timeout(WEBHOOK_TIMEOUT)
+                .build()
+                .map_err(|e| e.to_string())?;
+            let mut request = client
+                .post(url)
+                .header("Content-Type", "application/json")
+                .body(body);
+            for (name, value) in headers {
+                request = request.header(name, value);
+            }
+            let response = request
+                .send()
+                .await
+                .map_err(|e| format!("Webhook {} failed: {}", url, e))?;
+            let status = response.status();
+            if !status.is_success() {
+                let text = response.text().await.unwrap_or_default();
+                return Err(format!(
+                    "Webhook {} returned {}: {}",
+                    url,
+                    status,
+                    text.trim()
+                ));
+            }
+            Ok(())
+        }
+        PublishHook::Command { command } => {
+            let mut child = crate::secrets::shell(command)
+                .stdin(Stdio::piped())
+                .spawn()
+                .map_err(|e| format!("Could not run hook `{}`: {}", command, e))?;
+            if let Some(mut stdin) = child.stdin.take() {
+                // A hook that does not read its input closes the pipe early
+                let _ = stdin.write_all(&body);
+            }
+            let status = child.wait().map_err(|e| e.to_string())?;
+            if !status.success() {
+                return Err(format!("Hook `{}` failed ({})", command, status));
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(slug: &str) -> BlogMeta {
+        BlogMeta {
+            slug: slug.to_string(),
+            title: "Hello".to_string(),
+            description: String::new(),
+            author: "Ada".to_string(),
+            date: "2025-01-15".to_string(),
+            cover_image: None,
+            tags: vec![],
+            publish_at: None,
+            content_hash: None,
+        }
+    }
+
+    #[test]
+    fn test_event_json() {
+        let event = BlogEvent::published(vec![meta("hello")]);
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["event"], "publish");
+        assert_eq!(json["posts"][0]["slug"], "hello");
+        assert_eq!(json["deleted"], serde_json::json!([]));
+
+        let event = BlogEvent::removed(BlogEventKind::Unpublish, "hello");
+        assert_eq!(serde_json::to_value(&event).unwrap()["event"], "unpublish");
+        assert!(!event.is_empty());
+        assert!(BlogEvent::published(vec![]).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_command_hook() {
+        let output = std::env::temp_dir().join(format!("cfkv-hook-{}", std::process::id()));
+        let hook = PublishHook::Command {
+            command: format!("cat > {}", output.display()),
+        };
+        let event = BlogEvent::removed(BlogEventKind::Delete, "old-post");
+        run(&hook, &event).await.unwrap();
+        let received: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&output).unwrap()).unwrap();
+        assert_eq!(received, serde_json::to_value(&event).unwrap());
+        std::fs::remove_file(&output).unwrap();
+
+        let failing = PublishHook::Command {
+            command: "exit 3".to_string(),
+        };
+        assert!(run(&failing, &event).await.is_err());
+    }
+}
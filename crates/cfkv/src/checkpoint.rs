@@ -0,0 +1,264 @@
+//! Checkpoints for resuming `batch export` and `batch import`
+//!
+//! Both commands keep a checkpoint next to their file while they run
+//! (`backup.json.checkpoint`) and remove it when they finish; after a
+//! failure, `--resume <checkpoint>` continues from it.
+//!
+//! An export appends each listed page to `<output>.partial` as JSON lines
+//! and records the cursor of the next page and how much of the partial file
+//! is complete. An import records the SHA-256 of every chunk that was
+//! written without failures, and skips those chunks when resumed.
+
+use cloudflare_kv::{value_hash, BatchOperation, KvError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Progress of an interrupted `batch export` or `batch import`
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Checkpoint {
+    Export(ExportCheckpoint),
+    Import(ImportCheckpoint),
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExportCheckpoint {
+    pub output: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    /// Pages listed so far
+    pub pages: usize,
+    /// Cursor of the next page; `None` once every page was listed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+    /// Length of the partial file covering the listed pages, in bytes
+    pub partial_len: u64,
+    pub exported: usize,
+    pub skipped: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImportCheckpoint {
+    pub file: PathBuf,
+    pub chunk_size: usize,
+    /// SHA-256 of each chunk imported without failures
+    pub completed_chunks: BTreeSet<String>,
+}
+
+/// `Checkpoint` borrowing its contents, to write it
+#[derive(Serialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum CheckpointRef<'c> {
+    Export(&'c ExportCheckpoint),
+    Import(&'c ImportCheckpoint),
+}
+
+impl Checkpoint {
+    /// Checkpoint path used for a file unless `--resume` names another
+    pub fn path_for(file: &Path) -> PathBuf {
+        with_suffix(file, ".checkpoint")
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        serde_json::from_str(&content).map_err(|e| {
+            KvError::SerializationError(format!("Invalid checkpoint {}: {}", path.display(), e))
+        })
+    }
+}
+
+/// Write a checkpoint, replacing the previous one atomically
+fn write(path: &Path, checkpoint: CheckpointRef) -> Result<()> {
+    let temporary = with_suffix(path, ".tmp");
+    fs::write(
+        &temporary,
+        serde_json::to_string_pretty(&checkpoint)? + "\n",
+    )?;
+    fs::rename(&temporary, path)?;
+    Ok(())
+}
+
+impl ExportCheckpoint {
+    pub fn new(output: &Path, prefix: Option<String>) -> Self {
+        Self {
+            output: output.to_path_buf(),
+            prefix,
+            ..Default::default()
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        write(path, CheckpointRef::Export(self))
+    }
+
+    /// Whether every page was listed
+    pub fn is_listed(&self) -> bool {
+        self.pages > 0 && self.cursor.is_none()
+    }
+
+    pub fn partial_path(&self) -> PathBuf {
+        with_suffix(&self.output, ".partial")
+    }
+
+    /// Open the partial file to append pages, dropping anything written
+    /// after the checkpoint
+    pub fn open_partial(&self) -> Result<fs::File> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.partial_path())?;
+        file.set_len(self.partial_len)?;
+        Ok(file)
+    }
+
+    /// Append a page of export records and count it
+    pub fn append_page(
+        &mut self,
+        partial: &mut fs::File,
+        records: &[serde_json::Value],
+        cursor: Option<String>,
+    ) -> Result<()> {
+        let mut lines = String::new();
+        for record in records {
+            lines.push_str(&serde_json::to_string(record)?);
+            lines.push('\n');
+        }
+        partial.write_all(lines.as_bytes())?;
+        partial.sync_data()?;
+        self.partial_len += lines.len() as u64;
+        self.pages += 1;
+        self.cursor = cursor;
+        Ok(())
+    }
+
+    /// Records exported so far, in import format
+    pub fn read_partial(&self) -> Result<Vec<crate::batch::ImportRecord>> {
+        let file = fs::File::open(self.partial_path())?;
+        BufReader::new(file.take(self.partial_len))
+            .lines()
+            .map(|line| Ok(serde_json::from_str(&line?)?))
+            .collect()
+    }
+}
+
+impl ImportCheckpoint {
+    pub fn new(file: &Path, chunk_size: usize) -> Self {
+        Self {
+            file: file.to_path_buf(),
+            chunk_size,
+            ..Default::default()
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        write(path, CheckpointRef::Import(self))
+    }
+}
+
+/// Hash identifying a chunk of operations by their content
+pub fn chunk_hash(operations: &[BatchOperation]) -> String {
+    let items: Vec<_> = operations
+        .iter()
+        .map(|operation| match operation.to_bulk_item() {
+            Some(item) => item,
+            None => serde_json::json!({ "delete": operation.key() }),
+        })
+        .collect();
+    value_hash(serde_json::Value::Array(items).to_string().as_bytes())
+}
+
+/// Remove a finished run's checkpoint (and an export's partial file)
+pub fn remove(path: &Path, partial: Option<&Path>) -> Result<()> {
+    for path in std::iter::once(path).chain(partial) {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("cfkv-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_export_checkpoint_round_trip() {
+        let output = temp_path("export.json");
+        let path = Checkpoint::path_for(&output);
+        let mut checkpoint = ExportCheckpoint::new(&output, Some("user:".to_string()));
+        let mut partial = checkpoint.open_partial().unwrap();
+        checkpoint
+            .append_page(
+                &mut partial,
+                &[json!({"key": "user:1", "value": "ada"})],
+                Some("next".to_string()),
+            )
+            .unwrap();
+        assert!(!checkpoint.is_listed());
+        checkpoint.write(&path).unwrap();
+
+        // Lines written after the checkpoint are dropped on resume
+        writeln!(partial, "{}", json!({"key": "user:2", "value": "bob"})).unwrap();
+        let Checkpoint::Export(mut resumed) = Checkpoint::read(&path).unwrap() else {
+            panic!("expected an export checkpoint");
+        };
+        let mut partial = resumed.open_partial().unwrap();
+        resumed
+            .append_page(
+                &mut partial,
+                &[json!({"key": "user:3", "value": "cy"})],
+                None,
+            )
+            .unwrap();
+        assert!(resumed.is_listed());
+        assert_eq!(resumed.cursor, None);
+
+        let keys: Vec<_> = resumed
+            .read_partial()
+            .unwrap()
+            .into_iter()
+            .map(|record| record.key)
+            .collect();
+        assert_eq!(keys, ["user:1", "user:3"]);
+
+        remove(&path, Some(&resumed.partial_path())).unwrap();
+        assert!(!path.exists() && !resumed.partial_path().exists());
+        remove(&path, None).unwrap();
+    }
+
+    #[test]
+    fn test_chunk_hash() {
+        let put = |value: &str| BatchOperation::Put {
+            key: "k".to_string(),
+            value: value.as_bytes().to_vec(),
+            expiration_ttl: None,
+            expiration: None,
+            metadata: None,
+            base64: false,
+        };
+        assert_eq!(chunk_hash(&[put("a")]), chunk_hash(&[put("a")]));
+        assert_ne!(chunk_hash(&[put("a")]), chunk_hash(&[put("b")]));
+        assert_ne!(
+            chunk_hash(&[put("a")]),
+            chunk_hash(&[BatchOperation::Delete {
+                key: "k".to_string()
+            }])
+        );
+    }
+}
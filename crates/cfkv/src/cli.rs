@@ -1,5 +1,16 @@
-use clap::{Parser, Subcommand};
+use crate::agent::parse_duration;
+use crate::config::ConfigFormat;
+use crate::formatter::ColorChoice;
+use crate::logging::{LogFormat, LogLevel};
+use crate::notify::NotifyOn;
+use crate::table::Column;
+use crate::transform::{KeyRewrite, Transform};
+use cfkv_blog::{FeedFormat, RenderMode, RenderOptions};
+use clap::{Args, Parser, Subcommand};
+use clap_complete::Shell;
+use cloudflare_kv::{CompressionAlgorithm, CounterValue};
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Parser)]
 #[command(
@@ -25,13 +36,105 @@ pub struct Cli {
     #[arg(long, env = "CF_KV_CONFIG")]
     pub config: Option<PathBuf>,
 
-    /// Output format (json, yaml, text)
+    /// Storage to use for this invocation (does not change the active storage)
+    #[arg(long, global = true, env = "CFKV_STORAGE")]
+    pub storage: Option<String>,
+
+    /// Named namespace alias within the selected storage
+    #[arg(long, global = true)]
+    pub namespace: Option<String>,
+
+    /// Output format (json, yaml, text, table, gha)
     #[arg(short, long, default_value = "text")]
     pub format: String,
 
-    /// Enable debug logging
-    #[arg(short, long)]
-    pub debug: bool,
+    /// Color text output (auto, always, never); auto honors NO_COLOR
+    #[arg(long, global = true, value_enum, default_value = "auto")]
+    pub color: ColorChoice,
+
+    /// Disable colored output (same as --color never)
+    #[arg(long, global = true)]
+    pub no_color: bool,
+
+    /// Use a local JSON file as the namespace instead of Cloudflare (offline)
+    #[arg(long, global = true, env = "CFKV_LOCAL", value_name = "PATH")]
+    pub local: Option<PathBuf>,
+
+    /// Send at most this many API requests per second
+    #[arg(long, global = true, env = "CFKV_RATE_LIMIT", value_parser = parse_rate_limit)]
+    pub rate_limit: Option<f64>,
+
+    /// Give up on API requests taking longer than this (e.g. 30s), instead
+    /// of the config file's http.timeout
+    #[arg(long, global = true, env = "CFKV_TIMEOUT", value_name = "DURATION", value_parser = parse_duration)]
+    pub timeout: Option<Duration>,
+
+    /// Retry API requests failing with a network error, 429 or 5xx this
+    /// many times, instead of the config file's http.retries
+    #[arg(long, global = true, env = "CFKV_RETRIES", value_name = "COUNT")]
+    pub retries: Option<u32>,
+
+    /// Wait this long before the first retry (e.g. 500ms), doubling for each
+    /// one after; instead of the config file's http.retry_delay
+    #[arg(long, global = true, env = "CFKV_RETRY_DELAY", value_name = "DURATION", value_parser = parse_duration)]
+    pub retry_delay: Option<Duration>,
+
+    /// Reach the Cloudflare API through this gateway URL, instead of the
+    /// storage's api_base_url
+    #[arg(long, global = true, env = "CFKV_API_BASE_URL", value_name = "URL", value_parser = crate::config::parse_api_base_url)]
+    pub api_base_url: Option<String>,
+
+    /// Do not show progress bars for bulk operations
+    #[arg(long, global = true)]
+    pub no_progress: bool,
+
+    /// Print the API requests sent and bytes transferred on stderr once the
+    /// command finishes
+    #[arg(long, global = true)]
+    pub stats: bool,
+
+    /// Allow commands that write to a read-only storage
+    #[arg(long, global = true)]
+    pub force: bool,
+
+    /// Do not ask before deleting or resetting anything
+    #[arg(short, long, global = true)]
+    pub yes: bool,
+
+    /// Enable logging at debug level, or at the level given (e.g. --debug=trace)
+    #[arg(
+        short,
+        long,
+        value_enum,
+        value_name = "LEVEL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "debug"
+    )]
+    pub debug: Option<LogLevel>,
+
+    /// Log format (pretty, json); enables logging at info level without --debug
+    #[arg(long, global = true, value_enum)]
+    pub log_format: Option<LogFormat>,
+
+    /// Export request spans and API usage to this OpenTelemetry collector
+    /// over OTLP/HTTP (e.g. http://localhost:4318)
+    #[cfg(feature = "otel")]
+    #[arg(long, global = true, env = "CFKV_TRACE_ENDPOINT", value_name = "URL")]
+    pub trace_endpoint: Option<String>,
+
+    /// Ignore the .cfkv.toml project file of the working directory
+    #[arg(long, global = true, env = "CFKV_NO_PROJECT")]
+    pub no_project: bool,
+
+    /// Post a summary to this Slack, Discord or other webhook when the
+    /// command finishes (for `agent`, to jobs without their own `notify`)
+    #[arg(long, global = true, env = "CFKV_NOTIFY", value_name = "URL", value_parser = crate::notify::parse_url)]
+    pub notify: Option<String>,
+
+    /// Which runs --notify reports
+    #[arg(long, global = true, value_enum, requires = "notify")]
+    pub notify_on: Option<NotifyOn>,
 
     #[command(subcommand)]
     pub command: Commands,
@@ -39,33 +142,200 @@ pub struct Cli {
 
 #[derive(Subcommand)]
 pub enum Commands {
-    /// Get a value by key
+    /// Get a value by key, or several keys at once
     Get {
-        key: String,
+        #[arg(id = "key", value_name = "KEY", required_unless_present = "keys_from")]
+        keys: Vec<String>,
+        /// Also read keys from a file, one per line ("-" for stdin)
+        #[arg(long)]
+        keys_from: Option<PathBuf>,
+        /// With several keys, print one JSON object per line
+        #[arg(long)]
+        ndjson: bool,
+        /// Number of values fetched at once
+        #[arg(long, default_value = "8")]
+        concurrency: usize,
         /// Pretty print output
         #[arg(short, long)]
         pretty: bool,
+        /// Print the value's SHA-256 (for `put --if-match`) instead of the value
+        #[arg(long)]
+        hash: bool,
+        /// Print the value base64-encoded, for binary values
+        #[arg(long, conflicts_with_all = ["hash", "pretty"])]
+        base64: bool,
+        /// Print values under the storage's sensitive prefixes instead of masking them
+        #[arg(long)]
+        reveal: bool,
+        /// Serve the value from the local read cache when fresh
+        #[arg(long)]
+        cached: bool,
+        /// How long cached values stay fresh, in seconds
+        #[arg(long, default_value = "300", requires = "cached")]
+        cache_ttl: u64,
     },
 
     /// Put a value with a key
     Put {
+        /// Key name; with --template, a key template such as "user:{uuid}"
         key: String,
         /// Value to store
         #[arg(short, long)]
         value: Option<String>,
-        /// Read value from file
+        /// Read value from file (`-` for stdin)
         #[arg(short, long)]
         file: Option<PathBuf>,
+        /// Read value from stdin (the default when stdin is piped and neither
+        /// --value nor --file is given)
+        #[arg(long, conflicts_with_all = ["value", "file"])]
+        stdin: bool,
+        /// The value (or file content) is base64; store the decoded bytes
+        #[arg(long)]
+        base64: bool,
         /// TTL in seconds
         #[arg(long)]
         ttl: Option<u64>,
         /// Metadata as JSON
         #[arg(long)]
         metadata: Option<String>,
+        /// Compress large values (gzip or zstd)
+        #[arg(long)]
+        compress: Option<CompressionAlgorithm>,
+        /// Only write if the key does not exist yet
+        #[arg(long, conflicts_with = "if_match")]
+        if_absent: bool,
+        /// Only write if the current value's SHA-256 matches (see `get --hash`)
+        #[arg(long, value_name = "SHA256")]
+        if_match: Option<String>,
+        /// Purge this URL from the Cloudflare cache after the write; may
+        /// contain {key} (repeatable)
+        #[arg(long, value_name = "URL", value_parser = crate::purge::parse_url)]
+        purge_url: Vec<String>,
+        /// Zone of the --purge-url URLs, looked up from their host when not given
+        #[arg(long, value_name = "ZONE_ID", requires = "purge_url")]
+        purge_zone: Option<String>,
+        /// Expand placeholders such as {uuid} or {date} in the key
+        #[arg(long)]
+        template: bool,
+    },
+
+    /// Write several string values at once with the bulk API
+    PutMany {
+        /// KEY=VALUE pairs; later pairs replace earlier ones with the same key
+        #[arg(value_name = "KEY=VALUE", value_parser = crate::env_file::parse_pair)]
+        pairs: Vec<(String, String)>,
+        /// Read pairs from an env-style file (before the arguments)
+        #[arg(long, value_name = "PATH")]
+        env_file: Option<PathBuf>,
+        /// Prefix added to every key, e.g. "config/"
+        #[arg(long)]
+        prefix: Option<String>,
+        /// TTL in seconds
+        #[arg(long)]
+        ttl: Option<u64>,
+    },
+
+    /// Append to a value, creating the key if needed
+    Append {
+        key: String,
+        /// Text to append
+        #[arg(short, long)]
+        value: Option<String>,
+        /// Append the contents of a file
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+        /// Add a newline after the appended text
+        #[arg(short, long)]
+        newline: bool,
+    },
+
+    /// Increment a numeric value (a missing key counts as 0)
+    Incr {
+        key: String,
+        /// Amount to add (integer or decimal)
+        #[arg(long, default_value = "1", allow_hyphen_values = true)]
+        by: CounterValue,
+    },
+
+    /// Decrement a numeric value (a missing key counts as 0)
+    Decr {
+        key: String,
+        /// Amount to subtract (integer or decimal)
+        #[arg(long, default_value = "1", allow_hyphen_values = true)]
+        by: CounterValue,
     },
 
-    /// Delete a key
-    Delete { key: String },
+    /// Delete a key, or every key under a prefix
+    Delete {
+        #[arg(required_unless_present = "prefix")]
+        key: Option<String>,
+        /// Delete every key starting with this prefix, after confirmation
+        #[arg(long, conflicts_with = "key")]
+        prefix: Option<String>,
+    },
+
+    /// Move a key (value, metadata and remaining TTL) to a new name
+    Rename {
+        /// Key to move (a key prefix with --prefix)
+        old: String,
+        /// New key name (the replacement prefix with --prefix)
+        new: String,
+        /// Rename every key starting with OLD, replacing that prefix with NEW
+        #[arg(long)]
+        prefix: bool,
+        /// Copy instead of move: leave the source key in place
+        #[arg(long)]
+        keep_source: bool,
+        /// Overwrite destination keys that already exist
+        #[arg(long)]
+        force: bool,
+        /// Only print what would be renamed
+        #[arg(long)]
+        dry_run: bool,
+        /// Expand placeholders such as {timestamp} in NEW
+        #[arg(long, conflicts_with = "prefix")]
+        template: bool,
+    },
+
+    /// Patch a JSON value in place, keeping its TTL and metadata
+    Patch {
+        key: String,
+        /// RFC 6902 JSON Patch (array of operations) or RFC 7386 merge patch (object)
+        patch: String,
+    },
+
+    /// Check whether a key exists (exit code 0 if it does, 2 if not)
+    Exists {
+        key: String,
+        /// Print nothing, only set the exit code
+        #[arg(short, long)]
+        quiet: bool,
+    },
+
+    /// Open a value in $EDITOR and write it back, keeping its TTL and metadata
+    Edit {
+        key: String,
+        /// Start from an empty value if the key does not exist
+        #[arg(long)]
+        create: bool,
+    },
+
+    /// Read or change a key's metadata, keeping its value and TTL
+    Meta {
+        #[command(subcommand)]
+        command: MetaCommands,
+    },
+
+    /// Set string labels in a key's metadata, e.g. `cfkv tag invoice:7 owner=payments`
+    Tag {
+        key: String,
+        /// NAME=VALUE labels to set
+        #[arg(value_name = "NAME=VALUE", value_parser = crate::env_file::parse_pair, required_unless_present = "remove")]
+        labels: Vec<(String, String)>,
+        /// Remove this label (repeatable)
+        #[arg(long, value_name = "NAME")]
+        remove: Vec<String>,
+    },
 
     /// List all keys
     List {
@@ -78,6 +348,150 @@ pub enum Commands {
         /// Include metadata
         #[arg(long)]
         metadata: bool,
+        /// Print keys as objects with their expiration and metadata in JSON
+        /// and YAML output, instead of names
+        #[arg(long)]
+        detailed: bool,
+        /// Only show listed keys matching this expression, e.g.
+        /// 'metadata.owner == "payments"' (filters each page of --limit keys)
+        #[arg(long = "where", value_name = "EXPR", value_parser = crate::filter::Filter::parse)]
+        filter: Option<crate::filter::Filter>,
+        /// Columns for --format table (key, expiration, ttl, metadata, size);
+        /// an error with other formats
+        #[arg(long, value_enum, value_delimiter = ',')]
+        columns: Vec<Column>,
+    },
+
+    /// Search values for a pattern and print matching keys and snippets
+    Grep {
+        /// Text to search for (a regular expression with --regex)
+        pattern: String,
+        /// Only search keys starting with this prefix
+        #[arg(long)]
+        prefix: Option<String>,
+        /// Treat the pattern as a regular expression
+        #[arg(short = 'E', long)]
+        regex: bool,
+        /// Match case-insensitively
+        #[arg(short, long)]
+        ignore_case: bool,
+        /// Only search the JSON field at this path (e.g. $.config.url)
+        #[arg(long)]
+        json_path: Option<String>,
+        /// Print only the matching keys
+        #[arg(short = 'l', long)]
+        keys_only: bool,
+        /// Number of values fetched at once
+        #[arg(long, default_value = "8")]
+        concurrency: usize,
+    },
+
+    /// Show key counts, value sizes and expirations for the namespace
+    Stats {
+        /// Count keys under this prefix (repeatable; replaces --delimiter grouping)
+        #[arg(long = "prefix")]
+        prefixes: Vec<String>,
+        /// Group keys by the part up to this delimiter
+        #[arg(long, default_value = ":")]
+        delimiter: char,
+        /// Number of values fetched at once to measure sizes
+        #[arg(long, default_value = "8")]
+        concurrency: usize,
+        /// Only count keys; skip downloading values to measure sizes
+        #[arg(long)]
+        no_sizes: bool,
+    },
+
+    /// Show how keys are organized as a tree of prefixes, with key counts
+    /// and sizes per subtree
+    Tree {
+        /// Split keys into prefixes at this delimiter
+        #[arg(long, default_value = ":")]
+        delimiter: char,
+        /// Number of prefix levels shown; deeper keys count toward their
+        /// ancestor at this level
+        #[arg(long, default_value = "2")]
+        depth: usize,
+        /// Number of values fetched at once to measure sizes
+        #[arg(long, default_value = "8")]
+        concurrency: usize,
+        /// Only count keys; skip downloading values to measure sizes
+        #[arg(long)]
+        no_sizes: bool,
+    },
+
+    /// Show keys added, removed or changed between two storages, or between
+    /// a storage and an export file; exits 1 when they differ
+    Diff {
+        /// Storage name or export file to compare from
+        from: String,
+        /// Storage name or export file to compare to
+        to: String,
+        /// Only compare keys starting with this prefix
+        #[arg(long, default_value = "")]
+        prefix: String,
+        /// Number of values fetched at once
+        #[arg(long, default_value = "8")]
+        concurrency: usize,
+    },
+
+    /// Check values against a manifest of SHA-256 hashes, or write one
+    Verify {
+        /// Manifest to check against (or to write with --generate)
+        #[arg(long)]
+        manifest: PathBuf,
+        /// Record the current values in the manifest instead of checking them
+        #[arg(long)]
+        generate: bool,
+        /// Only record keys starting with this prefix
+        #[arg(long, default_value = "", requires = "generate")]
+        prefix: String,
+        /// Number of values fetched at once
+        #[arg(long, default_value = "8")]
+        concurrency: usize,
+    },
+
+    /// Copy keys, values and metadata into a SQL table, refreshing only
+    /// what changed since the last run
+    Mirror {
+        /// Target database: sqlite://<file> or d1://<database-id>
+        #[arg(long)]
+        to: String,
+        /// Only mirror keys starting with this prefix
+        #[arg(long, default_value = "")]
+        prefix: String,
+        /// Table to mirror into
+        #[arg(long, default_value = "kv")]
+        table: String,
+        /// Number of values fetched at once
+        #[arg(long, default_value = "8")]
+        concurrency: usize,
+    },
+
+    /// Serve the namespace over a local REST API
+    Serve {
+        /// Port to listen on
+        #[arg(long, default_value = "8080")]
+        port: u16,
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1")]
+        bind: std::net::IpAddr,
+        /// Require `Authorization: Bearer <TOKEN>` on every request
+        #[arg(long, env = "CFKV_SERVE_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+        /// Speak the Workers KV binding protocol used by Miniflare instead
+        #[arg(long)]
+        worker_compat: bool,
+    },
+
+    /// Run recurring jobs from a jobs file until interrupted
+    Agent {
+        /// Jobs file (YAML)
+        #[arg(long)]
+        config: PathBuf,
+        /// Run every job once, in order, and exit
+        #[arg(long)]
+        once: bool,
     },
 
     /// Batch operations
@@ -112,6 +526,125 @@ pub enum Commands {
         #[command(subcommand)]
         command: BlogCommands,
     },
+
+    /// Markdown content collections (docs, changelogs, ...)
+    Content {
+        #[command(subcommand)]
+        command: ContentCommands,
+    },
+
+    /// Feature flags stored under `flag:<name>` keys
+    Flags {
+        #[command(subcommand)]
+        command: FlagCommands,
+    },
+
+    /// Inspect and clean up session records under a prefix
+    Sessions {
+        #[command(subcommand)]
+        command: SessionCommands,
+    },
+
+    /// Value plugin management
+    Plugin {
+        #[command(subcommand)]
+        command: PluginCommands,
+    },
+
+    /// Local read cache management
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+
+    /// Generate shell completion scripts
+    Completions {
+        /// Target shell
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+
+    /// Print key names starting with a prefix (used by completion scripts)
+    #[command(name = "__complete-keys", hide = true)]
+    CompleteKeys {
+        /// Key prefix to complete
+        #[arg(default_value = "")]
+        prefix: String,
+    },
+}
+
+impl Commands {
+    /// Whether the command changes keys in the namespace
+    pub fn writes(&self) -> bool {
+        match self {
+            Commands::Put { .. }
+            | Commands::PutMany { .. }
+            | Commands::Append { .. }
+            | Commands::Incr { .. }
+            | Commands::Decr { .. }
+            | Commands::Delete { .. }
+            | Commands::Patch { .. }
+            | Commands::Edit { .. }
+            | Commands::Tag { .. } => true,
+            Commands::Rename { dry_run, .. } => !dry_run,
+            Commands::Batch { command } => match command {
+                BatchCommands::Delete { .. } | BatchCommands::Import { .. } => true,
+                BatchCommands::Export { .. } => false,
+            },
+            Commands::Blog { command } => match command {
+                BlogCommands::Publish { .. }
+                | BlogCommands::PublishDir { .. }
+                | BlogCommands::Draft { .. }
+                | BlogCommands::Promote { .. }
+                | BlogCommands::Unpublish { .. }
+                | BlogCommands::ReleaseDue
+                | BlogCommands::MigrateList
+                | BlogCommands::Delete { .. } => true,
+                BlogCommands::Feed { store, .. } => *store,
+                BlogCommands::Gc { fix } => *fix,
+                BlogCommands::Get { .. }
+                | BlogCommands::Preview { .. }
+                | BlogCommands::List { .. } => false,
+            },
+            Commands::Content { command } => match command {
+                ContentCommands::Publish { .. } | ContentCommands::Delete { .. } => true,
+                ContentCommands::Get { .. }
+                | ContentCommands::List { .. }
+                | ContentCommands::Collections => false,
+            },
+            Commands::Flags { command } => match command {
+                FlagCommands::Set { .. } | FlagCommands::Rollout { .. } => true,
+                FlagCommands::Get { .. } | FlagCommands::List => false,
+            },
+            Commands::Sessions { command } => match command {
+                SessionCommands::Purge { dry_run, .. } => !dry_run,
+                SessionCommands::List { .. } | SessionCommands::Count { .. } => false,
+            },
+            Commands::Meta { command } => match command {
+                MetaCommands::Set { .. } | MetaCommands::Merge { .. } => true,
+                MetaCommands::Get { .. } => false,
+            },
+            Commands::Get { .. }
+            | Commands::Exists { .. }
+            | Commands::List { .. }
+            | Commands::Grep { .. }
+            | Commands::Stats { .. }
+            | Commands::Tree { .. }
+            | Commands::Diff { .. }
+            | Commands::Verify { .. }
+            | Commands::Mirror { .. }
+            | Commands::Serve { .. }
+            | Commands::Agent { .. }
+            | Commands::Namespace { .. }
+            | Commands::Storage { .. }
+            | Commands::Interactive
+            | Commands::Config { .. }
+            | Commands::Plugin { .. }
+            | Commands::Cache { .. }
+            | Commands::Completions { .. }
+            | Commands::CompleteKeys { .. } => false,
+        }
+    }
 }
 
 #[derive(Subcommand)]
@@ -126,12 +659,69 @@ pub enum BatchCommands {
     Import {
         /// File path
         file: PathBuf,
+        /// Compress large values (gzip or zstd)
+        #[arg(long)]
+        compress: Option<CompressionAlgorithm>,
+        /// Records written per chunk; without --continue-on-error, the
+        /// import stops after the first chunk with a failure
+        #[arg(long, default_value_t = cloudflare_kv::batch::DEFAULT_CHUNK_SIZE)]
+        chunk_size: usize,
+        /// Keep importing after records fail
+        #[arg(long)]
+        continue_on_error: bool,
+        /// Write the records that failed or were not imported to this file,
+        /// to import again
+        #[arg(long, value_name = "PATH")]
+        retry_file: Option<PathBuf>,
+        /// Add or strip a key prefix (add:<prefix> or strip:<prefix>);
+        /// repeat to apply several in order
+        #[arg(long, value_name = "add:PREFIX|strip:PREFIX", value_parser = KeyRewrite::parse_prefix)]
+        key_prefix: Vec<KeyRewrite>,
+        /// Rewrite keys with a sed substitution, e.g. 's/^staging:/prod:/';
+        /// applied after --key-prefix, in order when repeated
+        #[arg(long, value_name = "s/REGEX/REPLACEMENT/[g]", value_parser = KeyRewrite::parse_map)]
+        key_map: Vec<KeyRewrite>,
+        /// Rewrite values with a jq-style filter, e.g. '.env = "prod"'
+        #[arg(long, value_name = "FILTER", value_parser = Transform::parse)]
+        transform: Option<Transform>,
+        /// Continue an interrupted import, skipping the chunks this
+        /// checkpoint records as imported
+        #[arg(long, value_name = "CHECKPOINT")]
+        resume: Option<PathBuf>,
     },
 
-    /// Export keys to file
+    /// Export keys with their values, expirations and metadata to a
+    /// JSON/YAML file that `batch import` (or wrangler) can load
     Export {
         /// Output file path
         output: PathBuf,
+        /// Only export keys starting with this prefix
+        #[arg(long)]
+        prefix: Option<String>,
+        /// Only export keys matching this regular expression
+        #[arg(long = "match", value_name = "REGEX")]
+        pattern: Option<String>,
+        /// Skip values smaller than this (e.g. 512, 10KiB, 1MiB)
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        min_size: Option<u64>,
+        /// Skip values larger than this
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        max_size: Option<u64>,
+        /// Only export keys added or changed since this `cfkv verify
+        /// --generate` manifest was written
+        #[arg(long, value_name = "MANIFEST")]
+        modified_after: Option<PathBuf>,
+        /// Continue an interrupted export from its checkpoint; pass the
+        /// same filters as the first run
+        #[arg(long, value_name = "CHECKPOINT")]
+        resume: Option<PathBuf>,
+        /// Number of values fetched at once
+        #[arg(long, default_value_t = 8)]
+        concurrency: usize,
+        /// Write each page's records as their values arrive instead of in
+        /// key order, so one slow value does not hold up the others
+        #[arg(long)]
+        unordered: bool,
     },
 }
 
@@ -166,6 +756,32 @@ pub enum ConfigCommands {
 
     /// Reset configuration
     Reset,
+
+    /// Encrypt the config file with a passphrase (or CFKV_CONFIG_KEY)
+    Encrypt,
+
+    /// Store the config file as plaintext again
+    Decrypt,
+
+    /// Convert the config file to another format
+    Convert {
+        /// Target format
+        #[arg(long, value_enum)]
+        to: ConfigFormat,
+    },
+
+    /// Check the config file for errors without changing it
+    Validate,
+
+    /// Rewrite a damaged config file with the settings that can still be
+    /// read, keeping the damaged file as a .bak
+    Repair,
+
+    /// Turn confirmation prompts for destructive commands on or off
+    SetConfirm {
+        #[arg(action = clap::ArgAction::Set)]
+        enabled: bool,
+    },
 }
 
 #[derive(Subcommand)]
@@ -175,14 +791,35 @@ pub enum StorageCommands {
         /// Storage name
         name: String,
         /// Account ID
-        #[arg(short = 'a', long)]
-        account_id: String,
+        #[arg(short = 'a', long, required_unless_present = "account")]
+        account_id: Option<String>,
         /// Namespace ID
         #[arg(short = 'n', long)]
         namespace_id: String,
         /// API token
-        #[arg(short = 't', long)]
-        api_token: String,
+        #[arg(short = 't', long, required_unless_present_any = ["account", "api_token_cmd"])]
+        api_token: Option<String>,
+        /// Command printing the API token (e.g. "op read op://vault/cf/token"), run when needed
+        #[arg(long, conflicts_with = "api_token")]
+        api_token_cmd: Option<String>,
+        /// Take the account ID and API token from a shared account
+        #[arg(long, conflicts_with_all = ["account_id", "api_token", "api_token_cmd"])]
+        account: Option<String>,
+        /// Refuse writes to this storage unless --force is given
+        #[arg(long)]
+        read_only: bool,
+        /// Prefix added to every key (e.g. "billing/")
+        #[arg(long)]
+        key_prefix: Option<String>,
+        /// R2 bucket to offload values too large for KV to
+        #[arg(long)]
+        r2_bucket: Option<String>,
+        /// Reach the Cloudflare API through this gateway URL
+        #[arg(long, value_name = "URL", value_parser = crate::config::parse_api_base_url)]
+        api_base_url: Option<String>,
+        /// Header sent with every API request (repeatable)
+        #[arg(long = "header", value_name = "NAME=VALUE", value_parser = crate::env_file::parse_pair)]
+        headers: Vec<(String, String)>,
     },
 
     /// List all storages
@@ -232,8 +869,389 @@ pub enum StorageCommands {
         file: PathBuf,
     },
 
+    /// Add a storage for each KV namespace binding of a wrangler.toml, named
+    /// after the binding (preview namespaces get a "-preview" storage)
+    ImportWrangler {
+        /// Path to wrangler.toml
+        file: PathBuf,
+        /// Import the bindings of [env.<ENV>], naming storages "<BINDING>-<ENV>"
+        #[arg(long)]
+        env: Option<String>,
+        /// Account ID (defaults to the account_id in wrangler.toml)
+        #[arg(short = 'a', long)]
+        account_id: Option<String>,
+        /// API token
+        #[arg(short = 't', long, required_unless_present_any = ["account", "api_token_cmd"])]
+        api_token: Option<String>,
+        /// Command printing the API token, run when needed
+        #[arg(long, conflicts_with = "api_token")]
+        api_token_cmd: Option<String>,
+        /// Take the account ID and API token from a shared account
+        #[arg(long, conflicts_with_all = ["account_id", "api_token", "api_token_cmd"])]
+        account: Option<String>,
+        /// Skip preview namespaces
+        #[arg(long)]
+        no_preview: bool,
+        /// Replace storages that already exist instead of skipping them
+        #[arg(long)]
+        overwrite: bool,
+    },
+
     /// Load storages from environment variables
     LoadEnv,
+
+    /// Encrypt values client-side for a storage (generates a key if none is given)
+    SetEncryptionKey {
+        /// Storage name
+        name: String,
+        /// Base64-encoded 256-bit key
+        key: Option<String>,
+    },
+
+    /// Stop encrypting values for a storage
+    ClearEncryptionKey {
+        /// Storage name
+        name: String,
+    },
+
+    /// Refuse writes to a storage unless --force is given
+    ReadOnly {
+        /// Storage name
+        name: String,
+    },
+
+    /// Allow writes to a storage again
+    ReadWrite {
+        /// Storage name
+        name: String,
+    },
+
+    /// Scope a storage to keys under a prefix, added on writes and stripped from listings
+    SetKeyPrefix {
+        /// Storage name
+        name: String,
+        /// Key prefix (e.g. "billing/")
+        prefix: String,
+    },
+
+    /// Stop scoping a storage's keys
+    ClearKeyPrefix {
+        /// Storage name
+        name: String,
+    },
+
+    /// Offload values too large for KV (over 25 MiB) to an R2 bucket, storing
+    /// a pointer in KV
+    SetR2Bucket {
+        /// Storage name
+        name: String,
+        /// R2 bucket in the storage's account
+        bucket: String,
+    },
+
+    /// Stop offloading large values to R2 (offloaded values stay readable
+    /// only while a bucket is set)
+    ClearR2Bucket {
+        /// Storage name
+        name: String,
+    },
+
+    /// Reach the Cloudflare API through a gateway for a storage
+    SetApiBaseUrl {
+        /// Storage name
+        name: String,
+        /// Gateway URL standing in for https://api.cloudflare.com/client/v4
+        #[arg(value_parser = crate::config::parse_api_base_url)]
+        url: String,
+    },
+
+    /// Reach the Cloudflare API directly again
+    ClearApiBaseUrl {
+        /// Storage name
+        name: String,
+    },
+
+    /// Send a header with every API request of a storage (values may use ${VAR})
+    SetHeader {
+        /// Storage name
+        name: String,
+        /// Header and value
+        #[arg(value_name = "NAME=VALUE", value_parser = crate::env_file::parse_pair)]
+        header: (String, String),
+    },
+
+    /// Stop sending a header
+    RemoveHeader {
+        /// Storage name
+        name: String,
+        /// Header name
+        header: String,
+    },
+
+    /// Treat values under a key prefix as secrets: `get` masks them unless
+    /// --reveal is given
+    AddSensitive {
+        /// Storage name
+        name: String,
+        /// Key prefix (e.g. "secrets/")
+        prefix: String,
+    },
+
+    /// Stop treating values under a key prefix as secrets
+    RemoveSensitive {
+        /// Storage name
+        name: String,
+        /// Key prefix added with add-sensitive
+        prefix: String,
+    },
+
+    /// Purge a URL from the Cloudflare cache after writes to keys under a
+    /// prefix
+    AddPurgeUrl {
+        /// Storage name
+        name: String,
+        /// Key prefix (e.g. "pages/"; "" for every key)
+        prefix: String,
+        /// URL template; {key} is the written key, {path} the key without
+        /// the prefix
+        #[arg(value_parser = crate::purge::parse_url)]
+        url: String,
+        /// Zone of the prefix's URLs, looked up from their host when not given
+        #[arg(long)]
+        zone_id: Option<String>,
+    },
+
+    /// Stop purging URLs after writes to keys under a prefix
+    RemovePurgeUrls {
+        /// Storage name
+        name: String,
+        /// Key prefix given to add-purge-url
+        prefix: String,
+    },
+
+    /// Obtain a storage's API token from a command and remove the stored token
+    SetTokenCmd {
+        /// Storage name
+        name: String,
+        /// Command printing the token (e.g. "pass show cloudflare/token")
+        command: String,
+    },
+
+    /// Add a shared account, or replace the credentials of an existing one
+    /// (e.g. to rotate its token for every storage using it)
+    SetAccount {
+        /// Account name
+        name: String,
+        /// Account ID
+        #[arg(short = 'a', long)]
+        account_id: String,
+        /// API token
+        #[arg(short = 't', long, required_unless_present = "api_token_cmd")]
+        api_token: Option<String>,
+        /// Command printing the API token, run when needed
+        #[arg(long, conflicts_with = "api_token")]
+        api_token_cmd: Option<String>,
+    },
+
+    /// Remove a shared account no storage uses
+    RemoveAccount {
+        /// Account name
+        name: String,
+    },
+
+    /// List shared accounts and the storages using them
+    Accounts,
+
+    /// Make a storage take its credentials from a shared account
+    UseAccount {
+        /// Storage name
+        storage: String,
+        /// Account name
+        account: String,
+    },
+
+    /// Stop sharing an account's credentials, keeping a copy in the storage
+    UnlinkAccount {
+        /// Storage name
+        storage: String,
+    },
+
+    /// Add a named namespace to a storage
+    AddNamespace {
+        /// Storage name
+        storage: String,
+        /// Alias used with --namespace
+        alias: String,
+        /// Namespace ID
+        namespace_id: String,
+    },
+
+    /// Check storages work: token, namespace, read and write access
+    Doctor {
+        /// Storage name (defaults to every storage)
+        name: Option<String>,
+    },
+
+    /// List the namespaces of every configured account and offer to add unmapped ones
+    Scan {
+        /// Only scan the account of this storage
+        #[arg(short, long)]
+        name: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ContentCommands {
+    /// Publish a markdown file to a collection
+    Publish {
+        /// Collection name
+        #[arg(short, long)]
+        collection: String,
+        /// Path to markdown file
+        file: PathBuf,
+        #[command(flatten)]
+        render: RenderArgs,
+    },
+
+    /// Print an entry as markdown with frontmatter
+    Get {
+        /// Collection name
+        #[arg(short, long)]
+        collection: String,
+        /// Entry slug
+        slug: String,
+    },
+
+    /// List the entries of a collection
+    List {
+        /// Collection name
+        #[arg(short, long)]
+        collection: String,
+        /// Show a single page of the list (0-based)
+        #[arg(long)]
+        page: Option<usize>,
+    },
+
+    /// Delete an entry by slug
+    Delete {
+        /// Collection name
+        #[arg(short, long)]
+        collection: String,
+        /// Entry slug
+        slug: String,
+    },
+
+    /// Show the available collections
+    Collections,
+}
+
+#[derive(Subcommand)]
+pub enum MetaCommands {
+    /// Print a key's metadata
+    Get { key: String },
+
+    /// Replace a key's metadata ('null' removes it)
+    Set {
+        key: String,
+        /// Metadata as JSON, e.g. '{"owner": "payments"}'
+        metadata: String,
+    },
+
+    /// Merge fields into a key's metadata; null fields are removed
+    Merge {
+        key: String,
+        /// RFC 7386 merge patch, e.g. '{"ttl_policy": "30d", "draft": null}'
+        patch: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CacheCommands {
+    /// Remove every cached value
+    Clear,
+}
+
+#[derive(Subcommand)]
+pub enum FlagCommands {
+    /// Turn a flag on or off, or give it a JSON value (replaces any rollout)
+    Set {
+        /// Flag name
+        name: String,
+        /// on, off or a JSON value (e.g. '{"limit": 5}' or '"blue"')
+        setting: String,
+    },
+
+    /// Show a flag, or whether it is on for a subject
+    Get {
+        /// Flag name
+        name: String,
+        /// User or account ID to evaluate the rollout for
+        #[arg(long)]
+        subject: Option<String>,
+    },
+
+    /// List flags and their settings
+    List,
+
+    /// Turn a flag on for a percentage of subjects
+    Rollout {
+        /// Flag name
+        name: String,
+        /// Percentage of subjects, 0-100
+        #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+        percent: u8,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SessionCommands {
+    /// List sessions with their remaining TTL, soonest expiry first
+    List {
+        #[command(flatten)]
+        sessions: SessionArgs,
+        /// Show at most this many sessions
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+
+    /// Count active and expired sessions
+    Count {
+        #[command(flatten)]
+        sessions: SessionArgs,
+    },
+
+    /// Delete sessions past their expiry, such as pointer records written
+    /// without a TTL
+    Purge {
+        #[command(flatten)]
+        sessions: SessionArgs,
+        /// Only show what would be deleted
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct SessionArgs {
+    /// Key prefix of the session records
+    #[arg(long, default_value = "session:")]
+    pub prefix: String,
+    /// Metadata field holding an expiry time (Unix seconds or milliseconds,
+    /// or RFC 3339)
+    #[arg(long, default_value = "expires_at")]
+    pub expiry_field: String,
+}
+
+#[derive(Subcommand)]
+pub enum PluginCommands {
+    /// List loaded plugins in chain order
+    List,
+
+    /// Install a plugin from a shared library (.so, .dylib, .dll)
+    Install {
+        /// Path to the plugin library
+        path: PathBuf,
+    },
 }
 
 #[derive(Subcommand)]
@@ -242,14 +1260,256 @@ pub enum BlogCommands {
     Publish {
         /// Path to markdown file
         file: PathBuf,
+
+        /// Upload local images the post references, resolved from this directory
+        #[arg(long)]
+        with_assets: Option<PathBuf>,
+
+        #[command(flatten)]
+        render: RenderArgs,
     },
 
-    /// List all published blog posts
-    List,
+    /// Publish all markdown files in a directory, skipping unchanged posts
+    PublishDir {
+        /// Directory containing markdown files
+        dir: PathBuf,
+
+        /// Delete published posts whose source file no longer exists
+        #[arg(long)]
+        prune: bool,
+
+        #[command(flatten)]
+        render: RenderArgs,
+    },
+
+    /// Save a markdown file as an unpublished draft
+    Draft {
+        /// Path to markdown file
+        file: PathBuf,
+
+        #[command(flatten)]
+        render: RenderArgs,
+    },
+
+    /// Publish a draft by slug
+    Promote {
+        /// Draft slug
+        slug: String,
+    },
+
+    /// Move a published post back to drafts
+    Unpublish {
+        /// Post slug
+        slug: String,
+    },
+
+    /// Print a stored post as markdown with frontmatter
+    Get {
+        /// Post slug
+        slug: String,
+
+        /// Read the draft instead of the published post
+        #[arg(long)]
+        draft: bool,
+    },
+
+    /// Show a markdown file exactly as it would be stored, without publishing
+    Preview {
+        /// Path to markdown file
+        file: PathBuf,
+
+        #[command(flatten)]
+        render: RenderArgs,
+    },
+
+    /// List published blog posts
+    List {
+        /// Only posts with this tag
+        #[arg(long)]
+        tag: Option<String>,
+
+        /// Only posts by this author
+        #[arg(long)]
+        author: Option<String>,
+
+        /// Show a single page of the list (0-based)
+        #[arg(long)]
+        page: Option<usize>,
+
+        /// Show posts waiting for their publish_at instead
+        #[arg(long, conflicts_with_all = ["tag", "author", "page"])]
+        scheduled: bool,
+    },
+
+    /// Publish scheduled posts whose publish_at has passed (suitable for cron)
+    ReleaseDue,
+
+    /// Convert a blog list written by older versions to pages and indexes
+    MigrateList,
+
+    /// Find posts missing from the blog list and list entries whose post is gone
+    Gc {
+        /// Add unlisted posts to the blog list and drop entries of missing posts
+        #[arg(long)]
+        fix: bool,
+    },
 
     /// Delete a blog post by slug
     Delete {
         /// Post slug
         slug: String,
     },
+
+    /// Generate an RSS or Atom feed or a sitemap from the blog list
+    Feed {
+        /// Feed format (rss, atom, sitemap)
+        #[arg(long, default_value = "rss")]
+        feed_format: FeedFormat,
+
+        /// Base URL of the site
+        #[arg(long, env = "CFKV_BLOG_SITE_URL")]
+        site_url: String,
+
+        /// Feed title
+        #[arg(long, env = "CFKV_BLOG_TITLE")]
+        title: String,
+
+        /// Feed description
+        #[arg(long)]
+        description: Option<String>,
+
+        /// Maximum number of posts in RSS and Atom feeds
+        #[arg(long, default_value = "20")]
+        max_entries: usize,
+
+        /// Path posts are served under
+        #[arg(long, default_value = "/blog/")]
+        post_path: String,
+
+        /// Store the feed in KV (e.g. under `_blog_feed.xml`) instead of printing it
+        #[arg(long)]
+        store: bool,
+    },
+}
+
+/// Options for rendering blog posts to HTML at publish time
+#[derive(Args, Clone, Debug)]
+pub struct RenderArgs {
+    /// Store markdown, rendered html, or both
+    #[arg(long, default_value = "markdown")]
+    pub render: RenderMode,
+
+    /// Disable footnotes when rendering HTML
+    #[arg(long)]
+    pub no_footnotes: bool,
+
+    /// Disable tables when rendering HTML
+    #[arg(long)]
+    pub no_tables: bool,
+
+    /// Omit `language-*` classes on fenced code blocks
+    #[arg(long)]
+    pub no_code_classes: bool,
+}
+
+impl RenderArgs {
+    /// Render options with the disabled extensions turned off
+    pub fn options(&self) -> RenderOptions {
+        RenderOptions {
+            footnotes: !self.no_footnotes,
+            tables: !self.no_tables,
+            code_language_classes: !self.no_code_classes,
+            ..Default::default()
+        }
+    }
+}
+
+/// Parse a positive requests-per-second limit
+fn parse_rate_limit(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+        _ => Err(format!("'{}' is not a positive number", value)),
+    }
+}
+
+/// Parse a size in bytes, with an optional KiB/MiB/GiB suffix (K, KB and
+/// so on are read the same way)
+fn parse_size(value: &str) -> Result<u64, String> {
+    let trimmed = value.trim();
+    let split = trimmed
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        _ => return Err(format!("'{}' is not a size", value)),
+    };
+    match number.parse::<f64>() {
+        Ok(number) if number.is_finite() && number >= 0.0 => {
+            Ok((number * multiplier as f64).round() as u64)
+        }
+        _ => Err(format!("'{}' is not a size", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn writes(args: &[&str]) -> bool {
+        let args = std::iter::once("cfkv").chain(args.iter().copied());
+        Cli::try_parse_from(args).unwrap().command.writes()
+    }
+
+    #[test]
+    fn test_write_commands() {
+        assert!(writes(&["put", "k", "--value", "v"]));
+        assert!(writes(&["put-many", "a=1", "b=2"]));
+        assert!(writes(&["flags", "rollout", "beta", "--percent", "20"]));
+        assert!(!writes(&["flags", "list"]));
+        assert!(writes(&["meta", "set", "k", "{}"]));
+        assert!(writes(&["meta", "merge", "k", "{}"]));
+        assert!(!writes(&["meta", "get", "k"]));
+        assert!(writes(&["tag", "k", "owner=payments"]));
+        assert!(writes(&["sessions", "purge"]));
+        assert!(!writes(&["sessions", "purge", "--dry-run"]));
+        assert!(writes(&["delete", "k"]));
+        assert!(writes(&["batch", "import", "data.json"]));
+        assert!(writes(&["blog", "publish", "post.md"]));
+        assert!(writes(&["blog", "gc", "--fix"]));
+        assert!(!writes(&["blog", "gc"]));
+        assert!(writes(&["rename", "a", "b"]));
+        assert!(!writes(&["rename", "a", "b", "--dry-run"]));
+        assert!(!writes(&["get", "k"]));
+        assert!(!writes(&["list"]));
+        assert!(!writes(&["batch", "export", "out.json"]));
+        assert!(!writes(&[
+            "blog",
+            "feed",
+            "--site-url",
+            "https://x",
+            "--title",
+            "t"
+        ]));
+    }
+
+    #[test]
+    fn test_cli_definition() {
+        use clap::CommandFactory;
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("10KiB"), Ok(10 * 1024));
+        assert_eq!(parse_size("1.5 MB"), Ok(1024 * 1024 * 3 / 2));
+        assert_eq!(parse_size("2g"), Ok(2 << 30));
+        assert!(parse_size("ten").is_err());
+        assert!(parse_size("5 parsecs").is_err());
+        assert!(parse_size("").is_err());
+    }
 }
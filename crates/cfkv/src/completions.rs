@@ -0,0 +1,116 @@
+use crate::cli::Cli;
+use clap::CommandFactory;
+use clap_complete::Shell;
+use std::io::Write;
+
+/// Subcommands whose `key` argument completes against live KV key names
+const KEY_COMMANDS: &[&str] = &["get", "delete"];
+
+/// Name of the hidden helper subcommand that prints matching keys
+pub const COMPLETE_KEYS_COMMAND: &str = "__complete-keys";
+
+const ZSH_KEYS_FUNCTION: &str = r#"
+_cfkv_keys() {
+    local -a keys
+    keys=(${(f)"$(cfkv __complete-keys "$PREFIX" 2>/dev/null)"})
+    compadd -a keys
+}
+"#;
+
+/// Generate a completion script for the given shell
+pub fn generate(shell: Shell, out: &mut dyn Write) -> std::io::Result<()> {
+    let mut buf = Vec::new();
+    clap_complete::generate(shell, &mut Cli::command(), "cfkv", &mut buf);
+    let script = String::from_utf8_lossy(&buf);
+
+    let script = match shell {
+        Shell::Zsh => add_zsh_key_completion(&script),
+        Shell::Fish => add_fish_key_completion(&script),
+        _ => script.into_owned(),
+    };
+
+    out.write_all(script.as_bytes())
+}
+
+/// Route the `key` argument of key commands to `_cfkv_keys`
+fn add_zsh_key_completion(script: &str) -> String {
+    let mut output = String::with_capacity(script.len() + ZSH_KEYS_FUNCTION.len());
+    let mut current_command: Option<&str> = None;
+
+    for (i, line) in script.lines().enumerate() {
+        let trimmed = line.trim();
+        if let Some(name) = trimmed
+            .strip_prefix('(')
+            .and_then(|rest| rest.strip_suffix(')'))
+        {
+            current_command = Some(name);
+        }
+
+        if current_command.is_some_and(|c| KEY_COMMANDS.contains(&c))
+            && trimmed
+                .trim_start_matches(['\'', '*', ':'])
+                .starts_with("key:_default'")
+        {
+            output.push_str(&line.replacen("_default", "_cfkv_keys", 1));
+        } else {
+            output.push_str(line);
+        }
+        output.push('\n');
+
+        // The helper must be defined before the script invokes `_cfkv`
+        if i == 0 {
+            output.push_str(ZSH_KEYS_FUNCTION);
+        }
+    }
+
+    output
+}
+
+/// Append fish completions that call back into `cfkv __complete-keys`
+fn add_fish_key_completion(script: &str) -> String {
+    let condition = KEY_COMMANDS
+        .iter()
+        .map(|c| format!("__fish_cfkv_using_subcommand {}", c))
+        .collect::<Vec<_>>()
+        .join("; or ");
+
+    format!(
+        "{}complete -c cfkv -n \"{}\" -f -a \"(cfkv {} (commandline -ct) 2>/dev/null)\"\n",
+        script, condition, COMPLETE_KEYS_COMMAND
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generate_string(shell: Shell) -> String {
+        let mut buf = Vec::new();
+        generate(shell, &mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_zsh_completes_keys_for_get_and_delete() {
+        let script = generate_string(Shell::Zsh);
+        assert!(script.starts_with("#compdef cfkv"));
+        assert!(script.contains("_cfkv_keys() {"));
+        assert!(script.contains("'*::key:_cfkv_keys'"));
+        assert!(script.contains("'::key:_cfkv_keys'"));
+        // `put` keeps the default completion
+        assert!(script.contains("':key:_default'"));
+    }
+
+    #[test]
+    fn test_fish_completes_keys() {
+        let script = generate_string(Shell::Fish);
+        assert!(script.contains("__complete-keys (commandline -ct)"));
+        assert!(script.contains("__fish_cfkv_using_subcommand get; or"));
+    }
+
+    #[test]
+    fn test_bash_and_powershell_generate() {
+        assert!(generate_string(Shell::Bash).contains("cfkv"));
+        assert!(generate_string(Shell::PowerShell).contains("cfkv"));
+    }
+}
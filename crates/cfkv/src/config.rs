@@ -1,10 +1,67 @@
-use cloudflare_kv::Result;
+use crate::agent::{deserialize_optional_duration, serialize_optional_duration};
+use crate::encryption::{self, EncryptedEnvelope};
+use cfkv_blog::Collection;
+use cloudflare_kv::{ClientConfig, KvError, Result};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-#[cfg(unix)]
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Schema version of the config files this version of cfkv writes
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Migrations to the next schema version, by the version they migrate from
+const MIGRATIONS: [fn(&mut Config); SCHEMA_VERSION as usize] = [
+    // 0 -> 1: legacy single-storage fields into `storages`
+    Config::migrate_legacy_format,
+];
+
+/// On-disk format of the config file, chosen by file extension
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+}
+
+impl ConfigFormat {
+    /// Detect the format from a path's extension (JSON unless `.toml`)
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => ConfigFormat::Toml,
+            _ => ConfigFormat::Json,
+        }
+    }
+
+    /// File extension for this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ConfigFormat::Json => "json",
+            ConfigFormat::Toml => "toml",
+        }
+    }
+
+    /// Serialize a value in this format
+    pub fn serialize<T: Serialize>(&self, value: &T) -> Result<String> {
+        match self {
+            ConfigFormat::Json => Ok(serde_json::to_string_pretty(value)?),
+            ConfigFormat::Toml => toml::to_string_pretty(value)
+                .map_err(|e| KvError::SerializationError(e.to_string())),
+        }
+    }
+
+    /// Deserialize a value in this format
+    pub fn deserialize<T: DeserializeOwned>(&self, content: &str) -> Result<T> {
+        match self {
+            ConfigFormat::Json => Ok(serde_json::from_str(content)?),
+            ConfigFormat::Toml => {
+                toml::from_str(content).map_err(|e| KvError::SerializationError(e.to_string()))
+            }
+        }
+    }
+}
 
 /// Format for exporting/importing storages
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -13,22 +70,231 @@ pub struct StorageExport {
     pub active_storage: Option<String>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+/// Credentials shared by the storages that reference the account by name
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Default)]
+pub struct Account {
+    pub account_id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub api_token: String,
+    /// Command printing the API token, run instead of storing it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_token_cmd: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Default)]
 pub struct Storage {
     pub name: String,
+    /// Shared account the credentials below are taken from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub account_id: String,
     pub namespace_id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub api_token: String,
+    /// Command printing the API token (e.g. `op read ...`), run instead of
+    /// storing it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_token_cmd: Option<String>,
+    /// Additional namespaces in the same account, keyed by alias
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub namespaces: HashMap<String, String>,
+    /// Base64 key for client-side value encryption
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption_key: Option<String>,
+    /// Refuse commands that write unless `--force` is given
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub read_only: bool,
+    /// Prefix added to every key, so several apps can share the namespace
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_prefix: Option<String>,
+    /// Key prefixes whose values `get` masks unless `--reveal` is given
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sensitive_prefixes: Vec<String>,
+    /// R2 bucket values too large for KV are offloaded to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub r2_bucket: Option<String>,
+    /// URLs purged from the cache after writes to keys under a prefix
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub purge_urls: Vec<PurgeHook>,
+    /// Gateway the Cloudflare API is reached through, instead of
+    /// api.cloudflare.com
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_base_url: Option<String>,
+    /// Headers sent with every API request (e.g. a gateway's own key)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+/// URLs purged from a zone's cache after writes to keys under `prefix`
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Default)]
+pub struct PurgeHook {
+    pub prefix: String,
+    /// URL templates; `{key}` is the written key, `{path}` the key without
+    /// `prefix`
+    pub urls: Vec<String>,
+    /// Zone of the URLs, looked up from their host name when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zone_id: Option<String>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl Storage {
+    /// The API token, running `api_token_cmd` when one is set
+    pub fn token(&self) -> Result<String> {
+        match &self.api_token_cmd {
+            Some(command) => crate::secrets::token_from_command(command),
+            None => Ok(self.api_token.clone()),
+        }
+    }
+
+    /// Whether the storage has a token or a command to obtain one
+    pub fn has_token(&self) -> bool {
+        !self.api_token.trim().is_empty() || self.api_token_cmd.is_some()
+    }
+
+    /// `client_config` sent through the storage's gateway, with its headers
+    pub fn endpoint(&self, mut client_config: ClientConfig) -> ClientConfig {
+        if let Some(url) = &self.api_base_url {
+            client_config = client_config.with_base_url(url);
+        }
+        for (name, value) in &self.headers {
+            client_config = client_config.with_header(name, value);
+        }
+        client_config
+    }
+
+    /// Resolve a namespace alias to its ID, defaulting to the storage's primary namespace
+    pub fn resolve_namespace(&self, alias: Option<&str>) -> Result<&str> {
+        match alias {
+            Some(alias) => self
+                .namespaces
+                .get(alias)
+                .map(|id| id.as_str())
+                .ok_or_else(|| {
+                    cloudflare_kv::KvError::InvalidConfig(format!(
+                        "Namespace alias '{}' not found in storage '{}'",
+                        alias, self.name
+                    ))
+                }),
+            None => Ok(&self.namespace_id),
+        }
+    }
+}
+
+/// A plugin entry in the config; enabled plugins are chained in list order
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct PluginConfig {
+    pub name: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Plugin-specific configuration passed to `KvPlugin::init`
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
+    pub config: serde_json::Value,
+    /// Shared library of an installed third-party plugin
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Blog publishing settings
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Default)]
+pub struct BlogConfig {
+    /// Frontmatter fields every post must set, on top of the built-in ones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub required_fields: Vec<String>,
+    /// Webhook or command run after posts are published or deleted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_publish_hook: Option<PublishHook>,
+}
+
+impl BlogConfig {
+    fn is_empty(&self) -> bool {
+        self.required_fields.is_empty() && self.post_publish_hook.is_none()
+    }
 }
 
+/// Defaults for requests to the Cloudflare API
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Default)]
+pub struct HttpConfig {
+    /// Longest a request may take, such as `30s`
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_optional_duration",
+        serialize_with = "serialize_optional_duration"
+    )]
+    pub timeout: Option<Duration>,
+    /// Retries of requests failing with a network error, 429 or 5xx
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+    /// Wait before the first retry, such as `500ms`, doubling for each one
+    /// after
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_optional_duration",
+        serialize_with = "serialize_optional_duration"
+    )]
+    pub retry_delay: Option<Duration>,
+}
+
+impl HttpConfig {
+    fn is_empty(&self) -> bool {
+        *self == HttpConfig::default()
+    }
+}
+
+/// Hook receiving blog events as JSON (see `blog_hook`)
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum PublishHook {
+    /// POST the event to a URL
+    Webhook {
+        url: String,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        headers: BTreeMap<String, String>,
+    },
+    /// Run a shell command with the event on stdin
+    Command { command: String },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Config {
+    /// Schema version the file was written with; 0 for files from before
+    /// versioning
+    #[serde(default)]
+    pub schema_version: u32,
+    /// Shared credentials, keyed by account name
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub accounts: HashMap<String, Account>,
     /// Map of storage names to their configurations
     #[serde(default)]
     pub storages: HashMap<String, Storage>,
     /// Name of the currently active storage
     #[serde(default)]
     pub active_storage: Option<String>,
+    /// Value plugins applied to reads and writes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub plugins: Vec<PluginConfig>,
+    /// Blog publishing settings
+    #[serde(default, skip_serializing_if = "BlogConfig::is_empty")]
+    pub blog: BlogConfig,
+    /// Content collections published with `cfkv content`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub collections: Vec<Collection>,
+    /// Ask before destructive commands; on unless set to false
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confirm_destructive: Option<bool>,
+    /// Request timeout and retries, unless given on the command line
+    #[serde(default, skip_serializing_if = "HttpConfig::is_empty")]
+    pub http: HttpConfig,
     /// Legacy fields for backwards compatibility
     #[serde(skip_serializing_if = "Option::is_none")]
     pub account_id: Option<String>,
@@ -36,25 +302,52 @@ pub struct Config {
     pub namespace_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub api_token: Option<String>,
+    /// Passphrase used to encrypt the file on save (never serialized)
+    #[serde(skip)]
+    pub passphrase: Option<String>,
+    /// `${VAR}` placeholders by the values they expanded to, written back in
+    /// place of those values on save
+    #[serde(skip)]
+    pub(crate) expansions: BTreeMap<String, String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            schema_version: SCHEMA_VERSION,
+            accounts: HashMap::new(),
+            storages: HashMap::new(),
+            active_storage: None,
+            plugins: Vec::new(),
+            blog: BlogConfig::default(),
+            collections: Vec::new(),
+            confirm_destructive: None,
+            http: HttpConfig::default(),
+            account_id: None,
+            namespace_id: None,
+            api_token: None,
+            passphrase: None,
+            expansions: BTreeMap::new(),
+        }
+    }
 }
 
 impl Config {
     /// Load or create config
     pub fn load_or_create(path: &Path) -> Result<Self> {
         if path.exists() {
-            let content = fs::read_to_string(path)?;
-            let mut config: Config = serde_json::from_str(&content).unwrap_or_default();
-
-            // Migrate legacy config format to new format if needed
-            let was_migrated = config.storages.is_empty()
-                && (config.account_id.is_some()
-                    || config.namespace_id.is_some()
-                    || config.api_token.is_some());
-
-            if was_migrated {
-                config.migrate_legacy_format();
-                // Auto-save the migrated config
-                config.save(path)?;
+            let content = read_locked(path)?;
+            let mut config = Self::parse(path, &content)?;
+            config.expand_env()?;
+            config.resolve_accounts();
+
+            let version = config.schema_version;
+            if config.migrate()? {
+                // Keep the file as the older version wrote it, then save the
+                // migrated config unless another process changed the file
+                // since it was read
+                write_private(&sibling(path, &format!(".v{}.bak", version)), &content)?;
+                config.write(path, Some(&content))?;
             }
 
             Ok(config)
@@ -63,6 +356,248 @@ impl Config {
         }
     }
 
+    /// Parse the content of the config file at `path`, decrypting it if it
+    /// is encrypted
+    ///
+    /// An empty file is an empty config. A file that does not parse is an
+    /// error, never replaced by an empty config that would be saved over it.
+    fn parse(path: &Path, content: &str) -> Result<Self> {
+        let format = ConfigFormat::from_path(path);
+        if content.trim().is_empty() {
+            return Ok(Config::default());
+        }
+        if let Ok(envelope) = format.deserialize::<EncryptedEnvelope>(content) {
+            let passphrase = encryption::read_passphrase("Config passphrase: ")?;
+            return Self::decrypt(&envelope, format, passphrase);
+        }
+        format.deserialize(content).map_err(|e| {
+            KvError::InvalidConfig(format!(
+                "Failed to parse {}: {}. Fix the file, or run `cfkv config repair` to keep the settings that can be read",
+                path.display(),
+                e
+            ))
+        })
+    }
+
+    /// Bring a config read from an older schema version up to date,
+    /// returning whether anything was migrated
+    pub fn migrate(&mut self) -> Result<bool> {
+        if self.schema_version > SCHEMA_VERSION {
+            return Err(KvError::InvalidConfig(format!(
+                "Config schema version {} is newer than version {} supported by this cfkv; upgrade cfkv to use it",
+                self.schema_version, SCHEMA_VERSION
+            )));
+        }
+        let from = self.schema_version;
+        for migration in &MIGRATIONS[from as usize..] {
+            migration(self);
+        }
+        self.schema_version = SCHEMA_VERSION;
+        Ok(from < SCHEMA_VERSION)
+    }
+
+    /// Problems with the config's contents: a dangling `active_storage`,
+    /// unknown accounts, and storages missing what is needed to reach them
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if let Some(active) = &self.active_storage {
+            if !self.storages.contains_key(active) {
+                problems.push(format!(
+                    "active_storage '{}' is not a configured storage",
+                    active
+                ));
+            }
+        }
+
+        let mut names: Vec<&String> = self.storages.keys().collect();
+        names.sort();
+        for name in names {
+            let storage = &self.storages[name];
+            if storage.name != *name {
+                problems.push(format!(
+                    "storage '{}' is named '{}' inside its entry",
+                    name, storage.name
+                ));
+            }
+            if let Some(account) = &storage.account {
+                if !self.accounts.contains_key(account) {
+                    problems.push(format!(
+                        "storage '{}' uses unknown account '{}'",
+                        name, account
+                    ));
+                }
+            }
+            let missing: Vec<&str> = [
+                ("account_id", !storage.account_id.trim().is_empty()),
+                ("namespace_id", !storage.namespace_id.trim().is_empty()),
+                ("api_token", storage.has_token()),
+            ]
+            .into_iter()
+            .filter(|(_, present)| !present)
+            .map(|(field, _)| field)
+            .collect();
+            if !missing.is_empty() {
+                problems.push(format!(
+                    "storage '{}' is unreachable: missing {}",
+                    name,
+                    missing.join(", ")
+                ));
+            }
+            if let Some(url) = &storage.api_base_url {
+                if let Err(e) = parse_api_base_url(url) {
+                    problems.push(format!("storage '{}' api_base_url: {}", name, e));
+                }
+            }
+            for (header, value) in &storage.headers {
+                // Values may still hold `${VAR}` placeholders
+                let expanded = expand_env(value).unwrap_or_else(|_| value.clone());
+                if let Err(e) = check_header(header, &expanded) {
+                    problems.push(format!("storage '{}' header: {}", name, e));
+                }
+            }
+            let mut aliases: Vec<(&String, &String)> = storage.namespaces.iter().collect();
+            aliases.sort();
+            for (alias, id) in aliases {
+                if id.trim().is_empty() {
+                    problems.push(format!(
+                        "storage '{}' namespace alias '{}' has no namespace ID",
+                        name, alias
+                    ));
+                }
+            }
+        }
+        problems
+    }
+
+    /// Decrypt an encrypted config envelope
+    pub fn decrypt(
+        envelope: &EncryptedEnvelope,
+        format: ConfigFormat,
+        passphrase: String,
+    ) -> Result<Self> {
+        let plaintext = envelope.open(&passphrase)?;
+        let plaintext =
+            String::from_utf8(plaintext).map_err(|e| KvError::SerializationError(e.to_string()))?;
+        let mut config: Config = format.deserialize(&plaintext)?;
+        config.resolve_accounts();
+        config.passphrase = Some(passphrase);
+        Ok(config)
+    }
+
+    /// Serialize the config, encrypting it if a passphrase is set
+    pub fn to_file_content(&self, format: ConfigFormat) -> Result<String> {
+        let mut config = self.without_inherited_credentials();
+        config.restore_placeholders();
+        let content = format.serialize(&config)?;
+        match &self.passphrase {
+            Some(passphrase) => {
+                let envelope = EncryptedEnvelope::seal(content.as_bytes(), passphrase)?;
+                format.serialize(&envelope)
+            }
+            None => Ok(content),
+        }
+    }
+
+    /// Copy the credentials of each referenced account into its storages
+    ///
+    /// A storage referencing an unknown account keeps its inline credentials.
+    pub fn resolve_accounts(&mut self) {
+        for storage in self.storages.values_mut() {
+            if let Some(account) = storage.account.as_ref().and_then(|a| self.accounts.get(a)) {
+                storage.account_id = account.account_id.clone();
+                storage.api_token = account.api_token.clone();
+                storage.api_token_cmd = account.api_token_cmd.clone();
+            }
+        }
+    }
+
+    /// Credential fields that may hold `${VAR}` placeholders, with a
+    /// description for error messages
+    fn credential_fields(&mut self) -> Vec<(String, &mut String)> {
+        let mut fields = Vec::new();
+        for (name, account) in &mut self.accounts {
+            fields.push((
+                format!("account '{}' account_id", name),
+                &mut account.account_id,
+            ));
+            fields.push((
+                format!("account '{}' api_token", name),
+                &mut account.api_token,
+            ));
+        }
+        for (name, storage) in &mut self.storages {
+            fields.push((
+                format!("storage '{}' account_id", name),
+                &mut storage.account_id,
+            ));
+            fields.push((
+                format!("storage '{}' namespace_id", name),
+                &mut storage.namespace_id,
+            ));
+            fields.push((
+                format!("storage '{}' api_token", name),
+                &mut storage.api_token,
+            ));
+            for (header, value) in &mut storage.headers {
+                fields.push((format!("storage '{}' header {}", name, header), value));
+            }
+        }
+        for (field, value) in [
+            ("account_id", &mut self.account_id),
+            ("namespace_id", &mut self.namespace_id),
+            ("api_token", &mut self.api_token),
+        ] {
+            if let Some(value) = value {
+                fields.push((field.to_string(), value));
+            }
+        }
+        fields
+    }
+
+    /// Replace `${VAR}` placeholders in credential fields with environment
+    /// variables, remembering them for `restore_placeholders`
+    fn expand_env(&mut self) -> Result<()> {
+        let mut expansions = BTreeMap::new();
+        for (field, value) in self.credential_fields() {
+            let expanded = expand_env(value)
+                .map_err(|e| KvError::InvalidConfig(format!("{}: {}", field, e)))?;
+            if expanded != *value && !expanded.is_empty() {
+                expansions.insert(expanded.clone(), std::mem::replace(value, expanded));
+            }
+        }
+        self.expansions = expansions;
+        Ok(())
+    }
+
+    /// Put placeholders back where their values are still in use, so
+    /// saving never writes the secrets they stood for
+    fn restore_placeholders(&mut self) {
+        let expansions = std::mem::take(&mut self.expansions);
+        for (_, value) in self.credential_fields() {
+            if let Some(placeholder) = expansions.get(value.as_str()) {
+                *value = placeholder.clone();
+            }
+        }
+    }
+
+    /// The config as written to disk, with credentials taken from an account
+    /// left out of the storages referencing it
+    fn without_inherited_credentials(&self) -> Config {
+        let mut config = self.clone();
+        for storage in config.storages.values_mut() {
+            if storage
+                .account
+                .as_ref()
+                .is_some_and(|a| self.accounts.contains_key(a))
+            {
+                storage.account_id.clear();
+                storage.api_token.clear();
+                storage.api_token_cmd = None;
+            }
+        }
+        config
+    }
+
     /// Migrate from legacy single-storage format to multi-storage format
     pub fn migrate_legacy_format(&mut self) {
         if self.storages.is_empty()
@@ -80,6 +615,7 @@ impl Config {
                     account_id,
                     namespace_id,
                     api_token,
+                    ..Default::default()
                 };
                 self.storages.insert("default".to_string(), storage);
                 self.active_storage = Some("default".to_string());
@@ -87,29 +623,47 @@ impl Config {
         }
     }
 
+    /// Whether destructive commands ask for confirmation
+    pub fn confirms_destructive(&self) -> bool {
+        self.confirm_destructive.unwrap_or(true)
+    }
+
     /// Save config to file
     pub fn save(&self, path: &Path) -> Result<()> {
+        self.write(path, None)
+    }
+
+    /// Replace the config file under the config lock, through a temporary
+    /// file renamed over it so readers never see a partial write
+    ///
+    /// Nothing is written when the file already holds the same content, or,
+    /// with `expected`, when it no longer holds `expected`. The content
+    /// replaced is kept in a `.bak` file beside the config.
+    fn write(&self, path: &Path, expected: Option<&str>) -> Result<()> {
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent)?;
         }
 
-        let content = serde_json::to_string_pretty(self)?;
-
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::OpenOptionsExt;
-            std::fs::OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .mode(0o600)
-                .open(path)?
-                .write_all(content.as_bytes())?;
+        let content = self.to_file_content(ConfigFormat::from_path(path))?;
+        let _lock = lock(path, true)?;
+        let current = fs::read_to_string(path).ok();
+        if current.as_deref() == Some(content.as_str()) {
+            return Ok(());
+        }
+        if expected.is_some() && current.as_deref() != expected {
+            return Ok(());
         }
 
-        #[cfg(not(unix))]
-        {
-            fs::write(path, content)?;
+        // Replace the file a symlinked config points to, not the link
+        let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if let Some(current) = &current {
+            write_private(&sibling(&target, ".bak"), current)?;
+        }
+        let temp = sibling(&target, &format!(".tmp-{}", std::process::id()));
+        let written = write_private(&temp, &content);
+        if let Err(e) = written.and_then(|_| Ok(fs::rename(&temp, &target)?)) {
+            let _ = fs::remove_file(&temp);
+            return Err(e);
         }
 
         Ok(())
@@ -135,10 +689,19 @@ impl Config {
         }
     }
 
-    /// Get default config file path
+    /// Directory of the on-disk read cache (`cfkv get --cached`)
+    pub fn cache_dir() -> Result<PathBuf> {
+        Ok(Self::config_dir()?.join("cfkv").join("cache"))
+    }
+
+    /// Get default config file path, preferring `config.toml` when it exists
     pub fn default_path() -> Result<PathBuf> {
-        let config_dir = Self::config_dir()?;
-        Ok(config_dir.join("cfkv").join("config.json"))
+        let config_dir = Self::config_dir()?.join("cfkv");
+        let toml_path = config_dir.join("config.toml");
+        if toml_path.exists() {
+            return Ok(toml_path);
+        }
+        Ok(config_dir.join("config.json"))
     }
 
     /// Add a new storage
@@ -154,6 +717,7 @@ impl Config {
             account_id,
             namespace_id,
             api_token,
+            ..Default::default()
         };
         self.storages.insert(name.clone(), storage);
 
@@ -175,6 +739,27 @@ impl Config {
             .and_then(|name| self.storages.get(name))
     }
 
+    /// Get the storage selected for this invocation, falling back to the active storage
+    pub fn select_storage(&self, name: Option<&str>) -> Result<Option<&Storage>> {
+        let storage = match name {
+            Some(name) => self.get_storage(name).map(Some).ok_or_else(|| {
+                cloudflare_kv::KvError::InvalidConfig(format!("Storage '{}' not found", name))
+            })?,
+            None => self.get_active_storage(),
+        };
+        if let Some(storage) = storage {
+            if let Some(account) = &storage.account {
+                if !self.accounts.contains_key(account) && !storage.has_token() {
+                    return Err(cloudflare_kv::KvError::InvalidConfig(format!(
+                        "Storage '{}' references account '{}', which is not configured",
+                        storage.name, account
+                    )));
+                }
+            }
+        }
+        Ok(storage)
+    }
+
     /// Set the active storage
     pub fn set_active_storage(&mut self, name: String) -> Result<()> {
         if self.storages.contains_key(&name) {
@@ -232,6 +817,356 @@ impl Config {
         }
     }
 
+    /// Add a named namespace to an existing storage
+    pub fn add_namespace(
+        &mut self,
+        storage_name: &str,
+        alias: String,
+        namespace_id: String,
+    ) -> Result<()> {
+        let storage = self.storages.get_mut(storage_name).ok_or_else(|| {
+            cloudflare_kv::KvError::InvalidConfig(format!("Storage '{}' not found", storage_name))
+        })?;
+        storage.namespaces.insert(alias, namespace_id);
+        Ok(())
+    }
+
+    /// Add a shared account, or replace its credentials in every storage
+    /// referencing it (e.g. to rotate the token)
+    pub fn set_account(&mut self, name: String, account: Account) {
+        self.accounts.insert(name, account);
+        self.resolve_accounts();
+    }
+
+    /// Remove a shared account no storage references
+    pub fn remove_account(&mut self, name: &str) -> Result<()> {
+        if !self.accounts.contains_key(name) {
+            return Err(cloudflare_kv::KvError::InvalidConfig(format!(
+                "Account '{}' not found",
+                name
+            )));
+        }
+        let mut users = self.account_users(name);
+        if !users.is_empty() {
+            users.sort();
+            return Err(cloudflare_kv::KvError::InvalidConfig(format!(
+                "Account '{}' is used by storage(s): {}",
+                name,
+                users.join(", ")
+            )));
+        }
+        self.accounts.remove(name);
+        Ok(())
+    }
+
+    /// Names of the storages referencing a shared account
+    pub fn account_users(&self, name: &str) -> Vec<&str> {
+        self.storages
+            .values()
+            .filter(|s| s.account.as_deref() == Some(name))
+            .map(|s| s.name.as_str())
+            .collect()
+    }
+
+    /// Make a storage take its credentials from a shared account, or keep
+    /// its current credentials inline when `account` is `None`
+    pub fn set_storage_account(
+        &mut self,
+        storage_name: &str,
+        account: Option<String>,
+    ) -> Result<()> {
+        if let Some(account) = &account {
+            if !self.accounts.contains_key(account) {
+                return Err(cloudflare_kv::KvError::InvalidConfig(format!(
+                    "Account '{}' not found",
+                    account
+                )));
+            }
+        }
+        let storage = self.storages.get_mut(storage_name).ok_or_else(|| {
+            cloudflare_kv::KvError::InvalidConfig(format!("Storage '{}' not found", storage_name))
+        })?;
+        storage.account = account;
+        self.resolve_accounts();
+        Ok(())
+    }
+
+    /// Names of the storages sharing each account and API token, sorted
+    pub fn storages_by_account(&self) -> Vec<Vec<String>> {
+        let mut groups: BTreeMap<(&str, &str, Option<&str>), Vec<String>> = BTreeMap::new();
+        for storage in self.storages.values() {
+            groups
+                .entry((
+                    &storage.account_id,
+                    &storage.api_token,
+                    storage.api_token_cmd.as_deref(),
+                ))
+                .or_default()
+                .push(storage.name.clone());
+        }
+        groups
+            .into_values()
+            .map(|mut names| {
+                names.sort();
+                names
+            })
+            .collect()
+    }
+
+    /// Where each namespace of an account is configured: `storage` for a
+    /// storage's primary namespace, `storage/alias` for a named one
+    pub fn namespace_mappings(&self, account_id: &str) -> HashMap<String, Vec<String>> {
+        let mut mappings: HashMap<String, Vec<String>> = HashMap::new();
+        for storage in self.storages.values() {
+            if storage.account_id != account_id {
+                continue;
+            }
+            mappings
+                .entry(storage.namespace_id.clone())
+                .or_default()
+                .push(storage.name.clone());
+            for (alias, namespace_id) in &storage.namespaces {
+                mappings
+                    .entry(namespace_id.clone())
+                    .or_default()
+                    .push(format!("{}/{}", storage.name, alias));
+            }
+        }
+        for labels in mappings.values_mut() {
+            labels.sort();
+        }
+        mappings
+    }
+
+    /// Add a plugin entry, replacing an existing entry with the same name in place
+    pub fn add_plugin(&mut self, plugin: PluginConfig) {
+        match self.plugins.iter_mut().find(|p| p.name == plugin.name) {
+            Some(existing) => *existing = plugin,
+            None => self.plugins.push(plugin),
+        }
+    }
+
+    /// All content collections: the built-in `blog` followed by configured ones
+    pub fn collections(&self) -> Vec<Collection> {
+        std::iter::once(Collection::blog())
+            .chain(self.collections.iter().cloned())
+            .collect()
+    }
+
+    /// Look up a content collection by name
+    ///
+    /// Configured collections must not be named `blog` or share keys with
+    /// the blog (see `Collection::validate_custom`).
+    pub fn get_collection(&self, name: &str) -> Result<Collection> {
+        let invalid = |e: cfkv_blog::BlogError| KvError::InvalidConfig(e.to_string());
+        if name == Collection::BLOG {
+            // Rather than silently shadowing a configured `blog`
+            if let Some(shadowed) = self.collections.iter().find(|c| c.is_blog()) {
+                shadowed.validate_custom().map_err(invalid)?;
+            }
+            return Ok(Collection::blog());
+        }
+        let collection = self
+            .collections
+            .iter()
+            .find(|c| c.name == name)
+            .cloned()
+            .ok_or_else(|| {
+                let names: Vec<String> = self.collections().into_iter().map(|c| c.name).collect();
+                KvError::InvalidConfig(format!(
+                    "Unknown collection '{}'. Available collections: {}",
+                    name,
+                    names.join(", ")
+                ))
+            })?;
+        collection.validate_custom().map_err(invalid)?;
+        Ok(collection)
+    }
+
+    /// Set or clear the client-side encryption key of a storage
+    pub fn set_encryption_key(&mut self, storage_name: &str, key: Option<String>) -> Result<()> {
+        let storage = self.storages.get_mut(storage_name).ok_or_else(|| {
+            cloudflare_kv::KvError::InvalidConfig(format!("Storage '{}' not found", storage_name))
+        })?;
+        storage.encryption_key = key;
+        Ok(())
+    }
+
+    /// Mark a storage read-only, or writable again
+    pub fn set_read_only(&mut self, storage_name: &str, read_only: bool) -> Result<()> {
+        let storage = self.storages.get_mut(storage_name).ok_or_else(|| {
+            cloudflare_kv::KvError::InvalidConfig(format!("Storage '{}' not found", storage_name))
+        })?;
+        storage.read_only = read_only;
+        Ok(())
+    }
+
+    /// Set or clear the key prefix of a storage
+    pub fn set_key_prefix(&mut self, storage_name: &str, prefix: Option<String>) -> Result<()> {
+        let storage = self.storages.get_mut(storage_name).ok_or_else(|| {
+            cloudflare_kv::KvError::InvalidConfig(format!("Storage '{}' not found", storage_name))
+        })?;
+        storage.key_prefix = prefix.filter(|p| !p.is_empty());
+        Ok(())
+    }
+
+    /// Offload values too large for KV to an R2 bucket, or stop doing so
+    pub fn set_r2_bucket(&mut self, storage_name: &str, bucket: Option<String>) -> Result<()> {
+        if let Some(bucket) = &bucket {
+            // R2's naming rules
+            let valid = (3..=63).contains(&bucket.len())
+                && bucket
+                    .bytes()
+                    .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+                && !bucket.starts_with('-')
+                && !bucket.ends_with('-');
+            if !valid {
+                return Err(cloudflare_kv::KvError::InvalidConfig(format!(
+                    "Invalid R2 bucket name '{}': use 3-63 lowercase letters, digits and hyphens",
+                    bucket
+                )));
+            }
+        }
+        let storage = self.storages.get_mut(storage_name).ok_or_else(|| {
+            cloudflare_kv::KvError::InvalidConfig(format!("Storage '{}' not found", storage_name))
+        })?;
+        storage.r2_bucket = bucket;
+        Ok(())
+    }
+
+    /// Reach the Cloudflare API through `url` for a storage, or through
+    /// api.cloudflare.com again
+    pub fn set_api_base_url(&mut self, storage_name: &str, url: Option<String>) -> Result<()> {
+        let url = url
+            .map(|url| parse_api_base_url(&url))
+            .transpose()
+            .map_err(KvError::InvalidConfig)?;
+        self.storage_mut(storage_name)?.api_base_url = url;
+        Ok(())
+    }
+
+    /// Send the header `name: value` with a storage's API requests, or stop
+    /// sending it
+    pub fn set_header(
+        &mut self,
+        storage_name: &str,
+        name: &str,
+        value: Option<String>,
+    ) -> Result<()> {
+        let storage = self.storage_mut(storage_name)?;
+        match value {
+            Some(value) => {
+                // Placeholders may be for variables set only where cfkv runs
+                let expanded = expand_env(&value).unwrap_or_else(|_| value.clone());
+                check_header(name, &expanded).map_err(KvError::InvalidConfig)?;
+                storage.headers.insert(name.to_string(), value);
+            }
+            None => {
+                if storage.headers.remove(name).is_none() {
+                    return Err(KvError::InvalidConfig(format!(
+                        "Storage '{}' sends no header '{}'",
+                        storage_name, name
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn storage_mut(&mut self, storage_name: &str) -> Result<&mut Storage> {
+        self.storages
+            .get_mut(storage_name)
+            .ok_or_else(|| KvError::InvalidConfig(format!("Storage '{}' not found", storage_name)))
+    }
+
+    /// Mark keys under `prefix` as sensitive, or stop treating them so
+    pub fn set_sensitive(
+        &mut self,
+        storage_name: &str,
+        prefix: &str,
+        sensitive: bool,
+    ) -> Result<()> {
+        let storage = self.storages.get_mut(storage_name).ok_or_else(|| {
+            cloudflare_kv::KvError::InvalidConfig(format!("Storage '{}' not found", storage_name))
+        })?;
+        let position = storage.sensitive_prefixes.iter().position(|p| p == prefix);
+        match (position, sensitive) {
+            (None, true) => storage.sensitive_prefixes.push(prefix.to_string()),
+            (Some(index), false) => {
+                storage.sensitive_prefixes.remove(index);
+            }
+            (None, false) => {
+                return Err(cloudflare_kv::KvError::InvalidConfig(format!(
+                    "'{}' is not a sensitive prefix of storage '{}'",
+                    prefix, storage_name
+                )))
+            }
+            (Some(_), true) => {}
+        }
+        Ok(())
+    }
+
+    /// Purge `url` after writes to keys under `prefix`, setting the zone of
+    /// the prefix's URLs when one is given
+    pub fn add_purge_url(
+        &mut self,
+        storage_name: &str,
+        prefix: &str,
+        url: &str,
+        zone_id: Option<String>,
+    ) -> Result<()> {
+        let storage = self.storages.get_mut(storage_name).ok_or_else(|| {
+            cloudflare_kv::KvError::InvalidConfig(format!("Storage '{}' not found", storage_name))
+        })?;
+        let hook = match storage.purge_urls.iter().position(|h| h.prefix == prefix) {
+            Some(index) => &mut storage.purge_urls[index],
+            None => {
+                storage.purge_urls.push(PurgeHook {
+                    prefix: prefix.to_string(),
+                    ..Default::default()
+                });
+                storage.purge_urls.last_mut().expect("just pushed")
+            }
+        };
+        if !hook.urls.iter().any(|u| u == url) {
+            hook.urls.push(url.to_string());
+        }
+        if zone_id.is_some() {
+            hook.zone_id = zone_id;
+        }
+        Ok(())
+    }
+
+    /// Stop purging URLs after writes to keys under `prefix`
+    pub fn remove_purge_urls(&mut self, storage_name: &str, prefix: &str) -> Result<()> {
+        let storage = self.storages.get_mut(storage_name).ok_or_else(|| {
+            cloudflare_kv::KvError::InvalidConfig(format!("Storage '{}' not found", storage_name))
+        })?;
+        match storage.purge_urls.iter().position(|h| h.prefix == prefix) {
+            Some(index) => {
+                storage.purge_urls.remove(index);
+                Ok(())
+            }
+            None => Err(cloudflare_kv::KvError::InvalidConfig(format!(
+                "Storage '{}' purges no URLs for prefix '{}'",
+                storage_name, prefix
+            ))),
+        }
+    }
+
+    /// Obtain a storage's API token from a command, dropping the stored
+    /// token, or stop using a command
+    pub fn set_api_token_cmd(&mut self, storage_name: &str, command: Option<String>) -> Result<()> {
+        let storage = self.storages.get_mut(storage_name).ok_or_else(|| {
+            cloudflare_kv::KvError::InvalidConfig(format!("Storage '{}' not found", storage_name))
+        })?;
+        if command.is_some() {
+            storage.api_token.clear();
+        }
+        storage.api_token_cmd = command;
+        Ok(())
+    }
+
     /// Export storages to JSON format
     pub fn export_to_json(&self) -> Result<String> {
         let export = StorageExport {
@@ -246,6 +1181,7 @@ impl Config {
         let export: StorageExport = serde_json::from_str(json)?;
         self.storages = export.storages;
         self.active_storage = export.active_storage;
+        self.resolve_accounts();
         Ok(())
     }
 
@@ -285,6 +1221,7 @@ impl Config {
                     account_id,
                     namespace_id,
                     api_token,
+                    ..Default::default()
                 };
                 storages.insert(storage_name, storage);
             }
@@ -303,23 +1240,277 @@ impl Config {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::sync::Mutex;
-
-    lazy_static::lazy_static! {
-        static ref ENV_TEST_LOCK: Mutex<()> = Mutex::new(());
+/// Check an API base URL, returning it without a trailing slash
+pub fn parse_api_base_url(url: &str) -> std::result::Result<String, String> {
+    match reqwest::Url::parse(url) {
+        Ok(parsed)
+            if matches!(parsed.scheme(), "http" | "https")
+                && parsed.host().is_some()
+                && parsed.query().is_none()
+                && parsed.fragment().is_none() =>
+        {
+            Ok(url.trim_end_matches('/').to_string())
+        }
+        _ => Err(format!(
+            "'{}' is not an http(s) URL without a query or fragment",
+            url
+        )),
     }
+}
 
-    #[test]
-    fn test_config_default() {
-        let config = Config::default();
-        assert!(config.storages.is_empty());
-        assert_eq!(config.active_storage, None);
+/// Check a header to send with API requests
+pub fn check_header(name: &str, value: &str) -> std::result::Result<(), String> {
+    if reqwest::header::HeaderName::from_bytes(name.as_bytes()).is_err() {
+        return Err(format!("'{}' is not a valid header name", name));
     }
-
-    #[test]
+    if name.eq_ignore_ascii_case("authorization") {
+        return Err("the Authorization header is set from the API token".to_string());
+    }
+    if reqwest::header::HeaderValue::from_str(value).is_err() {
+        return Err(format!("header '{}' has an invalid value", name));
+    }
+    Ok(())
+}
+
+/// Replace `${NAME}` with the environment variable `NAME`; `$${` is a
+/// literal `${`
+pub fn expand_env(value: &str) -> std::result::Result<String, String> {
+    expand_with(value, |name| std::env::var(name).ok())
+}
+
+pub(crate) fn expand_with(
+    value: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> std::result::Result<String, String> {
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(after) = rest.strip_prefix("$${") {
+            expanded.push_str("${");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end = after
+                .find('}')
+                .ok_or_else(|| "'${' without a closing '}'".to_string())?;
+            let name = &after[..end];
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(format!("invalid environment variable name '{}'", name));
+            }
+            let variable =
+                lookup(name).ok_or_else(|| format!("environment variable {} is not set", name))?;
+            expanded.push_str(&variable);
+            rest = &after[end + 1..];
+        } else {
+            expanded.push('$');
+            rest = &rest[1..];
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// `path` with `suffix` appended to its file name
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Write a file only the user can read, as it may hold credentials
+fn write_private(path: &Path, content: &str) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Read what can be read from a damaged config file, returning the config
+/// and a description of each part left out
+///
+/// Settings are kept one top-level field at a time, and a field that does
+/// not read as a whole keeps its readable entries, so one broken storage
+/// does not cost the others. A file that is not even valid JSON or TOML
+/// leaves nothing to keep.
+pub fn salvage(path: &Path, content: &str) -> Result<(Config, Vec<String>)> {
+    let format = ConfigFormat::from_path(path);
+    if let Ok(envelope) = format.deserialize::<EncryptedEnvelope>(content) {
+        let passphrase = encryption::read_passphrase("Config passphrase: ")?;
+        let plaintext = envelope.open(&passphrase)?;
+        let plaintext =
+            String::from_utf8(plaintext).map_err(|e| KvError::SerializationError(e.to_string()))?;
+        let (mut config, dropped) = salvage_plaintext(&plaintext, format);
+        config.passphrase = Some(passphrase);
+        return Ok((config, dropped));
+    }
+    Ok(salvage_plaintext(content, format))
+}
+
+fn salvage_plaintext(content: &str, format: ConfigFormat) -> (Config, Vec<String>) {
+    let fields = match format.deserialize::<serde_json::Value>(content) {
+        Ok(serde_json::Value::Object(fields)) => fields,
+        Ok(_) => {
+            return (
+                Config::default(),
+                vec!["the whole file: not a table of settings".to_string()],
+            )
+        }
+        Err(e) => return (Config::default(), vec![format!("the whole file: {}", e)]),
+    };
+
+    let read = |kept: &serde_json::Map<String, serde_json::Value>| {
+        serde_json::from_value::<Config>(serde_json::Value::Object(kept.clone()))
+    };
+    let mut kept = serde_json::Map::new();
+    let mut dropped = Vec::new();
+    for (field, value) in fields {
+        kept.insert(field.clone(), value.clone());
+        let error = match read(&kept) {
+            Ok(_) => continue,
+            Err(e) => e,
+        };
+
+        // Keep the entries of a map or list that read on their own
+        let (mut section, entries): (serde_json::Value, Vec<(String, serde_json::Value)>) =
+            match value {
+                serde_json::Value::Object(entries) => (
+                    serde_json::Value::Object(serde_json::Map::new()),
+                    entries.into_iter().collect(),
+                ),
+                serde_json::Value::Array(items) => (
+                    serde_json::Value::Array(Vec::new()),
+                    items
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, item)| (i.to_string(), item))
+                        .collect(),
+                ),
+                _ => {
+                    kept.remove(&field);
+                    dropped.push(format!("{}: {}", field, error));
+                    continue;
+                }
+            };
+        for (name, entry) in entries {
+            let mut candidate = section.clone();
+            match &mut candidate {
+                serde_json::Value::Object(map) => {
+                    map.insert(name.clone(), entry);
+                }
+                serde_json::Value::Array(items) => items.push(entry),
+                _ => unreachable!("sections are maps or lists"),
+            }
+            kept.insert(field.clone(), candidate.clone());
+            match read(&kept) {
+                Ok(_) => section = candidate,
+                Err(e) => dropped.push(format!("{}.{}: {}", field, name, e)),
+            }
+        }
+        kept.insert(field.clone(), section);
+        if read(&kept).is_err() {
+            kept.remove(&field);
+            dropped.push(format!("{}: {}", field, error));
+        }
+    }
+
+    let mut config = read(&kept).unwrap_or_default();
+    config.resolve_accounts();
+    (config, dropped)
+}
+
+/// Result of `cfkv config validate`
+#[derive(Debug, Serialize)]
+pub struct Validation {
+    pub path: PathBuf,
+    pub schema_version: u32,
+    /// Empty when the config is valid
+    pub problems: Vec<String>,
+}
+
+/// Check the config file at `path` without changing it
+///
+/// A file that does not parse is reported as a problem rather than an
+/// error, as are missing environment variables and a schema version newer
+/// than this cfkv supports. Older files are checked as they will be after
+/// migration.
+pub fn validate(path: &Path) -> Result<Validation> {
+    let content = read_locked(path)?;
+    let mut validation = Validation {
+        path: path.to_path_buf(),
+        schema_version: 0,
+        problems: Vec::new(),
+    };
+    let mut config = match Config::parse(path, &content) {
+        Ok(config) => config,
+        Err(e) => {
+            validation.problems.push(e.to_string());
+            return Ok(validation);
+        }
+    };
+    validation.schema_version = config.schema_version;
+    if let Err(e) = config.clone().expand_env() {
+        validation.problems.push(e.to_string());
+    }
+    config.resolve_accounts();
+    match config.migrate() {
+        Ok(_) => validation.problems.extend(config.problems()),
+        Err(e) => validation.problems.push(e.to_string()),
+    }
+    Ok(validation)
+}
+
+/// Take the advisory lock guarding a config file, shared for reading or
+/// exclusive for writing
+///
+/// The lock is on a `.lock` file beside the config, since saving replaces
+/// the config file itself. It is released when the returned file is dropped.
+fn lock(path: &Path, exclusive: bool) -> std::io::Result<fs::File> {
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(sibling(path, ".lock"))?;
+    if exclusive {
+        file.lock()?;
+    } else {
+        file.lock_shared()?;
+    }
+    Ok(file)
+}
+
+/// Read a config file while no other cfkv process is saving it
+fn read_locked(path: &Path) -> Result<String> {
+    // A config in a read-only directory cannot be saved either, so it is
+    // read without the lock
+    let _lock = lock(path, false).ok();
+    Ok(fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    lazy_static::lazy_static! {
+        static ref ENV_TEST_LOCK: Mutex<()> = Mutex::new(());
+    }
+
+    #[test]
+    fn test_config_default() {
+        let config = Config::default();
+        assert!(config.storages.is_empty());
+        assert_eq!(config.active_storage, None);
+    }
+
+    #[test]
     fn test_add_storage() {
         let mut config = Config::default();
         config.add_storage(
@@ -372,6 +1563,32 @@ mod tests {
         assert_eq!(config.get_active_storage().unwrap().name, "dev");
     }
 
+    #[test]
+    fn test_select_storage_override() {
+        let mut config = Config::default();
+        config.add_storage(
+            "prod".to_string(),
+            "acc123".to_string(),
+            "ns456".to_string(),
+            "token789".to_string(),
+        );
+        config.add_storage(
+            "dev".to_string(),
+            "acc999".to_string(),
+            "ns999".to_string(),
+            "token999".to_string(),
+        );
+
+        assert_eq!(config.select_storage(None).unwrap().unwrap().name, "prod");
+        assert_eq!(
+            config.select_storage(Some("dev")).unwrap().unwrap().name,
+            "dev"
+        );
+        assert!(config.select_storage(Some("missing")).is_err());
+        // Selecting a storage never changes the active one
+        assert_eq!(config.active_storage, Some("prod".to_string()));
+    }
+
     #[test]
     fn test_remove_storage() {
         let mut config = Config::default();
@@ -437,6 +1654,371 @@ mod tests {
         assert_eq!(config.active_storage, Some("production".to_string()));
     }
 
+    #[test]
+    fn test_add_and_resolve_namespace() {
+        let mut config = Config::default();
+        config.add_storage(
+            "prod".to_string(),
+            "acc123".to_string(),
+            "ns456".to_string(),
+            "token789".to_string(),
+        );
+        config
+            .add_namespace("prod", "sessions".to_string(), "ns-sessions".to_string())
+            .unwrap();
+
+        let storage = config.get_storage("prod").unwrap();
+        assert_eq!(storage.resolve_namespace(None).unwrap(), "ns456");
+        assert_eq!(
+            storage.resolve_namespace(Some("sessions")).unwrap(),
+            "ns-sessions"
+        );
+        assert!(storage.resolve_namespace(Some("cache")).is_err());
+        assert!(config
+            .add_namespace("missing", "cache".to_string(), "ns".to_string())
+            .is_err());
+    }
+
+    #[test]
+    fn test_namespace_mappings() {
+        let mut config = Config::default();
+        for (name, account, namespace) in [
+            ("prod", "acc1", "ns-prod"),
+            ("staging", "acc1", "ns-staging"),
+            ("other", "acc2", "ns-other"),
+        ] {
+            config.add_storage(
+                name.to_string(),
+                account.to_string(),
+                namespace.to_string(),
+                "token".to_string(),
+            );
+        }
+        config
+            .add_namespace("prod", "sessions".to_string(), "ns-sessions".to_string())
+            .unwrap();
+        config
+            .add_namespace("staging", "prod".to_string(), "ns-prod".to_string())
+            .unwrap();
+
+        assert_eq!(
+            config.storages_by_account(),
+            vec![
+                vec!["prod".to_string(), "staging".to_string()],
+                vec!["other".to_string()]
+            ]
+        );
+
+        let mappings = config.namespace_mappings("acc1");
+        assert_eq!(mappings.len(), 3);
+        assert_eq!(mappings["ns-prod"], vec!["prod", "staging/prod"]);
+        assert_eq!(mappings["ns-sessions"], vec!["prod/sessions"]);
+        assert!(!mappings.contains_key("ns-other"));
+    }
+
+    #[test]
+    fn test_set_encryption_key() {
+        let mut config = Config::default();
+        config.add_storage(
+            "prod".to_string(),
+            "acc123".to_string(),
+            "ns456".to_string(),
+            "token789".to_string(),
+        );
+
+        config
+            .set_encryption_key("prod", Some("a2V5".to_string()))
+            .unwrap();
+        assert_eq!(
+            config
+                .get_storage("prod")
+                .unwrap()
+                .encryption_key
+                .as_deref(),
+            Some("a2V5")
+        );
+
+        config.set_encryption_key("prod", None).unwrap();
+        assert!(config.get_storage("prod").unwrap().encryption_key.is_none());
+        assert!(config.set_encryption_key("missing", None).is_err());
+    }
+
+    #[test]
+    fn test_set_read_only() {
+        let mut config = Config::default();
+        config.add_storage(
+            "prod".to_string(),
+            "acc123".to_string(),
+            "ns456".to_string(),
+            "token789".to_string(),
+        );
+        let json = serde_json::to_string(config.get_storage("prod").unwrap()).unwrap();
+        assert!(!json.contains("read_only"));
+
+        config.set_read_only("prod", true).unwrap();
+        let json = serde_json::to_string(config.get_storage("prod").unwrap()).unwrap();
+        let storage: Storage = serde_json::from_str(&json).unwrap();
+        assert!(storage.read_only);
+        assert!(config.set_read_only("missing", true).is_err());
+    }
+
+    #[test]
+    fn test_set_key_prefix() {
+        let mut config = Config::default();
+        config.add_storage(
+            "shared".to_string(),
+            "acc123".to_string(),
+            "ns456".to_string(),
+            "token789".to_string(),
+        );
+        config
+            .set_key_prefix("shared", Some("billing/".to_string()))
+            .unwrap();
+        assert_eq!(
+            config.get_storage("shared").unwrap().key_prefix.as_deref(),
+            Some("billing/")
+        );
+
+        config
+            .set_key_prefix("shared", Some(String::new()))
+            .unwrap();
+        assert!(config.get_storage("shared").unwrap().key_prefix.is_none());
+        assert!(config.set_key_prefix("missing", None).is_err());
+    }
+
+    #[test]
+    fn test_set_r2_bucket() {
+        let mut config = Config::default();
+        config.add_storage(
+            "media".to_string(),
+            "acc123".to_string(),
+            "ns456".to_string(),
+            "token789".to_string(),
+        );
+        config
+            .set_r2_bucket("media", Some("kv-large-values".to_string()))
+            .unwrap();
+        assert_eq!(
+            config.get_storage("media").unwrap().r2_bucket.as_deref(),
+            Some("kv-large-values")
+        );
+        for invalid in ["ab", "Upper", "-edge", "under_score"] {
+            assert!(config
+                .set_r2_bucket("media", Some(invalid.to_string()))
+                .is_err());
+        }
+
+        config.set_r2_bucket("media", None).unwrap();
+        assert!(config.get_storage("media").unwrap().r2_bucket.is_none());
+        assert!(config.set_r2_bucket("missing", None).is_err());
+    }
+
+    #[test]
+    fn test_set_sensitive() {
+        let mut config = Config::default();
+        config.add_storage(
+            "prod".to_string(),
+            "acc123".to_string(),
+            "ns456".to_string(),
+            "token789".to_string(),
+        );
+        config.set_sensitive("prod", "secrets/", true).unwrap();
+        config.set_sensitive("prod", "secrets/", true).unwrap();
+        config.set_sensitive("prod", "token:", true).unwrap();
+        assert_eq!(
+            config.get_storage("prod").unwrap().sensitive_prefixes,
+            vec!["secrets/", "token:"]
+        );
+
+        config.set_sensitive("prod", "secrets/", false).unwrap();
+        assert_eq!(
+            config.get_storage("prod").unwrap().sensitive_prefixes,
+            vec!["token:"]
+        );
+        assert!(config.set_sensitive("prod", "secrets/", false).is_err());
+        assert!(config.set_sensitive("missing", "x", true).is_err());
+    }
+
+    #[test]
+    fn test_purge_urls() {
+        let mut config = Config::default();
+        config.add_storage(
+            "prod".to_string(),
+            "acc123".to_string(),
+            "ns456".to_string(),
+            "token789".to_string(),
+        );
+        config
+            .add_purge_url("prod", "pages/", "https://example.com/{path}", None)
+            .unwrap();
+        config
+            .add_purge_url("prod", "pages/", "https://example.com/{path}", None)
+            .unwrap();
+        config
+            .add_purge_url(
+                "prod",
+                "pages/",
+                "https://example.com/sitemap.xml",
+                Some("zone1".to_string()),
+            )
+            .unwrap();
+        assert_eq!(
+            config.get_storage("prod").unwrap().purge_urls,
+            vec![PurgeHook {
+                prefix: "pages/".to_string(),
+                urls: vec![
+                    "https://example.com/{path}".to_string(),
+                    "https://example.com/sitemap.xml".to_string(),
+                ],
+                zone_id: Some("zone1".to_string()),
+            }]
+        );
+
+        config.remove_purge_urls("prod", "pages/").unwrap();
+        assert!(config.get_storage("prod").unwrap().purge_urls.is_empty());
+        assert!(config.remove_purge_urls("prod", "pages/").is_err());
+        assert!(config
+            .add_purge_url("missing", "", "https://x.io", None)
+            .is_err());
+    }
+
+    #[test]
+    fn test_shared_account() {
+        let mut config = Config::default();
+        config.set_account(
+            "main".to_string(),
+            Account {
+                account_id: "acc123".to_string(),
+                api_token: "old".to_string(),
+                ..Default::default()
+            },
+        );
+        for name in ["prod", "staging"] {
+            config.add_storage(
+                name.to_string(),
+                String::new(),
+                format!("ns-{}", name),
+                String::new(),
+            );
+            config
+                .set_storage_account(name, Some("main".to_string()))
+                .unwrap();
+        }
+        assert_eq!(config.get_storage("prod").unwrap().account_id, "acc123");
+
+        // Rotating the token updates every storage referencing the account
+        config.set_account(
+            "main".to_string(),
+            Account {
+                account_id: "acc123".to_string(),
+                api_token: "new".to_string(),
+                ..Default::default()
+            },
+        );
+        for name in ["prod", "staging"] {
+            assert_eq!(config.get_storage(name).unwrap().api_token, "new");
+        }
+
+        assert!(config
+            .set_storage_account("prod", Some("missing".to_string()))
+            .is_err());
+        assert!(config.remove_account("main").is_err());
+
+        // Unlinking keeps the credentials inline
+        config.set_storage_account("prod", None).unwrap();
+        config.set_storage_account("staging", None).unwrap();
+        assert_eq!(config.get_storage("prod").unwrap().api_token, "new");
+        config.remove_account("main").unwrap();
+        assert!(config.accounts.is_empty());
+    }
+
+    #[test]
+    fn test_shared_account_serialization() {
+        let json = r#"{
+            "accounts": {"main": {"account_id": "acc123", "api_token": "token789"}},
+            "storages": {
+                "prod": {"name": "prod", "account": "main", "namespace_id": "ns1"},
+                "legacy": {
+                    "name": "legacy",
+                    "account_id": "acc999",
+                    "namespace_id": "ns2",
+                    "api_token": "inline"
+                }
+            },
+            "active_storage": "prod"
+        }"#;
+        let dir = std::env::temp_dir().join(format!("cfkv-account-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+        fs::write(&path, json).unwrap();
+
+        let config = Config::load_or_create(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let prod = config.select_storage(None).unwrap().unwrap();
+        assert_eq!(prod.account_id, "acc123");
+        assert_eq!(prod.api_token, "token789");
+        assert_eq!(config.get_storage("legacy").unwrap().api_token, "inline");
+
+        // Inherited credentials are not written into the storage
+        let content = config.to_file_content(ConfigFormat::Json).unwrap();
+        assert_eq!(content.matches("token789").count(), 1);
+        assert!(content.contains("inline"));
+
+        let mut config = config;
+        config.accounts.clear();
+        config.storages.get_mut("prod").unwrap().api_token.clear();
+        assert!(config.select_storage(Some("prod")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_api_token_cmd() {
+        let json = r#"{
+            "accounts": {"main": {"account_id": "acc123", "api_token_cmd": "echo from-cmd"}},
+            "storages": {
+                "prod": {"name": "prod", "account": "main", "namespace_id": "ns1"},
+                "dev": {
+                    "name": "dev",
+                    "account_id": "acc123",
+                    "namespace_id": "ns2",
+                    "api_token_cmd": "echo dev-token"
+                }
+            }
+        }"#;
+        let mut config: Config = serde_json::from_str(json).unwrap();
+        config.resolve_accounts();
+
+        assert_eq!(
+            config.get_storage("prod").unwrap().token().unwrap(),
+            "from-cmd"
+        );
+        assert_eq!(
+            config.get_storage("dev").unwrap().token().unwrap(),
+            "dev-token"
+        );
+        assert!(config.select_storage(Some("prod")).is_ok());
+
+        // Only the commands are written, never their output
+        let content = config.to_file_content(ConfigFormat::Json).unwrap();
+        assert!(!content.contains("\"api_token\""));
+        assert_eq!(content.matches("api_token_cmd").count(), 2);
+    }
+
+    #[test]
+    fn test_storage_without_namespaces_field() {
+        let json = r#"{
+            "name": "prod",
+            "account_id": "acc123",
+            "namespace_id": "ns456",
+            "api_token": "token789"
+        }"#;
+        let storage: Storage = serde_json::from_str(json).unwrap();
+        assert!(storage.namespaces.is_empty());
+        assert!(!serde_json::to_string(&storage)
+            .unwrap()
+            .contains("namespaces"));
+    }
+
     #[test]
     fn test_migration_from_legacy_format() {
         let mut config = Config {
@@ -445,6 +2027,7 @@ mod tests {
             account_id: Some("acc123".to_string()),
             namespace_id: Some("ns456".to_string()),
             api_token: Some("token789".to_string()),
+            ..Default::default()
         };
 
         config.migrate_legacy_format();
@@ -455,6 +2038,354 @@ mod tests {
         assert!(config.account_id.is_none());
     }
 
+    #[test]
+    fn test_expand_env() {
+        let lookup = |name: &str| (name == "TOKEN").then(|| "secret".to_string());
+        assert_eq!(expand_with("${TOKEN}", lookup).unwrap(), "secret");
+        assert_eq!(expand_with("a-${TOKEN}-b", lookup).unwrap(), "a-secret-b");
+        assert_eq!(
+            expand_with("$5 and $${TOKEN}", lookup).unwrap(),
+            "$5 and ${TOKEN}"
+        );
+        assert_eq!(
+            expand_with("${MISSING}", lookup).unwrap_err(),
+            "environment variable MISSING is not set"
+        );
+        assert!(expand_with("${TOKEN", lookup).is_err());
+        assert!(expand_with("${}", lookup).is_err());
+    }
+
+    #[test]
+    fn test_placeholders_not_saved() {
+        let variable = format!("CFKV_TEST_TOKEN_{}", std::process::id());
+        std::env::set_var(&variable, "secret-token");
+        let json = format!(
+            r#"{{"storages": {{"prod": {{"name": "prod", "account_id": "acc", "namespace_id": "ns", "api_token": "${{{}}}"}}}}}}"#,
+            variable
+        );
+        let mut config: Config = ConfigFormat::Json.deserialize(&json).unwrap();
+        config.expand_env().unwrap();
+        assert_eq!(
+            config.get_storage("prod").unwrap().token().unwrap(),
+            "secret-token"
+        );
+
+        // Saving keeps the placeholder, also for storages copied from it
+        config
+            .rename_storage("prod", "production".to_string())
+            .unwrap();
+        let content = config.to_file_content(ConfigFormat::Json).unwrap();
+        assert!(!content.contains("secret-token"));
+        assert!(content.contains(&format!("${{{}}}", variable)));
+
+        std::env::remove_var(&variable);
+        let error = config.clone().expand_env();
+        assert!(error.is_ok(), "expanded values have no placeholders left");
+        let mut reloaded: Config = ConfigFormat::Json.deserialize(&content).unwrap();
+        let error = reloaded.expand_env().unwrap_err().to_string();
+        assert!(
+            error.contains("storage 'production' api_token"),
+            "{}",
+            error
+        );
+        assert!(error.contains(&variable), "{}", error);
+    }
+
+    #[test]
+    fn test_http_section() {
+        let config: Config = ConfigFormat::Toml
+            .deserialize("[http]\ntimeout = \"30s\"\nretries = 3\nretry_delay = \"250ms\"\n")
+            .unwrap();
+        assert_eq!(config.http.timeout, Some(Duration::from_secs(30)));
+        assert_eq!(config.http.retries, Some(3));
+        assert_eq!(config.http.retry_delay, Some(Duration::from_millis(250)));
+        let content = config.to_file_content(ConfigFormat::Json).unwrap();
+        assert!(content.contains("\"timeout\": \"30s\""), "{}", content);
+        assert!(
+            content.contains("\"retry_delay\": \"250ms\""),
+            "{}",
+            content
+        );
+
+        assert!(!Config::default()
+            .to_file_content(ConfigFormat::Json)
+            .unwrap()
+            .contains("http"));
+        assert!(ConfigFormat::Json
+            .deserialize::<Config>(r#"{"http": {"timeout": "soon"}}"#)
+            .is_err());
+    }
+
+    #[test]
+    fn test_schema_migration() {
+        // Files from before versioning are migrated to the current version
+        let mut config: Config = ConfigFormat::Json
+            .deserialize(r#"{"storages": {}, "account_id": "acc", "namespace_id": "ns", "api_token": "token"}"#)
+            .unwrap();
+        assert_eq!(config.schema_version, 0);
+        assert!(config.migrate().unwrap());
+        assert_eq!(config.schema_version, SCHEMA_VERSION);
+        assert_eq!(config.active_storage.as_deref(), Some("default"));
+        assert!(!config.migrate().unwrap());
+
+        let mut newer = Config {
+            schema_version: SCHEMA_VERSION + 1,
+            ..Default::default()
+        };
+        let error = newer.migrate().unwrap_err().to_string();
+        assert!(error.contains("upgrade cfkv"), "{}", error);
+    }
+
+    #[test]
+    fn test_problems() {
+        let mut config = Config::default();
+        config.add_storage(
+            "prod".to_string(),
+            "acc".to_string(),
+            "ns".to_string(),
+            "token".to_string(),
+        );
+        config.set_active_storage("prod".to_string()).unwrap();
+        assert!(config.problems().is_empty());
+
+        config.active_storage = Some("gone".to_string());
+        config.storages.insert(
+            "dev".to_string(),
+            Storage {
+                name: "develop".to_string(),
+                account: Some("missing".to_string()),
+                namespace_id: "ns".to_string(),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            config.problems(),
+            [
+                "active_storage 'gone' is not a configured storage",
+                "storage 'dev' is named 'develop' inside its entry",
+                "storage 'dev' uses unknown account 'missing'",
+                "storage 'dev' is unreachable: missing account_id, api_token",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_gateway_settings() {
+        let mut config = Config::default();
+        config.add_storage(
+            "prod".to_string(),
+            "acc".to_string(),
+            "ns".to_string(),
+            "token".to_string(),
+        );
+
+        for url in ["ftp://gw", "gateway.internal", "https://gw/api?x=1"] {
+            assert!(config
+                .set_api_base_url("prod", Some(url.to_string()))
+                .is_err());
+        }
+        config
+            .set_api_base_url("prod", Some("https://gw.internal/cloudflare/".to_string()))
+            .unwrap();
+        assert!(config
+            .set_header("prod", "Authorization", Some("x".to_string()))
+            .is_err());
+        assert!(config
+            .set_header("prod", "Bad Header", Some("x".to_string()))
+            .is_err());
+        config
+            .set_header("prod", "X-Gateway-Key", Some("${HOME}".to_string()))
+            .unwrap();
+        assert!(config.problems().is_empty());
+
+        let client_config = config.storages["prod"].endpoint(ClientConfig::new(
+            "acc",
+            "ns",
+            cloudflare_kv::AuthCredentials::token("token"),
+        ));
+        assert_eq!(client_config.base_url, "https://gw.internal/cloudflare");
+        assert_eq!(
+            client_config.headers,
+            [("X-Gateway-Key".to_string(), "${HOME}".to_string())]
+        );
+
+        config.set_header("prod", "X-Gateway-Key", None).unwrap();
+        assert!(config.set_header("prod", "X-Gateway-Key", None).is_err());
+        let storage = config.storages.get_mut("prod").unwrap();
+        storage.api_base_url = Some("not a url".to_string());
+        storage.headers.insert("X Bad".to_string(), "v".to_string());
+        assert_eq!(config.problems().len(), 2);
+    }
+
+    #[test]
+    fn test_validate() {
+        let dir = std::env::temp_dir().join(format!("cfkv-validate-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+
+        fs::write(&path, r#"{"storages": {"#).unwrap();
+        let validation = validate(&path).unwrap();
+        assert_eq!(validation.problems.len(), 1);
+        assert!(validation.problems[0].contains("Failed to parse"));
+
+        fs::write(&path, r#"{"schema_version": 99}"#).unwrap();
+        let validation = validate(&path).unwrap();
+        assert_eq!(validation.schema_version, 99);
+        assert!(validation.problems[0].contains("newer"));
+
+        let legacy = r#"{"account_id": "acc", "namespace_id": "ns", "api_token": "token"}"#;
+        fs::write(&path, legacy).unwrap();
+        let validation = validate(&path).unwrap();
+        assert_eq!(validation.schema_version, 0);
+        assert!(validation.problems.is_empty(), "{:?}", validation.problems);
+        // Validating never migrates the file
+        assert_eq!(fs::read_to_string(&path).unwrap(), legacy);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_salvage() {
+        let path = Path::new("config.json");
+        let content = r#"{
+            "storages": {
+                "prod": {"name": "prod", "account_id": "acc", "namespace_id": "ns", "api_token": "token"},
+                "broken": {"name": "broken", "namespace_id": 42}
+            },
+            "active_storage": "prod",
+            "confirm_destructive": "sometimes"
+        }"#;
+        let (config, dropped) = salvage(path, content).unwrap();
+        assert!(config.get_storage("prod").is_some());
+        assert!(config.get_storage("broken").is_none());
+        assert_eq!(config.active_storage.as_deref(), Some("prod"));
+        assert_eq!(config.confirm_destructive, None);
+        assert_eq!(dropped.len(), 2, "{:?}", dropped);
+        assert!(dropped[0].starts_with("confirm_destructive: "));
+        assert!(dropped[1].starts_with("storages.broken: "));
+
+        let (config, dropped) = salvage(path, r#"{"storages": {"#).unwrap();
+        assert_eq!(config, Config::default());
+        assert!(dropped[0].starts_with("the whole file: "));
+        assert!(salvage(path, "{}").unwrap().1.is_empty());
+    }
+
+    #[test]
+    fn test_corrupt_config_kept() {
+        let dir = std::env::temp_dir().join(format!("cfkv-corrupt-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+
+        // A file that does not parse is an error naming where, not an
+        // empty config
+        fs::write(&path, "{\n  \"storages\": {,\n}").unwrap();
+        let error = Config::load_or_create(&path).unwrap_err().to_string();
+        assert!(error.contains("line 2"), "{}", error);
+        assert!(error.contains("cfkv config repair"), "{}", error);
+
+        // An empty file is an empty config
+        fs::write(&path, "").unwrap();
+        assert_eq!(Config::load_or_create(&path).unwrap(), Config::default());
+
+        // Saving keeps the content it replaces
+        fs::write(&path, "{\"storages\": {,}").unwrap();
+        Config::default().save(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("config.json.bak")).unwrap(),
+            "{\"storages\": {,}"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_migration_saved_once() {
+        let dir = std::env::temp_dir().join(format!("cfkv-migrate-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+        fs::write(
+            &path,
+            r#"{"account_id": "acc", "namespace_id": "ns", "api_token": "token"}"#,
+        )
+        .unwrap();
+
+        let config = Config::load_or_create(&path).unwrap();
+        assert!(config.get_storage("default").is_some());
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(saved.contains("\"storages\""));
+        assert!(saved.contains(&format!("\"schema_version\": {}", SCHEMA_VERSION)));
+        assert_eq!(
+            fs::read_to_string(dir.join("config.json.v0.bak")).unwrap(),
+            r#"{"account_id": "acc", "namespace_id": "ns", "api_token": "token"}"#
+        );
+
+        // Loading or saving the migrated config again leaves the file alone
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        assert_eq!(Config::load_or_create(&path).unwrap(), config);
+        config.save(&path).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), modified);
+
+        // A migration is not written over a file changed since it was read
+        let legacy = Config {
+            account_id: Some("old".to_string()),
+            ..Default::default()
+        };
+        legacy.write(&path, Some("{}")).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), saved);
+
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "config.json",
+                "config.json.bak",
+                "config.json.lock",
+                "config.json.v0.bak"
+            ]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_saves() {
+        let dir = std::env::temp_dir().join(format!("cfkv-lock-test-{}", std::process::id()));
+        let path = dir.join("config.json");
+        Config::default().save(&path).unwrap();
+
+        let writers: Vec<_> = (0..4)
+            .map(|writer| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    for i in 0..20 {
+                        let mut config = Config::default();
+                        config.add_storage(
+                            format!("storage-{}-{}", writer, i),
+                            "acc".to_string(),
+                            "ns".to_string(),
+                            "x".repeat(10_000),
+                        );
+                        config.save(&path).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for _ in 0..50 {
+            // A torn read would fail to parse and load as an empty config
+            let content = fs::read_to_string(&path).unwrap();
+            if content.contains("storage-") {
+                let config: Config = ConfigFormat::Json.deserialize(&content).unwrap();
+                assert_eq!(config.storages.len(), 1);
+            }
+        }
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        assert_eq!(Config::load_or_create(&path).unwrap().storages.len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     fn test_config_serialization_deserialization() {
         let mut config = Config::default();
@@ -474,6 +2405,217 @@ mod tests {
         assert_eq!(config.storages.len(), deserialized.storages.len());
     }
 
+    #[test]
+    fn test_encrypted_config_roundtrip() {
+        let mut config = Config::default();
+        config.add_storage(
+            "prod".to_string(),
+            "acc123".to_string(),
+            "ns456".to_string(),
+            "token789".to_string(),
+        );
+        config.passphrase = Some("correct horse".to_string());
+
+        for format in [ConfigFormat::Json, ConfigFormat::Toml] {
+            let content = config.to_file_content(format).unwrap();
+            assert!(!content.contains("token789"));
+
+            let envelope: EncryptedEnvelope = format.deserialize(&content).unwrap();
+            let decrypted =
+                Config::decrypt(&envelope, format, "correct horse".to_string()).unwrap();
+            assert_eq!(decrypted, config);
+            assert!(Config::decrypt(&envelope, format, "wrong".to_string()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_plaintext_config_content() {
+        let config = Config::default();
+        let content = config.to_file_content(ConfigFormat::Json).unwrap();
+        assert!(ConfigFormat::Json
+            .deserialize::<EncryptedEnvelope>(&content)
+            .is_err());
+        assert!(!content.contains("passphrase"));
+    }
+
+    #[test]
+    fn test_plugins_section() {
+        let json = r#"{
+            "storages": {},
+            "plugins": [
+                {"name": "compression", "config": {"algorithm": "zstd"}},
+                {"name": "encryption", "enabled": false}
+            ]
+        }"#;
+        let config: Config = serde_json::from_str(json).unwrap();
+        assert_eq!(config.plugins.len(), 2);
+        assert!(config.plugins[0].enabled);
+        assert_eq!(config.plugins[0].config["algorithm"], "zstd");
+        assert!(!config.plugins[1].enabled);
+        assert!(config.plugins[1].config.is_null());
+
+        let toml = config.to_file_content(ConfigFormat::Toml).unwrap();
+        let parsed: Config = ConfigFormat::Toml.deserialize(&toml).unwrap();
+        assert_eq!(parsed, config);
+    }
+
+    #[test]
+    fn test_blog_section() {
+        let config = Config::default();
+        assert!(!serde_json::to_string(&config).unwrap().contains("blog"));
+
+        let config = Config {
+            blog: BlogConfig {
+                required_fields: vec!["cover_image".to_string(), "series".to_string()],
+                post_publish_hook: Some(PublishHook::Command {
+                    command: "./rebuild.sh".to_string(),
+                }),
+            },
+            account_id: Some("acc".to_string()),
+            ..Default::default()
+        };
+        let toml = config.to_file_content(ConfigFormat::Toml).unwrap();
+        assert!(toml.contains("[blog]"));
+        let parsed: Config = ConfigFormat::Toml.deserialize(&toml).unwrap();
+        assert_eq!(parsed, config);
+
+        let parsed: Config = serde_json::from_str(
+            r#"{"blog": {"post_publish_hook": {"url": "https://example.com/hook", "headers": {"X-Token": "t"}}}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            parsed.blog.post_publish_hook,
+            Some(PublishHook::Webhook {
+                url: "https://example.com/hook".to_string(),
+                headers: BTreeMap::from([("X-Token".to_string(), "t".to_string())]),
+            })
+        );
+    }
+
+    #[test]
+    fn test_collections() {
+        let toml = r#"
+[[collections]]
+name = "docs"
+key_prefix = "doc:"
+list_key = "_docs_list"
+required_fields = ["title"]
+
+[[collections]]
+name = "broken"
+key_prefix = "x:"
+list_key = "x:list"
+"#;
+        let config: Config = ConfigFormat::Toml.deserialize(toml).unwrap();
+        let names: Vec<String> = config.collections().into_iter().map(|c| c.name).collect();
+        assert_eq!(names, vec!["blog", "docs", "broken"]);
+
+        let docs = config.get_collection("docs").unwrap();
+        assert_eq!(docs.slug_field, "slug");
+        assert_eq!(docs.required_fields, vec!["title"]);
+        assert!(config.get_collection("blog").unwrap().is_blog());
+        assert!(config.get_collection("broken").is_err());
+
+        let err = config.get_collection("missing").unwrap_err().to_string();
+        assert!(err.contains("blog, docs, broken"));
+
+        let toml = r#"
+[[collections]]
+name = "blog"
+key_prefix = "article:"
+list_key = "_articles"
+
+[[collections]]
+name = "pages"
+key_prefix = "page:"
+list_key = "_blog_pages"
+"#;
+        let config: Config = ConfigFormat::Toml.deserialize(toml).unwrap();
+        let err = config.get_collection("blog").unwrap_err().to_string();
+        assert!(err.contains("reserved"));
+        let err = config.get_collection("pages").unwrap_err().to_string();
+        assert!(err.contains("reserved"));
+    }
+
+    #[test]
+    fn test_add_plugin_replaces_by_name() {
+        let mut config = Config::default();
+        let plugin = |name: &str, path: &str| PluginConfig {
+            name: name.to_string(),
+            enabled: true,
+            config: serde_json::Value::Null,
+            path: Some(PathBuf::from(path)),
+        };
+        config.add_plugin(plugin("rot13", "/old/librot13.so"));
+        config.add_plugin(plugin("audit", "/libaudit.so"));
+        config.add_plugin(plugin("rot13", "/new/librot13.so"));
+
+        assert_eq!(config.plugins.len(), 2);
+        assert_eq!(config.plugins[0].name, "rot13");
+        assert_eq!(
+            config.plugins[0].path,
+            Some(PathBuf::from("/new/librot13.so"))
+        );
+    }
+
+    #[test]
+    fn test_config_format_from_path() {
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("config.toml")),
+            ConfigFormat::Toml
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("config.json")),
+            ConfigFormat::Json
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("config")),
+            ConfigFormat::Json
+        );
+    }
+
+    #[test]
+    fn test_toml_roundtrip() {
+        let mut config = Config::default();
+        config.add_storage(
+            "prod".to_string(),
+            "acc123".to_string(),
+            "ns456".to_string(),
+            "token789".to_string(),
+        );
+        config
+            .add_namespace("prod", "cache".to_string(), "ns-cache".to_string())
+            .unwrap();
+
+        let content = config.to_file_content(ConfigFormat::Toml).unwrap();
+        assert!(content.contains("[storages.prod]"));
+
+        let parsed: Config = ConfigFormat::Toml.deserialize(&content).unwrap();
+        assert_eq!(parsed, config);
+    }
+
+    #[test]
+    fn test_save_and_load_by_extension() {
+        let dir = std::env::temp_dir().join(format!("cfkv-config-test-{}", std::process::id()));
+        let mut config = Config::default();
+        config.add_storage(
+            "prod".to_string(),
+            "acc123".to_string(),
+            "ns456".to_string(),
+            "token789".to_string(),
+        );
+
+        for name in ["config.json", "config.toml"] {
+            let path = dir.join(name);
+            config.save(&path).unwrap();
+            assert_eq!(Config::load_or_create(&path).unwrap(), config);
+        }
+
+        let toml = fs::read_to_string(dir.join("config.toml")).unwrap();
+        assert!(toml.contains("active_storage = \"prod\""));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     fn test_export_to_json() {
         let mut config = Config::default();
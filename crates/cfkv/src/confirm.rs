@@ -0,0 +1,98 @@
+//! Confirmation before destructive commands
+//!
+//! Commands that delete data list what they are about to destroy and ask
+//! before going ahead. `--yes` skips the question for one command and
+//! `confirm_destructive = false` in the config turns it off entirely. When
+//! stdin is not a terminal nobody can answer, so single deletes go ahead as
+//! they always did; bulk deletes refuse to run without `--yes` instead.
+//! Declining exits with the cancelled code, so scripts can tell nothing was
+//! done.
+
+use crate::exit::{self, ErrorKind};
+use crate::formatter::OutputFormat;
+use std::io::{IsTerminal, Write};
+
+/// Most targets listed in a prompt before summarizing the rest
+const SHOWN_TARGETS: usize = 10;
+
+/// Whether and how to ask before destroying data
+#[derive(Clone, Copy, Debug)]
+pub struct Confirm {
+    enabled: bool,
+    interactive: bool,
+}
+
+impl Confirm {
+    /// `yes` is the `--yes` flag; `enabled` the `confirm_destructive` setting
+    pub fn new(yes: bool, enabled: bool) -> Self {
+        Self {
+            enabled: enabled && !yes,
+            interactive: std::io::stdin().is_terminal(),
+        }
+    }
+
+    /// True when a prompt is due but cannot be shown, so a bulk delete
+    /// should ask for `--yes` rather than go ahead
+    pub fn needs_yes(&self) -> bool {
+        self.enabled && !self.interactive
+    }
+
+    /// Show `targets` and ask `question`, exiting if the answer is no
+    ///
+    /// Prompts go to stderr so `--format json` output stays clean.
+    pub fn ask(
+        &self,
+        question: &str,
+        targets: &[String],
+        format: OutputFormat,
+    ) -> std::io::Result<()> {
+        if !self.enabled || !self.interactive {
+            return Ok(());
+        }
+
+        for target in targets.iter().take(SHOWN_TARGETS) {
+            eprintln!("  {}", target);
+        }
+        if targets.len() > SHOWN_TARGETS {
+            eprintln!("  ... and {} more", targets.len() - SHOWN_TARGETS);
+        }
+        eprint!("{} [y/N] ", question);
+        std::io::stderr().flush()?;
+
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        if !is_yes(&answer) {
+            exit::fail("Aborted", ErrorKind::Cancelled, format);
+        }
+        Ok(())
+    }
+}
+
+fn is_yes(answer: &str) -> bool {
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skipped_confirmation() {
+        let yes = Confirm::new(true, true);
+        assert!(!yes.needs_yes());
+        yes.ask("Delete?", &["k".to_string()], OutputFormat::Text)
+            .unwrap();
+
+        let disabled = Confirm::new(false, false);
+        assert!(!disabled.needs_yes());
+        disabled.ask("Delete?", &[], OutputFormat::Text).unwrap();
+    }
+
+    #[test]
+    fn test_is_yes() {
+        assert!(is_yes("y\n"));
+        assert!(is_yes(" YES "));
+        assert!(!is_yes("\n"));
+        assert!(!is_yes("no"));
+    }
+}
@@ -0,0 +1,268 @@
+//! Comparing two namespaces, or a namespace and an export file, for
+//! `cfkv diff`
+//!
+//! Each side is reduced to a snapshot of key → value hash and size, so
+//! values never need to be held in memory together. Values are compared
+//! after plugin decoding, the way `cfkv get` and `batch export` see them, so
+//! a compressed or encrypted copy matches its plaintext.
+
+use crate::batch;
+use crate::formatter::Formatter;
+use cloudflare_kv::{value_hash, BatchOperation, KvError, PluginClient, Result};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Hash and size of a value
+#[derive(Clone, Debug, PartialEq)]
+pub struct Digest {
+    pub hash: String,
+    pub size: u64,
+}
+
+impl Digest {
+    pub fn of(value: &[u8]) -> Self {
+        Self {
+            hash: value_hash(value),
+            size: value.len() as u64,
+        }
+    }
+}
+
+/// Digests of one side, by key
+pub type Snapshot = BTreeMap<String, Digest>;
+
+/// Snapshot of the keys under `prefix` in a namespace
+pub async fn storage_snapshot(
+    client: &PluginClient,
+    prefix: &str,
+    concurrency: usize,
+) -> Result<Snapshot> {
+    let keys = client.client().list_keys(prefix).await?;
+    keys_snapshot(client, &keys, concurrency).await
+}
+
+/// Snapshot of `keys` in a namespace; keys that do not exist are left out
+pub async fn keys_snapshot(
+    client: &PluginClient,
+    keys: &[String],
+    concurrency: usize,
+) -> Result<Snapshot> {
+    let digests: Vec<Option<Digest>> = stream::iter(keys)
+        .map(|key| async move {
+            let value = client.get_bytes(key).await?;
+            Ok::<_, KvError>(value.map(|value| Digest::of(&value)))
+        })
+        .buffered(concurrency.max(1))
+        .try_collect()
+        .await?;
+    Ok(keys
+        .iter()
+        .zip(digests)
+        .filter_map(|(key, digest)| Some((key.clone(), digest?)))
+        .collect())
+}
+
+/// Snapshot of the records under `prefix` in an export (or import) file
+pub fn file_snapshot(path: &Path, prefix: &str) -> Result<Snapshot> {
+    let records = batch::read_import_file(path)?;
+    let batch = batch::to_batch(&records)?;
+    Ok(batch
+        .operations()
+        .iter()
+        .filter_map(|operation| match operation {
+            BatchOperation::Put { key, value, .. } if key.starts_with(prefix) => {
+                Some((key.clone(), Digest::of(value)))
+            }
+            _ => None,
+        })
+        .collect())
+}
+
+/// A key on only one side
+#[derive(Debug, PartialEq, Serialize)]
+pub struct KeySize {
+    pub key: String,
+    pub size: u64,
+}
+
+/// A key whose value differs
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ChangedKey {
+    pub key: String,
+    pub old_size: u64,
+    pub new_size: u64,
+    pub size_delta: i64,
+}
+
+/// What it takes to turn the first side into the second
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct DiffReport {
+    /// Only in the second side
+    pub added: Vec<KeySize>,
+    /// Only in the first side
+    pub removed: Vec<KeySize>,
+    pub changed: Vec<ChangedKey>,
+    pub unchanged: usize,
+}
+
+impl DiffReport {
+    pub fn compare(old: &Snapshot, new: &Snapshot) -> Self {
+        let mut report = DiffReport::default();
+        for (key, digest) in old {
+            match new.get(key) {
+                None => report.removed.push(KeySize {
+                    key: key.clone(),
+                    size: digest.size,
+                }),
+                Some(other) if other.hash != digest.hash => report.changed.push(ChangedKey {
+                    key: key.clone(),
+                    old_size: digest.size,
+                    new_size: other.size,
+                    size_delta: other.size as i64 - digest.size as i64,
+                }),
+                Some(_) => report.unchanged += 1,
+            }
+        }
+        report.added = new
+            .iter()
+            .filter(|(key, _)| !old.contains_key(*key))
+            .map(|(key, digest)| KeySize {
+                key: key.clone(),
+                size: digest.size,
+            })
+            .collect();
+        report
+    }
+
+    /// Whether both sides hold the same keys and values
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// One line per difference, `+` added, `-` removed and `~` changed,
+    /// then a summary line
+    pub fn render_text(&self) -> String {
+        if self.is_empty() {
+            return format!("No differences ({} keys compared)", self.unchanged);
+        }
+
+        let mut lines: Vec<(&str, String)> = Vec::new();
+        for entry in &self.added {
+            let line = format!("+ {} ({})", entry.key, Formatter::human_size(entry.size));
+            lines.push((&entry.key, line));
+        }
+        for entry in &self.removed {
+            let line = format!("- {} ({})", entry.key, Formatter::human_size(entry.size));
+            lines.push((&entry.key, line));
+        }
+        for entry in &self.changed {
+            let sign = if entry.size_delta < 0 { "-" } else { "+" };
+            let line = format!(
+                "~ {} ({} -> {}, {}{})",
+                entry.key,
+                Formatter::human_size(entry.old_size),
+                Formatter::human_size(entry.new_size),
+                sign,
+                Formatter::human_size(entry.size_delta.unsigned_abs())
+            );
+            lines.push((&entry.key, line));
+        }
+        lines.sort();
+
+        let mut text: String = lines.into_iter().map(|(_, line)| line + "\n").collect();
+        text.push_str(&format!(
+            "{} added, {} removed, {} changed, {} unchanged",
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len(),
+            self.unchanged
+        ));
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(entries: &[(&str, &str)]) -> Snapshot {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), Digest::of(value.as_bytes())))
+            .collect()
+    }
+
+    #[test]
+    fn test_compare() {
+        let old = snapshot(&[("a", "1"), ("b", "2"), ("c", "3")]);
+        let new = snapshot(&[("b", "2"), ("c", "333"), ("d", "4")]);
+        let report = DiffReport::compare(&old, &new);
+
+        assert_eq!(
+            report.added,
+            vec![KeySize {
+                key: "d".into(),
+                size: 1
+            }]
+        );
+        assert_eq!(
+            report.removed,
+            vec![KeySize {
+                key: "a".into(),
+                size: 1
+            }]
+        );
+        assert_eq!(
+            report.changed,
+            vec![ChangedKey {
+                key: "c".into(),
+                old_size: 1,
+                new_size: 3,
+                size_delta: 2,
+            }]
+        );
+        assert_eq!(report.unchanged, 1);
+        assert!(!report.is_empty());
+        assert_eq!(
+            report.render_text(),
+            "- a (1 B)\n~ c (1 B -> 3 B, +2 B)\n+ d (1 B)\n1 added, 1 removed, 1 changed, 1 unchanged"
+        );
+        let same = DiffReport::compare(&old, &old);
+        assert!(same.is_empty());
+        assert_eq!(same.render_text(), "No differences (3 keys compared)");
+    }
+
+    #[test]
+    fn test_file_snapshot() {
+        let path = std::env::temp_dir().join(format!("cfkv-diff-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"[{"key": "user:1", "value": "ada"}, {"key": "post:1", "value": "aGk=", "base64": true}]"#,
+        )
+        .unwrap();
+
+        let all = file_snapshot(&path, "").unwrap();
+        assert_eq!(all.len(), 2);
+        // Base64 values are compared decoded
+        assert_eq!(all["post:1"], Digest::of(b"hi"));
+        let users = file_snapshot(&path, "user:").unwrap();
+        assert_eq!(users.keys().collect::<Vec<_>>(), vec!["user:1"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_storage_snapshot() {
+        let client = PluginClient::new(
+            cloudflare_kv::KvClient::in_memory(),
+            cloudflare_kv::PluginRegistry::new(),
+        );
+        client.put("user:1", "ada").await.unwrap();
+        client.put("post:1", "hi").await.unwrap();
+
+        let users = storage_snapshot(&client, "user:", 4).await.unwrap();
+        assert_eq!(users.len(), 1);
+        assert_eq!(users["user:1"], Digest::of(b"ada"));
+    }
+}
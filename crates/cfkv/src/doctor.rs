@@ -0,0 +1,418 @@
+//! Storage health checks for `cfkv storage doctor`
+//!
+//! Each storage is checked in order: its configuration is complete, the
+//! token is active, the namespace exists, keys can be listed, and a
+//! temporary key can be written, read back and deleted. The first failure
+//! skips the checks after it, which could only fail for the same reason.
+//! Read-only storages skip the write check unless `--force` is given.
+
+use crate::config::Storage;
+use crate::RequestOptions;
+use cloudflare_kv::{KvClient, KvError, PaginationParams, ScopedKvClient};
+use serde::Serialize;
+
+/// Checks in the order they run
+const CHECKS: [&str; 5] = ["config", "token", "namespace", "read", "write"];
+
+/// Expiration of the temporary key, in case deleting it fails
+const PROBE_TTL_SECS: u64 = 60;
+
+/// Why the write check is skipped on a read-only storage
+const READ_ONLY_DETAIL: &str = "storage is read-only (use --force to test writes)";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Failed,
+    Skipped,
+}
+
+/// Outcome of one check
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+/// Outcome of every check of one storage
+#[derive(Debug, Serialize)]
+pub struct Diagnosis {
+    pub storage: String,
+    pub checks: Vec<Check>,
+}
+
+impl Diagnosis {
+    /// The failed check, if any; every check passed when there is none
+    pub fn failure(&self) -> Option<&Check> {
+        self.checks.iter().find(|c| c.status == CheckStatus::Failed)
+    }
+
+    /// Record the next check; returns whether it passed, skipping the rest if not
+    fn record(&mut self, result: Result<String, String>) -> bool {
+        let name = CHECKS[self.checks.len()];
+        let (status, detail) = match result {
+            Ok(detail) => (CheckStatus::Ok, detail),
+            Err(detail) => (CheckStatus::Failed, detail),
+        };
+        self.checks.push(Check {
+            name,
+            status,
+            detail,
+        });
+        if status == CheckStatus::Failed {
+            self.skip_rest();
+        }
+        status == CheckStatus::Ok
+    }
+
+    fn skip_rest(&mut self) {
+        for name in &CHECKS[self.checks.len()..] {
+            self.checks.push(Check {
+                name,
+                status: CheckStatus::Skipped,
+                detail: String::new(),
+            });
+        }
+    }
+}
+
+/// Check a configured storage against the Cloudflare API with the command's
+/// request options, without writing to it when `read_only` is set
+pub(crate) async fn diagnose_storage(
+    storage: &Storage,
+    requests: &RequestOptions,
+    read_only: bool,
+) -> Diagnosis {
+    let missing: Vec<&str> = [
+        ("account_id", !storage.account_id.trim().is_empty()),
+        ("namespace_id", !storage.namespace_id.trim().is_empty()),
+        ("api_token", storage.has_token()),
+    ]
+    .into_iter()
+    .filter(|(_, present)| !present)
+    .map(|(field, _)| field)
+    .collect();
+
+    let mut diagnosis = Diagnosis {
+        storage: storage.name.clone(),
+        checks: Vec::new(),
+    };
+    if !missing.is_empty() {
+        diagnosis.record(Err(format!("Missing {}", missing.join(", "))));
+        return diagnosis;
+    }
+    diagnosis.record(Ok(format!(
+        "account {}, namespace {}",
+        storage.account_id, storage.namespace_id
+    )));
+
+    // A failing token command fails the token check
+    let token = match storage.token() {
+        Ok(token) => token,
+        Err(e) => {
+            diagnosis.record(Err(e.to_string()));
+            return diagnosis;
+        }
+    };
+
+    let client = crate::namespace_client(storage, token, requests);
+    let key_prefix = storage.key_prefix.as_deref();
+    diagnose_client(client, key_prefix, diagnosis, read_only).await
+}
+
+/// Run the checks after `config` with `client`, skipping the write check
+/// when `read_only` is set
+///
+/// Keys are read and written under `key_prefix`, where the storage's keys
+/// live; the token and namespace are checked without it, since a scoped
+/// client no longer talks to the API for them.
+pub async fn diagnose_client(
+    client: KvClient,
+    key_prefix: Option<&str>,
+    mut diagnosis: Diagnosis,
+    read_only: bool,
+) -> Diagnosis {
+    let token = client
+        .verify_token()
+        .await
+        .map(|_| "Token is active".to_string())
+        .map_err(|e| explain(&e));
+    if !diagnosis.record(token) {
+        return diagnosis;
+    }
+
+    let namespace = match client.namespace().await {
+        Ok(Some(info)) => Ok(format!("Found '{}'", info.title)),
+        Ok(None) => Err(format!(
+            "Namespace {} does not exist in account {}",
+            client.config().namespace_id,
+            client.config().account_id
+        )),
+        Err(e) => Err(explain(&e)),
+    };
+    if !diagnosis.record(namespace) {
+        return diagnosis;
+    }
+
+    let client = match key_prefix {
+        Some(prefix) => ScopedKvClient::new(client, prefix).into_client(),
+        None => client,
+    };
+
+    let read = client
+        .list(Some(PaginationParams::new().with_limit(1)))
+        .await
+        .map(|_| "Listed keys".to_string())
+        .map_err(|e| explain(&e));
+    if !diagnosis.record(read) {
+        return diagnosis;
+    }
+
+    if read_only {
+        diagnosis.checks.push(Check {
+            name: "write",
+            status: CheckStatus::Skipped,
+            detail: READ_ONLY_DETAIL.to_string(),
+        });
+        return diagnosis;
+    }
+    let write = probe_write(&client).await.map_err(|e| explain(&e));
+    diagnosis.record(write);
+    diagnosis
+}
+
+/// Write, read back and delete a temporary key
+async fn probe_write(client: &KvClient) -> cloudflare_kv::Result<String> {
+    let key = format!("__cfkv_doctor_{:016x}", rand::random::<u64>());
+    let value = key.as_bytes();
+
+    client
+        .put_with_options(&key, value, Some(PROBE_TTL_SECS), None)
+        .await?;
+    let read_back = client.get_bytes(&key).await;
+    let deleted = client.delete(&key).await;
+
+    if read_back?.as_deref() != Some(value) {
+        return Err(KvError::RequestFailed(format!(
+            "Temporary key {} did not read back as written",
+            key
+        )));
+    }
+    deleted?;
+    Ok("Wrote, read back and deleted a temporary key".to_string())
+}
+
+/// Error message with a hint for the usual causes
+fn explain(error: &KvError) -> String {
+    match error {
+        error if error.is_auth_error() => format!(
+            "{} (check the token is active and has Workers KV Storage permissions)",
+            error
+        ),
+        KvError::HttpError(_) | KvError::NetworkError(_) => {
+            format!("{} (check the network connection)", error)
+        }
+        _ => error.to_string(),
+    }
+}
+
+/// Text report of diagnoses
+pub fn render_text(diagnoses: &[Diagnosis]) -> String {
+    let mut output = String::new();
+    for diagnosis in diagnoses {
+        output.push_str(&format!("Storage '{}'\n", diagnosis.storage));
+        for check in &diagnosis.checks {
+            let status = match check.status {
+                CheckStatus::Ok => "ok",
+                CheckStatus::Failed => "FAILED",
+                CheckStatus::Skipped => "skipped",
+            };
+            output.push_str(
+                format!("  {:<10} {:<8} {}", check.name, status, check.detail).trim_end(),
+            );
+            output.push('\n');
+        }
+    }
+    output.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use cloudflare_kv::{
+        AuthCredentials, ClientConfig, Expiration, FakeCloudflareServer, InMemoryTransport,
+        KvTransport, ListResponse,
+    };
+    use serde_json::Value;
+    use std::sync::{Arc, Mutex};
+
+    /// In-memory store recording the keys written and deleted
+    #[derive(Default)]
+    struct Recorder {
+        store: InMemoryTransport,
+        writes: Mutex<Vec<String>>,
+    }
+
+    impl Recorder {
+        fn writes(&self) -> Vec<String> {
+            self.writes.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl KvTransport for Recorder {
+        async fn get(&self, key: &str) -> cloudflare_kv::Result<Option<Vec<u8>>> {
+            self.store.get(key).await
+        }
+
+        async fn put(
+            &self,
+            key: &str,
+            value: &[u8],
+            expiration: Option<Expiration>,
+            metadata: Option<Value>,
+        ) -> cloudflare_kv::Result<()> {
+            self.writes.lock().unwrap().push(format!("put {}", key));
+            self.store.put(key, value, expiration, metadata).await
+        }
+
+        async fn delete(&self, key: &str) -> cloudflare_kv::Result<()> {
+            self.writes.lock().unwrap().push(format!("delete {}", key));
+            self.store.delete(key).await
+        }
+
+        async fn list(&self, params: PaginationParams) -> cloudflare_kv::Result<ListResponse> {
+            self.store.list(params).await
+        }
+
+        async fn delete_bulk(&self, keys: &[&str]) -> cloudflare_kv::Result<()> {
+            for key in keys {
+                self.delete(key).await?;
+            }
+            Ok(())
+        }
+    }
+
+    fn recorded_client(recorder: &Arc<Recorder>) -> KvClient {
+        let config = ClientConfig::new("acc", "ns", AuthCredentials::token("token"));
+        KvClient::with_transport(config, recorder.clone())
+    }
+
+    fn diagnosis() -> Diagnosis {
+        let mut diagnosis = Diagnosis {
+            storage: "test".to_string(),
+            checks: Vec::new(),
+        };
+        diagnosis.record(Ok("configured".to_string()));
+        diagnosis
+    }
+
+    #[tokio::test]
+    async fn test_healthy_storage() {
+        let server = FakeCloudflareServer::start().await;
+        let result = diagnose_client(server.client(), None, diagnosis(), false).await;
+
+        assert!(result.failure().is_none(), "{:?}", result);
+        assert!(result.checks.iter().all(|c| c.status == CheckStatus::Ok));
+        assert_eq!(result.checks.len(), CHECKS.len());
+        // The temporary key is gone
+        assert!(server.keys().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failures_skip_later_checks() {
+        let server = FakeCloudflareServer::start().await;
+        let mut config = server.config();
+        config.namespace_id = "deleted".to_string();
+        let result = diagnose_client(KvClient::new(config), None, diagnosis(), false).await;
+        assert_eq!(result.failure().unwrap().name, "namespace");
+        assert_eq!(result.checks[3].status, CheckStatus::Skipped);
+
+        server.revoke_token();
+        let result = diagnose_client(server.client(), None, diagnosis(), false).await;
+        let failure = result.failure().unwrap();
+        assert_eq!(failure.name, "token");
+        assert!(failure.detail.contains("Workers KV Storage"));
+        assert!(render_text(&[result]).contains("  namespace  skipped\n"));
+    }
+
+    #[tokio::test]
+    async fn test_incomplete_config() {
+        let storage = Storage {
+            name: "broken".to_string(),
+            account_id: "acc".to_string(),
+            ..Default::default()
+        };
+        let result = diagnose_storage(&storage, &RequestOptions::default(), false).await;
+        assert_eq!(
+            result.failure().unwrap().detail,
+            "Missing namespace_id, api_token"
+        );
+        assert_eq!(result.checks.len(), CHECKS.len());
+    }
+
+    #[tokio::test]
+    async fn test_read_only_storage_is_not_written() {
+        let recorder = Arc::new(Recorder::default());
+        let result = diagnose_client(recorded_client(&recorder), None, diagnosis(), true).await;
+
+        assert!(result.failure().is_none(), "{:?}", result);
+        let write = result.checks.last().unwrap();
+        assert_eq!(write.name, "write");
+        assert_eq!(write.status, CheckStatus::Skipped);
+        assert!(render_text(&[result]).ends_with(&format!("skipped  {}", READ_ONLY_DETAIL)));
+        assert!(recorder.writes().is_empty());
+
+        // Forcing the checks writes and deletes the temporary key
+        let result = diagnose_client(recorded_client(&recorder), None, diagnosis(), false).await;
+        assert!(result.failure().is_none(), "{:?}", result);
+        assert_eq!(recorder.writes().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_probe_key_carries_prefix() {
+        let recorder = Arc::new(Recorder::default());
+        let result =
+            diagnose_client(recorded_client(&recorder), Some("app/"), diagnosis(), false).await;
+
+        assert!(result.failure().is_none(), "{:?}", result);
+        let writes = recorder.writes();
+        assert_eq!(writes.len(), 2);
+        assert!(
+            writes[0].starts_with("put app/__cfkv_doctor_"),
+            "{:?}",
+            writes
+        );
+        assert!(
+            writes[1].starts_with("delete app/__cfkv_doctor_"),
+            "{:?}",
+            writes
+        );
+    }
+
+    #[tokio::test]
+    async fn test_storage_uses_request_options() {
+        let server = FakeCloudflareServer::start().await;
+        let config = server.config();
+        let storage = Storage {
+            name: "app".to_string(),
+            account_id: config.account_id,
+            namespace_id: config.namespace_id,
+            api_token: "test-token".to_string(),
+            api_base_url: Some(server.uri()),
+            key_prefix: Some("app/".to_string()),
+            ..Default::default()
+        };
+        let requests = RequestOptions::default();
+        let result = diagnose_storage(&storage, &requests, false).await;
+
+        assert!(result.failure().is_none(), "{:?}", result);
+        assert!(server.keys().is_empty());
+        // The list, put, get and delete went through the client built from
+        // the options
+        assert_eq!(requests.metrics.snapshot().requests, 4);
+    }
+}
@@ -0,0 +1,203 @@
+//! Editing values in `$EDITOR` for `cfkv edit`
+//!
+//! Values that hold a JSON or YAML document (an object/mapping or an array)
+//! are checked to still parse after editing, in the same format.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Format a value is validated against after editing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueKind {
+    Json,
+    Yaml,
+    Text,
+}
+
+impl ValueKind {
+    /// Detect the kind of a stored value
+    pub fn detect(value: &str) -> Self {
+        let is_document = |v: &serde_json::Value| v.is_object() || v.is_array();
+        if serde_json::from_str::<serde_json::Value>(value).is_ok_and(|v| is_document(&v)) {
+            return ValueKind::Json;
+        }
+        match serde_yaml::from_str::<serde_yaml::Value>(value) {
+            Ok(serde_yaml::Value::Mapping(_)) | Ok(serde_yaml::Value::Sequence(_)) => {
+                ValueKind::Yaml
+            }
+            _ => ValueKind::Text,
+        }
+    }
+
+    /// File extension for the temp file, so editors pick the right syntax
+    pub fn extension(self) -> &'static str {
+        match self {
+            ValueKind::Json => "json",
+            ValueKind::Yaml => "yaml",
+            ValueKind::Text => "txt",
+        }
+    }
+
+    /// Check an edited value still parses as this kind
+    pub fn validate(self, value: &str) -> Result<(), String> {
+        match self {
+            ValueKind::Json => serde_json::from_str::<serde_json::Value>(value)
+                .map(|_| ())
+                .map_err(|e| format!("Edited value is not valid JSON: {}", e)),
+            ValueKind::Yaml => serde_yaml::from_str::<serde_yaml::Value>(value)
+                .map(|_| ())
+                .map_err(|e| format!("Edited value is not valid YAML: {}", e)),
+            ValueKind::Text => Ok(()),
+        }
+    }
+}
+
+/// Editor command from `$VISUAL` or `$EDITOR`, falling back to `vi`
+pub fn editor() -> String {
+    std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+/// Temp file a key is edited in
+///
+/// The file is created fresh (readable only by the user on Unix) inside a
+/// private temp directory, and both are removed when this is dropped.
+/// `exit::fail` skips destructors, so drop it before failing.
+pub struct EditFile {
+    dir: tempfile::TempDir,
+    path: PathBuf,
+}
+
+impl EditFile {
+    /// Create an empty temp file named after `key`, with the extension of `kind`
+    pub fn create(key: &str, kind: ValueKind) -> io::Result<Self> {
+        let mut builder = tempfile::Builder::new();
+        builder.prefix("cfkv-edit-");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            builder.permissions(fs::Permissions::from_mode(0o700));
+        }
+        let dir = builder.tempdir()?;
+        let name: String = key
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let path = dir.path().join(format!("{}.{}", name, kind.extension()));
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options.open(&path)?;
+
+        Ok(Self { dir, path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write `text` to the file, open it in `editor` and return the edited text
+    ///
+    /// The editor command may include arguments (e.g. `code --wait`).
+    pub fn edit(&self, editor: &str, text: &str) -> io::Result<String> {
+        fs::write(&self.path, text)?;
+
+        let mut parts = editor.split_whitespace();
+        let program = parts.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Editor command is empty")
+        })?;
+        let status = Command::new(program).args(parts).arg(&self.path).status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "Editor '{}' exited with {}",
+                editor, status
+            )));
+        }
+
+        fs::read_to_string(&self.path)
+    }
+
+    /// Remove the file and its directory now
+    pub fn remove(self) -> io::Result<()> {
+        self.dir.close()
+    }
+}
+
+/// After an edit failed validation, ask on the terminal whether to edit it
+/// again; `false` when not interactive
+pub fn ask_edit_again(error: &str) -> io::Result<bool> {
+    use std::io::{IsTerminal, Write};
+
+    if !io::stdin().is_terminal() || !io::stderr().is_terminal() {
+        return Ok(false);
+    }
+    eprint!("{}. Edit again? [Y/n] ", error);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer)? == 0 {
+        return Ok(false);
+    }
+    Ok(!answer.trim().to_lowercase().starts_with('n'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_kind() {
+        assert_eq!(ValueKind::detect(r#"{"a": 1}"#), ValueKind::Json);
+        assert_eq!(ValueKind::detect("[1, 2]"), ValueKind::Json);
+        assert_eq!(ValueKind::detect("a: 1\nb: [x]\n"), ValueKind::Yaml);
+        assert_eq!(ValueKind::detect("- one\n- two\n"), ValueKind::Yaml);
+        assert_eq!(ValueKind::detect("hello world"), ValueKind::Text);
+        assert_eq!(ValueKind::detect("42"), ValueKind::Text);
+        assert_eq!(ValueKind::detect(""), ValueKind::Text);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(ValueKind::Json.validate(r#"{"a": 2}"#).is_ok());
+        assert!(ValueKind::Json.validate(r#"{"a": }"#).is_err());
+        assert!(ValueKind::Yaml.validate("a: [1, 2]").is_ok());
+        assert!(ValueKind::Yaml.validate("a: [1, 2").is_err());
+        assert!(ValueKind::Text.validate("{ anything").is_ok());
+    }
+
+    #[test]
+    fn test_edit_file() {
+        let file = EditFile::create("test:edit/file", ValueKind::Text).unwrap();
+        let path = file.path().to_path_buf();
+        assert!(path.to_string_lossy().ends_with("/test_edit_file.txt"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+            let dir_mode = fs::metadata(path.parent().unwrap())
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(dir_mode & 0o777, 0o700);
+        }
+
+        // `true` leaves the file untouched
+        assert_eq!(file.edit("true", "unchanged").unwrap(), "unchanged");
+        assert!(file.edit("false", "x").is_err());
+
+        let dir = path.parent().unwrap().to_path_buf();
+        drop(file);
+        assert!(!path.exists());
+        assert!(!dir.exists());
+    }
+}
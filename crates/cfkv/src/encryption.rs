@@ -0,0 +1,151 @@
+//! Passphrase-based encryption for the config file
+//!
+//! Encrypted configs are stored as an envelope (in the config file's own
+//! format) holding an Argon2id salt, an AES-256-GCM nonce, and the ciphertext
+//! of the plaintext config.
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use cloudflare_kv::{KvError, Result};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+/// Environment variable holding the config passphrase
+pub const CONFIG_KEY_ENV: &str = "CFKV_CONFIG_KEY";
+
+const ENVELOPE_VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// On-disk format of an encrypted config file
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EncryptedEnvelope {
+    pub cfkv_encrypted: u32,
+    pub kdf: String,
+    pub cipher: String,
+    pub salt: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+impl EncryptedEnvelope {
+    /// Encrypt plaintext with a passphrase
+    pub fn seal(plaintext: &[u8], passphrase: &str) -> Result<Self> {
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+
+        let cipher = Self::cipher(passphrase, &salt)?;
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .map_err(|_| KvError::SerializationError("Failed to encrypt config".to_string()))?;
+
+        Ok(Self {
+            cfkv_encrypted: ENVELOPE_VERSION,
+            kdf: "argon2id".to_string(),
+            cipher: "aes-256-gcm".to_string(),
+            salt: BASE64.encode(salt),
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        })
+    }
+
+    /// Decrypt the envelope with a passphrase
+    pub fn open(&self, passphrase: &str) -> Result<Vec<u8>> {
+        if self.cfkv_encrypted != ENVELOPE_VERSION {
+            return Err(KvError::InvalidConfig(format!(
+                "Unsupported encrypted config version: {}",
+                self.cfkv_encrypted
+            )));
+        }
+
+        let salt = Self::decode("salt", &self.salt)?;
+        let nonce = Self::decode("nonce", &self.nonce)?;
+        let ciphertext = Self::decode("ciphertext", &self.ciphertext)?;
+        if nonce.len() != NONCE_LEN {
+            return Err(KvError::InvalidConfig(
+                "Encrypted config has an invalid nonce".to_string(),
+            ));
+        }
+
+        Self::cipher(passphrase, &salt)?
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| {
+                KvError::AuthError(
+                    "Failed to decrypt config: wrong passphrase or corrupted file".to_string(),
+                )
+            })
+    }
+
+    fn cipher(passphrase: &str, salt: &[u8]) -> Result<Aes256Gcm> {
+        let mut key = [0u8; 32];
+        argon2::Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| KvError::InvalidConfig(format!("Key derivation failed: {}", e)))?;
+        Ok(Aes256Gcm::new(&key.into()))
+    }
+
+    fn decode(field: &str, value: &str) -> Result<Vec<u8>> {
+        BASE64.decode(value).map_err(|e| {
+            KvError::InvalidConfig(format!("Encrypted config has invalid {}: {}", field, e))
+        })
+    }
+}
+
+/// Read the config passphrase from `CFKV_CONFIG_KEY` or prompt for it
+pub fn read_passphrase(prompt: &str) -> Result<String> {
+    if let Ok(key) = std::env::var(CONFIG_KEY_ENV) {
+        return Ok(key);
+    }
+    let passphrase = rpassword::prompt_password(prompt)?;
+    if passphrase.is_empty() {
+        return Err(KvError::InvalidConfig(
+            "Passphrase must not be empty".to_string(),
+        ));
+    }
+    Ok(passphrase)
+}
+
+/// Prompt for a new passphrase twice, unless `CFKV_CONFIG_KEY` is set
+pub fn read_new_passphrase() -> Result<String> {
+    if let Ok(key) = std::env::var(CONFIG_KEY_ENV) {
+        return Ok(key);
+    }
+    let passphrase = read_passphrase("New config passphrase: ")?;
+    let confirm = rpassword::prompt_password("Confirm passphrase: ")?;
+    if passphrase != confirm {
+        return Err(KvError::InvalidConfig(
+            "Passphrases do not match".to_string(),
+        ));
+    }
+    Ok(passphrase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open_roundtrip() {
+        let envelope = EncryptedEnvelope::seal(b"{\"storages\":{}}", "hunter2").unwrap();
+        assert_eq!(envelope.open("hunter2").unwrap(), b"{\"storages\":{}}");
+    }
+
+    #[test]
+    fn test_open_wrong_passphrase() {
+        let envelope = EncryptedEnvelope::seal(b"secret", "right").unwrap();
+        assert!(matches!(envelope.open("wrong"), Err(KvError::AuthError(_))));
+    }
+
+    #[test]
+    fn test_envelope_hides_plaintext() {
+        let envelope = EncryptedEnvelope::seal(b"secret", "pass").unwrap();
+        let json = serde_json::to_string(&envelope).unwrap();
+        assert!(!json.contains("secret"));
+        assert!(serde_json::from_str::<EncryptedEnvelope>(r#"{"storages":{}}"#).is_err());
+    }
+}
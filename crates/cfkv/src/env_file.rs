@@ -0,0 +1,131 @@
+//! `KEY=value` pairs for `cfkv put-many`
+//!
+//! Pairs come from arguments or from env-style files:
+//!
+//! ```text
+//! # Feature flags
+//! export BETA=on
+//! GREETING="hello\nworld"   # double quotes take \n, \t, \" and \\
+//! PATTERN='^[a-z]+$'        # single quotes are literal
+//! EMPTY=
+//! ```
+//!
+//! Blank lines and `#` comments are skipped, as is a leading `export `.
+//! Unquoted values end at ` #` and are trimmed.
+
+/// Split a `KEY=value` argument at its first `=`
+pub fn parse_pair(pair: &str) -> Result<(String, String), String> {
+    match pair.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("'{}' is not of the form KEY=VALUE", pair)),
+    }
+}
+
+/// Parse the pairs of an env-style file, in file order
+pub fn parse(content: &str) -> Result<Vec<(String, String)>, String> {
+    let mut pairs = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = parse_pair(line).map_err(|e| format!("line {}: {}", number + 1, e))?;
+        let value =
+            parse_value(value.trim_start()).map_err(|e| format!("line {}: {}", number + 1, e))?;
+        pairs.push((key.trim_end().to_string(), value));
+    }
+    Ok(pairs)
+}
+
+fn parse_value(value: &str) -> Result<String, String> {
+    let mut chars = value.chars();
+    match chars.next() {
+        Some('\'') => match chars.as_str().split_once('\'') {
+            Some((quoted, rest)) => trailing(quoted.to_string(), rest),
+            None => Err("unterminated single quote".to_string()),
+        },
+        Some('"') => {
+            let mut quoted = String::new();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => return trailing(quoted, chars.as_str()),
+                    '\\' => match chars.next() {
+                        Some('n') => quoted.push('\n'),
+                        Some('t') => quoted.push('\t'),
+                        Some('r') => quoted.push('\r'),
+                        Some(other) => quoted.push(other),
+                        None => break,
+                    },
+                    c => quoted.push(c),
+                }
+            }
+            Err("unterminated double quote".to_string())
+        }
+        _ => {
+            let end = value.find(" #").unwrap_or(value.len());
+            Ok(value[..end].trim_end().to_string())
+        }
+    }
+}
+
+/// Only a comment may follow a quoted value
+fn trailing(value: String, rest: &str) -> Result<String, String> {
+    let rest = rest.trim_start();
+    if rest.is_empty() || rest.starts_with('#') {
+        Ok(value)
+    } else {
+        Err(format!("unexpected '{}' after the quoted value", rest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pair() {
+        assert_eq!(
+            parse_pair("url=https://x?a=b"),
+            Ok(("url".to_string(), "https://x?a=b".to_string()))
+        );
+        assert_eq!(
+            parse_pair("empty="),
+            Ok(("empty".to_string(), String::new()))
+        );
+        assert!(parse_pair("novalue").is_err());
+        assert!(parse_pair("=value").is_err());
+    }
+
+    #[test]
+    fn test_parse_env_file() {
+        let content = r#"
+# Feature flags
+export BETA=on
+GREETING="hello\nworld \"you\""   # comment
+PATTERN='^[a-z]+ #$'
+SPACED = padded value # comment
+EMPTY=
+"#;
+        let pairs = parse(content).unwrap();
+        let expected = [
+            ("BETA", "on"),
+            ("GREETING", "hello\nworld \"you\""),
+            ("PATTERN", "^[a-z]+ #$"),
+            ("SPACED", "padded value"),
+            ("EMPTY", ""),
+        ];
+        let pairs: Vec<_> = pairs
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        assert_eq!(pairs, expected);
+
+        assert_eq!(
+            parse("A=1\nB\n").unwrap_err(),
+            "line 2: 'B' is not of the form KEY=VALUE"
+        );
+        assert!(parse("A=\"open").is_err());
+        assert!(parse("A='x' y").is_err());
+    }
+}
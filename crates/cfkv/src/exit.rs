@@ -0,0 +1,239 @@
+//! Exit codes for failures
+//!
+//! Every failure exits with a code that tells scripts what went wrong, so
+//! they can retry rate limits and network errors but not missing keys:
+//!
+//! | Code | Kind          |
+//! |------|---------------|
+//! | 1    | general       |
+//! | 2    | not found     |
+//! | 3    | auth          |
+//! | 4    | rate limited  |
+//! | 5    | validation    |
+//! | 6    | network       |
+//! | 7    | cancelled     |
+//! | 130  | interrupted   |
+
+use crate::formatter::{Formatter, OutputFormat};
+use cfkv_blog::BlogError;
+use cloudflare_kv::KvError;
+use std::error::Error;
+
+/// What kind of failure ended the command
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    General,
+    NotFound,
+    Auth,
+    RateLimited,
+    Validation,
+    Network,
+    /// A confirmation prompt was declined
+    Cancelled,
+    /// Stopped by Ctrl-C
+    Interrupted,
+}
+
+impl ErrorKind {
+    /// Classify an error returned by a command
+    pub fn of(error: &(dyn Error + 'static)) -> Self {
+        if let Some(error) = error.downcast_ref::<KvError>() {
+            return Self::of_kv(error);
+        }
+        if let Some(error) = error.downcast_ref::<BlogError>() {
+            return match error {
+                BlogError::NotFound(_) => ErrorKind::NotFound,
+                BlogError::FrontmatterError(_)
+                | BlogError::ValidationError(_)
+                | BlogError::JsonError(_)
+                | BlogError::YamlError(_) => ErrorKind::Validation,
+                _ => ErrorKind::General,
+            };
+        }
+        if let Some(error) = error.downcast_ref::<reqwest::Error>() {
+            return Self::of_http(error);
+        }
+        if error.is::<serde_json::Error>() || error.is::<serde_yaml::Error>() {
+            return ErrorKind::Validation;
+        }
+        ErrorKind::General
+    }
+
+    fn of_kv(error: &KvError) -> Self {
+        match error {
+            KvError::KeyNotFound(_) => ErrorKind::NotFound,
+            KvError::AuthError(_) => ErrorKind::Auth,
+            KvError::RateLimited(_) => ErrorKind::RateLimited,
+            KvError::InvalidConfig(_)
+            | KvError::JsonError(_)
+            | KvError::SerializationError(_)
+            | KvError::PatchError(_)
+            | KvError::PreconditionFailed(_) => ErrorKind::Validation,
+            KvError::HttpError(error) => Self::of_http(error),
+            KvError::Http(response) => Self::of_status(response.status),
+            KvError::NetworkError(_) => ErrorKind::Network,
+            KvError::RequestFailed(_)
+            | KvError::UnexpectedResponse(_)
+            | KvError::IoError(_)
+            | KvError::PluginError(_) => ErrorKind::General,
+        }
+    }
+
+    fn of_http(error: &reqwest::Error) -> Self {
+        match error.status() {
+            Some(status) => Self::of_status(status.as_u16()),
+            None if error.is_decode() => ErrorKind::General,
+            None => ErrorKind::Network,
+        }
+    }
+
+    fn of_status(status: u16) -> Self {
+        match status {
+            401 | 403 => ErrorKind::Auth,
+            429 => ErrorKind::RateLimited,
+            _ => ErrorKind::General,
+        }
+    }
+
+    /// Process exit code
+    pub fn code(self) -> i32 {
+        match self {
+            ErrorKind::General => 1,
+            ErrorKind::NotFound => 2,
+            ErrorKind::Auth => 3,
+            ErrorKind::RateLimited => 4,
+            ErrorKind::Validation => 5,
+            ErrorKind::Network => 6,
+            ErrorKind::Cancelled => 7,
+            ErrorKind::Interrupted => 130,
+        }
+    }
+
+    /// Name used in structured error output
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorKind::General => "general",
+            ErrorKind::NotFound => "not_found",
+            ErrorKind::Auth => "auth",
+            ErrorKind::RateLimited => "rate_limited",
+            ErrorKind::Validation => "validation",
+            ErrorKind::Network => "network",
+            ErrorKind::Cancelled => "cancelled",
+            ErrorKind::Interrupted => "interrupted",
+        }
+    }
+}
+
+/// Print an error on stderr and exit with the code for `kind`
+pub fn fail(message: &str, kind: ErrorKind, format: OutputFormat) -> ! {
+    eprintln!("{}", Formatter::format_failure(message, kind, format));
+    crate::usage::report();
+    #[cfg(feature = "otel")]
+    crate::telemetry::shutdown();
+    crate::notify::command_failed(message, kind);
+    std::process::exit(kind.code());
+}
+
+/// Print an error on stderr and exit with the code for its kind
+pub fn fail_with(error: &(dyn Error + 'static), format: OutputFormat) -> ! {
+    fail(&error.to_string(), ErrorKind::of(error), format)
+}
+
+/// Print a command line error and exit
+///
+/// Help and version requests exit 0 as usual; usage errors exit with the
+/// validation code instead of clap's 2, which means "not found" here.
+pub fn usage_error(error: clap::Error) -> ! {
+    use clap::error::ErrorKind as ClapKind;
+
+    match error.kind() {
+        ClapKind::DisplayHelp | ClapKind::DisplayVersion => error.exit(),
+        _ => {
+            let _ = error.print();
+            std::process::exit(ErrorKind::Validation.code());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind(error: impl Error + 'static) -> ErrorKind {
+        let boxed: Box<dyn Error> = Box::new(error);
+        ErrorKind::of(boxed.as_ref())
+    }
+
+    #[test]
+    fn test_kv_error_kinds() {
+        assert_eq!(kind(KvError::KeyNotFound("k".into())), ErrorKind::NotFound);
+        assert_eq!(kind(KvError::AuthError("bad".into())), ErrorKind::Auth);
+        assert_eq!(
+            kind(KvError::RateLimited("slow".into())),
+            ErrorKind::RateLimited
+        );
+        assert_eq!(
+            kind(KvError::Http(Box::new(cloudflare_kv::ErrorResponse {
+                status: 429,
+                headers: Default::default(),
+                body: String::new(),
+                message: "slow".into(),
+            }))),
+            ErrorKind::RateLimited
+        );
+        assert_eq!(
+            kind(KvError::InvalidConfig("x".into())),
+            ErrorKind::Validation
+        );
+        assert_eq!(
+            kind(KvError::RequestFailed("500".into())),
+            ErrorKind::General
+        );
+    }
+
+    #[test]
+    fn test_other_error_kinds() {
+        assert_eq!(
+            kind(BlogError::NotFound("post".into())),
+            ErrorKind::NotFound
+        );
+        assert_eq!(
+            kind(BlogError::ValidationError("x".into())),
+            ErrorKind::Validation
+        );
+        let json = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+        assert_eq!(kind(json), ErrorKind::Validation);
+        assert_eq!(kind(std::io::Error::other("disk")), ErrorKind::General);
+    }
+
+    #[test]
+    fn test_usage_errors_are_validation_errors() {
+        use crate::cli::Cli;
+        use clap::Parser;
+
+        // clap's own exit code for usage errors is "not found" here
+        let error = Cli::try_parse_from(["cfkv", "no-such-command"])
+            .err()
+            .unwrap();
+        assert_eq!(error.exit_code(), ErrorKind::NotFound.code());
+        assert_ne!(ErrorKind::Validation.code(), error.exit_code());
+    }
+
+    #[test]
+    fn test_codes_are_distinct() {
+        let kinds = [
+            ErrorKind::General,
+            ErrorKind::NotFound,
+            ErrorKind::Auth,
+            ErrorKind::RateLimited,
+            ErrorKind::Validation,
+            ErrorKind::Network,
+            ErrorKind::Cancelled,
+            ErrorKind::Interrupted,
+        ];
+        let codes: std::collections::HashSet<i32> = kinds.iter().map(|k| k.code()).collect();
+        assert_eq!(codes.len(), kinds.len());
+        assert_eq!(ErrorKind::NotFound.code(), 2);
+        assert_eq!(ErrorKind::Network.code(), 6);
+    }
+}
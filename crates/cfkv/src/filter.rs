@@ -0,0 +1,390 @@
+//! Filter expressions for `cfkv list --where`
+//!
+//! An expression tests what the list API returns for each key: `key`,
+//! `expiration` and `metadata`, with dotted paths into the metadata:
+//!
+//! ```text
+//! metadata.owner == "payments"
+//! metadata.size >= 1000 && !metadata.archived
+//! (metadata.env == 'prod' || metadata.env == 'staging') && key != "config"
+//! ```
+//!
+//! Comparisons are `==`, `!=`, `<`, `<=`, `>` and `>=`. Ordering compares
+//! numbers with numbers and strings with strings, and is false for anything
+//! else. A path on its own is true when the field exists and is not `false`
+//! or `null`. Conditions combine with `&&`, `||`, `!` and parentheses.
+
+use cloudflare_kv::types::KeyMetadata;
+use serde_json::Value;
+use std::cmp::Ordering;
+
+/// A parsed filter expression
+#[derive(Clone, Debug, PartialEq)]
+pub struct Filter(Expr);
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Operand, Op, Operand),
+    Truthy(Operand),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Operand {
+    /// A listed field, e.g. `["metadata", "owner"]`
+    Path(Vec<String>),
+    Literal(Value),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Path(Vec<String>),
+    Literal(Value),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl Filter {
+    /// Parse an expression
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.or()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(Filter(expr)),
+            Some(token) => Err(format!("Unexpected {} in filter", describe(token))),
+        }
+    }
+
+    /// Whether a listed key passes the filter
+    pub fn matches(&self, key: &KeyMetadata) -> bool {
+        let fields = serde_json::json!({
+            "key": key.name,
+            "expiration": key.expiration,
+            "metadata": key.metadata,
+        });
+        eval(&self.0, &fields)
+    }
+}
+
+fn eval(expr: &Expr, fields: &Value) -> bool {
+    match expr {
+        Expr::Or(left, right) => eval(left, fields) || eval(right, fields),
+        Expr::And(left, right) => eval(left, fields) && eval(right, fields),
+        Expr::Not(inner) => !eval(inner, fields),
+        Expr::Truthy(operand) => {
+            !matches!(resolve(operand, fields), Value::Null | Value::Bool(false))
+        }
+        Expr::Compare(left, op, right) => {
+            let (left, right) = (resolve(left, fields), resolve(right, fields));
+            match op {
+                Op::Eq => equal(&left, &right),
+                Op::Ne => !equal(&left, &right),
+                Op::Lt => compare(&left, &right) == Some(Ordering::Less),
+                Op::Le => matches!(
+                    compare(&left, &right),
+                    Some(Ordering::Less | Ordering::Equal)
+                ),
+                Op::Gt => compare(&left, &right) == Some(Ordering::Greater),
+                Op::Ge => matches!(
+                    compare(&left, &right),
+                    Some(Ordering::Greater | Ordering::Equal)
+                ),
+            }
+        }
+    }
+}
+
+/// The value of an operand; missing fields are `null`
+fn resolve(operand: &Operand, fields: &Value) -> Value {
+    match operand {
+        Operand::Literal(value) => value.clone(),
+        Operand::Path(path) => path
+            .iter()
+            .try_fold(fields, |value, segment| value.get(segment))
+            .cloned()
+            .unwrap_or(Value::Null),
+    }
+}
+
+/// Equality where `1` and `1.0` are the same number
+fn equal(left: &Value, right: &Value) -> bool {
+    match (left.as_f64(), right.as_f64()) {
+        (Some(left), Some(right)) => left == right,
+        _ => left == right,
+    }
+}
+
+fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Number(left), Value::Number(right)) => left.as_f64()?.partial_cmp(&right.as_f64()?),
+        (Value::String(left), Value::String(right)) => Some(left.cmp(right)),
+        _ => None,
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        let two = input.get(start..start + 2);
+        let (token, len) = match (c, two) {
+            (_, Some("==")) => (Token::Op(Op::Eq), 2),
+            (_, Some("!=")) => (Token::Op(Op::Ne), 2),
+            (_, Some("<=")) => (Token::Op(Op::Le), 2),
+            (_, Some(">=")) => (Token::Op(Op::Ge), 2),
+            (_, Some("&&")) => (Token::And, 2),
+            (_, Some("||")) => (Token::Or, 2),
+            ('<', _) => (Token::Op(Op::Lt), 1),
+            ('>', _) => (Token::Op(Op::Gt), 1),
+            ('!', _) => (Token::Not, 1),
+            ('(', _) => (Token::Open, 1),
+            (')', _) => (Token::Close, 1),
+            (c, _) if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            ('"' | '\'', _) => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, quote)) if quote == c => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, escaped)) => text.push(escaped),
+                            None => return Err("Unterminated string in filter".to_string()),
+                        },
+                        Some((_, other)) => text.push(other),
+                        None => return Err("Unterminated string in filter".to_string()),
+                    }
+                }
+                tokens.push(Token::Literal(Value::String(text)));
+                continue;
+            }
+            _ => {
+                let end = input[start..]
+                    .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '+')))
+                    .map_or(input.len(), |len| start + len);
+                if end == start {
+                    return Err(format!("Unexpected '{}' in filter", c));
+                }
+                (word(&input[start..end])?, end - start)
+            }
+        };
+        tokens.push(token);
+        while chars.peek().is_some_and(|&(i, _)| i < start + len) {
+            chars.next();
+        }
+    }
+    Ok(tokens)
+}
+
+/// A literal or a field path
+fn word(word: &str) -> Result<Token, String> {
+    match word {
+        "true" => return Ok(Token::Literal(Value::Bool(true))),
+        "false" => return Ok(Token::Literal(Value::Bool(false))),
+        "null" => return Ok(Token::Literal(Value::Null)),
+        "and" => return Ok(Token::And),
+        "or" => return Ok(Token::Or),
+        "not" => return Ok(Token::Not),
+        _ => {}
+    }
+    if word.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+        return serde_json::from_str::<serde_json::Number>(word)
+            .map(|number| Token::Literal(Value::Number(number)))
+            .map_err(|_| format!("Invalid number '{}' in filter", word));
+    }
+    let path: Vec<String> = word.split('.').map(str::to_string).collect();
+    match path.as_slice() {
+        [root] if root == "key" || root == "expiration" || root == "metadata" => {}
+        [root, rest @ ..] if root == "metadata" && rest.iter().all(|s| !s.is_empty()) => {}
+        _ => {
+            return Err(format!(
+                "Unknown field '{}' in filter (use key, expiration or metadata.<name>)",
+                word
+            ))
+        }
+    }
+    Ok(Token::Path(path))
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Path(path) => format!("'{}'", path.join(".")),
+        Token::Literal(value) => value.to_string(),
+        Token::Op(op) => format!("{:?}", op),
+        Token::And => "'&&'".to_string(),
+        Token::Or => "'||'".to_string(),
+        Token::Not => "'!'".to_string(),
+        Token::Open => "'('".to_string(),
+        Token::Close => "')'".to_string(),
+    }
+}
+
+/// Recursive descent over the tokens: `||` binds looser than `&&`, which
+/// binds looser than `!`
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err("Missing ')' in filter".to_string()),
+                }
+            }
+            Some(token) => {
+                let left = operand(token)?;
+                let Some(&Token::Op(op)) = self.peek() else {
+                    return Ok(Expr::Truthy(left));
+                };
+                self.pos += 1;
+                let right = match self.next() {
+                    Some(token) => operand(token)?,
+                    None => return Err("Filter ends after a comparison".to_string()),
+                };
+                Ok(Expr::Compare(left, op, right))
+            }
+            None => Err("Filter is incomplete".to_string()),
+        }
+    }
+}
+
+fn operand(token: Token) -> Result<Operand, String> {
+    match token {
+        Token::Path(path) => Ok(Operand::Path(path)),
+        Token::Literal(value) => Ok(Operand::Literal(value)),
+        other => Err(format!("Unexpected {} in filter", describe(&other))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn key(name: &str, metadata: Option<Value>) -> KeyMetadata {
+        KeyMetadata {
+            name: name.to_string(),
+            expiration: Some(1_700_000_000),
+            metadata,
+        }
+    }
+
+    fn matches(filter: &str, key: &KeyMetadata) -> bool {
+        Filter::parse(filter).unwrap().matches(key)
+    }
+
+    #[test]
+    fn test_comparisons() {
+        let invoice = key(
+            "invoice:1",
+            Some(
+                json!({"owner": "payments", "size": 2048, "archived": false, "team": {"lead": "ana"}}),
+            ),
+        );
+        assert!(matches(r#"metadata.owner == "payments""#, &invoice));
+        assert!(matches("metadata.owner != 'search'", &invoice));
+        assert!(matches("metadata.size >= 2048.0", &invoice));
+        assert!(matches(
+            "metadata.size > 1000 && metadata.size < 4096",
+            &invoice
+        ));
+        assert!(!matches("metadata.size <= 10", &invoice));
+        assert!(matches("metadata.team.lead == 'ana'", &invoice));
+        assert!(matches(
+            r#"key >= "invoice:" && key < "invoice;""#,
+            &invoice
+        ));
+        assert!(matches("expiration > 0", &invoice));
+        // Mixed types never order
+        assert!(!matches("metadata.owner > 5", &invoice));
+        assert!(!matches("metadata.owner < 5", &invoice));
+    }
+
+    #[test]
+    fn test_presence_and_logic() {
+        let tagged = key("a", Some(json!({"owner": "payments", "archived": false})));
+        let bare = key("b", None);
+        assert!(matches("metadata.owner", &tagged));
+        assert!(!matches("metadata.archived", &tagged));
+        assert!(matches("!metadata.archived", &tagged));
+        assert!(!matches("metadata.owner", &bare));
+        assert!(matches("metadata == null", &bare));
+        assert!(matches("metadata.missing == null", &tagged));
+        assert!(matches(
+            "(metadata.owner == 'x' || metadata.owner == 'payments') && !metadata.archived",
+            &tagged
+        ));
+        assert!(matches("not metadata.owner or key == 'b'", &bare));
+        // && binds tighter than ||
+        assert!(matches("key == 'a' || key == 'x' && false", &tagged));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for (filter, error) in [
+            ("owner == 'x'", "Unknown field 'owner'"),
+            ("metadata.owner ==", "ends after a comparison"),
+            ("(metadata.owner", "Missing ')'"),
+            ("metadata.owner == 'x", "Unterminated string"),
+            ("metadata.owner == 'x' 'y'", "Unexpected \"y\""),
+            ("metadata.owner ~ 'x'", "Unexpected '~'"),
+            ("", "incomplete"),
+        ] {
+            let e = Filter::parse(filter).unwrap_err();
+            assert!(e.contains(error), "{}: {}", filter, e);
+        }
+    }
+}
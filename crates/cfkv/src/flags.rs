@@ -0,0 +1,191 @@
+//! Feature flags stored as KV values, for `cfkv flags`
+//!
+//! A flag named `beta` is the JSON document at key `flag:beta`:
+//!
+//! ```text
+//! {"enabled": true}                          # on
+//! {"enabled": false}                         # off
+//! {"enabled": true, "value": {"limit": 5}}   # on, with a value
+//! {"enabled": true, "rollout": 20}           # on for 20% of subjects
+//! ```
+//!
+//! A subject (a user or account ID) is in a rollout when the first four
+//! bytes of SHA-256 of `<name>:<subject>`, read as a big-endian integer,
+//! modulo 100 are below the percentage. Applications reading flags directly
+//! from KV can bucket subjects the same way.
+
+use cloudflare_kv::value_hash;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Prefix of flag keys
+pub const PREFIX: &str = "flag:";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Flag {
+    pub enabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<Value>,
+    /// Percentage of subjects the flag is on for; all of them when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rollout: Option<u8>,
+}
+
+impl Flag {
+    /// Parse the `set` argument: `on`, `off` or a JSON value
+    pub fn parse_setting(setting: &str) -> Result<Self, String> {
+        match setting {
+            "on" => Ok(Self::on(None)),
+            "off" => Ok(Self {
+                enabled: false,
+                value: None,
+                rollout: None,
+            }),
+            json => match serde_json::from_str(json) {
+                Ok(value) => Ok(Self::on(Some(value))),
+                Err(_) => Err(format!(
+                    "'{}' is not on, off or a JSON value (quote strings: '\"blue\"')",
+                    setting
+                )),
+            },
+        }
+    }
+
+    /// A flag that is on for everyone
+    pub fn on(value: Option<Value>) -> Self {
+        Self {
+            enabled: true,
+            value,
+            rollout: None,
+        }
+    }
+
+    /// Parse a stored flag document
+    pub fn from_stored(stored: &str) -> Result<Self, String> {
+        let flag: Self = serde_json::from_str(stored).map_err(|e| e.to_string())?;
+        match flag.rollout {
+            Some(percent) if percent > 100 => {
+                Err(format!("rollout {} is over 100 percent", percent))
+            }
+            _ => Ok(flag),
+        }
+    }
+
+    /// Whether the flag is on for a subject
+    pub fn is_on_for(&self, name: &str, subject: &str) -> bool {
+        self.enabled
+            && match self.rollout {
+                Some(percent) => bucket(name, subject) < u32::from(percent),
+                None => true,
+            }
+    }
+
+    /// Short description for listings, e.g. `on (20%)` or `on = {"limit":5}`
+    pub fn summary(&self) -> String {
+        let mut summary = if self.enabled { "on" } else { "off" }.to_string();
+        if let Some(percent) = self.rollout {
+            summary.push_str(&format!(" ({}%)", percent));
+        }
+        if let Some(value) = &self.value {
+            summary.push_str(&format!(" = {}", value));
+        }
+        summary
+    }
+}
+
+/// Key holding a flag, after checking its name
+pub fn key(name: &str) -> Result<String, String> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/');
+    if name.is_empty() || !name.chars().all(valid) {
+        return Err(format!(
+            "Invalid flag name '{}': use letters, digits, '-', '_', '.' and '/'",
+            name
+        ));
+    }
+    Ok(format!("{}{}", PREFIX, name))
+}
+
+/// Rollout bucket of a subject, from 0 to 99
+fn bucket(name: &str, subject: &str) -> u32 {
+    let hash = value_hash(format!("{}:{}", name, subject).as_bytes());
+    u32::from_str_radix(&hash[..8], 16).expect("SHA-256 is hex") % 100
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_setting() {
+        assert_eq!(Flag::parse_setting("on").unwrap(), Flag::on(None));
+        assert!(!Flag::parse_setting("off").unwrap().enabled);
+        assert_eq!(
+            Flag::parse_setting(r#"{"limit": 5}"#).unwrap(),
+            Flag::on(Some(json!({"limit": 5})))
+        );
+        assert_eq!(
+            Flag::parse_setting("\"blue\"").unwrap().value,
+            Some(json!("blue"))
+        );
+        assert!(Flag::parse_setting("blue").is_err());
+    }
+
+    #[test]
+    fn test_stored_round_trip() {
+        let flag = Flag {
+            rollout: Some(20),
+            ..Flag::on(None)
+        };
+        let stored = serde_json::to_string(&flag).unwrap();
+        assert_eq!(stored, r#"{"enabled":true,"rollout":20}"#);
+        assert_eq!(Flag::from_stored(&stored).unwrap(), flag);
+        assert_eq!(flag.summary(), "on (20%)");
+
+        assert!(Flag::from_stored("true").is_err());
+        assert!(Flag::from_stored(r#"{"enabled":true,"extra":1}"#).is_err());
+        assert!(Flag::from_stored(r#"{"enabled":true,"rollout":150}"#).is_err());
+    }
+
+    #[test]
+    fn test_rollout() {
+        let flag = |rollout| Flag {
+            rollout,
+            ..Flag::on(None)
+        };
+        let subjects: Vec<String> = (0..1000).map(|i| format!("user-{}", i)).collect();
+        let on = |flag: &Flag| {
+            subjects
+                .iter()
+                .filter(|s| flag.is_on_for("beta", s))
+                .count()
+        };
+
+        assert_eq!(on(&flag(None)), 1000);
+        assert_eq!(on(&flag(Some(0))), 0);
+        assert_eq!(on(&flag(Some(100))), 1000);
+        let twenty = on(&flag(Some(20)));
+        assert!((150..250).contains(&twenty), "{} of 1000", twenty);
+        // Raising the percentage keeps subjects already in the rollout
+        assert!(subjects
+            .iter()
+            .filter(|s| flag(Some(20)).is_on_for("beta", s))
+            .all(|s| flag(Some(50)).is_on_for("beta", s)));
+        assert_eq!(
+            on(&Flag {
+                enabled: false,
+                ..flag(None)
+            }),
+            0
+        );
+    }
+
+    #[test]
+    fn test_key() {
+        assert_eq!(key("checkout/new-flow").unwrap(), "flag:checkout/new-flow");
+        assert!(key("").is_err());
+        assert!(key("has space").is_err());
+        assert!(key("a:b").is_err());
+    }
+}
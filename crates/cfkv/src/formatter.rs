@@ -1,10 +1,52 @@
+use crate::exit::ErrorKind;
 use serde_json::json;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+/// Whether text written to stdout / stderr is colored (set once by `init_color`)
+static STDOUT_COLOR: AtomicBool = AtomicBool::new(false);
+static STDERR_COLOR: AtomicBool = AtomicBool::new(false);
+
+/// When to color text output
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorChoice {
+    /// Color when writing to a terminal and `NO_COLOR` is not set
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether to color a stream, given whether it is a terminal
+    fn enabled(self, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+                let dumb = std::env::var("TERM").is_ok_and(|t| t == "dumb");
+                is_terminal && !no_color && !dumb
+            }
+        }
+    }
+}
 
 #[derive(Clone, Copy, Debug)]
 pub enum OutputFormat {
     Json,
     Yaml,
     Text,
+    /// Aligned columns for listings; other output falls back to text
+    Table,
+    /// GitHub Actions annotations and step outputs; other output falls back
+    /// to text
+    Gha,
 }
 
 impl OutputFormat {
@@ -13,6 +55,8 @@ impl OutputFormat {
             "json" => Some(OutputFormat::Json),
             "yaml" | "yml" => Some(OutputFormat::Yaml),
             "text" => Some(OutputFormat::Text),
+            "table" => Some(OutputFormat::Table),
+            "gha" | "github" => Some(OutputFormat::Gha),
             _ => None,
         }
     }
@@ -21,6 +65,39 @@ impl OutputFormat {
 pub struct Formatter;
 
 impl Formatter {
+    /// Decide once whether text output is colored
+    pub fn init_color(choice: ColorChoice) {
+        STDOUT_COLOR.store(
+            choice.enabled(std::io::stdout().is_terminal()),
+            Ordering::Relaxed,
+        );
+        STDERR_COLOR.store(
+            choice.enabled(std::io::stderr().is_terminal()),
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Dim secondary details such as metadata (stdout)
+    pub fn dim(text: &str) -> String {
+        paint(text, DIM, STDOUT_COLOR.load(Ordering::Relaxed))
+    }
+
+    /// Human-readable byte size, e.g. `1.5 KiB`
+    pub fn human_size(bytes: u64) -> String {
+        const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+        let mut size = bytes as f64;
+        let mut unit = 0;
+        while size >= 1024.0 && unit < UNITS.len() - 1 {
+            size /= 1024.0;
+            unit += 1;
+        }
+        if unit == 0 {
+            format!("{} B", bytes)
+        } else {
+            format!("{:.1} {}", size, UNITS[unit])
+        }
+    }
+
     /// Format a text value based on the output format
     fn format_json(value: serde_json::Value) -> String {
         serde_json::to_string(&value).unwrap_or_else(|_| String::new())
@@ -31,7 +108,7 @@ impl Formatter {
         match format {
             OutputFormat::Json => Self::format_json(value),
             OutputFormat::Yaml => serde_yaml::to_string(&value).unwrap_or_else(|_| String::new()),
-            OutputFormat::Text => String::new(),
+            OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => String::new(),
         }
     }
 
@@ -39,7 +116,7 @@ impl Formatter {
         match format {
             OutputFormat::Json => Self::format_structured(json!({ "value": text }), format),
             OutputFormat::Yaml => Self::format_structured(json!({ "value": text }), format),
-            OutputFormat::Text => text.to_string(),
+            OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => text.to_string(),
         }
     }
 
@@ -49,25 +126,102 @@ impl Formatter {
             OutputFormat::Yaml => {
                 Self::format_structured(json!({ "success": true, "message": message }), format)
             }
-            OutputFormat::Text => message.to_string(),
+            OutputFormat::Text | OutputFormat::Table => {
+                success_text(message, STDOUT_COLOR.load(Ordering::Relaxed))
+            }
+            OutputFormat::Gha => crate::gha::notice(message),
         }
     }
 
-    pub fn format_error(error: &str, format: OutputFormat) -> String {
+    /// Format an error with its kind and exit code (`kind` and `code` fields
+    /// in JSON and YAML)
+    pub fn format_failure(error: &str, kind: ErrorKind, format: OutputFormat) -> String {
+        let value = json!({
+            "error": error,
+            "kind": kind.name(),
+            "code": kind.code(),
+            "success": false,
+        });
         match format {
-            OutputFormat::Json => Self::format_json(json!({ "error": error, "success": false })),
-            OutputFormat::Yaml => {
-                Self::format_structured(json!({ "error": error, "success": false }), format)
+            OutputFormat::Json => Self::format_json(value),
+            OutputFormat::Yaml => Self::format_structured(value, format),
+            OutputFormat::Text | OutputFormat::Table => {
+                error_text(error, STDERR_COLOR.load(Ordering::Relaxed))
             }
-            OutputFormat::Text => format!("Error: {}", error),
+            OutputFormat::Gha => crate::gha::error(error, kind.name()),
         }
     }
 }
 
+fn paint(text: &str, style: &str, enabled: bool) -> String {
+    if enabled {
+        format!("{}{}{}", style, text, RESET)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Success message, with a green check mark when colored
+fn success_text(message: &str, color: bool) -> String {
+    if color {
+        format!("{} {}", paint("✓", GREEN, true), message)
+    } else {
+        message.to_string()
+    }
+}
+
+/// Error message, with a bold red marker when colored
+fn error_text(error: &str, color: bool) -> String {
+    format!(
+        "{} {}",
+        paint("Error:", &format!("{}{}", BOLD, RED), color),
+        error
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_format_failure() {
+        let json = Formatter::format_failure("missing", ErrorKind::NotFound, OutputFormat::Json);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["kind"], "not_found");
+        assert_eq!(value["code"], 2);
+        assert_eq!(value["success"], false);
+
+        let general = Formatter::format_failure("boom", ErrorKind::General, OutputFormat::Json);
+        assert!(general.contains("\"code\":1"));
+    }
+
+    #[test]
+    fn test_color_markers() {
+        assert_eq!(success_text("done", false), "done");
+        assert_eq!(success_text("done", true), "\x1b[32m✓\x1b[0m done");
+        assert_eq!(error_text("boom", false), "Error: boom");
+        assert_eq!(
+            error_text("boom", true),
+            "\x1b[1m\x1b[31mError:\x1b[0m boom"
+        );
+        assert_eq!(paint("meta", DIM, false), "meta");
+    }
+
+    #[test]
+    fn test_color_choice() {
+        assert!(ColorChoice::Always.enabled(false));
+        assert!(!ColorChoice::Never.enabled(true));
+        assert!(!ColorChoice::Auto.enabled(false));
+    }
+
+    #[test]
+    fn test_human_size() {
+        assert_eq!(Formatter::human_size(0), "0 B");
+        assert_eq!(Formatter::human_size(1023), "1023 B");
+        assert_eq!(Formatter::human_size(1536), "1.5 KiB");
+        assert_eq!(Formatter::human_size(25 * 1024 * 1024), "25.0 MiB");
+    }
+
     #[test]
     fn test_output_format_from_str() {
         assert!(matches!(
@@ -86,6 +240,10 @@ mod tests {
             OutputFormat::from_str("text"),
             Some(OutputFormat::Text)
         ));
+        assert!(matches!(
+            OutputFormat::from_str("table"),
+            Some(OutputFormat::Table)
+        ));
         assert!(OutputFormat::from_str("invalid").is_none());
     }
 
@@ -125,8 +283,14 @@ mod tests {
     #[test]
     fn test_format_error() {
         let err = "Something went wrong";
-        assert!(Formatter::format_error(err, OutputFormat::Text).contains("Error"));
-        assert!(Formatter::format_error(err, OutputFormat::Json).contains("error"));
+        assert!(
+            Formatter::format_failure(err, ErrorKind::General, OutputFormat::Text)
+                .contains("Error")
+        );
+        assert!(
+            Formatter::format_failure(err, ErrorKind::General, OutputFormat::Json)
+                .contains("error")
+        );
     }
 
     #[test]
@@ -0,0 +1,115 @@
+//! GitHub Actions workflow commands for `--format gha`
+//!
+//! Successes become `::notice::` annotations and errors `::error::`
+//! annotations. Commands with a result worth branching on (key counts, diff
+//! totals) also set step outputs by appending to the file `$GITHUB_OUTPUT`
+//! names, so later steps can read e.g. `steps.<id>.outputs.key_count`. The
+//! API token and encryption key are masked in the job log before any
+//! request is made.
+
+use crate::config::Config;
+use std::fs::OpenOptions;
+use std::io::Write;
+
+/// Escape a message for a workflow command
+pub fn escape_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escape a workflow command property such as `title`
+pub fn escape_property(text: &str) -> String {
+    escape_data(text).replace(':', "%3A").replace(',', "%2C")
+}
+
+/// A `::notice::` annotation
+pub fn notice(message: &str) -> String {
+    format!("::notice::{}", escape_data(message))
+}
+
+/// An `::error::` annotation titled with the error kind
+pub fn error(message: &str, title: &str) -> String {
+    format!(
+        "::error title={}::{}",
+        escape_property(title),
+        escape_data(message)
+    )
+}
+
+/// Hide `secret` wherever it would appear in the job log
+pub fn add_mask(secret: &str) {
+    if !secret.trim().is_empty() {
+        println!("::add-mask::{}", escape_data(secret));
+    }
+}
+
+/// Mask the API tokens and encryption keys stored in the config; tokens
+/// obtained from a command are masked when they are fetched
+pub fn mask_config(config: &Config) {
+    let storages = config
+        .storages
+        .values()
+        .flat_map(|storage| [Some(&storage.api_token), storage.encryption_key.as_ref()]);
+    let accounts = config
+        .accounts
+        .values()
+        .map(|account| Some(&account.api_token));
+    for secret in storages
+        .chain(accounts)
+        .chain([config.api_token.as_ref(), config.passphrase.as_ref()])
+        .flatten()
+    {
+        add_mask(secret);
+    }
+}
+
+/// Lines appended to `$GITHUB_OUTPUT` for one output
+fn output_entry(name: &str, value: &str) -> String {
+    if value.contains('\n') {
+        let delimiter = format!("cfkv_{}_eof", name);
+        format!("{}<<{}\n{}\n{}\n", name, delimiter, value, delimiter)
+    } else {
+        format!("{}={}\n", name, value)
+    }
+}
+
+/// Set step outputs; outside of Actions (no `$GITHUB_OUTPUT`) they are
+/// printed as `name=value` lines instead
+pub fn set_outputs(outputs: &[(&str, String)]) -> std::io::Result<()> {
+    let entries: String = outputs
+        .iter()
+        .map(|(name, value)| output_entry(name, value))
+        .collect();
+    match std::env::var_os("GITHUB_OUTPUT") {
+        Some(path) => OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?
+            .write_all(entries.as_bytes()),
+        None => {
+            print!("{}", entries);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workflow_commands() {
+        assert_eq!(notice("50% done\nnext"), "::notice::50%25 done%0Anext");
+        assert_eq!(
+            error("Key not found: a", "not_found"),
+            "::error title=not_found::Key not found: a"
+        );
+        assert_eq!(escape_property("a:b,c"), "a%3Ab%2Cc");
+        assert_eq!(output_entry("key_count", "3"), "key_count=3\n");
+        assert_eq!(
+            output_entry("keys", "a\nb"),
+            "keys<<cfkv_keys_eof\na\nb\ncfkv_keys_eof\n"
+        );
+    }
+}
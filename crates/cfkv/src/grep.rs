@@ -0,0 +1,218 @@
+//! Value matching for `cfkv grep`
+//!
+//! A `ValueMatcher` searches a value for a literal or regex pattern, either in
+//! the whole text (reporting each matching line) or in the field selected by
+//! a JSONPath expression. Only the simple subset of JSONPath is supported:
+//! `$.field`, `$.a.b`, `$.items[0]` and `$['key with spaces']`.
+
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+use serde_json::Value;
+
+/// Longest snippet printed for a match, in characters
+const MAX_SNIPPET_CHARS: usize = 160;
+
+/// A key whose value matched, with the matched snippets
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct GrepMatch {
+    pub key: String,
+    pub matches: Vec<String>,
+}
+
+/// One step of a JSONPath expression
+#[derive(Clone, Debug, PartialEq)]
+enum PathSegment {
+    Field(String),
+    Index(usize),
+}
+
+/// Searches values for a pattern
+#[derive(Clone, Debug)]
+pub struct ValueMatcher {
+    pattern: Regex,
+    json_path: Option<Vec<PathSegment>>,
+}
+
+impl ValueMatcher {
+    /// Build a matcher; `pattern` is a literal unless `regex` is set
+    pub fn new(
+        pattern: &str,
+        regex: bool,
+        ignore_case: bool,
+        json_path: Option<&str>,
+    ) -> Result<Self, String> {
+        let source = if regex {
+            pattern.to_string()
+        } else {
+            regex::escape(pattern)
+        };
+        let pattern = RegexBuilder::new(&source)
+            .case_insensitive(ignore_case)
+            .build()
+            .map_err(|e| format!("Invalid pattern: {}", e))?;
+        let json_path = json_path.map(parse_json_path).transpose()?;
+
+        Ok(Self { pattern, json_path })
+    }
+
+    /// Snippets of `value` that match, empty if it does not match
+    pub fn find(&self, value: &str) -> Vec<String> {
+        match &self.json_path {
+            Some(path) => {
+                let Ok(document) = serde_json::from_str::<Value>(value) else {
+                    return vec![];
+                };
+                match select(&document, path) {
+                    Some(Value::String(text)) if self.pattern.is_match(text) => {
+                        vec![snippet(text, &self.pattern)]
+                    }
+                    Some(Value::String(_)) | None => vec![],
+                    Some(other) => {
+                        let text = other.to_string();
+                        if self.pattern.is_match(&text) {
+                            vec![snippet(&text, &self.pattern)]
+                        } else {
+                            vec![]
+                        }
+                    }
+                }
+            }
+            None => value
+                .lines()
+                .filter(|line| self.pattern.is_match(line))
+                .map(|line| snippet(line.trim(), &self.pattern))
+                .collect(),
+        }
+    }
+}
+
+/// Shorten a matching line to a window around the first match
+fn snippet(line: &str, pattern: &Regex) -> String {
+    if line.chars().count() <= MAX_SNIPPET_CHARS {
+        return line.to_string();
+    }
+
+    let start = pattern.find(line).map(|m| m.start()).unwrap_or(0);
+    let before = line[..start].chars().count();
+    let skip = before.saturating_sub(MAX_SNIPPET_CHARS / 4);
+    let window: String = line.chars().skip(skip).take(MAX_SNIPPET_CHARS).collect();
+    let prefix = if skip > 0 { "..." } else { "" };
+    let suffix = if skip + MAX_SNIPPET_CHARS < line.chars().count() {
+        "..."
+    } else {
+        ""
+    };
+    format!("{}{}{}", prefix, window, suffix)
+}
+
+/// Parse the supported JSONPath subset into segments
+fn parse_json_path(path: &str) -> Result<Vec<PathSegment>, String> {
+    let invalid = || format!("Unsupported JSONPath '{}' (use e.g. $.a.b[0])", path);
+    let mut rest = path.strip_prefix('$').ok_or_else(invalid)?;
+    let mut segments = Vec::new();
+
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            if end == 0 {
+                return Err(invalid());
+            }
+            segments.push(PathSegment::Field(after[..end].to_string()));
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']').ok_or_else(invalid)?;
+            let inner = &after[..end];
+            let quoted = inner
+                .strip_prefix('\'')
+                .and_then(|i| i.strip_suffix('\''))
+                .or_else(|| inner.strip_prefix('"').and_then(|i| i.strip_suffix('"')));
+            let segment = match quoted {
+                Some(field) => PathSegment::Field(field.to_string()),
+                None => PathSegment::Index(inner.parse().map_err(|_| invalid())?),
+            };
+            segments.push(segment);
+            rest = &after[end + 1..];
+        } else {
+            return Err(invalid());
+        }
+    }
+
+    Ok(segments)
+}
+
+fn select<'v>(value: &'v Value, path: &[PathSegment]) -> Option<&'v Value> {
+    path.iter()
+        .try_fold(value, |current, segment| match segment {
+            PathSegment::Field(field) => current.get(field),
+            PathSegment::Index(index) => current.get(index),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_literal_and_regex_lines() {
+        let value = "name = api\nurl = https://api.example.com/v1\nretries = 3\n";
+
+        let literal = ValueMatcher::new("example.com", false, false, None).unwrap();
+        assert_eq!(
+            literal.find(value),
+            vec!["url = https://api.example.com/v1"]
+        );
+        // A literal dot is not a wildcard
+        assert!(literal.find("exampleXcom").is_empty());
+
+        let regex = ValueMatcher::new(r"retries = \d+", true, false, None).unwrap();
+        assert_eq!(regex.find(value), vec!["retries = 3"]);
+
+        let ignore_case = ValueMatcher::new("NAME", false, true, None).unwrap();
+        assert_eq!(ignore_case.find(value).len(), 1);
+
+        assert!(ValueMatcher::new("(", true, false, None).is_err());
+    }
+
+    #[test]
+    fn test_json_path() {
+        let value = r#"{"service": {"url": "https://a.example.com", "ports": [80, 443]}}"#;
+
+        let url = ValueMatcher::new("a.example", false, false, Some("$.service.url")).unwrap();
+        assert_eq!(url.find(value), vec!["https://a.example.com"]);
+
+        let port = ValueMatcher::new("443", false, false, Some("$.service.ports[1]")).unwrap();
+        assert_eq!(port.find(value), vec!["443"]);
+
+        let quoted = ValueMatcher::new("80", false, false, Some("$['service'].ports")).unwrap();
+        assert_eq!(quoted.find(value), vec!["[80,443]"]);
+
+        let missing = ValueMatcher::new("a", false, false, Some("$.nope")).unwrap();
+        assert!(missing.find(value).is_empty());
+        assert!(url.find("not json").is_empty());
+    }
+
+    #[test]
+    fn test_parse_json_path() {
+        assert_eq!(
+            parse_json_path("$.a[2]['b c']").unwrap(),
+            vec![
+                PathSegment::Field("a".into()),
+                PathSegment::Index(2),
+                PathSegment::Field("b c".into())
+            ]
+        );
+        assert_eq!(parse_json_path("$").unwrap(), vec![]);
+        assert!(parse_json_path("a.b").is_err());
+        assert!(parse_json_path("$..a").is_err());
+        assert!(parse_json_path("$[x]").is_err());
+    }
+
+    #[test]
+    fn test_long_line_snippet() {
+        let line = format!("{}needle{}", "a".repeat(500), "b".repeat(500));
+        let matcher = ValueMatcher::new("needle", false, false, None).unwrap();
+        let found = matcher.find(&line);
+        assert!(found[0].starts_with("...") && found[0].ends_with("..."));
+        assert!(found[0].contains("needle"));
+    }
+}
@@ -0,0 +1,33 @@
+//! Ctrl-C handling for long commands
+//!
+//! `batch export`, `batch import` and `grep` watch for Ctrl-C instead of
+//! being killed by it: the first one lets the requests in flight finish, so
+//! the export checkpoint or the import's completed chunks match what was
+//! written, and the command then stops with exit code 130 and a hint to
+//! resume. A second Ctrl-C exits at once.
+
+use crate::exit::{self, ErrorKind};
+use crate::formatter::OutputFormat;
+use cloudflare_kv::CancellationToken;
+
+/// Token cancelled by the first Ctrl-C; the second exits the process
+pub fn watch() -> CancellationToken {
+    let token = CancellationToken::new();
+    let cancelled = token.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        cancelled.cancel();
+        eprintln!("Stopping after the requests in flight; press Ctrl-C again to quit now");
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(ErrorKind::Interrupted.code());
+        }
+    });
+    token
+}
+
+/// Report a command stopped by Ctrl-C and exit with code 130
+pub fn stopped(message: &str, format: OutputFormat) -> ! {
+    exit::fail(message, ErrorKind::Interrupted, format)
+}
@@ -0,0 +1,84 @@
+//! Log output for `--debug` and `--log-format`
+
+use tracing_subscriber::{
+    layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer, Registry,
+};
+
+/// Most verbose level logged
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    fn as_str(self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        }
+    }
+}
+
+/// How log lines are written
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable lines
+    Pretty,
+    /// One JSON object per line, with the timestamp and enclosing spans
+    Json,
+}
+
+/// Filter logging cfkv's own crates at `level`
+pub fn filter(level: LogLevel) -> String {
+    ["cfkv", "cloudflare_kv", "cfkv_blog"]
+        .map(|target| format!("{}={}", target, level.as_str()))
+        .join(",")
+}
+
+/// Log to stderr, so logs never mix with command output, at `level` in
+/// `format` when given, and send spans to the `export` layer if any
+///
+/// `RUST_LOG`, when set, replaces the filter derived from `level`.
+pub fn init(
+    logs: Option<(LogLevel, LogFormat)>,
+    export: Option<Box<dyn Layer<Registry> + Send + Sync>>,
+) {
+    let log = logs.map(|(level, format)| {
+        let filter =
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(filter(level)));
+        match format {
+            LogFormat::Pretty => tracing_subscriber::fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_filter(filter)
+                .boxed(),
+            LogFormat::Json => tracing_subscriber::fmt::layer()
+                .json()
+                .with_current_span(true)
+                .with_span_list(true)
+                .with_writer(std::io::stderr)
+                .with_filter(filter)
+                .boxed(),
+        }
+    });
+    tracing_subscriber::registry().with(export).with(log).init();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter() {
+        assert_eq!(
+            filter(LogLevel::Trace),
+            "cfkv=trace,cloudflare_kv=trace,cfkv_blog=trace"
+        );
+    }
+}
@@ -1,56 +1,263 @@
+mod agent;
+mod batch;
+mod blog_hook;
+mod checkpoint;
 mod cli;
+mod completions;
 mod config;
+mod confirm;
+mod diff;
+mod doctor;
+mod edit;
+mod encryption;
+mod env_file;
+mod exit;
+mod filter;
+mod flags;
 mod formatter;
+mod gha;
+mod grep;
+mod interrupt;
+mod logging;
+mod mirror;
+mod notify;
+mod patch;
+mod plugins;
+mod progress;
+mod project;
+mod purge;
+mod redact;
+mod scan;
+mod secrets;
+mod serve;
+mod sessions;
+mod table;
+#[cfg(feature = "otel")]
+mod telemetry;
+mod template;
+mod tracked;
+mod transform;
+mod tree;
+mod usage;
+mod verify;
+mod worker;
+mod wrangler;
 
-use cfkv_blog::BlogPublisher;
-use clap::Parser;
-use cli::{BatchCommands, BlogCommands, Cli, Commands, ConfigCommands, StorageCommands};
-use cloudflare_kv::{ClientConfig, KvClient, PaginationParams};
-use formatter::{Formatter, OutputFormat};
+use cfkv_blog::{BlogPublisher, ContentPublisher, FeedConfig};
+use checkpoint::{Checkpoint, ExportCheckpoint, ImportCheckpoint};
+use clap::{CommandFactory, FromArgMatches};
+use cli::{
+    BatchCommands, BlogCommands, CacheCommands, Cli, Commands, ConfigCommands, ContentCommands,
+    FlagCommands, MetaCommands, PluginCommands, SessionCommands, StorageCommands,
+};
+use cloudflare_kv::{
+    keep_tags, value_hash, BatchBuilder, BatchOperation, BatchOptions, BulkWrite, CacheOptions,
+    ClientConfig, ConnectionOptions, CounterValue, EncryptionPlugin, Expiration, KvClient, Metrics,
+    NamespaceAnalyzer, NamespaceStats, PaginationParams, PluginClient, PrefixGrouping, ReadCache,
+    RetryPolicy, ScopedKvClient, WriteCondition,
+};
+use confirm::Confirm;
+use exit::ErrorKind;
+use formatter::{ColorChoice, Formatter, OutputFormat};
+use logging::{LogFormat, LogLevel};
+use patch::JsonPatch;
+use progress::Progress;
+use redact::Redactor;
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
+async fn main() {
+    let matches = Cli::command()
+        .try_get_matches()
+        .unwrap_or_else(|e| exit::usage_error(e));
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| exit::usage_error(e));
 
     // Initialize logging
-    if cli.debug {
-        tracing_subscriber::registry()
-            .with(
-                tracing_subscriber::EnvFilter::try_from_default_env()
-                    .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("cf_kv=debug")),
-            )
-            .with(tracing_subscriber::fmt::layer())
-            .init();
+    // The agent logs each job run and serve each request, so they log at
+    // info level by default
+    let logs = (cli.debug.is_some()
+        || cli.log_format.is_some()
+        || matches!(cli.command, Commands::Agent { .. } | Commands::Serve { .. }))
+    .then(|| {
+        (
+            cli.debug.unwrap_or(LogLevel::Info),
+            cli.log_format.unwrap_or(LogFormat::Pretty),
+        )
+    });
+    #[cfg(feature = "otel")]
+    let export = cli.trace_endpoint.as_deref().map(|endpoint| {
+        telemetry::layer(endpoint)
+            .unwrap_or_else(|e| exit::fail(&e, ErrorKind::Validation, OutputFormat::Text))
+    });
+    #[cfg(not(feature = "otel"))]
+    let export = None;
+    if logs.is_some() || export.is_some() {
+        logging::init(logs, export);
+    }
+
+    // Project defaults apply where no option or environment variable was given
+    let project = if cli.no_project {
+        None
+    } else {
+        project::discover().unwrap_or_else(|e| exit::fail_with(&e, OutputFormat::Text))
+    };
+    let mut project_prefix = None;
+    if let Some((path, project)) = project {
+        tracing::debug!("Using project file {}", path.display());
+        if cli.storage.is_none() {
+            cli.storage = project.storage;
+            cli.namespace = cli.namespace.or(project.namespace);
+        }
+        cli.account_id = cli.account_id.or(project.account_id);
+        cli.namespace_id = cli.namespace_id.or(project.namespace_id);
+        cli.api_token = cli.api_token.or(project.api_token);
+        if matches.value_source("format") == Some(clap::parser::ValueSource::DefaultValue) {
+            cli.format = project.format.unwrap_or(cli.format);
+        }
+        project_prefix = project.key_prefix;
+    }
+
+    let Some(format) = OutputFormat::from_str(&cli.format) else {
+        exit::fail(
+            &format!(
+                "Unknown output format '{}' (json, yaml, text, table, gha)",
+                cli.format
+            ),
+            ErrorKind::Validation,
+            OutputFormat::Text,
+        );
+    };
+    Formatter::init_color(if cli.no_color {
+        ColorChoice::Never
+    } else {
+        cli.color
+    });
+
+    // The agent notifies about each job run instead
+    if let (Some(url), false) = (&cli.notify, matches!(cli.command, Commands::Agent { .. })) {
+        let notifier = notify::Notifier::new(url.clone(), cli.notify_on.unwrap_or_default());
+        notify::watch(notifier, command_name(&matches));
+    }
+
+    if cli.stats {
+        usage::watch(format);
+    }
+
+    let run = run(cli, project_prefix, format);
+    #[cfg(feature = "otel")]
+    let run = tracing::Instrument::instrument(
+        run,
+        tracing::info_span!("cfkv", command = command_name(&matches)),
+    );
+    if let Err(e) = run.await {
+        exit::fail_with(e.as_ref(), format);
     }
+    usage::report();
+    #[cfg(feature = "otel")]
+    telemetry::shutdown();
+    notify::command_succeeded().await;
+}
 
-    let format = OutputFormat::from_str(&cli.format).unwrap_or(OutputFormat::Text);
+/// Name of the command for notifications, such as `cfkv batch export`
+///
+/// Only subcommand names are used; arguments may hold values or secrets.
+fn command_name(matches: &clap::ArgMatches) -> String {
+    let mut name = "cfkv".to_string();
+    let mut matches = matches;
+    while let Some((subcommand, sub_matches)) = matches.subcommand() {
+        name.push(' ');
+        name.push_str(subcommand);
+        matches = sub_matches;
+    }
+    name
+}
 
+async fn run(
+    cli: Cli,
+    project_prefix: Option<String>,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
     // Load configuration
-    let config_path = if let Some(config) = cli.config {
-        config
+    let config_path = if let Some(config) = &cli.config {
+        config.clone()
     } else {
         config::Config::default_path()?
     };
 
-    let mut config = config::Config::load_or_create(&config_path).unwrap_or_default();
-
-    // Merge CLI arguments with config
-    if let Some(account_id) = cli.account_id {
-        config.account_id = Some(account_id);
+    // Validating and repairing read the file themselves, as loading fails
+    // on the damage they are for
+    match &cli.command {
+        Commands::Config {
+            command: ConfigCommands::Validate,
+        } => return handle_config_validate(&config_path, format),
+        Commands::Config {
+            command: ConfigCommands::Repair,
+        } => return handle_config_repair(&config_path, cli.yes, format),
+        _ => {}
     }
-    if let Some(namespace_id) = cli.namespace_id {
-        config.namespace_id = Some(namespace_id);
+
+    let mut config = config::Config::load_or_create(&config_path)?;
+    if let Commands::Agent {
+        config: jobs_path,
+        once,
+    } = &cli.command
+    {
+        return handle_agent(&cli, &config_path, jobs_path, *once, format).await;
     }
-    if let Some(api_token) = cli.api_token {
-        config.api_token = Some(api_token);
+    let confirm = Confirm::new(cli.yes, config.confirms_destructive());
+    let requests = RequestOptions::new(&cli, &config.http);
+
+    if matches!(format, OutputFormat::Gha) {
+        gha::mask_config(&config);
     }
 
     match cli.command {
         Commands::Config { command } => {
-            handle_config_command(command, &config, &config_path, format).await?
+            handle_config_command(command, &config, &config_path, confirm, format).await?
+        }
+        Commands::Plugin { command } => {
+            handle_plugin_command(
+                command,
+                &mut config,
+                &config_path,
+                cli.storage.as_deref(),
+                format,
+            )
+            .await?
+        }
+        Commands::Blog {
+            command: BlogCommands::Preview { file, render },
+        } => handle_blog_preview(&file, &render, &config.blog, format)?,
+        Commands::Content {
+            command: ContentCommands::Collections,
+        } => handle_content_collections(&config, format)?,
+        Commands::Completions { shell } => {
+            completions::generate(shell, &mut std::io::stdout())?;
+        }
+        Commands::Diff {
+            from,
+            to,
+            prefix,
+            concurrency,
+        } => {
+            let sides = DiffSides {
+                from: &from,
+                to: &to,
+                prefix: &prefix,
+                concurrency,
+            };
+            handle_diff(&config, sides, &requests, format).await?
+        }
+        Commands::Cache {
+            command: CacheCommands::Clear,
+        } => {
+            cloudflare_kv::cache::clear_dir(&config::Config::cache_dir()?)?;
+            println!("{}", Formatter::format_success("Cache cleared", format));
         }
         Commands::Storage { command } => {
             // For storage commands, ensure migration is done and config is saved if needed
@@ -64,50 +271,424 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 config.save(&config_path)?;
             }
 
-            handle_storage_command(command, &mut config, &config_path, format).await?
+            match command {
+                StorageCommands::Doctor { name } => {
+                    let name = name.as_deref().or(cli.storage.as_deref());
+                    handle_storage_doctor(&config, name, &requests, cli.force, format).await?
+                }
+                command => {
+                    handle_storage_command(
+                        command,
+                        &mut config,
+                        &config_path,
+                        cli.storage.as_deref(),
+                        confirm,
+                        format,
+                    )
+                    .await?
+                }
+            }
         }
         _ => {
-            // Validate configuration for other commands
-            // Try to get active storage, fallback to legacy format if available
-            let (account_id, namespace_id, api_token) = if let Some(storage) =
-                config.get_active_storage()
-            {
-                (
-                    storage.account_id.clone(),
-                    storage.namespace_id.clone(),
-                    storage.api_token.clone(),
-                )
-            } else if let (Some(acc), Some(ns), Some(token)) =
-                (&config.account_id, &config.namespace_id, &config.api_token)
-            {
-                (acc.clone(), ns.clone(), token.clone())
+            let mut client_config = if let Some(path) = &cli.local {
+                // Offline: no storage or credentials needed
+                ClientConfig::local_file(path)
             } else {
-                return Err("No storage configured. Add one with: cfkv storage add <name> --account-id <ID> --namespace-id <ID> --api-token <TOKEN>".into());
-            };
+                // Validate configuration for other commands
+                // Try to get active storage, fallback to legacy format if available
+                let (account_id, namespace_id, api_token) = if let Some(storage) =
+                    config.select_storage(cli.storage.as_deref())?
+                {
+                    if storage.read_only && cli.command.writes() && !cli.force {
+                        exit::fail(
+                            &format!(
+                                "Storage '{}' is read-only; pass --force to write to it",
+                                storage.name
+                            ),
+                            ErrorKind::Validation,
+                            format,
+                        );
+                    }
+                    (
+                        storage.account_id.clone(),
+                        storage
+                            .resolve_namespace(cli.namespace.as_deref())?
+                            .to_string(),
+                        storage.token()?,
+                    )
+                } else if cli.namespace.is_some() {
+                    return Err("--namespace requires a configured storage".into());
+                } else if let (Some(acc), Some(ns), Some(token)) = (
+                    // Credentials given on the command line override the
+                    // file's for this run only; they are never saved
+                    cli.account_id.as_ref().or(config.account_id.as_ref()),
+                    cli.namespace_id.as_ref().or(config.namespace_id.as_ref()),
+                    cli.api_token.as_ref().or(config.api_token.as_ref()),
+                ) {
+                    (acc.clone(), ns.clone(), token.clone())
+                } else {
+                    return Err("No storage configured. Add one with: cfkv storage add <name> --account-id <ID> --namespace-id <ID> --api-token <TOKEN>".into());
+                };
+                if matches!(format, OutputFormat::Gha) {
+                    gha::add_mask(&api_token);
+                }
 
-            let client_config = ClientConfig::new(
-                &account_id,
-                &namespace_id,
-                cloudflare_kv::AuthCredentials::token(api_token),
-            );
-            let client = KvClient::new(client_config);
+                // Keep connections open across the many requests of bulk jobs
+                let client_config = ClientConfig::new(
+                    &account_id,
+                    &namespace_id,
+                    cloudflare_kv::AuthCredentials::token(api_token),
+                )
+                .with_connection(ConnectionOptions::keep_alive());
+                match config.select_storage(cli.storage.as_deref())? {
+                    Some(storage) => storage.endpoint(client_config),
+                    None => client_config,
+                }
+            };
+            client_config = requests.apply(client_config);
+            let compress = match &cli.command {
+                Commands::Put { compress, .. } => *compress,
+                Commands::Batch {
+                    command: BatchCommands::Import { compress, .. },
+                } => *compress,
+                _ => None,
+            };
+            let registry = plugins::build_registry(
+                &config.plugins,
+                config.select_storage(cli.storage.as_deref())?,
+                compress,
+            )
+            .await?;
+            let mut kv_client = KvClient::new(client_config);
+            let key_prefix = match &cli.local {
+                Some(_) => None,
+                None => config
+                    .select_storage(cli.storage.as_deref())?
+                    .and_then(|storage| storage.key_prefix.clone()),
+            };
+            // A project's prefix scopes keys within the storage's
+            for prefix in key_prefix.into_iter().chain(project_prefix) {
+                kv_client = ScopedKvClient::new(kv_client, prefix).into_client();
+            }
+            let cache = disk_cache(&kv_client, Duration::ZERO).ok();
+            let written = tracked::WrittenKeys::default();
+            let kv_client = kv_client.wrap_transport(|inner| {
+                let transport = tracked::TrackedTransport::new(inner, cache);
+                match cli.command.writes() {
+                    true => Arc::new(transport.with_written(written.clone())),
+                    false => Arc::new(transport),
+                }
+            });
+            let client_config = kv_client.config().clone();
+            let plugin_client = PluginClient::new(kv_client, registry);
+            let client = plugin_client.client();
+            let sensitive_prefixes = match &cli.local {
+                Some(_) => vec![],
+                None => config
+                    .select_storage(cli.storage.as_deref())?
+                    .map(|storage| storage.sensitive_prefixes.clone())
+                    .unwrap_or_default(),
+            };
+            let mut purge_hooks = match &cli.local {
+                Some(_) => vec![],
+                None => config
+                    .select_storage(cli.storage.as_deref())?
+                    .map(|storage| storage.purge_urls.clone())
+                    .unwrap_or_default(),
+            };
 
             match cli.command {
-                Commands::Get { key, pretty } => handle_get(&client, &key, format, pretty).await?,
+                Commands::Get {
+                    keys,
+                    keys_from,
+                    ndjson,
+                    concurrency,
+                    pretty,
+                    hash,
+                    base64,
+                    reveal,
+                    cached,
+                    cache_ttl,
+                } => {
+                    let redactor = Redactor::new(sensitive_prefixes, reveal);
+                    let key = match (keys.as_slice(), &keys_from) {
+                        ([key], None) => key.clone(),
+                        _ => {
+                            if hash || base64 || cached {
+                                exit::fail(
+                                    "--hash, --base64 and --cached take a single key",
+                                    ErrorKind::Validation,
+                                    format,
+                                );
+                            }
+                            let keys = read_keys(keys, keys_from.as_deref())?;
+                            let output = GetManyOutput {
+                                ndjson,
+                                pretty,
+                                redactor,
+                            };
+                            handle_get_many(&plugin_client, &keys, concurrency, output, format)
+                                .await?;
+                            return Ok(());
+                        }
+                    };
+                    let cache = if cached {
+                        Some(disk_cache(client, Duration::from_secs(cache_ttl))?)
+                    } else {
+                        None
+                    };
+                    if base64 {
+                        handle_get_base64(&plugin_client, &key, cache.as_ref(), &redactor, format)
+                            .await?
+                    } else {
+                        let cache = cache.as_ref();
+                        handle_get(&plugin_client, &key, cache, &redactor, format, pretty, hash)
+                            .await?
+                    }
+                }
                 Commands::Put {
                     key,
                     value,
                     file,
+                    stdin,
+                    base64,
                     ttl,
                     metadata,
-                } => handle_put(&client, &key, value, file, ttl, metadata, format).await?,
-                Commands::Delete { key } => handle_delete(&client, &key, format).await?,
+                    if_absent,
+                    if_match,
+                    purge_url,
+                    purge_zone,
+                    template,
+                    ..
+                } => {
+                    if !purge_url.is_empty() && cli.local.is_some() {
+                        exit::fail(
+                            "--purge-url needs the Cloudflare API, not a local file",
+                            ErrorKind::Validation,
+                            format,
+                        );
+                    }
+                    let key = if template {
+                        template::expand(&key)?
+                    } else {
+                        key
+                    };
+                    let condition = match (if_absent, if_match) {
+                        (true, _) => Some(WriteCondition::IfAbsent),
+                        (false, Some(hash)) => Some(WriteCondition::IfMatch(hash)),
+                        (false, None) => None,
+                    };
+                    if let Some(condition) = condition {
+                        if let Err(e) = plugin_client.check_condition(&key, &condition).await {
+                            exit::fail_with(&e, format);
+                        }
+                    }
+                    let value = PutValue {
+                        value,
+                        file,
+                        stdin,
+                        base64,
+                    };
+                    handle_put(&plugin_client, &key, value, ttl, metadata, format).await?;
+                    if !purge_url.is_empty() {
+                        purge_hooks.push(config::PurgeHook {
+                            prefix: String::new(),
+                            urls: purge_url,
+                            zone_id: purge_zone,
+                        });
+                    }
+                }
+                Commands::PutMany {
+                    pairs,
+                    env_file,
+                    prefix,
+                    ttl,
+                } => {
+                    let mut all = match env_file {
+                        Some(path) => match env_file::parse(&fs::read_to_string(&path)?) {
+                            Ok(pairs) => pairs,
+                            Err(e) => exit::fail(
+                                &format!("Invalid env file '{}': {}", path.display(), e),
+                                ErrorKind::Validation,
+                                format,
+                            ),
+                        },
+                        None => Vec::new(),
+                    };
+                    all.extend(pairs);
+                    handle_put_many(&plugin_client, all, prefix.as_deref(), ttl, format).await?
+                }
+                Commands::Append {
+                    key,
+                    value,
+                    file,
+                    newline,
+                } => handle_append(&plugin_client, &key, value, file, newline, format).await?,
+                Commands::Incr { key, by } => {
+                    handle_increment(&plugin_client, &key, Ok(by), format).await?
+                }
+                Commands::Decr { key, by } => {
+                    handle_increment(&plugin_client, &key, by.negate(), format).await?
+                }
+                Commands::Delete { key, prefix } => match (key, prefix) {
+                    (_, Some(prefix)) => {
+                        handle_delete_prefix(client, &prefix, confirm, format).await?
+                    }
+                    (Some(key), None) => {
+                        confirm.ask(&format!("Delete key {}?", key), &[], format)?;
+                        handle_delete(client, &key, format).await?
+                    }
+                    (None, None) => unreachable!("clap requires a key or --prefix"),
+                },
+                Commands::Exists { key, quiet } => handle_exists(client, &key, quiet, format).await,
+                Commands::Edit { key, create } => {
+                    handle_edit(&plugin_client, &key, create, format).await?
+                }
+                Commands::Rename {
+                    old,
+                    new,
+                    prefix,
+                    keep_source,
+                    force,
+                    dry_run,
+                    template,
+                } => {
+                    let new = if template {
+                        template::expand(&new)?
+                    } else {
+                        new
+                    };
+                    let options = RenameOptions {
+                        prefix,
+                        keep_source,
+                        force,
+                        dry_run,
+                    };
+                    handle_rename(client, &old, &new, options, format).await?
+                }
+                Commands::Patch { key, patch } => {
+                    handle_patch(&plugin_client, &key, &patch, format).await?
+                }
+                Commands::Meta { command } => handle_meta(client, command, format).await?,
+                Commands::Tag {
+                    key,
+                    labels,
+                    remove,
+                } => handle_tag(client, &key, labels, remove, format).await?,
                 Commands::List {
                     limit,
                     cursor,
                     metadata,
-                } => handle_list(&client, limit, cursor, metadata, format).await?,
-                Commands::Batch { command } => handle_batch(&client, command, format).await?,
+                    detailed,
+                    filter,
+                    columns,
+                } => {
+                    let redactor = Redactor::new(sensitive_prefixes, false);
+                    let view = ListView {
+                        metadata,
+                        detailed,
+                        filter,
+                        columns,
+                    };
+                    handle_list(client, limit, cursor, view, &redactor, format).await?
+                }
+                Commands::Grep {
+                    pattern,
+                    prefix,
+                    regex,
+                    ignore_case,
+                    json_path,
+                    keys_only,
+                    concurrency,
+                } => {
+                    let matcher = match grep::ValueMatcher::new(
+                        &pattern,
+                        regex,
+                        ignore_case,
+                        json_path.as_deref(),
+                    ) {
+                        Ok(matcher) => matcher,
+                        Err(e) => exit::fail(&e, ErrorKind::Validation, format),
+                    };
+                    let progress = Progress::new("Searching", None, !cli.no_progress);
+                    let output = GrepOutput {
+                        keys_only,
+                        redactor: Redactor::new(sensitive_prefixes, false),
+                    };
+                    handle_grep(
+                        &plugin_client,
+                        &matcher,
+                        prefix,
+                        output,
+                        concurrency,
+                        &progress,
+                        format,
+                    )
+                    .await?
+                }
+                Commands::Stats {
+                    prefixes,
+                    delimiter,
+                    concurrency,
+                    no_sizes,
+                } => {
+                    let grouping = if prefixes.is_empty() {
+                        PrefixGrouping::Delimiter(delimiter)
+                    } else {
+                        PrefixGrouping::Prefixes(prefixes)
+                    };
+                    let progress = Progress::new("Analyzing", None, !cli.no_progress);
+                    let analyzer = NamespaceAnalyzer::new(client)
+                        .with_grouping(grouping)
+                        .with_concurrency(concurrency)
+                        .with_sizes(!no_sizes)
+                        .with_progress(|_, size| progress.inc(size.unwrap_or(0)));
+                    let stats = analyzer.analyze().await;
+                    progress.clear();
+                    match stats {
+                        Ok(stats) => handle_stats(&stats, format)?,
+                        Err(e) => exit::fail_with(&e, format),
+                    }
+                }
+                Commands::Tree {
+                    delimiter,
+                    depth,
+                    concurrency,
+                    no_sizes,
+                } => {
+                    let tree =
+                        std::sync::Mutex::new(tree::KeyTree::new(delimiter, depth, !no_sizes));
+                    let progress = Progress::new("Analyzing", None, !cli.no_progress);
+                    // The analyzer borrows the tree until it is dropped
+                    let result = {
+                        let analyzer = NamespaceAnalyzer::new(client)
+                            .with_concurrency(concurrency)
+                            .with_sizes(!no_sizes)
+                            .with_progress(|key, size| {
+                                progress.inc(size.unwrap_or(0));
+                                tree.lock().unwrap().insert(&key.name, size);
+                            });
+                        analyzer.analyze().await
+                    };
+                    progress.clear();
+                    if let Err(e) = result {
+                        exit::fail_with(&e, format);
+                    }
+                    handle_tree(&tree.into_inner().unwrap().finish(), format)?;
+                }
+                Commands::Batch { command } => {
+                    let redactor = Redactor::new(sensitive_prefixes, false);
+                    let show_progress = !cli.no_progress;
+                    handle_batch(
+                        &plugin_client,
+                        command,
+                        show_progress,
+                        confirm,
+                        &redactor,
+                        format,
+                    )
+                    .await?
+                }
                 Commands::Namespace { command: _ } => {
                     println!(
                         "{}",
@@ -120,10 +701,105 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         Formatter::format_text("Interactive mode coming soon", format)
                     );
                 }
-                Commands::Blog { command } => handle_blog(&client, command, format).await?,
+                Commands::Blog { command } => {
+                    handle_blog(client, command, &config.blog, confirm, format).await?
+                }
+                Commands::CompleteKeys { prefix } => handle_complete_keys(client, &prefix).await,
                 Commands::Config { .. } => unreachable!(),
                 Commands::Storage { .. } => unreachable!(),
+                Commands::Completions { .. } => unreachable!(),
+                Commands::Content { command } => {
+                    handle_content(client, command, &config, confirm, format).await?
+                }
+                Commands::Flags { command } => {
+                    handle_flags(&plugin_client, command, format).await?
+                }
+                Commands::Sessions { command } => {
+                    handle_sessions(client, command, confirm, format).await?
+                }
+                Commands::Plugin { .. } => unreachable!(),
+                Commands::Cache { .. } => unreachable!(),
+                Commands::Diff { .. } => unreachable!(),
+                Commands::Agent { .. } => unreachable!(),
+                Commands::Verify {
+                    manifest,
+                    generate,
+                    prefix,
+                    concurrency,
+                } => {
+                    if generate {
+                        handle_verify_generate(
+                            &plugin_client,
+                            &manifest,
+                            &prefix,
+                            concurrency,
+                            format,
+                        )
+                        .await?
+                    } else {
+                        handle_verify(&plugin_client, &manifest, concurrency, format).await?
+                    }
+                }
+                Commands::Mirror {
+                    to,
+                    prefix,
+                    table,
+                    concurrency,
+                } => {
+                    let database = match mirror::TargetUrl::parse(&to) {
+                        Ok(mirror::TargetUrl::Sqlite(path)) => mirror::Database::Sqlite(path),
+                        Ok(mirror::TargetUrl::D1(database_id)) => {
+                            let Some(storage) = config.select_storage(cli.storage.as_deref())?
+                            else {
+                                exit::fail(
+                                    "D1 targets need a configured storage for the account and token",
+                                    ErrorKind::Validation,
+                                    format,
+                                );
+                            };
+                            mirror::Database::d1(
+                                &storage.account_id,
+                                &database_id,
+                                cloudflare_kv::AuthCredentials::token(storage.token()?),
+                            )
+                        }
+                        Err(e) => exit::fail_with(&e, format),
+                    };
+                    let options = MirrorOptions {
+                        prefix: &prefix,
+                        table: &table,
+                        concurrency,
+                        show_progress: !cli.no_progress,
+                    };
+                    handle_mirror(&plugin_client, &database, options, format).await?
+                }
+                Commands::Serve {
+                    port,
+                    bind,
+                    token,
+                    worker_compat,
+                } => {
+                    // Writes over HTTP are refused rather than the whole server
+                    let read_only = cli.local.is_none()
+                        && !cli.force
+                        && config
+                            .select_storage(cli.storage.as_deref())?
+                            .is_some_and(|storage| storage.read_only);
+                    let options = serve::ServeOptions {
+                        addr: (bind, port).into(),
+                        token,
+                        read_only,
+                        worker_compat,
+                    };
+                    if let Err(e) = serve::serve(plugin_client, options).await {
+                        exit::fail(&e.to_string(), ErrorKind::General, format);
+                    }
+                }
             }
+
+            let keys = written.keys();
+            let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+            purge_after_write(&client_config, &purge_hooks, &keys, format).await;
         }
     }
 
@@ -131,12 +807,36 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 async fn handle_get(
-    client: &KvClient,
+    client: &PluginClient,
     key: &str,
+    cache: Option<&ReadCache>,
+    redactor: &Redactor,
     format: OutputFormat,
     pretty: bool,
+    hash: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    match client.get(key).await {
+    let result = match cache {
+        Some(cache) => client.get_cached(key, cache).await,
+        None => client.get(key).await,
+    };
+    if !hash && redactor.masks(key) {
+        eprintln!("{} is sensitive; pass --reveal to show its value", key);
+    }
+    let result = result.map(|pair| {
+        pair.map(|mut pair| {
+            if !hash {
+                pair.value = redactor.value(key, &pair.value).to_string();
+            }
+            pair
+        })
+    });
+    match result {
+        Ok(Some(kv_pair)) if hash => {
+            println!(
+                "{}",
+                Formatter::format_text(&value_hash(kv_pair.value.as_bytes()), format)
+            );
+        }
         Ok(Some(kv_pair)) => {
             let output = match format {
                 OutputFormat::Json => {
@@ -155,46 +855,226 @@ async fn handle_get(
                 OutputFormat::Yaml => {
                     format!("key: {}\nvalue: {}", kv_pair.key, kv_pair.value)
                 }
-                OutputFormat::Text => kv_pair.value,
+                OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => kv_pair.value,
             };
             println!("{}", output);
         }
-        Ok(None) => {
-            eprintln!(
-                "{}",
-                Formatter::format_error(&format!("Key not found: {}", key), format)
-            );
-            std::process::exit(1);
+        Ok(None) => exit::fail(
+            &format!("Key not found: {}", key),
+            ErrorKind::NotFound,
+            format,
+        ),
+        Err(e) => exit::fail_with(&e, format),
+    }
+
+    Ok(())
+}
+
+/// Keys given as arguments followed by those in `keys_from`, one per line
+fn read_keys(
+    mut keys: Vec<String>,
+    keys_from: Option<&Path>,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let content = match keys_from {
+        Some(path) if path == Path::new("-") => std::io::read_to_string(std::io::stdin())?,
+        Some(path) => fs::read_to_string(path)?,
+        None => return Ok(keys),
+    };
+    keys.extend(
+        content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(String::from),
+    );
+    Ok(keys)
+}
+
+/// How `get` prints several keys
+struct GetManyOutput {
+    /// One JSON object per line instead of a single object
+    ndjson: bool,
+    pretty: bool,
+    redactor: Redactor,
+}
+
+/// Fetch several keys concurrently and print them in the order given
+///
+/// Missing keys are printed as null (or skipped in text output) and make
+/// the command fail once everything found has been printed.
+async fn handle_get_many(
+    client: &PluginClient,
+    keys: &[String],
+    concurrency: usize,
+    output: GetManyOutput,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pairs = match client.get_many(keys, concurrency).await {
+        Ok(pairs) => pairs,
+        Err(e) => exit::fail_with(&e, format),
+    };
+    let values: Vec<(&String, Option<String>)> = keys
+        .iter()
+        .zip(pairs)
+        .map(|(key, pair)| {
+            let value = pair.map(|pair| output.redactor.value(key, &pair.value).to_string());
+            (key, value)
+        })
+        .collect();
+
+    if output.ndjson {
+        for (key, value) in &values {
+            println!("{}", serde_json::json!({"key": key, "value": value}));
         }
-        Err(e) => {
-            eprintln!("{}", Formatter::format_error(&e.to_string(), format));
-            std::process::exit(1);
+    } else {
+        match format {
+            OutputFormat::Json | OutputFormat::Yaml => {
+                let object: serde_json::Map<String, serde_json::Value> = values
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), serde_json::json!(value)))
+                    .collect();
+                match format {
+                    OutputFormat::Yaml => print!("{}", serde_yaml::to_string(&object)?),
+                    _ if output.pretty => println!("{}", serde_json::to_string_pretty(&object)?),
+                    _ => println!("{}", serde_json::to_string(&object)?),
+                }
+            }
+            OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
+                let found = values
+                    .iter()
+                    .filter_map(|(key, value)| Some((key, value.as_ref()?)));
+                for (i, (key, value)) in found.enumerate() {
+                    if i > 0 {
+                        println!();
+                    }
+                    println!("==> {} <==\n{}", key, value);
+                }
+            }
         }
     }
 
+    let missing: Vec<&str> = values
+        .iter()
+        .filter(|(_, value)| value.is_none())
+        .map(|(key, _)| key.as_str())
+        .collect();
+    if !missing.is_empty() {
+        exit::fail(
+            &format!("Keys not found: {}", missing.join(", ")),
+            ErrorKind::NotFound,
+            format,
+        );
+    }
     Ok(())
 }
 
-async fn handle_put(
-    client: &KvClient,
+/// Print a value base64-encoded, keeping binary values intact
+async fn handle_get_base64(
+    client: &PluginClient,
     key: &str,
+    cache: Option<&ReadCache>,
+    redactor: &Redactor,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use base64::Engine;
+
+    let result = match cache {
+        Some(cache) => client.get_bytes_cached(key, cache).await,
+        None => client.get_bytes(key).await,
+    };
+    let value = match result {
+        Ok(Some(_)) if redactor.masks(key) => {
+            eprintln!("{} is sensitive; pass --reveal to show its value", key);
+            redact::MASK.to_string()
+        }
+        Ok(Some(value)) => base64::engine::general_purpose::STANDARD.encode(value),
+        Ok(None) => exit::fail(
+            &format!("Key not found: {}", key),
+            ErrorKind::NotFound,
+            format,
+        ),
+        Err(e) => exit::fail_with(&e, format),
+    };
+
+    let record = serde_json::json!({"key": key, "value": value, "base64": true});
+    match format {
+        OutputFormat::Json => println!("{}", record),
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(&record)?),
+        OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => println!("{}", value),
+    }
+    Ok(())
+}
+
+/// Where `put` takes its value from
+struct PutValue {
     value: Option<String>,
     file: Option<std::path::PathBuf>,
+    /// Read the value from stdin
+    stdin: bool,
+    /// The value is base64 of the bytes to store
+    base64: bool,
+}
+
+async fn handle_put(
+    client: &PluginClient,
+    key: &str,
+    value: PutValue,
     ttl: Option<u64>,
     metadata: Option<String>,
     format: OutputFormat,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let value_bytes = if let Some(file_path) = file {
-        fs::read(&file_path)?
-    } else if let Some(val) = value {
+    use std::io::{IsTerminal, Read};
+
+    let read_stdin = || -> std::io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        std::io::stdin().read_to_end(&mut bytes)?;
+        Ok(bytes)
+    };
+    let mut value_bytes = if let Some(file_path) = value.file {
+        if file_path == Path::new("-") {
+            read_stdin()?
+        } else {
+            fs::read(&file_path)?
+        }
+    } else if let Some(val) = value.value {
         val.into_bytes()
+    } else if value.stdin {
+        read_stdin()?
+    } else if !std::io::stdin().is_terminal() {
+        // Without --stdin, empty input is more likely a broken pipe than
+        // an intended empty value
+        let bytes = read_stdin()?;
+        if bytes.is_empty() {
+            exit::fail(
+                "No value given and stdin is empty; pass --stdin or --file - to store an empty value",
+                ErrorKind::Validation,
+                format,
+            );
+        }
+        bytes
     } else {
-        eprintln!(
-            "{}",
-            Formatter::format_error("Either --value or --file must be provided", format)
+        exit::fail(
+            "Either --value, --file or --stdin must be provided",
+            ErrorKind::Validation,
+            format,
         );
-        std::process::exit(1);
     };
+    if value.base64 {
+        use base64::Engine;
+
+        let encoded: Vec<u8> = value_bytes
+            .into_iter()
+            .filter(|b| !b.is_ascii_whitespace())
+            .collect();
+        value_bytes = match base64::engine::general_purpose::STANDARD.decode(encoded) {
+            Ok(bytes) => bytes,
+            Err(e) => exit::fail(
+                &format!("Invalid base64 value: {}", e),
+                ErrorKind::Validation,
+                format,
+            ),
+        };
+    }
 
     let result = if ttl.is_some() || metadata.is_some() {
         let meta = metadata.and_then(|m| serde_json::from_str(&m).ok());
@@ -208,427 +1088,3666 @@ async fn handle_put(
             "{}",
             Formatter::format_success(&format!("Successfully put key: {}", key), format)
         ),
-        Err(e) => {
-            eprintln!("{}", Formatter::format_error(&e.to_string(), format));
-            std::process::exit(1);
-        }
+        Err(e) => exit::fail_with(&e, format),
     }
 
     Ok(())
 }
 
-async fn handle_delete(
-    client: &KvClient,
-    key: &str,
+async fn handle_put_many(
+    client: &PluginClient,
+    pairs: Vec<(String, String)>,
+    prefix: Option<&str>,
+    ttl: Option<u64>,
     format: OutputFormat,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    match client.delete(key).await {
-        Ok(()) => println!(
-            "{}",
-            Formatter::format_success(&format!("Successfully deleted key: {}", key), format)
-        ),
-        Err(e) => {
-            eprintln!("{}", Formatter::format_error(&e.to_string(), format));
-            std::process::exit(1);
+    if pairs.is_empty() {
+        exit::fail(
+            "Give KEY=VALUE pairs or --env-file",
+            ErrorKind::Validation,
+            format,
+        );
+    }
+    // The last value given for a key wins, at the position it first had
+    let mut writes: Vec<BulkWrite> = Vec::new();
+    let mut positions = HashMap::new();
+    for (key, value) in pairs {
+        let mut write = BulkWrite::new(format!("{}{}", prefix.unwrap_or_default(), key), value);
+        write.expiration = ttl.map(Expiration::Ttl);
+        match positions.get(&write.key) {
+            Some(&position) => writes[position] = write,
+            None => {
+                positions.insert(write.key.clone(), writes.len());
+                writes.push(write);
+            }
         }
     }
 
+    if let Err(e) = client.put_bulk(&writes).await {
+        exit::fail_with(&e, format);
+    }
+    if matches!(format, OutputFormat::Gha) {
+        gha::set_outputs(&[("key_count", writes.len().to_string())])?;
+    }
+    println!(
+        "{}",
+        Formatter::format_success(&format!("Wrote {} keys", writes.len()), format)
+    );
     Ok(())
 }
 
-async fn handle_list(
-    client: &KvClient,
-    limit: u32,
-    cursor: Option<String>,
-    _metadata: bool,
+/// Purge the cached URLs of written keys named by purge hooks, after a
+/// write command succeeded
+async fn purge_after_write(
+    config: &ClientConfig,
+    hooks: &[config::PurgeHook],
+    keys: &[&str],
     format: OutputFormat,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let params = PaginationParams::new()
-        .with_limit(limit)
-        .with_cursor(cursor.unwrap_or_default());
-
-    match client.list(Some(params)).await {
-        Ok(response) => {
-            let keys: Vec<String> = response.keys.into_iter().map(|k| k.name).collect();
-
-            let output = match format {
-                OutputFormat::Json => serde_json::to_string_pretty(&serde_json::json!({
-                    "keys": keys,
-                    "list_complete": response.list_complete,
-                    "cursor": response.cursor
-                }))?,
-                OutputFormat::Yaml => serde_yaml::to_string(&serde_json::json!({
-                    "keys": keys,
-                    "list_complete": response.list_complete,
-                    "cursor": response.cursor
-                }))?,
-                OutputFormat::Text => {
-                    let mut output = String::new();
-                    for key in keys {
-                        output.push_str(&format!("{}\n", key));
-                    }
-                    output
-                }
-            };
+) {
+    let targets = purge::targets(hooks, keys);
+    if targets.is_empty() {
+        return;
+    }
+    let count = targets.len();
+    let zones = cloudflare_kv::ZoneClient::for_config(config);
+    match purge::purge(&zones, targets).await {
+        Ok(()) => eprintln!("Purged {} cached URL(s)", count),
+        Err(e) => exit::fail(
+            &format!("Written, but purging cached URLs failed: {}", e),
+            ErrorKind::of(&e),
+            format,
+        ),
+    }
+}
 
-            println!("{}", output);
-        }
+async fn handle_exists(client: &KvClient, key: &str, quiet: bool, format: OutputFormat) {
+    let info = match client.head(key).await {
+        Ok(info) => info,
         Err(e) => {
-            eprintln!("{}", Formatter::format_error(&e.to_string(), format));
-            std::process::exit(1);
+            // A missing key is not an error, so failures print their own
+            let kind = ErrorKind::of(&e);
+            if !quiet {
+                eprintln!(
+                    "{}",
+                    Formatter::format_failure(&e.to_string(), kind, format)
+                );
+            }
+            std::process::exit(kind.code());
         }
+    };
+
+    if !quiet {
+        let report = serde_json::json!({
+            "key": key,
+            "exists": info.is_some(),
+            "expiration": info.as_ref().and_then(|i| i.expiration),
+            "metadata": info.as_ref().and_then(|i| i.metadata.clone()),
+        });
+        let output = match (format, &info) {
+            (OutputFormat::Json, _) => serde_json::to_string_pretty(&report).unwrap_or_default(),
+            (OutputFormat::Yaml, _) => serde_yaml::to_string(&report).unwrap_or_default(),
+            (OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha, Some(_)) => {
+                format!("Key exists: {}", key)
+            }
+            (OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha, None) => {
+                format!("Key not found: {}", key)
+            }
+        };
+        println!("{}", output);
     }
 
-    Ok(())
+    if info.is_none() {
+        std::process::exit(ErrorKind::NotFound.code());
+    }
 }
 
-async fn handle_batch(
-    client: &KvClient,
-    command: BatchCommands,
+async fn handle_edit(
+    client: &PluginClient,
+    key: &str,
+    create: bool,
     format: OutputFormat,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    match command {
-        BatchCommands::Delete { keys } => {
-            let key_refs: Vec<&str> = keys.iter().map(|k: &String| k.as_str()).collect();
-            match client.batch_delete(key_refs).await {
-                Ok(()) => println!(
-                    "{}",
-                    Formatter::format_success("Batch delete successful", format)
-                ),
-                Err(e) => {
-                    eprintln!("{}", Formatter::format_error(&e.to_string(), format));
-                    std::process::exit(1);
-                }
+    let (original, previous) = match client.get(key).await {
+        Ok(Some(pair)) => (pair.value, client.client().head(key).await?),
+        Ok(None) if create => (String::new(), None),
+        Ok(None) => exit::fail(
+            &format!("Key not found: {} (use --create to start a new value)", key),
+            ErrorKind::NotFound,
+            format,
+        ),
+        Err(e) => exit::fail_with(&e, format),
+    };
+
+    let kind = edit::ValueKind::detect(&original);
+    let file = match edit::EditFile::create(key, kind) {
+        Ok(file) => file,
+        Err(e) => exit::fail_with(&e, format),
+    };
+    tracing::debug!("Editing {} in {}", key, file.path().display());
+
+    // Like git, leave Ctrl-C to the editor while it runs, so the temp file
+    // is always removed; afterwards it exits as usual
+    let editing = Arc::new(AtomicBool::new(true));
+    let interrupts = editing.clone();
+    tokio::spawn(async move {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            if !interrupts.load(Ordering::SeqCst) {
+                std::process::exit(ErrorKind::Interrupted.code());
             }
         }
-        BatchCommands::Import { file } => {
-            let _content = fs::read_to_string(&file)?;
-            // TODO: Parse JSON/YAML and import
-            println!(
-                "{}",
-                Formatter::format_text("Batch import coming soon", format)
-            );
-        }
-        BatchCommands::Export { output: _ } => {
-            // TODO: Export keys to file
-            println!(
-                "{}",
-                Formatter::format_text("Batch export coming soon", format)
-            );
+    });
+
+    let mut text = original.clone();
+    let edited = loop {
+        let edited = match file.edit(&edit::editor(), &text) {
+            Ok(edited) => edited,
+            Err(e) => {
+                drop(file);
+                exit::fail_with(&e, format)
+            }
+        };
+        match kind.validate(&edited) {
+            Ok(()) => break edited,
+            Err(e) if edit::ask_edit_again(&e)? => text = edited,
+            Err(e) => {
+                drop(file);
+                exit::fail(&e, ErrorKind::Validation, format)
+            }
         }
+    };
+    file.remove()?;
+    editing.store(false, Ordering::SeqCst);
+
+    if edited == original {
+        println!(
+            "{}",
+            Formatter::format_success(&format!("No changes to key: {}", key), format)
+        );
+        return Ok(());
     }
 
+    match client
+        .put_preserving(key, edited.as_bytes(), previous)
+        .await
+    {
+        Ok(()) => println!(
+            "{}",
+            Formatter::format_success(&format!("Successfully updated key: {}", key), format)
+        ),
+        Err(e) => exit::fail_with(&e, format),
+    }
+
+    Ok(())
+}
+
+struct RenameOptions {
+    prefix: bool,
+    keep_source: bool,
+    force: bool,
+    dry_run: bool,
+}
+
+async fn handle_rename(
+    client: &KvClient,
+    old: &str,
+    new: &str,
+    options: RenameOptions,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Pairs of (source, destination)
+    let moves: Vec<(String, String)> = if options.prefix {
+        match client.list_keys(old).await {
+            Ok(keys) => keys
+                .into_iter()
+                .map(|key| {
+                    let renamed = format!("{}{}", new, &key[old.len()..]);
+                    (key, renamed)
+                })
+                .filter(|(from, to)| from != to)
+                .collect(),
+            Err(e) => exit::fail_with(&e, format),
+        }
+    } else if old == new {
+        exit::fail(
+            "Source and destination are the same key",
+            ErrorKind::Validation,
+            format,
+        );
+    } else {
+        vec![(old.to_string(), new.to_string())]
+    };
+
+    if moves.is_empty() {
+        exit::fail(
+            &format!("No keys start with: {}", old),
+            ErrorKind::NotFound,
+            format,
+        );
+    }
+
+    // Moving onto a key that is itself moved later would overwrite it first
+    let sources: std::collections::HashSet<&str> =
+        moves.iter().map(|(from, _)| from.as_str()).collect();
+    if let Some((_, to)) = moves.iter().find(|(_, to)| sources.contains(to.as_str())) {
+        exit::fail(
+            &format!(
+                "Prefixes overlap: {} would be overwritten before it is moved",
+                to
+            ),
+            ErrorKind::Validation,
+            format,
+        );
+    }
+
+    if !options.force {
+        let existing = if options.prefix {
+            client.list_keys(new).await
+        } else {
+            client
+                .exists(new)
+                .await
+                .map(|exists| exists.then(|| new.to_string()).into_iter().collect())
+        };
+        let existing: std::collections::HashSet<String> = match existing {
+            Ok(keys) => keys.into_iter().collect(),
+            Err(e) => exit::fail_with(&e, format),
+        };
+        let conflicts: Vec<&str> = moves
+            .iter()
+            .map(|(_, to)| to.as_str())
+            .filter(|to| existing.contains(*to))
+            .collect();
+        if !conflicts.is_empty() {
+            exit::fail(
+                &format!(
+                    "Destination keys already exist (use --force to overwrite): {}",
+                    conflicts.join(", ")
+                ),
+                ErrorKind::Validation,
+                format,
+            );
+        }
+    }
+
+    let verb = if options.keep_source {
+        "Copied"
+    } else {
+        "Renamed"
+    };
+    for (from, to) in &moves {
+        if options.dry_run {
+            if let OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha = format {
+                println!("{} -> {}", from, to);
+            }
+            continue;
+        }
+
+        let result = if options.keep_source {
+            client.copy(from, to).await
+        } else {
+            client.rename(from, to).await
+        };
+        match result {
+            Ok(()) => {
+                if let OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha = format {
+                    println!("{} {} -> {}", verb, from, to);
+                }
+            }
+            Err(e) => exit::fail(
+                &format!("Failed to move {} to {}: {}", from, to, e),
+                ErrorKind::of(&e),
+                format,
+            ),
+        }
+    }
+
+    match format {
+        OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {}
+        _ => {
+            let message = if options.dry_run {
+                format!("Would move {} key(s)", moves.len())
+            } else {
+                format!("{} {} key(s)", verb, moves.len())
+            };
+            println!("{}", Formatter::format_success(&message, format));
+        }
+    }
+
+    Ok(())
+}
+
+/// Every key starting with `prefix`, following pagination
+async fn handle_patch(
+    client: &PluginClient,
+    key: &str,
+    patch: &str,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let result = match JsonPatch::parse(patch) {
+        Ok(patch) => client.modify_json(key, |value| patch.apply(value)).await,
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(_) => println!(
+            "{}",
+            Formatter::format_success(&format!("Successfully patched key: {}", key), format)
+        ),
+        Err(e) => exit::fail_with(&e, format),
+    }
+
+    Ok(())
+}
+
+async fn handle_meta(
+    client: &KvClient,
+    command: MetaCommands,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let parse = |json: &str| match serde_json::from_str::<serde_json::Value>(json) {
+        Ok(value) => value,
+        Err(e) => exit::fail(
+            &format!("Metadata is not valid JSON: {}", e),
+            ErrorKind::Validation,
+            format,
+        ),
+    };
+    let (key, result) = match command {
+        MetaCommands::Get { key } => {
+            let Some(info) = client.head(&key).await? else {
+                exit::fail(
+                    &format!("Key not found: {}", key),
+                    ErrorKind::NotFound,
+                    format,
+                );
+            };
+            let report = serde_json::json!({ "key": key, "metadata": info.metadata });
+            match format {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+                OutputFormat::Yaml => print!("{}", serde_yaml::to_string(&report)?),
+                OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
+                    println!("{}", serde_json::to_string_pretty(&report["metadata"])?)
+                }
+            }
+            return Ok(());
+        }
+        MetaCommands::Set { key, metadata } => {
+            let metadata = Some(parse(&metadata)).filter(|value| !value.is_null());
+            // Keep how plugins stored the value, or it could not be read back
+            let result = client
+                .modify_metadata(&key, |current| keep_tags(current.as_ref(), metadata))
+                .await;
+            (key, result.map(|_| ()))
+        }
+        MetaCommands::Merge { key, patch } => {
+            let patch = parse(&patch);
+            if !patch.is_object() {
+                exit::fail(
+                    "The merge patch must be a JSON object",
+                    ErrorKind::Validation,
+                    format,
+                );
+            }
+            let result = client
+                .modify_metadata(&key, |metadata| {
+                    let mut metadata = metadata.unwrap_or_else(|| serde_json::json!({}));
+                    json_patch::merge(&mut metadata, &patch);
+                    Ok(Some(metadata))
+                })
+                .await;
+            (key, result.map(|_| ()))
+        }
+    };
+
+    match result {
+        Ok(()) => println!(
+            "{}",
+            Formatter::format_success(&format!("Updated metadata of key: {}", key), format)
+        ),
+        Err(e) => exit::fail_with(&e, format),
+    }
+    Ok(())
+}
+
+async fn handle_tag(
+    client: &KvClient,
+    key: &str,
+    labels: Vec<(String, String)>,
+    remove: Vec<String>,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let result = client
+        .modify_metadata(key, |metadata| {
+            let mut metadata = match metadata.unwrap_or_else(|| serde_json::json!({})) {
+                serde_json::Value::Object(metadata) => metadata,
+                _ => exit::fail(
+                    &format!(
+                        "Metadata of key {} is not a JSON object; replace it with `cfkv meta set`",
+                        key
+                    ),
+                    ErrorKind::Validation,
+                    format,
+                ),
+            };
+            for name in &remove {
+                metadata.remove(name);
+            }
+            for (name, value) in &labels {
+                metadata.insert(name.clone(), value.clone().into());
+            }
+            Ok(Some(metadata.into()))
+        })
+        .await;
+
+    match result {
+        Ok(_) => {
+            let changes: Vec<String> = labels
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .chain(remove.iter().map(|name| format!("-{}", name)))
+                .collect();
+            println!(
+                "{}",
+                Formatter::format_success(
+                    &format!("Tagged key {}: {}", key, changes.join(" ")),
+                    format
+                )
+            );
+        }
+        Err(e) => exit::fail_with(&e, format),
+    }
+    Ok(())
+}
+
+async fn handle_append(
+    client: &PluginClient,
+    key: &str,
+    value: Option<String>,
+    file: Option<std::path::PathBuf>,
+    newline: bool,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut bytes = if let Some(file_path) = file {
+        fs::read(&file_path)?
+    } else if let Some(val) = value {
+        val.into_bytes()
+    } else {
+        exit::fail(
+            "Either --value or --file must be provided",
+            ErrorKind::Validation,
+            format,
+        );
+    };
+    if newline {
+        bytes.push(b'\n');
+    }
+
+    match client.append(key, &bytes).await {
+        Ok(()) => println!(
+            "{}",
+            Formatter::format_success(&format!("Successfully appended to key: {}", key), format)
+        ),
+        Err(e) => exit::fail_with(&e, format),
+    }
+
+    Ok(())
+}
+
+async fn handle_increment(
+    client: &PluginClient,
+    key: &str,
+    delta: cloudflare_kv::Result<CounterValue>,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let result = match delta {
+        Ok(delta) => client.increment(key, delta).await,
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(value) => {
+            let report = serde_json::json!({ "key": key, "value": value });
+            let output = match format {
+                OutputFormat::Json => serde_json::to_string(&report)?,
+                OutputFormat::Yaml => serde_yaml::to_string(&report)?,
+                OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => value.to_string(),
+            };
+            println!("{}", output);
+        }
+        Err(e) => exit::fail_with(&e, format),
+    }
+
+    Ok(())
+}
+
+/// The on-disk read cache of the client's namespace
+fn disk_cache(client: &KvClient, ttl: Duration) -> Result<ReadCache, Box<dyn std::error::Error>> {
+    let options = CacheOptions::new(ttl).with_dir(config::Config::cache_dir()?);
+    Ok(ReadCache::for_client(options, client))
+}
+
+async fn handle_delete(
+    client: &KvClient,
+    key: &str,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match client.delete(key).await {
+        Ok(()) => println!(
+            "{}",
+            Formatter::format_success(&format!("Successfully deleted key: {}", key), format)
+        ),
+        Err(e) => exit::fail_with(&e, format),
+    }
+
+    Ok(())
+}
+
+/// Delete every key under `prefix` after showing the count and confirming
+async fn handle_delete_prefix(
+    client: &KvClient,
+    prefix: &str,
+    confirm: Confirm,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if prefix.is_empty() {
+        exit::fail("--prefix must not be empty", ErrorKind::Validation, format);
+    }
+    let keys = match client.list_keys(prefix).await {
+        Ok(keys) => keys,
+        Err(e) => exit::fail_with(&e, format),
+    };
+    if keys.is_empty() {
+        println!(
+            "{}",
+            Formatter::format_success(&format!("No keys start with {}", prefix), format)
+        );
+        return Ok(());
+    }
+
+    if confirm.needs_yes() {
+        exit::fail(
+            &format!(
+                "{} key(s) start with {}; pass --yes to delete them without a prompt",
+                keys.len(),
+                prefix
+            ),
+            ErrorKind::Validation,
+            format,
+        );
+    }
+    let question = format!("Delete {} key(s) starting with {}?", keys.len(), prefix);
+    confirm.ask(&question, &keys, format)?;
+
+    match client.delete_keys(&keys).await {
+        Ok(()) => println!(
+            "{}",
+            Formatter::format_success(
+                &format!("Deleted {} key(s) starting with {}", keys.len(), prefix),
+                format
+            )
+        ),
+        Err(e) => exit::fail_with(&e, format),
+    }
+    Ok(())
+}
+
+/// What `cfkv list` shows of the listed keys
+struct ListView {
+    metadata: bool,
+    /// Key objects instead of names in JSON and YAML
+    detailed: bool,
+    filter: Option<filter::Filter>,
+    columns: Vec<table::Column>,
+}
+
+async fn handle_list(
+    client: &KvClient,
+    limit: u32,
+    cursor: Option<String>,
+    view: ListView,
+    redactor: &Redactor,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let ListView {
+        metadata,
+        detailed,
+        filter,
+        columns,
+    } = view;
+    if !columns.is_empty() && !matches!(format, OutputFormat::Table) {
+        exit::fail(
+            "--columns only applies to --format table",
+            ErrorKind::Validation,
+            format,
+        );
+    }
+    let params = PaginationParams::new()
+        .with_limit(limit)
+        .with_cursor(cursor.unwrap_or_default());
+
+    let listed = client.list(Some(params)).await.map(|mut response| {
+        if let Some(filter) = &filter {
+            response.keys.retain(|key| filter.matches(key));
+        }
+        response
+    });
+    match listed {
+        Ok(response) if matches!(format, OutputFormat::Table) => {
+            use futures::stream::{self, StreamExt, TryStreamExt};
+
+            let columns = if columns.is_empty() {
+                table::DEFAULT_COLUMNS.to_vec()
+            } else {
+                columns
+            };
+            // Sizes need one GET per key, so only fetch them when asked for
+            let sizes: Vec<Option<u64>> = if columns.contains(&table::Column::Size) {
+                stream::iter(response.keys.iter())
+                    .map(|key| client.value_size(&key.name))
+                    .buffered(8)
+                    .try_collect()
+                    .await?
+            } else {
+                vec![]
+            };
+
+            let keys: Vec<_> = response
+                .keys
+                .iter()
+                .cloned()
+                .map(|mut key| {
+                    if redactor.is_sensitive(&key.name) {
+                        key.name.push_str(" [sensitive]");
+                    }
+                    key
+                })
+                .collect();
+            println!(
+                "{}",
+                table::render_keys(&keys, &sizes, &columns, chrono::Utc::now())
+            );
+            if let Some(cursor) = response.cursor.filter(|_| !response.list_complete) {
+                println!("Next cursor: {}", cursor);
+            }
+        }
+        Ok(response) => {
+            let keys: serde_json::Value = if detailed {
+                serde_json::to_value(&response.keys)?
+            } else {
+                response.keys.iter().map(|k| k.name.clone()).collect()
+            };
+
+            let sensitive: Vec<&str> = response
+                .keys
+                .iter()
+                .map(|k| k.name.as_str())
+                .filter(|name| redactor.is_sensitive(name))
+                .collect();
+            let mut object = serde_json::json!({
+                "keys": keys,
+                "list_complete": response.list_complete,
+                "cursor": response.cursor
+            });
+            if !sensitive.is_empty() {
+                object["sensitive"] = serde_json::json!(sensitive);
+            }
+
+            let output = match format {
+                OutputFormat::Json => serde_json::to_string_pretty(&object)?,
+                OutputFormat::Yaml => serde_yaml::to_string(&object)?,
+                OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
+                    let mut output = String::new();
+                    for key in &response.keys {
+                        output.push_str(&key.name);
+                        if redactor.is_sensitive(&key.name) {
+                            output.push_str(&format!(" {}", Formatter::dim("[sensitive]")));
+                        }
+                        if let (Some(meta), true) = (&key.metadata, metadata) {
+                            output.push_str(&format!("  {}", Formatter::dim(&meta.to_string())));
+                        }
+                        output.push('\n');
+                    }
+                    output
+                }
+            };
+
+            println!("{}", output);
+            if matches!(format, OutputFormat::Gha) {
+                gha::set_outputs(&[
+                    ("key_count", response.keys.len().to_string()),
+                    ("list_complete", response.list_complete.to_string()),
+                    ("cursor", response.cursor.unwrap_or_default()),
+                ])?;
+            }
+        }
+        Err(e) => exit::fail_with(&e, format),
+    }
+
+    Ok(())
+}
+
+/// What `grep` prints for each match
+struct GrepOutput {
+    keys_only: bool,
+    redactor: Redactor,
+}
+
+async fn handle_grep(
+    client: &PluginClient,
+    matcher: &grep::ValueMatcher,
+    prefix: Option<String>,
+    output: GrepOutput,
+    concurrency: usize,
+    progress: &Progress,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use futures::stream::{self, StreamExt};
+
+    let mut found = Vec::new();
+    let mut cursor: Option<String> = None;
+    let interrupted = interrupt::watch();
+    let mut searched_all = false;
+
+    while !interrupted.is_cancelled() {
+        let mut params = PaginationParams::new().with_limit(1000);
+        if let Some(prefix) = &prefix {
+            params = params.with_prefix(prefix.clone());
+        }
+        if let Some(cursor) = cursor.take() {
+            params = params.with_cursor(cursor);
+        }
+        let page = match client.client().list(Some(params)).await {
+            Ok(page) => page,
+            Err(e) => {
+                progress.clear();
+                exit::fail_with(&e, format)
+            }
+        };
+
+        let mut values = stream::iter(page.keys.iter())
+            .map(|key| async move { (key.name.as_str(), client.get(&key.name).await) })
+            .buffered(concurrency.max(1));
+
+        while let Some((key, value)) = values.next().await {
+            let value = match value {
+                Ok(Some(pair)) => pair.value,
+                // Deleted since it was listed
+                Ok(None) => {
+                    progress.inc(0);
+                    continue;
+                }
+                Err(e) => {
+                    // One unreadable value should not stop the search
+                    let message = format!("Skipping {}: {}", key, e);
+                    progress.eprintln(&Formatter::format_failure(
+                        &message,
+                        ErrorKind::of(&e),
+                        format,
+                    ));
+                    progress.inc(0);
+                    continue;
+                }
+            };
+            progress.inc(value.len() as u64);
+
+            let mut matches = matcher.find(&value);
+            if matches.is_empty() {
+                continue;
+            }
+            // Snippets would leak the secret they matched
+            if output.redactor.masks(key) {
+                matches = vec![redact::MASK.to_string()];
+            }
+
+            // Text output is printed as it is found, like grep
+            if let OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha = format {
+                if output.keys_only {
+                    progress.println(key);
+                } else {
+                    for snippet in &matches {
+                        progress.println(&format!("{}: {}", key, snippet));
+                    }
+                }
+            }
+            found.push(grep::GrepMatch {
+                key: key.to_string(),
+                matches: if output.keys_only { vec![] } else { matches },
+            });
+        }
+
+        match page.cursor {
+            Some(next) if !page.list_complete && !next.is_empty() => cursor = Some(next),
+            _ => {
+                searched_all = true;
+                break;
+            }
+        }
+    }
+    progress.clear();
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&found)?),
+        OutputFormat::Yaml => println!("{}", serde_yaml::to_string(&found)?),
+        OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {}
+    }
+
+    if !searched_all {
+        interrupt::stopped(
+            &format!(
+                "Search interrupted; {} matching keys found so far",
+                found.len()
+            ),
+            format,
+        );
+    }
+    // Like grep, a search without matches fails, with the "not found" code
+    if found.is_empty() {
+        std::process::exit(ErrorKind::NotFound.code());
+    }
+    Ok(())
+}
+
+fn handle_stats(
+    stats: &NamespaceStats,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let output = match format {
+        OutputFormat::Json => serde_json::to_string_pretty(&stats)?,
+        OutputFormat::Yaml => serde_yaml::to_string(&stats)?,
+        OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
+            let mut output = format!(
+                "Keys:             {}\nWith expiration:  {}\nWith metadata:    {}\n",
+                stats.key_count, stats.keys_with_expiration, stats.keys_with_metadata
+            );
+            if let Some(sizes) = &stats.sizes {
+                output.push_str(&format!(
+                    "Total size:       {}\nAverage size:     {}\nLargest value:    {} {}\n",
+                    Formatter::human_size(sizes.total),
+                    Formatter::human_size(sizes.average),
+                    Formatter::human_size(sizes.max),
+                    Formatter::dim(&format!(
+                        "({})",
+                        sizes.largest_key.as_deref().unwrap_or("-")
+                    ))
+                ));
+            }
+            if !stats.prefixes.is_empty() {
+                let width = stats.prefixes.keys().map(|p| p.len()).max().unwrap_or(0);
+                output.push_str("\nKeys by prefix:\n");
+                for (prefix, count) in &stats.prefixes {
+                    output.push_str(&format!("  {:width$}  {}\n", prefix, count));
+                }
+            }
+            output
+        }
+    };
+
+    println!("{}", output);
+    Ok(())
+}
+
+fn handle_tree(
+    root: &tree::TreeNode,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let output = match format {
+        OutputFormat::Json => serde_json::to_string_pretty(root)?,
+        OutputFormat::Yaml => serde_yaml::to_string(root)?,
+        OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => tree::render(root),
+    };
+    println!("{}", output.trim_end());
+    Ok(())
+}
+
+async fn handle_complete_keys(client: &KvClient, prefix: &str) {
+    // Completion must never print errors into the user's prompt
+    let params = PaginationParams::new().with_limit(1000).with_prefix(prefix);
+    if let Ok(response) = client.list(Some(params)).await {
+        for key in response.keys {
+            println!("{}", key.name);
+        }
+    }
+}
+
+async fn handle_batch(
+    client: &PluginClient,
+    command: BatchCommands,
+    show_progress: bool,
+    confirm: Confirm,
+    redactor: &Redactor,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        BatchCommands::Delete { keys } => {
+            confirm.ask(&format!("Delete {} key(s)?", keys.len()), &keys, format)?;
+            let key_refs: Vec<&str> = keys.iter().map(|k: &String| k.as_str()).collect();
+            match client.client().batch_delete(key_refs).await {
+                Ok(()) => println!(
+                    "{}",
+                    Formatter::format_success("Batch delete successful", format)
+                ),
+                Err(e) => exit::fail_with(&e, format),
+            }
+        }
+        BatchCommands::Import {
+            file,
+            chunk_size,
+            continue_on_error,
+            retry_file,
+            key_prefix,
+            key_map,
+            transform,
+            resume,
+            ..
+        } => {
+            let mut records = batch::read_import_file(&file)?;
+            for record in &mut records {
+                for rewrite in key_prefix.iter().chain(&key_map) {
+                    record.key = rewrite.apply(&record.key);
+                }
+                let Some(transform) = &transform else {
+                    continue;
+                };
+                if record.base64 {
+                    exit::fail(
+                        &format!("Cannot transform the binary value of key {}", record.key),
+                        ErrorKind::Validation,
+                        format,
+                    );
+                }
+                match transform.apply_to_text(&record.value) {
+                    Ok(value) => record.value = value,
+                    Err(e) => exit::fail(
+                        &format!("Failed to transform key {}: {}", record.key, e),
+                        ErrorKind::Validation,
+                        format,
+                    ),
+                }
+            }
+            let batch = batch::to_batch(&records)?;
+
+            let (checkpoint_path, mut state) = match resume {
+                Some(path) => match read_checkpoint(&path, format) {
+                    Checkpoint::Import(state) if state.file == file => (path, state),
+                    _ => exit::fail(
+                        &format!("'{}' is not a checkpoint of this import", path.display()),
+                        ErrorKind::Validation,
+                        format,
+                    ),
+                },
+                None => (
+                    Checkpoint::path_for(&file),
+                    ImportCheckpoint::new(&file, chunk_size),
+                ),
+            };
+            // Skip whole chunks, so the rest keep the boundaries they were
+            // hashed with; a resumed import keeps its first run's chunk size
+            let (pending, hashes): (Vec<_>, Vec<_>) = batch
+                .operations()
+                .chunks(state.chunk_size.max(1))
+                .map(|chunk| (chunk, checkpoint::chunk_hash(chunk)))
+                .filter(|(_, hash)| !state.completed_chunks.contains(hash))
+                .unzip();
+            let imported_before = batch.len() - pending.iter().map(|c| c.len()).sum::<usize>();
+            let batch: BatchBuilder = pending.concat().into_iter().collect();
+            state.write(&checkpoint_path)?;
+
+            let sizes: HashMap<&str, u64> = batch
+                .operations()
+                .iter()
+                .filter_map(|operation| match operation {
+                    BatchOperation::Put { key, value, .. } => {
+                        Some((key.as_str(), value.len() as u64))
+                    }
+                    BatchOperation::Delete { .. } => None,
+                })
+                .collect();
+            let progress = Progress::new("Importing", Some(batch.len() as u64), show_progress);
+            let options = BatchOptions::new()
+                .with_chunk_size(state.chunk_size)
+                .with_continue_on_error(continue_on_error)
+                .with_cancellation(interrupt::watch());
+
+            let report = batch
+                .execute(client, &options, |chunk| {
+                    if chunk.failed.is_empty() {
+                        state.completed_chunks.insert(hashes[chunk.index].clone());
+                        if let Err(e) = state.write(&checkpoint_path) {
+                            progress.eprintln(&format!(
+                                "Failed to update checkpoint {}: {}",
+                                checkpoint_path.display(),
+                                e
+                            ));
+                        }
+                    }
+                    for key in &chunk.succeeded {
+                        progress.inc(sizes.get(key.as_str()).copied().unwrap_or_default());
+                    }
+                    for failed in &chunk.failed {
+                        progress.inc(0);
+                        progress.eprintln(&format!(
+                            "Failed to import key {}: {}",
+                            failed.operation.key(),
+                            failed.error
+                        ));
+                    }
+                })
+                .await;
+            progress.clear();
+
+            if let Some(first) = report.failed().next() {
+                let mut message = format!(
+                    "Imported {} of {} keys from '{}'; {} failed",
+                    report.succeeded(),
+                    batch.len(),
+                    file.display(),
+                    report.failed().count()
+                );
+                if !report.skipped.is_empty() {
+                    message.push_str(&format!(
+                        " and {} were not attempted (use --continue-on-error to import them anyway)",
+                        report.skipped.len()
+                    ));
+                }
+                if let Some(path) = &retry_file {
+                    let retry = report.retry_batch().bulk_write_body();
+                    fs::write(path, serde_json::to_string_pretty(&retry)?)?;
+                    message.push_str(&format!(
+                        ". Import the rest with: cfkv batch import {}",
+                        path.display()
+                    ));
+                }
+                message.push_str(&format!(
+                    ". Resume with: cfkv batch import {} --resume {}",
+                    file.display(),
+                    checkpoint_path.display()
+                ));
+                if matches!(format, OutputFormat::Gha) {
+                    gha::set_outputs(&[
+                        ("key_count", report.succeeded().to_string()),
+                        ("failed_count", report.failed().count().to_string()),
+                    ])?;
+                }
+                exit::fail(&message, ErrorKind::of(&first.error), format);
+            }
+            if report.cancelled {
+                interrupt::stopped(
+                    &format!(
+                        "Import interrupted after {} of {} keys. Resume with: cfkv batch import {} --resume {}",
+                        report.succeeded(),
+                        batch.len(),
+                        file.display(),
+                        checkpoint_path.display()
+                    ),
+                    format,
+                );
+            }
+            if matches!(format, OutputFormat::Gha) {
+                gha::set_outputs(&[
+                    ("key_count", batch.len().to_string()),
+                    ("failed_count", "0".to_string()),
+                ])?;
+            }
+            checkpoint::remove(&checkpoint_path, None)?;
+
+            let mut message = format!(
+                "Imported {} keys ({}) from '{}'",
+                batch.len(),
+                Formatter::human_size(progress.bytes()),
+                file.display()
+            );
+            if imported_before > 0 {
+                message.push_str(&format!("; {} keys were imported before", imported_before));
+            }
+            println!("{}", Formatter::format_success(&message, format));
+        }
+        BatchCommands::Export {
+            output,
+            prefix,
+            pattern,
+            min_size,
+            max_size,
+            modified_after,
+            resume,
+            concurrency,
+            unordered,
+        } => {
+            use futures::channel::mpsc;
+            use futures::stream::{self, StreamExt};
+            use futures::SinkExt;
+
+            let pattern = match pattern.as_deref().map(regex::Regex::new).transpose() {
+                Ok(pattern) => pattern,
+                Err(e) => exit::fail(
+                    &format!("Invalid --match pattern: {}", e),
+                    ErrorKind::Validation,
+                    format,
+                ),
+            };
+            let baseline = match modified_after.as_deref().map(verify::Manifest::read) {
+                Some(Ok(manifest)) => Some(manifest),
+                Some(Err(e)) => exit::fail(
+                    &format!(
+                        "Failed to read manifest '{}': {}",
+                        modified_after.unwrap_or_default().display(),
+                        e
+                    ),
+                    ErrorKind::of(&e),
+                    format,
+                ),
+                None => None,
+            };
+            let filter = batch::ExportFilter {
+                pattern,
+                min_size,
+                max_size,
+                baseline,
+            };
+            let (checkpoint_path, mut state) = match resume {
+                Some(path) => match read_checkpoint(&path, format) {
+                    Checkpoint::Export(state)
+                        if state.output == output && state.prefix == prefix =>
+                    {
+                        (path, state)
+                    }
+                    _ => exit::fail(
+                        &format!("'{}' is not a checkpoint of this export", path.display()),
+                        ErrorKind::Validation,
+                        format,
+                    ),
+                },
+                None => (
+                    Checkpoint::path_for(&output),
+                    ExportCheckpoint::new(&output, prefix.clone()),
+                ),
+            };
+            let resume_hint = format!(
+                "Resume with: cfkv batch export {} --resume {}",
+                output.display(),
+                checkpoint_path.display()
+            );
+            let mut partial = state.open_partial()?;
+
+            let progress = Progress::new("Exporting", None, show_progress);
+            let interrupted = interrupt::watch();
+            // Pages are listed while the values of the previous one are
+            // fetched; the channel holds a single page, so listing stays at
+            // most one page ahead of the fetches
+            let (mut pages_tx, mut pages_rx) = mpsc::channel(1);
+            let mut cursor = state.cursor.clone();
+            let mut listed = state.is_listed();
+            let producer = async {
+                while !listed && !interrupted.is_cancelled() {
+                    let mut params = PaginationParams::new().with_limit(1000);
+                    if let Some(prefix) = &prefix {
+                        params = params.with_prefix(prefix.clone());
+                    }
+                    if let Some(cursor) = cursor.take() {
+                        params = params.with_cursor(cursor);
+                    }
+                    let page = match client.client().list(Some(params)).await {
+                        Ok(page) => page,
+                        Err(e) => {
+                            progress.clear();
+                            exit::fail(
+                                &format!("{}. {}", e, resume_hint),
+                                ErrorKind::of(&e),
+                                format,
+                            )
+                        }
+                    };
+                    cursor = match page.cursor {
+                        Some(next) if !page.list_complete && !next.is_empty() => Some(next),
+                        _ => None,
+                    };
+                    listed = cursor.is_none();
+                    if pages_tx.send((page.keys, cursor.clone())).await.is_err() {
+                        break;
+                    }
+                }
+                // Dropping the sender ends the consumer's loop
+                drop(pages_tx);
+            };
+
+            let consumer = async {
+                while let Some((keys, cursor)) = pages_rx.next().await {
+                    let (wanted, unwanted): (Vec<_>, Vec<_>) =
+                        keys.iter().partition(|key| filter.matches_key(&key.name));
+                    let mut skipped = unwanted.len();
+                    let fetches = stream::iter(wanted)
+                        .map(|key| async move { (key, client.get_bytes(&key.name).await) });
+                    let mut values = if unordered {
+                        fetches.buffer_unordered(concurrency.max(1)).boxed_local()
+                    } else {
+                        fetches.buffered(concurrency.max(1)).boxed_local()
+                    };
+
+                    let mut batch = BatchBuilder::new();
+                    let mut bytes = 0;
+                    while let Some((key, value)) = values.next().await {
+                        let value = match value {
+                            Ok(Some(value)) => value,
+                            // Deleted since it was listed
+                            Ok(None) => continue,
+                            Err(e) => {
+                                progress.clear();
+                                exit::fail(
+                                    &format!(
+                                        "Failed to export key {}: {}. {}",
+                                        key.name, e, resume_hint
+                                    ),
+                                    ErrorKind::of(&e),
+                                    format,
+                                );
+                            }
+                        };
+                        if !filter.matches_value(&key.name, &value) {
+                            skipped += 1;
+                            continue;
+                        }
+                        progress.inc(value.len() as u64);
+                        bytes += value.len() as u64;
+                        batch = batch.put_with_options(
+                            &key.name,
+                            value,
+                            None,
+                            key.expiration,
+                            key.metadata.clone(),
+                            false,
+                        );
+                    }
+                    drop(values);
+
+                    let records: Vec<_> = batch
+                        .operations()
+                        .iter()
+                        .filter_map(BatchOperation::to_bulk_item)
+                        .collect();
+                    state.append_page(&mut partial, &records, cursor)?;
+                    state.exported += batch.len();
+                    state.skipped += skipped;
+                    state.bytes += bytes;
+                    state.write(&checkpoint_path)?;
+                    // The checkpoint covers every page written so far
+                    if interrupted.is_cancelled() {
+                        pages_rx.close();
+                        break;
+                    }
+                }
+                Ok::<_, cloudflare_kv::KvError>(())
+            };
+            let ((), result) = tokio::join!(producer, consumer);
+            result?;
+            progress.clear();
+            if !state.is_listed() {
+                interrupt::stopped(
+                    &format!(
+                        "Export interrupted after {} keys. {}",
+                        state.exported, resume_hint
+                    ),
+                    format,
+                );
+            }
+
+            let batch = batch::to_batch(&state.read_partial()?)?;
+            let yaml = batch::is_yaml_path(&output);
+            let content = batch::export_content(&batch, yaml, |key| redactor.is_sensitive(key))?;
+            fs::write(&output, content)?;
+            checkpoint::remove(&checkpoint_path, Some(&state.partial_path()))?;
+            let sensitive = batch
+                .operations()
+                .iter()
+                .filter(|operation| match operation {
+                    BatchOperation::Put { key, .. } => redactor.is_sensitive(key),
+                    _ => false,
+                })
+                .count();
+            if sensitive > 0 {
+                eprintln!(
+                    "{}",
+                    Formatter::dim(&format!(
+                        "Note: {} sensitive key(s) were exported in plaintext",
+                        sensitive
+                    ))
+                );
+            }
+            if matches!(format, OutputFormat::Gha) {
+                gha::set_outputs(&[("key_count", batch.len().to_string())])?;
+            }
+            let mut message = format!(
+                "Exported {} keys ({}) to '{}'",
+                batch.len(),
+                Formatter::human_size(state.bytes),
+                output.display()
+            );
+            if filter.is_active() {
+                message.push_str(&format!(
+                    "; {} keys did not match the filters",
+                    state.skipped
+                ));
+            }
+            println!("{}", Formatter::format_success(&message, format));
+        }
+    }
+
+    Ok(())
+}
+
+/// Read a `--resume` checkpoint, failing with its path in the message
+fn read_checkpoint(path: &Path, format: OutputFormat) -> Checkpoint {
+    Checkpoint::read(path).unwrap_or_else(|e| {
+        exit::fail(
+            &format!("Failed to read checkpoint '{}': {}", path.display(), e),
+            ErrorKind::of(&e),
+            format,
+        )
+    })
+}
+
+async fn handle_config_command(
+    command: ConfigCommands,
+    config: &config::Config,
+    config_path: &Path,
+    confirm: Confirm,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        ConfigCommands::SetToken { token } => {
+            let mut new_config = config.clone();
+            new_config.api_token = Some(token);
+            new_config.save(config_path)?;
+            println!("{}", Formatter::format_success("API token saved", format));
+        }
+        ConfigCommands::SetAccount { account_id } => {
+            let mut new_config = config.clone();
+            new_config.account_id = Some(account_id);
+            new_config.save(config_path)?;
+            println!("{}", Formatter::format_success("Account ID saved", format));
+        }
+        ConfigCommands::SetNamespace { namespace_id } => {
+            let mut new_config = config.clone();
+            new_config.namespace_id = Some(namespace_id);
+            new_config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success("Namespace ID saved", format)
+            );
+        }
+        ConfigCommands::Show => {
+            let output = match format {
+                OutputFormat::Json => serde_json::to_string_pretty(config)?,
+                OutputFormat::Yaml => serde_yaml::to_string(config)?,
+                OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
+                    format!(
+                        "Account ID: {}\nNamespace ID: {}\nAPI Token: {}",
+                        config.account_id.as_deref().unwrap_or("Not set"),
+                        config.namespace_id.as_deref().unwrap_or("Not set"),
+                        if config.api_token.is_some() {
+                            "***"
+                        } else {
+                            "Not set"
+                        }
+                    )
+                }
+            };
+            println!("{}", output);
+        }
+        ConfigCommands::Reset => {
+            let mut targets: Vec<String> = config
+                .storages
+                .keys()
+                .map(|name| format!("storage {}", name))
+                .collect();
+            targets.sort();
+            let question = format!(
+                "Reset {}, losing every storage and setting?",
+                config_path.display()
+            );
+            confirm.ask(&question, &targets, format)?;
+            let new_config = config::Config {
+                passphrase: config.passphrase.clone(),
+                ..Default::default()
+            };
+            new_config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success("Configuration reset", format)
+            );
+        }
+        ConfigCommands::Encrypt => {
+            let mut new_config = config.clone();
+            new_config.passphrase = Some(encryption::read_new_passphrase()?);
+            new_config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success("Configuration encrypted", format)
+            );
+        }
+        ConfigCommands::SetConfirm { enabled } => {
+            let mut new_config = config.clone();
+            new_config.confirm_destructive = Some(enabled);
+            new_config.save(config_path)?;
+            let message = if enabled {
+                "Destructive commands will ask for confirmation"
+            } else {
+                "Destructive commands will no longer ask for confirmation"
+            };
+            println!("{}", Formatter::format_success(message, format));
+        }
+        ConfigCommands::Convert { to } => {
+            if config::ConfigFormat::from_path(config_path) == to {
+                println!(
+                    "{}",
+                    Formatter::format_text(
+                        &format!("Configuration is already in {} format", to.extension()),
+                        format
+                    )
+                );
+                return Ok(());
+            }
+
+            // The original stays where it is: a --config path or
+            // CF_KV_CONFIG pointing at it must not start reading an empty
+            // config
+            let target = config_path.with_extension(to.extension());
+            config.save(&target)?;
+            println!(
+                "{}",
+                Formatter::format_success(
+                    &format!(
+                        "Configuration converted to '{}'; '{}' is unchanged, remove it once nothing points at it",
+                        target.display(),
+                        config_path.display()
+                    ),
+                    format
+                )
+            );
+        }
+        ConfigCommands::Validate | ConfigCommands::Repair => unreachable!(),
+        ConfigCommands::Decrypt => {
+            let mut new_config = config.clone();
+            new_config.passphrase = None;
+            new_config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success("Configuration decrypted", format)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_config_validate(
+    config_path: &Path,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !config_path.exists() {
+        exit::fail(
+            &format!("No config file at '{}'", config_path.display()),
+            ErrorKind::NotFound,
+            format,
+        );
+    }
+    let validation = config::validate(config_path)?;
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&validation)?),
+        OutputFormat::Yaml => println!("{}", serde_yaml::to_string(&validation)?),
+        OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
+            if validation.problems.is_empty() {
+                let mut message = format!(
+                    "'{}' is valid (schema version {})",
+                    config_path.display(),
+                    validation.schema_version
+                );
+                if validation.schema_version < config::SCHEMA_VERSION {
+                    message.push_str(&format!(
+                        "; it will be migrated to version {} on next use",
+                        config::SCHEMA_VERSION
+                    ));
+                }
+                println!("{}", Formatter::format_success(&message, format));
+            } else {
+                for problem in &validation.problems {
+                    println!("  - {}", problem);
+                }
+            }
+        }
+    }
+
+    if !validation.problems.is_empty() {
+        exit::fail(
+            &format!(
+                "{} problem(s) in '{}'",
+                validation.problems.len(),
+                config_path.display()
+            ),
+            ErrorKind::Validation,
+            format,
+        );
+    }
+    Ok(())
+}
+
+fn handle_config_repair(
+    config_path: &Path,
+    yes: bool,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !config_path.exists() {
+        exit::fail(
+            &format!("No config file at '{}'", config_path.display()),
+            ErrorKind::NotFound,
+            format,
+        );
+    }
+    let content = fs::read_to_string(config_path)?;
+    let (mut config, dropped) = config::salvage(config_path, &content)?;
+    if dropped.is_empty() {
+        println!(
+            "{}",
+            Formatter::format_text(
+                &format!(
+                    "'{}' reads without errors; nothing to repair",
+                    config_path.display()
+                ),
+                format
+            )
+        );
+        return Ok(());
+    }
+
+    let confirm = Confirm::new(yes, config.confirms_destructive());
+    let question = format!("Rewrite {} without the parts above?", config_path.display());
+    confirm.ask(&question, &dropped, format)?;
+    config.migrate()?;
+    // Saving keeps the damaged file as a .bak
+    config.save(config_path)?;
+    println!(
+        "{}",
+        Formatter::format_success(
+            &format!(
+                "Repaired '{}', leaving out {} damaged part(s); the damaged file is kept as '{}.bak'",
+                config_path.display(),
+                dropped.len(),
+                config_path.display()
+            ),
+            format
+        )
+    );
+    if matches!(format, OutputFormat::Text) {
+        for part in &dropped {
+            println!("  - {}", part);
+        }
+    }
+    Ok(())
+}
+
+async fn handle_storage_command(
+    command: StorageCommands,
+    config: &mut config::Config,
+    config_path: &Path,
+    storage_override: Option<&str>,
+    confirm: Confirm,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        StorageCommands::Add {
+            name,
+            account_id,
+            namespace_id,
+            api_token,
+            api_token_cmd,
+            account,
+            read_only,
+            key_prefix,
+            r2_bucket,
+            api_base_url,
+            headers,
+        } => {
+            if let Some(account) = account.as_deref() {
+                if !config.accounts.contains_key(account) {
+                    exit::fail(
+                        &format!("Account '{}' not found", account),
+                        ErrorKind::NotFound,
+                        format,
+                    );
+                }
+            }
+            config.add_storage(
+                name.clone(),
+                account_id.unwrap_or_default(),
+                namespace_id,
+                api_token.unwrap_or_default(),
+            );
+            config.set_storage_account(&name, account)?;
+            if api_token_cmd.is_some() {
+                config.set_api_token_cmd(&name, api_token_cmd)?;
+            }
+            config.set_read_only(&name, read_only)?;
+            config.set_key_prefix(&name, key_prefix)?;
+            config.set_r2_bucket(&name, r2_bucket)?;
+            config.set_api_base_url(&name, api_base_url)?;
+            for (header, value) in headers {
+                config.set_header(&name, &header, Some(value))?;
+            }
+            config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success(&format!("Storage '{}' added", name), format)
+            );
+        }
+        StorageCommands::List => {
+            let storages = config.list_storages();
+            if storages.is_empty() {
+                println!(
+                    "{}",
+                    Formatter::format_text("No storages configured", format)
+                );
+                return Ok(());
+            }
+
+            match format {
+                OutputFormat::Json => {
+                    let storage_list: Vec<serde_json::Value> = storages
+                        .iter()
+                        .map(|name| {
+                            let storage = config.get_storage(name).unwrap();
+                            let is_active = config.active_storage.as_deref() == Some(name);
+                            serde_json::json!({
+                                "name": storage.name,
+                                "account_id": storage.account_id,
+                                "namespace_id": storage.namespace_id,
+                                "active": is_active,
+                                "read_only": storage.read_only,
+                            })
+                        })
+                        .collect();
+                    println!("{}", serde_json::to_string_pretty(&storage_list)?);
+                }
+                OutputFormat::Yaml => {
+                    let storage_list: Vec<serde_json::Value> = storages
+                        .iter()
+                        .map(|name| {
+                            let storage = config.get_storage(name).unwrap();
+                            let is_active = config.active_storage.as_deref() == Some(name);
+                            serde_json::json!({
+                                "name": storage.name,
+                                "account_id": storage.account_id,
+                                "namespace_id": storage.namespace_id,
+                                "active": is_active,
+                                "read_only": storage.read_only,
+                            })
+                        })
+                        .collect();
+                    println!("{}", serde_yaml::to_string(&storage_list)?);
+                }
+                OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
+                    println!("Available storages:\n");
+                    for name in storages {
+                        let storage = config.get_storage(name).unwrap();
+                        let is_active = config.active_storage.as_deref() == Some(name);
+                        let marker = if is_active { "* " } else { "  " };
+                        let read_only = if storage.read_only { ", read-only" } else { "" };
+                        println!(
+                            "{}{}  (account: {}, namespace: {}{})",
+                            marker, name, storage.account_id, storage.namespace_id, read_only
+                        );
+                    }
+                }
+            }
+        }
+        StorageCommands::Current => match config.get_active_storage() {
+            Some(storage) => {
+                let output = match format {
+                    OutputFormat::Json => serde_json::to_string_pretty(&serde_json::json!({
+                        "name": storage.name,
+                        "account_id": storage.account_id,
+                        "namespace_id": storage.namespace_id,
+                    }))?,
+                    OutputFormat::Yaml => serde_yaml::to_string(&serde_json::json!({
+                        "name": storage.name,
+                        "account_id": storage.account_id,
+                        "namespace_id": storage.namespace_id,
+                    }))?,
+                    OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
+                        format!(
+                            "Current storage: {}\nAccount ID: {}\nNamespace ID: {}",
+                            storage.name, storage.account_id, storage.namespace_id
+                        )
+                    }
+                };
+                println!("{}", output);
+            }
+            None => {
+                exit::fail(
+                    "No active storage configured",
+                    ErrorKind::Validation,
+                    format,
+                );
+            }
+        },
+        StorageCommands::Switch { name } => {
+            config.set_active_storage(name.clone())?;
+            config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success(&format!("Switched to storage '{}'", name), format)
+            );
+        }
+        StorageCommands::Remove { name } => {
+            if let Some(storage) = config.get_storage(&name) {
+                let target = format!(
+                    "{} (account {}, namespace {})",
+                    storage.name, storage.account_id, storage.namespace_id
+                );
+                let question = format!("Remove storage '{}' from the config?", name);
+                confirm.ask(&question, &[target], format)?;
+            }
+            config.remove_storage(&name)?;
+            config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success(&format!("Storage '{}' removed", name), format)
+            );
+        }
+        StorageCommands::Rename { old_name, new_name } => {
+            config.rename_storage(&old_name, new_name.clone())?;
+            config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success(
+                    &format!("Storage renamed from '{}' to '{}'", old_name, new_name),
+                    format
+                )
+            );
+        }
+        StorageCommands::Show { name } => {
+            let storage = if let Some(storage_name) = name.as_deref().or(storage_override) {
+                config.get_storage(storage_name).ok_or_else(|| {
+                    Box::new(std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        format!("Storage '{}' not found", storage_name),
+                    )) as Box<dyn std::error::Error>
+                })?
+            } else {
+                config.get_active_storage().ok_or_else(|| {
+                    Box::new(std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        "No active storage configured",
+                    )) as Box<dyn std::error::Error>
+                })?
+            };
+
+            let output = match format {
+                OutputFormat::Json => serde_json::to_string_pretty(&serde_json::json!({
+                    "name": storage.name,
+                    "account_id": storage.account_id,
+                    "namespace_id": storage.namespace_id,
+                    "namespaces": storage.namespaces,
+                    "read_only": storage.read_only,
+                    "key_prefix": storage.key_prefix,
+                    "sensitive_prefixes": storage.sensitive_prefixes,
+                    "r2_bucket": storage.r2_bucket,
+                    "purge_urls": storage.purge_urls,
+                    "account": storage.account,
+                    "api_base_url": storage.api_base_url,
+                    "headers": storage.headers.keys().collect::<Vec<_>>(),
+                }))?,
+                OutputFormat::Yaml => serde_yaml::to_string(&serde_json::json!({
+                    "name": storage.name,
+                    "account_id": storage.account_id,
+                    "namespace_id": storage.namespace_id,
+                    "namespaces": storage.namespaces,
+                    "read_only": storage.read_only,
+                    "key_prefix": storage.key_prefix,
+                    "sensitive_prefixes": storage.sensitive_prefixes,
+                    "r2_bucket": storage.r2_bucket,
+                    "purge_urls": storage.purge_urls,
+                    "account": storage.account,
+                    "api_base_url": storage.api_base_url,
+                    "headers": storage.headers.keys().collect::<Vec<_>>(),
+                }))?,
+                OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
+                    let mut output = format!(
+                        "Storage: {}\nAccount ID: {}\nNamespace ID: {}",
+                        storage.name, storage.account_id, storage.namespace_id
+                    );
+                    if let Some(account) = &storage.account {
+                        output.push_str(&format!("\nShared account: {}", account));
+                    }
+                    if storage.read_only {
+                        output.push_str("\nRead-only: yes");
+                    }
+                    if let Some(prefix) = &storage.key_prefix {
+                        output.push_str(&format!("\nKey prefix: {}", prefix));
+                    }
+                    if !storage.sensitive_prefixes.is_empty() {
+                        output.push_str(&format!(
+                            "\nSensitive prefixes: {}",
+                            storage.sensitive_prefixes.join(", ")
+                        ));
+                    }
+                    if let Some(bucket) = &storage.r2_bucket {
+                        output.push_str(&format!("\nR2 bucket: {}", bucket));
+                    }
+                    if let Some(url) = &storage.api_base_url {
+                        output.push_str(&format!("\nAPI base URL: {}", url));
+                    }
+                    if !storage.headers.is_empty() {
+                        // Values often hold gateway keys
+                        let names: Vec<&str> = storage.headers.keys().map(String::as_str).collect();
+                        output.push_str(&format!("\nHeaders: {}", names.join(", ")));
+                    }
+                    for hook in &storage.purge_urls {
+                        output.push_str(&format!(
+                            "\nPurge after writes to '{}': {}",
+                            hook.prefix,
+                            hook.urls.join(", ")
+                        ));
+                    }
+                    let mut aliases: Vec<_> = storage.namespaces.iter().collect();
+                    aliases.sort();
+                    if !aliases.is_empty() {
+                        output.push_str("\nNamespaces:");
+                    }
+                    for (alias, namespace_id) in aliases {
+                        output.push_str(&format!("\n  {}: {}", alias, namespace_id));
+                    }
+                    output
+                }
+            };
+            println!("{}", output);
+        }
+        StorageCommands::Export { file } => {
+            let json = config.export_to_json()?;
+
+            if let Some(output_path) = file {
+                fs::write(&output_path, &json)?;
+                println!(
+                    "{}",
+                    Formatter::format_success(
+                        &format!("Storages exported to '{}'", output_path.display()),
+                        format
+                    )
+                );
+            } else {
+                println!("{}", json);
+            }
+        }
+        StorageCommands::Import { file } => {
+            let json = fs::read_to_string(&file)?;
+            config.import_from_json(&json)?;
+            config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success(
+                    &format!("Storages imported from '{}'", file.display()),
+                    format
+                )
+            );
+        }
+        StorageCommands::ImportWrangler {
+            file,
+            env,
+            account_id,
+            api_token,
+            api_token_cmd,
+            account,
+            no_preview,
+            overwrite,
+        } => {
+            let wrangler = wrangler::WranglerBindings::read(&file, env.as_deref())?;
+            if let Some(account) = account.as_deref() {
+                if !config.accounts.contains_key(account) {
+                    exit::fail(
+                        &format!("Account '{}' not found", account),
+                        ErrorKind::NotFound,
+                        format,
+                    );
+                }
+            }
+            let account_id = account_id.or(wrangler.account_id).unwrap_or_default();
+            if account.is_none() && account_id.is_empty() {
+                exit::fail(
+                    "No account_id in wrangler.toml; pass --account-id or --account",
+                    ErrorKind::Validation,
+                    format,
+                );
+            }
+
+            let mut namespaces = Vec::new();
+            for binding in wrangler.bindings {
+                let name = match &env {
+                    Some(env) => format!("{}-{}", binding.binding, env),
+                    None => binding.binding,
+                };
+                if let Some(preview_id) = binding.preview_id.filter(|_| !no_preview) {
+                    namespaces.push((format!("{}-preview", name), preview_id, true));
+                }
+                namespaces.push((name, binding.id, false));
+            }
+            namespaces.sort();
+
+            let mut added = Vec::new();
+            for (name, namespace_id, preview) in namespaces {
+                if config.get_storage(&name).is_some() && !overwrite {
+                    eprintln!(
+                        "Skipping '{}': storage exists (pass --overwrite to replace it)",
+                        name
+                    );
+                    continue;
+                }
+                config.add_storage(
+                    name.clone(),
+                    account_id.clone(),
+                    namespace_id.clone(),
+                    api_token.clone().unwrap_or_default(),
+                );
+                config.set_storage_account(&name, account.clone())?;
+                if api_token_cmd.is_some() {
+                    config.set_api_token_cmd(&name, api_token_cmd.clone())?;
+                }
+                added.push(serde_json::json!({
+                    "name": name,
+                    "namespace_id": namespace_id,
+                    "preview": preview,
+                }));
+            }
+            config.save(config_path)?;
+
+            match format {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&added)?),
+                OutputFormat::Yaml => print!("{}", serde_yaml::to_string(&added)?),
+                OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
+                    for storage in &added {
+                        let kind = if storage["preview"] == true {
+                            "preview namespace"
+                        } else {
+                            "namespace"
+                        };
+                        println!(
+                            "{}",
+                            Formatter::format_success(
+                                &format!(
+                                    "Storage '{}' added ({} {})",
+                                    storage["name"].as_str().unwrap_or_default(),
+                                    kind,
+                                    storage["namespace_id"].as_str().unwrap_or_default()
+                                ),
+                                format
+                            )
+                        );
+                    }
+                }
+            }
+        }
+        StorageCommands::SetEncryptionKey { name, key } => {
+            let generated = key.is_none();
+            let key = key.unwrap_or_else(EncryptionPlugin::generate_key);
+            EncryptionPlugin::with_key(&key)?;
+            config.set_encryption_key(&name, Some(key.clone()))?;
+            config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success(
+                    &format!("Encryption enabled for storage '{}'", name),
+                    format
+                )
+            );
+            if generated {
+                eprintln!("Generated key (back it up, values cannot be read without it):");
+                eprintln!("{}", key);
+            }
+        }
+        StorageCommands::ClearEncryptionKey { name } => {
+            config.set_encryption_key(&name, None)?;
+            config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success(
+                    &format!("Encryption disabled for storage '{}'", name),
+                    format
+                )
+            );
+        }
+        StorageCommands::ReadOnly { name } => {
+            config.set_read_only(&name, true)?;
+            config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success(&format!("Storage '{}' is now read-only", name), format)
+            );
+        }
+        StorageCommands::ReadWrite { name } => {
+            config.set_read_only(&name, false)?;
+            config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success(&format!("Storage '{}' is now writable", name), format)
+            );
+        }
+        StorageCommands::SetKeyPrefix { name, prefix } => {
+            config.set_key_prefix(&name, Some(prefix.clone()))?;
+            config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success(
+                    &format!("Storage '{}' now uses keys under '{}'", name, prefix),
+                    format
+                )
+            );
+        }
+        StorageCommands::SetR2Bucket { name, bucket } => {
+            config.set_r2_bucket(&name, Some(bucket.clone()))?;
+            config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success(
+                    &format!(
+                        "Storage '{}' now offloads values over 25 MiB to R2 bucket '{}'",
+                        name, bucket
+                    ),
+                    format
+                )
+            );
+        }
+        StorageCommands::ClearR2Bucket { name } => {
+            config.set_r2_bucket(&name, None)?;
+            config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success(
+                    &format!("Storage '{}' no longer offloads values to R2", name),
+                    format
+                )
+            );
+        }
+        StorageCommands::SetApiBaseUrl { name, url } => {
+            config.set_api_base_url(&name, Some(url.clone()))?;
+            config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success(
+                    &format!("Storage '{}' now reaches the API through {}", name, url),
+                    format
+                )
+            );
+        }
+        StorageCommands::ClearApiBaseUrl { name } => {
+            config.set_api_base_url(&name, None)?;
+            config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success(
+                    &format!("Storage '{}' now reaches the Cloudflare API directly", name),
+                    format
+                )
+            );
+        }
+        StorageCommands::SetHeader {
+            name,
+            header: (header, value),
+        } => {
+            config.set_header(&name, &header, Some(value))?;
+            config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success(
+                    &format!("Storage '{}' now sends header '{}'", name, header),
+                    format
+                )
+            );
+        }
+        StorageCommands::RemoveHeader { name, header } => {
+            config.set_header(&name, &header, None)?;
+            config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success(
+                    &format!("Storage '{}' no longer sends header '{}'", name, header),
+                    format
+                )
+            );
+        }
+        StorageCommands::AddSensitive { name, prefix } => {
+            config.set_sensitive(&name, &prefix, true)?;
+            config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success(
+                    &format!(
+                        "Values under '{}' in storage '{}' are now masked",
+                        prefix, name
+                    ),
+                    format
+                )
+            );
+        }
+        StorageCommands::RemoveSensitive { name, prefix } => {
+            config.set_sensitive(&name, &prefix, false)?;
+            config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success(
+                    &format!(
+                        "Values under '{}' in storage '{}' are no longer masked",
+                        prefix, name
+                    ),
+                    format
+                )
+            );
+        }
+        StorageCommands::AddPurgeUrl {
+            name,
+            prefix,
+            url,
+            zone_id,
+        } => {
+            config.add_purge_url(&name, &prefix, &url, zone_id)?;
+            config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success(
+                    &format!(
+                        "Writes under '{}' in storage '{}' now purge {}",
+                        prefix, name, url
+                    ),
+                    format
+                )
+            );
+        }
+        StorageCommands::RemovePurgeUrls { name, prefix } => {
+            config.remove_purge_urls(&name, &prefix)?;
+            config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success(
+                    &format!(
+                        "Writes under '{}' in storage '{}' no longer purge URLs",
+                        prefix, name
+                    ),
+                    format
+                )
+            );
+        }
+        StorageCommands::ClearKeyPrefix { name } => {
+            config.set_key_prefix(&name, None)?;
+            config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success(
+                    &format!("Key prefix removed from storage '{}'", name),
+                    format
+                )
+            );
+        }
+        StorageCommands::SetTokenCmd { name, command } => {
+            config.set_api_token_cmd(&name, Some(command))?;
+            config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success(
+                    &format!("Storage '{}' now runs a command for its API token", name),
+                    format
+                )
+            );
+        }
+        StorageCommands::SetAccount {
+            name,
+            account_id,
+            api_token,
+            api_token_cmd,
+        } => {
+            let existed = config.accounts.contains_key(&name);
+            config.set_account(
+                name.clone(),
+                config::Account {
+                    account_id,
+                    api_token: api_token.unwrap_or_default(),
+                    api_token_cmd,
+                },
+            );
+            config.save(config_path)?;
+            let message = if existed {
+                let mut users = config.account_users(&name);
+                users.sort();
+                format!(
+                    "Account '{}' updated for {} storage(s){}",
+                    name,
+                    users.len(),
+                    if users.is_empty() {
+                        String::new()
+                    } else {
+                        format!(": {}", users.join(", "))
+                    }
+                )
+            } else {
+                format!("Account '{}' added", name)
+            };
+            println!("{}", Formatter::format_success(&message, format));
+        }
+        StorageCommands::RemoveAccount { name } => {
+            config.remove_account(&name)?;
+            config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success(&format!("Account '{}' removed", name), format)
+            );
+        }
+        StorageCommands::Accounts => {
+            let mut names: Vec<&String> = config.accounts.keys().collect();
+            names.sort();
+            let accounts: Vec<serde_json::Value> = names
+                .iter()
+                .map(|name| {
+                    let mut users = config.account_users(name);
+                    users.sort();
+                    serde_json::json!({
+                        "name": name,
+                        "account_id": config.accounts[*name].account_id,
+                        "storages": users,
+                    })
+                })
+                .collect();
+            match format {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&accounts)?),
+                OutputFormat::Yaml => println!("{}", serde_yaml::to_string(&accounts)?),
+                OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
+                    if names.is_empty() {
+                        println!("No shared accounts configured");
+                    }
+                    for name in names {
+                        let mut users = config.account_users(name);
+                        users.sort();
+                        println!(
+                            "{}  (account: {}, storages: {})",
+                            name,
+                            config.accounts[name].account_id,
+                            if users.is_empty() {
+                                "none".to_string()
+                            } else {
+                                users.join(", ")
+                            }
+                        );
+                    }
+                }
+            }
+        }
+        StorageCommands::UseAccount { storage, account } => {
+            config.set_storage_account(&storage, Some(account.clone()))?;
+            config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success(
+                    &format!("Storage '{}' now uses account '{}'", storage, account),
+                    format
+                )
+            );
+        }
+        StorageCommands::UnlinkAccount { storage } => {
+            config.set_storage_account(&storage, None)?;
+            config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success(
+                    &format!("Storage '{}' now keeps its own credentials", storage),
+                    format
+                )
+            );
+        }
+        StorageCommands::AddNamespace {
+            storage,
+            alias,
+            namespace_id,
+        } => {
+            config.add_namespace(&storage, alias.clone(), namespace_id)?;
+            config.save(config_path)?;
+            println!(
+                "{}",
+                Formatter::format_success(
+                    &format!("Namespace '{}' added to storage '{}'", alias, storage),
+                    format
+                )
+            );
+        }
+        StorageCommands::Doctor { .. } => unreachable!(),
+        StorageCommands::Scan { name } => {
+            handle_storage_scan(
+                config,
+                config_path,
+                name.as_deref().or(storage_override),
+                format,
+            )
+            .await?
+        }
+        StorageCommands::LoadEnv => {
+            config.merge_from_env()?;
+            config.save(config_path)?;
+            let env_storages = config::Config::load_from_env()?;
+            if env_storages.is_empty() {
+                println!(
+                    "{}",
+                    Formatter::format_text("No storages found in environment variables", format)
+                );
+            } else {
+                let count = env_storages.len();
+                println!(
+                    "{}",
+                    Formatter::format_success(
+                        &format!("Loaded {} storage(ies) from environment variables", count),
+                        format
+                    )
+                );
+                for (name, _) in env_storages {
+                    println!("  - {}", name);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Run the jobs of a jobs file on their intervals, or each once with `--once`
+async fn handle_agent(
+    cli: &Cli,
+    config_path: &Path,
+    jobs_path: &Path,
+    once: bool,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut jobs = match agent::JobsFile::read(jobs_path) {
+        Ok(file) => file.jobs,
+        Err(e) => exit::fail_with(&e, format),
+    };
+    // Jobs run against the agent's storage and notify its webhook unless
+    // they name their own
+    for job in &mut jobs {
+        if job.storage.is_none() {
+            job.storage = cli.storage.clone();
+        }
+        if job.notify.is_none() {
+            job.notify = cli.notify.clone();
+            job.notify_on = job.notify_on.or(cli.notify_on);
+        }
+    }
+
+    // Runs inherit the agent's options; credentials passed through the
+    // environment are inherited with it
+    let mut global = vec!["--config".to_string(), config_path.display().to_string()];
+    if let Some(path) = &cli.local {
+        global.extend(["--local".to_string(), path.display().to_string()]);
+    }
+    if let Some(rate_limit) = cli.rate_limit {
+        global.extend(["--rate-limit".to_string(), rate_limit.to_string()]);
+    }
+    if let Some(timeout) = cli.timeout {
+        global.extend(["--timeout".to_string(), agent::format_duration(timeout)]);
+    }
+    if let Some(retries) = cli.retries {
+        global.extend(["--retries".to_string(), retries.to_string()]);
+    }
+    if let Some(delay) = cli.retry_delay {
+        global.extend(["--retry-delay".to_string(), agent::format_duration(delay)]);
+    }
+    if let Some(url) = &cli.api_base_url {
+        global.extend(["--api-base-url".to_string(), url.clone()]);
+    }
+    if let Some(log_format) = cli
+        .log_format
+        .and_then(|f| clap::ValueEnum::to_possible_value(&f))
+    {
+        global.extend([
+            "--log-format".to_string(),
+            log_format.get_name().to_string(),
+        ]);
+    }
+    for (set, flag) in [
+        (cli.yes, "--yes"),
+        (cli.force, "--force"),
+        (cli.no_progress, "--no-progress"),
+    ] {
+        if set {
+            global.push(flag.to_string());
+        }
+    }
+
+    if once {
+        let mut failed = Vec::new();
+        for job in &jobs {
+            if !job.run(&global, &mut agent::JobStats::default()).await {
+                failed.push(job.name.as_str());
+            }
+        }
+        if !failed.is_empty() {
+            exit::fail(
+                &format!("Jobs failed: {}", failed.join(", ")),
+                ErrorKind::General,
+                format,
+            );
+        }
+        return Ok(());
+    }
+
+    agent::run(jobs, global).await;
+    Ok(())
+}
+
+/// Rate limit, timeout and retries of API requests, from the command line
+/// or else the config file
+#[derive(Default)]
+struct RequestOptions {
+    rate_limit: Option<f64>,
+    timeout: Option<Duration>,
+    retry: RetryPolicy,
+    api_base_url: Option<String>,
+    metrics: Metrics,
+}
+
+impl RequestOptions {
+    fn new(cli: &Cli, http: &config::HttpConfig) -> Self {
+        let mut retry = RetryPolicy::default();
+        if let Some(retries) = cli.retries.or(http.retries) {
+            retry.max_retries = retries;
+        }
+        if let Some(delay) = cli.retry_delay.or(http.retry_delay) {
+            retry.delay = delay;
+        }
+        Self {
+            rate_limit: cli.rate_limit,
+            timeout: cli.timeout.or(http.timeout),
+            retry,
+            api_base_url: cli.api_base_url.clone(),
+            metrics: usage::metrics(),
+        }
+    }
+
+    fn apply(&self, mut client_config: ClientConfig) -> ClientConfig {
+        if let Some(requests_per_second) = self.rate_limit {
+            client_config = client_config.with_rate_limit(requests_per_second);
+        }
+        if let Some(url) = &self.api_base_url {
+            client_config = client_config.with_base_url(url);
+        }
+        client_config.timeout = self.timeout;
+        client_config
+            .with_retry(self.retry.clone())
+            .with_metrics(self.metrics.clone())
+    }
+}
+
+/// What `cfkv diff` compares
+struct DiffSides<'a> {
+    from: &'a str,
+    to: &'a str,
+    prefix: &'a str,
+    concurrency: usize,
+}
+
+async fn handle_diff(
+    config: &config::Config,
+    sides: DiffSides<'_>,
+    requests: &RequestOptions,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut snapshots = Vec::with_capacity(2);
+    for side in [sides.from, sides.to] {
+        let snapshot = if config.get_storage(side).is_some() {
+            let client = storage_client(config, side, requests).await?;
+            diff::storage_snapshot(&client, sides.prefix, sides.concurrency).await
+        } else if Path::new(side).is_file() {
+            diff::file_snapshot(Path::new(side), sides.prefix)
+        } else {
+            exit::fail(
+                &format!("'{}' is neither a configured storage nor a file", side),
+                ErrorKind::NotFound,
+                format,
+            );
+        };
+        match snapshot {
+            Ok(snapshot) => snapshots.push(snapshot),
+            Err(e) => exit::fail_with(&e, format),
+        }
+    }
+
+    let report = diff::DiffReport::compare(&snapshots[0], &snapshots[1]);
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(&report)?),
+        OutputFormat::Text | OutputFormat::Table => println!("{}", report.render_text()),
+        OutputFormat::Gha => {
+            println!("{}", report.render_text());
+            gha::set_outputs(&[
+                ("added_count", report.added.len().to_string()),
+                ("removed_count", report.removed.len().to_string()),
+                ("changed_count", report.changed.len().to_string()),
+                ("unchanged_count", report.unchanged.to_string()),
+                ("has_changes", (!report.is_empty()).to_string()),
+            ])?;
+        }
+    }
+    // Like diff(1), differences make the command fail, with the general code
+    if !report.is_empty() {
+        std::process::exit(ErrorKind::General.code());
+    }
+    Ok(())
+}
+
+/// Write a manifest of the values under `prefix`
+async fn handle_verify_generate(
+    client: &PluginClient,
+    path: &Path,
+    prefix: &str,
+    concurrency: usize,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let snapshot = match diff::storage_snapshot(client, prefix, concurrency).await {
+        Ok(snapshot) => snapshot,
+        Err(e) => exit::fail_with(&e, format),
+    };
+    let manifest = verify::Manifest::from_snapshot(snapshot, prefix);
+    manifest.write(path)?;
+    println!(
+        "{}",
+        Formatter::format_success(
+            &format!(
+                "Recorded {} key(s) in {}",
+                manifest.keys.len(),
+                path.display()
+            ),
+            format
+        )
+    );
     Ok(())
 }
 
-async fn handle_config_command(
-    command: ConfigCommands,
+/// What `cfkv mirror` copies and how
+struct MirrorOptions<'a> {
+    prefix: &'a str,
+    table: &'a str,
+    concurrency: usize,
+    show_progress: bool,
+}
+
+async fn handle_mirror(
+    client: &PluginClient,
+    database: &mirror::Database,
+    options: MirrorOptions<'_>,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let progress = Progress::new("Mirroring", None, options.show_progress);
+    let result = mirror::run(
+        client,
+        database,
+        options.table,
+        options.prefix,
+        options.concurrency,
+        &progress,
+    )
+    .await;
+    progress.clear();
+    let report = match result {
+        Ok(report) => report,
+        Err(e) => exit::fail_with(&e, format),
+    };
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(&report)?),
+        OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => println!(
+            "{}",
+            Formatter::format_success(
+                &format!(
+                    "Mirrored into {}: {} added, {} updated, {} deleted, {} unchanged",
+                    options.table, report.added, report.updated, report.deleted, report.unchanged
+                ),
+                format
+            )
+        ),
+    }
+    Ok(())
+}
+
+/// Check the keys of a manifest, failing if any is missing or corrupted
+async fn handle_verify(
+    client: &PluginClient,
+    path: &Path,
+    concurrency: usize,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let manifest = verify::Manifest::read(path)?;
+    let current = match diff::keys_snapshot(client, &manifest.key_names(), concurrency).await {
+        Ok(current) => current,
+        Err(e) => exit::fail_with(&e, format),
+    };
+    let report = manifest.verify(&current);
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(&report)?),
+        OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
+            println!("{}", report.render_text())
+        }
+    }
+    if !report.is_ok() {
+        exit::fail(
+            &format!(
+                "{} corrupted and {} missing key(s)",
+                report.corrupted.len(),
+                report.missing.len()
+            ),
+            ErrorKind::Validation,
+            format,
+        );
+    }
+    Ok(())
+}
+
+/// Client for a named storage, scoped to its key prefix and decoding values
+/// with the configured plugins
+async fn storage_client(
     config: &config::Config,
-    config_path: &Path,
+    name: &str,
+    requests: &RequestOptions,
+) -> Result<PluginClient, Box<dyn std::error::Error>> {
+    let storage = config
+        .get_storage(name)
+        .ok_or_else(|| format!("Storage '{}' not found", name))?;
+    let mut client = namespace_client(storage, storage.token()?, requests);
+    if let Some(prefix) = &storage.key_prefix {
+        client = ScopedKvClient::new(client, prefix.clone()).into_client();
+    }
+    let registry = plugins::build_registry(&config.plugins, Some(storage), None).await?;
+    Ok(PluginClient::new(client, registry))
+}
+
+/// Client for a storage's namespace with the command's request options,
+/// before scoping it to the storage's key prefix
+fn namespace_client(
+    storage: &config::Storage,
+    token: String,
+    requests: &RequestOptions,
+) -> KvClient {
+    let client_config = ClientConfig::new(
+        &storage.account_id,
+        &storage.namespace_id,
+        cloudflare_kv::AuthCredentials::token(token),
+    )
+    .with_connection(ConnectionOptions::keep_alive());
+    KvClient::new(requests.apply(storage.endpoint(client_config)))
+}
+
+async fn handle_storage_doctor(
+    config: &config::Config,
+    name: Option<&str>,
+    requests: &RequestOptions,
+    force: bool,
     format: OutputFormat,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    match command {
-        ConfigCommands::SetToken { token } => {
-            let mut new_config = config.clone();
-            new_config.api_token = Some(token);
-            new_config.save(config_path)?;
-            println!("{}", Formatter::format_success("API token saved", format));
+    let mut names: Vec<&str> = match name {
+        Some(name) => {
+            if config.get_storage(name).is_none() {
+                exit::fail(
+                    &format!("Storage '{}' not found", name),
+                    ErrorKind::NotFound,
+                    format,
+                );
+            }
+            vec![name]
         }
-        ConfigCommands::SetAccount { account_id } => {
-            let mut new_config = config.clone();
-            new_config.account_id = Some(account_id);
-            new_config.save(config_path)?;
-            println!("{}", Formatter::format_success("Account ID saved", format));
+        None => config.list_storages(),
+    };
+    if names.is_empty() {
+        println!(
+            "{}",
+            Formatter::format_text("No storages configured", format)
+        );
+        return Ok(());
+    }
+    names.sort();
+
+    let mut diagnoses = Vec::new();
+    for name in names {
+        let storage = config.get_storage(name).ok_or("storage disappeared")?;
+        // Writing to a read-only storage needs --force, like any other write
+        let read_only = storage.read_only && !force;
+        diagnoses.push(doctor::diagnose_storage(storage, requests, read_only).await);
+    }
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&diagnoses)?),
+        OutputFormat::Yaml => println!("{}", serde_yaml::to_string(&diagnoses)?),
+        OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
+            println!("{}", doctor::render_text(&diagnoses))
         }
-        ConfigCommands::SetNamespace { namespace_id } => {
-            let mut new_config = config.clone();
-            new_config.namespace_id = Some(namespace_id);
-            new_config.save(config_path)?;
-            println!(
-                "{}",
-                Formatter::format_success("Namespace ID saved", format)
+    }
+
+    let unhealthy: Vec<String> = diagnoses
+        .iter()
+        .filter_map(|d| {
+            d.failure()
+                .map(|check| format!("{} ({})", d.storage, check.name))
+        })
+        .collect();
+    if !unhealthy.is_empty() {
+        exit::fail(
+            &format!("Unhealthy storage(s): {}", unhealthy.join(", ")),
+            ErrorKind::General,
+            format,
+        );
+    }
+    Ok(())
+}
+
+async fn handle_storage_scan(
+    config: &mut config::Config,
+    config_path: &Path,
+    only: Option<&str>,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut groups = config.storages_by_account();
+    if let Some(only) = only {
+        groups.retain(|storages| storages.iter().any(|name| name == only));
+        if groups.is_empty() {
+            exit::fail(
+                &format!("Storage '{}' not found", only),
+                ErrorKind::NotFound,
+                format,
             );
         }
-        ConfigCommands::Show => {
-            let output = match format {
-                OutputFormat::Json => serde_json::to_string_pretty(config)?,
-                OutputFormat::Yaml => serde_yaml::to_string(config)?,
-                OutputFormat::Text => {
-                    format!(
-                        "Account ID: {}\nNamespace ID: {}\nAPI Token: {}",
-                        config.account_id.as_deref().unwrap_or("Not set"),
-                        config.namespace_id.as_deref().unwrap_or("Not set"),
-                        if config.api_token.is_some() {
-                            "***"
-                        } else {
-                            "Not set"
-                        }
-                    )
+    }
+    if groups.is_empty() {
+        println!(
+            "{}",
+            Formatter::format_text("No storages configured", format)
+        );
+        return Ok(());
+    }
+
+    let mut accounts = Vec::new();
+    let mut errors = Vec::new();
+    for storages in groups {
+        let (account, error) = scan::scan_account(config, storages).await;
+        accounts.push(account);
+        errors.extend(error);
+    }
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&accounts)?),
+        OutputFormat::Yaml => println!("{}", serde_yaml::to_string(&accounts)?),
+        OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
+            println!("{}", scan::render_text(&accounts));
+
+            let unmapped: Vec<(&str, &scan::ScannedNamespace)> = accounts
+                .iter()
+                .flat_map(|account| {
+                    account
+                        .unmapped()
+                        .map(|ns| (account.storages[0].as_str(), ns))
+                })
+                .collect();
+            if !unmapped.is_empty() {
+                use std::io::IsTerminal;
+                if std::io::stdin().is_terminal() && std::io::stdout().is_terminal() {
+                    add_scanned_namespaces(config, config_path, &unmapped, format)?;
+                } else {
+                    println!(
+                        "\n{} namespace(s) not mapped. Add one with: cfkv storage add-namespace <STORAGE> <ALIAS> <NAMESPACE_ID>",
+                        unmapped.len()
+                    );
                 }
-            };
-            println!("{}", output);
+            }
         }
-        ConfigCommands::Reset => {
-            let new_config = config::Config::default();
-            new_config.save(config_path)?;
-            println!(
-                "{}",
-                Formatter::format_success("Configuration reset", format)
+    }
+
+    if let Some(e) = errors.first() {
+        exit::fail(
+            &format!(
+                "Could not list the namespaces of {} account(s)",
+                errors.len()
+            ),
+            ErrorKind::of(e),
+            format,
+        );
+    }
+    Ok(())
+}
+
+/// Ask for an alias for each unmapped namespace and add the ones given
+fn add_scanned_namespaces(
+    config: &mut config::Config,
+    config_path: &Path,
+    unmapped: &[(&str, &scan::ScannedNamespace)],
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::Write;
+
+    println!();
+    let mut added = 0;
+    for (storage, ns) in unmapped {
+        let alias = scan::default_alias(&ns.title);
+        print!(
+            "Add '{}' ({}) to storage '{}'? Alias [{}], '-' to skip: ",
+            ns.title, ns.id, storage, alias
+        );
+        std::io::stdout().flush()?;
+
+        let mut answer = String::new();
+        if std::io::stdin().read_line(&mut answer)? == 0 {
+            break;
+        }
+        let alias = match answer.trim() {
+            "-" => continue,
+            "" => alias,
+            other => other.to_string(),
+        };
+        if config
+            .get_storage(storage)
+            .is_some_and(|s| s.namespaces.contains_key(&alias))
+        {
+            eprintln!(
+                "Alias '{}' is already used in storage '{}', skipped",
+                alias, storage
             );
+            continue;
         }
+        config.add_namespace(storage, alias, ns.id.clone())?;
+        added += 1;
     }
 
+    if added > 0 {
+        config.save(config_path)?;
+        println!(
+            "{}",
+            Formatter::format_success(&format!("Added {} namespace(s)", added), format)
+        );
+    }
     Ok(())
 }
 
-async fn handle_storage_command(
-    command: StorageCommands,
+async fn handle_plugin_command(
+    command: PluginCommands,
     config: &mut config::Config,
     config_path: &Path,
+    storage_override: Option<&str>,
     format: OutputFormat,
 ) -> Result<(), Box<dyn std::error::Error>> {
     match command {
-        StorageCommands::Add {
-            name,
-            account_id,
-            namespace_id,
-            api_token,
-        } => {
-            config.add_storage(name.clone(), account_id, namespace_id, api_token);
-            config.save(config_path)?;
-            println!(
-                "{}",
-                Formatter::format_success(&format!("Storage '{}' added", name), format)
-            );
+        PluginCommands::List => {
+            let registry = plugins::build_registry(
+                &config.plugins,
+                config.select_storage(storage_override)?,
+                None,
+            )
+            .await?;
+            let plugins: Vec<serde_json::Value> = registry
+                .list()
+                .into_iter()
+                .map(|meta| {
+                    let commands = registry
+                        .get(&meta.name)
+                        .map(|p| p.commands())
+                        .unwrap_or_default();
+                    serde_json::json!({
+                        "name": meta.name,
+                        "version": meta.version,
+                        "description": meta.description,
+                        "author": meta.author,
+                        "commands": commands,
+                    })
+                })
+                .collect();
+
+            match format {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&plugins)?),
+                OutputFormat::Yaml => println!("{}", serde_yaml::to_string(&plugins)?),
+                OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
+                    println!("Loaded plugins (in chain order):\n");
+                    for plugin in plugins {
+                        println!(
+                            "• {} {}",
+                            plugin["name"].as_str().unwrap_or_default(),
+                            plugin["version"].as_str().unwrap_or_default()
+                        );
+                        println!("  {}", plugin["description"].as_str().unwrap_or_default());
+                        let commands: Vec<&str> = plugin["commands"]
+                            .as_array()
+                            .map(|c| c.iter().filter_map(|c| c.as_str()).collect())
+                            .unwrap_or_default();
+                        if commands.is_empty() {
+                            println!("  Commands: (none)\n");
+                        } else {
+                            println!("  Commands: {}\n", commands.join(", "));
+                        }
+                    }
+                }
+            }
         }
-        StorageCommands::List => {
-            let storages = config.list_storages();
-            if storages.is_empty() {
+        PluginCommands::Install { path } => match plugins::install(&path) {
+            Ok(plugin) => {
+                let name = plugin.name.clone();
+                config.add_plugin(plugin);
+                config.save(config_path)?;
                 println!(
                     "{}",
-                    Formatter::format_text("No storages configured", format)
+                    Formatter::format_success(&format!("Plugin '{}' installed", name), format)
                 );
-                return Ok(());
             }
+            Err(e) => exit::fail(
+                &format!("Failed to install plugin: {}", e),
+                ErrorKind::of(&e),
+                format,
+            ),
+        },
+    }
 
-            match format {
-                OutputFormat::Json => {
-                    let storage_list: Vec<serde_json::Value> = storages
-                        .iter()
-                        .map(|name| {
-                            let storage = config.get_storage(name).unwrap();
-                            let is_active = config.active_storage.as_deref() == Some(name);
-                            serde_json::json!({
-                                "name": storage.name,
-                                "account_id": storage.account_id,
-                                "namespace_id": storage.namespace_id,
-                                "active": is_active,
-                            })
-                        })
-                        .collect();
-                    println!("{}", serde_json::to_string_pretty(&storage_list)?);
+    Ok(())
+}
+
+fn handle_blog_preview(
+    file: &Path,
+    render: &cli::RenderArgs,
+    blog_config: &config::BlogConfig,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let post = match BlogPublisher::preview(
+        file,
+        render.render,
+        &render.options(),
+        &blog_config.required_fields,
+    ) {
+        Ok(post) => post,
+        Err(e) => {
+            exit::fail(
+                &format!("Invalid blog post: {}", e),
+                ErrorKind::Validation,
+                format,
+            );
+        }
+    };
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&post)?),
+        OutputFormat::Yaml => println!("{}", serde_yaml::to_string(&post)?),
+        OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
+            println!("Key: {}", BlogPublisher::storage_key(&post));
+            if !post.draft {
+                println!("Blog list entry:");
+                println!("{}", serde_json::to_string_pretty(&post.meta())?);
+            }
+            println!("Value:");
+            println!("{}", serde_json::to_string_pretty(&post)?);
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_content_collections(
+    config: &config::Config,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let collections = config.collections();
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&collections)?),
+        OutputFormat::Yaml => println!("{}", serde_yaml::to_string(&collections)?),
+        OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
+            for collection in collections {
+                let builtin = if collection.is_blog() {
+                    " (built-in)"
+                } else {
+                    ""
+                };
+                println!("• {}{}", collection.name, builtin);
+                println!("  Entries: {}<slug>", collection.key_prefix);
+                println!("  List: {}", collection.list_key);
+                if !collection.required_fields.is_empty() {
+                    println!("  Required: {}", collection.required_fields.join(", "));
                 }
-                OutputFormat::Yaml => {
-                    let storage_list: Vec<serde_json::Value> = storages
+                if let Err(e) = config.get_collection(&collection.name) {
+                    println!("  Invalid: {}", e);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn handle_sessions(
+    client: &KvClient,
+    command: SessionCommands,
+    confirm: Confirm,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let args = match &command {
+        SessionCommands::List { sessions, .. }
+        | SessionCommands::Count { sessions }
+        | SessionCommands::Purge { sessions, .. } => sessions.clone(),
+    };
+    if args.prefix.is_empty() {
+        exit::fail("--prefix must not be empty", ErrorKind::Validation, format);
+    }
+    let keys = match client.list_all(&args.prefix).await {
+        Ok(keys) => keys,
+        Err(e) => exit::fail_with(&e, format),
+    };
+    let found = sessions::sessions(&keys, &args.expiry_field);
+    let now = chrono::Utc::now().timestamp() as u64;
+
+    match command {
+        SessionCommands::List { limit, .. } => {
+            let shown = &found[..limit.unwrap_or(found.len()).min(found.len())];
+            match format {
+                OutputFormat::Json | OutputFormat::Yaml => {
+                    let report: Vec<serde_json::Value> = shown
                         .iter()
-                        .map(|name| {
-                            let storage = config.get_storage(name).unwrap();
-                            let is_active = config.active_storage.as_deref() == Some(name);
+                        .map(|session| {
                             serde_json::json!({
-                                "name": storage.name,
-                                "account_id": storage.account_id,
-                                "namespace_id": storage.namespace_id,
-                                "active": is_active,
+                                "key": session.key,
+                                "expires_at": session.expires_at,
+                                "remaining": session.remaining(now),
+                                "expired": session.is_expired(now),
                             })
                         })
                         .collect();
-                    println!("{}", serde_yaml::to_string(&storage_list)?);
+                    match format {
+                        OutputFormat::Json => {
+                            println!("{}", serde_json::to_string_pretty(&report)?)
+                        }
+                        _ => print!("{}", serde_yaml::to_string(&report)?),
+                    }
                 }
-                OutputFormat::Text => {
-                    println!("Available storages:\n");
-                    for name in storages {
-                        let storage = config.get_storage(name).unwrap();
-                        let is_active = config.active_storage.as_deref() == Some(name);
-                        let marker = if is_active { "* " } else { "  " };
+                OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
+                    let width = shown.iter().map(|session| session.key.len()).max();
+                    for session in shown {
+                        let remaining = match session.remaining(now) {
+                            Some(seconds) => table::format_duration(seconds as i64),
+                            None => "-".to_string(),
+                        };
                         println!(
-                            "{}{}  (account: {}, namespace: {})",
-                            marker, name, storage.account_id, storage.namespace_id
+                            "{:width$}  {}",
+                            session.key,
+                            remaining,
+                            width = width.unwrap_or(0)
                         );
                     }
                 }
             }
         }
-        StorageCommands::Current => match config.get_active_storage() {
-            Some(storage) => {
-                let output = match format {
-                    OutputFormat::Json => serde_json::to_string_pretty(&serde_json::json!({
-                        "name": storage.name,
-                        "account_id": storage.account_id,
-                        "namespace_id": storage.namespace_id,
-                    }))?,
-                    OutputFormat::Yaml => serde_yaml::to_string(&serde_json::json!({
-                        "name": storage.name,
-                        "account_id": storage.account_id,
-                        "namespace_id": storage.namespace_id,
-                    }))?,
-                    OutputFormat::Text => {
-                        format!(
-                            "Current storage: {}\nAccount ID: {}\nNamespace ID: {}",
-                            storage.name, storage.account_id, storage.namespace_id
-                        )
-                    }
-                };
-                println!("{}", output);
+        SessionCommands::Count { .. } => {
+            let counts = sessions::count(&found, now);
+            match format {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&counts)?),
+                OutputFormat::Yaml => print!("{}", serde_yaml::to_string(&counts)?),
+                OutputFormat::Gha => gha::set_outputs(&[
+                    ("active", counts.active.to_string()),
+                    ("expired", counts.expired.to_string()),
+                ])?,
+                OutputFormat::Text | OutputFormat::Table => {
+                    println!("Active:         {}", counts.active);
+                    println!("Expired:        {}", counts.expired);
+                    println!("Without expiry: {}", counts.without_expiry);
+                }
+            }
+        }
+        SessionCommands::Purge { dry_run, .. } => {
+            let expired: Vec<String> = found
+                .into_iter()
+                .filter(|session| session.is_expired(now))
+                .map(|session| session.key)
+                .collect();
+            if expired.is_empty() {
+                println!(
+                    "{}",
+                    Formatter::format_success("No expired sessions", format)
+                );
+                return Ok(());
+            }
+            if dry_run {
+                for key in &expired {
+                    println!("{}", key);
+                }
+                return Ok(());
             }
-            None => {
-                eprintln!(
-                    "{}",
-                    Formatter::format_error("No active storage configured", format)
+
+            if confirm.needs_yes() {
+                exit::fail(
+                    &format!(
+                        "{} expired session(s); pass --yes to delete them without a prompt",
+                        expired.len()
+                    ),
+                    ErrorKind::Validation,
+                    format,
                 );
-                std::process::exit(1);
             }
-        },
-        StorageCommands::Switch { name } => {
-            config.set_active_storage(name.clone())?;
-            config.save(config_path)?;
+            let question = format!("Delete {} expired session(s)?", expired.len());
+            confirm.ask(&question, &expired, format)?;
+            if let Err(e) = client.delete_keys(&expired).await {
+                exit::fail_with(&e, format);
+            }
             println!(
                 "{}",
-                Formatter::format_success(&format!("Switched to storage '{}'", name), format)
+                Formatter::format_success(
+                    &format!("Deleted {} expired session(s)", expired.len()),
+                    format
+                )
             );
         }
-        StorageCommands::Remove { name } => {
-            config.remove_storage(&name)?;
-            config.save(config_path)?;
+    }
+    Ok(())
+}
+
+/// Read a flag, failing when the key holds something else
+async fn read_flag(
+    client: &PluginClient,
+    key: &str,
+    format: OutputFormat,
+) -> Result<Option<flags::Flag>, Box<dyn std::error::Error>> {
+    let Some(pair) = client.get(key).await? else {
+        return Ok(None);
+    };
+    match flags::Flag::from_stored(&pair.value) {
+        Ok(flag) => Ok(Some(flag)),
+        Err(e) => exit::fail(
+            &format!("Key {} is not a valid flag: {}", key, e),
+            ErrorKind::Validation,
+            format,
+        ),
+    }
+}
+
+async fn handle_flags(
+    client: &PluginClient,
+    command: FlagCommands,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let name = match &command {
+        FlagCommands::Set { name, .. }
+        | FlagCommands::Get { name, .. }
+        | FlagCommands::Rollout { name, .. } => Some(name.as_str()),
+        FlagCommands::List => None,
+    };
+    let key = match name.map(flags::key).transpose() {
+        Ok(key) => key.unwrap_or_default(),
+        Err(e) => exit::fail(&e, ErrorKind::Validation, format),
+    };
+
+    match command {
+        FlagCommands::Set { name, setting } => {
+            let flag = match flags::Flag::parse_setting(&setting) {
+                Ok(flag) => flag,
+                Err(e) => exit::fail(&e, ErrorKind::Validation, format),
+            };
+            client.put(&key, serde_json::to_string(&flag)?).await?;
             println!(
                 "{}",
-                Formatter::format_success(&format!("Storage '{}' removed", name), format)
+                Formatter::format_success(
+                    &format!("Flag '{}' set: {}", name, flag.summary()),
+                    format
+                )
             );
         }
-        StorageCommands::Rename { old_name, new_name } => {
-            config.rename_storage(&old_name, new_name.clone())?;
-            config.save(config_path)?;
+        FlagCommands::Rollout { name, percent } => {
+            let mut flag = read_flag(client, &key, format)
+                .await?
+                .unwrap_or_else(|| flags::Flag::on(None));
+            flag.enabled = true;
+            flag.rollout = Some(percent);
+            client.put(&key, serde_json::to_string(&flag)?).await?;
             println!(
                 "{}",
                 Formatter::format_success(
-                    &format!("Storage renamed from '{}' to '{}'", old_name, new_name),
+                    &format!("Flag '{}' on for {}% of subjects", name, percent),
                     format
                 )
             );
         }
-        StorageCommands::Show { name } => {
-            let storage = if let Some(storage_name) = name {
-                config.get_storage(&storage_name).ok_or_else(|| {
-                    Box::new(std::io::Error::new(
-                        std::io::ErrorKind::NotFound,
-                        format!("Storage '{}' not found", &storage_name),
-                    )) as Box<dyn std::error::Error>
-                })?
-            } else {
-                config.get_active_storage().ok_or_else(|| {
-                    Box::new(std::io::Error::new(
-                        std::io::ErrorKind::NotFound,
-                        "No active storage configured",
-                    )) as Box<dyn std::error::Error>
-                })?
+        FlagCommands::Get { name, subject } => {
+            let Some(flag) = read_flag(client, &key, format).await? else {
+                exit::fail(
+                    &format!("Flag not found: {}", name),
+                    ErrorKind::NotFound,
+                    format,
+                );
             };
-
-            let output = match format {
-                OutputFormat::Json => serde_json::to_string_pretty(&serde_json::json!({
-                    "name": storage.name,
-                    "account_id": storage.account_id,
-                    "namespace_id": storage.namespace_id,
-                }))?,
-                OutputFormat::Yaml => serde_yaml::to_string(&serde_json::json!({
-                    "name": storage.name,
-                    "account_id": storage.account_id,
-                    "namespace_id": storage.namespace_id,
-                }))?,
-                OutputFormat::Text => {
-                    format!(
-                        "Storage: {}\nAccount ID: {}\nNamespace ID: {}",
-                        storage.name, storage.account_id, storage.namespace_id
-                    )
+            let report = match &subject {
+                Some(subject) => serde_json::json!({
+                    "name": name,
+                    "subject": subject,
+                    "on": flag.is_on_for(&name, subject),
+                    "value": flag.value,
+                }),
+                None => {
+                    let mut report = serde_json::to_value(&flag)?;
+                    report["name"] = name.clone().into();
+                    report
                 }
             };
-            println!("{}", output);
+            match format {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+                OutputFormat::Yaml => print!("{}", serde_yaml::to_string(&report)?),
+                OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => match subject {
+                    Some(subject) if flag.is_on_for(&name, &subject) => match &flag.value {
+                        Some(value) => println!("on = {}", value),
+                        None => println!("on"),
+                    },
+                    Some(_) => println!("off"),
+                    None => println!("{}", flag.summary()),
+                },
+            }
         }
-        StorageCommands::Export { file } => {
-            let json = config.export_to_json()?;
+        FlagCommands::List => {
+            let keys = client.client().list_keys(flags::PREFIX).await?;
+            let values = client.get_many(&keys, 8).await?;
+            let mut listed = Vec::new();
+            for (key, pair) in keys.iter().zip(values) {
+                let name = key.strip_prefix(flags::PREFIX).unwrap_or(key);
+                // Flags deleted since listing are left out
+                let Some(pair) = pair else { continue };
+                listed.push((name, flags::Flag::from_stored(&pair.value)));
+            }
 
-            if let Some(output_path) = file {
-                fs::write(&output_path, &json)?;
-                println!(
-                    "{}",
-                    Formatter::format_success(
-                        &format!("Storages exported to '{}'", output_path.display()),
-                        format
-                    )
-                );
-            } else {
-                println!("{}", json);
+            match format {
+                OutputFormat::Json | OutputFormat::Yaml => {
+                    let report: Vec<serde_json::Value> = listed
+                        .iter()
+                        .map(|(name, flag)| match flag {
+                            Ok(flag) => {
+                                let mut entry = serde_json::to_value(flag).unwrap_or_default();
+                                entry["name"] = (*name).into();
+                                entry
+                            }
+                            Err(e) => serde_json::json!({ "name": name, "error": e }),
+                        })
+                        .collect();
+                    match format {
+                        OutputFormat::Json => {
+                            println!("{}", serde_json::to_string_pretty(&report)?)
+                        }
+                        _ => print!("{}", serde_yaml::to_string(&report)?),
+                    }
+                }
+                OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
+                    let width = listed.iter().map(|(name, _)| name.len()).max();
+                    for (name, flag) in &listed {
+                        let summary = match flag {
+                            Ok(flag) => flag.summary(),
+                            Err(e) => format!("invalid: {}", e),
+                        };
+                        println!("{:width$}  {}", name, summary, width = width.unwrap_or(0));
+                    }
+                }
             }
         }
-        StorageCommands::Import { file } => {
-            let json = fs::read_to_string(&file)?;
-            config.import_from_json(&json)?;
-            config.save(config_path)?;
+    }
+    Ok(())
+}
+
+async fn handle_content(
+    client: &KvClient,
+    command: ContentCommands,
+    config: &config::Config,
+    confirm: Confirm,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let name = match &command {
+        ContentCommands::Publish { collection, .. }
+        | ContentCommands::Get { collection, .. }
+        | ContentCommands::List { collection, .. }
+        | ContentCommands::Delete { collection, .. } => collection.clone(),
+        ContentCommands::Collections => unreachable!(),
+    };
+    let collection = match config.get_collection(&name) {
+        Ok(collection) => collection,
+        Err(e) => exit::fail_with(&e, format),
+    };
+
+    // The blog collection is served by the blog commands
+    if collection.is_blog() {
+        let command = match command {
+            ContentCommands::Publish { file, render, .. } => BlogCommands::Publish {
+                file,
+                with_assets: None,
+                render,
+            },
+            ContentCommands::Get { slug, .. } => BlogCommands::Get { slug, draft: false },
+            ContentCommands::List { page, .. } => BlogCommands::List {
+                tag: None,
+                author: None,
+                page,
+                scheduled: false,
+            },
+            ContentCommands::Delete { slug, .. } => BlogCommands::Delete { slug },
+            ContentCommands::Collections => unreachable!(),
+        };
+        return handle_blog(client, command, &config.blog, confirm, format).await;
+    }
+
+    let publisher = match &command {
+        ContentCommands::Publish { render, .. } => {
+            ContentPublisher::new(client, collection).with_render(render.render, render.options())
+        }
+        _ => ContentPublisher::new(client, collection),
+    };
+    let name = &publisher.collection().name;
+    match command {
+        ContentCommands::Publish { file, .. } => {
+            let entry = publisher.publish_from_file(&file).await?;
             println!(
                 "{}",
                 Formatter::format_success(
-                    &format!("Storages imported from '{}'", file.display()),
+                    &format!("Published to {}: {}", name, entry.slug),
                     format
                 )
             );
         }
-        StorageCommands::LoadEnv => {
-            config.merge_from_env()?;
-            config.save(config_path)?;
-            let env_storages = config::Config::load_from_env()?;
-            if env_storages.is_empty() {
-                println!(
-                    "{}",
-                    Formatter::format_text("No storages found in environment variables", format)
-                );
-            } else {
-                let count = env_storages.len();
+        ContentCommands::Get { slug, .. } => {
+            let entry = match publisher.get(&slug).await? {
+                Some(entry) => entry,
+                None => {
+                    exit::fail(
+                        &format!("Entry not found in {}: {}", name, slug),
+                        ErrorKind::NotFound,
+                        format,
+                    );
+                }
+            };
+
+            match format {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&entry)?),
+                OutputFormat::Yaml => println!("{}", serde_yaml::to_string(&entry)?),
+                OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
+                    print!("{}", entry.to_markdown()?)
+                }
+            }
+        }
+        ContentCommands::List { page, .. } => {
+            let entries = match page {
+                Some(page) => publisher.list_page(page).await?,
+                None => publisher.list().await?,
+            };
+            if entries.is_empty() {
                 println!(
                     "{}",
-                    Formatter::format_success(
-                        &format!("Loaded {} storage(ies) from environment variables", count),
-                        format
-                    )
+                    Formatter::format_text(&format!("No entries found in {}", name), format)
                 );
-                for (name, _) in env_storages {
-                    println!("  - {}", name);
+                return Ok(());
+            }
+
+            match format {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&entries)?),
+                OutputFormat::Yaml => println!("{}", serde_yaml::to_string(&entries)?),
+                OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
+                    println!("Found {} entries in {}:\n", entries.len(), name);
+                    for entry in entries {
+                        match entry.fields.get("title").and_then(|t| t.as_str()) {
+                            Some(title) => println!("• {}", title),
+                            None => println!("• {}", entry.slug),
+                        }
+                        println!("  Slug: {}\n", entry.slug);
+                    }
                 }
             }
         }
+        ContentCommands::Delete { slug, .. } => {
+            confirm.ask(&format!("Delete {} from {}?", slug, name), &[], format)?;
+            publisher.delete(&slug).await?;
+            println!(
+                "{}",
+                Formatter::format_success(&format!("Deleted from {}: {}", name, slug), format)
+            );
+        }
+        ContentCommands::Collections => unreachable!(),
     }
 
     Ok(())
 }
 
+/// Run the blog's post-publish hook, if any, for an event that changed the
+/// blog
+async fn run_blog_hook(
+    blog_config: &config::BlogConfig,
+    event: blog_hook::BlogEvent,
+    format: OutputFormat,
+) {
+    let Some(hook) = &blog_config.post_publish_hook else {
+        return;
+    };
+    if event.is_empty() {
+        return;
+    }
+    if let Err(e) = blog_hook::run(hook, &event).await {
+        exit::fail(
+            &format!("Post-publish hook failed: {}", e),
+            ErrorKind::General,
+            format,
+        );
+    }
+}
+
 async fn handle_blog(
     client: &KvClient,
     command: BlogCommands,
+    blog_config: &config::BlogConfig,
+    confirm: Confirm,
     format: OutputFormat,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let publisher = BlogPublisher::new(client);
+    let publisher =
+        BlogPublisher::new(client).with_required_fields(blog_config.required_fields.clone());
+    let publisher = match &command {
+        BlogCommands::Publish { render, .. }
+        | BlogCommands::PublishDir { render, .. }
+        | BlogCommands::Draft { render, .. } => {
+            publisher.with_render(render.render, render.options())
+        }
+        _ => publisher,
+    };
 
     match command {
-        BlogCommands::Publish { file } => {
-            publisher.publish_from_file(&file).await?;
-            let title = file
-                .file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or("blog post");
+        BlogCommands::Publish {
+            file, with_assets, ..
+        } => {
+            let (post, assets) = match with_assets {
+                Some(dir) => publisher.publish_with_assets(&file, &dir).await?,
+                None => (publisher.publish_from_file(&file).await?, Vec::new()),
+            };
+            let mut message = match post.publish_at {
+                _ if post.draft => format!("Saved as draft: {}", post.slug),
+                Some(at) if post.is_scheduled(chrono::Utc::now()) => {
+                    format!("Scheduled for {}: {}", at.to_rfc3339(), post.slug)
+                }
+                _ => format!("Successfully published: {}", post.slug),
+            };
+            if !assets.is_empty() {
+                message.push_str(&format!(" ({} assets uploaded)", assets.len()));
+            }
+            println!("{}", Formatter::format_success(&message, format));
+            if !post.draft && !post.is_scheduled(chrono::Utc::now()) {
+                let event = blog_hook::BlogEvent::published(vec![post.meta()]);
+                run_blog_hook(blog_config, event, format).await;
+            }
+        }
+        BlogCommands::PublishDir { dir, prune, .. } => {
+            let report = publisher.publish_dir(&dir, prune).await?;
+            match format {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+                OutputFormat::Yaml => println!("{}", serde_yaml::to_string(&report)?),
+                OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
+                    println!(
+                        "{}",
+                        Formatter::format_success(
+                            &format!(
+                                "Published {}: {} created, {} updated, {} unchanged, {} drafts, {} scheduled",
+                                dir.display(),
+                                report.created,
+                                report.updated,
+                                report.unchanged,
+                                report.drafts,
+                                report.scheduled
+                            ),
+                            format
+                        )
+                    );
+                    for slug in &report.unpublished {
+                        println!("  Unpublished: {}", slug);
+                    }
+                    for slug in &report.deleted {
+                        println!("  Deleted: {}", slug);
+                    }
+                }
+            }
+            // Posts moved back to drafts are gone from the site like deleted ones
+            let event = blog_hook::BlogEvent {
+                deleted: [report.unpublished, report.deleted].concat(),
+                ..blog_hook::BlogEvent::published(report.published)
+            };
+            run_blog_hook(blog_config, event, format).await;
+        }
+        BlogCommands::Draft { file, .. } => {
+            let post = publisher.publish_draft(&file).await?;
+            println!(
+                "{}",
+                Formatter::format_success(&format!("Saved draft: {}", post.slug), format)
+            );
+        }
+        BlogCommands::Promote { slug } => {
+            let post = publisher.promote(&slug).await?;
+            println!(
+                "{}",
+                Formatter::format_success(&format!("Successfully published: {}", slug), format)
+            );
+            let event = blog_hook::BlogEvent::published(vec![post.meta()]);
+            run_blog_hook(blog_config, event, format).await;
+        }
+        BlogCommands::Unpublish { slug } => {
+            publisher.unpublish(&slug).await?;
             println!(
                 "{}",
-                Formatter::format_success(&format!("Successfully published: {}", title), format)
+                Formatter::format_success(&format!("Moved to drafts: {}", slug), format)
             );
+            let event = blog_hook::BlogEvent::removed(blog_hook::BlogEventKind::Unpublish, &slug);
+            run_blog_hook(blog_config, event, format).await;
+        }
+        BlogCommands::List {
+            scheduled: true, ..
+        } => {
+            let posts: Vec<_> = publisher
+                .list_scheduled()
+                .await?
+                .iter()
+                .map(|p| p.meta())
+                .collect();
+
+            if posts.is_empty() {
+                println!("{}", Formatter::format_text("No scheduled posts", format));
+                return Ok(());
+            }
+
+            match format {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&posts)?),
+                OutputFormat::Yaml => println!("{}", serde_yaml::to_string(&posts)?),
+                OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
+                    println!("Found {} scheduled posts:\n", posts.len());
+                    for post in posts {
+                        println!("• {}", post.title);
+                        println!("  Slug: {}", post.slug);
+                        if let Some(at) = post.publish_at {
+                            println!("  Publish at: {}", at.to_rfc3339());
+                        }
+                        println!("  Author: {}\n", post.author);
+                    }
+                }
+            }
+        }
+        BlogCommands::ReleaseDue => {
+            let released = publisher.release_due(chrono::Utc::now()).await?;
+            let slugs: Vec<&str> = released.iter().map(|p| p.slug.as_str()).collect();
+            match format {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&slugs)?),
+                OutputFormat::Yaml => println!("{}", serde_yaml::to_string(&slugs)?),
+                OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
+                    println!(
+                        "{}",
+                        Formatter::format_success(
+                            &format!("Released {} scheduled posts", slugs.len()),
+                            format
+                        )
+                    );
+                    for slug in slugs {
+                        println!("  Published: {}", slug);
+                    }
+                }
+            }
+            let event =
+                blog_hook::BlogEvent::published(released.iter().map(|p| p.meta()).collect());
+            run_blog_hook(blog_config, event, format).await;
+        }
+        BlogCommands::MigrateList => {
+            let message = match publisher.migrate_list().await? {
+                Some(total) => format!("Converted the blog list ({} posts) to pages", total),
+                None => "The blog list is already paged".to_string(),
+            };
+            println!("{}", Formatter::format_success(&message, format));
+        }
+        BlogCommands::Gc { fix } => {
+            let report = publisher.gc(fix).await?;
+            match format {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+                OutputFormat::Yaml => println!("{}", serde_yaml::to_string(&report)?),
+                OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
+                    if report.is_clean() {
+                        println!(
+                            "{}",
+                            Formatter::format_success("Posts and blog list agree", format)
+                        );
+                    }
+                    let (unlisted, missing) = match report.fixed {
+                        true => ("Added to the list", "Removed from the list"),
+                        false => ("Not in the list", "Post gone"),
+                    };
+                    for slug in &report.unlisted {
+                        println!("  {}: {}", unlisted, slug);
+                    }
+                    for slug in &report.missing {
+                        println!("  {}: {}", missing, slug);
+                    }
+                    for slug in &report.unreadable {
+                        println!("  Not a valid post: post:{}", slug);
+                    }
+                    let repairable = !report.unlisted.is_empty() || !report.missing.is_empty();
+                    if repairable && !report.fixed {
+                        println!("Repair the list with: cfkv blog gc --fix");
+                    }
+                }
+            }
+            if report.fixed {
+                let event = blog_hook::BlogEvent {
+                    deleted: report.missing,
+                    ..blog_hook::BlogEvent::published(report.relisted)
+                };
+                run_blog_hook(blog_config, event, format).await;
+            }
         }
-        BlogCommands::List => {
-            let posts = publisher.list_posts().await?;
+        BlogCommands::List {
+            tag, author, page, ..
+        } => {
+            let mut posts = match (&tag, &author, page) {
+                (Some(tag), _, _) => publisher.list_posts_by_tag(tag).await?,
+                (None, Some(author), _) => publisher.list_posts_by_author(author).await?,
+                (None, None, Some(page)) => publisher.list_posts_page(page).await?,
+                (None, None, None) => publisher.list_posts().await?,
+            };
+            if let (Some(_), Some(author)) = (&tag, &author) {
+                posts.retain(|p| &p.author == author);
+            }
+            if let (true, Some(page)) = (tag.is_some() || author.is_some(), page) {
+                posts = posts
+                    .chunks(cfkv_blog::index::LIST_PAGE_SIZE)
+                    .nth(page)
+                    .map(|p| p.to_vec())
+                    .unwrap_or_default();
+            }
 
             if posts.is_empty() {
                 println!("{}", Formatter::format_text("No blog posts found", format));
@@ -642,7 +4761,7 @@ async fn handle_blog(
                 OutputFormat::Yaml => {
                     println!("{}", serde_yaml::to_string(&posts)?);
                 }
-                OutputFormat::Text => {
+                OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
                     println!("Found {} blog posts:\n", posts.len());
                     for post in posts {
                         println!("• {}", post.title);
@@ -654,12 +4773,84 @@ async fn handle_blog(
                 }
             }
         }
+        BlogCommands::Get { slug, draft } => {
+            let post = if draft {
+                publisher.get_draft(&slug).await?
+            } else {
+                publisher.get_post(&slug).await?
+            };
+            let post = match post {
+                Some(post) => post,
+                None => {
+                    let kind = if draft { "Draft" } else { "Post" };
+                    exit::fail(
+                        &format!("{} not found: {}", kind, slug),
+                        ErrorKind::NotFound,
+                        format,
+                    );
+                }
+            };
+
+            match format {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&post)?),
+                OutputFormat::Yaml => println!("{}", serde_yaml::to_string(&post)?),
+                OutputFormat::Text | OutputFormat::Table | OutputFormat::Gha => {
+                    if post.content.is_empty() {
+                        if let Some(html) = &post.html {
+                            print!("{}", html);
+                            return Ok(());
+                        }
+                    }
+                    print!("{}", post.to_markdown()?);
+                }
+            }
+        }
+        BlogCommands::Feed {
+            feed_format,
+            site_url,
+            title,
+            description,
+            max_entries,
+            post_path,
+            store,
+        } => {
+            let feed_config = FeedConfig {
+                site_url,
+                title,
+                description,
+                max_entries,
+                post_path,
+            };
+            let xml = publisher
+                .generate_feed(feed_format, &feed_config, store)
+                .await?;
+            if store {
+                println!(
+                    "{}",
+                    Formatter::format_success(
+                        &format!("Stored feed under: {}", feed_format.storage_key()),
+                        format
+                    )
+                );
+            } else {
+                print!("{}", xml);
+            }
+        }
+        BlogCommands::Preview { .. } => unreachable!(),
         BlogCommands::Delete { slug } => {
+            let targets = match publisher.get_post(&slug).await? {
+                Some(post) => vec![format!("{} ({}, {})", post.title, post.slug, post.date)],
+                None => vec![],
+            };
+            let question = format!("Delete post {} and remove it from the blog list?", slug);
+            confirm.ask(&question, &targets, format)?;
             publisher.delete_post(&slug).await?;
             println!(
                 "{}",
                 Formatter::format_success(&format!("Successfully deleted: {}", slug), format)
             );
+            let event = blog_hook::BlogEvent::removed(blog_hook::BlogEventKind::Delete, &slug);
+            run_blog_hook(blog_config, event, format).await;
         }
     }
 
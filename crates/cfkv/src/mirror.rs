@@ -0,0 +1,557 @@
+//! Mirroring a namespace into a SQL table for `cfkv mirror`
+//!
+//! Every key becomes a row of key, value, size, SHA-256, metadata and
+//! expiration, so values can be explored with plain SQL:
+//!
+//! ```sql
+//! CREATE TABLE kv (
+//!     key TEXT PRIMARY KEY,
+//!     value,                -- text when the value is UTF-8, else a blob
+//!     size INTEGER NOT NULL,
+//!     sha256 TEXT NOT NULL,
+//!     metadata TEXT,        -- JSON
+//!     expiration INTEGER,   -- Unix time
+//!     mirrored_at TEXT NOT NULL
+//! )
+//! ```
+//!
+//! Refreshes are incremental: rows whose hash, metadata and expiration still
+//! match are left alone, and rows of keys that no longer exist are deleted.
+//! SQLite files are written through the `sqlite3` command-line tool; D1
+//! databases through the Cloudflare API.
+
+use crate::progress::Progress;
+use cloudflare_kv::{
+    AuthCredentials, KeyMetadata, KvError, PaginationParams, PluginClient, Result,
+};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// Largest value copied into D1, whose rows are limited to 2 MB; larger
+/// values are mirrored with a NULL value
+pub const D1_MAX_VALUE_BYTES: usize = 1024 * 1024;
+
+/// Where `--to` points
+#[derive(Debug, PartialEq)]
+pub enum TargetUrl {
+    /// `sqlite://path/to/file.db`
+    Sqlite(PathBuf),
+    /// `d1://<database-id>`
+    D1(String),
+}
+
+impl TargetUrl {
+    pub fn parse(url: &str) -> Result<Self> {
+        if let Some(path) = url.strip_prefix("sqlite://") {
+            if !path.is_empty() {
+                return Ok(TargetUrl::Sqlite(PathBuf::from(path)));
+            }
+        }
+        if let Some(database_id) = url.strip_prefix("d1://") {
+            if !database_id.is_empty() {
+                return Ok(TargetUrl::D1(database_id.to_string()));
+            }
+        }
+        Err(KvError::InvalidConfig(format!(
+            "Unsupported mirror target '{}' (use sqlite://<file> or d1://<database-id>)",
+            url
+        )))
+    }
+}
+
+/// A SQL parameter
+#[derive(Clone, Debug, PartialEq)]
+pub enum SqlValue {
+    Null,
+    Integer(i64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+impl SqlValue {
+    /// The value as an SQLite literal
+    fn literal(&self) -> String {
+        match self {
+            SqlValue::Null => "NULL".to_string(),
+            SqlValue::Integer(n) => n.to_string(),
+            SqlValue::Text(text) => format!("'{}'", text.replace('\'', "''")),
+            SqlValue::Blob(bytes) => {
+                let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                format!("X'{}'", hex)
+            }
+        }
+    }
+
+    fn json(&self) -> Value {
+        match self {
+            SqlValue::Null | SqlValue::Blob(_) => Value::Null,
+            SqlValue::Integer(n) => (*n).into(),
+            SqlValue::Text(text) => text.clone().into(),
+        }
+    }
+}
+
+/// A statement with `?` placeholders
+#[derive(Debug, PartialEq)]
+pub struct Statement {
+    pub sql: String,
+    pub params: Vec<SqlValue>,
+}
+
+impl Statement {
+    /// The statement with its parameters inlined as literals
+    fn inline(&self) -> String {
+        let mut params = self.params.iter();
+        let mut sql = String::with_capacity(self.sql.len());
+        for c in self.sql.chars() {
+            match c {
+                '?' => sql.push_str(&params.next().unwrap_or(&SqlValue::Null).literal()),
+                c => sql.push(c),
+            }
+        }
+        sql
+    }
+}
+
+/// A database to mirror into
+pub enum Database {
+    Sqlite(PathBuf),
+    D1 {
+        http_client: reqwest::Client,
+        query_url: String,
+        credentials: AuthCredentials,
+    },
+}
+
+impl Database {
+    /// Connect to D1 database `database_id` of `account_id`
+    pub fn d1(account_id: &str, database_id: &str, credentials: AuthCredentials) -> Self {
+        Database::D1 {
+            http_client: reqwest::Client::new(),
+            query_url: format!(
+                "https://api.cloudflare.com/client/v4/accounts/{}/d1/database/{}/query",
+                account_id, database_id
+            ),
+            credentials,
+        }
+    }
+
+    /// Largest value stored in the `value` column
+    pub fn max_value_bytes(&self) -> Option<usize> {
+        match self {
+            Database::Sqlite(_) => None,
+            Database::D1 { .. } => Some(D1_MAX_VALUE_BYTES),
+        }
+    }
+
+    /// Whether binary values can be stored as blobs
+    pub fn supports_blobs(&self) -> bool {
+        matches!(self, Database::Sqlite(_))
+    }
+
+    /// Run statements, in one transaction where the database allows it
+    pub async fn execute(&self, statements: &[Statement]) -> Result<()> {
+        if statements.is_empty() {
+            return Ok(());
+        }
+        match self {
+            Database::Sqlite(path) => {
+                let mut script = String::from("BEGIN;\n");
+                for statement in statements {
+                    script.push_str(&statement.inline());
+                    script.push_str(";\n");
+                }
+                script.push_str("COMMIT;\n");
+                sqlite(path, &["-bail"], Some(&script))?;
+                Ok(())
+            }
+            Database::D1 { .. } => {
+                for statement in statements {
+                    self.d1_query(statement).await?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Rows of a query, as JSON objects
+    pub async fn query(&self, sql: &str) -> Result<Vec<Value>> {
+        match self {
+            Database::Sqlite(path) => {
+                let output = sqlite(path, &["-json", sql], None)?;
+                if output.trim().is_empty() {
+                    return Ok(vec![]);
+                }
+                Ok(serde_json::from_str(&output)?)
+            }
+            Database::D1 { .. } => {
+                let statement = Statement {
+                    sql: sql.to_string(),
+                    params: vec![],
+                };
+                self.d1_query(&statement).await
+            }
+        }
+    }
+
+    async fn d1_query(&self, statement: &Statement) -> Result<Vec<Value>> {
+        let Database::D1 {
+            http_client,
+            query_url,
+            credentials,
+        } = self
+        else {
+            unreachable!("d1_query on a SQLite database");
+        };
+        let params: Vec<Value> = statement.params.iter().map(SqlValue::json).collect();
+        let response = http_client
+            .post(query_url)
+            .header("Authorization", credentials.auth_header())
+            .json(&serde_json::json!({ "sql": statement.sql, "params": params }))
+            .send()
+            .await?;
+        let status = response.status();
+        let body: Value = response.json().await?;
+        if !status.is_success() || body["success"] == false {
+            let message = format!("D1 query failed: {} - {}", status, body["errors"]);
+            return Err(match status.as_u16() {
+                401 | 403 => KvError::AuthError(message),
+                429 => KvError::RateLimited(message),
+                _ => KvError::RequestFailed(message),
+            });
+        }
+        Ok(body["result"][0]["results"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default())
+    }
+}
+
+/// Run the `sqlite3` tool on `path`, feeding it `script` on stdin
+fn sqlite(path: &std::path::Path, args: &[&str], script: Option<&str>) -> Result<String> {
+    let mut child = Command::new("sqlite3")
+        .arg(path)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            KvError::InvalidConfig(format!(
+                "Could not run sqlite3 ({}); install the SQLite command-line tool",
+                e
+            ))
+        })?;
+    if let Some(script) = script {
+        child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(script.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(KvError::RequestFailed(format!(
+            "sqlite3 failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Check a table name is a plain identifier, as it is spliced into SQL
+pub fn check_table(table: &str) -> Result<()> {
+    let mut chars = table.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(KvError::InvalidConfig(format!(
+            "Invalid table name '{}': use letters, digits and underscores",
+            table
+        )))
+    }
+}
+
+pub fn create_table(table: &str) -> Statement {
+    Statement {
+        sql: format!(
+            "CREATE TABLE IF NOT EXISTS {} (key TEXT PRIMARY KEY, value, size INTEGER NOT NULL, \
+             sha256 TEXT NOT NULL, metadata TEXT, expiration INTEGER, mirrored_at TEXT NOT NULL)",
+            table
+        ),
+        params: vec![],
+    }
+}
+
+/// What decides whether a row is up to date
+#[derive(Debug, PartialEq)]
+pub struct Fingerprint {
+    pub sha256: String,
+    pub metadata: Option<String>,
+    pub expiration: Option<i64>,
+}
+
+impl Fingerprint {
+    fn of(sha256: &str, key: &KeyMetadata) -> Self {
+        Self {
+            sha256: sha256.to_string(),
+            metadata: key.metadata.as_ref().map(Value::to_string),
+            expiration: key.expiration.map(|at| at as i64),
+        }
+    }
+}
+
+pub fn select_fingerprints(table: &str) -> String {
+    format!("SELECT key, sha256, metadata, expiration FROM {}", table)
+}
+
+/// Fingerprints of the mirrored rows, by key
+pub fn parse_fingerprints(rows: &[Value]) -> HashMap<String, Fingerprint> {
+    rows.iter()
+        .filter_map(|row| {
+            let fingerprint = Fingerprint {
+                sha256: row["sha256"].as_str()?.to_string(),
+                metadata: row["metadata"].as_str().map(String::from),
+                expiration: row["expiration"].as_i64(),
+            };
+            Some((row["key"].as_str()?.to_string(), fingerprint))
+        })
+        .collect()
+}
+
+/// Outcome of a mirror run
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct MirrorReport {
+    pub added: usize,
+    pub updated: usize,
+    pub deleted: usize,
+    pub unchanged: usize,
+}
+
+/// Builds the statements that bring a table up to date
+pub struct Mirror<'a> {
+    table: &'a str,
+    existing: HashMap<String, Fingerprint>,
+    max_value_bytes: Option<usize>,
+    blobs: bool,
+    mirrored_at: String,
+    pub report: MirrorReport,
+}
+
+impl<'a> Mirror<'a> {
+    pub fn new(
+        table: &'a str,
+        existing: HashMap<String, Fingerprint>,
+        database: &Database,
+    ) -> Self {
+        Self {
+            table,
+            existing,
+            max_value_bytes: database.max_value_bytes(),
+            blobs: database.supports_blobs(),
+            mirrored_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            report: MirrorReport::default(),
+        }
+    }
+
+    /// The upsert for a key, or `None` when its row is up to date
+    pub fn row(&mut self, key: &KeyMetadata, value: &[u8]) -> Option<Statement> {
+        let sha256 = cloudflare_kv::value_hash(value);
+        let fingerprint = Fingerprint::of(&sha256, key);
+        match self.existing.remove(&key.name) {
+            Some(existing) if existing == fingerprint => {
+                self.report.unchanged += 1;
+                return None;
+            }
+            Some(_) => self.report.updated += 1,
+            None => self.report.added += 1,
+        }
+
+        let size = value.len() as i64;
+        let too_large = self.max_value_bytes.is_some_and(|max| value.len() > max);
+        let value = match std::str::from_utf8(value) {
+            _ if too_large => SqlValue::Null,
+            Ok(text) => SqlValue::Text(text.to_string()),
+            Err(_) if self.blobs => SqlValue::Blob(value.to_vec()),
+            Err(_) => SqlValue::Null,
+        };
+        let optional_text = |text: Option<String>| text.map_or(SqlValue::Null, SqlValue::Text);
+        Some(Statement {
+            sql: format!(
+                "INSERT OR REPLACE INTO {} (key, value, size, sha256, metadata, expiration, \
+                 mirrored_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+                self.table
+            ),
+            params: vec![
+                SqlValue::Text(key.name.clone()),
+                value,
+                SqlValue::Integer(size),
+                SqlValue::Text(sha256),
+                optional_text(fingerprint.metadata),
+                fingerprint
+                    .expiration
+                    .map_or(SqlValue::Null, SqlValue::Integer),
+                SqlValue::Text(self.mirrored_at.clone()),
+            ],
+        })
+    }
+
+    /// Deletes for mirrored keys under `prefix` that were not seen
+    pub fn finish(&mut self, prefix: &str) -> Vec<Statement> {
+        let mut gone: Vec<String> = self
+            .existing
+            .drain()
+            .map(|(key, _)| key)
+            .filter(|key| key.starts_with(prefix))
+            .collect();
+        gone.sort();
+        self.report.deleted = gone.len();
+        gone.into_iter()
+            .map(|key| Statement {
+                sql: format!("DELETE FROM {} WHERE key = ?", self.table),
+                params: vec![SqlValue::Text(key)],
+            })
+            .collect()
+    }
+}
+
+/// Bring `table` in line with the keys under `prefix`
+///
+/// Keys are listed a page at a time and each page is written in one
+/// transaction, so an interrupted run leaves a usable, partly refreshed table.
+pub async fn run(
+    client: &PluginClient,
+    database: &Database,
+    table: &str,
+    prefix: &str,
+    concurrency: usize,
+    progress: &Progress,
+) -> Result<MirrorReport> {
+    check_table(table)?;
+    database.execute(&[create_table(table)]).await?;
+    let existing = parse_fingerprints(&database.query(&select_fingerprints(table)).await?);
+    let mut mirror = Mirror::new(table, existing, database);
+
+    let mut cursor = None;
+    loop {
+        let mut params = PaginationParams::new().with_prefix(prefix);
+        if let Some(cursor) = cursor.take() {
+            params = params.with_cursor(cursor);
+        }
+        let page = client.client().list(Some(params)).await?;
+        let values: Vec<Option<Vec<u8>>> = stream::iter(&page.keys)
+            .map(|key| client.get_bytes(&key.name))
+            .buffered(concurrency.max(1))
+            .try_collect()
+            .await?;
+
+        let mut statements = Vec::new();
+        for (key, value) in page.keys.iter().zip(values) {
+            // Deleted between listing and fetching; dropped as gone below
+            let Some(value) = value else { continue };
+            progress.inc(value.len() as u64);
+            statements.extend(mirror.row(key, &value));
+        }
+        database.execute(&statements).await?;
+
+        match page.cursor {
+            Some(next) if !page.list_complete && !next.is_empty() => cursor = Some(next),
+            _ => break,
+        }
+    }
+
+    let deletes = mirror.finish(prefix);
+    database.execute(&deletes).await?;
+    Ok(mirror.report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str, metadata: Option<Value>) -> KeyMetadata {
+        KeyMetadata {
+            name: name.to_string(),
+            expiration: None,
+            metadata,
+        }
+    }
+
+    #[test]
+    fn test_parse_target_and_inline() {
+        assert_eq!(
+            TargetUrl::parse("sqlite://dump.db").unwrap(),
+            TargetUrl::Sqlite(PathBuf::from("dump.db"))
+        );
+        assert_eq!(
+            TargetUrl::parse("d1://abc").unwrap(),
+            TargetUrl::D1("abc".to_string())
+        );
+        assert!(TargetUrl::parse("postgres://db").is_err());
+        assert!(TargetUrl::parse("sqlite://").is_err());
+        assert!(check_table("kv_2").is_ok());
+        assert!(check_table("kv; DROP TABLE kv").is_err());
+
+        let statement = Statement {
+            sql: "INSERT INTO kv VALUES (?, ?, ?, ?)".to_string(),
+            params: vec![
+                SqlValue::Text("it's".to_string()),
+                SqlValue::Blob(vec![0, 255]),
+                SqlValue::Integer(3),
+                SqlValue::Null,
+            ],
+        };
+        assert_eq!(
+            statement.inline(),
+            "INSERT INTO kv VALUES ('it''s', X'00ff', 3, NULL)"
+        );
+    }
+
+    #[test]
+    fn test_incremental_rows() {
+        let database = Database::Sqlite(PathBuf::from("unused.db"));
+        let unchanged = Fingerprint::of(&cloudflare_kv::value_hash(b"same"), &key("a", None));
+        let changed = Fingerprint::of(&cloudflare_kv::value_hash(b"old"), &key("b", None));
+        let existing = HashMap::from([
+            ("a".to_string(), unchanged),
+            ("b".to_string(), changed),
+            ("gone".to_string(), Fingerprint::of("x", &key("gone", None))),
+            (
+                "other:gone".to_string(),
+                Fingerprint::of("x", &key("other:gone", None)),
+            ),
+        ]);
+        let mut mirror = Mirror::new("kv", existing, &database);
+
+        assert!(mirror.row(&key("a", None), b"same").is_none());
+        let update = mirror.row(&key("b", None), b"new").unwrap();
+        assert_eq!(update.params[2], SqlValue::Integer(3));
+        let insert = mirror
+            .row(&key("c", Some(serde_json::json!({"v": 1}))), &[0xff])
+            .unwrap();
+        assert_eq!(insert.params[1], SqlValue::Blob(vec![0xff]));
+        assert_eq!(insert.params[4], SqlValue::Text(r#"{"v":1}"#.to_string()));
+
+        let deletes = mirror.finish("g");
+        assert_eq!(deletes.len(), 1);
+        assert_eq!(deletes[0].params, [SqlValue::Text("gone".to_string())]);
+        assert_eq!(
+            mirror.report,
+            MirrorReport {
+                added: 1,
+                updated: 1,
+                deleted: 1,
+                unchanged: 1,
+            }
+        );
+    }
+}
@@ -0,0 +1,302 @@
+//! Webhook notifications for finished commands and agent jobs
+//!
+//! `cfkv --notify <URL> <command>` and an agent job's `notify` post a
+//! summary once the command or job run finishes. The payload suits the
+//! webhook:
+//!
+//! - Slack (`hooks.slack.com`): `{"text": "..."}`
+//! - Discord (`discord.com/api/webhooks/...`): `{"content": "..."}`
+//! - anything else: the summary as JSON, with the same `text`:
+//!
+//! ```json
+//! {"text": "...", "name": "job nightly-export", "success": false,
+//!  "duration_ms": 8123, "exit_code": 3, "error": null,
+//!  "counts": {"runs": 12, "failures": 1, "consecutive_failures": 1}}
+//! ```
+//!
+//! A notification that cannot be sent is logged as a warning; it never
+//! changes the command's result.
+
+use crate::exit::ErrorKind;
+use crate::table::format_duration;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// How long a webhook may take to answer
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Which runs send a notification
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifyOn {
+    /// Every run
+    #[default]
+    Always,
+    /// Failed runs only
+    Failure,
+}
+
+/// Payload style of a webhook, from its URL
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Style {
+    Slack,
+    Discord,
+    Generic,
+}
+
+impl Style {
+    fn of(url: &str) -> Self {
+        let Ok(url) = Url::parse(url) else {
+            return Style::Generic;
+        };
+        match url.host_str() {
+            Some("hooks.slack.com") => Style::Slack,
+            Some("discord.com" | "discordapp.com") if url.path().starts_with("/api/webhooks/") => {
+                Style::Discord
+            }
+            _ => Style::Generic,
+        }
+    }
+}
+
+/// Check a notification URL is an http(s) URL
+pub fn parse_url(url: &str) -> Result<String, String> {
+    match Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => Ok(url.to_string()),
+        _ => Err(format!("'{}' is not an http(s) URL", url)),
+    }
+}
+
+/// How a command or job run went
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Summary {
+    /// `cfkv batch export` or `job nightly-export`
+    pub name: String,
+    pub success: bool,
+    pub duration_ms: u64,
+    /// Process exit code, when the run exited normally
+    pub exit_code: Option<i32>,
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub counts: BTreeMap<&'static str, u64>,
+}
+
+impl Summary {
+    /// One line for chat webhooks
+    pub fn text(&self) -> String {
+        let status = if self.success { "succeeded" } else { "failed" };
+        let duration = match self.duration_ms / 1000 {
+            0 => format!("{}ms", self.duration_ms),
+            seconds => format_duration(seconds as i64),
+        };
+        let mut text = format!("{} {} in {}", self.name, status, duration);
+        match (&self.error, self.exit_code) {
+            (Some(error), _) => text.push_str(&format!(": {}", error)),
+            (None, Some(code)) if code != 0 => text.push_str(&format!(" (exit code {})", code)),
+            _ => {}
+        }
+        if !self.counts.is_empty() {
+            let counts: Vec<String> = self
+                .counts
+                .iter()
+                .map(|(name, count)| format!("{}: {}", name.replace('_', " "), count))
+                .collect();
+            text.push_str(&format!(" [{}]", counts.join(", ")));
+        }
+        text
+    }
+}
+
+/// A webhook and the runs it hears about
+#[derive(Clone, Debug, PartialEq)]
+pub struct Notifier {
+    pub url: String,
+    pub on: NotifyOn,
+}
+
+impl Notifier {
+    pub fn new(url: String, on: NotifyOn) -> Self {
+        Self { url, on }
+    }
+
+    fn payload(&self, summary: &Summary) -> serde_json::Value {
+        match Style::of(&self.url) {
+            Style::Slack => json!({ "text": summary.text() }),
+            Style::Discord => json!({ "content": summary.text() }),
+            Style::Generic => {
+                let mut payload = serde_json::to_value(summary).expect("summary is JSON");
+                payload["text"] = summary.text().into();
+                payload
+            }
+        }
+    }
+
+    /// Send the summary, unless this notifier only hears about failures
+    pub async fn notify(&self, summary: &Summary) {
+        if summary.success && self.on == NotifyOn::Failure {
+            return;
+        }
+        if let Err(e) = self.send(summary).await {
+            warn!(url = %self.url, error = %e, "Could not send notification");
+            eprintln!("Warning: could not send notification: {}", e);
+        }
+    }
+
+    async fn send(&self, summary: &Summary) -> Result<(), String> {
+        let client = reqwest::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let response = client
+            .post(&self.url)
+            .json(&self.payload(summary))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(format!("webhook returned {}: {}", status, text.trim()));
+        }
+        Ok(())
+    }
+}
+
+/// The one-shot command being watched by `--notify`
+struct Watched {
+    notifier: Notifier,
+    name: String,
+    started: Instant,
+}
+
+static WATCHED: OnceLock<Watched> = OnceLock::new();
+
+/// Notify about the current command once it finishes
+pub fn watch(notifier: Notifier, name: String) {
+    let _ = WATCHED.set(Watched {
+        notifier,
+        name,
+        started: Instant::now(),
+    });
+}
+
+impl Watched {
+    fn summary(&self, failure: Option<(&str, ErrorKind)>) -> Summary {
+        Summary {
+            name: self.name.clone(),
+            success: failure.is_none(),
+            duration_ms: self.started.elapsed().as_millis() as u64,
+            exit_code: Some(failure.map_or(0, |(_, kind)| kind.code())),
+            error: failure.map(|(message, _)| message.to_string()),
+            counts: BTreeMap::new(),
+        }
+    }
+}
+
+/// Report the watched command's success
+pub async fn command_succeeded() {
+    if let Some(watched) = WATCHED.get() {
+        watched.notifier.notify(&watched.summary(None)).await;
+    }
+}
+
+/// Report the watched command's failure, just before the process exits
+///
+/// Failures exit from synchronous code, so the notification is sent from a
+/// runtime of its own on a separate thread.
+pub fn command_failed(message: &str, kind: ErrorKind) {
+    let Some(watched) = WATCHED.get() else {
+        return;
+    };
+    let summary = watched.summary(Some((message, kind)));
+    let notifier = watched.notifier.clone();
+    let sent = std::thread::spawn(move || {
+        if let Ok(runtime) = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        {
+            runtime.block_on(notifier.notify(&summary));
+        }
+    });
+    let _ = sent.join();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(success: bool) -> Summary {
+        Summary {
+            name: "job nightly-export".to_string(),
+            success,
+            duration_ms: 192_500,
+            exit_code: Some(if success { 0 } else { 3 }),
+            error: None,
+            counts: BTreeMap::from([("runs", 12), ("consecutive_failures", 1)]),
+        }
+    }
+
+    #[test]
+    fn test_text() {
+        assert_eq!(
+            summary(false).text(),
+            "job nightly-export failed in 3m 12s (exit code 3) [consecutive failures: 1, runs: 12]"
+        );
+        let quick = Summary {
+            name: "cfkv put".to_string(),
+            duration_ms: 42,
+            error: Some("Key not found".to_string()),
+            counts: BTreeMap::new(),
+            ..summary(false)
+        };
+        assert_eq!(quick.text(), "cfkv put failed in 42ms: Key not found");
+    }
+
+    #[test]
+    fn test_payloads() {
+        let notifier = |url: &str| Notifier::new(url.to_string(), NotifyOn::Always);
+        let summary = summary(true);
+
+        let slack = notifier("https://hooks.slack.com/services/T/B/x").payload(&summary);
+        assert_eq!(slack, json!({ "text": summary.text() }));
+        let discord = notifier("https://discord.com/api/webhooks/1/x").payload(&summary);
+        assert_eq!(discord, json!({ "content": summary.text() }));
+
+        let generic = notifier("https://alerts.example.com/cfkv").payload(&summary);
+        assert_eq!(generic["text"], summary.text());
+        assert_eq!(generic["success"], true);
+        assert_eq!(generic["counts"]["runs"], 12);
+    }
+
+    #[tokio::test]
+    async fn test_notify() {
+        use wiremock::matchers::{body_partial_json, method};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_partial_json(json!({ "success": false })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let always = Notifier::new(server.uri(), NotifyOn::Always);
+        let failures = Notifier::new(server.uri(), NotifyOn::Failure);
+        always.notify(&summary(false)).await;
+        failures.notify(&summary(false)).await;
+        // Skipped: a success, for a notifier that only hears about failures
+        failures.notify(&summary(true)).await;
+    }
+
+    #[test]
+    fn test_parse_url() {
+        assert!(parse_url("https://hooks.slack.com/services/x").is_ok());
+        assert!(parse_url("hooks.slack.com/services/x").is_err());
+    }
+}
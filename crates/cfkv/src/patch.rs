@@ -0,0 +1,102 @@
+//! Patch documents for `cfkv patch`
+//!
+//! A patch is either an RFC 6902 JSON Patch (an array of operations) or an
+//! RFC 7386 JSON Merge Patch (any other JSON value, usually an object):
+//!
+//! ```json
+//! [{"op": "replace", "path": "/status", "value": "done"}]
+//! {"status": "done", "draft": null}
+//! ```
+
+use cloudflare_kv::{KvError, Result};
+use serde_json::Value;
+
+/// A parsed patch document
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonPatch {
+    /// RFC 6902 operations, applied in order and all-or-nothing
+    Operations(json_patch::Patch),
+    /// RFC 7386 merge patch; `null` members remove fields
+    Merge(Value),
+}
+
+impl JsonPatch {
+    /// Parse a patch, choosing the kind from its shape
+    pub fn parse(input: &str) -> Result<Self> {
+        let value: Value = serde_json::from_str(input)
+            .map_err(|e| KvError::PatchError(format!("patch is not valid JSON: {}", e)))?;
+
+        if value.is_array() {
+            let operations = serde_json::from_value(value)
+                .map_err(|e| KvError::PatchError(format!("invalid JSON Patch: {}", e)))?;
+            Ok(JsonPatch::Operations(operations))
+        } else {
+            Ok(JsonPatch::Merge(value))
+        }
+    }
+
+    /// Apply the patch to a value
+    pub fn apply(&self, target: &mut Value) -> Result<()> {
+        match self {
+            JsonPatch::Operations(operations) => json_patch::patch(target, operations)
+                .map_err(|e| KvError::PatchError(e.to_string())),
+            JsonPatch::Merge(patch) => {
+                json_patch::merge(target, patch);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_json_patch_operations() {
+        let patch = JsonPatch::parse(
+            r#"[{"op": "replace", "path": "/status", "value": "done"},
+                {"op": "add", "path": "/tags/-", "value": "b"}]"#,
+        )
+        .unwrap();
+        assert!(matches!(patch, JsonPatch::Operations(_)));
+
+        let mut value = json!({"status": "open", "tags": ["a"]});
+        patch.apply(&mut value).unwrap();
+        assert_eq!(value, json!({"status": "done", "tags": ["a", "b"]}));
+    }
+
+    #[test]
+    fn test_json_patch_is_atomic() {
+        let patch = JsonPatch::parse(
+            r#"[{"op": "replace", "path": "/status", "value": "done"},
+                {"op": "remove", "path": "/missing"}]"#,
+        )
+        .unwrap();
+
+        let mut value = json!({"status": "open"});
+        assert!(matches!(
+            patch.apply(&mut value),
+            Err(KvError::PatchError(_))
+        ));
+        assert_eq!(value, json!({"status": "open"}));
+    }
+
+    #[test]
+    fn test_merge_patch() {
+        let patch =
+            JsonPatch::parse(r#"{"status": "done", "draft": null, "n": {"b": 2}}"#).unwrap();
+        assert!(matches!(patch, JsonPatch::Merge(_)));
+
+        let mut value = json!({"status": "open", "draft": true, "n": {"a": 1}});
+        patch.apply(&mut value).unwrap();
+        assert_eq!(value, json!({"status": "done", "n": {"a": 1, "b": 2}}));
+    }
+
+    #[test]
+    fn test_invalid_patch() {
+        assert!(JsonPatch::parse("status=done").is_err());
+        assert!(JsonPatch::parse(r#"[{"op": "frobnicate", "path": "/a"}]"#).is_err());
+    }
+}
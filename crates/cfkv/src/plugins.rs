@@ -0,0 +1,344 @@
+//! Plugin chain construction from the `plugins` config section
+//!
+//! Values written by the CLI (`put`, `batch import`) run through the chain
+//! before upload and values read by `get` run back through it in reverse.
+//! Blog commands bypass the chain because their keys are read by Workers
+//! that do not know about cfkv plugins.
+//!
+//! Third-party plugins are shared libraries installed with
+//! `cfkv plugin install`; see `cloudflare_kv::dynamic` for the ABI they export.
+
+use crate::config::{Config, PluginConfig, Storage};
+use cloudflare_kv::compression::COMPRESSION_PLUGIN_NAME;
+use cloudflare_kv::encryption::ENCRYPTION_PLUGIN_NAME;
+use cloudflare_kv::{
+    AuthCredentials, CompressionAlgorithm, CompressionPlugin, DynamicPlugin, EncryptionPlugin,
+    KvError, KvPlugin, OffloadPlugin, PluginRegistry, R2Client, Result,
+};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Names of the plugins built into cfkv
+pub const BUILTIN_PLUGINS: &[&str] = &[COMPRESSION_PLUGIN_NAME, ENCRYPTION_PLUGIN_NAME];
+
+/// Create an uninitialized built-in plugin by name
+pub fn create_builtin(name: &str) -> Option<Box<dyn KvPlugin>> {
+    match name {
+        COMPRESSION_PLUGIN_NAME => Some(Box::new(CompressionPlugin::new())),
+        ENCRYPTION_PLUGIN_NAME => Some(Box::new(EncryptionPlugin::new())),
+        _ => None,
+    }
+}
+
+/// Build the plugin chain for an invocation
+///
+/// Enabled config entries are applied in list order. A decompress-only
+/// compression plugin is placed first unless the config positions compression
+/// itself, so compressed values are always readable. The `--compress` flag and
+/// a storage's `encryption_key` configure the matching plugin, adding it to the
+/// end of the chain if the config doesn't list it. A storage's `r2_bucket`
+/// adds R2 offloading after everything else.
+pub async fn build_registry(
+    plugins: &[PluginConfig],
+    storage: Option<&Storage>,
+    compress: Option<CompressionAlgorithm>,
+) -> Result<PluginRegistry> {
+    let mut registry = PluginRegistry::new();
+
+    let enabled: Vec<&PluginConfig> = plugins.iter().filter(|p| p.enabled).collect();
+    if !enabled.iter().any(|p| p.name == COMPRESSION_PLUGIN_NAME) {
+        registry.register(Box::new(CompressionPlugin::new()));
+    }
+
+    for entry in enabled {
+        let mut plugin = match &entry.path {
+            Some(path) => Box::new(DynamicPlugin::load(path)?),
+            None => create_builtin(&entry.name).ok_or_else(|| unknown_plugin(&entry.name))?,
+        };
+        if !entry.config.is_null() {
+            init_plugin(plugin.as_mut(), &entry.name, entry.config.clone()).await?;
+        }
+        registry.register(plugin);
+    }
+
+    if let Some(algorithm) = compress {
+        configure(
+            &mut registry,
+            COMPRESSION_PLUGIN_NAME,
+            json!({ "algorithm": algorithm.as_str() }),
+        )
+        .await?;
+    }
+
+    if let Some(key) = storage.and_then(|s| s.encryption_key.as_deref()) {
+        configure(&mut registry, ENCRYPTION_PLUGIN_NAME, json!({ "key": key })).await?;
+    }
+
+    // Last, so objects hold values exactly as they would be stored in KV
+    if let Some(storage) = storage {
+        if let Some(bucket) = &storage.r2_bucket {
+            let credentials = AuthCredentials::token(storage.token()?);
+            let r2 = R2Client::new(&storage.account_id, bucket, credentials);
+            let plugin =
+                OffloadPlugin::new(r2).with_object_prefix(format!("{}/", storage.namespace_id));
+            registry.register(Box::new(plugin));
+        }
+    }
+
+    Ok(registry)
+}
+
+/// Initialize a registered plugin with extra config, registering it if missing
+async fn configure(registry: &mut PluginRegistry, name: &str, config: Value) -> Result<()> {
+    if let Some(plugin) = registry.get_mut(name) {
+        return init_plugin(plugin.as_mut(), name, config).await;
+    }
+
+    let mut plugin = create_builtin(name).ok_or_else(|| unknown_plugin(name))?;
+    init_plugin(plugin.as_mut(), name, config).await?;
+    registry.register(plugin);
+    Ok(())
+}
+
+/// Directory installed plugin libraries are copied into
+pub fn plugins_dir() -> Result<PathBuf> {
+    Ok(Config::config_dir()?.join("cfkv").join("plugins"))
+}
+
+/// Install a plugin library into the plugins directory
+///
+/// The library is loaded first so a file that doesn't implement the plugin ABI
+/// is rejected before anything is copied. Returns the config entry to save.
+pub fn install(path: &Path) -> Result<PluginConfig> {
+    install_into(path, &plugins_dir()?)
+}
+
+fn install_into(path: &Path, dir: &Path) -> Result<PluginConfig> {
+    let name = DynamicPlugin::load(path)?.metadata().name;
+    if create_builtin(&name).is_some() {
+        return Err(KvError::InvalidConfig(format!(
+            "Plugin name '{}' is reserved for a built-in plugin",
+            name
+        )));
+    }
+
+    let file_name = path.file_name().ok_or_else(|| {
+        KvError::InvalidConfig(format!("Invalid plugin path: {}", path.display()))
+    })?;
+    fs::create_dir_all(dir)?;
+    let target = dir.join(file_name);
+    // Reinstalling from the plugins directory itself must not copy the file
+    // onto itself, which truncates it
+    let source = fs::canonicalize(path)?;
+    if fs::canonicalize(&target).ok().as_ref() != Some(&source) {
+        fs::copy(&source, &target)?;
+    }
+
+    Ok(PluginConfig {
+        name,
+        enabled: true,
+        config: Value::Null,
+        path: Some(target),
+    })
+}
+
+fn unknown_plugin(name: &str) -> KvError {
+    KvError::InvalidConfig(format!(
+        "Unknown plugin: {} (available: {})",
+        name,
+        BUILTIN_PLUGINS.join(", ")
+    ))
+}
+
+async fn init_plugin(plugin: &mut dyn KvPlugin, name: &str, config: Value) -> Result<()> {
+    plugin
+        .init(config)
+        .await
+        .map_err(|e| KvError::PluginError(format!("{}: {}", name, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(registry: &PluginRegistry) -> Vec<String> {
+        registry.list().into_iter().map(|m| m.name).collect()
+    }
+
+    fn plugin(name: &str, config: Value) -> PluginConfig {
+        PluginConfig {
+            name: name.to_string(),
+            enabled: true,
+            config,
+            path: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_default_registry_decompresses() {
+        let registry = build_registry(&[], None, None).await.unwrap();
+        assert_eq!(names(&registry), vec!["compression"]);
+    }
+
+    #[tokio::test]
+    async fn test_config_order_is_preserved() {
+        let key = EncryptionPlugin::generate_key();
+        let plugins = vec![
+            plugin("encryption", json!({ "key": key })),
+            plugin("compression", json!({ "algorithm": "gzip" })),
+        ];
+        let registry = build_registry(&plugins, None, None).await.unwrap();
+        assert_eq!(names(&registry), vec!["encryption", "compression"]);
+    }
+
+    #[tokio::test]
+    async fn test_disabled_and_unknown_plugins() {
+        let mut disabled = plugin("encryption", Value::Null);
+        disabled.enabled = false;
+        let registry = build_registry(&[disabled], None, None).await.unwrap();
+        assert_eq!(names(&registry), vec!["compression"]);
+
+        let unknown = plugin("rot13", Value::Null);
+        assert!(build_registry(&[unknown], None, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_storage_key_and_compress_flag() {
+        let storage = Storage {
+            name: "prod".to_string(),
+            encryption_key: Some(EncryptionPlugin::generate_key()),
+            ..Default::default()
+        };
+        let registry = build_registry(&[], Some(&storage), Some(CompressionAlgorithm::Zstd))
+            .await
+            .unwrap();
+        assert_eq!(names(&registry), vec!["compression", "encryption"]);
+
+        let value = "compress me ".repeat(200);
+        let (stored, metadata) = registry
+            .pre_store_tagged("key", value.as_bytes(), None)
+            .await
+            .unwrap();
+        assert!(stored.len() < value.len());
+        assert_eq!(metadata, Some(json!({ "cfkv": { "compression": "zstd" } })));
+        let metadata = async { Ok(metadata) };
+        assert_eq!(
+            registry
+                .post_retrieve_tagged("key", &stored, || metadata)
+                .await
+                .unwrap(),
+            value.as_bytes()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unloadable_dynamic_plugin() {
+        let mut entry = plugin("custom", Value::Null);
+        entry.path = Some(PathBuf::from("/nonexistent/libcfkv_custom.so"));
+        assert!(build_registry(&[entry], None, None).await.is_err());
+        assert!(install(Path::new("/nonexistent/libcfkv_custom.so")).is_err());
+    }
+
+    /// A plugin that stores values reversed and rejects empty ones
+    const REVERSE_PLUGIN: &str = r#"
+#include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef struct { uint8_t *ptr; size_t len; size_t cap; } CfkvBuffer;
+
+static CfkvBuffer buffer(const uint8_t *data, size_t len) {
+    CfkvBuffer buf = { malloc(len + 1), len, len + 1 };
+    memcpy(buf.ptr, data, len);
+    return buf;
+}
+
+uint32_t cfkv_plugin_abi_version(void) { return 1; }
+
+CfkvBuffer cfkv_plugin_metadata(void) {
+    const char *json = "{\"name\": \"reverse\", \"version\": \"1.0.0\"}";
+    return buffer((const uint8_t *)json, strlen(json));
+}
+
+int32_t cfkv_plugin_init(const uint8_t *config, size_t len, CfkvBuffer *err) { return 0; }
+
+static int32_t reverse(const uint8_t *value, size_t len, CfkvBuffer *out) {
+    *out = buffer(value, len);
+    for (size_t i = 0; i < len; i++) out->ptr[i] = value[len - 1 - i];
+    return 0;
+}
+
+int32_t cfkv_plugin_pre_store(const uint8_t *key, size_t key_len,
+                              const uint8_t *value, size_t len, CfkvBuffer *out) {
+    return reverse(value, len, out);
+}
+
+int32_t cfkv_plugin_post_retrieve(const uint8_t *key, size_t key_len,
+                                  const uint8_t *value, size_t len, CfkvBuffer *out) {
+    return reverse(value, len, out);
+}
+
+int32_t cfkv_plugin_validate(const uint8_t *key, size_t key_len,
+                             const uint8_t *value, size_t len, CfkvBuffer *err) {
+    return len > 0;
+}
+
+void cfkv_plugin_free(CfkvBuffer buf) { free(buf.ptr); }
+"#;
+
+    /// Build `REVERSE_PLUGIN` with the system C compiler
+    fn build_reverse_plugin(dir: &Path) -> PathBuf {
+        let source = dir.join("reverse.c");
+        let library = dir.join(format!(
+            "{}cfkv_reverse{}",
+            std::env::consts::DLL_PREFIX,
+            std::env::consts::DLL_SUFFIX
+        ));
+        fs::write(&source, REVERSE_PLUGIN).unwrap();
+        let status = std::process::Command::new("cc")
+            .args(["-shared", "-fPIC", "-o"])
+            .arg(&library)
+            .arg(&source)
+            .status()
+            .expect("a C compiler is needed to build the test plugin");
+        assert!(status.success());
+        library
+    }
+
+    #[tokio::test]
+    async fn test_install_and_load_dynamic_plugin() {
+        let dir = std::env::temp_dir().join(format!("cfkv-plugin-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let library = build_reverse_plugin(&dir);
+        let plugins = dir.join("plugins");
+
+        let entry = install_into(&library, &plugins).unwrap();
+        assert_eq!(entry.name, "reverse");
+        let installed = entry.path.clone().unwrap();
+        let size = fs::metadata(&library).unwrap().len();
+        assert_eq!(fs::metadata(&installed).unwrap().len(), size);
+
+        // Installing the installed copy again leaves it intact
+        install_into(&installed, &plugins).unwrap();
+        assert_eq!(fs::metadata(&installed).unwrap().len(), size);
+
+        let registry = build_registry(&[entry], None, None).await.unwrap();
+        assert_eq!(names(&registry), vec!["compression", "reverse"]);
+        let stored = registry.pre_store("key", b"hello").await.unwrap();
+        assert_eq!(stored, b"olleh");
+        assert_eq!(
+            registry.post_retrieve("key", &stored).await.unwrap(),
+            b"hello"
+        );
+        assert!(registry.pre_store("key", b"").await.is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_invalid_plugin_config() {
+        let plugins = vec![plugin("compression", json!({ "algorithm": "lz4" }))];
+        assert!(build_registry(&plugins, None, None).await.is_err());
+    }
+}
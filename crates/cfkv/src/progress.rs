@@ -0,0 +1,99 @@
+//! Progress reporting for bulk operations
+//!
+//! Progress is drawn on stderr, and only when stderr is a terminal, so piped
+//! output and scripts are unaffected. Operations that walk a namespace do not
+//! know the key count up front; they show a spinner with running totals
+//! instead of a bar.
+
+use crate::formatter::Formatter;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+const BAR_TEMPLATE: &str = "{prefix} [{bar:30}] {pos}/{len} keys, {msg} ({per_sec}, ETA {eta})";
+const SPINNER_TEMPLATE: &str = "{spinner} {prefix} {pos} keys, {msg} ({per_sec}, {elapsed})";
+
+/// Keys and bytes processed by a bulk operation
+pub struct Progress {
+    bar: ProgressBar,
+    bytes: AtomicU64,
+}
+
+impl Progress {
+    /// Progress for `total` keys, or a spinner when the total is unknown.
+    /// Nothing is drawn unless `enabled` and stderr is a terminal.
+    pub fn new(label: &str, total: Option<u64>, enabled: bool) -> Self {
+        let (bar, template) = match total {
+            Some(total) => (ProgressBar::new(total), BAR_TEMPLATE),
+            None => (ProgressBar::new_spinner(), SPINNER_TEMPLATE),
+        };
+        bar.set_draw_target(ProgressDrawTarget::hidden());
+        if let Ok(style) = ProgressStyle::with_template(template) {
+            bar.set_style(style.progress_chars("=> "));
+        }
+        bar.set_prefix(label.to_string());
+        bar.set_message(Formatter::human_size(0));
+        if enabled && std::io::stderr().is_terminal() {
+            bar.set_draw_target(ProgressDrawTarget::stderr());
+            bar.enable_steady_tick(Duration::from_millis(120));
+        }
+
+        Self {
+            bar,
+            bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// Record one processed key of `bytes` bytes
+    pub fn inc(&self, bytes: u64) {
+        let total = self.bytes.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        self.bar.set_message(Formatter::human_size(total));
+        self.bar.inc(1);
+    }
+
+    /// Print a line without tearing the progress display
+    pub fn println(&self, line: &str) {
+        self.bar.suspend(|| println!("{}", line));
+    }
+
+    /// Print a line on stderr without tearing the progress display
+    pub fn eprintln(&self, line: &str) {
+        self.bar.suspend(|| eprintln!("{}", line));
+    }
+
+    /// Remove the progress display, e.g. before printing an error
+    pub fn clear(&self) {
+        self.bar.finish_and_clear();
+    }
+
+    /// Bytes processed so far
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        // Leave the terminal clean for the command's own output
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_when_hidden() {
+        let progress = Progress::new("Importing", Some(3), false);
+        progress.inc(100);
+        progress.inc(24);
+        assert_eq!(progress.bar.position(), 2);
+        assert_eq!(progress.bytes(), 124);
+
+        let spinner = Progress::new("Searching", None, false);
+        spinner.inc(0);
+        assert_eq!(spinner.bar.position(), 1);
+    }
+}
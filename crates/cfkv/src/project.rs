@@ -0,0 +1,181 @@
+//! Project files (`.cfkv.toml`) with per-repository defaults
+//!
+//! cfkv looks for `.cfkv.toml` in the working directory and each of its
+//! parents, and uses the first one found:
+//!
+//! ```toml
+//! storage = "staging"      # storage used unless --storage is given
+//! namespace = "cache"      # namespace alias of that storage
+//! key_prefix = "shop/"     # added to every key, after the storage's own
+//! format = "json"          # output format unless --format is given
+//! ```
+//!
+//! Project files are meant to be committed, so they only name storages
+//! defined in the user's config and never hold credentials. Values may use
+//! `${VAR}` placeholders for environment variables, and `account_id`,
+//! `namespace_id` and `api_token` may be given as a placeholder alone, for
+//! projects whose CI passes credentials in the environment:
+//!
+//! ```toml
+//! storage = "${DEPLOY_ENV}"
+//! api_token = "${CF_API_TOKEN}"
+//! ```
+
+use crate::config::expand_with;
+use crate::formatter::OutputFormat;
+use cloudflare_kv::{KvError, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+pub const FILE_NAME: &str = ".cfkv.toml";
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectConfig {
+    pub storage: Option<String>,
+    pub namespace: Option<String>,
+    pub key_prefix: Option<String>,
+    pub format: Option<String>,
+    /// Used when no storage is selected, like `--account-id`
+    pub account_id: Option<String>,
+    pub namespace_id: Option<String>,
+    pub api_token: Option<String>,
+}
+
+/// Whether a value is a single `${VAR}` placeholder
+fn is_placeholder(value: &str) -> bool {
+    value
+        .strip_prefix("${")
+        .and_then(|rest| rest.strip_suffix('}'))
+        .is_some_and(|name| {
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+}
+
+impl ProjectConfig {
+    fn parse(
+        content: &str,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> std::result::Result<Self, String> {
+        let mut project: Self =
+            toml::from_str(content).map_err(|e| e.to_string().trim().to_string())?;
+        for (field, value) in [
+            ("account_id", &project.account_id),
+            ("namespace_id", &project.namespace_id),
+            ("api_token", &project.api_token),
+        ] {
+            if value.as_deref().is_some_and(|value| !is_placeholder(value)) {
+                return Err(format!(
+                    "{} must be an environment variable placeholder such as \"${{CF_API_TOKEN}}\"; project files never hold credentials",
+                    field
+                ));
+            }
+        }
+        for (field, value) in [
+            ("storage", &mut project.storage),
+            ("namespace", &mut project.namespace),
+            ("key_prefix", &mut project.key_prefix),
+            ("format", &mut project.format),
+            ("account_id", &mut project.account_id),
+            ("namespace_id", &mut project.namespace_id),
+            ("api_token", &mut project.api_token),
+        ] {
+            if let Some(value) = value {
+                *value = expand_with(value, &lookup).map_err(|e| format!("{}: {}", field, e))?;
+            }
+        }
+        if let Some(format) = &project.format {
+            if OutputFormat::from_str(format).is_none() {
+                return Err(format!(
+                    "unknown format '{}' (json, yaml, text, table or gha)",
+                    format
+                ));
+            }
+        }
+        if project.key_prefix.as_deref() == Some("") {
+            return Err("key_prefix must not be empty".to_string());
+        }
+        Ok(project)
+    }
+
+    /// Read a project file
+    pub fn read(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Self::parse(&content, |name| std::env::var(name).ok())
+            .map_err(|e| KvError::InvalidConfig(format!("{}: {}", path.display(), e)))
+    }
+}
+
+/// The nearest project file at or above `dir`
+pub fn find(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+/// The project file for the working directory, if there is one
+pub fn discover() -> Result<Option<(PathBuf, ProjectConfig)>> {
+    let Some(path) = std::env::current_dir().ok().and_then(|dir| find(&dir)) else {
+        return Ok(None);
+    };
+    let project = ProjectConfig::read(&path)?;
+    Ok(Some((path, project)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> std::result::Result<ProjectConfig, String> {
+        ProjectConfig::parse(content, |name| match name {
+            "DEPLOY_ENV" => Some("staging".to_string()),
+            "CF_API_TOKEN" => Some("token".to_string()),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn test_parse() {
+        let project =
+            parse("storage = \"staging\"\nkey_prefix = \"shop/\"\nformat = \"json\"\n").unwrap();
+        assert_eq!(project.storage.as_deref(), Some("staging"));
+        assert_eq!(project.key_prefix.as_deref(), Some("shop/"));
+        assert_eq!(parse("").unwrap(), ProjectConfig::default());
+
+        // Credentials belong in the user's config, not in the repository
+        let error = parse("api_token = \"secret\"").unwrap_err();
+        assert!(error.contains("api_token"), "{}", error);
+        assert!(parse("format = \"xml\"").is_err());
+        assert!(parse("key_prefix = \"\"").is_err());
+    }
+
+    #[test]
+    fn test_placeholders() {
+        let project = parse(
+            "storage = \"${DEPLOY_ENV}\"\nkey_prefix = \"app-${DEPLOY_ENV}/\"\napi_token = \"${CF_API_TOKEN}\"\n",
+        )
+        .unwrap();
+        assert_eq!(project.storage.as_deref(), Some("staging"));
+        assert_eq!(project.key_prefix.as_deref(), Some("app-staging/"));
+        assert_eq!(project.api_token.as_deref(), Some("token"));
+
+        let error = parse("storage = \"${MISSING}\"").unwrap_err();
+        assert_eq!(error, "storage: environment variable MISSING is not set");
+        // Only a placeholder alone, so no part of a secret is committed
+        assert!(parse("api_token = \"abc${CF_API_TOKEN}\"").is_err());
+    }
+
+    #[test]
+    fn test_find() {
+        let root = std::env::temp_dir().join(format!("cfkv-project-test-{}", std::process::id()));
+        let nested = root.join("app").join("src");
+        std::fs::create_dir_all(&nested).unwrap();
+        assert_ne!(find(&nested), Some(root.join(FILE_NAME)));
+
+        std::fs::write(root.join(FILE_NAME), "storage = \"dev\"\n").unwrap();
+        assert_eq!(find(&nested), Some(root.join(FILE_NAME)));
+        std::fs::write(root.join("app").join(FILE_NAME), "").unwrap();
+        assert_eq!(find(&nested), Some(root.join("app").join(FILE_NAME)));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,153 @@
+//! Cache purges after writes, for `put --purge-url` and storage purge hooks
+//!
+//! A storage's purge hooks name URL templates for keys under a prefix:
+//! writing `pages/about` with a hook for `pages/` and the template
+//! `https://example.com/{path}` purges `https://example.com/about`. `{key}`
+//! expands to the whole key and `{path}` to the key without the prefix.
+
+use crate::config::PurgeHook;
+use cloudflare_kv::{KvError, Result, ZoneClient};
+use reqwest::Url;
+use std::collections::{BTreeMap, HashMap};
+
+/// A URL to purge, and its zone when known
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PurgeTarget {
+    pub url: String,
+    pub zone_id: Option<String>,
+}
+
+impl PurgeTarget {
+    pub fn new(url: impl Into<String>, zone_id: Option<String>) -> Self {
+        Self {
+            url: url.into(),
+            zone_id,
+        }
+    }
+}
+
+/// Check a URL or template names an http(s) URL with a host
+pub fn parse_url(url: &str) -> std::result::Result<String, String> {
+    let expanded = expand(url, "key", "");
+    match Url::parse(&expanded) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") && parsed.host().is_some() => {
+            Ok(url.to_string())
+        }
+        _ => Err(format!("'{}' is not an http(s) URL", url)),
+    }
+}
+
+fn expand(template: &str, key: &str, prefix: &str) -> String {
+    template
+        .replace("{key}", key)
+        .replace("{path}", key.strip_prefix(prefix).unwrap_or(key))
+}
+
+/// URLs the storage's hooks purge after `keys` were written, without
+/// duplicates
+pub fn targets(hooks: &[PurgeHook], keys: &[&str]) -> Vec<PurgeTarget> {
+    let mut targets: Vec<PurgeTarget> = Vec::new();
+    for key in keys {
+        for hook in hooks.iter().filter(|hook| key.starts_with(&hook.prefix)) {
+            for template in &hook.urls {
+                let target =
+                    PurgeTarget::new(expand(template, key, &hook.prefix), hook.zone_id.clone());
+                if !targets.contains(&target) {
+                    targets.push(target);
+                }
+            }
+        }
+    }
+    targets
+}
+
+/// Purge `targets`, looking up the zone of those without one by host name
+pub async fn purge(client: &ZoneClient, targets: Vec<PurgeTarget>) -> Result<()> {
+    let mut by_zone: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut zones_by_host: HashMap<String, String> = HashMap::new();
+    for target in targets {
+        let zone_id = match target.zone_id {
+            Some(zone_id) => zone_id,
+            None => {
+                let host = Url::parse(&target.url)
+                    .ok()
+                    .and_then(|url| url.host_str().map(str::to_string))
+                    .ok_or_else(|| {
+                        KvError::InvalidConfig(format!("'{}' is not a URL", target.url))
+                    })?;
+                match zones_by_host.get(&host) {
+                    Some(zone_id) => zone_id.clone(),
+                    None => {
+                        let zone = client.find_zone(&host).await?.ok_or_else(|| {
+                            KvError::InvalidConfig(format!(
+                                "No zone found for {}; give its zone ID",
+                                host
+                            ))
+                        })?;
+                        zones_by_host.insert(host, zone.id.clone());
+                        zone.id
+                    }
+                }
+            }
+        };
+        by_zone.entry(zone_id).or_default().push(target.url);
+    }
+    for (zone_id, urls) in by_zone {
+        client.purge_urls(&zone_id, &urls).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hook(prefix: &str, urls: &[&str], zone_id: Option<&str>) -> PurgeHook {
+        PurgeHook {
+            prefix: prefix.to_string(),
+            urls: urls.iter().map(|url| url.to_string()).collect(),
+            zone_id: zone_id.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_targets() {
+        let hooks = [
+            hook(
+                "pages/",
+                &[
+                    "https://example.com/{path}",
+                    "https://example.com/sitemap.xml",
+                ],
+                Some("z1"),
+            ),
+            hook("", &["https://api.example.com/kv/{key}"], None),
+        ];
+        let urls: Vec<_> = targets(&hooks, &["pages/about", "pages/team"])
+            .into_iter()
+            .map(|target| target.url)
+            .collect();
+        assert_eq!(
+            urls,
+            [
+                "https://example.com/about",
+                "https://example.com/sitemap.xml",
+                "https://api.example.com/kv/pages/about",
+                "https://example.com/team",
+                "https://api.example.com/kv/pages/team",
+            ]
+        );
+        assert_eq!(
+            targets(&hooks[..1], &["config/theme"]),
+            Vec::<PurgeTarget>::new()
+        );
+    }
+
+    #[test]
+    fn test_parse_url() {
+        assert!(parse_url("https://example.com/{path}").is_ok());
+        assert!(parse_url("http://localhost:8787/{key}").is_ok());
+        assert!(parse_url("example.com/{key}").is_err());
+        assert!(parse_url("ftp://example.com/x").is_err());
+    }
+}
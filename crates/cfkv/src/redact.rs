@@ -0,0 +1,59 @@
+//! Masking values under sensitive key prefixes
+//!
+//! A storage can list key prefixes whose values are secrets. `cfkv get`
+//! prints those values masked unless `--reveal` is given, and `list` and
+//! `batch export` mark the keys, so tokens kept in KV do not end up in
+//! terminal scrollback or CI logs by accident.
+
+/// Printed in place of a sensitive value
+pub const MASK: &str = "********";
+
+/// Which keys are sensitive, and whether to show their values anyway
+#[derive(Clone, Debug, Default)]
+pub struct Redactor {
+    prefixes: Vec<String>,
+    reveal: bool,
+}
+
+impl Redactor {
+    pub fn new(prefixes: Vec<String>, reveal: bool) -> Self {
+        Self { prefixes, reveal }
+    }
+
+    pub fn is_sensitive(&self, key: &str) -> bool {
+        self.prefixes.iter().any(|prefix| key.starts_with(prefix))
+    }
+
+    /// Whether `key`'s value should be masked
+    pub fn masks(&self, key: &str) -> bool {
+        !self.reveal && self.is_sensitive(key)
+    }
+
+    /// `value`, or the mask when `key` is sensitive and not revealed
+    pub fn value<'a>(&self, key: &str, value: &'a str) -> &'a str {
+        if self.masks(key) {
+            MASK
+        } else {
+            value
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redactor() {
+        let redactor = Redactor::new(vec!["secrets/".to_string(), "token:".to_string()], false);
+        assert!(redactor.is_sensitive("secrets/github"));
+        assert!(!redactor.is_sensitive("config/secrets/x"));
+        assert_eq!(redactor.value("token:ci", "abc123"), MASK);
+        assert_eq!(redactor.value("config", "abc123"), "abc123");
+
+        let revealed = Redactor::new(vec!["token:".to_string()], true);
+        assert!(revealed.is_sensitive("token:ci"));
+        assert_eq!(revealed.value("token:ci", "abc123"), "abc123");
+        assert!(!Redactor::default().is_sensitive("anything"));
+    }
+}
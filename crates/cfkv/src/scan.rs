@@ -0,0 +1,195 @@
+//! Comparing configured storages with the namespaces that exist, for
+//! `cfkv storage scan`
+
+use crate::config::Config;
+use cloudflare_kv::{AuthCredentials, ClientConfig, KvClient, KvError};
+use serde::Serialize;
+
+/// A namespace of a scanned account
+#[derive(Debug, Serialize)]
+pub struct ScannedNamespace {
+    pub id: String,
+    pub title: String,
+    /// Storages (`storage` or `storage/alias`) using the namespace
+    pub mapped: Vec<String>,
+}
+
+/// The namespaces of one account, as seen with one API token
+#[derive(Debug, Serialize)]
+pub struct ScannedAccount {
+    pub account_id: String,
+    /// Storages configured with this account and token
+    pub storages: Vec<String>,
+    pub namespaces: Vec<ScannedNamespace>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ScannedAccount {
+    /// Namespaces no storage uses
+    pub fn unmapped(&self) -> impl Iterator<Item = &ScannedNamespace> {
+        self.namespaces.iter().filter(|ns| ns.mapped.is_empty())
+    }
+}
+
+/// List the namespaces of the account the `storages` share
+///
+/// A failed listing is recorded in `error` so the other accounts can still
+/// be shown; the error itself is returned alongside for the exit code.
+pub async fn scan_account(
+    config: &Config,
+    storages: Vec<String>,
+) -> (ScannedAccount, Option<KvError>) {
+    let storage = &config.storages[&storages[0]];
+    let mut account = ScannedAccount {
+        account_id: storage.account_id.clone(),
+        storages,
+        namespaces: Vec::new(),
+        error: None,
+    };
+    let namespaces = match storage.token() {
+        Ok(token) => {
+            let client_config = storage.endpoint(ClientConfig::new(
+                &storage.account_id,
+                &storage.namespace_id,
+                AuthCredentials::token(token),
+            ));
+            KvClient::new(client_config).list_all_namespaces().await
+        }
+        Err(e) => Err(e),
+    };
+    match namespaces {
+        Ok(namespaces) => {
+            let mut mappings = config.namespace_mappings(&account.account_id);
+            account.namespaces = namespaces
+                .into_iter()
+                .map(|ns| ScannedNamespace {
+                    mapped: mappings.remove(&ns.id).unwrap_or_default(),
+                    id: ns.id,
+                    title: ns.title,
+                })
+                .collect();
+            (account, None)
+        }
+        Err(e) => {
+            account.error = Some(e.to_string());
+            (account, Some(e))
+        }
+    }
+}
+
+/// Text listing of scanned accounts
+pub fn render_text(accounts: &[ScannedAccount]) -> String {
+    let mut output = String::new();
+    for account in accounts {
+        output.push_str(&format!(
+            "Account {} (storages: {})\n",
+            account.account_id,
+            account.storages.join(", ")
+        ));
+        if let Some(error) = &account.error {
+            output.push_str(&format!("  Error: {}\n", error));
+            continue;
+        }
+        if account.namespaces.is_empty() {
+            output.push_str("  (no namespaces)\n");
+        }
+        let id_width = account
+            .namespaces
+            .iter()
+            .map(|ns| ns.id.len())
+            .max()
+            .unwrap_or(0);
+        let title_width = account
+            .namespaces
+            .iter()
+            .map(|ns| ns.title.len())
+            .max()
+            .unwrap_or(0);
+        for ns in &account.namespaces {
+            let mapped = if ns.mapped.is_empty() {
+                "(not mapped)".to_string()
+            } else {
+                ns.mapped.join(", ")
+            };
+            output.push_str(&format!(
+                "  {:<id_width$}  {:<title_width$}  {}\n",
+                ns.id, ns.title, mapped
+            ));
+        }
+    }
+    output.trim_end().to_string()
+}
+
+/// Alias suggested for a namespace: its title in lowercase, with runs of
+/// other characters than letters and digits replaced by `-`
+pub fn default_alias(title: &str) -> String {
+    let mut alias = String::new();
+    for c in title.chars() {
+        if c.is_ascii_alphanumeric() {
+            alias.push(c.to_ascii_lowercase());
+        } else if !alias.is_empty() && !alias.ends_with('-') {
+            alias.push('-');
+        }
+    }
+    let alias = alias.trim_end_matches('-');
+    if alias.is_empty() {
+        "namespace".to_string()
+    } else {
+        alias.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_alias() {
+        assert_eq!(default_alias("Production Sessions"), "production-sessions");
+        assert_eq!(
+            default_alias("my_worker-CACHE (preview)"),
+            "my-worker-cache-preview"
+        );
+        assert_eq!(default_alias("***"), "namespace");
+    }
+
+    #[test]
+    fn test_render_text() {
+        let accounts = vec![
+            ScannedAccount {
+                account_id: "acc1".to_string(),
+                storages: vec!["prod".to_string()],
+                namespaces: vec![
+                    ScannedNamespace {
+                        id: "ns1".to_string(),
+                        title: "main".to_string(),
+                        mapped: vec!["prod".to_string()],
+                    },
+                    ScannedNamespace {
+                        id: "ns22".to_string(),
+                        title: "sessions".to_string(),
+                        mapped: vec![],
+                    },
+                ],
+                error: None,
+            },
+            ScannedAccount {
+                account_id: "acc2".to_string(),
+                storages: vec!["old".to_string()],
+                namespaces: vec![],
+                error: Some("Authentication failed: bad token".to_string()),
+            },
+        ];
+
+        assert_eq!(accounts[0].unmapped().count(), 1);
+        assert_eq!(
+            render_text(&accounts),
+            "Account acc1 (storages: prod)\n\
+             \x20 ns1   main      prod\n\
+             \x20 ns22  sessions  (not mapped)\n\
+             Account acc2 (storages: old)\n\
+             \x20 Error: Authentication failed: bad token"
+        );
+    }
+}
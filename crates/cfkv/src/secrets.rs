@@ -0,0 +1,94 @@
+//! API tokens obtained from an external command (`api_token_cmd`)
+//!
+//! A storage or shared account can name a command such as
+//! `op read op://vault/cloudflare/token` instead of storing the token. The
+//! command runs through the shell the first time the token is needed and
+//! its output is kept in memory until the process exits, never on disk.
+
+use cloudflare_kv::{KvError, Result};
+use std::collections::HashMap;
+use std::process::Command;
+use std::sync::Mutex;
+
+lazy_static::lazy_static! {
+    static ref TOKENS: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+}
+
+/// Run `command` and return its trimmed output, reusing an earlier result
+pub fn token_from_command(command: &str) -> Result<String> {
+    if let Some(token) = TOKENS.lock().unwrap().get(command) {
+        return Ok(token.clone());
+    }
+
+    let output = shell(command).output().map_err(|e| {
+        KvError::AuthError(format!("Could not run token command `{}`: {}", command, e))
+    })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let mut message = format!("Token command `{}` failed ({})", command, output.status);
+        if !stderr.trim().is_empty() {
+            message.push_str(&format!(": {}", stderr.trim()));
+        }
+        return Err(KvError::AuthError(message));
+    }
+    let token = String::from_utf8(output.stdout)
+        .map_err(|_| {
+            KvError::AuthError(format!("Token command `{}` printed invalid UTF-8", command))
+        })?
+        .trim()
+        .to_string();
+    if token.is_empty() {
+        return Err(KvError::AuthError(format!(
+            "Token command `{}` printed nothing",
+            command
+        )));
+    }
+
+    TOKENS
+        .lock()
+        .unwrap()
+        .insert(command.to_string(), token.clone());
+    Ok(token)
+}
+
+/// `command` run by the platform's shell
+#[cfg(unix)]
+pub fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(not(unix))]
+pub fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_from_command() {
+        let dir = std::env::temp_dir().join(format!("cfkv-token-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let counter = dir.join("runs");
+        let command = format!("echo run >> {}; echo '  secret-token'", counter.display());
+
+        assert_eq!(token_from_command(&command).unwrap(), "secret-token");
+        assert_eq!(token_from_command(&command).unwrap(), "secret-token");
+        // The command ran once; the second call used the cached token
+        assert_eq!(std::fs::read_to_string(&counter).unwrap(), "run\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_failing_command() {
+        let error = token_from_command("echo locked >&2; exit 3").unwrap_err();
+        assert!(matches!(error, KvError::AuthError(_)));
+        assert!(error.to_string().contains("locked"));
+        assert!(token_from_command("true").is_err());
+    }
+}
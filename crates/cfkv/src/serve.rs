@@ -0,0 +1,340 @@
+//! Local HTTP facade over a namespace for `cfkv serve`
+//!
+//! Local tools and tests can read and write KV through localhost without
+//! each of them holding Cloudflare credentials:
+//!
+//! | Request              | Does                                              |
+//! |----------------------|---------------------------------------------------|
+//! | `GET /kv/:key`       | value as the body, 404 when missing               |
+//! | `PUT /kv/:key`       | store the body; `?ttl=SECS` sets an expiration    |
+//! | `DELETE /kv/:key`    | delete the key                                    |
+//! | `GET /keys`          | `{"keys", "list_complete", "cursor"}`; takes `prefix`, `limit` and `cursor` |
+//!
+//! Keys are percent-decoded, so `/kv/a%2Fb` is the key `a/b`. Values go
+//! through the storage's plugins like any other command. Errors are JSON
+//! objects, `{"error": "..."}`.
+//!
+//! With `--worker-compat` the server speaks the Workers binding protocol of
+//! [`crate::worker`] instead.
+
+use crate::exit::ErrorKind;
+use crate::worker::{self, EdgeCache};
+use cloudflare_kv::{KvError, PaginationParams, PluginClient};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// Largest page `GET /keys` returns, as in the KV API
+const MAX_LIST_LIMIT: u32 = 1000;
+
+/// How the server runs
+pub struct ServeOptions {
+    pub addr: SocketAddr,
+    /// Bearer token every request must carry
+    pub token: Option<String>,
+    /// Answer PUT and DELETE with 403
+    pub read_only: bool,
+    /// Emulate the Workers KV binding instead of the REST API
+    pub worker_compat: bool,
+}
+
+/// Serve `client` until interrupted
+pub async fn serve(client: PluginClient, options: ServeOptions) -> hyper::Result<()> {
+    let addr = options.addr;
+    let state = Arc::new((client, options, EdgeCache::default()));
+    let make_service = make_service_fn(move |_| {
+        let state = state.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let state = state.clone();
+                async move {
+                    let (client, options, cache) = &*state;
+                    let method = request.method().clone();
+                    let path = request.uri().path().to_string();
+                    let response = if options.worker_compat {
+                        worker::handle(client, options, cache, request).await
+                    } else {
+                        handle(client, options, request).await
+                    };
+                    tracing::info!(
+                        %method,
+                        %path,
+                        status = response.status().as_u16(),
+                        "Served request"
+                    );
+                    Ok::<_, Infallible>(response)
+                }
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&addr)?.serve(make_service);
+    eprintln!("Serving on http://{} (Ctrl-C to stop)", server.local_addr());
+    server
+        .with_graceful_shutdown(async {
+            tokio::signal::ctrl_c().await.ok();
+        })
+        .await
+}
+
+/// Answer one request
+async fn handle(
+    client: &PluginClient,
+    options: &ServeOptions,
+    request: Request<Body>,
+) -> Response<Body> {
+    if let Some(refused) = refuse(options, &request) {
+        return refused;
+    }
+
+    let path = request.uri().path().to_string();
+    let query = query_params(request.uri().query());
+    let method = request.method().clone();
+
+    if path == "/keys" {
+        return match method {
+            Method::GET => list(client, &query).await,
+            _ => error(StatusCode::METHOD_NOT_ALLOWED, "Use GET for /keys"),
+        };
+    }
+    let Some(key) = path.strip_prefix("/kv/").filter(|key| !key.is_empty()) else {
+        return error(StatusCode::NOT_FOUND, "Use /kv/:key or /keys");
+    };
+    let Some(key) = decode_key(key) else {
+        return error(StatusCode::BAD_REQUEST, "Key is not valid UTF-8");
+    };
+
+    match method {
+        Method::GET => match client.get_bytes(&key).await {
+            Ok(Some(value)) => Response::new(Body::from(value)),
+            Ok(None) => error(StatusCode::NOT_FOUND, &format!("Key not found: {}", key)),
+            Err(e) => kv_error(&e),
+        },
+        Method::PUT => {
+            let ttl = match query.get("ttl").map(|ttl| ttl.parse::<u64>()) {
+                Some(Ok(ttl)) => Some(ttl),
+                Some(Err(_)) => return error(StatusCode::BAD_REQUEST, "ttl must be seconds"),
+                None => None,
+            };
+            let body = match hyper::body::to_bytes(request.into_body()).await {
+                Ok(body) => body,
+                Err(e) => return error(StatusCode::BAD_REQUEST, &e.to_string()),
+            };
+            match client.put_with_options(&key, &body, ttl, None).await {
+                Ok(()) => no_content(),
+                Err(e) => kv_error(&e),
+            }
+        }
+        Method::DELETE => match client.client().delete(&key).await {
+            Ok(()) => no_content(),
+            Err(e) => kv_error(&e),
+        },
+        _ => error(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "Use GET, PUT or DELETE for /kv/:key",
+        ),
+    }
+}
+
+async fn list(client: &PluginClient, query: &HashMap<String, String>) -> Response<Body> {
+    let limit = match query.get("limit").map(|limit| limit.parse::<u32>()) {
+        Some(Ok(limit)) => limit.clamp(1, MAX_LIST_LIMIT),
+        Some(Err(_)) => return error(StatusCode::BAD_REQUEST, "limit must be a number"),
+        None => MAX_LIST_LIMIT,
+    };
+    let mut params = PaginationParams::new().with_limit(limit);
+    if let Some(prefix) = query.get("prefix") {
+        params = params.with_prefix(prefix.clone());
+    }
+    if let Some(cursor) = query.get("cursor") {
+        params = params.with_cursor(cursor.clone());
+    }
+
+    match client.client().list(Some(params)).await {
+        Ok(page) => json(
+            StatusCode::OK,
+            serde_json::json!({
+                "keys": page.keys.iter().map(|key| &key.name).collect::<Vec<_>>(),
+                "list_complete": page.list_complete,
+                "cursor": page.cursor,
+            }),
+        ),
+        Err(e) => kv_error(&e),
+    }
+}
+
+/// The response refusing `request`: 401 without the bearer token, 403 for
+/// writes to a read-only storage
+pub fn refuse(options: &ServeOptions, request: &Request<Body>) -> Option<Response<Body>> {
+    if let Some(token) = &options.token {
+        let authorized = request
+            .headers()
+            .get(hyper::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|given| tokens_match(given, token));
+        if !authorized {
+            return Some(error(
+                StatusCode::UNAUTHORIZED,
+                "Missing or wrong bearer token",
+            ));
+        }
+    }
+    if options.read_only && matches!(*request.method(), Method::PUT | Method::DELETE) {
+        return Some(error(
+            StatusCode::FORBIDDEN,
+            "Storage is read-only; restart with --force to allow writes",
+        ));
+    }
+    None
+}
+
+/// Compare bearer tokens in constant time
+///
+/// Both are hashed first, so neither the position of the first differing
+/// byte nor the token's length shows in the time taken.
+fn tokens_match(given: &str, token: &str) -> bool {
+    let given = cloudflare_kv::value_hash(given.as_bytes());
+    let token = cloudflare_kv::value_hash(token.as_bytes());
+    given
+        .bytes()
+        .zip(token.bytes())
+        .fold(0, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+/// Percent-decode a key taken from a path; `None` when it is not UTF-8
+pub fn decode_key(key: &str) -> Option<String> {
+    percent_encoding::percent_decode_str(key)
+        .decode_utf8()
+        .ok()
+        .map(|key| key.into_owned())
+}
+
+pub fn query_params(query: Option<&str>) -> HashMap<String, String> {
+    form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+        .into_owned()
+        .collect()
+}
+
+pub fn kv_error(e: &KvError) -> Response<Body> {
+    let status = match ErrorKind::of(e) {
+        ErrorKind::NotFound => StatusCode::NOT_FOUND,
+        ErrorKind::Validation => StatusCode::BAD_REQUEST,
+        ErrorKind::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+        ErrorKind::Auth
+        | ErrorKind::Network
+        | ErrorKind::General
+        | ErrorKind::Cancelled
+        | ErrorKind::Interrupted => StatusCode::BAD_GATEWAY,
+    };
+    error(status, &e.to_string())
+}
+
+pub fn error(status: StatusCode, message: &str) -> Response<Body> {
+    json(status, serde_json::json!({ "error": message }))
+}
+
+pub fn json(status: StatusCode, body: serde_json::Value) -> Response<Body> {
+    let mut response = Response::new(Body::from(body.to_string()));
+    *response.status_mut() = status;
+    response.headers_mut().insert(
+        hyper::header::CONTENT_TYPE,
+        hyper::header::HeaderValue::from_static("application/json"),
+    );
+    response
+}
+
+fn no_content() -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = StatusCode::NO_CONTENT;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cloudflare_kv::{KvClient, PluginRegistry};
+
+    fn options(token: Option<&str>, read_only: bool) -> ServeOptions {
+        ServeOptions {
+            addr: ([127, 0, 0, 1], 0).into(),
+            token: token.map(String::from),
+            read_only,
+            worker_compat: false,
+        }
+    }
+
+    fn request(method: Method, uri: &str, body: &str) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("Authorization", "Bearer secret")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    async fn body(response: Response<Body>) -> String {
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_key_routes() {
+        let client = PluginClient::new(KvClient::in_memory(), PluginRegistry::new());
+        let options = options(None, false);
+
+        let put = handle(&client, &options, request(Method::PUT, "/kv/a%2Fb", "v1")).await;
+        assert_eq!(put.status(), StatusCode::NO_CONTENT);
+        let get = handle(&client, &options, request(Method::GET, "/kv/a%2Fb", "")).await;
+        assert_eq!(get.status(), StatusCode::OK);
+        assert_eq!(body(get).await, "v1");
+
+        let keys = handle(
+            &client,
+            &options,
+            request(Method::GET, "/keys?prefix=a", ""),
+        )
+        .await;
+        let keys: serde_json::Value = serde_json::from_str(&body(keys).await).unwrap();
+        assert_eq!(keys["keys"], serde_json::json!(["a/b"]));
+
+        let delete = handle(&client, &options, request(Method::DELETE, "/kv/a%2Fb", "")).await;
+        assert_eq!(delete.status(), StatusCode::NO_CONTENT);
+        let missing = handle(&client, &options, request(Method::GET, "/kv/a%2Fb", "")).await;
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+
+        let bad_ttl = handle(&client, &options, request(Method::PUT, "/kv/k?ttl=x", "")).await;
+        assert_eq!(bad_ttl.status(), StatusCode::BAD_REQUEST);
+        let unknown = handle(&client, &options, request(Method::GET, "/other", "")).await;
+        assert_eq!(unknown.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_token_and_read_only() {
+        let client = PluginClient::new(KvClient::in_memory(), PluginRegistry::new());
+        client.put("k", "v").await.unwrap();
+
+        let guarded = options(Some("secret"), false);
+        let anonymous = Request::get("/kv/k").body(Body::empty()).unwrap();
+        let denied = handle(&client, &guarded, anonymous).await;
+        assert_eq!(denied.status(), StatusCode::UNAUTHORIZED);
+        let allowed = handle(&client, &guarded, request(Method::GET, "/kv/k", "")).await;
+        assert_eq!(allowed.status(), StatusCode::OK);
+        let wrong = Request::get("/kv/k")
+            .header("Authorization", "Bearer secreT")
+            .body(Body::empty())
+            .unwrap();
+        let denied = handle(&client, &guarded, wrong).await;
+        assert_eq!(denied.status(), StatusCode::UNAUTHORIZED);
+
+        let read_only = options(None, true);
+        let put = handle(&client, &read_only, request(Method::PUT, "/kv/k", "x")).await;
+        assert_eq!(put.status(), StatusCode::FORBIDDEN);
+        let get = handle(&client, &read_only, request(Method::GET, "/kv/k", "")).await;
+        assert_eq!(body(get).await, "v");
+    }
+}
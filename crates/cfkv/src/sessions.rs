@@ -0,0 +1,177 @@
+//! Session records under a key prefix, for `cfkv sessions`
+//!
+//! A session expires at its KV expiration, or at the time in a metadata
+//! field (`expires_at` by default) when that is earlier. The metadata field
+//! covers pointer records, such as `user-sessions:42:abc`, that applications
+//! write without a TTL and clean up themselves. It may hold Unix seconds,
+//! Unix milliseconds or an RFC 3339 time.
+//!
+//! KV removes keys once their expiration passes, though listings can still
+//! show them for a short while.
+
+use chrono::DateTime;
+use cloudflare_kv::KeyMetadata;
+use serde::Serialize;
+
+/// Timestamps above this are taken as milliseconds (year 5138 in seconds)
+const MILLISECONDS_FROM: u64 = 100_000_000_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Session {
+    pub key: String,
+    /// Unix time the session expires at, if it does
+    pub expires_at: Option<u64>,
+}
+
+impl Session {
+    pub fn new(key: &KeyMetadata, expiry_field: &str) -> Self {
+        let from_metadata = key
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.get(expiry_field))
+            .and_then(parse_time);
+        let expires_at = match (key.expiration, from_metadata) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        Self {
+            key: key.name.clone(),
+            expires_at,
+        }
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
+
+    /// Seconds left, zero once expired
+    pub fn remaining(&self, now: u64) -> Option<u64> {
+        self.expires_at.map(|at| at.saturating_sub(now))
+    }
+}
+
+/// Sessions sorted by expiry, soonest first; those without one come last
+pub fn sessions(keys: &[KeyMetadata], expiry_field: &str) -> Vec<Session> {
+    let mut sessions: Vec<Session> = keys
+        .iter()
+        .map(|key| Session::new(key, expiry_field))
+        .collect();
+    sessions.sort_by_key(|session| (session.expires_at.is_none(), session.expires_at));
+    sessions
+}
+
+/// Active, expired and never-expiring sessions
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Counts {
+    pub total: usize,
+    /// Not expired, including sessions without an expiry
+    pub active: usize,
+    pub expired: usize,
+    pub without_expiry: usize,
+}
+
+pub fn count(sessions: &[Session], now: u64) -> Counts {
+    let expired = sessions.iter().filter(|s| s.is_expired(now)).count();
+    Counts {
+        total: sessions.len(),
+        active: sessions.len() - expired,
+        expired,
+        without_expiry: sessions.iter().filter(|s| s.expires_at.is_none()).count(),
+    }
+}
+
+fn parse_time(value: &serde_json::Value) -> Option<u64> {
+    match value {
+        serde_json::Value::Number(number) => {
+            let time = number.as_u64()?;
+            Some(if time >= MILLISECONDS_FROM {
+                time / 1000
+            } else {
+                time
+            })
+        }
+        serde_json::Value::String(text) => DateTime::parse_from_rfc3339(text)
+            .ok()
+            .and_then(|time| u64::try_from(time.timestamp()).ok()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn key(
+        name: &str,
+        expiration: Option<u64>,
+        metadata: Option<serde_json::Value>,
+    ) -> KeyMetadata {
+        KeyMetadata {
+            name: name.to_string(),
+            expiration,
+            metadata,
+        }
+    }
+
+    #[test]
+    fn test_expiry_sources() {
+        let expiry = |key: KeyMetadata| Session::new(&key, "expires_at").expires_at;
+        assert_eq!(expiry(key("a", Some(2_000), None)), Some(2_000));
+        assert_eq!(
+            expiry(key("b", None, Some(json!({"expires_at": 1_500})))),
+            Some(1_500)
+        );
+        assert_eq!(
+            expiry(key(
+                "c",
+                None,
+                Some(json!({"expires_at": 1_500_000u64 * 1_000_000}))
+            )),
+            Some(1_500_000_000)
+        );
+        assert_eq!(
+            expiry(key(
+                "d",
+                None,
+                Some(json!({"expires_at": "2024-01-01T00:00:00Z"}))
+            )),
+            Some(1_704_067_200)
+        );
+        // The earlier of the two wins
+        assert_eq!(
+            expiry(key("e", Some(900), Some(json!({"expires_at": 1_500})))),
+            Some(900)
+        );
+        assert_eq!(
+            expiry(key("f", None, Some(json!({"expires_at": "soon"})))),
+            None
+        );
+        assert_eq!(expiry(key("g", None, Some(json!({"user": 42})))), None);
+    }
+
+    #[test]
+    fn test_sort_and_count() {
+        let keys = [
+            key("never", None, None),
+            key("late", Some(3_000), None),
+            key("gone", None, Some(json!({"expires_at": 500}))),
+            key("soon", Some(1_500), None),
+        ];
+        let sessions = sessions(&keys, "expires_at");
+        let order: Vec<_> = sessions.iter().map(|s| s.key.as_str()).collect();
+        assert_eq!(order, ["gone", "soon", "late", "never"]);
+        assert_eq!(sessions[1].remaining(1_000), Some(500));
+        assert_eq!(sessions[0].remaining(1_000), Some(0));
+
+        assert_eq!(
+            count(&sessions, 1_000),
+            Counts {
+                total: 4,
+                active: 3,
+                expired: 1,
+                without_expiry: 1,
+            }
+        );
+    }
+}
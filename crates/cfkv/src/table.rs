@@ -0,0 +1,153 @@
+//! Table output for key listings (`--format table`)
+
+use crate::formatter::Formatter;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use cloudflare_kv::KeyMetadata;
+use comfy_table::presets::UTF8_FULL_CONDENSED;
+use comfy_table::{CellAlignment, Table};
+
+/// A column of the key table
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Column {
+    Key,
+    /// Absolute expiration time (UTC)
+    Expiration,
+    /// Time left until expiration
+    Ttl,
+    Metadata,
+    /// Value size (downloads each value)
+    Size,
+}
+
+/// Columns shown when `--columns` is not given
+pub const DEFAULT_COLUMNS: [Column; 3] = [Column::Key, Column::Expiration, Column::Metadata];
+
+impl Column {
+    fn header(self) -> &'static str {
+        match self {
+            Column::Key => "KEY",
+            Column::Expiration => "EXPIRATION",
+            Column::Ttl => "TTL",
+            Column::Metadata => "METADATA",
+            Column::Size => "SIZE",
+        }
+    }
+
+    fn cell(self, key: &KeyMetadata, size: Option<u64>, now: DateTime<Utc>) -> String {
+        let expires_at = key
+            .expiration
+            .and_then(|at| DateTime::<Utc>::from_timestamp(at as i64, 0));
+        match self {
+            Column::Key => key.name.clone(),
+            Column::Expiration => expires_at
+                .map(|at| at.format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            Column::Ttl => expires_at
+                .map(|at| format_duration((at - now).num_seconds()))
+                .unwrap_or_else(|| "-".to_string()),
+            Column::Metadata => key
+                .metadata
+                .as_ref()
+                .map(|m| m.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            Column::Size => size
+                .map(Formatter::human_size)
+                .unwrap_or_else(|| "-".to_string()),
+        }
+    }
+}
+
+/// Render keys as a table; `sizes` lines up with `keys` when the size column
+/// is shown
+pub fn render_keys(
+    keys: &[KeyMetadata],
+    sizes: &[Option<u64>],
+    columns: &[Column],
+    now: DateTime<Utc>,
+) -> String {
+    let mut table = Table::new();
+    table
+        .load_style(UTF8_FULL_CONDENSED)
+        .set_header(columns.iter().map(|c| c.header()));
+
+    for (index, key) in keys.iter().enumerate() {
+        let size = sizes.get(index).copied().flatten();
+        table.add_row(columns.iter().map(|c| c.cell(key, size, now)));
+    }
+
+    for (index, column) in columns.iter().enumerate() {
+        if matches!(column, Column::Size | Column::Ttl) {
+            if let Some(column) = table.column_mut(index) {
+                column.set_cell_alignment(CellAlignment::Right);
+            }
+        }
+    }
+
+    table.to_string()
+}
+
+/// Compact duration such as `2d 3h`, `45m 10s` or `expired`
+pub fn format_duration(seconds: i64) -> String {
+    if seconds <= 0 {
+        return "expired".to_string();
+    }
+    let (days, hours, minutes, secs) = (
+        seconds / 86_400,
+        seconds % 86_400 / 3_600,
+        seconds % 3_600 / 60,
+        seconds % 60,
+    );
+    match (days, hours, minutes) {
+        (0, 0, 0) => format!("{}s", secs),
+        (0, 0, _) => format!("{}m {}s", minutes, secs),
+        (0, _, _) => format!("{}h {}m", hours, minutes),
+        _ => format!("{}d {}h", days, hours),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys() -> Vec<KeyMetadata> {
+        vec![
+            KeyMetadata {
+                name: "session:abc".to_string(),
+                expiration: Some(1_700_003_600),
+                metadata: Some(serde_json::json!({"user": 1})),
+            },
+            KeyMetadata {
+                name: "config".to_string(),
+                expiration: None,
+                metadata: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_render_keys() {
+        let now = DateTime::<Utc>::from_timestamp(1_700_000_000, 0).unwrap();
+        let columns = [Column::Key, Column::Expiration, Column::Ttl, Column::Size];
+        let output = render_keys(&keys(), &[Some(120), None], &columns, now);
+
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines[1].contains("KEY") && lines[1].contains("SIZE"));
+        let session = lines.iter().find(|l| l.contains("session:abc")).unwrap();
+        assert!(session.contains("2023-11-14 23:13:20"));
+        assert!(session.contains("1h 0m"));
+        assert!(session.contains("120 B"));
+        let config = lines.iter().find(|l| l.contains("config")).unwrap();
+        assert!(config.contains('-'));
+        assert!(!output.contains("METADATA"));
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(-5), "expired");
+        assert_eq!(format_duration(42), "42s");
+        assert_eq!(format_duration(130), "2m 10s");
+        assert_eq!(format_duration(7_500), "2h 5m");
+        assert_eq!(format_duration(183_600), "2d 3h");
+    }
+}
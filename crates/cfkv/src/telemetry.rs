@@ -0,0 +1,100 @@
+//! OpenTelemetry export for `--trace-endpoint` (the `otel` feature)
+//!
+//! The `kv` span of every request, inside a `cfkv` span for the command, is
+//! sent to an OTLP/HTTP collector, along with the `--stats` counters as
+//! metrics. Both are flushed when the command exits, failed or not.
+
+use crate::logging::{self, LogLevel};
+use cloudflare_kv::MetricsSnapshot;
+use opentelemetry::metrics::MeterProvider as _;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::{MetricExporter, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+use std::sync::OnceLock;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+/// Providers to flush on exit
+static PROVIDERS: OnceLock<(TracerProvider, SdkMeterProvider)> = OnceLock::new();
+
+/// Layer sending spans to the collector at `endpoint`
+///
+/// Spans are recorded at debug level whatever `--debug` says, so requests
+/// are traced without being logged.
+pub fn layer(endpoint: &str) -> Result<Box<dyn Layer<Registry> + Send + Sync>, String> {
+    let endpoint = endpoint.trim_end_matches('/');
+    let resource = Resource::new([KeyValue::new("service.name", "cfkv")]);
+
+    let spans = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", endpoint))
+        .build()
+        .map_err(|e| format!("Cannot export traces to {}: {}", endpoint, e))?;
+    let tracer_provider = TracerProvider::builder()
+        .with_batch_exporter(spans, runtime::Tokio)
+        .with_resource(resource.clone())
+        .build();
+
+    let metrics = MetricExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/metrics", endpoint))
+        .build()
+        .map_err(|e| format!("Cannot export metrics to {}: {}", endpoint, e))?;
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(PeriodicReader::builder(metrics, runtime::Tokio).build())
+        .with_resource(resource)
+        .build();
+    observe_usage(&meter_provider);
+
+    let tracer = tracer_provider.tracer("cfkv");
+    let _ = PROVIDERS.set((tracer_provider, meter_provider));
+    Ok(tracing_opentelemetry::layer()
+        .with_tracer(tracer)
+        .with_filter(EnvFilter::new(logging::filter(LogLevel::Debug)))
+        .boxed())
+}
+
+/// Reads one counter of the command's usage
+type Counter = fn(&MetricsSnapshot) -> u64;
+
+/// Counters reported as metrics, with their description
+const COUNTERS: [(&str, &str, Counter); 4] = [
+    ("kv.requests", "KV requests sent", |m| m.requests),
+    ("kv.requests.failed", "KV requests that failed", |m| {
+        m.failed
+    }),
+    ("kv.bytes.uploaded", "Value bytes written", |m| {
+        m.bytes_uploaded
+    }),
+    ("kv.bytes.downloaded", "Value bytes read", |m| {
+        m.bytes_downloaded
+    }),
+];
+
+/// Report the command's request and byte counters
+fn observe_usage(provider: &SdkMeterProvider) {
+    let meter = provider.meter("cfkv");
+    for (name, description, value) in COUNTERS {
+        meter
+            .u64_observable_counter(name)
+            .with_description(description)
+            .with_callback(move |observer| {
+                observer.observe(value(&crate::usage::metrics().snapshot()), &[])
+            })
+            .build();
+    }
+}
+
+/// Send the spans and metrics not exported yet
+pub fn shutdown() {
+    if let Some((tracer_provider, meter_provider)) = PROVIDERS.get() {
+        if let Err(e) = tracer_provider.shutdown() {
+            tracing::warn!("Failed to export traces: {}", e);
+        }
+        if let Err(e) = meter_provider.shutdown() {
+            tracing::warn!("Failed to export metrics: {}", e);
+        }
+    }
+}
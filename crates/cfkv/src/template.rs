@@ -0,0 +1,166 @@
+//! Key templates for `put --template` and `rename --template`
+//!
+//! A key may contain placeholders in braces, expanded when the command runs:
+//!
+//! | Placeholder   | Expands to                                   |
+//! |---------------|----------------------------------------------|
+//! | `{uuid}`      | random UUID (v4)                             |
+//! | `{ulid}`      | ULID, sortable by creation time              |
+//! | `{timestamp}` | Unix time in seconds                         |
+//! | `{date}`      | UTC date, `2024-05-01`                       |
+//! | `{datetime}`  | UTC date and time, `2024-05-01T12:30:00Z`    |
+//! | `{env:NAME}`  | value of the environment variable `NAME`     |
+//!
+//! `{{` and `}}` stand for literal braces.
+
+use chrono::{DateTime, Utc};
+use cloudflare_kv::{KvError, Result};
+
+/// Crockford's base32 alphabet, used by ULIDs
+const CROCKFORD: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Expand the placeholders in `template`
+///
+/// Keys without braces are returned unchanged. All time placeholders in one
+/// key use the same instant.
+pub fn expand(template: &str) -> Result<String> {
+    if !template.contains(['{', '}']) {
+        return Ok(template.to_string());
+    }
+
+    let now = Utc::now();
+    let mut key = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(i) = rest.find(['{', '}']) {
+        key.push_str(&rest[..i]);
+        let tail = &rest[i..];
+        if tail.starts_with("{{") || tail.starts_with("}}") {
+            key.push_str(&tail[..1]);
+            rest = &tail[2..];
+            continue;
+        }
+        if tail.starts_with('}') {
+            return Err(invalid(
+                template,
+                "unmatched '}' (write '}}' for a literal brace)",
+            ));
+        }
+        let end = tail
+            .find('}')
+            .ok_or_else(|| invalid(template, "unclosed '{' (write '{{' for a literal brace)"))?;
+        key.push_str(&placeholder(template, &tail[1..end], now)?);
+        rest = &tail[end + 1..];
+    }
+    key.push_str(rest);
+    Ok(key)
+}
+
+fn placeholder(template: &str, name: &str, now: DateTime<Utc>) -> Result<String> {
+    if let Some(var) = name.strip_prefix("env:") {
+        return std::env::var(var).map_err(|_| {
+            invalid(
+                template,
+                &format!("environment variable {} is not set", var),
+            )
+        });
+    }
+    match name {
+        "uuid" => Ok(uuid()),
+        "ulid" => Ok(ulid(now)),
+        "timestamp" => Ok(now.timestamp().to_string()),
+        "date" => Ok(now.format("%Y-%m-%d").to_string()),
+        "datetime" => Ok(now.format("%Y-%m-%dT%H:%M:%SZ").to_string()),
+        _ => Err(invalid(
+            template,
+            &format!(
+                "unknown placeholder {{{}}} (use uuid, ulid, timestamp, date, datetime or env:NAME)",
+                name
+            ),
+        )),
+    }
+}
+
+fn invalid(template: &str, reason: &str) -> KvError {
+    KvError::InvalidConfig(format!("Invalid key template '{}': {}", template, reason))
+}
+
+/// Random (version 4) UUID
+fn uuid() -> String {
+    let mut bits: u128 = rand::random();
+    bits = (bits & !(0xf << 76)) | (0x4 << 76);
+    bits = (bits & !(0x3 << 62)) | (0x2 << 62);
+    let hex = format!("{:032x}", bits);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// ULID: 48 bits of milliseconds then 80 random bits, in Crockford base32
+fn ulid(now: DateTime<Utc>) -> String {
+    let millis = now.timestamp_millis() as u128 & ((1 << 48) - 1);
+    let random = rand::random::<u128>() & ((1 << 80) - 1);
+    let bits = (millis << 80) | random;
+    (0..26)
+        .rev()
+        .map(|i| CROCKFORD[((bits >> (i * 5)) & 0x1f) as usize] as char)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_keys_unchanged() {
+        assert_eq!(expand("users/alice").unwrap(), "users/alice");
+        assert_eq!(expand("a{{b}}c").unwrap(), "a{b}c");
+    }
+
+    #[test]
+    fn test_generators() {
+        let key = expand("user:{uuid}").unwrap();
+        let uuid = key.strip_prefix("user:").unwrap();
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+        assert_ne!(expand("{uuid}").unwrap(), expand("{uuid}").unwrap());
+
+        let ulid = expand("{ulid}").unwrap();
+        assert_eq!(ulid.len(), 26);
+        assert!(ulid.bytes().all(|b| CROCKFORD.contains(&b)));
+
+        let date = expand("logs/{date}").unwrap();
+        assert_eq!(date, format!("logs/{}", Utc::now().format("%Y-%m-%d")));
+        let timestamp: i64 = expand("{timestamp}").unwrap().parse().unwrap();
+        assert!((Utc::now().timestamp() - timestamp).abs() < 5);
+    }
+
+    #[test]
+    fn test_ulids_sort_by_time() {
+        let earlier = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let later = DateTime::from_timestamp(1_700_000_001, 0).unwrap();
+        assert!(ulid(earlier) < ulid(later));
+    }
+
+    #[test]
+    fn test_env_and_errors() {
+        std::env::set_var("CFKV_TEMPLATE_TEST_ENV", "staging");
+        assert_eq!(
+            expand("config/{env:CFKV_TEMPLATE_TEST_ENV}/app").unwrap(),
+            "config/staging/app"
+        );
+
+        for template in [
+            "{env:CFKV_TEMPLATE_TEST_UNSET}",
+            "{nope}",
+            "open{uuid",
+            "close}",
+        ] {
+            assert!(matches!(expand(template), Err(KvError::InvalidConfig(_))));
+        }
+    }
+}
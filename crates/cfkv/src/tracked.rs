@@ -0,0 +1,157 @@
+//! Bookkeeping for the keys a command writes
+//!
+//! Every command's client sends its requests through `TrackedTransport`, so
+//! each key put or deleted, by any command or bulk operation, is dropped
+//! from the `get --cached` read cache as it is written. Write commands also
+//! record the keys, for the storage's purge hooks to run on once the command
+//! succeeds.
+
+use async_trait::async_trait;
+use cloudflare_kv::transport::{BulkWrite, ConditionalGet, Expiration, KvTransport};
+use cloudflare_kv::{ListResponse, PaginationParams, ReadCache, Result};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+/// Keys written through a `TrackedTransport`, shared with its owner
+#[derive(Clone, Default)]
+pub struct WrittenKeys(Arc<Mutex<Vec<String>>>);
+
+impl WrittenKeys {
+    /// The keys in the order first written, without duplicates
+    pub fn keys(&self) -> Vec<String> {
+        let mut seen = HashSet::new();
+        let keys = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        keys.iter()
+            .filter(|key| seen.insert(key.as_str()))
+            .cloned()
+            .collect()
+    }
+
+    fn extend<'k>(&self, keys: impl IntoIterator<Item = &'k str>) {
+        let mut written = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        written.extend(keys.into_iter().map(str::to_string));
+    }
+}
+
+/// Transport invalidating the cached values of the keys written through it
+pub struct TrackedTransport {
+    inner: Arc<dyn KvTransport>,
+    cache: Option<ReadCache>,
+    written: Option<WrittenKeys>,
+}
+
+impl TrackedTransport {
+    /// Track the writes sent to `inner`, dropping them from `cache`
+    pub fn new(inner: Arc<dyn KvTransport>, cache: Option<ReadCache>) -> Self {
+        Self {
+            inner,
+            cache,
+            written: None,
+        }
+    }
+
+    /// Also record the keys of successful writes in `written`
+    pub fn with_written(mut self, written: WrittenKeys) -> Self {
+        self.written = Some(written);
+        self
+    }
+
+    /// Note a write; also called when it failed, as it may have landed
+    fn written<'k>(&self, keys: impl IntoIterator<Item = &'k str> + Clone, succeeded: bool) {
+        if let Some(cache) = &self.cache {
+            for key in keys.clone() {
+                cache.invalidate(key);
+            }
+        }
+        if let (Some(written), true) = (&self.written, succeeded) {
+            written.extend(keys);
+        }
+    }
+}
+
+#[async_trait]
+impl KvTransport for TrackedTransport {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.inner.get(key).await
+    }
+
+    async fn get_if_none_match(&self, key: &str, etag: Option<&str>) -> Result<ConditionalGet> {
+        self.inner.get_if_none_match(key, etag).await
+    }
+
+    async fn put(
+        &self,
+        key: &str,
+        value: &[u8],
+        expiration: Option<Expiration>,
+        metadata: Option<Value>,
+    ) -> Result<()> {
+        let result = self.inner.put(key, value, expiration, metadata).await;
+        self.written([key], result.is_ok());
+        result
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let result = self.inner.delete(key).await;
+        self.written([key], result.is_ok());
+        result
+    }
+
+    async fn metadata(&self, key: &str) -> Result<Option<Value>> {
+        self.inner.metadata(key).await
+    }
+
+    async fn list(&self, params: PaginationParams) -> Result<ListResponse> {
+        self.inner.list(params).await
+    }
+
+    async fn delete_bulk(&self, keys: &[&str]) -> Result<()> {
+        let result = self.inner.delete_bulk(keys).await;
+        self.written(keys.iter().copied(), result.is_ok());
+        result
+    }
+
+    async fn put_bulk(&self, writes: &[BulkWrite]) -> Result<()> {
+        let result = self.inner.put_bulk(writes).await;
+        self.written(
+            writes.iter().map(|write| write.key.as_str()),
+            result.is_ok(),
+        );
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cloudflare_kv::{CacheOptions, InMemoryTransport};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_writes_invalidate_cache() {
+        let cache = ReadCache::new(CacheOptions::new(Duration::from_secs(60)), "test");
+        for key in ["a", "b", "c", "d", "e"] {
+            cache.insert(key, b"cached");
+        }
+        let written = WrittenKeys::default();
+        let transport = TrackedTransport::new(Arc::new(InMemoryTransport::new()), Some(cache))
+            .with_written(written.clone());
+        transport.put("a", b"new", None, None).await.unwrap();
+        transport.delete("b").await.unwrap();
+        transport
+            .put_bulk(&[BulkWrite::new("c", "new")])
+            .await
+            .unwrap();
+        transport.delete_bulk(&["d"]).await.unwrap();
+
+        let cache = transport.cache.as_ref().unwrap();
+        for key in ["a", "b", "c", "d"] {
+            assert_eq!(cache.get(key), None, "{}", key);
+        }
+        assert_eq!(cache.get("e"), Some(b"cached".to_vec()));
+
+        transport.delete("a").await.unwrap();
+        assert_eq!(written.keys(), ["a", "b", "c", "d"]);
+    }
+}
@@ -0,0 +1,682 @@
+//! Record rewrites for `batch import`
+//!
+//! Keys are rewritten with `--key-prefix add:<prefix>` or `strip:<prefix>`
+//! and sed-style `--key-map s/<regex>/<replacement>/[g]`. Values are
+//! rewritten with `--transform`, a small subset of jq:
+//!
+//! | Filter                            | Result                                      |
+//! |-----------------------------------|---------------------------------------------|
+//! | `.`                               | the input unchanged                         |
+//! | `.a.b`, `.items[0]`, `.["a b"]`   | the value at a path (`null` when missing)   |
+//! | `"text"`, `42`, `{"a": 1}`, `null` | a JSON literal                             |
+//! | `PATH = F`                        | the input with `F` of the input at `PATH`   |
+//! | `PATH \|= F`                      | the input with `F` applied to `PATH`        |
+//! | `del(PATH)`                       | the input without `PATH`                    |
+//! | `sub("re"; "with")`, `gsub(..)`   | a string with the first/all matches replaced |
+//! | `ascii_downcase`, `ascii_upcase`  | a string in lower/upper case                |
+//! | `tostring`, `tonumber`            | the input converted                         |
+//! | `F \| G`, `(F)`                   | `G` applied to the output of `F`; grouping  |
+//!
+//! Values that are not JSON are transformed as a JSON string, and string
+//! results are stored as plain text, so `gsub("staging"; "prod")` works on
+//! text values too. Other results are stored as compact JSON.
+
+use regex::Regex;
+use serde_json::{Map, Value};
+
+/// A rewrite of imported keys
+#[derive(Clone, Debug)]
+pub enum KeyRewrite {
+    AddPrefix(String),
+    /// Keys without the prefix are left unchanged
+    StripPrefix(String),
+    Replace {
+        pattern: Regex,
+        /// In `regex` syntax (`${1}`), converted from sed's `\1` and `&`
+        replacement: String,
+        all: bool,
+    },
+}
+
+impl KeyRewrite {
+    /// Parse `add:<prefix>` or `strip:<prefix>`
+    pub fn parse_prefix(spec: &str) -> Result<Self, String> {
+        if let Some(prefix) = spec.strip_prefix("add:") {
+            Ok(KeyRewrite::AddPrefix(prefix.to_string()))
+        } else if let Some(prefix) = spec.strip_prefix("strip:") {
+            Ok(KeyRewrite::StripPrefix(prefix.to_string()))
+        } else {
+            Err(format!("'{}' is not add:<prefix> or strip:<prefix>", spec))
+        }
+    }
+
+    /// Parse a sed substitution, `s/<regex>/<replacement>/` with an optional
+    /// `g` flag; any character after `s` can be the delimiter
+    pub fn parse_map(spec: &str) -> Result<Self, String> {
+        let invalid = || format!("'{}' is not of the form s/REGEX/REPLACEMENT/[g]", spec);
+        let mut chars = spec.chars();
+        if chars.next() != Some('s') {
+            return Err(invalid());
+        }
+        let delimiter = chars.next().filter(|c| *c != '\\').ok_or_else(invalid)?;
+        let parts = split_unescaped(chars.as_str(), delimiter);
+        let [pattern, replacement, flags] = parts.as_slice() else {
+            return Err(invalid());
+        };
+        let all = match flags.as_str() {
+            "" => false,
+            "g" => true,
+            _ => return Err(format!("Unknown flags '{}' in '{}'", flags, spec)),
+        };
+        let pattern = Regex::new(pattern).map_err(|e| format!("Invalid key map regex: {}", e))?;
+        Ok(KeyRewrite::Replace {
+            pattern,
+            replacement: sed_replacement(replacement),
+            all,
+        })
+    }
+
+    pub fn apply(&self, key: &str) -> String {
+        match self {
+            KeyRewrite::AddPrefix(prefix) => format!("{}{}", prefix, key),
+            KeyRewrite::StripPrefix(prefix) => {
+                key.strip_prefix(prefix.as_str()).unwrap_or(key).to_string()
+            }
+            KeyRewrite::Replace {
+                pattern,
+                replacement,
+                all: true,
+            } => pattern.replace_all(key, replacement.as_str()).into_owned(),
+            KeyRewrite::Replace {
+                pattern,
+                replacement,
+                all: false,
+            } => pattern.replace(key, replacement.as_str()).into_owned(),
+        }
+    }
+}
+
+/// Split at unescaped delimiters, unescaping them; other escapes are kept
+/// for the regex
+fn split_unescaped(text: &str, delimiter: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        let part = parts.last_mut().unwrap();
+        match c {
+            '\\' => match chars.next() {
+                Some(next) if next == delimiter => part.push(next),
+                Some(next) => {
+                    part.push('\\');
+                    part.push(next);
+                }
+                None => part.push('\\'),
+            },
+            c if c == delimiter => parts.push(String::new()),
+            c => part.push(c),
+        }
+    }
+    parts
+}
+
+/// Convert a sed replacement (`\1`, `&`) to `regex` syntax
+fn sed_replacement(replacement: &str) -> String {
+    let mut converted = String::new();
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => converted.push_str(&format!("${{{}}}", digit)),
+                Some('$') => converted.push_str("$$"),
+                Some(other) => converted.push(other),
+                None => converted.push('\\'),
+            },
+            '&' => converted.push_str("${0}"),
+            '$' => converted.push_str("$$"),
+            c => converted.push(c),
+        }
+    }
+    converted
+}
+
+/// A parsed `--transform` filter
+#[derive(Clone, Debug)]
+pub struct Transform(Filter);
+
+impl Transform {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            source,
+            position: 0,
+        };
+        let filter = parser.pipe()?;
+        parser.skip_whitespace();
+        match parser.peek() {
+            None => Ok(Transform(filter)),
+            Some(c) => Err(parser.error(&format!("unexpected '{}'", c))),
+        }
+    }
+
+    /// Apply the filter to a JSON value
+    pub fn apply(&self, input: Value) -> Result<Value, String> {
+        self.0.apply(input)
+    }
+
+    /// Apply the filter to a stored value, read as JSON when it parses
+    pub fn apply_to_text(&self, value: &str) -> Result<String, String> {
+        let input =
+            serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()));
+        Ok(match self.apply(input)? {
+            Value::String(text) => text,
+            other => other.to_string(),
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Field(String),
+    Index(i64),
+}
+
+#[derive(Clone, Debug)]
+enum Filter {
+    Path(Vec<Segment>),
+    Literal(Value),
+    Assign(Vec<Segment>, Box<Filter>),
+    Update(Vec<Segment>, Box<Filter>),
+    Delete(Vec<Segment>),
+    Replace {
+        pattern: Regex,
+        replacement: String,
+        all: bool,
+    },
+    Downcase,
+    Upcase,
+    ToString,
+    ToNumber,
+    Pipe(Box<Filter>, Box<Filter>),
+}
+
+impl Filter {
+    fn apply(&self, input: Value) -> Result<Value, String> {
+        match self {
+            Filter::Path(path) => get(&input, path),
+            Filter::Literal(value) => Ok(value.clone()),
+            Filter::Assign(path, filter) => {
+                let value = filter.apply(input.clone())?;
+                set(input, path, value)
+            }
+            Filter::Update(path, filter) => {
+                let value = filter.apply(get(&input, path)?)?;
+                set(input, path, value)
+            }
+            Filter::Delete(path) => Ok(delete(input, path)),
+            Filter::Replace {
+                pattern,
+                replacement,
+                all,
+            } => {
+                let text = expect_string(&input, "sub")?;
+                Ok(Value::String(if *all {
+                    pattern.replace_all(text, replacement.as_str()).into_owned()
+                } else {
+                    pattern.replace(text, replacement.as_str()).into_owned()
+                }))
+            }
+            Filter::Downcase => Ok(expect_string(&input, "ascii_downcase")?
+                .to_ascii_lowercase()
+                .into()),
+            Filter::Upcase => Ok(expect_string(&input, "ascii_upcase")?
+                .to_ascii_uppercase()
+                .into()),
+            Filter::ToString => Ok(match input {
+                Value::String(_) => input,
+                other => Value::String(other.to_string()),
+            }),
+            Filter::ToNumber => match &input {
+                Value::Number(_) => Ok(input),
+                Value::String(text) => serde_json::from_str::<serde_json::Number>(text.trim())
+                    .map(Value::Number)
+                    .map_err(|_| format!("cannot parse \"{}\" as a number", text)),
+                other => Err(format!("{} cannot be parsed as a number", type_name(other))),
+            },
+            Filter::Pipe(first, second) => second.apply(first.apply(input)?),
+        }
+    }
+}
+
+fn expect_string<'v>(value: &'v Value, function: &str) -> Result<&'v str, String> {
+    value
+        .as_str()
+        .ok_or_else(|| format!("{} needs a string, not {}", function, type_name(value)))
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+/// Resolve a negative index from the end of an array of `len` items
+fn array_index(index: i64, len: usize) -> Option<usize> {
+    if index >= 0 {
+        Some(index as usize)
+    } else {
+        len.checked_sub(index.unsigned_abs() as usize)
+    }
+}
+
+fn get(value: &Value, path: &[Segment]) -> Result<Value, String> {
+    let Some((segment, rest)) = path.split_first() else {
+        return Ok(value.clone());
+    };
+    let child = match (value, segment) {
+        (Value::Null, _) => None,
+        (Value::Object(map), Segment::Field(name)) => map.get(name),
+        (Value::Array(items), Segment::Index(index)) => {
+            array_index(*index, items.len()).and_then(|index| items.get(index))
+        }
+        (other, segment) => return Err(index_error(other, segment)),
+    };
+    get(child.unwrap_or(&Value::Null), rest)
+}
+
+fn set(value: Value, path: &[Segment], new: Value) -> Result<Value, String> {
+    let Some((segment, rest)) = path.split_first() else {
+        return Ok(new);
+    };
+    match (value, segment) {
+        (Value::Null, Segment::Field(name)) => {
+            let mut map = Map::new();
+            map.insert(name.clone(), set(Value::Null, rest, new)?);
+            Ok(Value::Object(map))
+        }
+        (Value::Object(mut map), Segment::Field(name)) => {
+            let child = map.remove(name).unwrap_or(Value::Null);
+            map.insert(name.clone(), set(child, rest, new)?);
+            Ok(Value::Object(map))
+        }
+        (Value::Null, Segment::Index(_)) => set(Value::Array(vec![]), path, new),
+        (Value::Array(mut items), Segment::Index(index)) => {
+            let index = array_index(*index, items.len())
+                .ok_or_else(|| format!("index {} is out of range", index))?;
+            if index >= items.len() {
+                items.resize(index + 1, Value::Null);
+            }
+            let child = std::mem::take(&mut items[index]);
+            items[index] = set(child, rest, new)?;
+            Ok(Value::Array(items))
+        }
+        (other, segment) => Err(index_error(&other, segment)),
+    }
+}
+
+fn delete(value: Value, path: &[Segment]) -> Value {
+    let Some((segment, rest)) = path.split_first() else {
+        return Value::Null;
+    };
+    match (value, segment) {
+        (Value::Object(mut map), Segment::Field(name)) => {
+            if rest.is_empty() {
+                map.remove(name);
+            } else if let Some(child) = map.remove(name) {
+                map.insert(name.clone(), delete(child, rest));
+            }
+            Value::Object(map)
+        }
+        (Value::Array(mut items), Segment::Index(index)) => {
+            if let Some(index) = array_index(*index, items.len()).filter(|i| *i < items.len()) {
+                if rest.is_empty() {
+                    items.remove(index);
+                } else {
+                    let child = std::mem::take(&mut items[index]);
+                    items[index] = delete(child, rest);
+                }
+            }
+            Value::Array(items)
+        }
+        // Nothing to delete
+        (other, _) => other,
+    }
+}
+
+fn index_error(value: &Value, segment: &Segment) -> String {
+    match segment {
+        Segment::Field(name) => format!("cannot index {} with \"{}\"", type_name(value), name),
+        Segment::Index(index) => format!("cannot index {} with {}", type_name(value), index),
+    }
+}
+
+/// Recursive descent parser over the filter source
+struct Parser<'s> {
+    source: &'s str,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn rest(&self) -> &str {
+        &self.source[self.position..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consume `token` if the input continues with it
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.position += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", token)))
+        }
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!(
+            "Invalid transform at column {}: {}",
+            self.position + 1,
+            message
+        )
+    }
+
+    /// `F | G | ...`
+    fn pipe(&mut self) -> Result<Filter, String> {
+        let mut filter = self.assignment()?;
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with('|') && !self.rest().starts_with("|=") {
+                self.position += 1;
+                let next = self.assignment()?;
+                filter = Filter::Pipe(Box::new(filter), Box::new(next));
+            } else {
+                return Ok(filter);
+            }
+        }
+    }
+
+    /// `PATH = F`, `PATH |= F` or a term
+    fn assignment(&mut self) -> Result<Filter, String> {
+        let start = self.position;
+        let term = self.term()?;
+        let update = if self.eat("|=") {
+            true
+        } else if self.rest().starts_with('=') && !self.rest().starts_with("==") {
+            self.position += 1;
+            false
+        } else {
+            return Ok(term);
+        };
+        let Filter::Path(path) = term else {
+            self.position = start;
+            return Err(self.error("the left side of an assignment must be a path"));
+        };
+        let value = Box::new(self.term()?);
+        Ok(if update {
+            Filter::Update(path, value)
+        } else {
+            Filter::Assign(path, value)
+        })
+    }
+
+    fn term(&mut self) -> Result<Filter, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('.') => Ok(Filter::Path(self.path()?)),
+            Some('(') => {
+                self.position += 1;
+                let filter = self.pipe()?;
+                self.expect(")")?;
+                Ok(filter)
+            }
+            Some('"' | '{' | '[') => Ok(Filter::Literal(self.literal()?)),
+            Some(c) if c == '-' || c.is_ascii_digit() => Ok(Filter::Literal(self.number()?)),
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => self.function(),
+            Some(c) => Err(self.error(&format!("unexpected '{}'", c))),
+            None => Err(self.error("unexpected end of filter")),
+        }
+    }
+
+    fn function(&mut self) -> Result<Filter, String> {
+        let start = self.position;
+        let name = self.identifier();
+        match name.as_str() {
+            "true" => Ok(Filter::Literal(Value::Bool(true))),
+            "false" => Ok(Filter::Literal(Value::Bool(false))),
+            "null" => Ok(Filter::Literal(Value::Null)),
+            "ascii_downcase" => Ok(Filter::Downcase),
+            "ascii_upcase" => Ok(Filter::Upcase),
+            "tostring" => Ok(Filter::ToString),
+            "tonumber" => Ok(Filter::ToNumber),
+            "del" => {
+                self.expect("(")?;
+                self.skip_whitespace();
+                let path = self.path()?;
+                self.expect(")")?;
+                Ok(Filter::Delete(path))
+            }
+            "sub" | "gsub" => {
+                self.expect("(")?;
+                let pattern = self.string()?;
+                self.expect(";")?;
+                let replacement = self.string()?;
+                self.expect(")")?;
+                let pattern = Regex::new(&pattern)
+                    .map_err(|e| self.error(&format!("invalid regex: {}", e)))?;
+                Ok(Filter::Replace {
+                    pattern,
+                    replacement,
+                    all: name == "gsub",
+                })
+            }
+            _ => {
+                self.position = start;
+                Err(self.error(&format!("unknown function '{}'", name)))
+            }
+        }
+    }
+
+    fn identifier(&mut self) -> String {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let identifier = rest[..len].to_string();
+        self.position += len;
+        identifier
+    }
+
+    /// `.`, `.a`, `."a b"`, `.a[0].b`, `.["a b"]`
+    fn path(&mut self) -> Result<Vec<Segment>, String> {
+        if !self.rest().starts_with('.') {
+            return Err(self.error("expected a path"));
+        }
+        let mut segments = Vec::new();
+        let mut dotted = true;
+        self.position += 1;
+        loop {
+            match self.peek() {
+                Some(c) if dotted && (c.is_ascii_alphabetic() || c == '_') => {
+                    segments.push(Segment::Field(self.identifier()));
+                }
+                Some('"') if dotted => segments.push(Segment::Field(self.string()?)),
+                Some('[') => {
+                    self.position += 1;
+                    self.skip_whitespace();
+                    let segment = if self.peek() == Some('"') {
+                        Segment::Field(self.string()?)
+                    } else {
+                        match self.number()? {
+                            Value::Number(n) if n.is_i64() => Segment::Index(n.as_i64().unwrap()),
+                            _ => return Err(self.error("array indexes must be integers")),
+                        }
+                    };
+                    self.expect("]")?;
+                    segments.push(segment);
+                }
+                _ if dotted && !segments.is_empty() => {
+                    return Err(self.error("expected a field name after '.'"));
+                }
+                _ => return Ok(segments),
+            }
+            dotted = self.peek() == Some('.');
+            if dotted {
+                self.position += 1;
+            }
+        }
+    }
+
+    /// A JSON string, object or array
+    fn literal(&mut self) -> Result<Value, String> {
+        let mut stream = serde_json::Deserializer::from_str(self.rest()).into_iter::<Value>();
+        match stream.next() {
+            Some(Ok(value)) => {
+                self.position += stream.byte_offset();
+                Ok(value)
+            }
+            Some(Err(e)) => Err(self.error(&format!("invalid literal: {}", e))),
+            None => Err(self.error("unexpected end of filter")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        match self.literal()? {
+            Value::String(text) => Ok(text),
+            _ => Err(self.error("expected a string")),
+        }
+    }
+
+    /// JSON numbers are not self-delimiting for serde_json, so scan them
+    fn number(&mut self) -> Result<Value, String> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')))
+            .unwrap_or(rest.len());
+        let number = serde_json::from_str::<serde_json::Number>(&rest[..len])
+            .map_err(|_| self.error(&format!("invalid number '{}'", &rest[..len])))?;
+        self.position += len;
+        Ok(Value::Number(number))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn run(filter: &str, input: Value) -> Result<Value, String> {
+        Transform::parse(filter)?.apply(input)
+    }
+
+    #[test]
+    fn test_key_rewrites() {
+        let add = KeyRewrite::parse_prefix("add:prod:").unwrap();
+        assert_eq!(add.apply("user:1"), "prod:user:1");
+        let strip = KeyRewrite::parse_prefix("strip:staging:").unwrap();
+        assert_eq!(strip.apply("staging:user:1"), "user:1");
+        assert_eq!(strip.apply("other:1"), "other:1");
+        assert!(KeyRewrite::parse_prefix("prod:").is_err());
+
+        let map = KeyRewrite::parse_map(r"s/^(\w+):(\d+)$/\2-\1 & $/").unwrap();
+        assert_eq!(map.apply("user:42"), "42-user user:42 $");
+        let map = KeyRewrite::parse_map("s|a/b|c|g").unwrap();
+        assert_eq!(map.apply("a/b:a/b"), "c:c");
+        let map = KeyRewrite::parse_map(r"s/\//:/").unwrap();
+        assert_eq!(map.apply("a/b/c"), "a:b/c");
+
+        assert!(KeyRewrite::parse_map("s/a/b").is_err());
+        assert!(KeyRewrite::parse_map("s/a/b/x").is_err());
+        assert!(KeyRewrite::parse_map("y/a/b/").is_err());
+        assert!(KeyRewrite::parse_map("s/(/b/").is_err());
+    }
+
+    #[test]
+    fn test_transform_paths() {
+        let input = json!({"a": {"b": [1, 2, 3]}, "c d": true});
+        assert_eq!(run(".", input.clone()), Ok(input.clone()));
+        assert_eq!(run(".a.b[1]", input.clone()), Ok(json!(2)));
+        assert_eq!(run(".a.b[-1]", input.clone()), Ok(json!(3)));
+        assert_eq!(run(r#".["c d"]"#, input.clone()), Ok(json!(true)));
+        assert_eq!(run(r#"."c d""#, input.clone()), Ok(json!(true)));
+        assert_eq!(run(".missing.x", input.clone()), Ok(Value::Null));
+        assert!(run(".a.b.c", input).is_err());
+    }
+
+    #[test]
+    fn test_transform_updates() {
+        let input = json!({"env": "staging", "host": "staging.example.com", "old": 1});
+        let output = run(
+            r#".env = "prod" | .host |= sub("^staging"; "www") | del(.old) | .tags[1] = .env"#,
+            input,
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            json!({"env": "prod", "host": "www.example.com", "tags": [null, "prod"]})
+        );
+
+        assert_eq!(
+            run(r#"gsub("a"; "o") | ascii_upcase"#, json!("banana")),
+            Ok(json!("BONONO"))
+        );
+        assert_eq!(
+            run(".n |= tonumber", json!({"n": "42"})),
+            Ok(json!({"n": 42}))
+        );
+        assert_eq!(
+            run(".n |= tostring", json!({"n": 4})),
+            Ok(json!({"n": "4"}))
+        );
+        assert_eq!(
+            run(r#".meta = {"v": [1, 2]}"#, json!({})),
+            Ok(json!({"meta": {"v": [1, 2]}}))
+        );
+        assert!(run("ascii_upcase", json!(1)).is_err());
+    }
+
+    #[test]
+    fn test_transform_errors() {
+        assert!(Transform::parse(".a = ").is_err());
+        assert!(Transform::parse(".a.").is_err());
+        assert!(Transform::parse("frobnicate").is_err());
+        assert!(Transform::parse(".a ]").is_err());
+        assert!(Transform::parse(r#"sub("("; "x")"#).is_err());
+        assert!(Transform::parse("1 = 2").is_err());
+        assert!(Transform::parse("(.a | .b) = 1").is_err());
+    }
+
+    #[test]
+    fn test_apply_to_text() {
+        let transform = Transform::parse(r#"gsub("staging"; "prod")"#).unwrap();
+        assert_eq!(
+            transform.apply_to_text("db.staging.internal").unwrap(),
+            "db.prod.internal"
+        );
+        let transform = Transform::parse(".replicas = 3").unwrap();
+        assert_eq!(
+            transform.apply_to_text("{\n  \"replicas\": 1\n}").unwrap(),
+            r#"{"replicas":3}"#
+        );
+    }
+}
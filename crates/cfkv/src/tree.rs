@@ -0,0 +1,177 @@
+//! Key prefix tree for `cfkv tree`
+//!
+//! Keys are split at a delimiter into nested prefixes (`post:2024:a` under
+//! `post:` and `post:2024:`), down to a maximum depth. Each prefix counts
+//! the keys below it and, when sizes are collected, their total size; keys
+//! themselves are not listed, so the tree stays readable for namespaces with
+//! many thousands of keys.
+
+use crate::formatter::Formatter;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Prefixes nested at a delimiter, built one key at a time
+pub struct KeyTree {
+    delimiter: char,
+    depth: usize,
+    sizes: bool,
+    root: Node,
+}
+
+#[derive(Default)]
+struct Node {
+    keys: u64,
+    bytes: u64,
+    children: BTreeMap<String, Node>,
+}
+
+impl Node {
+    fn add(&mut self, size: Option<u64>) {
+        self.keys += 1;
+        self.bytes += size.unwrap_or(0);
+    }
+}
+
+/// A prefix with the counts of the keys below it
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TreeNode {
+    /// Full prefix, e.g. `post:2024:`; empty for the whole namespace
+    pub prefix: String,
+    pub keys: u64,
+    /// Total size of the values below, when sizes were collected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<TreeNode>,
+}
+
+impl KeyTree {
+    /// An empty tree splitting keys at `delimiter`, `depth` levels deep
+    pub fn new(delimiter: char, depth: usize, sizes: bool) -> Self {
+        Self {
+            delimiter,
+            depth,
+            sizes,
+            root: Node::default(),
+        }
+    }
+
+    /// Count a key, with the size of its value if known
+    pub fn insert(&mut self, key: &str, size: Option<u64>) {
+        self.root.add(size);
+        let mut node = &mut self.root;
+        let mut end = 0;
+        for _ in 0..self.depth {
+            let Some(position) = key[end..].find(self.delimiter) else {
+                break;
+            };
+            end += position + self.delimiter.len_utf8();
+            node = node.children.entry(key[..end].to_string()).or_default();
+            node.add(size);
+        }
+    }
+
+    /// The tree with its prefixes in lexicographic order
+    pub fn finish(self) -> TreeNode {
+        let sizes = self.sizes;
+        convert(String::new(), self.root, sizes)
+    }
+}
+
+fn convert(prefix: String, node: Node, sizes: bool) -> TreeNode {
+    TreeNode {
+        prefix,
+        keys: node.keys,
+        bytes: sizes.then_some(node.bytes),
+        children: node
+            .children
+            .into_iter()
+            .map(|(prefix, child)| convert(prefix, child, sizes))
+            .collect(),
+    }
+}
+
+/// Render the tree like `tree`, one prefix per line with its key count and
+/// size; children show only their own segment of the prefix
+pub fn render(root: &TreeNode) -> String {
+    let mut lines = vec![("(all keys)".to_string(), root.keys, root.bytes)];
+    render_children(root, "", &mut lines);
+
+    let width = lines
+        .iter()
+        .map(|(label, _, _)| label.chars().count())
+        .max()
+        .unwrap_or(0);
+    let mut output = String::new();
+    for (label, keys, bytes) in lines {
+        let padding = width - label.chars().count();
+        output.push_str(&format!(
+            "{}{}  {:>7} {}",
+            label,
+            " ".repeat(padding),
+            keys,
+            if keys == 1 { "key " } else { "keys" }
+        ));
+        if let Some(bytes) = bytes {
+            output.push_str(&format!("  {:>10}", Formatter::human_size(bytes)));
+        }
+        output.push('\n');
+    }
+    output
+}
+
+fn render_children(node: &TreeNode, indent: &str, lines: &mut Vec<(String, u64, Option<u64>)>) {
+    for (index, child) in node.children.iter().enumerate() {
+        let last = index + 1 == node.children.len();
+        let segment = &child.prefix[node.prefix.len()..];
+        let branch = if last { "└── " } else { "├── " };
+        lines.push((
+            format!("{}{}{}", indent, branch, segment),
+            child.keys,
+            child.bytes,
+        ));
+        let indent = format!("{}{}", indent, if last { "    " } else { "│   " });
+        render_children(child, &indent, lines);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_tree() {
+        let mut tree = KeyTree::new(':', 2, true);
+        tree.insert("post:2024:a", Some(100));
+        tree.insert("post:2024:b", Some(50));
+        tree.insert("post:2023:a:draft", Some(10));
+        tree.insert("post:index", Some(5));
+        tree.insert("session:x", Some(1));
+        tree.insert("_blog_list", Some(2));
+        let root = tree.finish();
+
+        assert_eq!((root.keys, root.bytes), (6, Some(168)));
+        let prefixes: Vec<_> = root.children.iter().map(|c| c.prefix.as_str()).collect();
+        assert_eq!(prefixes, ["post:", "session:"]);
+
+        let post = &root.children[0];
+        assert_eq!((post.keys, post.bytes), (4, Some(165)));
+        let years: Vec<_> = post
+            .children
+            .iter()
+            .map(|c| (c.prefix.as_str(), c.keys))
+            .collect();
+        // `post:2023:a:` is below the depth limit and counts toward `post:2023:`
+        assert_eq!(years, [("post:2023:", 1), ("post:2024:", 2)]);
+        assert!(post.children[0].children.is_empty());
+
+        assert_eq!(
+            render(&root),
+            "(all keys)           6 keys       168 B\n\
+             ├── post:            4 keys       165 B\n\
+             │   ├── 2023:        1 key         10 B\n\
+             │   └── 2024:        2 keys       150 B\n\
+             └── session:         1 key          1 B\n"
+        );
+    }
+}
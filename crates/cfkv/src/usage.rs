@@ -0,0 +1,127 @@
+//! API usage summary for `--stats`
+//!
+//! Every client of a command adds to one set of `Metrics`, so `cfkv --stats`
+//! can print the requests sent and value bytes transferred once the command
+//! finishes, failed or not. The summary goes to stderr, keeping stdout for
+//! the command's output in pipelines:
+//!
+//! ```text
+//! Stats: 14 requests (1 failed), 2.0 KiB uploaded, 1.3 MiB downloaded in 2.4s; largest value: reports/2024 (1.2 MiB)
+//! ```
+//!
+//! JSON and YAML output print the same numbers as one structured object.
+
+use crate::formatter::{Formatter, OutputFormat};
+use cloudflare_kv::{Metrics, MetricsSnapshot};
+use serde_json::json;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+/// Counters shared by every client of the command
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+/// When `--stats` was given, the output format and start of the command
+static WATCHED: OnceLock<(OutputFormat, Instant)> = OnceLock::new();
+
+/// The command's counters, to give each client it builds
+pub fn metrics() -> Metrics {
+    METRICS.get_or_init(Metrics::new).clone()
+}
+
+/// Print a summary once the command finishes
+pub fn watch(format: OutputFormat) {
+    let _ = WATCHED.set((format, Instant::now()));
+}
+
+/// Print the summary if `--stats` was given
+pub fn report() {
+    if let Some((format, started)) = WATCHED.get() {
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        eprintln!("{}", summary(&metrics().snapshot(), elapsed_ms, *format));
+    }
+}
+
+fn summary(metrics: &MetricsSnapshot, elapsed_ms: u64, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Json | OutputFormat::Yaml => {
+            let mut value = serde_json::to_value(metrics).unwrap_or_default();
+            value["duration_ms"] = json!(elapsed_ms);
+            let value = json!({ "stats": value });
+            match format {
+                OutputFormat::Yaml => serde_yaml::to_string(&value).unwrap_or_default(),
+                _ => value.to_string(),
+            }
+        }
+        _ => {
+            let mut text = format!(
+                "Stats: {} request{}",
+                metrics.requests,
+                if metrics.requests == 1 { "" } else { "s" }
+            );
+            if metrics.failed > 0 {
+                text.push_str(&format!(" ({} failed)", metrics.failed));
+            }
+            text.push_str(&format!(
+                ", {} uploaded, {} downloaded in {}",
+                Formatter::human_size(metrics.bytes_uploaded),
+                Formatter::human_size(metrics.bytes_downloaded),
+                format_elapsed(elapsed_ms)
+            ));
+            if let Some(largest) = &metrics.largest_value {
+                text.push_str(&format!(
+                    "; largest value: {} ({})",
+                    largest.key,
+                    Formatter::human_size(largest.bytes)
+                ));
+            }
+            text
+        }
+    }
+}
+
+fn format_elapsed(ms: u64) -> String {
+    match ms {
+        0..=999 => format!("{}ms", ms),
+        _ => format!("{:.1}s", ms as f64 / 1000.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cloudflare_kv::LargestValue;
+
+    #[test]
+    fn test_summary() {
+        let mut metrics = MetricsSnapshot {
+            requests: 14,
+            failed: 1,
+            bytes_uploaded: 2048,
+            bytes_downloaded: 512,
+            largest_value: Some(LargestValue {
+                key: "reports/2024".to_string(),
+                bytes: 300,
+            }),
+        };
+        assert_eq!(
+            summary(&metrics, 2300, OutputFormat::Text),
+            "Stats: 14 requests (1 failed), 2.0 KiB uploaded, 512 B downloaded in 2.3s; largest value: reports/2024 (300 B)"
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&summary(&metrics, 40, OutputFormat::Json)).unwrap();
+        assert_eq!(json["stats"]["requests"], 14);
+        assert_eq!(json["stats"]["bytes_uploaded"], 2048);
+        assert_eq!(json["stats"]["largest_value"]["key"], "reports/2024");
+        assert_eq!(json["stats"]["duration_ms"], 40);
+
+        metrics = MetricsSnapshot {
+            requests: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            summary(&metrics, 40, OutputFormat::Text),
+            "Stats: 1 request, 0 B uploaded, 0 B downloaded in 40ms"
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! Integrity manifests for `cfkv verify`
+//!
+//! `cfkv verify --generate` records the SHA-256 and size of every value
+//! under a prefix; `cfkv verify` later reads the same keys and reports the
+//! ones that are missing or whose value no longer matches:
+//!
+//! ```json
+//! {
+//!   "generated_at": "2024-05-01T12:00:00Z",
+//!   "prefix": "user:",
+//!   "keys": {"user:1": {"sha256": "9f86d0...", "size": 4}}
+//! }
+//! ```
+
+use crate::diff::{Digest, Snapshot};
+use cloudflare_kv::{KvError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Hash and size recorded for one key
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub sha256: String,
+    pub size: u64,
+}
+
+/// Recorded hashes of a namespace's values
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub generated_at: String,
+    /// Prefix the keys were listed under
+    #[serde(default)]
+    pub prefix: String,
+    pub keys: BTreeMap<String, ManifestEntry>,
+}
+
+impl Manifest {
+    pub fn from_snapshot(snapshot: Snapshot, prefix: &str) -> Self {
+        Self {
+            generated_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            prefix: prefix.to_string(),
+            keys: snapshot
+                .into_iter()
+                .map(|(key, digest)| {
+                    let entry = ManifestEntry {
+                        sha256: digest.hash,
+                        size: digest.size,
+                    };
+                    (key, entry)
+                })
+                .collect(),
+        }
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        serde_json::from_str(&content).map_err(|e| {
+            KvError::SerializationError(format!("Invalid manifest {}: {}", path.display(), e))
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        Ok(())
+    }
+
+    /// Keys listed in the manifest
+    pub fn key_names(&self) -> Vec<String> {
+        self.keys.keys().cloned().collect()
+    }
+
+    /// Compare the manifest with the current values of its keys
+    pub fn verify(&self, current: &Snapshot) -> VerifyReport {
+        let mut report = VerifyReport::default();
+        for (key, expected) in &self.keys {
+            match current.get(key) {
+                None => report.missing.push(key.clone()),
+                Some(Digest { hash, size }) if *hash != expected.sha256 => {
+                    report.corrupted.push(CorruptedKey {
+                        key: key.clone(),
+                        expected_size: expected.size,
+                        actual_size: *size,
+                    })
+                }
+                Some(_) => report.verified += 1,
+            }
+        }
+        report
+    }
+}
+
+/// A key whose value no longer matches the manifest
+#[derive(Debug, PartialEq, Serialize)]
+pub struct CorruptedKey {
+    pub key: String,
+    pub expected_size: u64,
+    pub actual_size: u64,
+}
+
+/// Outcome of checking a manifest
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct VerifyReport {
+    pub verified: usize,
+    pub corrupted: Vec<CorruptedKey>,
+    pub missing: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.corrupted.is_empty() && self.missing.is_empty()
+    }
+
+    /// One line per failed key, then a summary line
+    pub fn render_text(&self) -> String {
+        let mut text = String::new();
+        for entry in &self.corrupted {
+            text.push_str(&format!(
+                "CORRUPTED {} ({} bytes, expected {})\n",
+                entry.key, entry.actual_size, entry.expected_size
+            ));
+        }
+        for key in &self.missing {
+            text.push_str(&format!("MISSING   {}\n", key));
+        }
+        text.push_str(&format!(
+            "{} verified, {} corrupted, {} missing",
+            self.verified,
+            self.corrupted.len(),
+            self.missing.len()
+        ));
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(entries: &[(&str, &str)]) -> Snapshot {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), Digest::of(value.as_bytes())))
+            .collect()
+    }
+
+    #[test]
+    fn test_verify() {
+        let manifest = Manifest::from_snapshot(snapshot(&[("a", "1"), ("b", "2"), ("c", "3")]), "");
+        let report = manifest.verify(&snapshot(&[("a", "1"), ("b", "22")]));
+
+        assert_eq!(report.verified, 1);
+        assert_eq!(
+            report.corrupted,
+            vec![CorruptedKey {
+                key: "b".into(),
+                expected_size: 1,
+                actual_size: 2,
+            }]
+        );
+        assert_eq!(report.missing, vec!["c"]);
+        assert!(!report.is_ok());
+        assert_eq!(
+            report.render_text(),
+            "CORRUPTED b (2 bytes, expected 1)\nMISSING   c\n1 verified, 1 corrupted, 1 missing"
+        );
+    }
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let path = std::env::temp_dir().join(format!("cfkv-manifest-{}.json", std::process::id()));
+        let manifest = Manifest::from_snapshot(snapshot(&[("user:1", "ada")]), "user:");
+        manifest.write(&path).unwrap();
+
+        let read = Manifest::read(&path).unwrap();
+        assert_eq!(read, manifest);
+        assert_eq!(read.key_names(), vec!["user:1"]);
+        assert!(read.verify(&snapshot(&[("user:1", "ada")])).is_ok());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,379 @@
+//! Workers KV binding emulation for `cfkv serve --worker-compat`
+//!
+//! Speaks the protocol Miniflare uses between a Worker's KV binding and its
+//! storage, so a `wrangler dev` session can proxy its binding to real data:
+//!
+//! | Request         | Does                                                       |
+//! |-----------------|------------------------------------------------------------|
+//! | `GET /:key`     | value as the body; `?cache_ttl=SECS` (at least 60)         |
+//! | `PUT /:key`     | store the body; `?expiration=` or `?expiration_ttl=`        |
+//! | `DELETE /:key`  | delete the key                                             |
+//! | `GET /`         | list as the binding returns it; `prefix`, `limit`, `cursor` |
+//!
+//! Metadata travels as JSON in the `CF-KV-Metadata` header and expirations in
+//! `CF-Expiration`, both on reads and writes. Like the edge, reads are served
+//! from a local cache for `cache_ttl` seconds (60 unless given); writes and
+//! deletes made through the proxy drop the cached copy.
+
+use crate::serve::{self, ServeOptions};
+use cloudflare_kv::{KeyMetadata, PaginationParams, PluginClient, ReadOptions, ReadValue};
+use hyper::header::HeaderValue;
+use hyper::{Body, Method, Request, Response, StatusCode};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const METADATA_HEADER: &str = "CF-KV-Metadata";
+const EXPIRATION_HEADER: &str = "CF-Expiration";
+
+/// Shortest `cache_ttl` and `expiration_ttl` Workers accept, in seconds
+const MIN_TTL: u64 = 60;
+/// Largest serialized metadata Workers accept
+const MAX_METADATA_BYTES: usize = 1024;
+/// Largest page a list returns
+const MAX_LIST_LIMIT: u32 = 1000;
+
+/// Values read recently, each kept for the `cache_ttl` of the read that
+/// fetched it
+#[derive(Default)]
+pub struct EdgeCache {
+    entries: Mutex<HashMap<String, (ReadValue, Instant, Duration)>>,
+}
+
+impl EdgeCache {
+    fn get(&self, key: &str) -> Option<ReadValue> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        match entries.get(key) {
+            Some((entry, stored_at, ttl)) if stored_at.elapsed() < *ttl => Some(entry.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&self, key: &str, entry: ReadValue, ttl: Duration) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.insert(key.to_string(), (entry, Instant::now(), ttl));
+    }
+
+    fn invalidate(&self, key: &str) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.remove(key);
+    }
+}
+
+/// Answer one request from a KV binding
+pub async fn handle(
+    client: &PluginClient,
+    options: &ServeOptions,
+    cache: &EdgeCache,
+    request: Request<Body>,
+) -> Response<Body> {
+    if let Some(refused) = serve::refuse(options, &request) {
+        return refused;
+    }
+
+    let query = serve::query_params(request.uri().query());
+    let path = request.uri().path().to_string();
+    let key = match path.strip_prefix('/').unwrap_or(&path) {
+        "" => {
+            return match *request.method() {
+                Method::GET => list(client, &query).await,
+                _ => serve::error(StatusCode::METHOD_NOT_ALLOWED, "Use GET to list keys"),
+            };
+        }
+        key => match serve::decode_key(key) {
+            Some(key) => key,
+            None => return serve::error(StatusCode::BAD_REQUEST, "Key is not valid UTF-8"),
+        },
+    };
+
+    match *request.method() {
+        Method::GET => get(client, cache, &key, &query).await,
+        Method::PUT => put(client, cache, &key, &query, request).await,
+        Method::DELETE => {
+            cache.invalidate(&key);
+            match client.client().delete(&key).await {
+                Ok(()) => Response::new(Body::empty()),
+                Err(e) => serve::kv_error(&e),
+            }
+        }
+        _ => serve::error(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "Use GET, PUT or DELETE for /:key",
+        ),
+    }
+}
+
+async fn get(
+    client: &PluginClient,
+    cache: &EdgeCache,
+    key: &str,
+    query: &HashMap<String, String>,
+) -> Response<Body> {
+    let options = match ttl_param(query, "cache_ttl") {
+        Ok(ttl) => ReadOptions::new()
+            .with_cache_ttl(ttl.unwrap_or(MIN_TTL))
+            .with_metadata(),
+        Err(message) => return serve::error(StatusCode::BAD_REQUEST, &message),
+    };
+
+    let entry = match cache.get(key) {
+        Some(entry) => entry,
+        None => {
+            let entry = match client.get_with_options(key, &options).await {
+                Ok(Some(read)) => read,
+                Ok(None) => {
+                    return serve::error(StatusCode::NOT_FOUND, &format!("Key not found: {}", key))
+                }
+                Err(e) => return serve::kv_error(&e),
+            };
+            let cache_ttl = options.cache_ttl.unwrap_or(MIN_TTL);
+            cache.insert(key, entry.clone(), Duration::from_secs(cache_ttl));
+            entry
+        }
+    };
+
+    let mut response = Response::new(Body::from(entry.value));
+    let headers = response.headers_mut();
+    if let Some(metadata) = entry.metadata {
+        if let Ok(value) = HeaderValue::from_str(&metadata.to_string()) {
+            headers.insert(METADATA_HEADER, value);
+        }
+    }
+    if let Some(expiration) = entry.expiration {
+        headers.insert(EXPIRATION_HEADER, expiration.into());
+    }
+    response
+}
+
+async fn put(
+    client: &PluginClient,
+    cache: &EdgeCache,
+    key: &str,
+    query: &HashMap<String, String>,
+    request: Request<Body>,
+) -> Response<Body> {
+    let expiration_ttl = match ttl_param(query, "expiration_ttl") {
+        Ok(ttl) => ttl,
+        Err(message) => return serve::error(StatusCode::BAD_REQUEST, &message),
+    };
+    let expiration = match query.get("expiration").map(|at| at.parse::<u64>()) {
+        Some(Ok(at)) if at < chrono::Utc::now().timestamp() as u64 + MIN_TTL => {
+            return serve::error(
+                StatusCode::BAD_REQUEST,
+                &format!("expiration must be at least {} seconds from now", MIN_TTL),
+            )
+        }
+        Some(Ok(at)) => Some(at),
+        Some(Err(_)) => {
+            return serve::error(StatusCode::BAD_REQUEST, "expiration must be a Unix time")
+        }
+        None => None,
+    };
+    let metadata = match request.headers().get(METADATA_HEADER) {
+        Some(header) if header.len() > MAX_METADATA_BYTES => {
+            return serve::error(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                &format!("Metadata is larger than {} bytes", MAX_METADATA_BYTES),
+            )
+        }
+        Some(header) => match serde_json::from_slice(header.as_bytes()) {
+            Ok(metadata) => Some(metadata),
+            Err(e) => {
+                return serve::error(
+                    StatusCode::BAD_REQUEST,
+                    &format!("{} is not JSON: {}", METADATA_HEADER, e),
+                )
+            }
+        },
+        None => None,
+    };
+    let body = match hyper::body::to_bytes(request.into_body()).await {
+        Ok(body) => body,
+        Err(e) => return serve::error(StatusCode::BAD_REQUEST, &e.to_string()),
+    };
+
+    cache.invalidate(key);
+    let result = match expiration {
+        Some(at) => {
+            client
+                .put_with_expiration_at(key, &body, Some(at), metadata)
+                .await
+        }
+        None => {
+            client
+                .put_with_options(key, &body, expiration_ttl, metadata)
+                .await
+        }
+    };
+    match result {
+        Ok(()) => Response::new(Body::empty()),
+        Err(e) => serve::kv_error(&e),
+    }
+}
+
+async fn list(client: &PluginClient, query: &HashMap<String, String>) -> Response<Body> {
+    let limit = match query.get("limit").map(|limit| limit.parse::<u32>()) {
+        Some(Ok(limit)) if (1..=MAX_LIST_LIMIT).contains(&limit) => limit,
+        Some(_) => {
+            return serve::error(
+                StatusCode::BAD_REQUEST,
+                &format!("limit must be between 1 and {}", MAX_LIST_LIMIT),
+            )
+        }
+        None => MAX_LIST_LIMIT,
+    };
+    let mut params = PaginationParams::new().with_limit(limit);
+    if let Some(prefix) = query.get("prefix") {
+        params = params.with_prefix(prefix.clone());
+    }
+    if let Some(cursor) = query.get("cursor").filter(|cursor| !cursor.is_empty()) {
+        params = params.with_cursor(cursor.clone());
+    }
+
+    match client.client().list(Some(params)).await {
+        Ok(page) => {
+            let keys: Vec<_> = page.keys.iter().map(list_key).collect();
+            let mut body = serde_json::json!({
+                "keys": keys,
+                "list_complete": page.list_complete,
+            });
+            // The binding only has a cursor while there are more pages
+            if let Some(cursor) = page.cursor.filter(|cursor| !cursor.is_empty()) {
+                if !page.list_complete {
+                    body["cursor"] = cursor.into();
+                }
+            }
+            serve::json(StatusCode::OK, body)
+        }
+        Err(e) => serve::kv_error(&e),
+    }
+}
+
+/// A listed key as the binding returns it, without absent fields
+fn list_key(key: &KeyMetadata) -> serde_json::Value {
+    let mut item = serde_json::json!({ "name": key.name });
+    if let Some(expiration) = key.expiration {
+        item["expiration"] = expiration.into();
+    }
+    if let Some(metadata) = &key.metadata {
+        item["metadata"] = metadata.clone();
+    }
+    item
+}
+
+/// A TTL query parameter, which Workers reject below 60 seconds
+fn ttl_param(query: &HashMap<String, String>, name: &str) -> Result<Option<u64>, String> {
+    match query.get(name).map(|ttl| ttl.parse::<u64>()) {
+        Some(Ok(ttl)) if ttl >= MIN_TTL => Ok(Some(ttl)),
+        Some(_) => Err(format!("{} must be at least {} seconds", name, MIN_TTL)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cloudflare_kv::{KvClient, PluginRegistry};
+
+    fn options() -> ServeOptions {
+        ServeOptions {
+            addr: ([127, 0, 0, 1], 0).into(),
+            token: None,
+            read_only: false,
+            worker_compat: true,
+        }
+    }
+
+    fn request(method: Method, uri: &str, body: &str) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    async fn json(response: Response<Body>) -> serde_json::Value {
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_metadata_and_list() {
+        let client = PluginClient::new(KvClient::in_memory(), PluginRegistry::new());
+        let cache = EdgeCache::default();
+        let options = options();
+
+        let mut put = request(Method::PUT, "/user%3A1?expiration_ttl=3600", "ada");
+        put.headers_mut().insert(
+            METADATA_HEADER,
+            HeaderValue::from_static(r#"{"role":"admin"}"#),
+        );
+        let put = handle(&client, &options, &cache, put).await;
+        assert_eq!(put.status(), StatusCode::OK);
+
+        let get = handle(
+            &client,
+            &options,
+            &cache,
+            request(Method::GET, "/user%3A1", ""),
+        )
+        .await;
+        assert_eq!(get.headers()[METADATA_HEADER], r#"{"role":"admin"}"#);
+        assert!(get.headers().contains_key(EXPIRATION_HEADER));
+
+        client.put("user:2", "bob").await.unwrap();
+        let page = handle(
+            &client,
+            &options,
+            &cache,
+            request(Method::GET, "/?limit=1", ""),
+        )
+        .await;
+        let page = json(page).await;
+        assert_eq!(page["keys"][0]["name"], "user:1");
+        assert_eq!(page["keys"][0]["metadata"]["role"], "admin");
+        assert_eq!(page["list_complete"], false);
+
+        let uri = format!("/?cursor={}", page["cursor"].as_str().unwrap());
+        let rest =
+            json(handle(&client, &options, &cache, request(Method::GET, &uri, "")).await).await;
+        assert_eq!(rest["keys"], serde_json::json!([{"name": "user:2"}]));
+        assert_eq!(rest["list_complete"], true);
+        assert!(rest.get("cursor").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cache_ttl() {
+        let client = PluginClient::new(KvClient::in_memory(), PluginRegistry::new());
+        let cache = EdgeCache::default();
+        let options = options();
+        client.put("k", "v1").await.unwrap();
+
+        let get =
+            |uri: &'static str| handle(&client, &options, &cache, request(Method::GET, uri, ""));
+        assert_eq!(
+            get("/k?cache_ttl=10").await.status(),
+            StatusCode::BAD_REQUEST
+        );
+        get("/k?cache_ttl=300").await;
+
+        // Written behind the proxy's back: the cached copy is still served
+        client.put("k", "v2").await.unwrap();
+        let stale = hyper::body::to_bytes(get("/k").await.into_body())
+            .await
+            .unwrap();
+        assert_eq!(&stale[..], b"v1");
+
+        // Written through the proxy: the cache is dropped
+        let put = request(Method::PUT, "/k", "v3");
+        handle(&client, &options, &cache, put).await;
+        let fresh = hyper::body::to_bytes(get("/k").await.into_body())
+            .await
+            .unwrap();
+        assert_eq!(&fresh[..], b"v3");
+    }
+}
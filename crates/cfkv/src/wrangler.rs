@@ -0,0 +1,119 @@
+//! Reading KV namespace bindings from a wrangler.toml
+//!
+//! Only the parts `storage import-wrangler` needs are parsed: the account ID
+//! and the `kv_namespaces` of the top level or of one `[env.<name>]`
+//! section. As in wrangler, environments do not inherit the top-level
+//! bindings.
+
+use cloudflare_kv::{KvError, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// A `[[kv_namespaces]]` entry
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct KvBinding {
+    /// Name the Worker sees the namespace under
+    pub binding: String,
+    pub id: String,
+    /// Namespace used by `wrangler dev` instead of `id`
+    #[serde(default)]
+    pub preview_id: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Section {
+    account_id: Option<String>,
+    #[serde(default)]
+    kv_namespaces: Vec<KvBinding>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WranglerToml {
+    #[serde(flatten)]
+    top: Section,
+    #[serde(default)]
+    env: HashMap<String, Section>,
+}
+
+/// The bindings of a wrangler.toml for one environment
+#[derive(Debug, PartialEq)]
+pub struct WranglerBindings {
+    pub account_id: Option<String>,
+    pub bindings: Vec<KvBinding>,
+}
+
+impl WranglerBindings {
+    /// Read the bindings of `env`, or of the top level when `None`
+    pub fn read(path: &Path, env: Option<&str>) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Self::parse(&content, env)
+            .map_err(|e| KvError::InvalidConfig(format!("{}: {}", path.display(), e)))
+    }
+
+    fn parse(content: &str, env: Option<&str>) -> std::result::Result<Self, String> {
+        let mut file: WranglerToml = toml::from_str(content).map_err(|e| e.to_string())?;
+        let top_account = file.top.account_id.take();
+        let section = match env {
+            Some(env) => file
+                .env
+                .remove(env)
+                .ok_or_else(|| format!("no [env.{}] section", env))?,
+            None => file.top,
+        };
+        if section.kv_namespaces.is_empty() {
+            return Err("no kv_namespaces bindings".to_string());
+        }
+        Ok(Self {
+            account_id: section.account_id.or(top_account),
+            bindings: section.kv_namespaces,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WRANGLER: &str = r#"
+name = "site"
+main = "src/index.ts"
+account_id = "acc"
+
+kv_namespaces = [
+  { binding = "CACHE", id = "c1", preview_id = "c1p" },
+]
+
+[[env.staging.kv_namespaces]]
+binding = "CACHE"
+id = "c2"
+
+[[env.staging.kv_namespaces]]
+binding = "SESSIONS"
+id = "s2"
+"#;
+
+    #[test]
+    fn test_parse_bindings() {
+        let top = WranglerBindings::parse(WRANGLER, None).unwrap();
+        assert_eq!(top.account_id.as_deref(), Some("acc"));
+        assert_eq!(
+            top.bindings,
+            [KvBinding {
+                binding: "CACHE".to_string(),
+                id: "c1".to_string(),
+                preview_id: Some("c1p".to_string()),
+            }]
+        );
+
+        let staging = WranglerBindings::parse(WRANGLER, Some("staging")).unwrap();
+        assert_eq!(staging.account_id.as_deref(), Some("acc"));
+        let ids: Vec<_> = staging.bindings.iter().map(|b| b.id.as_str()).collect();
+        assert_eq!(ids, ["c2", "s2"]);
+
+        assert!(WranglerBindings::parse(WRANGLER, Some("prod"))
+            .unwrap_err()
+            .contains("[env.prod]"));
+        assert!(WranglerBindings::parse("name = \"site\"", None).is_err());
+    }
+}
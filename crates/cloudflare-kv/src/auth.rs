@@ -1,8 +1,10 @@
 use crate::error::{KvError, Result};
 use crate::types::AuthCredentials;
+#[cfg(feature = "native")]
 use std::fs;
-#[cfg(unix)]
+#[cfg(all(unix, feature = "native"))]
 use std::io::Write;
+#[cfg(feature = "native")]
 use std::path::Path;
 
 /// Authentication manager for handling credentials
@@ -37,6 +39,7 @@ impl AuthManager {
     }
 
     /// Load credentials from a config file
+    #[cfg(feature = "native")]
     pub fn from_file(path: &Path) -> Result<Self> {
         if !path.exists() {
             return Err(KvError::AuthError(format!(
@@ -54,6 +57,7 @@ impl AuthManager {
     }
 
     /// Parse credentials from config file content
+    #[cfg_attr(not(feature = "native"), allow(dead_code))]
     fn parse_config(content: &str) -> Result<AuthCredentials> {
         for line in content.lines() {
             let line = line.trim();
@@ -86,6 +90,7 @@ impl AuthManager {
     }
 
     /// Save credentials to a config file
+    #[cfg(feature = "native")]
     pub fn save_to_file(&self, path: &Path) -> Result<()> {
         let creds = self.credentials()?;
 
@@ -1,5 +1,10 @@
-use crate::error::Result;
+use crate::cancel::CancellationToken;
+use crate::error::{KvError, Result};
+use crate::transport::{BulkWrite, Expiration};
 use crate::KvClient;
+use async_trait::async_trait;
+use serde_json::Value;
+use std::collections::HashSet;
 
 /// Batch operation builder for efficient bulk operations
 pub struct BatchBuilder {
@@ -8,8 +13,64 @@ pub struct BatchBuilder {
 
 #[derive(Clone, Debug)]
 pub enum BatchOperation {
-    Put { key: String, value: Vec<u8> },
-    Delete { key: String },
+    /// Write a value, with the options of the Cloudflare bulk write API
+    Put {
+        key: String,
+        value: Vec<u8>,
+        /// Seconds from now until the key expires
+        expiration_ttl: Option<u64>,
+        /// Unix timestamp at which the key expires
+        expiration: Option<u64>,
+        metadata: Option<Value>,
+        /// Send the value base64-encoded, as binary values must be
+        base64: bool,
+    },
+    Delete {
+        key: String,
+    },
+}
+
+impl BatchOperation {
+    /// Key the operation writes or deletes
+    pub fn key(&self) -> &str {
+        match self {
+            BatchOperation::Put { key, .. } | BatchOperation::Delete { key } => key,
+        }
+    }
+
+    /// The write for a bulk request, or `None` for a delete
+    ///
+    /// A TTL takes precedence over an expiration timestamp, as in `apply`.
+    pub fn to_bulk_write(&self) -> Option<BulkWrite> {
+        let BatchOperation::Put {
+            key,
+            value,
+            expiration_ttl,
+            expiration,
+            metadata,
+            base64,
+        } = self
+        else {
+            return None;
+        };
+        Some(BulkWrite {
+            key: key.clone(),
+            value: value.clone(),
+            expiration: expiration_ttl
+                .map(Expiration::Ttl)
+                .or(expiration.map(Expiration::At)),
+            metadata: metadata.clone(),
+            base64: *base64,
+        })
+    }
+
+    /// The item for this write in a bulk write request body, or `None` for
+    /// a delete
+    ///
+    /// Values that are not UTF-8 are base64-encoded even without `base64`.
+    pub fn to_bulk_item(&self) -> Option<Value> {
+        self.to_bulk_write().map(|write| write.to_item())
+    }
 }
 
 impl BatchBuilder {
@@ -21,10 +82,27 @@ impl BatchBuilder {
     }
 
     /// Add a put operation
-    pub fn put(mut self, key: impl Into<String>, value: impl AsRef<[u8]>) -> Self {
+    pub fn put(self, key: impl Into<String>, value: impl AsRef<[u8]>) -> Self {
+        self.put_with_options(key, value, None, None, None, false)
+    }
+
+    /// Add a put operation with an expiration, metadata, or base64 encoding
+    pub fn put_with_options(
+        mut self,
+        key: impl Into<String>,
+        value: impl AsRef<[u8]>,
+        expiration_ttl: Option<u64>,
+        expiration: Option<u64>,
+        metadata: Option<Value>,
+        base64: bool,
+    ) -> Self {
         self.operations.push(BatchOperation::Put {
             key: key.into(),
             value: value.as_ref().to_vec(),
+            expiration_ttl,
+            expiration,
+            metadata,
+            base64,
         });
         self
     }
@@ -50,6 +128,24 @@ impl BatchBuilder {
     pub fn operations(&self) -> &[BatchOperation] {
         &self.operations
     }
+
+    /// Request body of the Cloudflare bulk write API for the batch's puts
+    pub fn bulk_write_body(&self) -> Value {
+        Value::Array(
+            self.operations
+                .iter()
+                .filter_map(BatchOperation::to_bulk_item)
+                .collect(),
+        )
+    }
+}
+
+impl FromIterator<BatchOperation> for BatchBuilder {
+    fn from_iter<I: IntoIterator<Item = BatchOperation>>(operations: I) -> Self {
+        Self {
+            operations: operations.into_iter().collect(),
+        }
+    }
 }
 
 impl Default for BatchBuilder {
@@ -58,12 +154,307 @@ impl Default for BatchBuilder {
     }
 }
 
+/// Operations per chunk unless set otherwise
+pub const DEFAULT_CHUNK_SIZE: usize = 100;
+
+/// Something batch operations can be applied to, such as a `KvClient` or a
+/// `PluginClient`
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait BatchTarget: Send + Sync {
+    /// Apply one operation
+    async fn apply(&self, operation: &BatchOperation) -> Result<()>;
+
+    /// Apply a run of puts, or a run of deletes, with bulk requests
+    ///
+    /// Returns `None` when the target has no bulk API; the operations are
+    /// then applied one at a time.
+    async fn apply_bulk(&self, operations: &[BatchOperation]) -> Option<Result<()>> {
+        let _ = operations;
+        None
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl BatchTarget for KvClient {
+    async fn apply(&self, operation: &BatchOperation) -> Result<()> {
+        match operation {
+            BatchOperation::Put {
+                key,
+                value,
+                expiration_ttl,
+                expiration: Some(at),
+                metadata,
+                ..
+            } if expiration_ttl.is_none() => {
+                self.put_with_expiration_at(key, value, Some(*at), metadata.clone())
+                    .await
+            }
+            BatchOperation::Put {
+                key,
+                value,
+                expiration_ttl,
+                metadata,
+                ..
+            } => {
+                self.put_with_options(key, value, *expiration_ttl, metadata.clone())
+                    .await
+            }
+            BatchOperation::Delete { key } => self.delete(key).await,
+        }
+    }
+
+    async fn apply_bulk(&self, operations: &[BatchOperation]) -> Option<Result<()>> {
+        Some(match operations.first()? {
+            BatchOperation::Put { .. } => {
+                let writes: Vec<BulkWrite> = operations
+                    .iter()
+                    .filter_map(BatchOperation::to_bulk_write)
+                    .collect();
+                self.put_bulk(&writes).await
+            }
+            BatchOperation::Delete { .. } => {
+                let keys: Vec<&str> = operations.iter().map(BatchOperation::key).collect();
+                self.delete_keys(&keys).await
+            }
+        })
+    }
+}
+
+/// How a batch is executed
+#[derive(Clone, Debug)]
+pub struct BatchOptions {
+    pub chunk_size: usize,
+    /// Run the remaining chunks after a chunk with a failure
+    pub continue_on_error: bool,
+    /// Stop before the next chunk once this is cancelled
+    pub cancellation: Option<CancellationToken>,
+}
+
+impl BatchOptions {
+    /// Chunks of `DEFAULT_CHUNK_SIZE`, stopping after the first failing chunk
+    pub fn new() -> Self {
+        Self {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            continue_on_error: false,
+            cancellation: None,
+        }
+    }
+
+    /// Set the number of operations per chunk
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Set whether to keep going after a chunk with a failure
+    pub fn with_continue_on_error(mut self, continue_on_error: bool) -> Self {
+        self.continue_on_error = continue_on_error;
+        self
+    }
+
+    /// Stop before the next chunk once `token` is cancelled
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+    }
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An operation that failed, with its error
+#[derive(Debug)]
+pub struct FailedOperation {
+    pub operation: BatchOperation,
+    pub error: KvError,
+}
+
+/// Outcome of one chunk of a batch
+#[derive(Debug, Default)]
+pub struct ChunkReport {
+    /// Position of the chunk in the batch, from 0
+    pub index: usize,
+    /// Keys of the operations that succeeded
+    pub succeeded: Vec<String>,
+    pub failed: Vec<FailedOperation>,
+}
+
+/// Outcome of executing a batch
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    /// Chunks that ran, in order
+    pub chunks: Vec<ChunkReport>,
+    /// Operations not run because execution stopped at a failing chunk or
+    /// was cancelled
+    pub skipped: Vec<BatchOperation>,
+    /// Whether execution stopped because it was cancelled
+    pub cancelled: bool,
+}
+
+impl BatchReport {
+    /// Number of operations that succeeded
+    pub fn succeeded(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.succeeded.len()).sum()
+    }
+
+    /// Operations that failed, in batch order
+    pub fn failed(&self) -> impl Iterator<Item = &FailedOperation> {
+        self.chunks.iter().flat_map(|chunk| chunk.failed.iter())
+    }
+
+    /// Whether every operation ran and succeeded
+    pub fn is_success(&self) -> bool {
+        self.failed().next().is_none() && self.skipped.is_empty()
+    }
+
+    /// Batch of the failed and skipped operations, to run again
+    pub fn retry_batch(&self) -> BatchBuilder {
+        BatchBuilder {
+            operations: self
+                .failed()
+                .map(|failed| failed.operation.clone())
+                .chain(self.skipped.iter().cloned())
+                .collect(),
+        }
+    }
+}
+
+impl BatchBuilder {
+    /// Apply the operations to `target` chunk by chunk, calling `on_chunk`
+    /// after each
+    ///
+    /// Operations in a chunk run in order and each one's failure is recorded
+    /// without stopping the chunk. Consecutive puts, or deletes, of distinct
+    /// keys are sent together through `BatchTarget::apply_bulk`; when such a
+    /// request fails, each of its operations is recorded as failed. Unless
+    /// `continue_on_error` is set, the chunks after the first one with a
+    /// failure are skipped. Once the options' cancellation token is
+    /// cancelled, the chunk running finishes and the rest are skipped.
+    pub async fn execute(
+        &self,
+        target: &dyn BatchTarget,
+        options: &BatchOptions,
+        mut on_chunk: impl FnMut(&ChunkReport),
+    ) -> BatchReport {
+        let mut report = BatchReport::default();
+        let mut chunks = self
+            .operations
+            .chunks(options.chunk_size.max(1))
+            .enumerate();
+
+        for (index, operations) in chunks.by_ref() {
+            if options.is_cancelled() {
+                report.cancelled = true;
+                report.skipped.extend(operations.iter().cloned());
+                break;
+            }
+            let mut chunk = ChunkReport {
+                index,
+                ..Default::default()
+            };
+            for run in bulk_runs(operations) {
+                let bulk = match run.len() {
+                    1 => None,
+                    _ => target.apply_bulk(run).await,
+                };
+                match bulk {
+                    Some(Ok(())) => chunk
+                        .succeeded
+                        .extend(run.iter().map(|operation| operation.key().to_string())),
+                    Some(Err(error)) => {
+                        for operation in run {
+                            chunk.failed.push(FailedOperation {
+                                operation: operation.clone(),
+                                error: copy_error(&error),
+                            });
+                        }
+                    }
+                    None => {
+                        for operation in run {
+                            match target.apply(operation).await {
+                                Ok(()) => chunk.succeeded.push(operation.key().to_string()),
+                                Err(error) => chunk.failed.push(FailedOperation {
+                                    operation: operation.clone(),
+                                    error,
+                                }),
+                            }
+                        }
+                    }
+                }
+            }
+            on_chunk(&chunk);
+            let failed = !chunk.failed.is_empty();
+            report.chunks.push(chunk);
+            if failed && !options.continue_on_error {
+                break;
+            }
+        }
+
+        report
+            .skipped
+            .extend(chunks.flat_map(|(_, operations)| operations.iter().cloned()));
+        report
+    }
+}
+
+/// Split operations into runs that can share a bulk request: consecutive
+/// puts or deletes, without a key twice, so their order cannot matter
+fn bulk_runs(operations: &[BatchOperation]) -> Vec<&[BatchOperation]> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    let mut keys = HashSet::new();
+    for (i, operation) in operations.iter().enumerate() {
+        let same_kind =
+            std::mem::discriminant(operation) == std::mem::discriminant(&operations[start]);
+        if !same_kind || !keys.insert(operation.key()) {
+            runs.push(&operations[start..i]);
+            start = i;
+            keys.clear();
+            keys.insert(operation.key());
+        }
+    }
+    if start < operations.len() {
+        runs.push(&operations[start..]);
+    }
+    runs
+}
+
+/// The error of a failed bulk request, for each operation it carried
+///
+/// Errors that cannot be copied keep their message.
+fn copy_error(error: &KvError) -> KvError {
+    match error {
+        KvError::Http(response) => KvError::Http(response.clone()),
+        KvError::NetworkError(message) => KvError::NetworkError(message.clone()),
+        KvError::InvalidConfig(message) => KvError::InvalidConfig(message.clone()),
+        KvError::AuthError(message) => KvError::AuthError(message.clone()),
+        KvError::RateLimited(message) => KvError::RateLimited(message.clone()),
+        KvError::PluginError(message) => KvError::PluginError(message.clone()),
+        other => KvError::RequestFailed(other.to_string()),
+    }
+}
+
 /// Paginated iterator for efficient list operations
+///
+/// Every page is requested with the same client, so its pooled connection is
+/// reused instead of negotiating TLS for each page.
 pub struct PaginatedIterator {
     client: std::sync::Arc<KvClient>,
     current_cursor: Option<String>,
     limit: u32,
     exhausted: bool,
+    cancellation: Option<CancellationToken>,
 }
 
 impl PaginatedIterator {
@@ -74,14 +465,28 @@ impl PaginatedIterator {
             current_cursor: None,
             limit,
             exhausted: false,
+            cancellation: None,
         }
     }
 
+    /// End the iteration before the next page once `token` is cancelled
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
     /// Get the next page of results
     pub async fn next_page(&mut self) -> Result<Option<Vec<String>>> {
         if self.exhausted {
             return Ok(None);
         }
+        if self
+            .cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+        {
+            return Ok(None);
+        }
 
         let response = self
             .client
@@ -117,6 +522,7 @@ impl PaginatedIterator {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::json;
 
     #[test]
     fn test_batch_builder_operations() {
@@ -151,6 +557,199 @@ mod tests {
         assert_eq!(batch.len(), 100);
     }
 
+    #[test]
+    fn test_bulk_write_body() {
+        let batch = BatchBuilder::new()
+            .put("plain", "text")
+            .put_with_options(
+                "session",
+                "abc",
+                Some(3600),
+                None,
+                Some(json!({"user": 1})),
+                false,
+            )
+            .put_with_options("logo", [0xff, 0xd8], None, Some(1_900_000_000), None, false)
+            .put_with_options("encoded", "hi", None, None, None, true)
+            .delete("old");
+
+        assert_eq!(
+            batch.bulk_write_body(),
+            json!([
+                {"key": "plain", "value": "text"},
+                {"key": "session", "value": "abc", "expiration_ttl": 3600, "metadata": {"user": 1}},
+                {"key": "logo", "value": "/9g=", "base64": true, "expiration": 1_900_000_000},
+                {"key": "encoded", "value": "aGk=", "base64": true}
+            ])
+        );
+    }
+
+    /// Client whose writes to keys starting with `bad` fail
+    struct FlakyTarget(KvClient);
+
+    #[async_trait]
+    impl BatchTarget for FlakyTarget {
+        async fn apply(&self, operation: &BatchOperation) -> Result<()> {
+            if operation.key().starts_with("bad") {
+                return Err(KvError::RequestFailed(operation.key().to_string()));
+            }
+            self.0.apply(operation).await
+        }
+    }
+
+    fn flaky_batch() -> BatchBuilder {
+        ["a", "bad1", "b", "c", "bad2", "d", "e"]
+            .iter()
+            .fold(BatchBuilder::new(), |batch, key| batch.put(*key, "v"))
+    }
+
+    #[tokio::test]
+    async fn test_execute_stops_at_failing_chunk() {
+        let target = FlakyTarget(KvClient::in_memory());
+        let options = BatchOptions::new().with_chunk_size(3);
+        let mut seen = Vec::new();
+        let report = flaky_batch()
+            .execute(&target, &options, |chunk| seen.push(chunk.index))
+            .await;
+
+        assert_eq!(seen, vec![0]);
+        assert_eq!(report.chunks[0].succeeded, vec!["a", "b"]);
+        assert_eq!(report.failed().count(), 1);
+        assert_eq!(report.skipped.len(), 4);
+        assert!(!report.is_success());
+
+        let retry = report.retry_batch();
+        let retry: Vec<&str> = retry.operations().iter().map(BatchOperation::key).collect();
+        assert_eq!(retry, vec!["bad1", "c", "bad2", "d", "e"]);
+        assert!(target.0.get("c").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_execute_continue_on_error() {
+        let target = FlakyTarget(KvClient::in_memory());
+        let options = BatchOptions::new()
+            .with_chunk_size(3)
+            .with_continue_on_error(true);
+        let report = flaky_batch().execute(&target, &options, |_| {}).await;
+
+        assert_eq!(report.chunks.len(), 3);
+        assert_eq!(report.succeeded(), 5);
+        assert_eq!(report.failed().count(), 2);
+        assert!(report.skipped.is_empty());
+        assert_eq!(target.0.get("e").await.unwrap().unwrap().value, "v");
+
+        let batch = BatchBuilder::new()
+            .put_with_options("ttl", "v", None, Some(4_000_000_000), None, false)
+            .delete("ttl");
+        let report = batch.execute(&target.0, &options, |_| {}).await;
+        assert!(report.is_success());
+    }
+
+    #[tokio::test]
+    async fn test_execute_cancelled() {
+        let client = KvClient::in_memory();
+        let token = CancellationToken::new();
+        let options = BatchOptions::new()
+            .with_chunk_size(2)
+            .with_cancellation(token.clone());
+        let batch = ["a", "b", "c", "d", "e"]
+            .iter()
+            .fold(BatchBuilder::new(), |batch, key| batch.put(*key, "v"));
+
+        // Cancelled while the first chunk runs: it finishes, the rest do not start
+        let report = batch.execute(&client, &options, |_| token.cancel()).await;
+        assert!(report.cancelled && !report.is_success());
+        assert_eq!(report.succeeded(), 2);
+        let skipped: Vec<&str> = report.skipped.iter().map(BatchOperation::key).collect();
+        assert_eq!(skipped, vec!["c", "d", "e"]);
+        assert_eq!(client.list_keys("").await.unwrap(), vec!["a", "b"]);
+
+        let mut pages =
+            PaginatedIterator::new(std::sync::Arc::new(client), 1).with_cancellation(token);
+        assert_eq!(pages.next_page().await.unwrap(), None);
+    }
+
+    /// In-memory store counting the requests it receives
+    #[derive(Default)]
+    struct CountingTransport {
+        store: crate::InMemoryTransport,
+        requests: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl CountingTransport {
+        fn record(&self, request: String) {
+            self.requests.lock().unwrap().push(request);
+        }
+    }
+
+    #[async_trait]
+    impl crate::KvTransport for CountingTransport {
+        async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+            self.store.get(key).await
+        }
+
+        async fn put(
+            &self,
+            key: &str,
+            value: &[u8],
+            expiration: Option<Expiration>,
+            metadata: Option<Value>,
+        ) -> Result<()> {
+            self.record(format!("put {}", key));
+            self.store.put(key, value, expiration, metadata).await
+        }
+
+        async fn delete(&self, key: &str) -> Result<()> {
+            self.record(format!("delete {}", key));
+            self.store.delete(key).await
+        }
+
+        async fn list(&self, params: crate::PaginationParams) -> Result<crate::ListResponse> {
+            self.store.list(params).await
+        }
+
+        async fn delete_bulk(&self, keys: &[&str]) -> Result<()> {
+            self.record(format!("delete_bulk {}", keys.join(",")));
+            self.store.delete_bulk(keys).await
+        }
+
+        async fn put_bulk(&self, writes: &[BulkWrite]) -> Result<()> {
+            let keys: Vec<&str> = writes.iter().map(|write| write.key.as_str()).collect();
+            self.record(format!("put_bulk {}", keys.join(",")));
+            self.store.put_bulk(writes).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_execute_with_bulk_requests() {
+        let transport = std::sync::Arc::new(CountingTransport::default());
+        let config = crate::ClientConfig::new("a", "n", crate::AuthCredentials::token(""));
+        let client = KvClient::with_transport(config, transport.clone());
+        let batch = BatchBuilder::new()
+            .put("a", "1")
+            .put_with_options("b", "2", Some(3600), None, None, false)
+            .put("a", "3")
+            .delete("c")
+            .delete("d")
+            .put("e", "5");
+
+        let options = BatchOptions::new().with_chunk_size(5);
+        let report = batch.execute(&client, &options, |_| {}).await;
+        assert!(report.is_success());
+        assert_eq!(
+            *transport.requests.lock().unwrap(),
+            vec!["put_bulk a,b", "put a", "delete_bulk c,d", "put e"]
+        );
+        assert_eq!(client.get("a").await.unwrap().unwrap().value, "3");
+        assert!(client
+            .head("b")
+            .await
+            .unwrap()
+            .unwrap()
+            .expiration
+            .is_some());
+    }
+
     #[test]
     fn test_batch_operations_access() {
         let batch = BatchBuilder::new().put("a", "1").delete("b").put("c", "3");
@@ -0,0 +1,183 @@
+//! Blocking client for code without an async runtime (feature `blocking`)
+//!
+//! Like `reqwest::blocking`, the client owns a single-threaded Tokio runtime
+//! and runs each call of the async client to completion on it, so build
+//! scripts and other synchronous tools need no runtime of their own.
+//!
+//! ```ignore
+//! use cloudflare_kv::{blocking::KvClient, AuthCredentials, ClientConfig};
+//!
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let creds = AuthCredentials::token("your-api-token");
+//!     let client = KvClient::new(ClientConfig::new("account-id", "namespace-id", creds))?;
+//!
+//!     client.put("key", "value")?;
+//!     let result = client.get("key")?;
+//!
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Calls must not be made from within an async runtime: blocking on one
+//! panics. Use the async `KvClient` there instead.
+
+use crate::error::Result;
+use crate::transport::BulkWrite;
+use crate::types::{
+    ClientConfig, KeyMetadata, KvPair, ListResponse, PaginationParams, ReadOptions, ReadValue,
+};
+use tokio::runtime::{Builder, Runtime};
+
+/// Blocking counterpart of the async `KvClient`
+pub struct KvClient {
+    inner: crate::KvClient,
+    runtime: Runtime,
+}
+
+impl KvClient {
+    /// Create a new blocking KV client
+    pub fn new(config: ClientConfig) -> Result<Self> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        let inner = {
+            // Transports may need the runtime's context while being built
+            let _guard = runtime.enter();
+            crate::KvClient::new(config)
+        };
+        Ok(Self { inner, runtime })
+    }
+
+    /// Wrap an existing async client, e.g. one with a custom transport
+    pub fn from_async(inner: crate::KvClient) -> Result<Self> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        Ok(Self { inner, runtime })
+    }
+
+    /// The async client the calls are made with
+    pub fn inner(&self) -> &crate::KvClient {
+        &self.inner
+    }
+
+    /// Get a value from KV by key
+    pub fn get(&self, key: &str) -> Result<Option<KvPair>> {
+        self.runtime.block_on(self.inner.get(key))
+    }
+
+    /// Get a value exactly as stored, without decoding it as text
+    pub fn get_bytes(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.runtime.block_on(self.inner.get_bytes(key))
+    }
+
+    /// Get a value's raw bytes with what `options` ask for
+    pub fn get_with_options(&self, key: &str, options: &ReadOptions) -> Result<Option<ReadValue>> {
+        self.runtime
+            .block_on(self.inner.get_with_options(key, options))
+    }
+
+    /// Put a value into KV
+    pub fn put(&self, key: &str, value: impl AsRef<[u8]>) -> Result<()> {
+        self.runtime.block_on(self.inner.put(key, value))
+    }
+
+    /// Put a value only if the key does not exist yet
+    pub fn put_if_absent(&self, key: &str, value: impl AsRef<[u8]>) -> Result<()> {
+        self.runtime.block_on(self.inner.put_if_absent(key, value))
+    }
+
+    /// Put a value with metadata and expiration (TTL in seconds)
+    pub fn put_with_options(
+        &self,
+        key: &str,
+        value: impl AsRef<[u8]>,
+        expiration: Option<u64>,
+        metadata: Option<serde_json::Value>,
+    ) -> Result<()> {
+        self.runtime.block_on(
+            self.inner
+                .put_with_options(key, value, expiration, metadata),
+        )
+    }
+
+    /// Get the expiration and metadata of a key without downloading its value
+    pub fn head(&self, key: &str) -> Result<Option<KeyMetadata>> {
+        self.runtime.block_on(self.inner.head(key))
+    }
+
+    /// Check whether a key exists without downloading its value
+    pub fn exists(&self, key: &str) -> Result<bool> {
+        self.runtime.block_on(self.inner.exists(key))
+    }
+
+    /// Delete a key
+    pub fn delete(&self, key: &str) -> Result<()> {
+        self.runtime.block_on(self.inner.delete(key))
+    }
+
+    /// List one page of keys
+    pub fn list(&self, params: Option<PaginationParams>) -> Result<ListResponse> {
+        self.runtime.block_on(self.inner.list(params))
+    }
+
+    /// List every key starting with `prefix`, following cursors
+    pub fn list_keys(&self, prefix: &str) -> Result<Vec<String>> {
+        self.runtime.block_on(self.inner.list_keys(prefix))
+    }
+
+    /// List every key starting with `prefix` with its expiration and metadata
+    pub fn list_all(&self, prefix: &str) -> Result<Vec<KeyMetadata>> {
+        self.runtime.block_on(self.inner.list_all(prefix))
+    }
+
+    /// Delete keys in bulk
+    pub fn delete_keys<K: AsRef<str>>(&self, keys: &[K]) -> Result<()> {
+        self.runtime.block_on(self.inner.delete_keys(keys))
+    }
+
+    /// Write values in bulk
+    pub fn put_bulk(&self, writes: &[BulkWrite]) -> Result<()> {
+        self.runtime.block_on(self.inner.put_bulk(writes))
+    }
+
+    /// Delete every key starting with `prefix`, returning how many were deleted
+    pub fn delete_prefix(&self, prefix: &str) -> Result<usize> {
+        self.runtime.block_on(self.inner.delete_prefix(prefix))
+    }
+
+    /// Copy a value with its metadata and remaining TTL to another key
+    pub fn copy(&self, from: &str, to: &str) -> Result<()> {
+        self.runtime.block_on(self.inner.copy(from, to))
+    }
+
+    /// Move a value with its metadata and remaining TTL to another key
+    pub fn rename(&self, from: &str, to: &str) -> Result<()> {
+        self.runtime.block_on(self.inner.rename(from, to))
+    }
+
+    /// Replace a key's metadata, keeping its value and remaining TTL
+    pub fn set_metadata(&self, key: &str, metadata: Option<serde_json::Value>) -> Result<()> {
+        self.runtime
+            .block_on(self.inner.set_metadata(key, metadata))
+    }
+
+    /// Check that the API token is valid
+    pub fn verify_token(&self) -> Result<()> {
+        self.runtime.block_on(self.inner.verify_token())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocking_round_trip() {
+        let client = KvClient::from_async(crate::KvClient::in_memory()).unwrap();
+        client.put("greeting", "hello").unwrap();
+        assert_eq!(client.get("greeting").unwrap().unwrap().value, "hello");
+        assert!(client.exists("greeting").unwrap());
+
+        client.rename("greeting", "salutation").unwrap();
+        assert_eq!(client.list_keys("").unwrap(), ["salutation"]);
+        client.delete("salutation").unwrap();
+        assert_eq!(client.get_bytes("salutation").unwrap(), None);
+    }
+}
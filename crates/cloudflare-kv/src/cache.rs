@@ -0,0 +1,492 @@
+//! Read cache for KV values
+//!
+//! `CachedKvClient` keeps recently read values in an in-memory LRU and,
+//! optionally, in a directory on disk so separate processes (e.g. CI steps)
+//! share them. Entries expire after a fixed TTL; writes and deletes made
+//! through the cached client drop the cached copy, but changes made elsewhere
+//! are only seen once the entry expires. An expired value the store sent an
+//! ETag with is kept and revalidated with `If-None-Match`, so a large value
+//! that has not changed is not downloaded again.
+//!
+//! Values are cached as stored, before any plugin decoding, so values written
+//! through the encryption plugin stay encrypted on disk.
+
+use crate::error::Result;
+use crate::transport::ConditionalGet;
+use crate::types::{value_hash, KvPair};
+use crate::KvClient;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use tracing::debug;
+use web_time::Instant;
+
+const DEFAULT_CAPACITY: usize = 1000;
+
+/// How long and where values are cached
+#[derive(Clone, Debug)]
+pub struct CacheOptions {
+    pub ttl: Duration,
+    /// Maximum number of values kept in memory
+    pub capacity: usize,
+    /// Directory for the on-disk cache, if any
+    pub dir: Option<PathBuf>,
+}
+
+impl CacheOptions {
+    /// Cache up to 1000 values in memory for `ttl`
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            capacity: DEFAULT_CAPACITY,
+            dir: None,
+        }
+    }
+
+    /// Set the maximum number of values kept in memory
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Also cache values as files in `dir`
+    pub fn with_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+}
+
+struct Entry {
+    value: Vec<u8>,
+    etag: Option<String>,
+    stored_at: Instant,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<String, Entry>,
+    clock: u64,
+}
+
+/// An expired cached value that can be revalidated with its ETag
+#[derive(Clone, Debug, PartialEq)]
+pub struct StaleValue {
+    pub value: Vec<u8>,
+    pub etag: String,
+}
+
+/// A cached value, its ETag, and whether it is still fresh
+type Lookup = (Vec<u8>, Option<String>, bool);
+
+/// In-memory LRU with an optional on-disk layer
+pub struct ReadCache {
+    options: CacheOptions,
+    /// Separates entries of different namespaces in a shared directory
+    scope: String,
+    memory: Mutex<Lru>,
+}
+
+impl ReadCache {
+    /// Create a cache for values of the namespace identified by `scope`
+    pub fn new(options: CacheOptions, scope: impl Into<String>) -> Self {
+        Self {
+            options,
+            scope: scope.into(),
+            memory: Mutex::new(Lru::default()),
+        }
+    }
+
+    /// Create a cache for values of the namespace `client` is configured for
+    /// (and the key prefix it is scoped to, if any)
+    pub fn for_client(options: CacheOptions, client: &KvClient) -> Self {
+        let config = client.config();
+        let mut scope = format!("{}/{}", config.account_id, config.namespace_id);
+        if let Some(prefix) = &config.key_prefix {
+            scope = format!("{}/{}", scope, prefix);
+        }
+        Self::new(options, scope)
+    }
+
+    /// Cached value of `key`, if present and not expired
+    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
+        match self.lookup(key)? {
+            (value, _, true) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Expired value of `key` kept for revalidation with its ETag
+    pub fn stale(&self, key: &str) -> Option<StaleValue> {
+        match self.lookup(key)? {
+            (value, Some(etag), false) => Some(StaleValue { value, etag }),
+            _ => None,
+        }
+    }
+
+    /// Cache `value` for `key`
+    pub fn insert(&self, key: &str, value: &[u8]) {
+        self.insert_tagged(key, value, None);
+    }
+
+    /// Cache `value` for `key` with the ETag the store sent with it, kept
+    /// past the TTL for revalidation
+    pub fn insert_tagged(&self, key: &str, value: &[u8], etag: Option<&str>) {
+        self.remember(key, value.to_vec(), etag.map(str::to_string));
+        if let Some(path) = self.file_path(key) {
+            let etag_path = etag_path(&path);
+            let written = write_private(&path, value).and_then(|_| match etag {
+                Some(etag) => write_private(&etag_path, etag.as_bytes()),
+                None => match fs::remove_file(&etag_path) {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+                    _ => Ok(()),
+                },
+            });
+            if let Err(e) = written {
+                debug!("Could not write cache file {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// Get the raw value of `key` from the cache when fresh, otherwise from
+    /// `client`, caching what it returns
+    ///
+    /// An expired value with an ETag is revalidated rather than downloaded
+    /// again when it has not changed.
+    pub async fn fetch(&self, client: &KvClient, key: &str) -> Result<Option<Vec<u8>>> {
+        if let Some(value) = self.get(key) {
+            debug!("Cache hit: {}", key);
+            return Ok(Some(value));
+        }
+
+        let stale = self.stale(key);
+        let etag = stale.as_ref().map(|stale| stale.etag.as_str());
+        match client.get_if_none_match(key, etag).await? {
+            ConditionalGet::Modified { value, etag } => {
+                self.insert_tagged(key, &value, etag.as_deref());
+                Ok(Some(value))
+            }
+            ConditionalGet::NotModified => match stale {
+                Some(stale) => {
+                    debug!("Cache revalidated: {}", key);
+                    self.insert_tagged(key, &stale.value, Some(&stale.etag));
+                    Ok(Some(stale.value))
+                }
+                // Only sent for an ETag given, which comes from a stale value
+                None => Ok(self.client_value(client, key).await?),
+            },
+            ConditionalGet::NotFound => {
+                self.invalidate(key);
+                Ok(None)
+            }
+        }
+    }
+
+    async fn client_value(&self, client: &KvClient, key: &str) -> Result<Option<Vec<u8>>> {
+        let value = client.get_bytes(key).await?;
+        if let Some(value) = &value {
+            self.insert(key, value);
+        }
+        Ok(value)
+    }
+
+    /// Drop the cached value of `key`
+    pub fn invalidate(&self, key: &str) {
+        self.memory().entries.remove(key);
+        if let Some(path) = self.file_path(key) {
+            let _ = fs::remove_file(etag_path(&path));
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    /// Drop every cached value, including all files in the cache directory
+    pub fn clear(&self) -> Result<()> {
+        self.memory().entries.clear();
+        match &self.options.dir {
+            Some(dir) => clear_dir(dir),
+            None => Ok(()),
+        }
+    }
+
+    fn memory(&self) -> std::sync::MutexGuard<'_, Lru> {
+        self.memory.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The cached value of `key`, with its ETag and whether it is fresh;
+    /// expired values without an ETag are dropped
+    fn lookup(&self, key: &str) -> Option<Lookup> {
+        {
+            let mut memory = self.memory();
+            memory.clock += 1;
+            let clock = memory.clock;
+            if let Some(entry) = memory.entries.get_mut(key) {
+                let fresh = entry.stored_at.elapsed() < self.options.ttl;
+                if fresh || entry.etag.is_some() {
+                    entry.last_used = clock;
+                    return Some((entry.value.clone(), entry.etag.clone(), fresh));
+                }
+                memory.entries.remove(key);
+            }
+        }
+
+        let (value, etag, fresh) = self.read_file(key)?;
+        if fresh {
+            self.remember(key, value.clone(), etag.clone());
+        }
+        Some((value, etag, fresh))
+    }
+
+    fn remember(&self, key: &str, value: Vec<u8>, etag: Option<String>) {
+        let mut memory = self.memory();
+        memory.clock += 1;
+        let clock = memory.clock;
+
+        if !memory.entries.contains_key(key) && memory.entries.len() >= self.options.capacity {
+            let oldest = memory
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                memory.entries.remove(&oldest);
+            }
+        }
+
+        memory.entries.insert(
+            key.to_string(),
+            Entry {
+                value,
+                etag,
+                stored_at: Instant::now(),
+                last_used: clock,
+            },
+        );
+    }
+
+    fn file_path(&self, key: &str) -> Option<PathBuf> {
+        let dir = self.options.dir.as_ref()?;
+        let name = value_hash(format!("{}/{}", self.scope, key).as_bytes());
+        Some(dir.join(name))
+    }
+
+    fn read_file(&self, key: &str) -> Option<Lookup> {
+        let path = self.file_path(key)?;
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default();
+        let fresh = age < self.options.ttl;
+        let etag = fs::read_to_string(etag_path(&path)).ok();
+        if !fresh && etag.is_none() {
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+        Some((fs::read(&path).ok()?, etag, fresh))
+    }
+}
+
+/// Remove every file in a cache directory; a missing directory is already clear
+pub fn clear_dir(dir: &Path) -> Result<()> {
+    match fs::remove_dir_all(dir) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// File holding the ETag of the value cached in `path`
+fn etag_path(path: &Path) -> PathBuf {
+    path.with_extension("etag")
+}
+
+/// Write a cache file readable only by the current user
+fn write_private(path: &Path, value: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    #[cfg(unix)]
+    {
+        use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+        fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)?
+            .write_all(value)
+    }
+
+    #[cfg(not(unix))]
+    {
+        fs::write(path, value)
+    }
+}
+
+/// KV client that serves repeated reads from a `ReadCache`
+pub struct CachedKvClient {
+    client: KvClient,
+    cache: ReadCache,
+}
+
+impl CachedKvClient {
+    /// Wrap a client; entries are scoped to its account and namespace
+    pub fn new(client: KvClient, options: CacheOptions) -> Self {
+        Self {
+            cache: ReadCache::for_client(options, &client),
+            client,
+        }
+    }
+
+    /// Get the underlying client
+    pub fn client(&self) -> &KvClient {
+        &self.client
+    }
+
+    /// Get the cache
+    pub fn cache(&self) -> &ReadCache {
+        &self.cache
+    }
+
+    /// Get a value's raw bytes, from the cache when fresh
+    pub async fn get_bytes(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.cache.fetch(&self.client, key).await
+    }
+
+    /// Get a value, from the cache when fresh
+    pub async fn get(&self, key: &str) -> Result<Option<KvPair>> {
+        Ok(self.get_bytes(key).await?.map(|value| KvPair {
+            key: key.to_string(),
+            value: String::from_utf8_lossy(&value).into_owned(),
+            metadata: None,
+            expiration: None,
+        }))
+    }
+
+    /// Put a value and drop its cached copy
+    pub async fn put(&self, key: &str, value: impl AsRef<[u8]>) -> Result<()> {
+        self.cache.invalidate(key);
+        self.client.put(key, value).await
+    }
+
+    /// Delete a key and drop its cached copy
+    pub async fn delete(&self, key: &str) -> Result<()> {
+        self.cache.invalidate(key);
+        self.client.delete(key).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cfkv-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_memory_lru_eviction() {
+        let cache = ReadCache::new(
+            CacheOptions::new(Duration::from_secs(60)).with_capacity(2),
+            "a/n",
+        );
+        cache.insert("a", b"1");
+        cache.insert("b", b"2");
+        // Touch "a" so "b" is the least recently used
+        assert_eq!(cache.get("a"), Some(b"1".to_vec()));
+        cache.insert("c", b"3");
+
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(b"1".to_vec()));
+        assert_eq!(cache.get("c"), Some(b"3".to_vec()));
+
+        cache.invalidate("a");
+        assert_eq!(cache.get("a"), None);
+    }
+
+    #[test]
+    fn test_ttl_expiry() {
+        let cache = ReadCache::new(CacheOptions::new(Duration::ZERO), "a/n");
+        cache.insert("a", b"1");
+        assert_eq!(cache.get("a"), None);
+    }
+
+    #[test]
+    fn test_disk_cache_shared_and_scoped() {
+        let dir = temp_dir("disk");
+        let options = CacheOptions::new(Duration::from_secs(60)).with_dir(&dir);
+
+        ReadCache::new(options.clone(), "a/n").insert("config", b"value");
+        // A new cache (another process) reads the file
+        let reader = ReadCache::new(options.clone(), "a/n");
+        assert_eq!(reader.get("config"), Some(b"value".to_vec()));
+        // Other namespaces do not see it
+        assert_eq!(
+            ReadCache::new(options.clone(), "a/other").get("config"),
+            None
+        );
+
+        reader.clear().unwrap();
+        assert!(!dir.exists());
+        assert_eq!(ReadCache::new(options, "a/n").get("config"), None);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_revalidates_expired_value() {
+        let client = KvClient::in_memory();
+        client.put("k", "v1").await.unwrap();
+        let cache = ReadCache::new(CacheOptions::new(Duration::ZERO), "a/n");
+
+        assert_eq!(
+            cache.fetch(&client, "k").await.unwrap(),
+            Some(b"v1".to_vec())
+        );
+        let stale = cache.stale("k").unwrap();
+        assert_eq!(stale.value, b"v1");
+
+        // An unchanged value is served from the cache, not downloaded again
+        let etag = format!("\"{}\"", value_hash(b"v1"));
+        cache.insert_tagged("k", b"cached", Some(&etag));
+        assert_eq!(
+            cache.fetch(&client, "k").await.unwrap(),
+            Some(b"cached".to_vec())
+        );
+
+        client.put("k", "v2").await.unwrap();
+        assert_eq!(
+            cache.fetch(&client, "k").await.unwrap(),
+            Some(b"v2".to_vec())
+        );
+
+        client.delete("k").await.unwrap();
+        assert_eq!(cache.fetch(&client, "k").await.unwrap(), None);
+        assert_eq!(cache.stale("k"), None);
+    }
+
+    #[test]
+    fn test_disk_cache_keeps_etag() {
+        let dir = temp_dir("etag");
+        let options = CacheOptions::new(Duration::ZERO).with_dir(&dir);
+
+        ReadCache::new(options.clone(), "a/n").insert_tagged("k", b"v", Some("\"e\""));
+        ReadCache::new(options.clone(), "a/n").insert("plain", b"v");
+        let reader = ReadCache::new(options, "a/n");
+        assert_eq!(reader.get("k"), None);
+        assert_eq!(
+            reader.stale("k"),
+            Some(StaleValue {
+                value: b"v".to_vec(),
+                etag: "\"e\"".to_string(),
+            })
+        );
+        assert_eq!(reader.stale("plain"), None);
+
+        reader.invalidate("k");
+        assert_eq!(reader.stale("k"), None);
+        reader.clear().unwrap();
+    }
+}
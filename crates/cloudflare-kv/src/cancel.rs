@@ -0,0 +1,42 @@
+//! Cooperative cancellation of long operations
+//!
+//! A `CancellationToken` is cancelled from elsewhere, e.g. a Ctrl-C handler,
+//! and checked by the bulk helpers between units of work:
+//! `BatchBuilder::execute` before each chunk and `PaginatedIterator` before
+//! each page. Work already in flight finishes, so callers can record how far
+//! they got (a checkpoint, a retry file) before stopping:
+//!
+//! ```ignore
+//! let token = CancellationToken::new();
+//! let options = BatchOptions::new().with_cancellation(token.clone());
+//! // ... token.cancel() from a signal handler ...
+//! let report = batch.execute(&client, &options, |_| {}).await;
+//! if report.cancelled {
+//!     // report.skipped holds the chunks that were not started
+//! }
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Flag shared by the code asking to stop and the operations checking it;
+/// clones share the same flag
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// A token that is not cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask every operation holding a clone of the token to stop
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether `cancel` was called on the token or a clone
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
@@ -1,81 +1,326 @@
 use crate::error::{KvError, Result};
-use crate::types::{ClientConfig, KeyMetadata, KvPair, ListResponse, PaginationParams};
-use reqwest::Client;
-use serde_json::json;
-use tracing::debug;
+use crate::local::LocalKvBackend;
+use crate::metrics::MetricsSnapshot;
+use crate::transport::{
+    BulkWrite, ConditionalGet, Expiration, HttpTransport, InMemoryTransport, KvTransport,
+    NAMESPACES_PER_PAGE,
+};
+use crate::types::{
+    Account, AuthCredentials, ClientConfig, CounterValue, KeyMetadata, KvPair, ListResponse,
+    Namespace, PaginationParams, ReadOptions, ReadValue, WriteCondition,
+};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use std::future::Future;
+use std::sync::Arc;
+use tracing::field::Empty;
+use tracing::{debug, debug_span, Instrument};
+use web_time::Instant;
+
+/// Shortest expiration Cloudflare accepts, in seconds from now
+const MIN_EXPIRATION_SECS: u64 = 60;
+
+/// Most keys Cloudflare accepts in one bulk delete
+pub const BULK_DELETE_LIMIT: usize = 10_000;
+
+/// Most values Cloudflare accepts in one bulk write
+pub const BULK_WRITE_LIMIT: usize = 10_000;
+
+/// Where a client sends its requests
+enum Backend {
+    /// The Cloudflare API for the client's configuration
+    Http(Arc<HttpTransport>),
+    /// The local file named in the client's configuration
+    Local(LocalKvBackend),
+    /// A transport supplied by the caller
+    Custom(Arc<dyn KvTransport>),
+    /// A wrapper around the transport of another backend; account-level
+    /// calls still go to that backend's API, if it had one
+    Wrapped {
+        keys: Arc<dyn KvTransport>,
+        http: Option<Arc<HttpTransport>>,
+    },
+}
+
+impl Backend {
+    fn for_config(config: &ClientConfig) -> Self {
+        match &config.local_file {
+            Some(path) => Backend::Local(LocalKvBackend::new(path)),
+            None => Backend::Http(Arc::new(HttpTransport::new(config.clone()))),
+        }
+    }
+
+    /// The Cloudflare API transport, under any wrappers
+    fn http(&self) -> Option<&Arc<HttpTransport>> {
+        match self {
+            Backend::Http(transport) => Some(transport),
+            Backend::Wrapped { http, .. } => http.as_ref(),
+            Backend::Local(_) | Backend::Custom(_) => None,
+        }
+    }
+}
 
 /// Cloudflare KV client for KV operations
 pub struct KvClient {
-    http_client: Client,
+    backend: Backend,
     config: ClientConfig,
 }
 
 impl KvClient {
     /// Create a new KV client
     pub fn new(config: ClientConfig) -> Self {
-        let http_client = Client::new();
         Self {
-            http_client,
+            backend: Backend::for_config(&config),
             config,
         }
     }
 
+    /// Create a client that sends every operation to `transport`
+    ///
+    /// `config` still supplies the rate limit and identifies the namespace
+    /// (e.g. for cache scoping).
+    pub fn with_transport(config: ClientConfig, transport: Arc<dyn KvTransport>) -> Self {
+        Self {
+            backend: Backend::Custom(transport),
+            config,
+        }
+    }
+
+    /// Create a client backed by a fresh `InMemoryTransport`, for tests
+    pub fn in_memory() -> Self {
+        let config = ClientConfig::new("memory", "memory", AuthCredentials::token(""));
+        Self::with_transport(config, Arc::new(InMemoryTransport::new()))
+    }
+
+    /// Get the transport requests are sent through
+    pub fn transport(&self) -> &dyn KvTransport {
+        match &self.backend {
+            Backend::Http(transport) => transport.as_ref(),
+            Backend::Local(transport) => transport,
+            Backend::Custom(transport)
+            | Backend::Wrapped {
+                keys: transport, ..
+            } => transport.as_ref(),
+        }
+    }
+
+    /// Take the transport out of the client, e.g. to wrap it in another one
+    pub fn into_transport(self) -> Arc<dyn KvTransport> {
+        match self.backend {
+            Backend::Http(transport) => transport,
+            Backend::Local(transport) => Arc::new(transport),
+            Backend::Custom(transport)
+            | Backend::Wrapped {
+                keys: transport, ..
+            } => transport,
+        }
+    }
+
+    /// Send key operations through `wrap`, given the current transport
+    ///
+    /// Unlike `with_transport`, account-level calls (`verify_token`,
+    /// `namespace`, `list_namespaces`, ...) still go to the Cloudflare API
+    /// when the client used it.
+    pub fn wrap_transport(
+        self,
+        wrap: impl FnOnce(Arc<dyn KvTransport>) -> Arc<dyn KvTransport>,
+    ) -> Self {
+        let http = self.backend.http().cloned();
+        let config = self.config.clone();
+        Self {
+            backend: Backend::Wrapped {
+                keys: wrap(self.into_transport()),
+                http,
+            },
+            config,
+        }
+    }
+
+    /// Wait for the configured rate limit, if any, before sending a request
+    async fn throttle(&self) {
+        if let Some(limiter) = &self.config.rate_limit {
+            limiter.acquire().await;
+        }
+    }
+
     /// Get a value from KV by key
     pub async fn get(&self, key: &str) -> Result<Option<KvPair>> {
-        let url = format!("{}/{}", self.config.kv_endpoint(), key);
         debug!("Getting key: {}", key);
+        Ok(self.fetch(key).await?.map(|value| KvPair {
+            key: key.to_string(),
+            value: String::from_utf8_lossy(&value).into_owned(),
+            metadata: None,
+            expiration: None,
+        }))
+    }
 
-        let response = self
-            .http_client
-            .get(&url)
-            .header("Authorization", self.config.credentials.auth_header())
-            .send()
-            .await?;
+    /// Get several values, with at most `concurrency` requests in flight
+    ///
+    /// Results are in the order of `keys`; missing keys are `None`.
+    pub async fn get_many<K: AsRef<str>>(
+        &self,
+        keys: &[K],
+        concurrency: usize,
+    ) -> Result<Vec<Option<KvPair>>> {
+        stream::iter(keys)
+            .map(|key| self.get(key.as_ref()))
+            .buffered(concurrency.max(1))
+            .try_collect()
+            .await
+    }
+
+    /// Get a value exactly as stored, without decoding it as text
+    pub async fn get_bytes(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        debug!("Getting bytes of key: {}", key);
+        self.fetch(key).await
+    }
+
+    /// Get a value's raw bytes with what `options` ask for
+    ///
+    /// The metadata comes from the list API, so a key written moments ago
+    /// may be read without it.
+    pub async fn get_with_options(
+        &self,
+        key: &str,
+        options: &ReadOptions,
+    ) -> Result<Option<ReadValue>> {
+        debug!("Getting key {} with {:?}", key, options);
+        let Some(value) = self.fetch(key).await? else {
+            return Ok(None);
+        };
+        let head = match options.metadata {
+            true => self.head(key).await?,
+            false => None,
+        };
+        Ok(Some(ReadValue {
+            value,
+            metadata: head.as_ref().and_then(|head| head.metadata.clone()),
+            expiration: head.and_then(|head| head.expiration),
+        }))
+    }
+
+    /// Get a value's raw bytes unless it still has the ETag `etag`, so an
+    /// unchanged value is not downloaded again
+    ///
+    /// Without `etag`, or when the store sends no validators, this is
+    /// `get_bytes` with the value's ETag, if any.
+    pub async fn get_if_none_match(&self, key: &str, etag: Option<&str>) -> Result<ConditionalGet> {
+        debug!("Getting key {} unless it has ETag {:?}", key, etag);
+        self.throttle().await;
+        self.traced(
+            "get",
+            Some(key),
+            self.transport().get_if_none_match(key, etag),
+            |result| match result {
+                ConditionalGet::Modified { value, .. } => Some(value.len()),
+                _ => None,
+            },
+        )
+        .await
+    }
+
+    async fn fetch(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.throttle().await;
+        self.traced("get", Some(key), self.transport().get(key), |value| {
+            value.as_ref().map(Vec::len)
+        })
+        .await
+    }
 
-        match response.status() {
-            reqwest::StatusCode::OK => {
-                let body = response.text().await?;
-                Ok(Some(KvPair {
-                    key: key.to_string(),
-                    value: body,
-                    metadata: None,
-                    expiration: None,
-                }))
+    /// Run a transport request in a `kv` span recording the operation, key
+    /// (the prefix for `list`), namespace, HTTP status, latency and bytes
+    /// transferred, and count it in the configuration's `Metrics`
+    ///
+    /// Transports only report the status of failed requests, so successful
+    /// ones record 200; failures without a response (network errors) leave
+    /// it empty.
+    async fn traced<T>(
+        &self,
+        operation: &'static str,
+        key: Option<&str>,
+        request: impl Future<Output = Result<T>>,
+        bytes: impl FnOnce(&T) -> Option<usize>,
+    ) -> Result<T> {
+        let span = debug_span!(
+            "kv",
+            operation,
+            namespace = %self.config.namespace_id,
+            key,
+            status = Empty,
+            latency_ms = Empty,
+            bytes = Empty,
+            error = Empty,
+        );
+        let started = Instant::now();
+        let result = request.instrument(span.clone()).await;
+        span.record("latency_ms", started.elapsed().as_millis() as u64);
+        let metrics = &self.config.metrics;
+        metrics.record_request(result.is_err());
+        match &result {
+            Ok(value) => {
+                span.record("status", 200u16);
+                if let Some(bytes) = bytes(value) {
+                    let bytes = bytes as u64;
+                    span.record("bytes", bytes);
+                    match operation {
+                        "put" | "put_bulk" => metrics.record_upload(bytes),
+                        _ => metrics.record_download(bytes),
+                    }
+                    if let ("get" | "put", Some(key)) = (operation, key) {
+                        metrics.record_value(key, bytes);
+                    }
+                }
             }
-            reqwest::StatusCode::NOT_FOUND => Ok(None),
-            status => {
-                let body = response.text().await?;
-                Err(KvError::RequestFailed(format!(
-                    "Failed to get key {}: {} - {}",
-                    key, status, body
-                )))
+            Err(e) => {
+                if let Some(status) = e.status() {
+                    span.record("status", status);
+                }
+                span.record("error", tracing::field::display(e));
             }
         }
+        span.in_scope(|| debug!("KV {} finished", operation));
+        result
+    }
+
+    /// Get the size in bytes of a stored value, or `None` if the key does not exist
+    pub async fn value_size(&self, key: &str) -> Result<Option<u64>> {
+        Ok(self.get_bytes(key).await?.map(|value| value.len() as u64))
     }
 
     /// Put a value into KV
     pub async fn put(&self, key: &str, value: impl AsRef<[u8]>) -> Result<()> {
-        let url = format!("{}/{}", self.config.kv_endpoint(), key);
         debug!("Putting key: {}", key);
+        self.put_request(key, value.as_ref(), None, None).await
+    }
+
+    /// Put a value only if the key does not exist yet
+    ///
+    /// Fails with `PreconditionFailed` if it does. See `WriteCondition` for
+    /// the consistency caveats.
+    pub async fn put_if_absent(&self, key: &str, value: impl AsRef<[u8]>) -> Result<()> {
+        self.check_condition(key, &WriteCondition::IfAbsent).await?;
+        self.put(key, value).await
+    }
 
-        let response = self
-            .http_client
-            .put(&url)
-            .header("Authorization", self.config.credentials.auth_header())
-            .body(value.as_ref().to_vec())
-            .send()
+    /// Put a value only if the current value's SHA-256 is `expected_hash`
+    ///
+    /// Fails with `PreconditionFailed` if the value changed and `KeyNotFound`
+    /// if the key does not exist. See `WriteCondition` for the consistency
+    /// caveats.
+    pub async fn put_if_match(
+        &self,
+        key: &str,
+        value: impl AsRef<[u8]>,
+        expected_hash: &str,
+    ) -> Result<()> {
+        self.check_condition(key, &WriteCondition::IfMatch(expected_hash.to_string()))
             .await?;
+        self.put(key, value).await
+    }
 
-        match response.status() {
-            reqwest::StatusCode::OK => Ok(()),
-            status => {
-                let body = response.text().await?;
-                Err(KvError::RequestFailed(format!(
-                    "Failed to put key {}: {} - {}",
-                    key, status, body
-                )))
-            }
-        }
+    /// Check a write condition against the value currently stored
+    pub async fn check_condition(&self, key: &str, condition: &WriteCondition) -> Result<()> {
+        let current = self.get_bytes(key).await?;
+        condition.check(key, current.as_deref())
     }
 
     /// Put a value with metadata and expiration
@@ -86,170 +331,664 @@ impl KvClient {
         expiration: Option<u64>,
         metadata: Option<serde_json::Value>,
     ) -> Result<()> {
-        let url = format!("{}/{}", self.config.kv_endpoint(), key);
         debug!("Putting key with options: {}", key);
+        let expiration = expiration.map(Expiration::Ttl);
+        self.put_request(key, value.as_ref(), expiration, metadata)
+            .await
+    }
 
-        let mut request = self
-            .http_client
-            .put(&url)
-            .header("Authorization", self.config.credentials.auth_header());
+    /// Put a value with metadata, expiring at an absolute Unix timestamp
+    pub async fn put_with_expiration_at(
+        &self,
+        key: &str,
+        value: impl AsRef<[u8]>,
+        expiration: Option<u64>,
+        metadata: Option<serde_json::Value>,
+    ) -> Result<()> {
+        debug!("Putting key with expiration at: {}", key);
+        let expiration = expiration.map(Expiration::At);
+        self.put_request(key, value.as_ref(), expiration, metadata)
+            .await
+    }
 
-        // Add optional query parameters
-        if let Some(exp) = expiration {
-            request = request.query(&[("expiration_ttl", exp.to_string())]);
-        }
+    async fn put_request(
+        &self,
+        key: &str,
+        value: &[u8],
+        expiration: Option<Expiration>,
+        metadata: Option<serde_json::Value>,
+    ) -> Result<()> {
+        self.throttle().await;
+        self.traced(
+            "put",
+            Some(key),
+            self.transport().put(key, value, expiration, metadata),
+            |_| Some(value.len()),
+        )
+        .await
+    }
 
-        if let Some(meta) = metadata {
-            request = request.header("X-Kv-Metadata", meta.to_string());
-        }
+    /// Get the expiration and metadata of a key without downloading its value
+    ///
+    /// Returns `None` if the key does not exist. The lookup goes through the
+    /// list API, which is eventually consistent: a key written in the last
+    /// minute or so may not be reported yet.
+    pub async fn head(&self, key: &str) -> Result<Option<KeyMetadata>> {
+        debug!("Looking up key: {}", key);
+        // Keys are listed in lexicographic order, so an exact match is always
+        // the first key with itself as prefix
+        let params = PaginationParams::new().with_prefix(key).with_limit(10);
+        let response = self.list(Some(params)).await?;
+        Ok(response.keys.into_iter().find(|k| k.name == key))
+    }
 
-        let response = request.body(value.as_ref().to_vec()).send().await?;
+    /// Get the metadata of a key, `None` if it does not exist or has none
+    ///
+    /// Unlike `head`, this sees metadata written moments ago.
+    pub async fn metadata(&self, key: &str) -> Result<Option<serde_json::Value>> {
+        debug!("Getting metadata of key: {}", key);
+        self.throttle().await;
+        self.traced(
+            "metadata",
+            Some(key),
+            self.transport().metadata(key),
+            |_| None,
+        )
+        .await
+    }
 
-        match response.status() {
-            reqwest::StatusCode::OK => Ok(()),
-            status => {
-                let body = response.text().await?;
-                Err(KvError::RequestFailed(format!(
-                    "Failed to put key {}: {} - {}",
-                    key, status, body
-                )))
-            }
+    /// Check whether a key exists without downloading its value
+    pub async fn exists(&self, key: &str) -> Result<bool> {
+        Ok(self.head(key).await?.is_some())
+    }
+
+    /// Apply a change to a JSON value, keeping its expiration and metadata
+    ///
+    /// Returns the updated value. Fails with `KeyNotFound` if the key does
+    /// not exist and with `SerializationError` if it does not hold JSON.
+    pub async fn modify_json<F>(&self, key: &str, modify: F) -> Result<serde_json::Value>
+    where
+        F: FnOnce(&mut serde_json::Value) -> Result<()>,
+    {
+        let pair = self
+            .get(key)
+            .await?
+            .ok_or_else(|| KvError::KeyNotFound(key.to_string()))?;
+        let info = self.head(key).await?;
+
+        let (value, bytes) = modify_json_bytes(key, pair.value.as_bytes(), modify)?;
+        self.put_preserving(key, bytes, info).await?;
+
+        debug!("Modified JSON value of key: {}", key);
+        Ok(value)
+    }
+
+    /// Copy a value with its metadata and remaining TTL to another key
+    ///
+    /// The value is copied exactly as stored, so values written through
+    /// plugins stay readable with the same plugins. An existing `to` key is
+    /// overwritten.
+    pub async fn copy(&self, from: &str, to: &str) -> Result<()> {
+        let value = self
+            .get_bytes(from)
+            .await?
+            .ok_or_else(|| KvError::KeyNotFound(from.to_string()))?;
+        let info = self.head(from).await?;
+        debug!("Copying key {} to {}", from, to);
+        self.put_preserving(to, value, info).await
+    }
+
+    /// Move a value with its metadata and remaining TTL to another key
+    ///
+    /// Like `copy`, followed by deleting `from` once the new key is written.
+    /// Renaming a key to itself does nothing.
+    pub async fn rename(&self, from: &str, to: &str) -> Result<()> {
+        if from == to {
+            return Ok(());
         }
+        self.copy(from, to).await?;
+        self.delete(from).await
     }
 
-    /// Delete a key from KV
-    pub async fn delete(&self, key: &str) -> Result<()> {
-        let url = format!("{}/{}", self.config.kv_endpoint(), key);
-        debug!("Deleting key: {}", key);
+    /// Replace a key's metadata, keeping its value and remaining TTL
+    ///
+    /// `None` removes the metadata. See `modify_metadata`.
+    pub async fn set_metadata(&self, key: &str, metadata: Option<serde_json::Value>) -> Result<()> {
+        self.modify_metadata(key, |_| Ok(metadata)).await?;
+        Ok(())
+    }
 
-        let response = self
-            .http_client
-            .delete(&url)
-            .header("Authorization", self.config.credentials.auth_header())
-            .send()
+    /// Change a key's metadata with `modify`, keeping its value and
+    /// remaining TTL, and return the metadata written
+    ///
+    /// `modify` gets the current metadata, `None` if the key has none, and
+    /// returns the new one. KV has no metadata-only write, so the value is
+    /// downloaded and written back exactly as stored. Fails with
+    /// `KeyNotFound` if the key does not exist.
+    pub async fn modify_metadata<F>(
+        &self,
+        key: &str,
+        modify: F,
+    ) -> Result<Option<serde_json::Value>>
+    where
+        F: FnOnce(Option<serde_json::Value>) -> Result<Option<serde_json::Value>>,
+    {
+        let value = self
+            .get_bytes(key)
+            .await?
+            .ok_or_else(|| KvError::KeyNotFound(key.to_string()))?;
+        let (expiration, _) = preserved_options(self.head(key).await?);
+        let metadata = modify(self.metadata(key).await?)?;
+        debug!("Setting metadata of key: {}", key);
+        self.put_with_expiration_at(key, value, expiration, metadata.clone())
             .await?;
+        Ok(metadata)
+    }
 
-        match response.status() {
-            reqwest::StatusCode::OK | reqwest::StatusCode::NOT_FOUND => Ok(()),
-            status => {
-                let body = response.text().await?;
-                Err(KvError::RequestFailed(format!(
-                    "Failed to delete key {}: {} - {}",
-                    key, status, body
-                )))
-            }
-        }
+    /// Append bytes to a value, creating the key if it does not exist
+    ///
+    /// Keeps the key's expiration and metadata. Like every read-modify-write
+    /// on KV this is not atomic: concurrent appends can overwrite each other.
+    pub async fn append(&self, key: &str, bytes: impl AsRef<[u8]>) -> Result<()> {
+        let current = self.get_bytes(key).await?;
+        let info = self.head(key).await?;
+        let value = appended(current, bytes.as_ref());
+        self.put_preserving(key, value, info).await
+    }
+
+    /// Add `delta` to a numeric value and return the result
+    ///
+    /// A missing key counts as 0. Keeps the key's expiration and metadata.
+    /// Not atomic: concurrent increments can be lost.
+    pub async fn increment(
+        &self,
+        key: &str,
+        delta: impl Into<CounterValue>,
+    ) -> Result<CounterValue> {
+        let current = self.get_bytes(key).await?;
+        let info = self.head(key).await?;
+        let value = incremented(key, current.as_deref(), delta.into())?;
+        self.put_preserving(key, value.to_string(), info).await?;
+        Ok(value)
+    }
+
+    /// Put a value, keeping the expiration and metadata the key had before
+    ///
+    /// `previous` is the key's `head`, read before the value was
+    /// changed; `None` stores the value without expiration or metadata.
+    pub async fn put_preserving(
+        &self,
+        key: &str,
+        value: impl AsRef<[u8]>,
+        previous: Option<KeyMetadata>,
+    ) -> Result<()> {
+        let (expiration, metadata) = preserved_options(previous);
+        self.put_with_expiration_at(key, value, expiration, metadata)
+            .await
+    }
+
+    /// Delete a key from KV
+    pub async fn delete(&self, key: &str) -> Result<()> {
+        debug!("Deleting key: {}", key);
+        self.throttle().await;
+        self.traced("delete", Some(key), self.transport().delete(key), |_| None)
+            .await
     }
 
     /// List all keys in the namespace with optional pagination
     pub async fn list(&self, params: Option<PaginationParams>) -> Result<ListResponse> {
-        let url = self.config.kv_list_endpoint();
         debug!("Listing keys");
+        self.throttle().await;
+        let params = params.unwrap_or_default();
+        let prefix = params.prefix.clone();
+        self.traced(
+            "list",
+            prefix.as_deref(),
+            self.transport().list(params),
+            |_| None,
+        )
+        .await
+    }
 
-        let mut request = self
-            .http_client
-            .get(&url)
-            .header("Authorization", self.config.credentials.auth_header());
+    /// Every key starting with `prefix`, following list pages to the end
+    pub async fn list_keys(&self, prefix: &str) -> Result<Vec<String>> {
+        Ok(self
+            .list_all(prefix)
+            .await?
+            .into_iter()
+            .map(|key| key.name)
+            .collect())
+    }
 
-        if let Some(params) = params {
-            if let Some(limit) = params.limit {
-                request = request.query(&[("limit", limit.to_string())]);
+    /// Every key starting with `prefix` with its expiration and metadata,
+    /// following list pages to the end
+    pub async fn list_all(&self, prefix: &str) -> Result<Vec<KeyMetadata>> {
+        let mut keys = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut params = PaginationParams::new().with_limit(1000).with_prefix(prefix);
+            if let Some(cursor) = cursor.take() {
+                params = params.with_cursor(cursor);
             }
-            if let Some(cursor) = params.cursor {
-                request = request.query(&[("cursor", cursor)]);
+            let page = self.list(Some(params)).await?;
+            keys.extend(page.keys);
+            match page.cursor {
+                Some(next) if !page.list_complete && !next.is_empty() => cursor = Some(next),
+                _ => return Ok(keys),
             }
         }
+    }
 
-        let response = request.send().await?;
-
-        match response.status() {
-            reqwest::StatusCode::OK => {
-                let body: serde_json::Value = response.json().await?;
-                let result = body
-                    .get("result")
-                    .ok_or_else(|| KvError::RequestFailed("No result in response".to_string()))?;
-
-                let keys: Vec<KeyMetadata> = result
-                    .get("keys")
-                    .and_then(|k| serde_json::from_value(k.clone()).ok())
-                    .unwrap_or_default();
-
-                let list_complete = result
-                    .get("list_complete")
-                    .and_then(|lc| lc.as_bool())
-                    .unwrap_or(false);
-
-                let cursor = result
-                    .get("cursor")
-                    .and_then(|c| c.as_str())
-                    .map(|s| s.to_string());
-
-                Ok(ListResponse {
-                    keys,
-                    list_complete,
-                    cursor,
-                })
-            }
-            status => {
-                let body = response.text().await?;
-                Err(KvError::RequestFailed(format!(
-                    "Failed to list keys: {} - {}",
-                    status, body
-                )))
-            }
+    /// Delete `keys` with bulk requests of at most `BULK_DELETE_LIMIT` keys
+    pub async fn delete_keys<K: AsRef<str>>(&self, keys: &[K]) -> Result<()> {
+        for chunk in keys.chunks(BULK_DELETE_LIMIT) {
+            self.batch_delete(chunk.iter().map(AsRef::as_ref).collect())
+                .await?;
         }
+        Ok(())
+    }
+
+    /// Write values with bulk requests of at most `BULK_WRITE_LIMIT` values
+    ///
+    /// Local files and custom transports without a bulk API write them one
+    /// by one.
+    pub async fn put_bulk(&self, writes: &[BulkWrite]) -> Result<()> {
+        for chunk in writes.chunks(BULK_WRITE_LIMIT) {
+            debug!("Bulk writing {} keys", chunk.len());
+            self.throttle().await;
+            self.traced("put_bulk", None, self.transport().put_bulk(chunk), |_| {
+                Some(chunk.iter().map(|write| write.value.len()).sum())
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Delete every key starting with `prefix` and return how many there were
+    ///
+    /// An empty prefix is rejected rather than emptying the namespace.
+    pub async fn delete_prefix(&self, prefix: &str) -> Result<usize> {
+        if prefix.is_empty() {
+            return Err(KvError::InvalidConfig(
+                "Refusing to delete by an empty prefix".to_string(),
+            ));
+        }
+        let keys = self.list_keys(prefix).await?;
+        self.delete_keys(&keys).await?;
+        Ok(keys.len())
     }
 
     /// Batch delete keys
     pub async fn batch_delete(&self, keys: Vec<&str>) -> Result<()> {
-        let url = format!("{}/bulk", self.config.kv_endpoint());
         debug!("Batch deleting {} keys", keys.len());
+        self.throttle().await;
+        self.traced(
+            "delete_bulk",
+            None,
+            self.transport().delete_bulk(&keys),
+            |_| None,
+        )
+        .await
+    }
 
-        let body = json!({
-            "keys": keys
-        });
+    /// Open a connection to the API before a bulk job, so its first
+    /// requests do not wait for the TLS handshake
+    ///
+    /// The connection is kept as `ClientConfig::connection` says, and every
+    /// request of the client, including each page of a `PaginatedIterator`,
+    /// reuses it. Local files and custom transports have nothing to warm up.
+    pub async fn warm_up(&self) -> Result<()> {
+        debug!("Warming up connection");
+        match self.backend.http() {
+            Some(transport) => {
+                self.throttle().await;
+                transport.warm_up().await
+            }
+            None => Ok(()),
+        }
+    }
 
-        let response = self
-            .http_client
-            .delete(&url)
-            .header("Authorization", self.config.credentials.auth_header())
-            .json(&body)
-            .send()
-            .await?;
+    /// Check the API token is valid and active
+    ///
+    /// Local files and custom transports need no token and always pass.
+    pub async fn verify_token(&self) -> Result<()> {
+        debug!("Verifying API token");
+        match self.backend.http() {
+            Some(transport) => {
+                self.throttle().await;
+                transport.verify_token().await
+            }
+            None => Ok(()),
+        }
+    }
 
-        match response.status() {
-            reqwest::StatusCode::OK => Ok(()),
-            status => {
-                let body = response.text().await?;
-                Err(KvError::RequestFailed(format!(
-                    "Failed to batch delete: {} - {}",
-                    status, body
-                )))
+    /// Details of the client's namespace, or `None` if it does not exist
+    ///
+    /// Local files and custom transports always hold the configured namespace.
+    pub async fn namespace(&self) -> Result<Option<Namespace>> {
+        debug!("Getting namespace {}", self.config.namespace_id);
+        match self.backend.http() {
+            Some(transport) => {
+                self.throttle().await;
+                transport.namespace().await
             }
+            None => Ok(Some(self.own_namespace())),
+        }
+    }
+
+    /// One page (from 1) of the namespaces of the client's account, and
+    /// whether more follow
+    ///
+    /// Local files and custom transports hold a single namespace, the one
+    /// in the client's configuration.
+    pub async fn list_namespaces(
+        &self,
+        page: u32,
+        per_page: u32,
+    ) -> Result<(Vec<Namespace>, bool)> {
+        debug!(
+            "Listing page {} of the namespaces of account {}",
+            page, self.config.account_id
+        );
+        match self.backend.http() {
+            Some(transport) => {
+                self.throttle().await;
+                transport.list_namespaces(page, per_page.max(1)).await
+            }
+            None => {
+                let namespaces = match page {
+                    1 => vec![self.own_namespace()],
+                    _ => Vec::new(),
+                };
+                Ok((namespaces, false))
+            }
+        }
+    }
+
+    /// List every namespace of the client's account, following pages
+    pub async fn list_all_namespaces(&self) -> Result<Vec<Namespace>> {
+        let mut namespaces = Vec::new();
+        let mut page = 1;
+        loop {
+            let (batch, more) = self.list_namespaces(page, NAMESPACES_PER_PAGE).await?;
+            namespaces.extend(batch);
+            if !more {
+                return Ok(namespaces);
+            }
+            page += 1;
+        }
+    }
+
+    /// Every account the client's API token can access, following pages
+    ///
+    /// Lets setup tools offer the accounts to pick from rather than asking
+    /// for an account ID. Needs the Cloudflare API.
+    pub async fn list_accounts(&self) -> Result<Vec<Account>> {
+        debug!("Listing accounts");
+        let transport = self.account_transport("list accounts")?;
+        let mut accounts = Vec::new();
+        let mut page = 1;
+        loop {
+            self.throttle().await;
+            let (batch, more) = transport.list_accounts(page).await?;
+            accounts.extend(batch);
+            if !more {
+                return Ok(accounts);
+            }
+            page += 1;
+        }
+    }
+
+    /// Create a namespace in the client's account
+    ///
+    /// The client keeps using its configured namespace; build another client
+    /// with the returned ID to use the new one. Needs the Cloudflare API.
+    pub async fn create_namespace(&self, title: &str) -> Result<Namespace> {
+        debug!("Creating namespace {}", title);
+        let transport = self.account_transport("create namespaces")?;
+        self.throttle().await;
+        transport.create_namespace(title).await
+    }
+
+    /// Delete a namespace of the client's account with all its keys
+    ///
+    /// Needs the Cloudflare API.
+    pub async fn delete_namespace(&self, id: &str) -> Result<()> {
+        debug!("Deleting namespace {}", id);
+        let transport = self.account_transport("delete namespaces")?;
+        self.throttle().await;
+        transport.delete_namespace(id).await
+    }
+
+    /// Change the title of a namespace of the client's account
+    ///
+    /// Needs the Cloudflare API.
+    pub async fn rename_namespace(&self, id: &str, title: &str) -> Result<()> {
+        debug!("Renaming namespace {} to {}", id, title);
+        let transport = self.account_transport("rename namespaces")?;
+        self.throttle().await;
+        transport.rename_namespace(id, title).await
+    }
+
+    /// The configured namespace, as local files and custom transports
+    /// describe themselves
+    fn own_namespace(&self) -> Namespace {
+        Namespace {
+            id: self.config.namespace_id.clone(),
+            title: self.config.namespace_id.clone(),
+            supports_url_encoding: None,
+        }
+    }
+
+    /// The HTTP transport, for operations on the account rather than the
+    /// client's namespace
+    fn account_transport(&self, operation: &str) -> Result<&HttpTransport> {
+        match self.backend.http() {
+            Some(transport) => Ok(transport),
+            None => Err(KvError::InvalidConfig(format!(
+                "Cannot {} without the Cloudflare API",
+                operation
+            ))),
         }
     }
 
     /// Update client configuration
+    ///
+    /// Later requests go to the API or local file of the new configuration;
+    /// a custom or wrapped transport is kept as is.
     pub fn update_config(&mut self, config: ClientConfig) {
+        if !matches!(self.backend, Backend::Custom(_) | Backend::Wrapped { .. }) {
+            self.backend = Backend::for_config(&config);
+        }
         self.config = config;
     }
 
+    /// Requests sent and value bytes transferred so far by this client and
+    /// every other client sharing its configuration's `Metrics`
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.config.metrics.snapshot()
+    }
+
     /// Get current configuration
     pub fn config(&self) -> &ClientConfig {
         &self.config
     }
 }
 
+/// Parse a stored value as JSON, apply `modify` and serialize the result
+pub(crate) fn modify_json_bytes<F>(
+    key: &str,
+    stored: &[u8],
+    modify: F,
+) -> Result<(serde_json::Value, Vec<u8>)>
+where
+    F: FnOnce(&mut serde_json::Value) -> Result<()>,
+{
+    let mut value: serde_json::Value = serde_json::from_slice(stored).map_err(|e| {
+        KvError::SerializationError(format!("Key {} does not hold JSON: {}", key, e))
+    })?;
+    modify(&mut value)?;
+    let bytes = serde_json::to_vec(&value)?;
+    Ok((value, bytes))
+}
+
+/// The current value (if any) followed by `bytes`
+pub(crate) fn appended(current: Option<Vec<u8>>, bytes: &[u8]) -> Vec<u8> {
+    let mut value = current.unwrap_or_default();
+    value.extend_from_slice(bytes);
+    value
+}
+
+/// The current value (0 if missing) plus `delta`
+pub(crate) fn incremented(
+    key: &str,
+    current: Option<&[u8]>,
+    delta: CounterValue,
+) -> Result<CounterValue> {
+    let current = match current {
+        Some(bytes) => String::from_utf8_lossy(bytes)
+            .parse::<CounterValue>()
+            .map_err(|e| {
+                KvError::SerializationError(format!("Key {} does not hold a number: {}", key, e))
+            })?,
+        None => CounterValue::Int(0),
+    };
+    current.checked_add(delta)
+}
+
+/// Expiration and metadata to write back when rewriting an existing key
+///
+/// Cloudflare rejects expirations less than 60 seconds away, so a key about
+/// to expire is given that minimum instead.
+fn preserved_options(info: Option<KeyMetadata>) -> (Option<u64>, Option<serde_json::Value>) {
+    match info {
+        Some(info) => {
+            let earliest = web_time::SystemTime::now()
+                .duration_since(web_time::UNIX_EPOCH)
+                .map(|d| d.as_secs() + MIN_EXPIRATION_SECS)
+                .unwrap_or_default();
+            (info.expiration.map(|at| at.max(earliest)), info.metadata)
+        }
+        None => (None, None),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::types::AuthCredentials;
+    use serde_json::json;
 
     fn test_config() -> ClientConfig {
         let creds = AuthCredentials::token("test-token");
         ClientConfig::new("account-id", "namespace-id", creds)
     }
 
+    #[tokio::test]
+    async fn test_operations_are_traced() {
+        #[derive(Clone, Default)]
+        struct Output(Arc<std::sync::Mutex<Vec<u8>>>);
+        impl std::io::Write for Output {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let output = Output::default();
+        let writer = output.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let client = KvClient::in_memory();
+        client.put("greeting", "hello").await.unwrap();
+        client.get("greeting").await.unwrap();
+
+        #[cfg(feature = "reqwest")]
+        {
+            use wiremock::{Mock, MockServer, ResponseTemplate};
+
+            let server = MockServer::start().await;
+            Mock::given(wiremock::matchers::method("PUT"))
+                .respond_with(ResponseTemplate::new(403))
+                .mount(&server)
+                .await;
+            let mut config = test_config();
+            config.base_url = server.uri();
+            let client = KvClient::new(config);
+            assert!(client.put("denied", "hello").await.is_err());
+        }
+
+        let logs = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let get = logs
+            .lines()
+            .find(|l| l.contains("KV get finished"))
+            .unwrap();
+        for field in [
+            "operation=\"get\"",
+            "namespace=memory",
+            "key=\"greeting\"",
+            "status=200",
+            "latency_ms=",
+            "bytes=5",
+        ] {
+            assert!(get.contains(field), "{} missing from {}", field, get);
+        }
+        assert!(logs.contains("KV put finished"));
+        #[cfg(feature = "reqwest")]
+        assert!(logs
+            .lines()
+            .any(|l| l.contains("key=\"denied\"") && l.contains("status=403")));
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let client = KvClient::in_memory();
+        client.put("small", "hello").await.unwrap();
+        client.put("large", "x".repeat(100)).await.unwrap();
+        client.get("small").await.unwrap();
+        client.get("missing").await.unwrap();
+        client.list(None).await.unwrap();
+
+        let metrics = client.metrics();
+        assert_eq!(metrics.requests, 5);
+        assert_eq!(metrics.failed, 0);
+        assert_eq!(metrics.bytes_uploaded, 105);
+        assert_eq!(metrics.bytes_downloaded, 5);
+        let largest = metrics.largest_value.unwrap();
+        assert_eq!((largest.key.as_str(), largest.bytes), ("large", 100));
+
+        // Clients built from clones of one configuration add up
+        let other =
+            KvClient::with_transport(client.config().clone(), Arc::new(InMemoryTransport::new()));
+        other.delete("small").await.unwrap();
+        assert_eq!(client.metrics().requests, 6);
+        assert_eq!(KvClient::in_memory().metrics().requests, 0);
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_namespaces() {
+        let client = KvClient::in_memory();
+        let (first, more) = client.list_namespaces(1, 20).await.unwrap();
+        assert_eq!(first, [client.own_namespace()]);
+        assert!(!more);
+        assert!(client.list_namespaces(2, 20).await.unwrap().0.is_empty());
+        assert!(matches!(
+            client.create_namespace("sessions").await,
+            Err(KvError::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            client.list_accounts().await,
+            Err(KvError::InvalidConfig(_))
+        ));
+    }
+
     #[test]
     fn test_client_config_creation() {
         let config = test_config();
@@ -280,6 +1019,9 @@ mod tests {
 
         let params_with_cursor = params.with_cursor("token".to_string());
         assert_eq!(params_with_cursor.cursor, Some("token".to_string()));
+
+        let params_with_prefix = PaginationParams::new().with_prefix("user:");
+        assert_eq!(params_with_prefix.prefix, Some("user:".to_string()));
     }
 
     #[test]
@@ -310,6 +1052,7 @@ mod tests {
             }],
             list_complete: false,
             cursor: Some("next".to_string()),
+            result_info: None,
         };
         assert_eq!(response.keys.len(), 1);
         assert!(!response.list_complete);
@@ -335,4 +1078,211 @@ mod tests {
         let oauth_creds = AuthCredentials::oauth("my-oauth");
         assert_eq!(oauth_creds.auth_header(), "Bearer my-oauth");
     }
+
+    #[tokio::test]
+    async fn test_in_memory_client_operations() {
+        let client = KvClient::in_memory();
+        let far = 4_102_444_800; // 2100-01-01
+        client
+            .put_with_expiration_at("a", "1", Some(far), Some(json!({"owner": "me"})))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            client.increment("a", 2).await.unwrap(),
+            CounterValue::Int(3)
+        );
+        client.rename("a", "b").await.unwrap();
+        assert!(!client.exists("a").await.unwrap());
+
+        // The value moved with its expiration and metadata
+        let info = client.head("b").await.unwrap().unwrap();
+        assert_eq!(info.expiration, Some(far));
+        assert_eq!(info.metadata, Some(json!({"owner": "me"})));
+        assert_eq!(client.get("b").await.unwrap().unwrap().value, "3");
+        let read = client
+            .get_with_options("b", &ReadOptions::new().with_metadata())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((read.text(), read.expiration), ("3".to_string(), Some(far)));
+        assert_eq!(read.metadata, Some(json!({"owner": "me"})));
+        let read = client
+            .get_with_options("b", &ReadOptions::new())
+            .await
+            .unwrap();
+        assert_eq!(read.unwrap().metadata, None);
+
+        assert!(matches!(
+            client.put_if_absent("b", "x").await,
+            Err(KvError::PreconditionFailed(_))
+        ));
+        client.batch_delete(vec!["b"]).await.unwrap();
+        assert!(client.list(None).await.unwrap().keys.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_modify_metadata() {
+        let client = KvClient::in_memory();
+        let far = 4_102_444_800; // 2100-01-01
+        client
+            .put_with_expiration_at("a", "value", Some(far), Some(json!({"owner": "me"})))
+            .await
+            .unwrap();
+
+        let metadata = client
+            .modify_metadata("a", |metadata| {
+                let mut metadata = metadata.unwrap();
+                metadata["team"] = json!("payments");
+                Ok(Some(metadata))
+            })
+            .await
+            .unwrap();
+        assert_eq!(metadata, Some(json!({"owner": "me", "team": "payments"})));
+
+        // The value and expiration are kept
+        let info = client.head("a").await.unwrap().unwrap();
+        assert_eq!(info.expiration, Some(far));
+        assert_eq!(info.metadata, metadata);
+        assert_eq!(client.get("a").await.unwrap().unwrap().value, "value");
+
+        client.set_metadata("a", None).await.unwrap();
+        assert_eq!(client.head("a").await.unwrap().unwrap().metadata, None);
+        assert!(matches!(
+            client.set_metadata("missing", Some(json!({}))).await,
+            Err(KvError::KeyNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_delete_prefix() {
+        let client = KvClient::in_memory();
+        for key in ["sessions:1", "sessions:2", "users:1"] {
+            client.put(key, "x").await.unwrap();
+        }
+
+        assert_eq!(
+            client.list_keys("sessions:").await.unwrap(),
+            vec!["sessions:1", "sessions:2"]
+        );
+        assert_eq!(client.delete_prefix("sessions:").await.unwrap(), 2);
+        assert_eq!(client.list_keys("").await.unwrap(), vec!["users:1"]);
+        assert!(matches!(
+            client.delete_prefix("").await,
+            Err(KvError::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_modify_json_bytes() {
+        let (value, bytes) = modify_json_bytes("k", br#"{"a": 1}"#, |v| {
+            v["b"] = json!(true);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(value, json!({"a": 1, "b": true}));
+        assert_eq!(bytes, br#"{"a":1,"b":true}"#);
+
+        assert!(matches!(
+            modify_json_bytes("k", b"not json", |_| Ok(())),
+            Err(KvError::SerializationError(_))
+        ));
+        assert!(matches!(
+            modify_json_bytes("k", b"{}", |_| Err(KvError::PatchError("x".into()))),
+            Err(KvError::PatchError(_))
+        ));
+    }
+
+    #[test]
+    fn test_preserved_options() {
+        assert_eq!(preserved_options(None), (None, None));
+
+        let far = 4_102_444_800; // 2100-01-01
+        let info = KeyMetadata {
+            name: "k".to_string(),
+            expiration: Some(far),
+            metadata: Some(json!({"owner": "me"})),
+        };
+        assert_eq!(
+            preserved_options(Some(info)),
+            (Some(far), Some(json!({"owner": "me"})))
+        );
+
+        let expiring = KeyMetadata {
+            name: "k".to_string(),
+            expiration: Some(1),
+            metadata: None,
+        };
+        let (expiration, _) = preserved_options(Some(expiring));
+        assert!(expiration.unwrap() > 1_700_000_000);
+    }
+
+    #[test]
+    fn test_write_conditions() {
+        let hash = crate::types::value_hash(b"v1");
+        assert_eq!(hash.len(), 64);
+
+        assert!(WriteCondition::IfAbsent.check("k", None).is_ok());
+        assert!(matches!(
+            WriteCondition::IfAbsent.check("k", Some(b"v1")),
+            Err(KvError::PreconditionFailed(_))
+        ));
+
+        let if_match = WriteCondition::IfMatch(hash.to_uppercase());
+        assert!(if_match.check("k", Some(b"v1")).is_ok());
+        assert!(matches!(
+            if_match.check("k", Some(b"v2")),
+            Err(KvError::PreconditionFailed(_))
+        ));
+        assert!(matches!(
+            if_match.check("k", None),
+            Err(KvError::KeyNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_appended() {
+        assert_eq!(appended(None, b"a"), b"a");
+        assert_eq!(
+            appended(Some(b"line 1\n".to_vec()), b"line 2\n"),
+            b"line 1\nline 2\n"
+        );
+    }
+
+    #[test]
+    fn test_incremented() {
+        use CounterValue::{Float, Int};
+
+        assert_eq!(incremented("k", None, Int(1)).unwrap(), Int(1));
+        assert_eq!(incremented("k", Some(b" 41\n"), Int(1)).unwrap(), Int(42));
+        assert_eq!(incremented("k", Some(b"10"), Int(-15)).unwrap(), Int(-5));
+        assert_eq!(incremented("k", Some(b"1.5"), Int(1)).unwrap(), Float(2.5));
+        assert_eq!(
+            incremented("k", Some(b"2"), Float(0.5)).unwrap(),
+            Float(2.5)
+        );
+
+        assert!(matches!(
+            incremented("k", Some(b"abc"), Int(1)),
+            Err(KvError::SerializationError(_))
+        ));
+        assert!(incremented("k", Some(i64::MAX.to_string().as_bytes()), Int(1)).is_err());
+    }
+
+    #[test]
+    fn test_counter_value_text() {
+        assert_eq!(CounterValue::Float(3.0).to_string(), "3.0");
+        assert_eq!(CounterValue::Int(3).to_string(), "3");
+        assert_eq!(
+            "3.0".parse::<CounterValue>().unwrap(),
+            CounterValue::Float(3.0)
+        );
+        assert_eq!("-7".parse::<CounterValue>().unwrap(), CounterValue::Int(-7));
+        assert!("NaN".parse::<CounterValue>().is_err());
+        assert_eq!(
+            CounterValue::Int(2).negate().unwrap(),
+            CounterValue::Int(-2)
+        );
+        assert!(CounterValue::Int(i64::MIN).negate().is_err());
+    }
 }
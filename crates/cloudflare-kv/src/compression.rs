@@ -0,0 +1,406 @@
+//! Transparent value compression plugin
+//!
+//! Values above a size threshold are compressed with gzip or zstd and stored
+//! as the compressed bytes, with the algorithm recorded in the key's metadata
+//! (`{"cfkv": {"compression": "zstd"}}`). Reads decompress whichever
+//! algorithm the metadata names and return other values unchanged, so
+//! compressed and plain values can live side by side in one namespace.
+//!
+//! Earlier versions stored an algorithm marker followed by the base64 of the
+//! compressed bytes; such values are still decompressed on read.
+
+use crate::error::{KvError, Result};
+use crate::plugin::{KvPlugin, PluginMetadata};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_json::Value;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+/// Name the compression plugin registers under
+pub const COMPRESSION_PLUGIN_NAME: &str = "compression";
+
+/// Values smaller than this are stored uncompressed by default
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Supported compression algorithms
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompressionAlgorithm {
+    Gzip,
+    Zstd,
+}
+
+impl CompressionAlgorithm {
+    /// Name used in configuration and on the command line
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CompressionAlgorithm::Gzip => "gzip",
+            CompressionAlgorithm::Zstd => "zstd",
+        }
+    }
+
+    /// In-band marker of values written by earlier versions
+    fn legacy_marker(&self) -> &'static str {
+        match self {
+            CompressionAlgorithm::Gzip => "cfkv-gzip:v1:",
+            CompressionAlgorithm::Zstd => "cfkv-zstd:v1:",
+        }
+    }
+
+    /// Leading bytes of the algorithm's compressed output
+    fn magic(&self) -> &'static [u8] {
+        match self {
+            CompressionAlgorithm::Gzip => &[0x1f, 0x8b],
+            CompressionAlgorithm::Zstd => &[0x28, 0xb5, 0x2f, 0xfd],
+        }
+    }
+
+    fn compress(&self, value: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            CompressionAlgorithm::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(value)?;
+                encoder.finish()
+            }
+            #[cfg(feature = "native")]
+            CompressionAlgorithm::Zstd => zstd::encode_all(value, 0),
+            #[cfg(not(feature = "native"))]
+            CompressionAlgorithm::Zstd => Err(zstd_unavailable()),
+        }
+    }
+
+    fn decompress(&self, value: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            CompressionAlgorithm::Gzip => {
+                let mut output = Vec::new();
+                flate2::read::GzDecoder::new(value).read_to_end(&mut output)?;
+                Ok(output)
+            }
+            #[cfg(feature = "native")]
+            CompressionAlgorithm::Zstd => zstd::decode_all(value),
+            #[cfg(not(feature = "native"))]
+            CompressionAlgorithm::Zstd => Err(zstd_unavailable()),
+        }
+    }
+}
+
+/// zstd is a C library, so builds without `native` (e.g. wasm32) only gzip
+#[cfg(not(feature = "native"))]
+fn zstd_unavailable() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "zstd needs the `native` feature of cloudflare-kv",
+    )
+}
+
+impl FromStr for CompressionAlgorithm {
+    type Err = KvError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "gzip" | "gz" => Ok(CompressionAlgorithm::Gzip),
+            "zstd" | "zst" => Ok(CompressionAlgorithm::Zstd),
+            other => Err(KvError::InvalidConfig(format!(
+                "Unknown compression algorithm: {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// Compression plugin
+///
+/// Without an algorithm the plugin only decompresses on read, which is what
+/// every client of a mixed namespace needs.
+pub struct CompressionPlugin {
+    algorithm: Option<CompressionAlgorithm>,
+    threshold: usize,
+}
+
+impl CompressionPlugin {
+    /// Create a decompress-only plugin
+    pub fn new() -> Self {
+        Self {
+            algorithm: None,
+            threshold: DEFAULT_COMPRESSION_THRESHOLD,
+        }
+    }
+
+    /// Compress stored values with the given algorithm
+    pub fn with_algorithm(mut self, algorithm: CompressionAlgorithm) -> Self {
+        self.algorithm = Some(algorithm);
+        self
+    }
+
+    /// Only compress values of at least `threshold` bytes
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Get the algorithm a value in the earlier marker format was
+    /// compressed with, if any
+    pub fn detect_legacy(value: &[u8]) -> Option<CompressionAlgorithm> {
+        [CompressionAlgorithm::Gzip, CompressionAlgorithm::Zstd]
+            .into_iter()
+            .find(|a| value.starts_with(a.legacy_marker().as_bytes()))
+    }
+
+    /// Compress a value, returning the algorithm used, or `None` if the
+    /// value is stored as-is
+    fn compress(&self, value: &[u8]) -> Result<(Vec<u8>, Option<CompressionAlgorithm>)> {
+        let algorithm = match self.algorithm {
+            Some(algorithm) if value.len() >= self.threshold => algorithm,
+            _ => return Ok((value.to_vec(), None)),
+        };
+
+        let compressed = algorithm.compress(value)?;
+
+        // Incompressible data is cheaper to store as-is
+        if compressed.len() >= value.len() {
+            Ok((value.to_vec(), None))
+        } else {
+            Ok((compressed, Some(algorithm)))
+        }
+    }
+
+    fn decompress(algorithm: CompressionAlgorithm, value: &[u8]) -> Result<Vec<u8>> {
+        algorithm.decompress(value).map_err(|e| {
+            KvError::PluginError(format!(
+                "Failed to decompress {} value: {}",
+                algorithm.as_str(),
+                e
+            ))
+        })
+    }
+
+    /// Decompress a value in the earlier marker format
+    ///
+    /// A value that merely starts with a marker but does not decode is
+    /// returned unchanged, as it is most likely plain text.
+    fn decompress_legacy(value: &[u8]) -> Vec<u8> {
+        let decoded = Self::detect_legacy(value).and_then(|algorithm| {
+            let compressed = BASE64
+                .decode(&value[algorithm.legacy_marker().len()..])
+                .ok()?;
+            algorithm.decompress(&compressed).ok()
+        });
+        decoded.unwrap_or_else(|| value.to_vec())
+    }
+}
+
+impl Default for CompressionPlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl KvPlugin for CompressionPlugin {
+    fn metadata(&self) -> PluginMetadata {
+        PluginMetadata {
+            name: COMPRESSION_PLUGIN_NAME.to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            description: "Transparent gzip/zstd value compression".to_string(),
+            author: "cfkv".to_string(),
+        }
+    }
+
+    /// Accepts `{"algorithm": "zstd", "threshold": 1024}`, both optional
+    async fn init(&mut self, config: Value) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        if let Some(algorithm) = config.get("algorithm").and_then(|a| a.as_str()) {
+            self.algorithm = Some(algorithm.parse()?);
+        }
+        if let Some(threshold) = config.get("threshold").and_then(|t| t.as_u64()) {
+            self.threshold = threshold as usize;
+        }
+        Ok(())
+    }
+
+    /// Values are only compressed when the algorithm can be recorded, see
+    /// `pre_store_tagged`
+    async fn pre_store(
+        &self,
+        _key: &str,
+        value: &[u8],
+    ) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Ok(value.to_vec())
+    }
+
+    async fn post_retrieve(
+        &self,
+        _key: &str,
+        value: &[u8],
+    ) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Ok(Self::decompress_legacy(value))
+    }
+
+    async fn pre_store_tagged(
+        &self,
+        _key: &str,
+        value: &[u8],
+    ) -> std::result::Result<(Vec<u8>, Option<Value>), Box<dyn std::error::Error>> {
+        let (value, algorithm) = self.compress(value)?;
+        Ok((value, algorithm.map(|a| Value::from(a.as_str()))))
+    }
+
+    fn may_be_tagged(&self, value: &[u8]) -> bool {
+        [CompressionAlgorithm::Gzip, CompressionAlgorithm::Zstd]
+            .iter()
+            .any(|a| value.starts_with(a.magic()))
+    }
+
+    async fn post_retrieve_tagged(
+        &self,
+        _key: &str,
+        value: &[u8],
+        tag: Option<&Value>,
+    ) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error>> {
+        match tag.and_then(Value::as_str) {
+            Some(algorithm) => Ok(Self::decompress(algorithm.parse()?, value)?),
+            None => Ok(Self::decompress_legacy(value)),
+        }
+    }
+
+    async fn validate(
+        &self,
+        _key: &str,
+        _value: &[u8],
+    ) -> std::result::Result<bool, Box<dyn std::error::Error>> {
+        Ok(true)
+    }
+
+    fn commands(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn large_value() -> Vec<u8> {
+        "hello world ".repeat(500).into_bytes()
+    }
+
+    #[cfg(feature = "native")]
+    #[tokio::test]
+    async fn test_roundtrip_both_algorithms() {
+        for algorithm in [CompressionAlgorithm::Gzip, CompressionAlgorithm::Zstd] {
+            let plugin = CompressionPlugin::new().with_algorithm(algorithm);
+            let (stored, tag) = plugin
+                .pre_store_tagged("key", &large_value())
+                .await
+                .unwrap();
+
+            assert_eq!(tag, Some(Value::from(algorithm.as_str())));
+            assert!(plugin.may_be_tagged(&stored));
+            assert!(stored.len() < large_value().len());
+            assert_eq!(
+                plugin
+                    .post_retrieve_tagged("key", &stored, tag.as_ref())
+                    .await
+                    .unwrap(),
+                large_value()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_below_threshold_not_compressed() {
+        let plugin = CompressionPlugin::new()
+            .with_algorithm(CompressionAlgorithm::Gzip)
+            .with_threshold(10_000);
+        let (stored, tag) = plugin
+            .pre_store_tagged("key", &large_value())
+            .await
+            .unwrap();
+        assert_eq!(stored, large_value());
+        assert_eq!(tag, None);
+    }
+
+    #[tokio::test]
+    async fn test_decode_only_reads_any_algorithm() {
+        let writer = CompressionPlugin::new().with_algorithm(CompressionAlgorithm::Gzip);
+        let (stored, tag) = writer
+            .pre_store_tagged("key", &large_value())
+            .await
+            .unwrap();
+
+        let reader = CompressionPlugin::new();
+        assert_eq!(
+            reader.pre_store_tagged("key", b"plain").await.unwrap(),
+            (b"plain".to_vec(), None)
+        );
+        assert_eq!(
+            reader
+                .post_retrieve_tagged("key", &stored, tag.as_ref())
+                .await
+                .unwrap(),
+            large_value()
+        );
+        assert_eq!(
+            reader
+                .post_retrieve_tagged("key", b"plain", None)
+                .await
+                .unwrap(),
+            b"plain"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_legacy_marker_values() {
+        let compressed = CompressionAlgorithm::Gzip.compress(&large_value()).unwrap();
+        let legacy = format!("cfkv-gzip:v1:{}", BASE64.encode(compressed));
+
+        let plugin = CompressionPlugin::new();
+        assert_eq!(
+            plugin
+                .post_retrieve_tagged("key", legacy.as_bytes(), None)
+                .await
+                .unwrap(),
+            large_value()
+        );
+        // Plain text that happens to start with a marker is left alone
+        let plain = b"cfkv-gzip:v1: is the old compression marker";
+        assert_eq!(
+            plugin
+                .post_retrieve_tagged("key", plain, None)
+                .await
+                .unwrap(),
+            plain
+        );
+    }
+
+    #[tokio::test]
+    async fn test_init_from_config() {
+        let mut plugin = CompressionPlugin::new();
+        plugin
+            .init(serde_json::json!({ "algorithm": "gzip", "threshold": 0 }))
+            .await
+            .unwrap();
+        assert_eq!(plugin.algorithm, Some(CompressionAlgorithm::Gzip));
+        assert_eq!(plugin.threshold, 0);
+
+        assert!(plugin
+            .init(serde_json::json!({ "algorithm": "lz4" }))
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_algorithm_from_str() {
+        assert_eq!(
+            "zstd".parse::<CompressionAlgorithm>().unwrap(),
+            CompressionAlgorithm::Zstd
+        );
+        assert_eq!(
+            "GZIP".parse::<CompressionAlgorithm>().unwrap(),
+            CompressionAlgorithm::Gzip
+        );
+        assert!("brotli".parse::<CompressionAlgorithm>().is_err());
+    }
+}
@@ -0,0 +1,261 @@
+//! Plugins loaded from dynamic libraries
+//!
+//! Third-party plugins are shared libraries (`.so`, `.dylib`, `.dll`) that
+//! export a small C ABI, so they don't need to be built with the same Rust
+//! compiler as cfkv. All exported functions are required:
+//!
+//! ```c
+//! typedef struct { uint8_t *ptr; size_t len; size_t cap; } CfkvBuffer;
+//!
+//! uint32_t   cfkv_plugin_abi_version(void);   // must return 1
+//! CfkvBuffer cfkv_plugin_metadata(void);      // JSON: name, version, description, author, commands
+//! int32_t    cfkv_plugin_init(const uint8_t *config, size_t config_len, CfkvBuffer *err);
+//! int32_t    cfkv_plugin_pre_store(const uint8_t *key, size_t key_len,
+//!                                  const uint8_t *value, size_t value_len, CfkvBuffer *out);
+//! int32_t    cfkv_plugin_post_retrieve(const uint8_t *key, size_t key_len,
+//!                                      const uint8_t *value, size_t value_len, CfkvBuffer *out);
+//! int32_t    cfkv_plugin_validate(const uint8_t *key, size_t key_len,
+//!                                 const uint8_t *value, size_t value_len, CfkvBuffer *err);
+//! void       cfkv_plugin_free(CfkvBuffer buf);
+//! ```
+//!
+//! Functions return 0 on success; on failure they return non-zero and write a
+//! UTF-8 error message to the output buffer. `cfkv_plugin_validate` returns 1
+//! for a valid pair, 0 for an invalid one and a negative value on error.
+//! Buffers handed to cfkv are released with the plugin's own
+//! `cfkv_plugin_free`, and `config` is the plugin's JSON config.
+//!
+//! Dynamic plugins run in-process with the same privileges as cfkv; only
+//! install libraries you trust.
+
+use crate::error::{KvError, Result};
+use crate::plugin::{KvPlugin, PluginMetadata};
+use async_trait::async_trait;
+use libloading::Library;
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::Path;
+
+/// ABI version this build of cfkv understands
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+/// Byte buffer owned by a dynamic plugin
+#[repr(C)]
+pub struct CfkvBuffer {
+    pub ptr: *mut u8,
+    pub len: usize,
+    pub cap: usize,
+}
+
+impl CfkvBuffer {
+    fn empty() -> Self {
+        Self {
+            ptr: std::ptr::null_mut(),
+            len: 0,
+            cap: 0,
+        }
+    }
+}
+
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+type MetadataFn = unsafe extern "C" fn() -> CfkvBuffer;
+type InitFn = unsafe extern "C" fn(*const u8, usize, *mut CfkvBuffer) -> i32;
+type TransformFn = unsafe extern "C" fn(*const u8, usize, *const u8, usize, *mut CfkvBuffer) -> i32;
+type FreeFn = unsafe extern "C" fn(CfkvBuffer);
+
+#[derive(Deserialize)]
+struct DynamicMetadata {
+    name: String,
+    #[serde(default)]
+    version: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    author: String,
+    #[serde(default)]
+    commands: Vec<String>,
+}
+
+/// A plugin implemented by a dynamic library
+pub struct DynamicPlugin {
+    metadata: DynamicMetadata,
+    init: InitFn,
+    pre_store: TransformFn,
+    post_retrieve: TransformFn,
+    validate: TransformFn,
+    free: FreeFn,
+    // Keeps the function pointers above valid; must outlive every call
+    _library: Library,
+}
+
+impl DynamicPlugin {
+    /// Load a plugin library and read its metadata
+    pub fn load(path: &Path) -> Result<Self> {
+        // SAFETY: loading runs the library's initializers; plugins are trusted
+        // code installed explicitly by the user.
+        let library = unsafe { Library::new(path) }.map_err(|e| {
+            KvError::PluginError(format!("Failed to load {}: {}", path.display(), e))
+        })?;
+
+        unsafe {
+            let abi_version: AbiVersionFn = symbol(&library, "cfkv_plugin_abi_version")?;
+            let version = abi_version();
+            if version != PLUGIN_ABI_VERSION {
+                return Err(KvError::PluginError(format!(
+                    "{} uses plugin ABI version {}, expected {}",
+                    path.display(),
+                    version,
+                    PLUGIN_ABI_VERSION
+                )));
+            }
+
+            let metadata_fn: MetadataFn = symbol(&library, "cfkv_plugin_metadata")?;
+            let free: FreeFn = symbol(&library, "cfkv_plugin_free")?;
+            let metadata = take_buffer(free, metadata_fn());
+            let metadata: DynamicMetadata = serde_json::from_slice(&metadata).map_err(|e| {
+                KvError::PluginError(format!(
+                    "{} returned invalid metadata: {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+
+            Ok(Self {
+                metadata,
+                init: symbol(&library, "cfkv_plugin_init")?,
+                pre_store: symbol(&library, "cfkv_plugin_pre_store")?,
+                post_retrieve: symbol(&library, "cfkv_plugin_post_retrieve")?,
+                validate: symbol(&library, "cfkv_plugin_validate")?,
+                free,
+                _library: library,
+            })
+        }
+    }
+
+    fn call(&self, function: TransformFn, key: &str, value: &[u8]) -> (i32, Vec<u8>) {
+        let mut out = CfkvBuffer::empty();
+        // SAFETY: the library is loaded for the lifetime of `self` and the
+        // input slices outlive the call.
+        unsafe {
+            let status = function(
+                key.as_ptr(),
+                key.len(),
+                value.as_ptr(),
+                value.len(),
+                &mut out,
+            );
+            (status, take_buffer(self.free, out))
+        }
+    }
+
+    fn error(&self, message: Vec<u8>) -> KvError {
+        KvError::PluginError(format!(
+            "{}: {}",
+            self.metadata.name,
+            String::from_utf8_lossy(&message)
+        ))
+    }
+}
+
+/// Look up a required function exported by a plugin library
+unsafe fn symbol<T: Copy>(library: &Library, name: &str) -> Result<T> {
+    library
+        .get::<T>(name.as_bytes())
+        .map(|s| *s)
+        .map_err(|e| KvError::PluginError(format!("Plugin is missing `{}`: {}", name, e)))
+}
+
+/// Copy a plugin-owned buffer and hand it back to the plugin to free
+unsafe fn take_buffer(free: FreeFn, buffer: CfkvBuffer) -> Vec<u8> {
+    if buffer.ptr.is_null() {
+        return Vec::new();
+    }
+    let data = std::slice::from_raw_parts(buffer.ptr, buffer.len).to_vec();
+    free(buffer);
+    data
+}
+
+#[async_trait]
+impl KvPlugin for DynamicPlugin {
+    fn metadata(&self) -> PluginMetadata {
+        PluginMetadata {
+            name: self.metadata.name.clone(),
+            version: self.metadata.version.clone(),
+            description: self.metadata.description.clone(),
+            author: self.metadata.author.clone(),
+        }
+    }
+
+    async fn init(&mut self, config: Value) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let config = serde_json::to_vec(&config)?;
+        let mut err = CfkvBuffer::empty();
+        // SAFETY: see `DynamicPlugin::call`
+        let (status, message) = unsafe {
+            let status = (self.init)(config.as_ptr(), config.len(), &mut err);
+            (status, take_buffer(self.free, err))
+        };
+        if status != 0 {
+            return Err(self.error(message).into());
+        }
+        Ok(())
+    }
+
+    async fn pre_store(
+        &self,
+        key: &str,
+        value: &[u8],
+    ) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error>> {
+        match self.call(self.pre_store, key, value) {
+            (0, output) => Ok(output),
+            (_, message) => Err(self.error(message).into()),
+        }
+    }
+
+    async fn post_retrieve(
+        &self,
+        key: &str,
+        value: &[u8],
+    ) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error>> {
+        match self.call(self.post_retrieve, key, value) {
+            (0, output) => Ok(output),
+            (_, message) => Err(self.error(message).into()),
+        }
+    }
+
+    async fn validate(
+        &self,
+        key: &str,
+        value: &[u8],
+    ) -> std::result::Result<bool, Box<dyn std::error::Error>> {
+        match self.call(self.validate, key, value) {
+            (1, _) => Ok(true),
+            (0, _) => Ok(false),
+            (_, message) => Err(self.error(message).into()),
+        }
+    }
+
+    fn commands(&self) -> Vec<String> {
+        self.metadata.commands.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_missing_library() {
+        let err = DynamicPlugin::load(Path::new("/nonexistent/libcfkv_missing.so"))
+            .err()
+            .unwrap();
+        assert!(matches!(err, KvError::PluginError(_)));
+    }
+
+    #[test]
+    fn test_load_rejects_non_library() {
+        let path = std::env::temp_dir().join("cfkv-not-a-plugin.so");
+        std::fs::write(&path, b"not a shared library").unwrap();
+        assert!(DynamicPlugin::load(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,219 @@
+//! Client-side value encryption plugin
+//!
+//! Values are encrypted with AES-256-GCM before they leave the machine and
+//! stored as text: a version marker followed by base64 of nonce + ciphertext.
+//! Values without the marker are passed through unchanged on read, so
+//! namespaces holding a mix of plain and encrypted values keep working.
+
+use crate::error::{KvError, Result};
+use crate::plugin::{KvPlugin, PluginMetadata};
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde_json::Value;
+
+/// Name the encryption plugin registers under
+pub const ENCRYPTION_PLUGIN_NAME: &str = "encryption";
+
+const ENCRYPTED_PREFIX: &str = "cfkv-enc:v1:";
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+/// AES-256-GCM encryption plugin
+pub struct EncryptionPlugin {
+    cipher: Option<Aes256Gcm>,
+}
+
+impl EncryptionPlugin {
+    /// Create a plugin that must be initialized with a key via `init`
+    pub fn new() -> Self {
+        Self { cipher: None }
+    }
+
+    /// Create a plugin from a base64-encoded 256-bit key
+    pub fn with_key(key: &str) -> Result<Self> {
+        let key = BASE64
+            .decode(key.trim())
+            .map_err(|e| KvError::InvalidConfig(format!("Invalid encryption key: {}", e)))?;
+        if key.len() != KEY_LEN {
+            return Err(KvError::InvalidConfig(format!(
+                "Encryption key must be {} bytes, got {}",
+                KEY_LEN,
+                key.len()
+            )));
+        }
+        Ok(Self {
+            cipher: Some(Aes256Gcm::new_from_slice(&key).expect("key length checked")),
+        })
+    }
+
+    /// Generate a random base64-encoded 256-bit key
+    pub fn generate_key() -> String {
+        let mut key = [0u8; KEY_LEN];
+        OsRng.fill_bytes(&mut key);
+        BASE64.encode(key)
+    }
+
+    /// Check whether a stored value was produced by this plugin
+    pub fn is_encrypted(value: &[u8]) -> bool {
+        value.starts_with(ENCRYPTED_PREFIX.as_bytes())
+    }
+
+    fn cipher(&self) -> Result<&Aes256Gcm> {
+        self.cipher.as_ref().ok_or_else(|| {
+            KvError::InvalidConfig("Encryption plugin has no key configured".to_string())
+        })
+    }
+
+    fn encrypt(&self, value: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+
+        let ciphertext = self
+            .cipher()?
+            .encrypt(Nonce::from_slice(&nonce), value)
+            .map_err(|_| KvError::PluginError("Failed to encrypt value".to_string()))?;
+
+        let mut payload = nonce.to_vec();
+        payload.extend_from_slice(&ciphertext);
+        Ok(format!("{}{}", ENCRYPTED_PREFIX, BASE64.encode(payload)).into_bytes())
+    }
+
+    fn decrypt(&self, value: &[u8]) -> Result<Vec<u8>> {
+        let payload = BASE64
+            .decode(&value[ENCRYPTED_PREFIX.len()..])
+            .map_err(|e| KvError::PluginError(format!("Invalid encrypted value: {}", e)))?;
+        if payload.len() < NONCE_LEN {
+            return Err(KvError::PluginError(
+                "Encrypted value is truncated".to_string(),
+            ));
+        }
+
+        let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+        self.cipher()?
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| {
+                KvError::PluginError(
+                    "Failed to decrypt value: wrong key or corrupted data".to_string(),
+                )
+            })
+    }
+}
+
+impl Default for EncryptionPlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl KvPlugin for EncryptionPlugin {
+    fn metadata(&self) -> PluginMetadata {
+        PluginMetadata {
+            name: ENCRYPTION_PLUGIN_NAME.to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            description: "Client-side AES-256-GCM value encryption".to_string(),
+            author: "cfkv".to_string(),
+        }
+    }
+
+    /// Expects `{"key": "<base64 256-bit key>"}`
+    async fn init(&mut self, config: Value) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let key = config
+            .get("key")
+            .and_then(|k| k.as_str())
+            .ok_or("missing 'key' in encryption plugin config")?;
+        *self = Self::with_key(key)?;
+        Ok(())
+    }
+
+    async fn pre_store(
+        &self,
+        _key: &str,
+        value: &[u8],
+    ) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Ok(self.encrypt(value)?)
+    }
+
+    async fn post_retrieve(
+        &self,
+        _key: &str,
+        value: &[u8],
+    ) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error>> {
+        if Self::is_encrypted(value) {
+            Ok(self.decrypt(value)?)
+        } else {
+            Ok(value.to_vec())
+        }
+    }
+
+    async fn validate(
+        &self,
+        _key: &str,
+        _value: &[u8],
+    ) -> std::result::Result<bool, Box<dyn std::error::Error>> {
+        Ok(self.cipher.is_some())
+    }
+
+    fn commands(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_encrypt_decrypt_roundtrip() {
+        let plugin = EncryptionPlugin::with_key(&EncryptionPlugin::generate_key()).unwrap();
+        let stored = plugin.pre_store("key", b"secret value").await.unwrap();
+
+        assert!(EncryptionPlugin::is_encrypted(&stored));
+        assert!(!String::from_utf8_lossy(&stored).contains("secret"));
+        assert_eq!(
+            plugin.post_retrieve("key", &stored).await.unwrap(),
+            b"secret value"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_plain_values_pass_through() {
+        let plugin = EncryptionPlugin::with_key(&EncryptionPlugin::generate_key()).unwrap();
+        assert_eq!(
+            plugin.post_retrieve("key", b"plain").await.unwrap(),
+            b"plain"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wrong_key_fails() {
+        let plugin = EncryptionPlugin::with_key(&EncryptionPlugin::generate_key()).unwrap();
+        let other = EncryptionPlugin::with_key(&EncryptionPlugin::generate_key()).unwrap();
+        let stored = plugin.pre_store("key", b"secret").await.unwrap();
+        assert!(other.post_retrieve("key", &stored).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_init_from_config() {
+        let mut plugin = EncryptionPlugin::new();
+        assert!(!plugin.validate("key", b"v").await.unwrap());
+
+        let config = serde_json::json!({ "key": EncryptionPlugin::generate_key() });
+        plugin.init(config).await.unwrap();
+        assert!(plugin.validate("key", b"v").await.unwrap());
+
+        assert!(plugin.init(serde_json::json!({})).await.is_err());
+    }
+
+    #[test]
+    fn test_invalid_keys() {
+        assert!(EncryptionPlugin::with_key("not base64!").is_err());
+        assert!(EncryptionPlugin::with_key(&BASE64.encode([0u8; 16])).is_err());
+    }
+}
@@ -1,10 +1,17 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum KvError {
+    #[cfg(feature = "reqwest")]
     #[error("HTTP error: {0}")]
     HttpError(#[from] reqwest::Error),
 
+    #[error("Network error: {0}")]
+    NetworkError(String),
+
     #[error("JSON error: {0}")]
     JsonError(#[from] serde_json::Error),
 
@@ -20,15 +27,110 @@ pub enum KvError {
     #[error("Request failed: {0}")]
     RequestFailed(String),
 
+    /// The API answered with an unsuccessful status
+    #[error("{0}")]
+    Http(Box<ErrorResponse>),
+
+    #[error("Unexpected response: {0}")]
+    UnexpectedResponse(String),
+
+    #[error("Rate limited: {0}")]
+    RateLimited(String),
+
     #[error("Serialization error: {0}")]
     SerializationError(String),
 
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
+
+    #[error("Plugin error: {0}")]
+    PluginError(String),
+
+    #[error("Patch failed: {0}")]
+    PatchError(String),
+
+    #[error("Precondition failed: {0}")]
+    PreconditionFailed(String),
 }
 
 pub type Result<T> = std::result::Result<T, KvError>;
 
+impl KvError {
+    /// HTTP status of the response that failed, if any
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            KvError::Http(response) => Some(response.status),
+            #[cfg(feature = "reqwest")]
+            KvError::HttpError(error) => error.status().map(|status| status.as_u16()),
+            _ => None,
+        }
+    }
+
+    /// Whether the credentials were rejected (401/403)
+    pub fn is_auth_error(&self) -> bool {
+        matches!(self, KvError::AuthError(_)) || matches!(self.status(), Some(401 | 403))
+    }
+
+    /// Whether the request was throttled (429)
+    pub fn is_rate_limited(&self) -> bool {
+        matches!(self, KvError::RateLimited(_)) || self.status() == Some(429)
+    }
+
+    /// How long the API asked to wait before retrying, from `Retry-After`
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            KvError::Http(response) => response
+                .header("retry-after")?
+                .trim()
+                .parse()
+                .ok()
+                .map(Duration::from_secs),
+            _ => None,
+        }
+    }
+}
+
+/// An API response with an unsuccessful status
+#[derive(Clone, Debug, PartialEq)]
+pub struct ErrorResponse {
+    pub status: u16,
+    /// The `Retry-After`, rate limit (`RateLimit-*`, `X-RateLimit-*`) and
+    /// `CF-Ray` headers, by lowercase name
+    pub headers: BTreeMap<String, String>,
+    /// The body, usually an envelope listing the API's error codes
+    pub body: String,
+    /// What failed, with the status and body
+    pub message: String,
+}
+
+impl ErrorResponse {
+    /// Whether a response header is kept in `headers`
+    pub(crate) fn keeps_header(name: &str) -> bool {
+        let name = name.to_ascii_lowercase();
+        name == "retry-after"
+            || name == "cf-ray"
+            || name.starts_with("ratelimit")
+            || name.starts_with("x-ratelimit")
+    }
+
+    /// A kept header, by case-insensitive name
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .map(String::as_str)
+    }
+}
+
+impl fmt::Display for ErrorResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.status {
+            401 | 403 => write!(f, "Authentication failed: {}", self.message),
+            429 => write!(f, "Rate limited: {}", self.message),
+            _ => write!(f, "Request failed: {}", self.message),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -56,6 +158,14 @@ mod tests {
                 KvError::SerializationError("invalid json".to_string()),
                 "Serialization error: invalid json",
             ),
+            (
+                KvError::PatchError("path /a does not exist".to_string()),
+                "Patch failed: path /a does not exist",
+            ),
+            (
+                KvError::PreconditionFailed("key a already exists".to_string()),
+                "Precondition failed: key a already exists",
+            ),
         ];
 
         for (error, expected) in test_cases {
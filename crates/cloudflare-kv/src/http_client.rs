@@ -0,0 +1,307 @@
+//! HTTP clients the Cloudflare API requests are sent with
+//!
+//! `HttpTransport` and `R2Client` build an `ApiRequest` and hand it to an
+//! `Engine`: reqwest with the default `reqwest` feature, or a small hyper
+//! client with the `hyper` feature, for embedders that want fewer
+//! dependencies. Request hooks take a `reqwest::Request`, so they only run
+//! with reqwest. Every request is logged at debug level with its status and
+//! latency.
+
+use crate::error::{KvError, Result};
+#[cfg(feature = "reqwest")]
+use crate::types::RequestHook;
+use crate::types::{value_hash, ConnectionOptions, ResultInfo};
+use http::{HeaderMap, Method, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::Duration;
+use tracing::debug;
+use web_time::Instant;
+
+/// A request to the Cloudflare API
+#[derive(Clone)]
+pub(crate) struct ApiRequest {
+    pub method: Method,
+    pub url: String,
+    pub query: Vec<(&'static str, String)>,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+    /// Longest the request may take, including reading the response
+    pub timeout: Option<Duration>,
+    #[cfg(feature = "reqwest")]
+    pub hooks: Vec<RequestHook>,
+}
+
+impl ApiRequest {
+    pub fn new(method: Method, url: impl Into<String>, authorization: String) -> Self {
+        Self {
+            method,
+            url: url.into(),
+            query: Vec::new(),
+            headers: vec![("Authorization".to_string(), authorization)],
+            body: None,
+            timeout: None,
+            #[cfg(feature = "reqwest")]
+            hooks: Vec::new(),
+        }
+    }
+
+    pub fn query(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.query.push((name, value.into()));
+        self
+    }
+
+    pub fn header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name.to_string(), value.into()));
+        self
+    }
+
+    pub fn body(mut self, body: Vec<u8>) -> Self {
+        self.body = Some(body);
+        self
+    }
+
+    pub fn json(self, body: &Value) -> Self {
+        self.header("Content-Type", "application/json")
+            .body(body.to_string().into_bytes())
+    }
+
+    /// A `multipart/form-data` body with one field per part
+    ///
+    /// The boundary is derived from the parts' hash, so it cannot occur in
+    /// them.
+    pub fn multipart(self, parts: &[(&str, &[u8])]) -> Self {
+        let mut hashed = Vec::new();
+        for (name, content) in parts {
+            hashed.extend_from_slice(name.as_bytes());
+            hashed.extend_from_slice(content);
+        }
+        let boundary = format!("cfkv-{}", value_hash(&hashed));
+
+        let mut body = Vec::new();
+        for (name, content) in parts {
+            body.extend_from_slice(
+                format!(
+                    "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n",
+                    boundary, name
+                )
+                .as_bytes(),
+            );
+            body.extend_from_slice(content);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+        self.header(
+            "Content-Type",
+            format!("multipart/form-data; boundary={}", boundary),
+        )
+        .body(body)
+    }
+}
+
+/// A response, read in full
+pub(crate) struct ApiResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+impl ApiResponse {
+    /// Parse the body as an API envelope around a `T`
+    ///
+    /// A body that is not JSON or does not match `T` is reported as
+    /// `UnexpectedResponse` rather than read as empty.
+    pub fn envelope<T: DeserializeOwned>(&self, what: &str) -> Result<ApiEnvelope<T>> {
+        serde_json::from_slice(&self.body)
+            .map_err(|e| KvError::UnexpectedResponse(format!("Invalid {} response: {}", what, e)))
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+/// The JSON body the Cloudflare API wraps its results in
+#[derive(Debug, Deserialize)]
+pub(crate) struct ApiEnvelope<T> {
+    pub result: Option<T>,
+    #[serde(default)]
+    pub result_info: Option<ResultInfo>,
+}
+
+impl<T> ApiEnvelope<T> {
+    /// The result, which must be present
+    pub fn into_result(self, what: &str) -> Result<T> {
+        self.result
+            .ok_or_else(|| KvError::UnexpectedResponse(format!("No result in {} response", what)))
+    }
+}
+
+/// The HTTP client requests are sent with
+pub(crate) enum Engine {
+    #[cfg(feature = "reqwest")]
+    Reqwest(reqwest::Client),
+    #[cfg(feature = "hyper")]
+    Hyper(Box<HyperClient>),
+}
+
+#[cfg(feature = "hyper")]
+type HyperClient =
+    hyper::Client<hyper_tls::HttpsConnector<hyper::client::HttpConnector>, hyper::Body>;
+
+impl Engine {
+    /// reqwest when it is compiled in, hyper otherwise
+    pub fn new(options: &ConnectionOptions) -> Self {
+        #[cfg(feature = "reqwest")]
+        return Self::reqwest(options);
+        #[cfg(not(feature = "reqwest"))]
+        return Self::hyper(options);
+    }
+
+    #[cfg(feature = "reqwest")]
+    fn reqwest(options: &ConnectionOptions) -> Self {
+        let builder = reqwest::Client::builder();
+        // The browser or runtime owns the connections on wasm32
+        #[cfg(target_arch = "wasm32")]
+        let _ = options;
+        #[cfg(not(target_arch = "wasm32"))]
+        let builder = {
+            let mut builder = builder
+                .tcp_keepalive(options.tcp_keepalive)
+                .tcp_nodelay(options.tcp_nodelay)
+                .http2_keep_alive_interval(options.http2_keep_alive_interval)
+                .http2_keep_alive_while_idle(options.http2_keep_alive_interval.is_some());
+            // `None` would keep idle connections forever, not the default 90s
+            if let Some(timeout) = options.pool_idle_timeout {
+                builder = builder.pool_idle_timeout(timeout);
+            }
+            if let Some(max) = options.pool_max_idle_per_host {
+                builder = builder.pool_max_idle_per_host(max);
+            }
+            builder
+        };
+        // Like `reqwest::Client::new`, only fails if TLS cannot be initialized
+        Engine::Reqwest(
+            builder
+                .build()
+                .expect("failed to initialize the HTTP client"),
+        )
+    }
+
+    #[cfg(feature = "hyper")]
+    pub fn hyper(options: &ConnectionOptions) -> Self {
+        let mut connector = hyper::client::HttpConnector::new();
+        connector.enforce_http(false);
+        connector.set_keepalive(options.tcp_keepalive);
+        connector.set_nodelay(options.tcp_nodelay);
+
+        let mut builder = hyper::Client::builder();
+        if let Some(timeout) = options.pool_idle_timeout {
+            builder.pool_idle_timeout(timeout);
+        }
+        if let Some(max) = options.pool_max_idle_per_host {
+            builder.pool_max_idle_per_host(max);
+        }
+        let client = builder.build(hyper_tls::HttpsConnector::new_with_connector(connector));
+        Engine::Hyper(Box::new(client))
+    }
+
+    /// Send a request and read the whole response, logging its latency
+    pub async fn send(&self, request: ApiRequest) -> Result<ApiResponse> {
+        let method = request.method.clone();
+        let url = request.url.clone();
+        let started = Instant::now();
+        let result = self.execute(request).await;
+        let latency_ms = started.elapsed().as_millis() as u64;
+        match &result {
+            Ok(response) => debug!(
+                %method,
+                %url,
+                status = response.status.as_u16(),
+                latency_ms,
+                "HTTP request finished"
+            ),
+            Err(e) => debug!(%method, %url, latency_ms, error = %e, "HTTP request failed"),
+        }
+        result
+    }
+
+    async fn execute(&self, request: ApiRequest) -> Result<ApiResponse> {
+        match self {
+            #[cfg(feature = "reqwest")]
+            Engine::Reqwest(client) => {
+                let mut builder = client.request(request.method, &request.url);
+                if !request.query.is_empty() {
+                    builder = builder.query(&request.query);
+                }
+                for (name, value) in request.headers {
+                    builder = builder.header(name, value);
+                }
+                if let Some(body) = request.body {
+                    builder = builder.body(body);
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                if let Some(timeout) = request.timeout {
+                    builder = builder.timeout(timeout);
+                }
+                let mut built = builder.build()?;
+                for hook in &request.hooks {
+                    hook.apply(&mut built)?;
+                }
+                let response = client.execute(built).await?;
+                Ok(ApiResponse {
+                    status: response.status(),
+                    headers: response.headers().clone(),
+                    body: response.bytes().await?.to_vec(),
+                })
+            }
+            #[cfg(feature = "hyper")]
+            Engine::Hyper(client) => {
+                let mut url = request.url;
+                if !request.query.is_empty() {
+                    let query = form_urlencoded::Serializer::new(String::new())
+                        .extend_pairs(&request.query)
+                        .finish();
+                    url = format!("{}?{}", url, query);
+                }
+                let mut builder = hyper::Request::builder().method(request.method).uri(&url);
+                for (name, value) in request.headers {
+                    builder = builder.header(name, value);
+                }
+                let body = hyper::Body::from(request.body.unwrap_or_default());
+                let built = builder.body(body).map_err(|e| {
+                    KvError::InvalidConfig(format!("Invalid request to {}: {}", url, e))
+                })?;
+
+                let exchange = async {
+                    let response = client.request(built).await.map_err(network_error)?;
+                    let (parts, body) = response.into_parts();
+                    let body = hyper::body::to_bytes(body).await.map_err(network_error)?;
+                    Ok(ApiResponse {
+                        status: parts.status,
+                        headers: parts.headers,
+                        body: body.to_vec(),
+                    })
+                };
+                match request.timeout {
+                    Some(timeout) => {
+                        tokio::time::timeout(timeout, exchange).await.map_err(|_| {
+                            KvError::NetworkError(format!(
+                                "Request to {} timed out after {:?}",
+                                url, timeout
+                            ))
+                        })?
+                    }
+                    None => exchange.await,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "hyper")]
+fn network_error(error: hyper::Error) -> KvError {
+    KvError::NetworkError(error.to_string())
+}
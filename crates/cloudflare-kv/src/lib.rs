@@ -5,9 +5,25 @@
 //! # Features
 //!
 //! - Get, put, and delete operations
-//! - Batch operations and pagination
+//! - Batch operations, bulk writes and deletes, and pagination
 //! - Type-safe serialization with serde
 //! - API token and OAuth authentication
+//! - Client-side rate limiting shared across tasks
+//! - Request and transfer accounting per client
+//! - Request hooks for audit headers, tracing or custom signing
+//! - Read cache in memory and on disk
+//! - Pluggable transport with an in-memory store for tests
+//! - Local JSON file backend for offline use
+//! - Key prefix scoping for apps sharing a namespace
+//! - Fake Cloudflare API server for integration tests (feature `test-util`)
+//! - Namespace statistics (key counts, sizes, expirations)
+//! - Plugin chain for transforming values (client-side encryption, compression)
+//! - Third-party plugins loaded from dynamic libraries
+//! - Offloading values too large for KV to R2
+//! - Purging cached URLs of a zone after writes
+//! - Blocking client for synchronous code (feature `blocking`)
+//! - reqwest or a slimmer hyper client for HTTP (features `reqwest`, `hyper`)
+//! - Builds for `wasm32-unknown-unknown` without the default `native` feature
 //!
 //! # Example
 //!
@@ -29,14 +45,71 @@
 
 pub mod auth;
 pub mod batch;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod cache;
+pub mod cancel;
 pub mod client;
+pub mod compression;
+#[cfg(feature = "native")]
+pub mod dynamic;
+pub mod encryption;
 pub mod error;
+mod http_client;
+pub mod local;
+pub mod metrics;
+pub mod offload;
+pub mod plugin;
+pub mod r2;
+pub mod rate_limit;
+pub mod scope;
+pub mod stats;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
+pub mod transport;
 pub mod types;
+pub mod zone;
 
 pub use auth::AuthManager;
-pub use batch::{BatchBuilder, PaginatedIterator};
-pub use client::KvClient;
-pub use error::{KvError, Result};
+pub use batch::{
+    BatchBuilder, BatchOperation, BatchOptions, BatchReport, BatchTarget, ChunkReport,
+    FailedOperation, PaginatedIterator,
+};
+pub use cache::{CacheOptions, CachedKvClient, ReadCache};
+pub use cancel::CancellationToken;
+pub use client::{KvClient, BULK_DELETE_LIMIT, BULK_WRITE_LIMIT};
+pub use compression::{CompressionAlgorithm, CompressionPlugin};
+#[cfg(feature = "native")]
+pub use dynamic::DynamicPlugin;
+pub use encryption::EncryptionPlugin;
+pub use error::{ErrorResponse, KvError, Result};
+pub use local::LocalKvBackend;
+pub use metrics::{LargestValue, Metrics, MetricsSnapshot};
+pub use offload::OffloadPlugin;
+pub use plugin::{
+    keep_tags, KvPlugin, PluginClient, PluginMetadata, PluginRegistry, PLUGIN_TAGS_FIELD,
+};
+pub use r2::R2Client;
+pub use rate_limit::RateLimiter;
+pub use scope::{ScopedKvClient, ScopedTransport};
+pub use stats::{NamespaceAnalyzer, NamespaceStats, PrefixGrouping, SizeStats};
+#[cfg(any(test, feature = "test-util"))]
+pub use testing::FakeCloudflareServer;
+pub use transport::{
+    BulkWrite, ConditionalGet, Expiration, HttpTransport, InMemoryTransport, KvTransport,
+};
+#[cfg(feature = "reqwest")]
+pub use types::RequestHook;
 pub use types::{
-    AuthCredentials, ClientConfig, KeyMetadata, KvPair, ListResponse, PaginationParams,
+    value_hash, Account, AuthCredentials, ClientConfig, ConnectionOptions, CounterValue,
+    KeyMetadata, KvPair, ListResponse, Namespace, NamespaceInfo, PaginationParams, ReadOptions,
+    ReadValue, ResultInfo, RetryPolicy, WriteCondition,
 };
+pub use zone::{Zone, ZoneClient, PURGE_URL_LIMIT};
+
+/// The HTTP client crate, for naming `reqwest::Request` in request hooks
+#[cfg(feature = "reqwest")]
+pub use reqwest;
+
+#[cfg(not(any(feature = "reqwest", feature = "hyper")))]
+compile_error!("cloudflare-kv needs an HTTP client: enable the `reqwest` or `hyper` feature");
@@ -0,0 +1,269 @@
+//! File-backed local KV store
+//!
+//! `LocalKvBackend` keeps a namespace in a single JSON file so the client and
+//! the CLI can be used offline, for demos, development and tests of scripts.
+//! The file is read on first use and rewritten after every change;
+//! concurrent processes writing the same file overwrite each other's changes.
+//!
+//! Each key maps to its value (as text, or base64 for binary values) and its
+//! optional expiration and metadata:
+//!
+//! ```json
+//! {
+//!   "config:app": { "value": "{\"debug\":true}", "metadata": { "owner": "ops" } },
+//!   "asset:logo.png": { "value_base64": "iVBORw0KGgo=", "expiration": 1893456000 }
+//! }
+//! ```
+
+use crate::error::{KvError, Result};
+use crate::transport::{Expiration, InMemoryTransport, KvTransport, StoredValue};
+use crate::types::{ListResponse, PaginationParams};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use tracing::debug;
+
+/// One key as written to the file
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct FileEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    value: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    value_base64: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expiration: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    metadata: Option<Value>,
+}
+
+impl FileEntry {
+    fn from_stored(stored: StoredValue) -> Self {
+        let (value, value_base64) = match String::from_utf8(stored.value) {
+            Ok(text) => (Some(text), None),
+            Err(e) => (None, Some(BASE64.encode(e.into_bytes()))),
+        };
+        Self {
+            value,
+            value_base64,
+            expiration: stored.expiration,
+            metadata: stored.metadata,
+        }
+    }
+
+    fn into_stored(self, key: &str) -> Result<StoredValue> {
+        let value = match (self.value, self.value_base64) {
+            (_, Some(encoded)) => BASE64.decode(encoded).map_err(|e| {
+                KvError::SerializationError(format!("Invalid base64 value of {}: {}", key, e))
+            })?,
+            (Some(text), None) => text.into_bytes(),
+            (None, None) => Vec::new(),
+        };
+        Ok(StoredValue {
+            value,
+            expiration: self.expiration,
+            metadata: self.metadata,
+        })
+    }
+}
+
+/// KV namespace stored in a local JSON file
+pub struct LocalKvBackend {
+    path: PathBuf,
+    /// Loaded from the file on first use
+    memory: OnceLock<InMemoryTransport>,
+    /// Held while writing the file so concurrent saves are not reordered
+    save_lock: Mutex<()>,
+}
+
+impl LocalKvBackend {
+    /// Use the store at `path`; a missing file is an empty namespace
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            memory: OnceLock::new(),
+            save_lock: Mutex::new(()),
+        }
+    }
+
+    /// Path of the backing file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The keys in the file, read on first use
+    fn memory(&self) -> Result<&InMemoryTransport> {
+        if let Some(memory) = self.memory.get() {
+            return Ok(memory);
+        }
+
+        let entries = match fs::read_to_string(&self.path) {
+            Ok(content) => parse_entries(&content)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        debug!(
+            "Opened local store {} ({} keys)",
+            self.path.display(),
+            entries.len()
+        );
+        Ok(self
+            .memory
+            .get_or_init(|| InMemoryTransport::from_entries(entries)))
+    }
+
+    /// Write every live key to the file
+    fn save(&self) -> Result<()> {
+        let _guard = self.save_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let entries: BTreeMap<String, FileEntry> = self
+            .memory()?
+            .live_entries()
+            .into_iter()
+            .map(|(key, stored)| (key, FileEntry::from_stored(stored)))
+            .collect();
+        let content = serde_json::to_string_pretty(&entries)?;
+
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        // Write a sibling file and rename it so readers never see half a file
+        let partial = self.path.with_extension("partial");
+        write_private(&partial, content.as_bytes())?;
+        fs::rename(&partial, &self.path)?;
+        Ok(())
+    }
+}
+
+fn parse_entries(content: &str) -> Result<BTreeMap<String, StoredValue>> {
+    if content.trim().is_empty() {
+        return Ok(BTreeMap::new());
+    }
+    let entries: BTreeMap<String, FileEntry> = serde_json::from_str(content)
+        .map_err(|e| KvError::SerializationError(format!("Invalid local store: {}", e)))?;
+    entries
+        .into_iter()
+        .map(|(key, entry)| {
+            let stored = entry.into_stored(&key)?;
+            Ok((key, stored))
+        })
+        .collect()
+}
+
+/// Write a file readable only by the current user; values may be secrets
+fn write_private(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+        fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)?
+            .write_all(content)
+    }
+
+    #[cfg(not(unix))]
+    {
+        fs::write(path, content)
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl KvTransport for LocalKvBackend {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.memory()?.get(key).await
+    }
+
+    async fn put(
+        &self,
+        key: &str,
+        value: &[u8],
+        expiration: Option<Expiration>,
+        metadata: Option<Value>,
+    ) -> Result<()> {
+        self.memory()?.put(key, value, expiration, metadata).await?;
+        self.save()
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.memory()?.delete(key).await?;
+        self.save()
+    }
+
+    async fn list(&self, params: PaginationParams) -> Result<ListResponse> {
+        self.memory()?.list(params).await
+    }
+
+    async fn delete_bulk(&self, keys: &[&str]) -> Result<()> {
+        self.memory()?.delete_bulk(keys).await?;
+        self.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn temp_file(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("cfkv-local-{}-{}.json", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[tokio::test]
+    async fn test_values_survive_reopening() {
+        let path = temp_file("reopen");
+        let store = LocalKvBackend::new(&path);
+        store
+            .put("text", b"hello", None, Some(json!({"owner": "me"})))
+            .await
+            .unwrap();
+        store
+            .put("binary", &[0, 159, 146, 150], None, None)
+            .await
+            .unwrap();
+        store.put("gone", b"x", None, None).await.unwrap();
+        store.delete("gone").await.unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains("\"value\": \"hello\""));
+        assert!(content.contains("value_base64"));
+
+        let reopened = LocalKvBackend::new(&path);
+        assert_eq!(reopened.get("text").await.unwrap(), Some(b"hello".to_vec()));
+        assert_eq!(
+            reopened.get("binary").await.unwrap(),
+            Some(vec![0, 159, 146, 150])
+        );
+        assert_eq!(reopened.get("gone").await.unwrap(), None);
+        let listed = reopened.list(PaginationParams::new()).await.unwrap();
+        assert_eq!(listed.keys[1].metadata, Some(json!({"owner": "me"})));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_missing_and_invalid_files() {
+        let path = temp_file("missing");
+        let empty = LocalKvBackend::new(&path);
+        assert_eq!(empty.get("a").await.unwrap(), None);
+        assert!(!path.exists());
+
+        fs::write(&path, "not json").unwrap();
+        assert!(matches!(
+            LocalKvBackend::new(&path).get("a").await,
+            Err(KvError::SerializationError(_))
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,133 @@
+//! Request and transfer accounting
+//!
+//! Every `KvClient` counts the KV requests it sends and the value bytes it
+//! uploads and downloads, so callers can estimate API usage or spot an
+//! unexpectedly large value in a pipeline. The counters live in the
+//! client's `ClientConfig`, so every client built from clones of one
+//! configuration adds to the same totals:
+//!
+//! ```ignore
+//! let metrics = Metrics::new();
+//! let client = KvClient::new(config.with_metrics(metrics.clone()));
+//! client.put("key", "value").await?;
+//! assert_eq!(client.metrics().bytes_uploaded, 5);
+//! ```
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Counters shared by the clients using one configuration; clones share the
+/// same counters
+#[derive(Clone, Debug, Default)]
+pub struct Metrics(Arc<Counters>);
+
+#[derive(Debug, Default)]
+struct Counters {
+    requests: AtomicU64,
+    failed: AtomicU64,
+    bytes_uploaded: AtomicU64,
+    bytes_downloaded: AtomicU64,
+    largest_value: Mutex<Option<LargestValue>>,
+}
+
+/// The largest single value read or written, and its key
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct LargestValue {
+    pub key: String,
+    pub bytes: u64,
+}
+
+/// Totals of a `Metrics` at one point in time
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct MetricsSnapshot {
+    /// KV requests sent, failed ones included
+    pub requests: u64,
+    /// Requests that ended in an error
+    pub failed: u64,
+    /// Value bytes sent by puts and bulk writes
+    pub bytes_uploaded: u64,
+    /// Value bytes received by gets
+    pub bytes_downloaded: u64,
+    /// Largest value read or written by a single-key request, if any
+    pub largest_value: Option<LargestValue>,
+}
+
+impl Metrics {
+    /// Counters starting at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count one request, failed or not
+    pub fn record_request(&self, failed: bool) {
+        self.0.requests.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            self.0.failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Count `bytes` sent to the store
+    pub fn record_upload(&self, bytes: u64) {
+        self.0.bytes_uploaded.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Count `bytes` received from the store
+    pub fn record_download(&self, bytes: u64) {
+        self.0.bytes_downloaded.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Remember `key` if its value of `bytes` is the largest seen so far
+    pub fn record_value(&self, key: &str, bytes: u64) {
+        let mut largest = self.0.largest_value.lock().unwrap();
+        if largest.as_ref().is_none_or(|largest| bytes > largest.bytes) {
+            *largest = Some(LargestValue {
+                key: key.to_string(),
+                bytes,
+            });
+        }
+    }
+
+    /// The totals so far
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            requests: self.0.requests.load(Ordering::Relaxed),
+            failed: self.0.failed.load(Ordering::Relaxed),
+            bytes_uploaded: self.0.bytes_uploaded.load(Ordering::Relaxed),
+            bytes_downloaded: self.0.bytes_downloaded.load(Ordering::Relaxed),
+            largest_value: self.0.largest_value.lock().unwrap().clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clones_share_counters() {
+        let metrics = Metrics::new();
+        let clone = metrics.clone();
+        metrics.record_request(false);
+        clone.record_request(true);
+        clone.record_upload(10);
+        metrics.record_download(4);
+        metrics.record_value("small", 4);
+        clone.record_value("big", 10);
+        metrics.record_value("medium", 7);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.requests, 2);
+        assert_eq!(snapshot.failed, 1);
+        assert_eq!(snapshot.bytes_uploaded, 10);
+        assert_eq!(snapshot.bytes_downloaded, 4);
+        assert_eq!(
+            snapshot.largest_value,
+            Some(LargestValue {
+                key: "big".to_string(),
+                bytes: 10
+            })
+        );
+        assert_eq!(Metrics::new().snapshot(), MetricsSnapshot::default());
+    }
+}
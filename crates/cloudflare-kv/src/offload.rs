@@ -0,0 +1,224 @@
+//! Offloading values too large for KV to R2
+//!
+//! Values above the threshold (the 25 MiB KV limit by default) are uploaded
+//! to an R2 bucket and KV stores a pointer record in their place: a marker
+//! followed by JSON naming the object, its size and SHA-256. Reads follow the
+//! pointer and check the hash; other values pass through unchanged.
+//!
+//! The plugin goes last in the chain so the object holds the value as it
+//! would have been stored in KV, compressed and encrypted alike. Objects are
+//! not removed when their key is deleted or overwritten with a small value.
+
+use crate::error::{KvError, Result};
+use crate::plugin::{KvPlugin, PluginMetadata};
+use crate::r2::R2Client;
+use crate::types::value_hash;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Name the offload plugin registers under
+pub const OFFLOAD_PLUGIN_NAME: &str = "r2-offload";
+
+/// Largest value KV accepts, above which values are offloaded by default
+pub const KV_VALUE_LIMIT: usize = 25 * 1024 * 1024;
+
+const MARKER: &str = "cfkv-r2:v1:";
+
+/// What KV stores in place of an offloaded value
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Pointer {
+    pub bucket: String,
+    pub object: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl Pointer {
+    /// The pointer a stored value holds, if it is one
+    pub fn detect(value: &[u8]) -> Option<Self> {
+        let json = value.strip_prefix(MARKER.as_bytes())?;
+        serde_json::from_slice(json).ok()
+    }
+
+    fn encode(&self) -> Result<Vec<u8>> {
+        Ok(format!("{}{}", MARKER, serde_json::to_string(self)?).into_bytes())
+    }
+}
+
+/// Plugin moving large values to R2
+pub struct OffloadPlugin {
+    r2: R2Client,
+    /// Prepended to keys to name objects, e.g. the namespace ID
+    object_prefix: String,
+    threshold: usize,
+}
+
+impl OffloadPlugin {
+    /// Offload values larger than the KV limit to `r2`
+    pub fn new(r2: R2Client) -> Self {
+        Self {
+            r2,
+            object_prefix: String::new(),
+            threshold: KV_VALUE_LIMIT,
+        }
+    }
+
+    /// Name objects `<prefix><key>`
+    pub fn with_object_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.object_prefix = prefix.into();
+        self
+    }
+
+    /// Offload values larger than `threshold` bytes
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    async fn offload(&self, key: &str, value: &[u8]) -> Result<Vec<u8>> {
+        if value.len() <= self.threshold {
+            return Ok(value.to_vec());
+        }
+
+        let pointer = Pointer {
+            bucket: self.r2.bucket().to_string(),
+            object: format!("{}{}", self.object_prefix, key),
+            size: value.len() as u64,
+            sha256: value_hash(value),
+        };
+        self.r2.put_object(&pointer.object, value).await?;
+        pointer.encode()
+    }
+
+    async fn follow(&self, value: &[u8]) -> Result<Vec<u8>> {
+        let pointer = match Pointer::detect(value) {
+            Some(pointer) => pointer,
+            None => return Ok(value.to_vec()),
+        };
+        if pointer.bucket != self.r2.bucket() {
+            return Err(KvError::PluginError(format!(
+                "Value is stored in R2 bucket '{}', but the storage uses '{}'",
+                pointer.bucket,
+                self.r2.bucket()
+            )));
+        }
+
+        let object = self.r2.get_object(&pointer.object).await?.ok_or_else(|| {
+            KvError::PluginError(format!(
+                "R2 object {}/{} is missing",
+                pointer.bucket, pointer.object
+            ))
+        })?;
+        if value_hash(&object) != pointer.sha256 {
+            return Err(KvError::PluginError(format!(
+                "R2 object {}/{} does not match its pointer's SHA-256",
+                pointer.bucket, pointer.object
+            )));
+        }
+        Ok(object)
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl KvPlugin for OffloadPlugin {
+    fn metadata(&self) -> PluginMetadata {
+        PluginMetadata {
+            name: OFFLOAD_PLUGIN_NAME.to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            description: "Offload values too large for KV to R2".to_string(),
+            author: "cfkv".to_string(),
+        }
+    }
+
+    /// Accepts `{"threshold": 26214400}`
+    async fn init(&mut self, config: Value) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        if let Some(threshold) = config.get("threshold").and_then(|t| t.as_u64()) {
+            self.threshold = threshold as usize;
+        }
+        Ok(())
+    }
+
+    async fn pre_store(
+        &self,
+        key: &str,
+        value: &[u8],
+    ) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Ok(self.offload(key, value).await?)
+    }
+
+    async fn post_retrieve(
+        &self,
+        _key: &str,
+        value: &[u8],
+    ) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Ok(self.follow(value).await?)
+    }
+
+    async fn validate(
+        &self,
+        _key: &str,
+        _value: &[u8],
+    ) -> std::result::Result<bool, Box<dyn std::error::Error>> {
+        Ok(true)
+    }
+
+    fn commands(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{KvClient, PluginClient, PluginRegistry};
+
+    fn client(threshold: usize) -> PluginClient {
+        let plugin = OffloadPlugin::new(R2Client::in_memory("big"))
+            .with_object_prefix("ns/")
+            .with_threshold(threshold);
+        let mut registry = PluginRegistry::new();
+        registry.register(Box::new(plugin));
+        PluginClient::new(KvClient::in_memory(), registry)
+    }
+
+    #[tokio::test]
+    async fn test_large_values_are_offloaded() {
+        let client = client(8);
+        client.put("small", "tiny").await.unwrap();
+        client.put("large", "more than eight bytes").await.unwrap();
+
+        let stored = client.client().get_bytes("large").await.unwrap().unwrap();
+        let pointer = Pointer::detect(&stored).unwrap();
+        assert_eq!(pointer.object, "ns/large");
+        assert_eq!(pointer.size, 21);
+        assert_eq!(
+            client.client().get_bytes("small").await.unwrap().unwrap(),
+            b"tiny"
+        );
+
+        assert_eq!(
+            client.get_bytes("large").await.unwrap().unwrap(),
+            b"more than eight bytes"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dangling_pointer() {
+        let client = client(8);
+        let pointer = Pointer {
+            bucket: "big".into(),
+            object: "ns/gone".into(),
+            size: 3,
+            sha256: value_hash(b"abc"),
+        };
+        client
+            .client()
+            .put("gone", pointer.encode().unwrap())
+            .await
+            .unwrap();
+        let error = client.get_bytes("gone").await.unwrap_err();
+        assert!(error.to_string().contains("missing"), "{}", error);
+    }
+}
@@ -3,8 +3,21 @@
 //! This module provides the core plugin interface and registry
 //! for domain-specific KV use cases.
 
+use crate::batch::{BatchOperation, BatchTarget};
+use crate::cache::ReadCache;
+use crate::client::{appended, incremented, modify_json_bytes};
+use crate::error::KvError;
+use crate::transport::BulkWrite;
+use crate::types::{CounterValue, KeyMetadata, KvPair, ReadOptions, ReadValue, WriteCondition};
+use crate::KvClient;
 use async_trait::async_trait;
-use serde_json::Value;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use serde_json::{Map, Value};
+use std::future::Future;
+
+/// Field of a value's metadata holding, by plugin name, the tags plugins
+/// stored the value with (see `KvPlugin::pre_store_tagged`)
+pub const PLUGIN_TAGS_FIELD: &str = "cfkv";
 
 /// Plugin metadata
 #[derive(Clone, Debug)]
@@ -16,7 +29,8 @@ pub struct PluginMetadata {
 }
 
 /// Core plugin trait that all domain-specific plugins must implement
-#[async_trait]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
 pub trait KvPlugin: Send + Sync {
     /// Get plugin metadata
     fn metadata(&self) -> PluginMetadata;
@@ -38,52 +52,181 @@ pub trait KvPlugin: Send + Sync {
         value: &[u8],
     ) -> Result<Vec<u8>, Box<dyn std::error::Error>>;
 
-    /// Validate a key-value pair
-    async fn validate(
+    /// Like `pre_store`, also returning a tag to keep in the value's
+    /// metadata, such as how the value was encoded
+    ///
+    /// Values written through a `PluginClient` go through this rather than
+    /// `pre_store`, and are read back with `post_retrieve_tagged`.
+    async fn pre_store_tagged(
+        &self,
+        key: &str,
+        value: &[u8],
+    ) -> Result<(Vec<u8>, Option<Value>), Box<dyn std::error::Error>> {
+        Ok((self.pre_store(key, value).await?, None))
+    }
+
+    /// Whether `value` may have been stored with a tag, so the key's
+    /// metadata must be read before `post_retrieve_tagged`
+    fn may_be_tagged(&self, _value: &[u8]) -> bool {
+        false
+    }
+
+    /// Like `post_retrieve`, given the tag `pre_store_tagged` returned for
+    /// the value, if any
+    async fn post_retrieve_tagged(
         &self,
         key: &str,
         value: &[u8],
-    ) -> Result<bool, Box<dyn std::error::Error>>;
+        tag: Option<&Value>,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let _ = tag;
+        self.post_retrieve(key, value).await
+    }
+
+    /// Validate a key-value pair
+    async fn validate(&self, key: &str, value: &[u8]) -> Result<bool, Box<dyn std::error::Error>>;
 
     /// Get plugin-specific commands
     fn commands(&self) -> Vec<String>;
 }
 
 /// Plugin registry
+///
+/// Plugins run in registration order on the way into KV (`pre_store`) and in
+/// reverse order on the way out (`post_retrieve`), so a chain such as
+/// compress-then-encrypt is undone as decrypt-then-decompress.
 pub struct PluginRegistry {
-    plugins: std::collections::HashMap<String, Box<dyn KvPlugin>>,
+    plugins: Vec<Box<dyn KvPlugin>>,
 }
 
 impl PluginRegistry {
     /// Create a new plugin registry
     pub fn new() -> Self {
         Self {
-            plugins: std::collections::HashMap::new(),
+            plugins: Vec::new(),
         }
     }
 
-    /// Register a plugin
+    /// Register a plugin, replacing any plugin with the same name
     pub fn register(&mut self, plugin: Box<dyn KvPlugin>) {
-        let name = plugin.metadata().name.clone();
-        self.plugins.insert(name, plugin);
+        let name = plugin.metadata().name;
+        match self.position(&name) {
+            Some(pos) => self.plugins[pos] = plugin,
+            None => self.plugins.push(plugin),
+        }
     }
 
     /// Get a plugin by name
-    pub fn get(&self, name: &str) -> Option<&Box<dyn KvPlugin>> {
-        self.plugins.get(name)
+    pub fn get(&self, name: &str) -> Option<&dyn KvPlugin> {
+        self.position(name).map(|pos| self.plugins[pos].as_ref())
     }
 
     /// Get a mutable plugin by name
     pub fn get_mut(&mut self, name: &str) -> Option<&mut Box<dyn KvPlugin>> {
-        self.plugins.get_mut(name)
+        self.position(name).map(|pos| &mut self.plugins[pos])
     }
 
     /// List all registered plugins
     pub fn list(&self) -> Vec<PluginMetadata> {
-        self.plugins
-            .values()
-            .map(|p| p.metadata())
-            .collect()
+        self.plugins.iter().map(|p| p.metadata()).collect()
+    }
+
+    /// Check if no plugins are registered
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
+
+    /// Validate a value and run it through every plugin's `pre_store`
+    pub async fn pre_store(&self, key: &str, value: &[u8]) -> crate::Result<Vec<u8>> {
+        let mut value = value.to_vec();
+        for plugin in &self.plugins {
+            let name = validate(plugin.as_ref(), key, &value).await?;
+            value = plugin
+                .pre_store(key, &value)
+                .await
+                .map_err(|e| plugin_error(&name, e))?;
+        }
+        Ok(value)
+    }
+
+    /// Validate a value and run it through every plugin's
+    /// `pre_store_tagged`
+    ///
+    /// Returns the value to store and its metadata: `metadata` with the tags
+    /// plugins returned in its `PLUGIN_TAGS_FIELD`, replacing any tags it had.
+    pub async fn pre_store_tagged(
+        &self,
+        key: &str,
+        value: &[u8],
+        metadata: Option<Value>,
+    ) -> crate::Result<(Vec<u8>, Option<Value>)> {
+        let mut value = value.to_vec();
+        let mut tags = Map::new();
+        for plugin in &self.plugins {
+            let name = validate(plugin.as_ref(), key, &value).await?;
+            let (stored, tag) = plugin
+                .pre_store_tagged(key, &value)
+                .await
+                .map_err(|e| plugin_error(&name, e))?;
+            value = stored;
+            if let Some(tag) = tag {
+                tags.insert(name, tag);
+            }
+        }
+        Ok((value, with_tags(metadata, tags)?))
+    }
+
+    /// Run a retrieved value through every plugin's `post_retrieve`
+    pub async fn post_retrieve(&self, key: &str, value: &[u8]) -> crate::Result<Vec<u8>> {
+        let mut value = value.to_vec();
+        for plugin in self.plugins.iter().rev() {
+            value = plugin
+                .post_retrieve(key, &value)
+                .await
+                .map_err(|e| plugin_error(&plugin.metadata().name, e))?;
+        }
+        Ok(value)
+    }
+
+    /// Run a retrieved value through every plugin's `post_retrieve_tagged`
+    ///
+    /// `metadata` reads the key's metadata for the tags; it is only called
+    /// once a plugin sees a value that may be tagged, so most reads need no
+    /// extra request.
+    pub async fn post_retrieve_tagged<F, Fut>(
+        &self,
+        key: &str,
+        value: &[u8],
+        metadata: F,
+    ) -> crate::Result<Vec<u8>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = crate::Result<Option<Value>>>,
+    {
+        let mut value = value.to_vec();
+        let mut read = Some(metadata);
+        let mut metadata = None;
+        for plugin in self.plugins.iter().rev() {
+            let name = plugin.metadata().name;
+            if plugin.may_be_tagged(&value) {
+                if let Some(read) = read.take() {
+                    metadata = read().await?;
+                }
+            }
+            let tag = metadata
+                .as_ref()
+                .and_then(|metadata| metadata.get(PLUGIN_TAGS_FIELD))
+                .and_then(|tags| tags.get(&name));
+            value = plugin
+                .post_retrieve_tagged(key, &value, tag)
+                .await
+                .map_err(|e| plugin_error(&name, e))?;
+        }
+        Ok(value)
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.plugins.iter().position(|p| p.metadata().name == name)
     }
 }
 
@@ -92,3 +235,547 @@ impl Default for PluginRegistry {
         Self::new()
     }
 }
+
+fn plugin_error(name: &str, error: Box<dyn std::error::Error>) -> KvError {
+    KvError::PluginError(format!("{}: {}", name, error))
+}
+
+/// Run a plugin's `validate`, returning the plugin's name
+async fn validate(plugin: &dyn KvPlugin, key: &str, value: &[u8]) -> crate::Result<String> {
+    let name = plugin.metadata().name;
+    let valid = plugin
+        .validate(key, value)
+        .await
+        .map_err(|e| plugin_error(&name, e))?;
+    if !valid {
+        return Err(KvError::PluginError(format!(
+            "{}: value for key '{}' rejected",
+            name, key
+        )));
+    }
+    Ok(name)
+}
+
+/// `metadata` with its plugin tags replaced by `tags`
+///
+/// Metadata left empty by removing stale tags is dropped. Tags can only be
+/// kept in metadata that is a JSON object.
+fn with_tags(metadata: Option<Value>, tags: Map<String, Value>) -> crate::Result<Option<Value>> {
+    let tags = (!tags.is_empty()).then_some(Value::Object(tags));
+    match (metadata, tags) {
+        (Some(Value::Object(mut fields)), tags) => {
+            let had_tags = fields.remove(PLUGIN_TAGS_FIELD).is_some();
+            match tags {
+                Some(tags) => {
+                    fields.insert(PLUGIN_TAGS_FIELD.to_string(), tags);
+                }
+                None if had_tags && fields.is_empty() => return Ok(None),
+                None => {}
+            }
+            Ok(Some(Value::Object(fields)))
+        }
+        (None, Some(tags)) => Ok(Some(serde_json::json!({ PLUGIN_TAGS_FIELD: tags }))),
+        (metadata, None) => Ok(metadata),
+        (Some(_), Some(_)) => Err(KvError::PluginError(
+            "Metadata must be a JSON object to record how the value was stored".to_string(),
+        )),
+    }
+}
+
+/// `metadata` with the plugin tags of `current`, for replacing a key's
+/// metadata without making its value unreadable
+pub fn keep_tags(current: Option<&Value>, metadata: Option<Value>) -> crate::Result<Option<Value>> {
+    let tags = current
+        .and_then(|current| current.get(PLUGIN_TAGS_FIELD))
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default();
+    with_tags(metadata, tags)
+}
+
+/// KV client that passes every value through a plugin chain
+pub struct PluginClient {
+    client: KvClient,
+    registry: PluginRegistry,
+}
+
+impl PluginClient {
+    /// Wrap a client with a plugin registry
+    pub fn new(client: KvClient, registry: PluginRegistry) -> Self {
+        Self { client, registry }
+    }
+
+    /// Get the underlying client
+    pub fn client(&self) -> &KvClient {
+        &self.client
+    }
+
+    /// Get the plugin registry
+    pub fn registry(&self) -> &PluginRegistry {
+        &self.registry
+    }
+
+    /// Get a value and run it through `post_retrieve`
+    pub async fn get(&self, key: &str) -> crate::Result<Option<KvPair>> {
+        // Read the raw bytes: plugins may store values that are not text
+        match self.get_bytes(key).await? {
+            Some(value) => Ok(Some(KvPair {
+                key: key.to_string(),
+                value: String::from_utf8_lossy(&value).into_owned(),
+                metadata: None,
+                expiration: None,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    /// Get several values through `post_retrieve`, with at most `concurrency`
+    /// requests in flight
+    ///
+    /// Results are in the order of `keys`; missing keys are `None`.
+    pub async fn get_many<K: AsRef<str>>(
+        &self,
+        keys: &[K],
+        concurrency: usize,
+    ) -> crate::Result<Vec<Option<KvPair>>> {
+        stream::iter(keys)
+            .map(|key| self.get(key.as_ref()))
+            .buffered(concurrency.max(1))
+            .try_collect()
+            .await
+    }
+
+    /// Get a value, from `cache` when fresh, and run it through `post_retrieve`
+    ///
+    /// The cache holds values as stored, before plugin decoding.
+    pub async fn get_cached(&self, key: &str, cache: &ReadCache) -> crate::Result<Option<KvPair>> {
+        Ok(self
+            .get_bytes_cached(key, cache)
+            .await?
+            .map(|value| KvPair {
+                key: key.to_string(),
+                value: String::from_utf8_lossy(&value).into_owned(),
+                metadata: None,
+                expiration: None,
+            }))
+    }
+
+    /// Get a value as bytes, without decoding it as text, and run it
+    /// through `post_retrieve`
+    pub async fn get_bytes(&self, key: &str) -> crate::Result<Option<Vec<u8>>> {
+        match self.client.get_bytes(key).await? {
+            Some(raw) => Ok(Some(self.decode(key, &raw).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get a value with what `options` ask for and run it through
+    /// `post_retrieve`
+    pub async fn get_with_options(
+        &self,
+        key: &str,
+        options: &ReadOptions,
+    ) -> crate::Result<Option<ReadValue>> {
+        match self.client.get_with_options(key, options).await? {
+            Some(mut read) => {
+                read.value = self.decode(key, &read.value).await?;
+                Ok(Some(read))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Like `get_bytes`, from `cache` when fresh
+    pub async fn get_bytes_cached(
+        &self,
+        key: &str,
+        cache: &ReadCache,
+    ) -> crate::Result<Option<Vec<u8>>> {
+        match cache.fetch(&self.client, key).await? {
+            Some(raw) => Ok(Some(self.decode(key, &raw).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Run a stored value through `post_retrieve_tagged`, reading the key's
+    /// metadata if a plugin needs its tags
+    async fn decode(&self, key: &str, raw: &[u8]) -> crate::Result<Vec<u8>> {
+        self.registry
+            .post_retrieve_tagged(key, raw, || self.client.metadata(key))
+            .await
+    }
+
+    /// Run a value through `pre_store` and put it
+    pub async fn put(&self, key: &str, value: impl AsRef<[u8]>) -> crate::Result<()> {
+        match self
+            .registry
+            .pre_store_tagged(key, value.as_ref(), None)
+            .await?
+        {
+            (value, None) => self.client.put(key, value).await,
+            (value, metadata) => {
+                self.client
+                    .put_with_options(key, value, None, metadata)
+                    .await
+            }
+        }
+    }
+
+    /// Run a value through `pre_store` and put it with metadata and expiration
+    pub async fn put_with_options(
+        &self,
+        key: &str,
+        value: impl AsRef<[u8]>,
+        expiration: Option<u64>,
+        metadata: Option<Value>,
+    ) -> crate::Result<()> {
+        let (value, metadata) = self
+            .registry
+            .pre_store_tagged(key, value.as_ref(), metadata)
+            .await?;
+        self.client
+            .put_with_options(key, value, expiration, metadata)
+            .await
+    }
+
+    /// Run a value through `pre_store` and put it with metadata, expiring at
+    /// an absolute Unix timestamp
+    pub async fn put_with_expiration_at(
+        &self,
+        key: &str,
+        value: impl AsRef<[u8]>,
+        expiration: Option<u64>,
+        metadata: Option<Value>,
+    ) -> crate::Result<()> {
+        let (value, metadata) = self
+            .registry
+            .pre_store_tagged(key, value.as_ref(), metadata)
+            .await?;
+        self.client
+            .put_with_expiration_at(key, value, expiration, metadata)
+            .await
+    }
+
+    /// Run each value through `pre_store` and write them with bulk requests
+    pub async fn put_bulk(&self, writes: &[BulkWrite]) -> crate::Result<()> {
+        let mut stored = Vec::with_capacity(writes.len());
+        for write in writes {
+            let (value, metadata) = self
+                .registry
+                .pre_store_tagged(&write.key, &write.value, write.metadata.clone())
+                .await?;
+            stored.push(BulkWrite {
+                value,
+                metadata,
+                ..write.clone()
+            });
+        }
+        self.client.put_bulk(&stored).await
+    }
+
+    /// Check a write condition against the value as returned by `get`, so
+    /// hashes match what users see rather than the encrypted or compressed
+    /// bytes in KV
+    pub async fn check_condition(
+        &self,
+        key: &str,
+        condition: &WriteCondition,
+    ) -> crate::Result<()> {
+        let current = self.get(key).await?.map(|pair| pair.value);
+        condition.check(key, current.as_deref().map(str::as_bytes))
+    }
+
+    /// Append bytes to a value through the plugin chain (see `KvClient::append`)
+    pub async fn append(&self, key: &str, bytes: impl AsRef<[u8]>) -> crate::Result<()> {
+        let current = self.get(key).await?.map(|pair| pair.value.into_bytes());
+        let info = self.client.head(key).await?;
+        let value = appended(current, bytes.as_ref());
+        self.put_preserving(key, value, info).await
+    }
+
+    /// Add `delta` to a numeric value through the plugin chain (see
+    /// `KvClient::increment`)
+    pub async fn increment(
+        &self,
+        key: &str,
+        delta: impl Into<CounterValue>,
+    ) -> crate::Result<CounterValue> {
+        let current = self.get(key).await?.map(|pair| pair.value);
+        let info = self.client.head(key).await?;
+        let value = incremented(key, current.as_deref().map(str::as_bytes), delta.into())?;
+        self.put_preserving(key, value.to_string(), info).await?;
+        Ok(value)
+    }
+
+    /// Apply a change to a JSON value through the plugin chain, keeping its
+    /// expiration and metadata (see `KvClient::modify_json`)
+    pub async fn modify_json<F>(&self, key: &str, modify: F) -> crate::Result<Value>
+    where
+        F: FnOnce(&mut Value) -> crate::Result<()>,
+    {
+        let pair = self
+            .get(key)
+            .await?
+            .ok_or_else(|| KvError::KeyNotFound(key.to_string()))?;
+        let info = self.client.head(key).await?;
+
+        let (value, bytes) = modify_json_bytes(key, pair.value.as_bytes(), modify)?;
+        self.put_preserving(key, bytes, info).await?;
+        Ok(value)
+    }
+
+    /// Run a value through `pre_store` and put it, keeping the expiration and
+    /// metadata the key had before (see `KvClient::put_preserving`)
+    pub async fn put_preserving(
+        &self,
+        key: &str,
+        value: impl AsRef<[u8]>,
+        previous: Option<KeyMetadata>,
+    ) -> crate::Result<()> {
+        let current = previous.as_ref().and_then(|info| info.metadata.clone());
+        let (value, metadata) = self
+            .registry
+            .pre_store_tagged(key, value.as_ref(), current)
+            .await?;
+        let previous = match previous {
+            Some(info) => Some(KeyMetadata { metadata, ..info }),
+            None => metadata.map(|metadata| KeyMetadata {
+                name: key.to_string(),
+                expiration: None,
+                metadata: Some(metadata),
+            }),
+        };
+        self.client.put_preserving(key, value, previous).await
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl BatchTarget for PluginClient {
+    /// Apply an operation, running put values through `pre_store`
+    async fn apply(&self, operation: &BatchOperation) -> crate::Result<()> {
+        match operation {
+            BatchOperation::Put {
+                key,
+                value,
+                expiration_ttl,
+                expiration,
+                metadata,
+                base64,
+            } => {
+                let (value, metadata) = self
+                    .registry
+                    .pre_store_tagged(key, value, metadata.clone())
+                    .await?;
+                let operation = BatchOperation::Put {
+                    key: key.clone(),
+                    value,
+                    expiration_ttl: *expiration_ttl,
+                    expiration: *expiration,
+                    metadata,
+                    base64: *base64,
+                };
+                self.client.apply(&operation).await
+            }
+            BatchOperation::Delete { .. } => self.client.apply(operation).await,
+        }
+    }
+
+    async fn apply_bulk(&self, operations: &[BatchOperation]) -> Option<crate::Result<()>> {
+        Some(match operations.first()? {
+            BatchOperation::Put { .. } => {
+                let writes: Vec<BulkWrite> = operations
+                    .iter()
+                    .filter_map(BatchOperation::to_bulk_write)
+                    .collect();
+                self.put_bulk(&writes).await
+            }
+            BatchOperation::Delete { .. } => return self.client.apply_bulk(operations).await,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Test plugin that wraps values in a marker
+    struct WrapPlugin {
+        name: &'static str,
+    }
+
+    #[async_trait]
+    impl KvPlugin for WrapPlugin {
+        fn metadata(&self) -> PluginMetadata {
+            PluginMetadata {
+                name: self.name.to_string(),
+                version: "0.1.0".to_string(),
+                description: "Wraps values".to_string(),
+                author: "test".to_string(),
+            }
+        }
+
+        async fn init(&mut self, _config: Value) -> Result<(), Box<dyn std::error::Error>> {
+            Ok(())
+        }
+
+        async fn pre_store(
+            &self,
+            _key: &str,
+            value: &[u8],
+        ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+            Ok(format!("{}({})", self.name, String::from_utf8_lossy(value)).into_bytes())
+        }
+
+        async fn post_retrieve(
+            &self,
+            _key: &str,
+            value: &[u8],
+        ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+            let value = String::from_utf8_lossy(value);
+            let inner = value
+                .strip_prefix(&format!("{}(", self.name))
+                .and_then(|v| v.strip_suffix(')'))
+                .ok_or("not wrapped")?;
+            Ok(inner.as_bytes().to_vec())
+        }
+
+        async fn validate(
+            &self,
+            _key: &str,
+            value: &[u8],
+        ) -> Result<bool, Box<dyn std::error::Error>> {
+            Ok(!value.is_empty())
+        }
+
+        fn commands(&self) -> Vec<String> {
+            vec![]
+        }
+    }
+
+    fn registry() -> PluginRegistry {
+        let mut registry = PluginRegistry::new();
+        registry.register(Box::new(WrapPlugin { name: "a" }));
+        registry.register(Box::new(WrapPlugin { name: "b" }));
+        registry
+    }
+
+    #[tokio::test]
+    async fn test_chain_order() {
+        let registry = registry();
+        let stored = registry.pre_store("key", b"value").await.unwrap();
+        assert_eq!(stored, b"b(a(value))");
+
+        let retrieved = registry.post_retrieve("key", &stored).await.unwrap();
+        assert_eq!(retrieved, b"value");
+    }
+
+    #[tokio::test]
+    async fn test_validation_rejects() {
+        let registry = registry();
+        assert!(matches!(
+            registry.pre_store("key", b"").await,
+            Err(KvError::PluginError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_many() {
+        let client = PluginClient::new(KvClient::in_memory(), registry());
+        client.put("x", "1").await.unwrap();
+        client.put("y", "2").await.unwrap();
+
+        let values = client.get_many(&["y", "missing", "x"], 2).await.unwrap();
+        let values: Vec<Option<String>> = values
+            .into_iter()
+            .map(|pair| pair.map(|pair| pair.value))
+            .collect();
+        assert_eq!(
+            values,
+            vec![Some("2".to_string()), None, Some("1".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_bytes_keeps_binary_values() {
+        let client = PluginClient::new(KvClient::in_memory(), PluginRegistry::new());
+        let value = [0u8, 159, 146, 150, 255];
+        client.put("blob", value).await.unwrap();
+
+        assert_eq!(client.get_bytes("blob").await.unwrap().unwrap(), value);
+        assert!(client.get_bytes("missing").await.unwrap().is_none());
+        // The text getter can only give a lossy version
+        assert_ne!(
+            client.get("blob").await.unwrap().unwrap().value.as_bytes(),
+            value
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tags_kept_in_metadata() {
+        use crate::compression::{CompressionAlgorithm, CompressionPlugin};
+
+        let mut registry = PluginRegistry::new();
+        registry.register(Box::new(
+            CompressionPlugin::new()
+                .with_algorithm(CompressionAlgorithm::Gzip)
+                .with_threshold(0),
+        ));
+        let client = PluginClient::new(KvClient::in_memory(), registry);
+        let value = "compress me ".repeat(100);
+
+        client
+            .put_with_options("key", &value, None, Some(json!({ "owner": "me" })))
+            .await
+            .unwrap();
+        assert_eq!(client.get("key").await.unwrap().unwrap().value, value);
+        assert_eq!(
+            client.client().metadata("key").await.unwrap(),
+            Some(json!({ "owner": "me", "cfkv": { "compression": "gzip" } }))
+        );
+        assert!(
+            client
+                .client()
+                .get_bytes("key")
+                .await
+                .unwrap()
+                .unwrap()
+                .len()
+                < value.len()
+        );
+
+        // A value that no longer compresses drops the stale tag
+        client.put("key", "short").await.unwrap();
+        assert_eq!(client.client().metadata("key").await.unwrap(), None);
+        assert_eq!(client.get("key").await.unwrap().unwrap().value, "short");
+    }
+
+    #[test]
+    fn test_keep_tags() {
+        let current = json!({ "old": true, "cfkv": { "compression": "zstd" } });
+        assert_eq!(
+            keep_tags(Some(&current), Some(json!({ "new": true }))).unwrap(),
+            Some(json!({ "new": true, "cfkv": { "compression": "zstd" } }))
+        );
+        assert_eq!(
+            keep_tags(Some(&current), None).unwrap(),
+            Some(json!({ "cfkv": { "compression": "zstd" } }))
+        );
+        assert!(keep_tags(Some(&current), Some(json!("text"))).is_err());
+        assert_eq!(
+            keep_tags(None, Some(json!("text"))).unwrap(),
+            Some(json!("text"))
+        );
+    }
+
+    #[test]
+    fn test_register_replaces_by_name() {
+        let mut registry = registry();
+        registry.register(Box::new(WrapPlugin { name: "a" }));
+        let names: Vec<String> = registry.list().into_iter().map(|m| m.name).collect();
+        assert_eq!(names, vec!["a", "b"]);
+        assert!(registry.get("b").is_some());
+        assert!(registry.get("c").is_none());
+    }
+}
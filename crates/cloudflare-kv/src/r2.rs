@@ -0,0 +1,214 @@
+//! Minimal R2 client for objects too large for KV
+//!
+//! Uses the R2 object endpoints of the Cloudflare API, which accept the same
+//! API token as KV, rather than the S3-compatible API and its signed requests.
+
+use crate::error::Result;
+use crate::http_client::{ApiRequest, Engine};
+use crate::transport::status_error;
+use crate::types::{AuthCredentials, ConnectionOptions};
+use http::{Method, StatusCode};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tracing::debug;
+
+enum Backend {
+    Http {
+        engine: Engine,
+        base_url: String,
+        account_id: String,
+        credentials: AuthCredentials,
+    },
+    Memory(Mutex<HashMap<String, Vec<u8>>>),
+}
+
+/// Client for the objects of one R2 bucket
+pub struct R2Client {
+    bucket: String,
+    backend: Backend,
+}
+
+impl R2Client {
+    /// Create a client for `bucket` in the given account
+    pub fn new(account_id: &str, bucket: &str, credentials: AuthCredentials) -> Self {
+        Self {
+            bucket: bucket.to_string(),
+            backend: Backend::Http {
+                engine: Engine::new(&ConnectionOptions::default()),
+                base_url: "https://api.cloudflare.com/client/v4".to_string(),
+                account_id: account_id.to_string(),
+                credentials,
+            },
+        }
+    }
+
+    /// Create a client keeping objects in memory, for tests
+    pub fn in_memory(bucket: &str) -> Self {
+        Self {
+            bucket: bucket.to_string(),
+            backend: Backend::Memory(Mutex::default()),
+        }
+    }
+
+    /// Send requests to `base_url` instead of the Cloudflare API
+    pub fn with_base_url(mut self, url: impl Into<String>) -> Self {
+        if let Backend::Http { base_url, .. } = &mut self.backend {
+            *base_url = url.into();
+        }
+        self
+    }
+
+    /// Name of the bucket
+    pub fn bucket(&self) -> &str {
+        &self.bucket
+    }
+
+    fn object_url(base_url: &str, account_id: &str, bucket: &str, key: &str) -> String {
+        format!(
+            "{}/accounts/{}/r2/buckets/{}/objects/{}",
+            base_url, account_id, bucket, key
+        )
+    }
+
+    /// Upload an object, replacing any object with the same key
+    pub async fn put_object(&self, key: &str, value: &[u8]) -> Result<()> {
+        debug!("Putting R2 object: {}/{}", self.bucket, key);
+        let (engine, base_url, account_id, credentials) = match &self.backend {
+            Backend::Memory(objects) => {
+                memory(objects).insert(key.to_string(), value.to_vec());
+                return Ok(());
+            }
+            Backend::Http {
+                engine,
+                base_url,
+                account_id,
+                credentials,
+            } => (engine, base_url, account_id, credentials),
+        };
+
+        let url = Self::object_url(base_url, account_id, &self.bucket, key);
+        let request = ApiRequest::new(Method::PUT, url, credentials.auth_header());
+        let response = engine.send(request.body(value.to_vec())).await?;
+        match response.status {
+            status if status.is_success() => Ok(()),
+            status => Err(status_error(
+                &response,
+                format!(
+                    "Failed to put R2 object {}: {} - {}",
+                    key,
+                    status,
+                    response.text()
+                ),
+            )),
+        }
+    }
+
+    /// Download an object, or `None` if it does not exist
+    pub async fn get_object(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        debug!("Getting R2 object: {}/{}", self.bucket, key);
+        let (engine, base_url, account_id, credentials) = match &self.backend {
+            Backend::Memory(objects) => return Ok(memory(objects).get(key).cloned()),
+            Backend::Http {
+                engine,
+                base_url,
+                account_id,
+                credentials,
+            } => (engine, base_url, account_id, credentials),
+        };
+
+        let url = Self::object_url(base_url, account_id, &self.bucket, key);
+        let request = ApiRequest::new(Method::GET, url, credentials.auth_header());
+        let response = engine.send(request).await?;
+        match response.status {
+            StatusCode::OK => Ok(Some(response.body)),
+            StatusCode::NOT_FOUND => Ok(None),
+            status => Err(status_error(
+                &response,
+                format!(
+                    "Failed to get R2 object {}: {} - {}",
+                    key,
+                    status,
+                    response.text()
+                ),
+            )),
+        }
+    }
+
+    /// Delete an object; deleting a missing object is not an error
+    pub async fn delete_object(&self, key: &str) -> Result<()> {
+        debug!("Deleting R2 object: {}/{}", self.bucket, key);
+        let (engine, base_url, account_id, credentials) = match &self.backend {
+            Backend::Memory(objects) => {
+                memory(objects).remove(key);
+                return Ok(());
+            }
+            Backend::Http {
+                engine,
+                base_url,
+                account_id,
+                credentials,
+            } => (engine, base_url, account_id, credentials),
+        };
+
+        let url = Self::object_url(base_url, account_id, &self.bucket, key);
+        let request = ApiRequest::new(Method::DELETE, url, credentials.auth_header());
+        let response = engine.send(request).await?;
+        match response.status {
+            status if status.is_success() || status == StatusCode::NOT_FOUND => Ok(()),
+            status => Err(status_error(
+                &response,
+                format!(
+                    "Failed to delete R2 object {}: {} - {}",
+                    key,
+                    status,
+                    response.text()
+                ),
+            )),
+        }
+    }
+}
+
+fn memory(
+    objects: &Mutex<HashMap<String, Vec<u8>>>,
+) -> std::sync::MutexGuard<'_, HashMap<String, Vec<u8>>> {
+    objects.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_http_objects() {
+        let server = MockServer::start().await;
+        let object = "/accounts/acc/r2/buckets/big/objects/ns/video";
+        Mock::given(method("PUT"))
+            .and(path(object))
+            .and(header("Authorization", "Bearer token"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(object))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"frames".to_vec()))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/accounts/acc/r2/buckets/big/objects/missing"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+
+        let client = R2Client::new("acc", "big", AuthCredentials::token("token"))
+            .with_base_url(server.uri());
+        client.put_object("ns/video", b"frames").await.unwrap();
+        assert_eq!(
+            client.get_object("ns/video").await.unwrap(),
+            Some(b"frames".to_vec())
+        );
+        assert_eq!(client.get_object("missing").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,134 @@
+//! Client-side request throttling
+//!
+//! `RateLimiter` is a token bucket: it holds up to one second's worth of
+//! requests and refills continuously. Callers that find the bucket empty
+//! reserve the next token and sleep until it is due, so concurrent tasks are
+//! served in the order they asked and the overall rate never exceeds the
+//! limit. Clones of a `ClientConfig` share the same bucket.
+
+use std::sync::Mutex;
+use std::time::Duration;
+use web_time::Instant;
+
+/// Token bucket limiting requests per second
+#[derive(Debug)]
+pub struct RateLimiter {
+    requests_per_second: f64,
+    capacity: f64,
+    state: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Available tokens; negative when requests are queued for future tokens
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    /// Allow `requests_per_second` requests, with bursts of up to one second's worth
+    pub fn new(requests_per_second: f64) -> Self {
+        let requests_per_second = requests_per_second.max(f64::MIN_POSITIVE);
+        let capacity = requests_per_second.max(1.0);
+        Self {
+            requests_per_second,
+            capacity,
+            state: Mutex::new(Bucket {
+                tokens: capacity,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Configured requests per second
+    pub fn requests_per_second(&self) -> f64 {
+        self.requests_per_second
+    }
+
+    /// Wait until a request may be sent
+    pub async fn acquire(&self) {
+        let wait = self.reserve(Instant::now());
+        if !wait.is_zero() {
+            sleep(wait).await;
+        }
+    }
+
+    /// Take a token at `now` and return how long to wait before using it
+    fn reserve(&self, now: Instant) -> Duration {
+        let mut bucket = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        let elapsed = now.saturating_duration_since(bucket.refilled_at);
+        bucket.tokens =
+            (bucket.tokens + elapsed.as_secs_f64() * self.requests_per_second).min(self.capacity);
+        bucket.refilled_at = now.max(bucket.refilled_at);
+        bucket.tokens -= 1.0;
+
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / self.requests_per_second)
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use tokio::time::sleep;
+
+/// Sleep with the host's `setTimeout`, as Workers and browsers have no Tokio
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn sleep(duration: Duration) {
+    use wasm_bindgen::JsCast;
+
+    let promise = js_sys::Promise::new(&mut |resolve, _reject| {
+        let global = js_sys::global();
+        if let Ok(set_timeout) = js_sys::Reflect::get(&global, &"setTimeout".into()) {
+            let set_timeout: js_sys::Function = set_timeout.unchecked_into();
+            let millis = duration.as_millis() as f64;
+            let _ = set_timeout.call2(&global, &resolve, &millis.into());
+        } else {
+            // No timers to wait with; send the request rather than hang
+            let _ = resolve.call0(&wasm_bindgen::JsValue::UNDEFINED);
+        }
+    });
+    let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_burst_then_throttle() {
+        let limiter = RateLimiter::new(10.0);
+        let start = Instant::now();
+
+        for _ in 0..10 {
+            assert_eq!(limiter.reserve(start), Duration::ZERO);
+        }
+        // Queued requests are spaced one token apart
+        let first = limiter.reserve(start);
+        let second = limiter.reserve(start);
+        assert!((first.as_secs_f64() - 0.1).abs() < 1e-9);
+        assert!((second.as_secs_f64() - 0.2).abs() < 1e-9);
+
+        // After the queue drains and a second passes, the bucket is full again
+        let later = start + Duration::from_secs(2);
+        for _ in 0..10 {
+            assert_eq!(limiter.reserve(later), Duration::ZERO);
+        }
+        assert!(!limiter.reserve(later).is_zero());
+    }
+
+    #[test]
+    fn test_slow_rates() {
+        let limiter = RateLimiter::new(0.5);
+        let start = Instant::now();
+
+        assert_eq!(limiter.reserve(start), Duration::ZERO);
+        assert_eq!(limiter.reserve(start), Duration::from_secs(2));
+        assert_eq!(
+            limiter.reserve(start + Duration::from_secs(1)),
+            Duration::from_secs(3)
+        );
+    }
+}
@@ -0,0 +1,227 @@
+//! Key prefix scoping
+//!
+//! `ScopedKvClient` lets several applications share one namespace: every key
+//! it writes gets the scope's prefix, reads and deletes go to prefixed keys,
+//! and listings only return keys under the prefix, with the prefix stripped.
+//! The prefix is applied at the transport, so every `KvClient` operation
+//! (copy, rename, conditional writes, ...) stays inside the scope, while
+//! account-level calls (token checks, namespace details) still reach the API.
+//!
+//! ```ignore
+//! let client = ScopedKvClient::new(KvClient::new(config), "billing/").into_client();
+//! client.put("config", "{}").await?; // stored as "billing/config"
+//! ```
+
+use crate::error::Result;
+use crate::transport::{BulkWrite, ConditionalGet, Expiration, KvTransport};
+use crate::types::{ListResponse, PaginationParams};
+use crate::KvClient;
+use async_trait::async_trait;
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Transport adding a prefix to the keys of another transport
+pub struct ScopedTransport {
+    inner: Arc<dyn KvTransport>,
+    prefix: String,
+}
+
+impl ScopedTransport {
+    /// Scope `inner` to keys starting with `prefix`
+    pub fn new(inner: Arc<dyn KvTransport>, prefix: impl Into<String>) -> Self {
+        Self {
+            inner,
+            prefix: prefix.into(),
+        }
+    }
+
+    fn scoped(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl KvTransport for ScopedTransport {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.inner.get(&self.scoped(key)).await
+    }
+
+    async fn get_if_none_match(&self, key: &str, etag: Option<&str>) -> Result<ConditionalGet> {
+        self.inner.get_if_none_match(&self.scoped(key), etag).await
+    }
+
+    async fn put(
+        &self,
+        key: &str,
+        value: &[u8],
+        expiration: Option<Expiration>,
+        metadata: Option<Value>,
+    ) -> Result<()> {
+        self.inner
+            .put(&self.scoped(key), value, expiration, metadata)
+            .await
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.inner.delete(&self.scoped(key)).await
+    }
+
+    async fn metadata(&self, key: &str) -> Result<Option<Value>> {
+        self.inner.metadata(&self.scoped(key)).await
+    }
+
+    async fn list(&self, mut params: PaginationParams) -> Result<ListResponse> {
+        params.prefix = Some(self.scoped(params.prefix.as_deref().unwrap_or_default()));
+        let mut response = self.inner.list(params).await?;
+        for key in &mut response.keys {
+            if let Some(name) = key.name.strip_prefix(&self.prefix) {
+                key.name = name.to_string();
+            }
+        }
+        Ok(response)
+    }
+
+    async fn delete_bulk(&self, keys: &[&str]) -> Result<()> {
+        let scoped: Vec<String> = keys.iter().map(|key| self.scoped(key)).collect();
+        let scoped: Vec<&str> = scoped.iter().map(String::as_str).collect();
+        self.inner.delete_bulk(&scoped).await
+    }
+
+    async fn put_bulk(&self, writes: &[BulkWrite]) -> Result<()> {
+        let scoped: Vec<BulkWrite> = writes
+            .iter()
+            .map(|write| BulkWrite {
+                key: self.scoped(&write.key),
+                ..write.clone()
+            })
+            .collect();
+        self.inner.put_bulk(&scoped).await
+    }
+}
+
+/// KV client whose keys all live under a prefix
+pub struct ScopedKvClient {
+    client: KvClient,
+}
+
+impl ScopedKvClient {
+    /// Scope `client` to keys starting with `prefix`
+    ///
+    /// Scoping an already scoped client nests the prefixes.
+    pub fn new(client: KvClient, prefix: impl Into<String>) -> Self {
+        let prefix = prefix.into();
+        let mut config = client.config().clone();
+        config.key_prefix = Some(format!(
+            "{}{}",
+            config.key_prefix.as_deref().unwrap_or_default(),
+            prefix
+        ));
+        let mut client =
+            client.wrap_transport(|inner| Arc::new(ScopedTransport::new(inner, prefix)));
+        client.update_config(config);
+        Self { client }
+    }
+
+    /// The full prefix added to keys
+    pub fn prefix(&self) -> &str {
+        self.client
+            .config()
+            .key_prefix
+            .as_deref()
+            .unwrap_or_default()
+    }
+
+    /// Get the scoped client
+    pub fn client(&self) -> &KvClient {
+        &self.client
+    }
+
+    /// Take the scoped client, e.g. to hand it to code expecting a `KvClient`
+    pub fn into_client(self) -> KvClient {
+        self.client
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::InMemoryTransport;
+    use crate::types::{AuthCredentials, ClientConfig};
+
+    fn shared_namespace() -> (Arc<InMemoryTransport>, KvClient, KvClient) {
+        let store = Arc::new(InMemoryTransport::new());
+        let config = ClientConfig::new("a", "n", AuthCredentials::token(""));
+        let client = |prefix: &str| {
+            ScopedKvClient::new(
+                KvClient::with_transport(config.clone(), store.clone()),
+                prefix,
+            )
+            .into_client()
+        };
+        (store.clone(), client("app1/"), client("app2/"))
+    }
+
+    #[tokio::test]
+    async fn test_keys_are_prefixed() {
+        let (store, app1, app2) = shared_namespace();
+        app1.put("config", "one").await.unwrap();
+        app2.put("config", "two").await.unwrap();
+        app1.rename("config", "settings").await.unwrap();
+
+        assert_eq!(app1.get("settings").await.unwrap().unwrap().value, "one");
+        assert!(app1.get("config").await.unwrap().is_none());
+        assert_eq!(app2.get("config").await.unwrap().unwrap().value, "two");
+        assert_eq!(
+            store.get("app1/settings").await.unwrap(),
+            Some(b"one".to_vec())
+        );
+
+        app2.batch_delete(vec!["config"]).await.unwrap();
+        assert_eq!(store.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_list_within_scope() {
+        let (_, app1, app2) = shared_namespace();
+        for key in ["user:1", "user:2", "order:1"] {
+            app1.put(key, "x").await.unwrap();
+        }
+        app2.put("user:3", "x").await.unwrap();
+
+        let names = |response: ListResponse| -> Vec<String> {
+            response.keys.into_iter().map(|k| k.name).collect()
+        };
+        assert_eq!(
+            names(app1.list(None).await.unwrap()),
+            vec!["order:1", "user:1", "user:2"]
+        );
+        let users = app1
+            .list(Some(PaginationParams::new().with_prefix("user:")))
+            .await
+            .unwrap();
+        assert_eq!(names(users), vec!["user:1", "user:2"]);
+
+        // Pages continue within the scope
+        let first = app1
+            .list(Some(PaginationParams::new().with_limit(2)))
+            .await
+            .unwrap();
+        let rest = app1
+            .list(Some(
+                PaginationParams::new()
+                    .with_limit(2)
+                    .with_cursor(first.cursor.unwrap()),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(names(rest), vec!["user:2"]);
+    }
+
+    #[test]
+    fn test_nested_prefix() {
+        let scoped = ScopedKvClient::new(KvClient::in_memory(), "team/");
+        let nested = ScopedKvClient::new(scoped.into_client(), "app/");
+        assert_eq!(nested.prefix(), "team/app/");
+    }
+}
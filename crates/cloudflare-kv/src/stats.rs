@@ -0,0 +1,252 @@
+//! Namespace statistics
+//!
+//! `NamespaceAnalyzer` walks every key in a namespace and collects counts,
+//! expirations and (optionally) value sizes. Sizes need one GET per key, so
+//! they are fetched with bounded concurrency and can be skipped entirely.
+
+use crate::error::{KvError, Result};
+use crate::types::{KeyMetadata, PaginationParams};
+use crate::KvClient;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tracing::debug;
+
+const LIST_PAGE_SIZE: u32 = 1000;
+const DEFAULT_CONCURRENCY: usize = 8;
+
+/// Statistics for a namespace
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct NamespaceStats {
+    pub key_count: u64,
+    pub keys_with_expiration: u64,
+    pub keys_with_metadata: u64,
+    /// Size totals, present when value sizes were collected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sizes: Option<SizeStats>,
+    /// Key count per prefix
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub prefixes: BTreeMap<String, u64>,
+}
+
+/// Value size totals, in bytes as stored
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct SizeStats {
+    pub total: u64,
+    pub average: u64,
+    pub max: u64,
+    pub largest_key: Option<String>,
+}
+
+/// How keys are grouped for per-prefix counts
+#[derive(Clone, Debug, PartialEq)]
+pub enum PrefixGrouping {
+    /// No per-prefix counts
+    None,
+    /// Count keys starting with each of these prefixes (a key may match several)
+    Prefixes(Vec<String>),
+    /// Group by the part of the key up to and including the first delimiter;
+    /// keys without the delimiter are not grouped
+    Delimiter(char),
+}
+
+impl NamespaceStats {
+    /// Add one key to the statistics
+    pub fn record(&mut self, key: &KeyMetadata, size: Option<u64>, grouping: &PrefixGrouping) {
+        self.key_count += 1;
+        if key.expiration.is_some() {
+            self.keys_with_expiration += 1;
+        }
+        if key.metadata.is_some() {
+            self.keys_with_metadata += 1;
+        }
+
+        if let Some(size) = size {
+            let sizes = self.sizes.get_or_insert_with(SizeStats::default);
+            sizes.total += size;
+            if size > sizes.max || sizes.largest_key.is_none() {
+                sizes.max = size;
+                sizes.largest_key = Some(key.name.clone());
+            }
+        }
+
+        match grouping {
+            PrefixGrouping::None => {}
+            PrefixGrouping::Prefixes(prefixes) => {
+                for prefix in prefixes.iter().filter(|p| key.name.starts_with(p.as_str())) {
+                    *self.prefixes.entry(prefix.clone()).or_default() += 1;
+                }
+            }
+            PrefixGrouping::Delimiter(delimiter) => {
+                if let Some(end) = key.name.find(*delimiter) {
+                    let prefix = &key.name[..end + delimiter.len_utf8()];
+                    *self.prefixes.entry(prefix.to_string()).or_default() += 1;
+                }
+            }
+        }
+    }
+
+    /// Compute the average size once every key has been recorded
+    fn finish(&mut self, sized_keys: u64) {
+        if let Some(sizes) = self.sizes.as_mut() {
+            sizes.average = sizes.total.checked_div(sized_keys).unwrap_or_default();
+        }
+    }
+}
+
+/// Called once per analyzed key with its size, if sizes are collected
+type ProgressCallback<'a> = Box<dyn Fn(&KeyMetadata, Option<u64>) + Send + Sync + 'a>;
+
+/// Walks a namespace and collects `NamespaceStats`
+pub struct NamespaceAnalyzer<'a> {
+    client: &'a KvClient,
+    grouping: PrefixGrouping,
+    concurrency: usize,
+    include_sizes: bool,
+    progress: Option<ProgressCallback<'a>>,
+}
+
+impl<'a> NamespaceAnalyzer<'a> {
+    /// Create an analyzer that collects sizes with the default concurrency
+    pub fn new(client: &'a KvClient) -> Self {
+        Self {
+            client,
+            grouping: PrefixGrouping::None,
+            concurrency: DEFAULT_CONCURRENCY,
+            include_sizes: true,
+            progress: None,
+        }
+    }
+
+    /// Set how keys are grouped for per-prefix counts
+    pub fn with_grouping(mut self, grouping: PrefixGrouping) -> Self {
+        self.grouping = grouping;
+        self
+    }
+
+    /// Set how many values are fetched at once when collecting sizes
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Collect value sizes (one GET per key) or only list-based counts
+    pub fn with_sizes(mut self, include_sizes: bool) -> Self {
+        self.include_sizes = include_sizes;
+        self
+    }
+
+    /// Report each key as it is analyzed, e.g. to drive a progress bar
+    pub fn with_progress(
+        mut self,
+        progress: impl Fn(&KeyMetadata, Option<u64>) + Send + Sync + 'a,
+    ) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Walk the namespace and collect statistics
+    pub async fn analyze(&self) -> Result<NamespaceStats> {
+        let mut stats = NamespaceStats::default();
+        let mut sized_keys = 0;
+        let mut cursor = None;
+
+        loop {
+            let mut params = PaginationParams::new().with_limit(LIST_PAGE_SIZE);
+            if let Some(cursor) = cursor.take() {
+                params = params.with_cursor(cursor);
+            }
+            let page = self.client.list(Some(params)).await?;
+            debug!("Analyzing {} keys", page.keys.len());
+
+            // Sizes are reported as they arrive so progress does not stall per page
+            let sizes: Vec<Option<u64>> = if self.include_sizes {
+                stream::iter(page.keys.iter())
+                    .map(|key| async move {
+                        let size = self.client.value_size(&key.name).await?;
+                        self.report(key, size);
+                        Ok::<_, KvError>(size)
+                    })
+                    .buffered(self.concurrency)
+                    .try_collect()
+                    .await?
+            } else {
+                page.keys.iter().for_each(|key| self.report(key, None));
+                vec![None; page.keys.len()]
+            };
+
+            for (key, size) in page.keys.iter().zip(sizes) {
+                sized_keys += u64::from(size.is_some());
+                stats.record(key, size, &self.grouping);
+            }
+
+            match page.cursor {
+                Some(next) if !page.list_complete && !next.is_empty() => cursor = Some(next),
+                _ => break,
+            }
+        }
+
+        stats.finish(sized_keys);
+        Ok(stats)
+    }
+
+    fn report(&self, key: &KeyMetadata, size: Option<u64>) {
+        if let Some(progress) = &self.progress {
+            progress(key, size);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str, expiration: Option<u64>) -> KeyMetadata {
+        KeyMetadata {
+            name: name.to_string(),
+            expiration,
+            metadata: None,
+        }
+    }
+
+    #[test]
+    fn test_record_counts_and_sizes() {
+        let mut stats = NamespaceStats::default();
+        let grouping = PrefixGrouping::Delimiter(':');
+        stats.record(&key("post:a", None), Some(100), &grouping);
+        stats.record(&key("post:b", Some(1)), Some(300), &grouping);
+        stats.record(&key("session:x", Some(1)), Some(20), &grouping);
+        stats.record(&key("_blog_list", None), None, &grouping);
+        stats.finish(3);
+
+        assert_eq!(stats.key_count, 4);
+        assert_eq!(stats.keys_with_expiration, 2);
+        assert_eq!(stats.keys_with_metadata, 0);
+        assert_eq!(
+            stats.sizes,
+            Some(SizeStats {
+                total: 420,
+                average: 140,
+                max: 300,
+                largest_key: Some("post:b".to_string()),
+            })
+        );
+        assert_eq!(stats.prefixes["post:"], 2);
+        assert_eq!(stats.prefixes["session:"], 1);
+        assert_eq!(stats.prefixes.len(), 2);
+    }
+
+    #[test]
+    fn test_record_explicit_prefixes() {
+        let mut stats = NamespaceStats::default();
+        let grouping = PrefixGrouping::Prefixes(vec!["post:".into(), "p".into(), "x".into()]);
+        stats.record(&key("post:a", None), None, &grouping);
+        stats.record(&key("page", None), None, &grouping);
+        stats.finish(0);
+
+        assert_eq!(stats.sizes, None);
+        assert_eq!(stats.prefixes["post:"], 1);
+        assert_eq!(stats.prefixes["p"], 2);
+        assert!(!stats.prefixes.contains_key("x"));
+    }
+}
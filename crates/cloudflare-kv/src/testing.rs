@@ -0,0 +1,658 @@
+//! Fake Cloudflare API for integration tests
+//!
+//! `FakeCloudflareServer` (feature `test-util`) is an HTTP server that answers
+//! the KV endpoints `KvClient` uses: reading, writing and deleting values,
+//! listing keys with opaque pagination cursors, bulk writes and deletes, listing the
+//! accounts and the account's namespaces and verifying the token. Responses
+//! use the Cloudflare envelope, and the server can be told to answer the next
+//! requests with `429 Too Many Requests`, so code built on `KvClient` can be
+//! tested over real HTTP without an account:
+//!
+//! ```ignore
+//! let server = FakeCloudflareServer::start().await;
+//! server.insert("config:app", "{\"debug\":true}");
+//!
+//! let client = server.client();
+//! assert!(client.exists("config:app").await?);
+//!
+//! server.rate_limit_next(1);
+//! let error = client.get("config:app").await.unwrap_err();
+//! assert!(error.is_rate_limited());
+//! assert_eq!(error.retry_after(), Some(Duration::from_secs(1)));
+//! ```
+
+use crate::transport::{Expiration, InMemoryTransport};
+use crate::types::{AuthCredentials, ClientConfig, PaginationParams};
+use crate::KvClient;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use base64::Engine;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use wiremock::http::Method;
+use wiremock::matchers::any;
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+/// Account the fake server accepts
+pub const FAKE_ACCOUNT_ID: &str = "test-account";
+/// Namespace the fake server accepts
+pub const FAKE_NAMESPACE_ID: &str = "test-namespace";
+/// Name of the fake account in the account listing
+pub const FAKE_ACCOUNT_NAME: &str = "Test Account";
+
+/// State shared between the server handle and its responder
+#[derive(Default)]
+struct FakeState {
+    store: InMemoryTransport,
+    /// Requests still to be answered with 429
+    throttled: AtomicUsize,
+    revoked: AtomicBool,
+    requests: AtomicUsize,
+}
+
+/// In-process HTTP server emulating one Cloudflare KV namespace
+pub struct FakeCloudflareServer {
+    server: MockServer,
+    state: Arc<FakeState>,
+}
+
+impl FakeCloudflareServer {
+    /// Start a server with an empty namespace on a random local port
+    pub async fn start() -> Self {
+        let server = MockServer::start().await;
+        let state = Arc::new(FakeState::default());
+        Mock::given(any())
+            .respond_with(FakeKvApi {
+                state: state.clone(),
+                namespace_path: format!(
+                    "/accounts/{}/storage/kv/namespaces/{}",
+                    FAKE_ACCOUNT_ID, FAKE_NAMESPACE_ID
+                ),
+            })
+            .mount(&server)
+            .await;
+        Self { server, state }
+    }
+
+    /// Base URL of the server, to use as `ClientConfig::base_url`
+    pub fn uri(&self) -> String {
+        self.server.uri()
+    }
+
+    /// Configuration for the fake account and namespace
+    pub fn config(&self) -> ClientConfig {
+        let mut config = ClientConfig::new(
+            FAKE_ACCOUNT_ID,
+            FAKE_NAMESPACE_ID,
+            AuthCredentials::token("test-token"),
+        );
+        config.base_url = self.uri();
+        config
+    }
+
+    /// Client talking to the server
+    pub fn client(&self) -> KvClient {
+        KvClient::new(self.config())
+    }
+
+    /// Store a value directly, without a request
+    pub fn insert(&self, key: &str, value: impl AsRef<[u8]>) {
+        self.state.store.write(key, value.as_ref(), None, None);
+    }
+
+    /// Store a value with an expiration and metadata, without a request
+    pub fn insert_with(
+        &self,
+        key: &str,
+        value: impl AsRef<[u8]>,
+        expiration: Option<Expiration>,
+        metadata: Option<Value>,
+    ) {
+        self.state
+            .store
+            .write(key, value.as_ref(), expiration, metadata);
+    }
+
+    /// Current value of `key`, if it exists and has not expired
+    pub fn value(&self, key: &str) -> Option<Vec<u8>> {
+        self.state.store.read(key)
+    }
+
+    /// Every live key, in order
+    pub fn keys(&self) -> Vec<String> {
+        self.state.store.live_entries().into_keys().collect()
+    }
+
+    /// Answer the next `count` requests with `429 Too Many Requests`
+    pub fn rate_limit_next(&self, count: usize) {
+        self.state.throttled.store(count, Ordering::SeqCst);
+    }
+
+    /// Answer every later request with `401`, as if the token had been revoked
+    pub fn revoke_token(&self) {
+        self.state.revoked.store(true, Ordering::SeqCst);
+    }
+
+    /// Number of requests received so far, including rate limited ones
+    pub fn request_count(&self) -> usize {
+        self.state.requests.load(Ordering::SeqCst)
+    }
+}
+
+/// Responder implementing the KV endpoints of one namespace
+struct FakeKvApi {
+    state: Arc<FakeState>,
+    namespace_path: String,
+}
+
+impl Respond for FakeKvApi {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        self.state.requests.fetch_add(1, Ordering::SeqCst);
+
+        let throttled = self
+            .state
+            .throttled
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok();
+        if throttled {
+            return error_response(
+                429,
+                971,
+                "Please wait and consider throttling your request speed",
+            )
+            .insert_header("Retry-After", "1");
+        }
+
+        if self.state.revoked.load(Ordering::SeqCst) {
+            return error_response(401, 10000, "Authentication error");
+        }
+        if !request.headers.contains_key("authorization") {
+            return error_response(401, 10000, "Authentication error");
+        }
+
+        if request.method == Method::GET {
+            if let Some(response) = self.account_route(request.url.path()) {
+                return response;
+            }
+        }
+
+        let Some(route) = request.url.path().strip_prefix(&self.namespace_path) else {
+            return error_response(404, 10013, "namespace not found");
+        };
+        let store = &self.state.store;
+
+        match (&request.method, route) {
+            (&Method::GET, "/keys") => self.list(request),
+            (&Method::DELETE, "/bulk") => bulk_delete(store, &request.body),
+            // A bulk delete sent to the values endpoint would delete a key
+            // named "bulk" and ignore the body
+            (&Method::DELETE, "/values/bulk") if !request.body.is_empty() => {
+                error_response(400, 10020, "Bulk deletes go to /bulk")
+            }
+            (&Method::PUT, "/bulk") => bulk_write(store, &request.body),
+            (&Method::GET, route) if route.starts_with("/metadata/") => {
+                let key = percent_decode(&route["/metadata/".len()..]);
+                match store.read_stored(&key) {
+                    Some(stored) => success(stored.metadata.unwrap_or(Value::Null)),
+                    None => error_response(404, 10009, "metadata: 'key not found'"),
+                }
+            }
+            (method, route) => {
+                let Some(key) = route.strip_prefix("/values/").map(percent_decode) else {
+                    return error_response(404, 7003, "No route for that URI");
+                };
+                match *method {
+                    Method::GET => match store.read(&key) {
+                        Some(value) => ResponseTemplate::new(200).set_body_bytes(value),
+                        None => error_response(404, 10009, "get: 'key not found'"),
+                    },
+                    Method::PUT => put_value(store, &key, request),
+                    Method::DELETE => {
+                        store.remove(&key);
+                        success(Value::Null)
+                    }
+                    _ => error_response(405, 10405, "Method not allowed"),
+                }
+            }
+        }
+    }
+}
+
+impl FakeKvApi {
+    fn list(&self, request: &Request) -> ResponseTemplate {
+        let mut params = PaginationParams::new();
+        for (name, value) in request.url.query_pairs() {
+            match name.as_ref() {
+                "limit" => match value.parse() {
+                    Ok(limit) => params.limit = Some(limit),
+                    Err(_) => return error_response(400, 10025, "Invalid limit"),
+                },
+                "prefix" => params.prefix = Some(value.into_owned()),
+                // Cursors are opaque to clients, as in the real API
+                "cursor" if !value.is_empty() => match decode_cursor(&value) {
+                    Some(after) => params.cursor = Some(after),
+                    None => return error_response(400, 10026, "Invalid cursor"),
+                },
+                _ => {}
+            }
+        }
+
+        let page = self.state.store.page(params);
+        let cursor = page
+            .cursor
+            .map(|after| BASE64.encode(after))
+            .unwrap_or_default();
+        let count = page.keys.len();
+        let mut body = envelope(json!(page.keys));
+        body["result_info"] = json!({ "count": count, "cursor": cursor });
+        ResponseTemplate::new(200).set_body_json(body)
+    }
+
+    /// Token verification and namespace details, which are not under the
+    /// namespace's data endpoints
+    fn account_route(&self, path: &str) -> Option<ResponseTemplate> {
+        let namespace = json!({ "id": FAKE_NAMESPACE_ID, "title": FAKE_NAMESPACE_ID });
+        let account_path = format!("/accounts/{}", FAKE_ACCOUNT_ID);
+
+        if path == "/user/tokens/verify" || path == format!("{}/tokens/verify", account_path) {
+            Some(success(json!({ "id": "test-token", "status": "active" })))
+        } else if path == "/accounts" {
+            let account = json!({ "id": FAKE_ACCOUNT_ID, "name": FAKE_ACCOUNT_NAME });
+            let mut body = envelope(json!([account]));
+            body["result_info"] = json!({ "page": 1, "per_page": 50, "count": 1, "total_count": 1, "total_pages": 1 });
+            Some(ResponseTemplate::new(200).set_body_json(body))
+        } else if path == format!("{}/storage/kv/namespaces", account_path) {
+            let mut body = envelope(json!([namespace]));
+            body["result_info"] = json!({ "page": 1, "per_page": 100, "count": 1, "total_count": 1, "total_pages": 1 });
+            Some(ResponseTemplate::new(200).set_body_json(body))
+        } else if path == self.namespace_path {
+            Some(success(namespace))
+        } else {
+            None
+        }
+    }
+}
+
+fn put_value(store: &InMemoryTransport, key: &str, request: &Request) -> ResponseTemplate {
+    let mut expiration = None;
+    for (name, value) in request.url.query_pairs() {
+        let parsed = match name.as_ref() {
+            "expiration" => value.parse().map(Expiration::At),
+            "expiration_ttl" => value.parse().map(Expiration::Ttl),
+            _ => continue,
+        };
+        match parsed {
+            Ok(parsed) => expiration = Some(parsed),
+            Err(_) => return error_response(400, 10021, "Invalid expiration"),
+        }
+    }
+
+    let content_type = request
+        .headers
+        .get("content-type")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    // Values with metadata come as a form with `value` and `metadata` fields
+    let Some(boundary) = content_type
+        .strip_prefix("multipart/form-data; boundary=")
+        .map(|boundary| boundary.trim_matches('"'))
+    else {
+        store.write(key, &request.body, expiration, None);
+        return success(Value::Null);
+    };
+
+    let Some(mut fields) = form_fields(&request.body, boundary) else {
+        return error_response(400, 10022, "Invalid multipart body");
+    };
+    let Some(value) = fields.remove("value") else {
+        return error_response(400, 10022, "Missing value field");
+    };
+    let metadata = match fields.get("metadata") {
+        Some(field) => match serde_json::from_slice(field) {
+            Ok(metadata) => Some(metadata),
+            Err(_) => return error_response(400, 10022, "Invalid metadata"),
+        },
+        None => None,
+    };
+
+    store.write(key, &value, expiration, metadata);
+    success(Value::Null)
+}
+
+/// Fields of a `multipart/form-data` body by name
+fn form_fields(body: &[u8], boundary: &str) -> Option<HashMap<String, Vec<u8>>> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = split(body, delimiter.as_bytes());
+    // Nothing comes before the first delimiter, and the last one ends in `--`
+    if !parts.next()?.is_empty() {
+        return None;
+    }
+
+    let mut fields = HashMap::new();
+    for part in parts {
+        if part.starts_with(b"--") {
+            return Some(fields);
+        }
+        let part = part.strip_prefix(b"\r\n")?.strip_suffix(b"\r\n")?;
+        let header_end = part.windows(4).position(|w| w == b"\r\n\r\n")?;
+        let headers = std::str::from_utf8(&part[..header_end]).ok()?;
+        let name = headers
+            .split("\r\n")
+            .find_map(|line| line.strip_prefix("Content-Disposition: form-data; name=\""))?
+            .split('"')
+            .next()?;
+        fields.insert(name.to_string(), part[header_end + 4..].to_vec());
+    }
+    None
+}
+
+/// Pieces of `data` between occurrences of `delimiter`
+fn split<'a>(data: &'a [u8], delimiter: &'a [u8]) -> impl Iterator<Item = &'a [u8]> {
+    let mut rest = Some(data);
+    std::iter::from_fn(move || {
+        let data = rest?;
+        match data.windows(delimiter.len()).position(|w| w == delimiter) {
+            Some(at) => {
+                rest = Some(&data[at + delimiter.len()..]);
+                Some(&data[..at])
+            }
+            None => {
+                rest = None;
+                Some(data)
+            }
+        }
+    })
+}
+
+fn bulk_delete(store: &InMemoryTransport, body: &[u8]) -> ResponseTemplate {
+    // A plain array of key names
+    match serde_json::from_slice::<Vec<String>>(body) {
+        Ok(keys) => {
+            let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+            store.remove_many(&keys);
+            success(Value::Null)
+        }
+        Err(_) => error_response(400, 10020, "Invalid bulk delete body"),
+    }
+}
+
+/// An item of a bulk write body
+#[derive(serde::Deserialize)]
+struct BulkItem {
+    key: String,
+    value: String,
+    #[serde(default)]
+    base64: bool,
+    expiration: Option<u64>,
+    expiration_ttl: Option<u64>,
+    metadata: Option<Value>,
+}
+
+fn bulk_write(store: &InMemoryTransport, body: &[u8]) -> ResponseTemplate {
+    let Ok(items) = serde_json::from_slice::<Vec<BulkItem>>(body) else {
+        return error_response(400, 10020, "Invalid bulk write body");
+    };
+    for item in items {
+        let value = if item.base64 {
+            match base64::engine::general_purpose::STANDARD.decode(&item.value) {
+                Ok(value) => value,
+                Err(_) => return error_response(400, 10020, "Invalid base64 value"),
+            }
+        } else {
+            item.value.into_bytes()
+        };
+        let expiration = match (item.expiration, item.expiration_ttl) {
+            (Some(at), _) => Some(Expiration::At(at)),
+            (None, Some(ttl)) => Some(Expiration::Ttl(ttl)),
+            (None, None) => None,
+        };
+        store.write(&item.key, &value, expiration, item.metadata);
+    }
+    success(Value::Null)
+}
+
+fn envelope(result: Value) -> Value {
+    json!({ "success": true, "errors": [], "messages": [], "result": result })
+}
+
+fn success(result: Value) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(envelope(result))
+}
+
+fn error_response(status: u16, code: u32, message: &str) -> ResponseTemplate {
+    ResponseTemplate::new(status).set_body_json(json!({
+        "success": false,
+        "errors": [{ "code": code, "message": message }],
+        "messages": [],
+        "result": null
+    }))
+}
+
+fn decode_cursor(cursor: &str) -> Option<String> {
+    String::from_utf8(BASE64.decode(cursor).ok()?).ok()
+}
+
+/// Decode `%XX` escapes in a key taken from the URL path
+fn percent_decode(encoded: &str) -> String {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| encoded.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_values_over_http() {
+        let server = FakeCloudflareServer::start().await;
+        server.insert("seeded", "from the test");
+        let client = server.client();
+
+        let pair = client.get("seeded").await.unwrap().unwrap();
+        assert_eq!(pair.value, "from the test");
+        assert!(client.get("missing").await.unwrap().is_none());
+
+        client
+            .put_with_options("config", "{}", Some(3600), Some(json!({"owner": "ops"})))
+            .await
+            .unwrap();
+        assert_eq!(server.value("config"), Some(b"{}".to_vec()));
+        let listed = client.list(None).await.unwrap();
+        assert_eq!(listed.keys[0].metadata, Some(json!({"owner": "ops"})));
+        assert!(listed.keys[0].expiration.is_some());
+
+        client.delete("config").await.unwrap();
+        client.delete("config").await.unwrap();
+        assert_eq!(server.keys(), vec!["seeded".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_keys_needing_escapes() {
+        let server = FakeCloudflareServer::start().await;
+        let client = server.client();
+        let keys = [
+            "a/b",
+            "what?",
+            "#tag",
+            "with space",
+            "100%",
+            "ключ:é",
+            "🚀",
+            "a+b&c=d",
+        ];
+
+        for key in keys {
+            client.put(key, key).await.unwrap();
+            assert_eq!(client.get(key).await.unwrap().unwrap().value, key);
+        }
+        let mut expected: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+        expected.sort();
+        assert_eq!(server.keys(), expected);
+
+        for key in keys {
+            client.delete(key).await.unwrap();
+        }
+        assert!(server.keys().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pagination_and_bulk_delete() {
+        let server = FakeCloudflareServer::start().await;
+        for i in 0..25 {
+            server.insert(&format!("item:{:02}", i), "x");
+        }
+        server.insert("other", "x");
+        let client = server.client();
+
+        let first = client
+            .list(Some(
+                PaginationParams::new().with_prefix("item:").with_limit(10),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(first.keys.len(), 10);
+        assert!(!first.list_complete);
+        // The cursor does not give away the last key
+        assert_ne!(first.cursor.as_deref(), Some("item:09"));
+
+        let mut all = first.keys;
+        let mut cursor = first.cursor;
+        while let Some(after) = cursor {
+            let page = client
+                .list(Some(
+                    PaginationParams::new()
+                        .with_prefix("item:")
+                        .with_limit(10)
+                        .with_cursor(after),
+                ))
+                .await
+                .unwrap();
+            all.extend(page.keys);
+            cursor = page.cursor;
+        }
+        assert_eq!(all.len(), 25);
+        assert_eq!(all[24].name, "item:24");
+
+        let keys: Vec<&str> = all.iter().map(|k| k.name.as_str()).collect();
+        client.batch_delete(keys).await.unwrap();
+        assert_eq!(server.keys(), vec!["other".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_bulk_write() {
+        let server = FakeCloudflareServer::start().await;
+        let client = server.client();
+        let mut flag = crate::BulkWrite::new("flag:beta", "on");
+        flag.expiration = Some(Expiration::Ttl(3600));
+        flag.metadata = Some(json!({"owner": "ops"}));
+        let writes = [
+            flag,
+            crate::BulkWrite::new("flag:dark-mode", "off"),
+            crate::BulkWrite::new("blob", [0xff, 0x00]),
+        ];
+        client.put_bulk(&writes).await.unwrap();
+
+        assert_eq!(server.value("flag:beta"), Some(b"on".to_vec()));
+        assert_eq!(server.value("blob"), Some(vec![0xff, 0x00]));
+        let listed = client
+            .list(Some(PaginationParams::new().with_prefix("flag:beta")))
+            .await
+            .unwrap();
+        assert_eq!(listed.keys[0].metadata, Some(json!({"owner": "ops"})));
+        assert!(listed.keys[0].expiration.is_some());
+
+        let scoped = crate::ScopedKvClient::new(server.client(), "app:").into_client();
+        scoped
+            .put_bulk(&[crate::BulkWrite::new("key", "value")])
+            .await
+            .unwrap();
+        assert_eq!(server.value("app:key"), Some(b"value".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_requests() {
+        let server = FakeCloudflareServer::start().await;
+        server.insert("key", "value");
+        let client = server.client();
+
+        server.rate_limit_next(2);
+        for _ in 0..2 {
+            let error = client.get("key").await.unwrap_err();
+            assert!(error.is_rate_limited());
+            assert_eq!(error.retry_after(), Some(Duration::from_secs(1)));
+        }
+        assert!(client.get("key").await.unwrap().is_some());
+        assert_eq!(server.request_count(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_list_namespaces() {
+        let server = FakeCloudflareServer::start().await;
+        let namespaces = server.client().list_all_namespaces().await.unwrap();
+        assert_eq!(namespaces.len(), 1);
+        assert_eq!(namespaces[0].id, FAKE_NAMESPACE_ID);
+
+        let accounts = server.client().list_accounts().await.unwrap();
+        assert_eq!(
+            accounts,
+            [crate::types::Account {
+                id: FAKE_ACCOUNT_ID.to_string(),
+                name: FAKE_ACCOUNT_NAME.to_string(),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_token_and_namespace() {
+        let server = FakeCloudflareServer::start().await;
+        let client = server.client();
+        client.verify_token().await.unwrap();
+        assert_eq!(
+            client.namespace().await.unwrap().unwrap().id,
+            FAKE_NAMESPACE_ID
+        );
+
+        let mut config = server.config();
+        config.namespace_id = "missing".to_string();
+        assert!(KvClient::new(config).namespace().await.unwrap().is_none());
+
+        // A scoped client still asks the API rather than assuming success
+        let mut config = server.config();
+        config.namespace_id = "missing".to_string();
+        let scoped = crate::ScopedKvClient::new(KvClient::new(config), "app:").into_client();
+        assert!(scoped.namespace().await.unwrap().is_none());
+        let scoped = crate::ScopedKvClient::new(server.client(), "app:").into_client();
+        assert_eq!(scoped.list_all_namespaces().await.unwrap().len(), 1);
+
+        server.revoke_token();
+        assert!(client.verify_token().await.unwrap_err().is_auth_error());
+        assert!(scoped.verify_token().await.unwrap_err().is_auth_error());
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("a%2Fb%20c"), "a/b c");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz"), "%zz");
+    }
+}
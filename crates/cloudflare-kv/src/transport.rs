@@ -0,0 +1,1563 @@
+//! Transports carry `KvClient` operations to a KV store
+//!
+//! `KvClient` builds every operation (copy, append, conditional writes, ...)
+//! on the five primitives of `KvTransport`; `put_bulk` defaults to a `put`
+//! per value for transports without a bulk API. `HttpTransport` talks to the
+//! Cloudflare API and is what `KvClient::new` uses; `InMemoryTransport` keeps
+//! values in a map so code built on `KvClient` can be tested without an
+//! account or a mock server:
+//!
+//! ```ignore
+//! let client = KvClient::in_memory();
+//! client.put("greeting", "hello").await?;
+//! assert_eq!(client.get("greeting").await?.unwrap().value, "hello");
+//! ```
+
+use crate::error::{ErrorResponse, KvError, Result};
+use crate::http_client::{ApiRequest, ApiResponse, Engine};
+use crate::rate_limit::sleep;
+use crate::types::{value_hash, ResultInfo};
+use crate::types::{Account, ClientConfig, KeyMetadata, ListResponse, Namespace, PaginationParams};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use http::{Method, StatusCode};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::debug;
+use web_time::{SystemTime, UNIX_EPOCH};
+
+/// Bytes escaped in keys put in URLs: everything but unreserved characters
+const KEY_ESCAPES: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Most keys returned by one list call, as in the Cloudflare API
+const MAX_LIST_LIMIT: usize = 1000;
+
+/// Namespaces requested per page when listing all of them
+pub(crate) const NAMESPACES_PER_PAGE: u32 = 100;
+
+/// Most accounts returned by one page of the account listing
+const ACCOUNTS_PER_PAGE: u32 = 50;
+
+/// When a written value expires
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Expiration {
+    /// Seconds from now
+    Ttl(u64),
+    /// Absolute Unix timestamp
+    At(u64),
+}
+
+/// One value of a bulk write
+#[derive(Clone, Debug, PartialEq)]
+pub struct BulkWrite {
+    pub key: String,
+    pub value: Vec<u8>,
+    pub expiration: Option<Expiration>,
+    pub metadata: Option<Value>,
+    /// Send the value base64-encoded even when it is UTF-8; values that
+    /// are not UTF-8 always are
+    pub base64: bool,
+}
+
+impl BulkWrite {
+    /// A value with no expiration or metadata
+    pub fn new(key: impl Into<String>, value: impl AsRef<[u8]>) -> Self {
+        Self {
+            key: key.into(),
+            value: value.as_ref().to_vec(),
+            expiration: None,
+            metadata: None,
+            base64: false,
+        }
+    }
+
+    /// The item for this write in a Cloudflare bulk write body
+    pub fn to_item(&self) -> Value {
+        let mut item = json!({ "key": self.key });
+        match std::str::from_utf8(&self.value) {
+            Ok(text) if !self.base64 => item["value"] = json!(text),
+            _ => {
+                item["value"] = json!(BASE64.encode(&self.value));
+                item["base64"] = json!(true);
+            }
+        }
+        match self.expiration {
+            Some(Expiration::Ttl(ttl)) => item["expiration_ttl"] = json!(ttl),
+            Some(Expiration::At(at)) => item["expiration"] = json!(at),
+            None => {}
+        }
+        if let Some(metadata) = &self.metadata {
+            item["metadata"] = metadata.clone();
+        }
+        item
+    }
+}
+
+/// Outcome of a read made with the ETag of a copy the caller already has
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConditionalGet {
+    /// The value, with its ETag when the store sends one
+    Modified {
+        value: Vec<u8>,
+        etag: Option<String>,
+    },
+    /// The value still has the ETag given, so it was not sent again
+    NotModified,
+    /// The key does not exist
+    NotFound,
+}
+
+/// The storage operations `KvClient` is built on
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait KvTransport: Send + Sync {
+    /// A value exactly as stored, or `None` if the key does not exist
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Write a value, replacing any existing value, expiration and metadata
+    async fn put(
+        &self,
+        key: &str,
+        value: &[u8],
+        expiration: Option<Expiration>,
+        metadata: Option<Value>,
+    ) -> Result<()>;
+
+    /// A value unless it still has the ETag `etag` (`If-None-Match`)
+    ///
+    /// Transports without validators always return the value, with no ETag.
+    async fn get_if_none_match(&self, key: &str, etag: Option<&str>) -> Result<ConditionalGet> {
+        let _ = etag;
+        Ok(match self.get(key).await? {
+            Some(value) => ConditionalGet::Modified { value, etag: None },
+            None => ConditionalGet::NotFound,
+        })
+    }
+
+    /// Delete a key; deleting a missing key succeeds
+    async fn delete(&self, key: &str) -> Result<()>;
+
+    /// One page of keys in lexicographic order
+    async fn list(&self, params: PaginationParams) -> Result<ListResponse>;
+
+    /// Delete several keys at once
+    async fn delete_bulk(&self, keys: &[&str]) -> Result<()>;
+
+    /// A key's metadata, or `None` if the key does not exist or has none
+    ///
+    /// Transports without a metadata lookup find the key with `list`.
+    async fn metadata(&self, key: &str) -> Result<Option<Value>> {
+        let params = PaginationParams::new().with_prefix(key).with_limit(10);
+        Ok(self
+            .list(params)
+            .await?
+            .keys
+            .into_iter()
+            .find(|k| k.name == key)
+            .and_then(|k| k.metadata))
+    }
+
+    /// Write several values at once, one `put` at a time unless the
+    /// transport has a bulk API
+    async fn put_bulk(&self, writes: &[BulkWrite]) -> Result<()> {
+        for write in writes {
+            self.put(
+                &write.key,
+                &write.value,
+                write.expiration,
+                write.metadata.clone(),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Transport for the Cloudflare REST API
+pub struct HttpTransport {
+    engine: Engine,
+    config: ClientConfig,
+}
+
+impl HttpTransport {
+    /// Create a transport for the account and namespace in `config`
+    ///
+    /// Requests are sent with reqwest, or with hyper when the library is
+    /// built with only the `hyper` feature.
+    pub fn new(config: ClientConfig) -> Self {
+        Self {
+            engine: Engine::new(&config.connection),
+            config,
+        }
+    }
+
+    /// Create a transport sending its requests with hyper (feature `hyper`)
+    ///
+    /// Request hooks only run with reqwest and are ignored here.
+    #[cfg(feature = "hyper")]
+    pub fn with_hyper(config: ClientConfig) -> Self {
+        Self {
+            engine: Engine::hyper(&config.connection),
+            config,
+        }
+    }
+
+    /// URL of the value of `key`, with the key percent-encoded as one
+    /// path segment
+    fn value_url(&self, key: &str) -> Result<String> {
+        self.key_url(&self.config.kv_endpoint(), key)
+    }
+
+    /// URL of the metadata of `key`
+    fn metadata_url(&self, key: &str) -> Result<String> {
+        let endpoint = format!("{}/metadata", self.config.namespace_endpoint());
+        self.key_url(&endpoint, key)
+    }
+
+    fn key_url(&self, endpoint: &str, key: &str) -> Result<String> {
+        if key == "." || key == ".." {
+            return Err(KvError::InvalidConfig(format!(
+                "Key '{}' cannot be used in a URL",
+                key
+            )));
+        }
+        let encoded = utf8_percent_encode(key, KEY_ESCAPES).to_string();
+        if !self.config.url_encoding && encoded != key {
+            return Err(KvError::InvalidConfig(format!(
+                "Key '{}' needs URL encoding, which namespace {} does not support",
+                key, self.config.namespace_id
+            )));
+        }
+        Ok(format!("{}/{}", endpoint, encoded))
+    }
+
+    /// A request carrying the credentials, the configured headers and
+    /// timeout, and the request hooks
+    fn request(&self, method: Method, url: impl Into<String>) -> ApiRequest {
+        let mut request = ApiRequest {
+            timeout: self.config.timeout,
+            ..ApiRequest::new(method, url, self.config.credentials.auth_header())
+        };
+        request.headers.extend(self.config.headers.iter().cloned());
+        #[cfg(feature = "reqwest")]
+        let request = ApiRequest {
+            hooks: self.config.request_hooks.clone(),
+            ..request
+        };
+        request
+    }
+
+    /// Send a request, retrying it as the configured `RetryPolicy` says
+    async fn send(&self, request: ApiRequest) -> Result<ApiResponse> {
+        let policy = &self.config.retry;
+        // Sending a POST again could create a second namespace
+        let retries = if request.method == Method::POST {
+            0
+        } else {
+            policy.max_retries
+        };
+        let mut attempt = 0;
+        loop {
+            if attempt >= retries {
+                return self.engine.send(request).await;
+            }
+            let result = self.engine.send(request.clone()).await;
+            let wait = match &result {
+                Ok(response)
+                    if response.status == StatusCode::TOO_MANY_REQUESTS
+                        || response.status.is_server_error() =>
+                {
+                    retry_after(response).unwrap_or_else(|| policy.backoff(attempt))
+                }
+                Err(KvError::NetworkError(_)) => policy.backoff(attempt),
+                #[cfg(feature = "reqwest")]
+                Err(KvError::HttpError(e)) if !e.is_builder() => policy.backoff(attempt),
+                _ => return result,
+            };
+            attempt += 1;
+            debug!(
+                url = %request.url,
+                attempt,
+                wait_ms = wait.as_millis() as u64,
+                "Retrying request"
+            );
+            sleep(wait).await;
+        }
+    }
+
+    /// Open a connection to the API ahead of the first request
+    ///
+    /// Any response counts as success; only failing to connect is an error.
+    pub async fn warm_up(&self) -> Result<()> {
+        let request = self.request(Method::GET, self.config.namespace_endpoint());
+        self.send(request).await.map(|_| ())
+    }
+
+    /// Check the API token is valid and active
+    ///
+    /// User tokens are verified with `/user/tokens/verify`; tokens owned by
+    /// the account are rejected there and verified with the account's endpoint.
+    pub async fn verify_token(&self) -> Result<()> {
+        let user_endpoint = format!("{}/user/tokens/verify", self.config.base_url);
+        let account_endpoint = format!(
+            "{}/accounts/{}/tokens/verify",
+            self.config.base_url, self.config.account_id
+        );
+
+        let mut result = self.verify_token_at(&user_endpoint).await;
+        if matches!(&result, Err(e) if e.is_auth_error()) {
+            result = self.verify_token_at(&account_endpoint).await;
+        }
+        result
+    }
+
+    async fn verify_token_at(&self, url: &str) -> Result<()> {
+        let response = self.send(self.request(Method::GET, url)).await?;
+
+        match response.status {
+            StatusCode::OK => {
+                let body = response.envelope::<TokenStatus>("token verification")?;
+                match body.result.and_then(|result| result.status).as_deref() {
+                    Some("active") | None => Ok(()),
+                    Some(status) => Err(KvError::AuthError(format!("Token is {}", status))),
+                }
+            }
+            // Unknown tokens get 400 rather than 401 on the verify endpoints
+            status if status.is_client_error() && status != StatusCode::TOO_MANY_REQUESTS => {
+                Err(KvError::AuthError(format!(
+                    "Token verification failed: {} - {}",
+                    status,
+                    response.text()
+                )))
+            }
+            status => Err(status_error(
+                &response,
+                format!("Failed to verify token: {} - {}", status, response.text()),
+            )),
+        }
+    }
+
+    /// Details of the configured namespace, or `None` if it does not exist
+    pub async fn namespace(&self) -> Result<Option<Namespace>> {
+        let request = self.request(Method::GET, self.config.namespace_endpoint());
+        let response = self.send(request).await?;
+
+        match response.status {
+            StatusCode::OK => Ok(Some(
+                response
+                    .envelope::<Namespace>("namespace")?
+                    .into_result("namespace")?,
+            )),
+            StatusCode::NOT_FOUND => Ok(None),
+            status => Err(status_error(
+                &response,
+                format!("Failed to get namespace: {} - {}", status, response.text()),
+            )),
+        }
+    }
+
+    /// One page (from 1) of the account's namespaces, and whether more follow
+    pub async fn list_namespaces(
+        &self,
+        page: u32,
+        per_page: u32,
+    ) -> Result<(Vec<Namespace>, bool)> {
+        let request = self
+            .request(Method::GET, self.config.namespaces_endpoint())
+            .query("page", page.to_string())
+            .query("per_page", per_page.to_string());
+        let response = self.send(request).await?;
+
+        match response.status {
+            StatusCode::OK => {
+                let body = response.envelope::<Vec<Namespace>>("namespace list")?;
+                let total_pages = body.result_info.as_ref().and_then(|info| info.total_pages);
+                let namespaces = body.into_result("namespace list")?;
+                let more = match total_pages {
+                    Some(total_pages) => u64::from(page) < total_pages,
+                    None => namespaces.len() == per_page as usize,
+                };
+                Ok((namespaces, more))
+            }
+            status => Err(status_error(
+                &response,
+                format!(
+                    "Failed to list namespaces: {} - {}",
+                    status,
+                    response.text()
+                ),
+            )),
+        }
+    }
+
+    /// One page (from 1) of the accounts the token can access, and whether
+    /// more follow
+    pub async fn list_accounts(&self, page: u32) -> Result<(Vec<Account>, bool)> {
+        let request = self
+            .request(Method::GET, self.config.accounts_endpoint())
+            .query("page", page.to_string())
+            .query("per_page", ACCOUNTS_PER_PAGE.to_string());
+        let response = self.send(request).await?;
+
+        match response.status {
+            StatusCode::OK => {
+                let body = response.envelope::<Vec<Account>>("account list")?;
+                let total_pages = body.result_info.as_ref().and_then(|info| info.total_pages);
+                let accounts = body.into_result("account list")?;
+                let more = match total_pages {
+                    Some(total_pages) => u64::from(page) < total_pages,
+                    None => accounts.len() == ACCOUNTS_PER_PAGE as usize,
+                };
+                Ok((accounts, more))
+            }
+            status => Err(status_error(
+                &response,
+                format!("Failed to list accounts: {} - {}", status, response.text()),
+            )),
+        }
+    }
+
+    /// Create a namespace in the configured account
+    pub async fn create_namespace(&self, title: &str) -> Result<Namespace> {
+        let request = self
+            .request(Method::POST, self.config.namespaces_endpoint())
+            .json(&json!({ "title": title }));
+        let response = self.send(request).await?;
+
+        match response.status {
+            StatusCode::OK | StatusCode::CREATED => response
+                .envelope::<Namespace>("namespace")?
+                .into_result("namespace"),
+            status => Err(status_error(
+                &response,
+                format!(
+                    "Failed to create namespace {}: {} - {}",
+                    title,
+                    status,
+                    response.text()
+                ),
+            )),
+        }
+    }
+
+    /// Delete a namespace of the configured account with all its keys
+    pub async fn delete_namespace(&self, id: &str) -> Result<()> {
+        let url = format!("{}/{}", self.config.namespaces_endpoint(), id);
+        let response = self.send(self.request(Method::DELETE, url)).await?;
+
+        match response.status {
+            StatusCode::OK => Ok(()),
+            status => Err(status_error(
+                &response,
+                format!(
+                    "Failed to delete namespace {}: {} - {}",
+                    id,
+                    status,
+                    response.text()
+                ),
+            )),
+        }
+    }
+
+    /// Change the title of a namespace of the configured account
+    pub async fn rename_namespace(&self, id: &str, title: &str) -> Result<()> {
+        let url = format!("{}/{}", self.config.namespaces_endpoint(), id);
+        let request = self
+            .request(Method::PUT, url)
+            .json(&json!({ "title": title }));
+        let response = self.send(request).await?;
+
+        match response.status {
+            StatusCode::OK => Ok(()),
+            status => Err(status_error(
+                &response,
+                format!(
+                    "Failed to rename namespace {}: {} - {}",
+                    id,
+                    status,
+                    response.text()
+                ),
+            )),
+        }
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl KvTransport for HttpTransport {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let request = self.request(Method::GET, self.value_url(key)?);
+        let response = self.send(request).await?;
+
+        match response.status {
+            StatusCode::OK => Ok(Some(response.body)),
+            StatusCode::NOT_FOUND => Ok(None),
+            status => Err(status_error(
+                &response,
+                format!(
+                    "Failed to get key {}: {} - {}",
+                    key,
+                    status,
+                    response.text()
+                ),
+            )),
+        }
+    }
+
+    async fn get_if_none_match(&self, key: &str, etag: Option<&str>) -> Result<ConditionalGet> {
+        let mut request = self.request(Method::GET, self.value_url(key)?);
+        if let Some(etag) = etag {
+            request = request.header("If-None-Match", etag);
+        }
+        let response = self.send(request).await?;
+
+        match response.status {
+            StatusCode::OK => Ok(ConditionalGet::Modified {
+                etag: response
+                    .headers
+                    .get("etag")
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string),
+                value: response.body,
+            }),
+            StatusCode::NOT_MODIFIED => Ok(ConditionalGet::NotModified),
+            StatusCode::NOT_FOUND => Ok(ConditionalGet::NotFound),
+            status => Err(status_error(
+                &response,
+                format!(
+                    "Failed to get key {}: {} - {}",
+                    key,
+                    status,
+                    response.text()
+                ),
+            )),
+        }
+    }
+
+    async fn put(
+        &self,
+        key: &str,
+        value: &[u8],
+        expiration: Option<Expiration>,
+        metadata: Option<Value>,
+    ) -> Result<()> {
+        let mut request = self.request(Method::PUT, self.value_url(key)?);
+
+        // Add optional query parameters
+        match expiration {
+            Some(Expiration::Ttl(ttl)) => {
+                request = request.query("expiration_ttl", ttl.to_string());
+            }
+            Some(Expiration::At(at)) => {
+                request = request.query("expiration", at.to_string());
+            }
+            None => {}
+        }
+
+        // Metadata is only accepted as a form field next to the value
+        let request = match metadata {
+            Some(meta) => {
+                request.multipart(&[("value", value), ("metadata", meta.to_string().as_bytes())])
+            }
+            None => request.body(value.to_vec()),
+        };
+
+        let response = self.send(request).await?;
+
+        match response.status {
+            StatusCode::OK => Ok(()),
+            status => Err(status_error(
+                &response,
+                format!(
+                    "Failed to put key {}: {} - {}",
+                    key,
+                    status,
+                    response.text()
+                ),
+            )),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let request = self.request(Method::DELETE, self.value_url(key)?);
+        let response = self.send(request).await?;
+
+        match response.status {
+            StatusCode::OK | StatusCode::NOT_FOUND => Ok(()),
+            status => Err(status_error(
+                &response,
+                format!(
+                    "Failed to delete key {}: {} - {}",
+                    key,
+                    status,
+                    response.text()
+                ),
+            )),
+        }
+    }
+
+    async fn list(&self, params: PaginationParams) -> Result<ListResponse> {
+        let mut request = self.request(Method::GET, self.config.kv_list_endpoint());
+
+        if let Some(limit) = params.limit {
+            request = request.query("limit", limit.to_string());
+        }
+        if let Some(cursor) = params.cursor {
+            request = request.query("cursor", cursor);
+        }
+        if let Some(prefix) = params.prefix {
+            request = request.query("prefix", prefix);
+        }
+
+        let response = self.send(request).await?;
+
+        match response.status {
+            StatusCode::OK => {
+                let body = response.envelope::<ListResult>("list")?;
+                let result_info = body.result_info.clone();
+                Ok(body.into_result("list")?.into_response(result_info))
+            }
+            status => Err(status_error(
+                &response,
+                format!("Failed to list keys: {} - {}", status, response.text()),
+            )),
+        }
+    }
+
+    async fn delete_bulk(&self, keys: &[&str]) -> Result<()> {
+        let url = format!("{}/bulk", self.config.namespace_endpoint());
+        let body = json!(keys);
+
+        let request = self.request(Method::DELETE, url).json(&body);
+        let response = self.send(request).await?;
+
+        match response.status {
+            StatusCode::OK => Ok(()),
+            status => Err(status_error(
+                &response,
+                format!("Failed to batch delete: {} - {}", status, response.text()),
+            )),
+        }
+    }
+
+    /// Read with the metadata endpoint, which unlike `list` sees metadata
+    /// written moments ago
+    async fn metadata(&self, key: &str) -> Result<Option<Value>> {
+        let request = self.request(Method::GET, self.metadata_url(key)?);
+        let response = self.send(request).await?;
+
+        match response.status {
+            StatusCode::OK => Ok(response
+                .envelope::<Value>("metadata")?
+                .result
+                .filter(|metadata| !metadata.is_null())),
+            StatusCode::NOT_FOUND => Ok(None),
+            status => Err(status_error(
+                &response,
+                format!(
+                    "Failed to get metadata of key {}: {} - {}",
+                    key,
+                    status,
+                    response.text()
+                ),
+            )),
+        }
+    }
+
+    async fn put_bulk(&self, writes: &[BulkWrite]) -> Result<()> {
+        let url = format!("{}/bulk", self.config.namespace_endpoint());
+        let body = Value::Array(writes.iter().map(BulkWrite::to_item).collect());
+
+        let request = self.request(Method::PUT, url).json(&body);
+        let response = self.send(request).await?;
+
+        match response.status {
+            StatusCode::OK => Ok(()),
+            status => Err(status_error(
+                &response,
+                format!("Failed to bulk write: {} - {}", status, response.text()),
+            )),
+        }
+    }
+}
+
+/// The `result` of a list response
+///
+/// The Cloudflare API returns the keys as an array with the cursor in
+/// `result_info` (empty on the last page); an object holding `keys`,
+/// `list_complete` and `cursor` is accepted as well. Either way an empty
+/// cursor means there are no more pages.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ListResult {
+    Keys(Vec<KeyMetadata>),
+    Page {
+        keys: Vec<KeyMetadata>,
+        #[serde(default)]
+        list_complete: bool,
+        #[serde(default)]
+        cursor: Option<String>,
+    },
+}
+
+impl ListResult {
+    fn into_response(self, result_info: Option<ResultInfo>) -> ListResponse {
+        match self {
+            ListResult::Keys(keys) => {
+                let cursor = result_info
+                    .as_ref()
+                    .and_then(|info| info.cursor.clone())
+                    .filter(|cursor| !cursor.is_empty());
+                ListResponse {
+                    keys,
+                    list_complete: cursor.is_none(),
+                    cursor,
+                    result_info,
+                }
+            }
+            ListResult::Page {
+                keys,
+                list_complete,
+                cursor,
+            } => {
+                let cursor = cursor.filter(|cursor| !cursor.is_empty());
+                ListResponse {
+                    keys,
+                    list_complete: list_complete || cursor.is_none(),
+                    cursor,
+                    result_info,
+                }
+            }
+        }
+    }
+}
+
+/// The `result` of a token verification
+#[derive(Deserialize)]
+struct TokenStatus {
+    status: Option<String>,
+}
+
+/// Error for an unexpected response status
+///
+/// The error keeps the status, body and rate limit headers, so callers can
+/// check `is_auth_error`, `is_rate_limited` and `retry_after` instead of
+/// parsing the message.
+pub(crate) fn status_error(response: &ApiResponse, message: String) -> KvError {
+    let headers = response
+        .headers
+        .iter()
+        .filter(|(name, _)| ErrorResponse::keeps_header(name.as_str()))
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+    KvError::Http(Box::new(ErrorResponse {
+        status: response.status.as_u16(),
+        headers,
+        body: response.text(),
+        message,
+    }))
+}
+
+/// How long a response's `Retry-After` header asks to wait
+fn retry_after(response: &ApiResponse) -> Option<Duration> {
+    response
+        .headers
+        .get("retry-after")?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct StoredValue {
+    pub(crate) value: Vec<u8>,
+    pub(crate) expiration: Option<u64>,
+    pub(crate) metadata: Option<Value>,
+}
+
+/// Transport keeping values in memory, for tests
+///
+/// Behaves like a namespace with immediate consistency: expired keys
+/// disappear, listing is ordered and paginated, and the cursor is the last
+/// key of the previous page.
+#[derive(Default)]
+pub struct InMemoryTransport {
+    entries: Mutex<BTreeMap<String, StoredValue>>,
+}
+
+impl InMemoryTransport {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a store holding `entries`
+    pub(crate) fn from_entries(entries: BTreeMap<String, StoredValue>) -> Self {
+        Self {
+            entries: Mutex::new(entries),
+        }
+    }
+
+    /// Every key that has not expired, with its value
+    pub(crate) fn live_entries(&self) -> BTreeMap<String, StoredValue> {
+        let now = unix_now();
+        self.entries()
+            .iter()
+            .filter(|(_, stored)| !is_expired(stored, now))
+            .map(|(name, stored)| (name.clone(), stored.clone()))
+            .collect()
+    }
+
+    /// Number of keys that have not expired
+    pub fn len(&self) -> usize {
+        let now = unix_now();
+        self.entries()
+            .values()
+            .filter(|stored| !is_expired(stored, now))
+            .count()
+    }
+
+    /// Whether the store holds no live keys
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, StoredValue>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Synchronous forms of the transport operations, also used by the fake
+    // Cloudflare server, which answers requests without an async context
+
+    pub(crate) fn read(&self, key: &str) -> Option<Vec<u8>> {
+        self.read_stored(key).map(|stored| stored.value)
+    }
+
+    pub(crate) fn read_stored(&self, key: &str) -> Option<StoredValue> {
+        let now = unix_now();
+        self.entries()
+            .get(key)
+            .filter(|stored| !is_expired(stored, now))
+            .cloned()
+    }
+
+    pub(crate) fn write(
+        &self,
+        key: &str,
+        value: &[u8],
+        expiration: Option<Expiration>,
+        metadata: Option<Value>,
+    ) {
+        let expiration = expiration.map(|expiration| match expiration {
+            Expiration::Ttl(ttl) => unix_now() + ttl,
+            Expiration::At(at) => at,
+        });
+        self.entries().insert(
+            key.to_string(),
+            StoredValue {
+                value: value.to_vec(),
+                expiration,
+                metadata,
+            },
+        );
+    }
+
+    pub(crate) fn remove(&self, key: &str) {
+        self.entries().remove(key);
+    }
+
+    pub(crate) fn page(&self, params: PaginationParams) -> ListResponse {
+        let now = unix_now();
+        let limit = params
+            .limit
+            .map(|limit| (limit as usize).clamp(1, MAX_LIST_LIMIT))
+            .unwrap_or(MAX_LIST_LIMIT);
+        let prefix = params.prefix.unwrap_or_default();
+
+        let entries = self.entries();
+        let mut matching = entries
+            .iter()
+            .filter(|(name, _)| params.cursor.as_ref().is_none_or(|after| *name > after))
+            .filter(|(name, _)| name.starts_with(&prefix))
+            .filter(|(_, stored)| !is_expired(stored, now));
+
+        let keys: Vec<KeyMetadata> = matching
+            .by_ref()
+            .take(limit)
+            .map(|(name, stored)| KeyMetadata {
+                name: name.clone(),
+                expiration: stored.expiration,
+                metadata: stored.metadata.clone(),
+            })
+            .collect();
+        let list_complete = matching.next().is_none();
+        let cursor = if list_complete {
+            None
+        } else {
+            keys.last().map(|key| key.name.clone())
+        };
+
+        ListResponse {
+            keys,
+            list_complete,
+            cursor,
+            result_info: None,
+        }
+    }
+
+    pub(crate) fn remove_many(&self, keys: &[&str]) {
+        let mut entries = self.entries();
+        for key in keys {
+            entries.remove(*key);
+        }
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl KvTransport for InMemoryTransport {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.read(key))
+    }
+
+    /// ETags are the values' SHA-256, as an HTTP store might send
+    async fn get_if_none_match(&self, key: &str, etag: Option<&str>) -> Result<ConditionalGet> {
+        let Some(value) = self.read(key) else {
+            return Ok(ConditionalGet::NotFound);
+        };
+        let current = format!("\"{}\"", value_hash(&value));
+        if etag == Some(current.as_str()) {
+            return Ok(ConditionalGet::NotModified);
+        }
+        Ok(ConditionalGet::Modified {
+            value,
+            etag: Some(current),
+        })
+    }
+
+    async fn put(
+        &self,
+        key: &str,
+        value: &[u8],
+        expiration: Option<Expiration>,
+        metadata: Option<Value>,
+    ) -> Result<()> {
+        self.write(key, value, expiration, metadata);
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.remove(key);
+        Ok(())
+    }
+
+    async fn list(&self, params: PaginationParams) -> Result<ListResponse> {
+        Ok(self.page(params))
+    }
+
+    async fn delete_bulk(&self, keys: &[&str]) -> Result<()> {
+        self.remove_many(keys);
+        Ok(())
+    }
+
+    async fn metadata(&self, key: &str) -> Result<Option<Value>> {
+        Ok(self.read_stored(key).and_then(|stored| stored.metadata))
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn is_expired(stored: &StoredValue, now: u64) -> bool {
+    stored.expiration.is_some_and(|at| at <= now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn response(status: StatusCode, headers: &[(&'static str, &str)]) -> ApiResponse {
+        let mut map = http::HeaderMap::new();
+        for (name, value) in headers {
+            map.insert(*name, value.parse().unwrap());
+        }
+        ApiResponse {
+            status,
+            headers: map,
+            body: b"{\"success\":false}".to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_status_error() {
+        let forbidden = status_error(&response(StatusCode::FORBIDDEN, &[]), "x".into());
+        assert!(forbidden.is_auth_error());
+        assert_eq!(forbidden.to_string(), "Authentication failed: x");
+
+        let throttled = status_error(
+            &response(
+                StatusCode::TOO_MANY_REQUESTS,
+                &[
+                    ("Retry-After", "30"),
+                    ("X-RateLimit-Remaining", "0"),
+                    ("Content-Type", "application/json"),
+                ],
+            ),
+            "x".into(),
+        );
+        assert!(throttled.is_rate_limited() && !throttled.is_auth_error());
+        assert_eq!(throttled.retry_after(), Some(Duration::from_secs(30)));
+        let KvError::Http(details) = &throttled else {
+            panic!("expected an HTTP error, got {:?}", throttled);
+        };
+        assert_eq!(details.header("x-ratelimit-remaining"), Some("0"));
+        assert_eq!(details.header("Content-Type"), None);
+        assert_eq!(details.body, "{\"success\":false}");
+
+        let failed = status_error(&response(StatusCode::BAD_GATEWAY, &[]), "x".into());
+        assert_eq!(failed.status(), Some(502));
+        assert!(!failed.is_rate_limited());
+        assert_eq!(failed.retry_after(), None);
+        assert_eq!(failed.to_string(), "Request failed: x");
+    }
+
+    #[cfg(feature = "reqwest")]
+    #[tokio::test]
+    async fn test_retries_and_timeout() {
+        use crate::types::{AuthCredentials, RetryPolicy};
+        use wiremock::matchers::method;
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("value"))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+
+        let mut config = ClientConfig::new("a", "n", AuthCredentials::token("t"))
+            .with_retry(RetryPolicy::new(2, Duration::from_millis(1)));
+        config.base_url = server.uri();
+        let transport = HttpTransport::new(config.clone());
+        assert_eq!(transport.get("k").await.unwrap(), Some(b"value".to_vec()));
+        assert_eq!(server.received_requests().await.unwrap().len(), 3);
+
+        // Creating a namespace is not retried
+        assert!(transport.create_namespace("new").await.is_err());
+        assert_eq!(server.received_requests().await.unwrap().len(), 4);
+
+        let slow = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(500)))
+            .mount(&slow)
+            .await;
+        config.base_url = slow.uri();
+        let transport = HttpTransport::new(config.with_timeout(Duration::from_millis(50)));
+        assert!(transport.get("k").await.is_err());
+        assert_eq!(slow.received_requests().await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_conditional_get() {
+        use crate::types::AuthCredentials;
+        use wiremock::matchers::{header, method, path_regex};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(header("If-None-Match", "\"e1\""))
+            .respond_with(ResponseTemplate::new(304))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path_regex("/missing$"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"e2\"")
+                    .set_body_string("value"),
+            )
+            .mount(&server)
+            .await;
+
+        let mut config = ClientConfig::new("a", "n", AuthCredentials::token("t"));
+        config.base_url = server.uri();
+        let transport = HttpTransport::new(config);
+        assert_eq!(
+            transport
+                .get_if_none_match("k", Some("\"e1\""))
+                .await
+                .unwrap(),
+            ConditionalGet::NotModified
+        );
+        assert_eq!(
+            transport.get_if_none_match("k", None).await.unwrap(),
+            ConditionalGet::Modified {
+                value: b"value".to_vec(),
+                etag: Some("\"e2\"".to_string()),
+            }
+        );
+        assert_eq!(
+            transport.get_if_none_match("missing", None).await.unwrap(),
+            ConditionalGet::NotFound
+        );
+    }
+
+    #[tokio::test]
+    async fn test_put_metadata_as_form() {
+        use crate::types::AuthCredentials;
+        use wiremock::matchers::method;
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("PUT"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let mut config = ClientConfig::new("a", "n", AuthCredentials::token("t"));
+        config.base_url = server.uri();
+        let transport = HttpTransport::new(config);
+        transport
+            .put(
+                "k",
+                b"line 1\r\nline 2",
+                Some(Expiration::Ttl(60)),
+                Some(json!({"owner": "ops"})),
+            )
+            .await
+            .unwrap();
+        transport.put("plain", b"raw", None, None).await.unwrap();
+
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests[0].url.query(), Some("expiration_ttl=60"));
+        let content_type = requests[0].headers["content-type"].to_str().unwrap();
+        let boundary = content_type
+            .strip_prefix("multipart/form-data; boundary=")
+            .unwrap();
+        let expected = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"value\"\r\n\r\nline 1\r\nline 2\r\n\
+             --{b}\r\nContent-Disposition: form-data; name=\"metadata\"\r\n\r\n{{\"owner\":\"ops\"}}\r\n\
+             --{b}--\r\n",
+            b = boundary
+        );
+        assert_eq!(String::from_utf8_lossy(&requests[0].body), expected);
+        assert!(requests[0].headers.get("x-kv-metadata").is_none());
+
+        // Without metadata the value is the whole body
+        assert!(requests[1].headers.get("content-type").is_none());
+        assert_eq!(requests[1].body, b"raw");
+    }
+
+    #[test]
+    fn test_value_url() {
+        use crate::types::AuthCredentials;
+
+        let config = ClientConfig::new("a", "n", AuthCredentials::token("t"));
+        let transport = HttpTransport::new(config.clone());
+        let endpoint = config.kv_endpoint();
+        assert_eq!(
+            transport.value_url("a/b c?#é").unwrap(),
+            format!("{}/a%2Fb%20c%3F%23%C3%A9", endpoint)
+        );
+        assert_eq!(
+            transport.value_url("plain-key_1.2~").unwrap(),
+            format!("{}/plain-key_1.2~", endpoint)
+        );
+        assert!(transport.value_url("..").is_err());
+
+        let transport = HttpTransport::new(config.with_url_encoding(false));
+        assert!(transport.value_url("plain").is_ok());
+        let error = transport.value_url("a/b").unwrap_err();
+        assert!(error.to_string().contains("does not support"));
+    }
+
+    #[tokio::test]
+    async fn test_gateway_base_url_and_headers() {
+        use crate::types::AuthCredentials;
+        use wiremock::matchers::{header, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(
+                "/cloudflare/accounts/a/storage/kv/namespaces/n/values/k",
+            ))
+            .and(header("X-Gateway-Key", "secret"))
+            .and(header("Authorization", "Bearer t"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("value"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let config = ClientConfig::new("a", "n", AuthCredentials::token("t"))
+            .with_base_url(format!("{}/cloudflare/", server.uri()))
+            .with_header("X-Gateway-Key", "secret");
+        let transport = HttpTransport::new(config);
+        assert_eq!(transport.get("k").await.unwrap(), Some(b"value".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_request_hooks() {
+        use crate::types::AuthCredentials;
+        use wiremock::matchers::{header, method};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(header("X-Audit-User", "ci"))
+            .and(header("Authorization", "Signed abc"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("value"))
+            .mount(&server)
+            .await;
+
+        let mut config = ClientConfig::new("a", "n", AuthCredentials::token("t"))
+            .with_request_hook(|request| {
+                request
+                    .headers_mut()
+                    .insert("X-Audit-User", "ci".parse().unwrap());
+                Ok(())
+            })
+            // Later hooks see the changes of earlier ones
+            .with_request_hook(|request| {
+                assert!(request.headers().contains_key("X-Audit-User"));
+                request
+                    .headers_mut()
+                    .insert("Authorization", "Signed abc".parse().unwrap());
+                Ok(())
+            });
+        config.base_url = server.uri();
+        let transport = HttpTransport::new(config.clone());
+        assert_eq!(transport.get("k").await.unwrap(), Some(b"value".to_vec()));
+
+        let failing =
+            HttpTransport::new(config.with_request_hook(|_| {
+                Err(KvError::AuthError("signing key unavailable".to_string()))
+            }));
+        assert!(matches!(failing.get("k").await, Err(KvError::AuthError(_))));
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_warm_up() {
+        use crate::types::{AuthCredentials, ConnectionOptions};
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/accounts/a/storage/kv/namespaces/n"))
+            .respond_with(ResponseTemplate::new(404))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut config = ClientConfig::new("a", "n", AuthCredentials::token("t"))
+            .with_connection(ConnectionOptions::keep_alive());
+        config.base_url = server.uri();
+        // Any response means the connection is open
+        HttpTransport::new(config.clone()).warm_up().await.unwrap();
+
+        config.base_url = "http://127.0.0.1:1".to_string();
+        assert!(HttpTransport::new(config).warm_up().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_list_envelope() {
+        use crate::types::AuthCredentials;
+        use wiremock::matchers::{method, path, query_param};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let keys = "/accounts/a/storage/kv/namespaces/n/keys";
+        Mock::given(method("GET"))
+            .and(path(keys))
+            .and(query_param("prefix", "ok"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "success": true,
+                "result": [{ "name": "ok1", "expiration": 1700000000 }],
+                "result_info": { "count": 1, "cursor": "c2" }
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(keys))
+            .and(query_param("prefix", "last"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "success": true,
+                "result": [],
+                "result_info": { "count": 0, "cursor": "" }
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(keys))
+            .and(query_param("prefix", "object"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "success": true,
+                "result": { "keys": [{ "name": "object1" }], "cursor": "" }
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(keys))
+            .and(query_param("prefix", "bad"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "success": true,
+                "result": [{ "key": "bad1" }]
+            })))
+            .mount(&server)
+            .await;
+
+        let mut config = ClientConfig::new("a", "n", AuthCredentials::token("t"));
+        config.base_url = server.uri();
+        let transport = HttpTransport::new(config);
+
+        let page = transport
+            .list(PaginationParams::new().with_prefix("ok"))
+            .await
+            .unwrap();
+        assert_eq!(page.keys[0].name, "ok1");
+        assert_eq!(page.cursor.as_deref(), Some("c2"));
+        assert!(!page.list_complete);
+        assert_eq!(
+            page.result_info,
+            Some(ResultInfo {
+                count: Some(1),
+                cursor: Some("c2".to_string()),
+                total_pages: None,
+            })
+        );
+
+        // An empty cursor ends the listing, in either result shape
+        for prefix in ["last", "object"] {
+            let page = transport
+                .list(PaginationParams::new().with_prefix(prefix))
+                .await
+                .unwrap();
+            assert!(page.list_complete, "{}", prefix);
+            assert_eq!(page.cursor, None);
+        }
+
+        // Keys that do not parse are an error, not an empty page
+        assert!(matches!(
+            transport
+                .list(PaginationParams::new().with_prefix("bad"))
+                .await,
+            Err(KvError::UnexpectedResponse(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_namespace_management() {
+        use crate::types::AuthCredentials;
+        use wiremock::matchers::{body_json, method, path, query_param};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let namespaces = "/accounts/a/storage/kv/namespaces";
+        Mock::given(method("POST"))
+            .and(path(namespaces))
+            .and(body_json(json!({ "title": "sessions" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "success": true,
+                "result": { "id": "s1", "title": "sessions", "supports_url_encoding": true }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path(format!("{}/s1", namespaces)))
+            .and(body_json(json!({ "title": "sessions-v2" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "success": true })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path(format!("{}/s1", namespaces)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "success": true })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(namespaces))
+            .and(query_param("page", "2"))
+            .and(query_param("per_page", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "success": true,
+                "result": [{ "id": "n", "title": "main" }],
+                "result_info": { "page": 2, "per_page": 1, "total_pages": 2 }
+            })))
+            .mount(&server)
+            .await;
+
+        let mut config = ClientConfig::new("a", "n", AuthCredentials::token("t"));
+        config.base_url = server.uri();
+        let transport = HttpTransport::new(config);
+
+        let created = transport.create_namespace("sessions").await.unwrap();
+        assert_eq!(
+            created,
+            Namespace {
+                id: "s1".to_string(),
+                title: "sessions".to_string(),
+                supports_url_encoding: Some(true),
+            }
+        );
+        transport
+            .rename_namespace("s1", "sessions-v2")
+            .await
+            .unwrap();
+        transport.delete_namespace("s1").await.unwrap();
+
+        let (page, more) = transport.list_namespaces(2, 1).await.unwrap();
+        assert_eq!(page[0].title, "main");
+        assert_eq!(page[0].supports_url_encoding, None);
+        assert!(!more);
+    }
+
+    #[cfg(feature = "hyper")]
+    #[tokio::test]
+    async fn test_hyper_transport() {
+        use crate::types::AuthCredentials;
+        use wiremock::matchers::{body_json, header, method, path, query_param};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let values = "/accounts/a/storage/kv/namespaces/n/values";
+        Mock::given(method("PUT"))
+            .and(path(format!("{}/k", values)))
+            .and(query_param("expiration_ttl", "60"))
+            .and(header("Authorization", "Bearer t"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}/k", values)))
+            .respond_with(ResponseTemplate::new(200).set_body_string("value"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}/missing", values)))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/accounts/a/storage/kv/namespaces/n/bulk"))
+            .and(body_json(json!(["k"])))
+            .respond_with(ResponseTemplate::new(403).set_body_string("denied"))
+            .mount(&server)
+            .await;
+
+        let mut config = ClientConfig::new("a", "n", AuthCredentials::token("t"));
+        config.base_url = server.uri();
+        let transport = HttpTransport::with_hyper(config);
+        transport
+            .put("k", b"value", Some(Expiration::Ttl(60)), None)
+            .await
+            .unwrap();
+        assert_eq!(transport.get("k").await.unwrap(), Some(b"value".to_vec()));
+        assert_eq!(transport.get("missing").await.unwrap(), None);
+        assert!(transport
+            .delete_bulk(&["k"])
+            .await
+            .unwrap_err()
+            .is_auth_error());
+
+        let unreachable = ClientConfig {
+            base_url: "http://127.0.0.1:1".to_string(),
+            ..ClientConfig::new("a", "n", AuthCredentials::token("t"))
+        };
+        assert!(matches!(
+            HttpTransport::with_hyper(unreachable).get("k").await,
+            Err(KvError::NetworkError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_values() {
+        let store = InMemoryTransport::new();
+        store
+            .put("a", b"1", None, Some(json!({"tag": "x"})))
+            .await
+            .unwrap();
+        store
+            .put("gone", b"2", Some(Expiration::At(1)), None)
+            .await
+            .unwrap();
+
+        assert_eq!(store.get("a").await.unwrap(), Some(b"1".to_vec()));
+        // Already expired
+        assert_eq!(store.get("gone").await.unwrap(), None);
+        assert_eq!(store.len(), 1);
+
+        store.delete("a").await.unwrap();
+        store.delete("missing").await.unwrap();
+        assert!(store.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_list_pages() {
+        let store = InMemoryTransport::new();
+        for key in ["post:a", "post:b", "post:c", "user:a"] {
+            store
+                .put(key, b"", Some(Expiration::Ttl(3600)), None)
+                .await
+                .unwrap();
+        }
+
+        let first = store
+            .list(PaginationParams::new().with_prefix("post:").with_limit(2))
+            .await
+            .unwrap();
+        let names: Vec<_> = first.keys.iter().map(|k| k.name.as_str()).collect();
+        assert_eq!(names, ["post:a", "post:b"]);
+        assert!(!first.list_complete);
+        assert!(first.keys[0].expiration.is_some());
+
+        let rest = store
+            .list(
+                PaginationParams::new()
+                    .with_prefix("post:")
+                    .with_limit(2)
+                    .with_cursor(first.cursor.unwrap()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(rest.keys.len(), 1);
+        assert!(rest.list_complete);
+        assert_eq!(rest.cursor, None);
+
+        store.delete_bulk(&["post:a", "user:a"]).await.unwrap();
+        assert_eq!(store.len(), 2);
+    }
+}
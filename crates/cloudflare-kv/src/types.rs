@@ -1,4 +1,11 @@
+use crate::error::{KvError, Result};
+use crate::metrics::Metrics;
+use crate::rate_limit::RateLimiter;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// Authentication credentials for Cloudflare API
 #[derive(Clone, Debug)]
@@ -29,6 +36,118 @@ impl AuthCredentials {
     }
 }
 
+/// Callback run on each request `HttpTransport` sends, just before it is sent
+///
+/// Hooks can add headers (audit or trace IDs), sign the request or replace
+/// its authentication. An error aborts the request. Hooks take a
+/// `reqwest::Request` and exist only with the `reqwest` feature.
+#[cfg(feature = "reqwest")]
+#[derive(Clone)]
+pub struct RequestHook(Arc<HookFn>);
+
+#[cfg(feature = "reqwest")]
+type HookFn = dyn Fn(&mut reqwest::Request) -> Result<()> + Send + Sync;
+
+#[cfg(feature = "reqwest")]
+impl RequestHook {
+    /// Wrap a function mutating outgoing requests
+    pub fn new(hook: impl Fn(&mut reqwest::Request) -> Result<()> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(hook))
+    }
+
+    /// Run the hook on a request
+    pub fn apply(&self, request: &mut reqwest::Request) -> Result<()> {
+        (self.0)(request)
+    }
+}
+
+/// How connections to the Cloudflare API are kept and reused
+///
+/// Long export and sync runs benefit from keeping connections open between
+/// requests so TLS is not negotiated again. `None` keeps the HTTP client's
+/// default. Ignored on wasm32, where the browser or runtime owns connections;
+/// the hyper backend speaks only HTTP/1.1 and ignores the HTTP/2 setting.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConnectionOptions {
+    /// How long an idle connection stays in the pool
+    pub pool_idle_timeout: Option<Duration>,
+    /// Most idle connections kept per host
+    pub pool_max_idle_per_host: Option<usize>,
+    /// Interval of TCP keep-alive probes
+    pub tcp_keepalive: Option<Duration>,
+    /// Send small requests without Nagle's delay
+    pub tcp_nodelay: bool,
+    /// Interval of HTTP/2 PING frames keeping the connection open
+    pub http2_keep_alive_interval: Option<Duration>,
+}
+
+impl Default for ConnectionOptions {
+    fn default() -> Self {
+        Self {
+            pool_idle_timeout: None,
+            pool_max_idle_per_host: None,
+            tcp_keepalive: None,
+            tcp_nodelay: true,
+            http2_keep_alive_interval: None,
+        }
+    }
+}
+
+impl ConnectionOptions {
+    /// Settings for long bulk jobs: idle connections kept for 5 minutes,
+    /// TCP keep-alive every 60 seconds and HTTP/2 pings every 30 seconds
+    pub fn keep_alive() -> Self {
+        Self {
+            pool_idle_timeout: Some(Duration::from_secs(300)),
+            tcp_keepalive: Some(Duration::from_secs(60)),
+            http2_keep_alive_interval: Some(Duration::from_secs(30)),
+            ..Self::default()
+        }
+    }
+}
+
+/// How requests failing for a passing reason are retried
+///
+/// Network errors, timeouts, 429 and 5xx responses are retried up to
+/// `max_retries` times. The first retry waits `delay` and each one after
+/// waits twice as long as the last, unless the API sends `Retry-After`.
+/// Creating a namespace is never retried, as it could create two.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    /// Wait before the first retry
+    pub delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    /// No retries
+    fn default() -> Self {
+        Self {
+            max_retries: 0,
+            delay: Duration::from_millis(500),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Retry up to `max_retries` times, starting with a wait of `delay`
+    pub fn new(max_retries: u32, delay: Duration) -> Self {
+        Self { max_retries, delay }
+    }
+
+    /// Wait before retry number `attempt`, counting from 0
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        self.delay.saturating_mul(2u32.saturating_pow(attempt))
+    }
+}
+
+#[cfg(feature = "reqwest")]
+impl std::fmt::Debug for RequestHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("RequestHook")
+    }
+}
+
 /// Configuration for Cloudflare KV client
 #[derive(Clone, Debug)]
 pub struct ClientConfig {
@@ -36,6 +155,31 @@ pub struct ClientConfig {
     pub namespace_id: String,
     pub credentials: AuthCredentials,
     pub base_url: String,
+    /// Shared request throttle, if any
+    pub rate_limit: Option<Arc<RateLimiter>>,
+    /// Local JSON file used instead of the Cloudflare API, if any
+    pub local_file: Option<PathBuf>,
+    /// Prefix a `ScopedKvClient` adds to every key, if any
+    pub key_prefix: Option<String>,
+    /// Connection pooling and keep-alive settings
+    pub connection: ConnectionOptions,
+    /// Longest a request may take, including reading the response; no
+    /// limit when `None`. Ignored on wasm32.
+    pub timeout: Option<Duration>,
+    /// Retries of requests failing for a passing reason
+    pub retry: RetryPolicy,
+    /// Whether the namespace URL-decodes keys in value URLs (see
+    /// `Namespace::supports_url_encoding`); when it does not, keys that
+    /// need escaping are refused instead of being stored escaped
+    pub url_encoding: bool,
+    /// Headers added to every API request, e.g. for a gateway in front of
+    /// the API
+    pub headers: Vec<(String, String)>,
+    /// Request and transfer counters, shared by clones of the configuration
+    pub metrics: Metrics,
+    /// Hooks run on every API request, in the order they were added
+    #[cfg(feature = "reqwest")]
+    pub request_hooks: Vec<RequestHook>,
 }
 
 impl ClientConfig {
@@ -50,9 +194,103 @@ impl ClientConfig {
             namespace_id: namespace_id.into(),
             credentials,
             base_url: "https://api.cloudflare.com/client/v4".to_string(),
+            rate_limit: None,
+            local_file: None,
+            key_prefix: None,
+            connection: ConnectionOptions::default(),
+            timeout: None,
+            retry: RetryPolicy::default(),
+            url_encoding: true,
+            headers: Vec::new(),
+            metrics: Metrics::new(),
+            #[cfg(feature = "reqwest")]
+            request_hooks: Vec::new(),
         }
     }
 
+    /// Configuration for a namespace kept in a local JSON file instead of
+    /// Cloudflare (see `LocalKvBackend`)
+    pub fn local_file(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let mut config = Self::new(
+            "local",
+            path.display().to_string(),
+            AuthCredentials::token(""),
+        );
+        config.local_file = Some(path);
+        config
+    }
+
+    /// Send at most `requests_per_second` requests, across every client and
+    /// task using this configuration
+    pub fn with_rate_limit(mut self, requests_per_second: f64) -> Self {
+        self.rate_limit = Some(Arc::new(RateLimiter::new(requests_per_second)));
+        self
+    }
+
+    /// Keep and reuse connections to the API as `options` say
+    pub fn with_connection(mut self, options: ConnectionOptions) -> Self {
+        self.connection = options;
+        self
+    }
+
+    /// Give up on requests taking longer than `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Retry requests failing for a passing reason as `retry` says
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Send requests to `base_url` instead of the Cloudflare API, e.g. an
+    /// internal gateway proxying it
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Add the header `name: value` to every API request
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Add the requests and bytes of clients using this configuration to
+    /// `metrics`, e.g. to total several namespaces
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Set whether the namespace URL-decodes keys, as its
+    /// `supports_url_encoding` says
+    pub fn with_url_encoding(mut self, url_encoding: bool) -> Self {
+        self.url_encoding = url_encoding;
+        self
+    }
+
+    /// Run `hook` on every request sent to the Cloudflare API, after the
+    /// hooks added before it
+    ///
+    /// ```ignore
+    /// let config = config.with_request_hook(|request| {
+    ///     request.headers_mut().insert("X-Audit-User", HeaderValue::from_static("ci"));
+    ///     Ok(())
+    /// });
+    /// ```
+    #[cfg(feature = "reqwest")]
+    pub fn with_request_hook(
+        mut self,
+        hook: impl Fn(&mut reqwest::Request) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.request_hooks.push(RequestHook::new(hook));
+        self
+    }
+
     /// Get KV API endpoint URL
     pub fn kv_endpoint(&self) -> String {
         format!(
@@ -68,6 +306,45 @@ impl ClientConfig {
             self.base_url, self.account_id, self.namespace_id
         )
     }
+
+    /// Get the URL listing the accounts the token can access
+    pub fn accounts_endpoint(&self) -> String {
+        format!("{}/accounts", self.base_url)
+    }
+
+    /// Get the URL listing every namespace of the account
+    pub fn namespaces_endpoint(&self) -> String {
+        format!(
+            "{}/accounts/{}/storage/kv/namespaces",
+            self.base_url, self.account_id
+        )
+    }
+
+    /// Get the URL of the namespace's details
+    pub fn namespace_endpoint(&self) -> String {
+        format!("{}/{}", self.namespaces_endpoint(), self.namespace_id)
+    }
+}
+
+/// A KV namespace of an account
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Namespace {
+    pub id: String,
+    pub title: String,
+    /// Whether keys in value URLs are URL-decoded before being stored, as
+    /// reported by the API
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub supports_url_encoding: Option<bool>,
+}
+
+/// Former name of `Namespace`
+pub type NamespaceInfo = Namespace;
+
+/// A Cloudflare account the API token can access
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Account {
+    pub id: String,
+    pub name: String,
 }
 
 /// Pagination parameters for list operations
@@ -75,6 +352,7 @@ impl ClientConfig {
 pub struct PaginationParams {
     pub limit: Option<u32>,
     pub cursor: Option<String>,
+    pub prefix: Option<String>,
 }
 
 impl PaginationParams {
@@ -83,6 +361,7 @@ impl PaginationParams {
         Self {
             limit: None,
             cursor: None,
+            prefix: None,
         }
     }
 
@@ -97,6 +376,12 @@ impl PaginationParams {
         self.cursor = Some(cursor);
         self
     }
+
+    /// Only return keys starting with the given prefix
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
 }
 
 impl Default for PaginationParams {
@@ -111,6 +396,23 @@ pub struct ListResponse {
     pub keys: Vec<KeyMetadata>,
     pub list_complete: bool,
     pub cursor: Option<String>,
+    /// Paging details as returned by the Cloudflare API, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result_info: Option<ResultInfo>,
+}
+
+/// The `result_info` of a Cloudflare API response
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResultInfo {
+    /// Number of results in this response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<u64>,
+    /// Cursor of the next page; empty on the last page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+    /// Number of pages, for page-numbered listings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_pages: Option<u64>,
 }
 
 /// Metadata for a KV key
@@ -129,3 +431,178 @@ pub struct KvPair {
     pub metadata: Option<serde_json::Value>,
     pub expiration: Option<u64>,
 }
+
+/// Options of a read made with `KvClient::get_with_options`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReadOptions {
+    /// Seconds an edge cache may serve the value before reading it again,
+    /// like a Workers binding's `cacheTtl` (which must be at least 60). The
+    /// Cloudflare API always reads the stored value; caches in front of it,
+    /// such as `cfkv serve --worker-compat`, honor it.
+    pub cache_ttl: Option<u64>,
+    /// Also read the key's metadata and expiration, at the cost of a list
+    /// request
+    pub metadata: bool,
+}
+
+impl ReadOptions {
+    /// Read the value alone
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Let caches serve the value for `seconds`
+    pub fn with_cache_ttl(mut self, seconds: u64) -> Self {
+        self.cache_ttl = Some(seconds);
+        self
+    }
+
+    /// Read the metadata and expiration along with the value
+    pub fn with_metadata(mut self) -> Self {
+        self.metadata = true;
+        self
+    }
+}
+
+/// A value read with `ReadOptions`, as stored
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReadValue {
+    pub value: Vec<u8>,
+    /// Only read when `ReadOptions::metadata` is set
+    pub metadata: Option<serde_json::Value>,
+    /// Only read when `ReadOptions::metadata` is set
+    pub expiration: Option<u64>,
+}
+
+impl ReadValue {
+    /// The value decoded as UTF-8, with invalid sequences replaced
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.value).into_owned()
+    }
+}
+
+/// A numeric value used by `KvClient::increment`
+///
+/// Integers stay integers as long as both sides are integers; anything else
+/// is added as a float.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CounterValue {
+    Int(i64),
+    Float(f64),
+}
+
+impl CounterValue {
+    /// Add two values, failing on integer overflow
+    pub fn checked_add(self, other: CounterValue) -> Result<CounterValue> {
+        match (self, other) {
+            (CounterValue::Int(a), CounterValue::Int(b)) => a
+                .checked_add(b)
+                .map(CounterValue::Int)
+                .ok_or_else(|| KvError::SerializationError(format!("{} + {} overflows i64", a, b))),
+            (a, b) => Ok(CounterValue::Float(a.as_f64() + b.as_f64())),
+        }
+    }
+
+    /// The value negated
+    pub fn negate(self) -> Result<CounterValue> {
+        match self {
+            CounterValue::Int(n) => n
+                .checked_neg()
+                .map(CounterValue::Int)
+                .ok_or_else(|| KvError::SerializationError(format!("-({}) overflows i64", n))),
+            CounterValue::Float(f) => Ok(CounterValue::Float(-f)),
+        }
+    }
+
+    fn as_f64(self) -> f64 {
+        match self {
+            CounterValue::Int(n) => n as f64,
+            CounterValue::Float(f) => f,
+        }
+    }
+}
+
+impl std::str::FromStr for CounterValue {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Ok(n) = s.parse::<i64>() {
+            return Ok(CounterValue::Int(n));
+        }
+        match s.parse::<f64>() {
+            Ok(f) if f.is_finite() => Ok(CounterValue::Float(f)),
+            _ => Err(format!("'{}' is not a number", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for CounterValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CounterValue::Int(n) => write!(f, "{}", n),
+            // Debug keeps the decimal point (`3.0`), so floats stay floats
+            CounterValue::Float(x) => write!(f, "{:?}", x),
+        }
+    }
+}
+
+impl From<i64> for CounterValue {
+    fn from(n: i64) -> Self {
+        CounterValue::Int(n)
+    }
+}
+
+impl From<f64> for CounterValue {
+    fn from(f: f64) -> Self {
+        CounterValue::Float(f)
+    }
+}
+
+/// Condition checked against the current value before a conditional write
+///
+/// Checks are a read followed by a write, not an atomic operation. KV is
+/// eventually consistent, so writers racing within the propagation window
+/// (up to 60 seconds across locations) can both pass. Conditions protect
+/// against accidental overwrites, not concurrent ones.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WriteCondition {
+    /// The key must not exist
+    IfAbsent,
+    /// The current value's SHA-256 (hex, see `value_hash`) must match
+    IfMatch(String),
+}
+
+impl WriteCondition {
+    /// Check the condition against the current value of `key`
+    pub fn check(&self, key: &str, current: Option<&[u8]>) -> Result<()> {
+        match (self, current) {
+            (WriteCondition::IfAbsent, None) => Ok(()),
+            (WriteCondition::IfAbsent, Some(_)) => Err(KvError::PreconditionFailed(format!(
+                "key {} already exists",
+                key
+            ))),
+            (WriteCondition::IfMatch(_), None) => Err(KvError::KeyNotFound(key.to_string())),
+            (WriteCondition::IfMatch(expected), Some(value)) => {
+                let actual = value_hash(value);
+                if actual.eq_ignore_ascii_case(expected.trim()) {
+                    Ok(())
+                } else {
+                    Err(KvError::PreconditionFailed(format!(
+                        "key {} has changed (current hash {})",
+                        key, actual
+                    )))
+                }
+            }
+        }
+    }
+}
+
+/// Hex-encoded SHA-256 of a value, as used by `WriteCondition::IfMatch`
+pub fn value_hash(value: &[u8]) -> String {
+    Sha256::digest(value)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
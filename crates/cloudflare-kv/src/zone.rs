@@ -0,0 +1,201 @@
+//! Minimal zone client for purging cached URLs
+//!
+//! Pages and API responses built from KV values are often cached at the
+//! edge; purging their URLs after a write makes the new value visible at
+//! once. The token needs the "Cache Purge" permission on the zone, and
+//! "Zone Read" to look zones up by host name.
+
+use crate::error::{KvError, Result};
+use crate::http_client::{ApiRequest, Engine};
+use crate::transport::status_error;
+use crate::types::{AuthCredentials, ClientConfig, ConnectionOptions};
+use http::{Method, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::debug;
+
+/// Most URLs purged by one request, as in the Cloudflare API
+pub const PURGE_URL_LIMIT: usize = 30;
+
+/// A zone (domain) of the account
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct Zone {
+    pub id: String,
+    pub name: String,
+}
+
+/// Client for the zone endpoints of the Cloudflare API
+pub struct ZoneClient {
+    engine: Engine,
+    base_url: String,
+    credentials: AuthCredentials,
+}
+
+impl ZoneClient {
+    pub fn new(credentials: AuthCredentials) -> Self {
+        Self {
+            engine: Engine::new(&ConnectionOptions::default()),
+            base_url: "https://api.cloudflare.com/client/v4".to_string(),
+            credentials,
+        }
+    }
+
+    /// Create a client with the credentials, API URL and connection options
+    /// of a KV client configuration
+    pub fn for_config(config: &ClientConfig) -> Self {
+        Self {
+            engine: Engine::new(&config.connection),
+            base_url: config.base_url.clone(),
+            credentials: config.credentials.clone(),
+        }
+    }
+
+    /// Send requests to `base_url` instead of the Cloudflare API
+    pub fn with_base_url(mut self, url: impl Into<String>) -> Self {
+        self.base_url = url.into();
+        self
+    }
+
+    /// The zone serving `host`, trying `host` and then each parent domain
+    /// (`www.shop.example.com`, `shop.example.com`, `example.com`)
+    pub async fn find_zone(&self, host: &str) -> Result<Option<Zone>> {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        let labels: Vec<&str> = host.split('.').collect();
+        for start in 0..labels.len().saturating_sub(1) {
+            let name = labels[start..].join(".");
+            if let Some(zone) = self.zone_named(&name).await? {
+                return Ok(Some(zone));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn zone_named(&self, name: &str) -> Result<Option<Zone>> {
+        debug!("Looking up zone {}", name);
+        let request = self
+            .request(Method::GET, format!("{}/zones", self.base_url))
+            .query("name", name);
+        let response = self.engine.send(request).await?;
+        match response.status {
+            StatusCode::OK => Ok(response
+                .envelope::<Vec<Zone>>("zone list")?
+                .into_result("zone list")?
+                .into_iter()
+                .find(|zone| zone.name.eq_ignore_ascii_case(name))),
+            status => Err(status_error(
+                &response,
+                format!(
+                    "Failed to look up zone {}: {} - {}",
+                    name,
+                    status,
+                    response.text()
+                ),
+            )),
+        }
+    }
+
+    /// Purge URLs from the zone's cache, `PURGE_URL_LIMIT` per request
+    pub async fn purge_urls(&self, zone_id: &str, urls: &[String]) -> Result<()> {
+        if zone_id.is_empty() {
+            return Err(KvError::InvalidConfig(
+                "Zone ID must not be empty".to_string(),
+            ));
+        }
+        for chunk in urls.chunks(PURGE_URL_LIMIT) {
+            debug!("Purging {} URL(s) from zone {}", chunk.len(), zone_id);
+            let url = format!("{}/zones/{}/purge_cache", self.base_url, zone_id);
+            let request = self
+                .request(Method::POST, url)
+                .json(&json!({ "files": chunk }));
+            let response = self.engine.send(request).await?;
+            if response.status != StatusCode::OK {
+                return Err(status_error(
+                    &response,
+                    format!(
+                        "Failed to purge cache of zone {}: {} - {}",
+                        zone_id,
+                        response.status,
+                        response.text()
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn request(&self, method: Method, url: String) -> ApiRequest {
+        ApiRequest::new(method, url, self.credentials.auth_header())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_json, header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn zones(zones: serde_json::Value) -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_json(json!({
+            "success": true,
+            "errors": [],
+            "result": zones,
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_find_zone() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/zones"))
+            .and(query_param("name", "example.com"))
+            .respond_with(zones(json!([{"id": "z1", "name": "example.com"}])))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/zones"))
+            .respond_with(zones(json!([])))
+            .mount(&server)
+            .await;
+
+        let client = ZoneClient::new(AuthCredentials::token("token")).with_base_url(server.uri());
+        let zone = client.find_zone("www.Shop.example.com").await.unwrap();
+        assert_eq!(zone.map(|zone| zone.id).as_deref(), Some("z1"));
+        assert_eq!(client.find_zone("example.org").await.unwrap(), None);
+        // A bare top-level domain is never a zone
+        assert_eq!(client.find_zone("localhost").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_purge_urls() {
+        let server = MockServer::start().await;
+        let urls: Vec<String> = (0..PURGE_URL_LIMIT + 1)
+            .map(|i| format!("https://example.com/{}", i))
+            .collect();
+        Mock::given(method("POST"))
+            .and(path("/zones/z1/purge_cache"))
+            .and(header("Authorization", "Bearer token"))
+            .and(body_json(json!({ "files": &urls[..PURGE_URL_LIMIT] })))
+            .respond_with(zones(json!({"id": "z1"})))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/zones/z1/purge_cache"))
+            .and(body_json(json!({ "files": &urls[PURGE_URL_LIMIT..] })))
+            .respond_with(zones(json!({"id": "z1"})))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/zones/denied/purge_cache"))
+            .respond_with(ResponseTemplate::new(403).set_body_string("forbidden"))
+            .mount(&server)
+            .await;
+
+        let client = ZoneClient::new(AuthCredentials::token("token")).with_base_url(server.uri());
+        client.purge_urls("z1", &urls).await.unwrap();
+        let error = client.purge_urls("denied", &urls[..1]).await.unwrap_err();
+        assert!(error.is_auth_error(), "{}", error);
+        assert!(client.purge_urls("", &urls).await.is_err());
+    }
+}